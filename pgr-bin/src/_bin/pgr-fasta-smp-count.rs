@@ -35,13 +35,7 @@ fn main() -> Result<(), std::io::Error> {
     CmdOptions::command().version(VERSION_STRING).get_matches();
     let args = CmdOptions::parse();
     let filepath = args.in_fasta;
-    let spec = ShmmrSpec {
-        w: args.w,
-        k: args.k,
-        r: args.r,
-        min_span: args.min_span,
-        sketch: false,
-    };
+    let spec = ShmmrSpec::new(args.w, args.k, args.r, args.min_span, false);
     let mut sdb = seq_db::CompactSeqDB::new(spec.clone());
     sdb.load_seqs_from_fastx(filepath)?;
     let mut out_buf = BufWriter::new(File::create(args.output_path)?);
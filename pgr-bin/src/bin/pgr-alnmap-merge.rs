@@ -0,0 +1,851 @@
+const VERSION_STRING: &str = env!("VERSION_STRING");
+use clap::{self, CommandFactory, Parser};
+use iset::set::IntervalSet;
+use pgr_db::ext::{get_fastx_reader, GZFastaReader};
+use pgr_db::fasta_io::{FastxWriter, SeqRec};
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// Merge the outputs of the pgr-alnmap runs partitioned by the reference sequences (with `--targets`)
+///
+/// The alignment indices are re-numbered and the target-centric outputs are concatenated. The
+/// query-centric outputs (the query duplicated / overlapped flags, the ctgsv.bed, the unaligned.fa,
+/// the tra.bed and the break ends in the VCF) are re-computed from the merged contig maps, and the
+/// merged VCF is sorted by the order of the sequences in the reference.
+#[derive(Parser, Debug)]
+#[clap(name = "pgr-alnmap-merge")]
+#[clap(author, version)]
+#[clap(about, long_about = None)]
+struct CmdOptions {
+    /// the path to the reference fasta file of the partitioned runs
+    reference_fasta_path: String,
+
+    /// the prefix of the merged output files
+    output_prefix: String,
+
+    /// the output prefixes of the partitioned pgr-alnmap runs
+    #[clap(required = true)]
+    input_prefixes: Vec<String>,
+}
+
+// the target-centric outputs, all alignments to a reference sequence are in the partition of the sequence
+const BED_EXTENSIONS: [&str; 3] = ["svcnd.bed", "inv.bed", "dup.bed"];
+
+// the min target span of the blocks flanking a translocation junction, the same as in pgr-alnmap
+const TRA_MIN_FLANK_LEN: u32 = 10000;
+
+#[derive(Serialize, Deserialize, Clone)]
+struct CtgMapRec {
+    t_name: String,
+    ts: u32,
+    te: u32,
+    q_name: String,
+    qs: u32,
+    qe: u32,
+    ctg_len: u32,
+    orientation: u32,
+    ctg_orientation: u32,
+    t_dup: bool,
+    t_ovlp: bool,
+    q_dup: bool,
+    q_ovlp: bool,
+}
+
+// (t_name, pos, the ref base is before the joined piece, the joined piece extends to the right of the mate position)
+type BreakEnd = (String, u32, bool, bool);
+
+struct TranslocationCall {
+    q_id: u32,
+    q_name: String,
+    q_bgn: u32,
+    q_end: u32,
+    break_ends: [BreakEnd; 2],
+    // the target intervals of the flanking blocks for checking the duplicated / overlapped target regions
+    target_spans: [(u32, u32); 2],
+}
+
+fn open_lines(prefix: &str, ext: &str) -> Option<impl Iterator<Item = String>> {
+    let path = Path::new(prefix).with_extension(ext);
+    if !path.exists() {
+        return None;
+    }
+    let file = BufReader::new(File::open(path).expect("can't open the input file"));
+    Some(
+        file.lines()
+            .map(|line| line.expect("can't read the input file")),
+    )
+}
+
+fn for_each_fasta_rec(
+    path: &Path,
+    to_upper_case: bool,
+    f: &mut dyn FnMut(SeqRec),
+) -> Result<(), std::io::Error> {
+    let mut add_recs = |seq_iter: &mut dyn Iterator<Item = io::Result<SeqRec>>| {
+        seq_iter
+            .into_iter()
+            .try_for_each(|r| -> Result<(), std::io::Error> {
+                f(r?);
+                Ok(())
+            })
+    };
+    match get_fastx_reader(path.to_string_lossy().to_string(), to_upper_case)? {
+        #[allow(clippy::useless_conversion)] // the into_iter() is necessary for dyn patching
+        GZFastaReader::GZFile(reader) => add_recs(&mut reader.into_iter()),
+
+        #[allow(clippy::useless_conversion)] // the into_iter() is necessary for dyn patching
+        GZFastaReader::RegularFile(reader) => add_recs(&mut reader.into_iter()),
+    }
+}
+
+fn merge_aln_files(args: &CmdOptions, ext: &str) -> Result<(), std::io::Error> {
+    let mut out_file = BufWriter::new(File::create(
+        Path::new(&args.output_prefix).with_extension(ext),
+    )?);
+    let mut offset = 0_usize;
+    args.input_prefixes
+        .iter()
+        .try_for_each(|prefix| -> Result<(), std::io::Error> {
+            let mut max_aln_idx = None;
+            if let Some(mut lines) = open_lines(prefix, ext) {
+                lines.try_for_each(|line| -> Result<(), std::io::Error> {
+                    let (aln_idx, rest) = line.split_once('\t').unwrap();
+                    let aln_idx = aln_idx.parse::<usize>().unwrap();
+                    max_aln_idx = Some(max_aln_idx.unwrap_or(0).max(aln_idx));
+                    writeln!(out_file, "{:06}\t{}", aln_idx + offset, rest)
+                })?;
+            }
+            if let Some(max_aln_idx) = max_aln_idx {
+                offset += max_aln_idx + 1;
+            }
+            Ok(())
+        })
+}
+
+fn merge_bed_files(args: &CmdOptions, ext: &str) -> Result<(), std::io::Error> {
+    let mut out_file = BufWriter::new(File::create(
+        Path::new(&args.output_prefix).with_extension(ext),
+    )?);
+    args.input_prefixes
+        .iter()
+        .try_for_each(|prefix| -> Result<(), std::io::Error> {
+            if let Some(mut lines) = open_lines(prefix, ext) {
+                lines.try_for_each(|line| writeln!(out_file, "{}", line))?;
+            }
+            Ok(())
+        })
+}
+
+// the names and the lengths of the reference sequences in the file order, and the reference bases
+// at the requested (1-based) positions
+#[allow(clippy::type_complexity)]
+fn read_reference(
+    reference_fasta_path: &str,
+    positions: &FxHashSet<(String, u32)>,
+) -> Result<(Vec<(String, u32)>, FxHashMap<(String, u32), String>), std::io::Error> {
+    let mut positions_by_name = FxHashMap::<&str, Vec<u32>>::default();
+    positions.iter().for_each(|(t_name, pos)| {
+        positions_by_name
+            .entry(t_name.as_str())
+            .or_default()
+            .push(*pos);
+    });
+    let mut ref_seqs = Vec::<(String, u32)>::new();
+    let mut ref_bases = FxHashMap::<(String, u32), String>::default();
+    for_each_fasta_rec(Path::new(reference_fasta_path), true, &mut |r| {
+        let name = String::from_utf8_lossy(&r.id[..]).to_string();
+        if let Some(positions) = positions_by_name.get(name.as_str()) {
+            positions.iter().for_each(|&pos| {
+                let base = String::from_utf8_lossy(&r.seq[pos as usize - 1..pos as usize]);
+                ref_bases.insert((name.clone(), pos), base.to_string());
+            });
+        }
+        ref_seqs.push((name, r.seq.len() as u32));
+    })?;
+    Ok((ref_seqs, ref_bases))
+}
+
+// merge the (id, name, length) lists by the names, the ids are re-assigned
+fn merge_length_lists(length_lists: Vec<Value>) -> Value {
+    let mut names = FxHashSet::<String>::default();
+    let mut merged = Vec::<Value>::new();
+    length_lists.into_iter().for_each(|length_list| {
+        if let Value::Array(mut length_list) = length_list {
+            length_list.sort_by_key(|v| v[0].as_u64());
+            length_list.into_iter().for_each(|v| {
+                let name = v[1].as_str().unwrap().to_string();
+                if names.insert(name.clone()) {
+                    merged.push(json!([merged.len(), name, v[2].as_u64().unwrap()]));
+                }
+            });
+        }
+    });
+    Value::Array(merged)
+}
+
+fn read_json(prefix: &str, ext: &str) -> Option<Value> {
+    let path = Path::new(prefix).with_extension(ext);
+    if !path.exists() {
+        return None;
+    }
+    let mut buffer = Vec::new();
+    BufReader::new(File::open(path).expect("can't open the input file"))
+        .read_to_end(&mut buffer)
+        .expect("can't read the input file");
+    Some(
+        serde_json::from_str(&String::from_utf8_lossy(&buffer[..]))
+            .expect("can't parse the json file"),
+    )
+}
+
+// the merged contig map records, target lengths, query lengths and the config of the partitions
+fn read_ctgmap_files(args: &CmdOptions) -> (Vec<CtgMapRec>, Value, Value, Option<Value>) {
+    let mut records = Vec::<CtgMapRec>::new();
+    let mut target_length_lists = Vec::<Value>::new();
+    let mut query_length_lists = Vec::<Value>::new();
+    // the partitions are run with the same parameters, the config of the first one is kept
+    let mut config = None;
+    args.input_prefixes.iter().for_each(|prefix| {
+        if let Some(Value::Object(mut ctg_map_set)) = read_json(prefix, "ctgmap.json") {
+            if let Some(v) = ctg_map_set.remove("records") {
+                let v: Vec<CtgMapRec> =
+                    serde_json::from_value(v).expect("can't parse the ctgmap records");
+                records.extend(v);
+            }
+            target_length_lists.push(ctg_map_set.remove("target_length").unwrap_or_default());
+            query_length_lists.push(ctg_map_set.remove("query_length").unwrap_or_default());
+            if config.is_none() {
+                config = ctg_map_set.remove("config");
+            }
+        }
+    });
+    (
+        records,
+        merge_length_lists(target_length_lists),
+        merge_length_lists(query_length_lists),
+        config,
+    )
+}
+
+// the indices of the contig map records of each query contig sorted by the query coordinates,
+// the query contigs are in the order of the merged query ids
+fn query_blocks(
+    records: &[CtgMapRec],
+    query_ids: &FxHashMap<String, u32>,
+) -> Vec<(u32, Vec<usize>)> {
+    let mut query_blocks = FxHashMap::<u32, Vec<usize>>::default();
+    records.iter().enumerate().for_each(|(idx, r)| {
+        let q_id = *query_ids.get(&r.q_name).unwrap();
+        query_blocks.entry(q_id).or_default().push(idx);
+    });
+    let mut query_blocks = query_blocks.into_iter().collect::<Vec<_>>();
+    query_blocks.sort_by_key(|v| v.0);
+    query_blocks.iter_mut().for_each(|(_, blocks)| {
+        blocks.sort_by_key(|&idx| records[idx].qs);
+    });
+    query_blocks
+}
+
+// set the query duplicated / overlapped flags with the alignments from all partitions and
+// get the query-centric bed records, the same as the ctgsv.bed records of pgr-alnmap
+fn mark_query_blocks(
+    records: &mut [CtgMapRec],
+    query_blocks: &[(u32, Vec<usize>)],
+    query_len: &FxHashMap<String, u32>,
+) -> Vec<(String, u32, u32, String)> {
+    let mut query_aln_bed_records = Vec::<(String, u32, u32, String)>::new();
+    let annotation = |c_target: &str, r: &CtgMapRec| {
+        format!(
+            "{}>{}:{}:{}:{}:{}:{}",
+            c_target, r.t_name, r.ts, r.te, r.ctg_len, r.orientation, r.ctg_orientation
+        )
+    };
+    query_blocks.iter().for_each(|(_, blocks)| {
+        let mut cqe = 0_u32;
+        let mut c_target = String::from("BGN");
+        let q_name = records[blocks[0]].q_name.clone();
+        blocks.iter().for_each(|&idx| {
+            let r = &mut records[idx];
+            r.q_dup = false;
+            r.q_ovlp = false;
+            if r.qs > cqe {
+                let bed_annotation = format!("QG:{}", annotation(&c_target, r));
+                query_aln_bed_records.push((q_name.clone(), cqe, r.qs, bed_annotation));
+                c_target = r.t_name.clone();
+                cqe = r.qe;
+            } else if r.qe <= cqe {
+                let bed_annotation = format!("QD:{}", annotation(&c_target, r));
+                r.q_dup = true;
+                query_aln_bed_records.push((q_name.clone(), r.qs, r.qe, bed_annotation));
+            } else {
+                let bed_annotation = format!("QO:{}", annotation(&c_target, r));
+                r.q_ovlp = true;
+                query_aln_bed_records.push((q_name.clone(), r.qs, cqe, bed_annotation));
+                c_target = r.t_name.clone();
+                cqe = r.qe;
+            }
+        });
+        let bed_annotation = format!("QG:{}>END", c_target);
+        let q_len = *query_len.get(&q_name).unwrap();
+        query_aln_bed_records.push((q_name, cqe, q_len, bed_annotation));
+    });
+    query_aln_bed_records.sort();
+    query_aln_bed_records
+}
+
+// identify the translocations from the non-duplicated blocks of a query contig sorted by the query coordinates,
+// the blocks may come from different partitions, see `call_translocations()` of pgr-alnmap
+fn call_translocations(
+    records: &[CtgMapRec],
+    q_id: u32,
+    blocks: &[usize],
+    max_gap: u32,
+) -> Vec<TranslocationCall> {
+    let blocks = blocks
+        .iter()
+        .map(|&idx| &records[idx])
+        .filter(|r| !r.q_dup)
+        .collect::<Vec<_>>();
+    blocks
+        .windows(2)
+        .filter_map(|blocks| {
+            let (b0, b1) = (blocks[0], blocks[1]);
+            if b0.t_name == b1.t_name
+                || b0.te < b0.ts + TRA_MIN_FLANK_LEN
+                || b1.te < b1.ts + TRA_MIN_FLANK_LEN
+            {
+                return None;
+            }
+            let qe0 = b0.qs.max(b0.qe);
+            let qs1 = b1.qs.min(b1.qe);
+            let (q_bgn, q_end) = if qe0 < qs1 { (qe0, qs1) } else { (qs1, qe0) };
+            if q_end - q_bgn > max_gap {
+                return None;
+            }
+            let pos0 = if b0.orientation == 0 {
+                b0.te
+            } else {
+                b0.ts + 1
+            };
+            let pos1 = if b1.orientation == 0 {
+                b1.ts + 1
+            } else {
+                b1.te
+            };
+            Some(TranslocationCall {
+                q_id,
+                q_name: b0.q_name.clone(),
+                q_bgn,
+                q_end,
+                break_ends: [
+                    (
+                        b0.t_name.clone(),
+                        pos0,
+                        b0.orientation == 0,
+                        b1.orientation == 0,
+                    ),
+                    (
+                        b1.t_name.clone(),
+                        pos1,
+                        b1.orientation == 1,
+                        b0.orientation == 1,
+                    ),
+                ],
+                target_spans: [(b0.ts, b0.te), (b1.ts, b1.te)],
+            })
+        })
+        .collect::<Vec<_>>()
+}
+
+// the ALT field of a break end in the VCF notation, e.g. "t[p[" or "]p]t"
+fn break_end_alt(
+    ref_base: &str,
+    mate_name: &str,
+    mate_pos: u32,
+    ref_base_first: bool,
+    mate_extends_right: bool,
+) -> String {
+    let bracket = if mate_extends_right { '[' } else { ']' };
+    if ref_base_first {
+        format!(
+            "{}{}{}:{}{}",
+            ref_base, bracket, mate_name, mate_pos, bracket
+        )
+    } else {
+        format!(
+            "{}{}:{}{}{}",
+            bracket, mate_name, mate_pos, bracket, ref_base
+        )
+    }
+}
+
+// the duplicated (TD) and overlapped (TO) target intervals from the merged svcnd.bed
+#[allow(clippy::type_complexity)]
+fn read_target_intervals(
+    output_prefix: &str,
+) -> (
+    FxHashMap<String, IntervalSet<u32>>,
+    FxHashMap<String, IntervalSet<u32>>,
+) {
+    let mut target_duplicate_intervals = FxHashMap::<String, IntervalSet<u32>>::default();
+    let mut target_overlap_intervals = FxHashMap::<String, IntervalSet<u32>>::default();
+    if let Some(lines) = open_lines(output_prefix, "svcnd.bed") {
+        lines.for_each(|line| {
+            let fields = line.split('\t').collect::<Vec<_>>();
+            let (bgn, end) = (
+                fields[1].parse::<u32>().unwrap(),
+                fields[2].parse::<u32>().unwrap(),
+            );
+            let intervals = if fields[3].starts_with("TD:") {
+                &mut target_duplicate_intervals
+            } else if fields[3].starts_with("TO:") {
+                &mut target_overlap_intervals
+            } else {
+                return;
+            };
+            if end > bgn {
+                intervals
+                    .entry(fields[0].to_string())
+                    .or_default()
+                    .insert(bgn..end);
+            }
+        });
+    }
+    (target_duplicate_intervals, target_overlap_intervals)
+}
+
+fn merge_vcf_files(
+    args: &CmdOptions,
+    ref_seqs: &[(String, u32)],
+    break_end_records: Vec<(String, u32, String)>,
+) -> Result<(), std::io::Error> {
+    let contig_order = ref_seqs
+        .iter()
+        .enumerate()
+        .map(|(idx, (name, _))| (name.clone(), idx))
+        .collect::<FxHashMap<_, _>>();
+    let mut meta_lines = Vec::<String>::new();
+    let mut seen_lines = FxHashSet::<String>::default();
+    let mut records = Vec::<(usize, u32, usize, String)>::new();
+    let mut add_record = |t_name: &str, pos: u32, line: String| {
+        let contig = *contig_order.get(t_name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not found in the reference file", t_name),
+            )
+        })?;
+        records.push((contig, pos, records.len(), line));
+        Ok::<(), std::io::Error>(())
+    };
+    for prefix in args.input_prefixes.iter() {
+        if let Some(lines) = open_lines(prefix, "vcf") {
+            for line in lines {
+                if line.starts_with("##") {
+                    if !line.starts_with("##contig=<ID=")
+                        && line != "##fileformat=VCFv4.2"
+                        && seen_lines.insert(line.clone())
+                    {
+                        meta_lines.push(line);
+                    }
+                    continue;
+                }
+                if line.starts_with('#') {
+                    continue;
+                }
+                let fields = line.split('\t').collect::<Vec<_>>();
+                // the break ends are re-computed across the partitions
+                if fields[7].split(';').any(|info| info == "SVTYPE=BND") {
+                    continue;
+                }
+                let pos = fields[1].parse::<u32>().unwrap();
+                let t_name = fields[0].to_string();
+                add_record(&t_name, pos, line)?;
+            }
+        }
+    }
+    for (t_name, pos, line) in break_end_records {
+        add_record(&t_name, pos, line)?;
+    }
+    records.sort();
+
+    let mut out_vcf = BufWriter::new(File::create(
+        Path::new(&args.output_prefix).with_extension("vcf"),
+    )?);
+    writeln!(out_vcf, "##fileformat=VCFv4.2")?;
+    for (t_name, t_len) in ref_seqs {
+        writeln!(out_vcf, r#"##contig=<ID={},length={}>"#, t_name, t_len)?;
+    }
+    for line in meta_lines {
+        writeln!(out_vcf, "{}", line)?;
+    }
+    writeln!(out_vcf, "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO")?;
+    records
+        .into_iter()
+        .try_for_each(|(_, _, _, line)| writeln!(out_vcf, "{}", line))
+}
+
+// the unaligned (bgn, end) intervals of the query contigs from the headers of a partition's unaligned.fa
+fn read_unaligned_intervals(
+    path: &Path,
+) -> Result<FxHashMap<String, Vec<(u32, u32)>>, std::io::Error> {
+    let mut intervals = FxHashMap::<String, Vec<(u32, u32)>>::default();
+    for_each_fasta_rec(path, false, &mut |r| {
+        let (q_name, bgn, end) = parse_unaligned_header(&r.id);
+        intervals.entry(q_name).or_default().push((bgn, end));
+    })?;
+    Ok(intervals)
+}
+
+fn parse_unaligned_header(id: &[u8]) -> (String, u32, u32) {
+    let id = String::from_utf8_lossy(id);
+    let (q_name, interval) = id.rsplit_once(':').unwrap();
+    let (bgn, end) = interval.split_once('-').unwrap();
+    (
+        q_name.to_string(),
+        bgn.parse::<u32>().unwrap(),
+        end.parse::<u32>().unwrap(),
+    )
+}
+
+// the intersection of two sorted lists of non-overlapping intervals
+fn intersect_intervals(a: &[(u32, u32)], b: &[(u32, u32)]) -> Vec<(u32, u32)> {
+    let mut intersection = Vec::<(u32, u32)>::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        let bgn = a[i].0.max(b[j].0);
+        let end = a[i].1.min(b[j].1);
+        if bgn < end {
+            intersection.push((bgn, end));
+        }
+        if a[i].1 < b[j].1 {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    intersection
+}
+
+// a query interval is unaligned if it is not aligned in any of the partitions, the sequences are
+// sliced from the unaligned intervals of the first partition
+fn merge_unaligned_files(args: &CmdOptions, min_len: u32) -> Result<(), std::io::Error> {
+    let paths = args
+        .input_prefixes
+        .iter()
+        .map(|prefix| Path::new(prefix).with_extension("unaligned.fa"))
+        .collect::<Vec<_>>();
+    if !paths.iter().all(|path| path.exists()) {
+        return Ok(());
+    }
+    let mut unaligned_intervals = read_unaligned_intervals(&paths[0])?;
+    for path in paths[1..].iter() {
+        let intervals = read_unaligned_intervals(path)?;
+        unaligned_intervals = unaligned_intervals
+            .into_iter()
+            .map(|(q_name, v)| {
+                let v = match intervals.get(&q_name) {
+                    Some(v1) => intersect_intervals(&v, v1),
+                    None => vec![],
+                };
+                (q_name, v)
+            })
+            .collect();
+    }
+
+    let mut out_unaligned = FastxWriter::new(
+        BufWriter::new(File::create(
+            Path::new(&args.output_prefix).with_extension("unaligned.fa"),
+        )?),
+        0,
+    );
+    let mut write_result = Ok(());
+    for_each_fasta_rec(&paths[0], false, &mut |r| {
+        let (q_name, bgn, end) = parse_unaligned_header(&r.id);
+        if let Some(intervals) = unaligned_intervals.get(&q_name) {
+            intervals
+                .iter()
+                .filter(|&&(s, e)| s >= bgn && e <= end && e - s >= min_len)
+                .for_each(|&(s, e)| {
+                    let seq = &r.seq[(s - bgn) as usize..(e - bgn) as usize];
+                    if write_result.is_ok() {
+                        write_result =
+                            out_unaligned.write_fasta(&format!("{}:{}-{}", q_name, s, e), seq);
+                    }
+                });
+        }
+    })?;
+    write_result
+}
+
+fn main() -> Result<(), std::io::Error> {
+    CmdOptions::command().version(VERSION_STRING).get_matches();
+    let args = CmdOptions::parse();
+
+    merge_aln_files(&args, "alnmap")?;
+    if args
+        .input_prefixes
+        .iter()
+        .any(|prefix| Path::new(prefix).with_extension("svcnd.seqs").exists())
+    {
+        merge_aln_files(&args, "svcnd.seqs")?;
+    }
+    BED_EXTENSIONS
+        .iter()
+        .try_for_each(|ext| merge_bed_files(&args, ext))?;
+
+    let (mut records, target_length, query_length, config) = read_ctgmap_files(&args);
+    let config_value = |key: &str, default: u64| {
+        config
+            .as_ref()
+            .and_then(|config| config[key].as_u64())
+            .unwrap_or(default) as u32
+    };
+    let max_gap = config_value("max_gap", 100000);
+    let unaligned_min_len = config_value("unaligned_min_len", 500);
+
+    let mut query_ids = FxHashMap::<String, u32>::default();
+    let mut query_len = FxHashMap::<String, u32>::default();
+    if let Value::Array(v) = &query_length {
+        v.iter().for_each(|v| {
+            let name = v[1].as_str().unwrap().to_string();
+            query_ids.insert(name.clone(), v[0].as_u64().unwrap() as u32);
+            query_len.insert(name, v[2].as_u64().unwrap() as u32);
+        });
+    }
+
+    let query_blocks = query_blocks(&records, &query_ids);
+    let query_aln_bed_records = mark_query_blocks(&mut records, &query_blocks, &query_len);
+    let mut translocation_calls = query_blocks
+        .iter()
+        .flat_map(|(q_id, blocks)| call_translocations(&records, *q_id, blocks, max_gap))
+        .collect::<Vec<_>>();
+    translocation_calls.sort_by_key(|v| (v.q_id, v.q_bgn, v.q_end));
+
+    let break_end_positions = translocation_calls
+        .iter()
+        .flat_map(|tra| tra.break_ends.iter().map(|be| (be.0.clone(), be.1)))
+        .collect::<FxHashSet<_>>();
+    let (ref_seqs, ref_bases) = read_reference(&args.reference_fasta_path, &break_end_positions)?;
+    let ref_order = ref_seqs
+        .iter()
+        .enumerate()
+        .map(|(idx, (name, _))| (name.as_str(), idx))
+        .collect::<FxHashMap<_, _>>();
+
+    // the contig maps sorted by the reference order as the ctgmap.bed of a single run
+    records.sort_by_key(|r| (ref_order.get(r.t_name.as_str()).copied(), r.ts));
+    let mut out_ctgmap = BufWriter::new(File::create(
+        Path::new(&args.output_prefix).with_extension("ctgmap.bed"),
+    )?);
+    for r in records.iter() {
+        writeln!(
+            out_ctgmap,
+            "{}\t{}\t{}\t{}:{}:{}:{}:{}:{}:{}:{}:{}:{}",
+            r.t_name,
+            r.ts,
+            r.te,
+            r.q_name,
+            r.qs,
+            r.qe,
+            r.ctg_len,
+            r.orientation,
+            r.ctg_orientation,
+            r.t_dup as u8,
+            r.t_ovlp as u8,
+            r.q_dup as u8,
+            r.q_ovlp as u8
+        )?;
+    }
+
+    let mut out_ctgsv = BufWriter::new(File::create(
+        Path::new(&args.output_prefix).with_extension("ctgsv.bed"),
+    )?);
+    for r in query_aln_bed_records {
+        writeln!(out_ctgsv, "{}\t{}\t{}\t{}", r.0, r.1, r.2, r.3)?;
+    }
+
+    let mut ctg_map_set = serde_json::Map::new();
+    ctg_map_set.insert(
+        "records".to_string(),
+        serde_json::to_value(&records).expect("fail to construct json for ctg map"),
+    );
+    ctg_map_set.insert("target_length".to_string(), target_length.clone());
+    ctg_map_set.insert("query_length".to_string(), query_length.clone());
+    if let Some(config) = config {
+        ctg_map_set.insert("config".to_string(), config);
+    }
+    [
+        ("ctgmap.json", Value::Object(ctg_map_set)),
+        ("target_len.json", target_length),
+        ("query_len.json", query_length),
+    ]
+    .into_iter()
+    .try_for_each(|(ext, value)| -> Result<(), std::io::Error> {
+        let mut out_file = BufWriter::new(File::create(
+            Path::new(&args.output_prefix).with_extension(ext),
+        )?);
+        let json = serde_json::to_string(&value).expect("fail to construct json for ctg map");
+        writeln!(out_file, "{}", json)
+    })?;
+
+    let (target_duplicate_intervals, target_overlap_intervals) =
+        read_target_intervals(&args.output_prefix);
+    let mut out_tra = BufWriter::new(File::create(
+        Path::new(&args.output_prefix).with_extension("tra.bed"),
+    )?);
+    let mut break_end_records = Vec::<(String, u32, String)>::new();
+    for (tra_idx, tra) in translocation_calls.iter().enumerate() {
+        let ids = [
+            format!("BND{:06}_1", tra_idx),
+            format!("BND{:06}_2", tra_idx),
+        ];
+        for i in 0..2 {
+            let (t_name, pos, ref_base_first, mate_extends_right) = &tra.break_ends[i];
+            let (mate_name, mate_pos, _, _) = &tra.break_ends[1 - i];
+            writeln!(
+                out_tra,
+                "{}\t{}\t{}\tTRA:{}:{}-{}:{}:{}:{}",
+                t_name,
+                pos - 1,
+                pos,
+                tra.q_name,
+                tra.q_bgn,
+                tra.q_end,
+                mate_name,
+                mate_pos,
+                ids[i]
+            )?;
+            let ref_base = ref_bases.get(&(t_name.clone(), *pos)).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} is not found in the reference file", t_name),
+                )
+            })?;
+            let alt = break_end_alt(
+                ref_base,
+                mate_name,
+                *mate_pos,
+                *ref_base_first,
+                *mate_extends_right,
+            );
+            let (ts, te) = tra.target_spans[i];
+            let has_overlap = |intervals: &FxHashMap<String, IntervalSet<u32>>| {
+                te > ts
+                    && intervals
+                        .get(t_name)
+                        .is_some_and(|intervals| intervals.has_overlap(ts..te))
+            };
+            let filter = if has_overlap(&target_duplicate_intervals) {
+                "DUP"
+            } else if has_overlap(&target_overlap_intervals) {
+                "OVLP"
+            } else {
+                "PASS"
+            };
+            let qv: u32 = if filter != "PASS" { 10 } else { 60 };
+            break_end_records.push((
+                t_name.clone(),
+                *pos,
+                format!(
+                    "{}\t{}\t{}\t{}\t{}\t{}\t{}\tSVTYPE=BND;MATEID={}",
+                    t_name,
+                    pos,
+                    ids[i],
+                    ref_base,
+                    alt,
+                    qv,
+                    filter,
+                    ids[1 - i]
+                ),
+            ));
+        }
+    }
+    merge_vcf_files(&args, &ref_seqs, break_end_records)?;
+    merge_unaligned_files(&args, unaligned_min_len)?;
+
+    let stderr = io::stderr();
+    let mut handle = stderr.lock();
+    let _ = writeln!(
+        handle,
+        "{} partitions merged to {}, {} translocations",
+        args.input_prefixes.len(),
+        args.output_prefix,
+        translocation_calls.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctgmap_rec(t_name: &str, ts: u32, te: u32, qs: u32, qe: u32, orientation: u32) -> CtgMapRec {
+        CtgMapRec {
+            t_name: t_name.to_string(),
+            ts,
+            te,
+            q_name: "ctg".to_string(),
+            qs,
+            qe,
+            ctg_len: 200000,
+            orientation,
+            ctg_orientation: 0,
+            t_dup: false,
+            t_ovlp: false,
+            q_dup: false,
+            q_ovlp: false,
+        }
+    }
+
+    #[test]
+    fn test_intersect_intervals() {
+        let a = vec![(0, 100), (200, 300), (400, 500)];
+        let b = vec![(50, 250), (280, 450)];
+        assert_eq!(
+            intersect_intervals(&a, &b),
+            vec![(50, 100), (200, 250), (280, 300), (400, 450)]
+        );
+        assert!(intersect_intervals(&a, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_query_blocks_across_partitions() {
+        // the blocks of a contig aligned to the sequences of two partitions
+        let mut records = vec![
+            ctgmap_rec("chr1", 1000, 61000, 100, 60000, 0),
+            ctgmap_rec("chr9", 5000, 85000, 59900, 139900, 0),
+            ctgmap_rec("chr9", 90000, 100000, 70000, 80000, 0),
+        ];
+        let query_ids = FxHashMap::from_iter([("ctg".to_string(), 0)]);
+        let query_len = FxHashMap::from_iter([("ctg".to_string(), 150000)]);
+        let blocks = query_blocks(&records, &query_ids);
+        assert_eq!(blocks, vec![(0, vec![0, 1, 2])]);
+
+        let bed_records = mark_query_blocks(&mut records, &blocks, &query_len);
+        assert!(records[1].q_ovlp && !records[1].q_dup);
+        assert!(records[2].q_dup);
+        assert_eq!(bed_records.len(), 4);
+        assert_eq!(
+            bed_records[3],
+            ("ctg".to_string(), 139900, 150000, "QG:chr9>END".to_string())
+        );
+
+        // the duplicated block is skipped, the junction between chr1 and chr9 is called
+        let calls = call_translocations(&records, 0, &blocks[0].1, 1000);
+        assert_eq!(calls.len(), 1);
+        assert_eq!((calls[0].q_bgn, calls[0].q_end), (59900, 60000));
+        assert_eq!(
+            calls[0].break_ends[0],
+            ("chr1".to_string(), 61000, true, true)
+        );
+        assert_eq!(
+            calls[0].break_ends[1],
+            ("chr9".to_string(), 5001, false, false)
+        );
+        assert!(call_translocations(&records, 0, &blocks[0].1, 10).is_empty());
+    }
+}
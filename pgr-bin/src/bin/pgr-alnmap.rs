@@ -2,14 +2,18 @@ const VERSION_STRING: &str = env!("VERSION_STRING");
 use clap::{self, CommandFactory, Parser};
 use iset::set::IntervalSet;
 use pgr_db::aln;
+use pgr_db::ec::{self, PolishedSvAllele};
 use pgr_db::ext::{get_fastx_reader, GZFastaReader, SeqIndexDB};
-use pgr_db::fasta_io::{reverse_complement, SeqRec};
+use pgr_db::fasta_io::{reverse_complement, FastxWriter, SeqRec};
+use pgr_db::gff_db::GFFDB;
+use pgr_db::seq_db;
 use rayon::prelude::*;
 use rustc_hash::{FxHashMap, FxHashSet};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::fs::File;
-use std::io::{self, BufWriter, Write};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
 use std::path::Path;
+use std::sync::Mutex;
 
 #[derive(Clone, Copy, clap::ValueEnum, Default, Debug)]
 enum OptPreset {
@@ -39,6 +43,10 @@ struct CmdOptions {
     #[clap(long, default_value_t, value_enum)]
     preset: OptPreset,
 
+    /// load the full parameter set from a JSON or TOML (with the .toml extension) configuration file, the preset and the parameter options are ignored
+    #[clap(long, default_value = None)]
+    config: Option<String>,
+
     ///number of threads used in parallel (more memory usage), default to "0" using all CPUs available or the number set by RAYON_NUM_THREADS
     #[clap(long, default_value_t = 0)]
     number_of_thread: usize,
@@ -63,10 +71,52 @@ struct CmdOptions {
     #[clap(long, short, default_value_t = 1024)]
     max_sw_aln_size: u32,
 
+    /// the band width of the banded SW alignments for the SV candidates longer than max_sw_aln_size, the banded alignments
+    /// use at most the memory of a SW alignment of max_sw_aln_size, "0" to disable
+    #[clap(long, default_value_t = 64)]
+    sw_band_width: u32,
+
+    /// the match score for the base level SW alignments (the WFA alignments always use zero)
+    #[clap(long, default_value_t = 0)]
+    match_score: i32,
+
+    /// the mismatch penalty for the base level alignments (WFA and SW)
+    #[clap(long, default_value_t = 4)]
+    mismatch_penalty: i32,
+
+    /// the gap open penalty for the base level alignments (WFA and SW)
+    #[clap(long, default_value_t = 4)]
+    gap_open_penalty: i32,
+
+    /// the gap extension penalty for the base level alignments (WFA and SW)
+    #[clap(long, default_value_t = 1)]
+    gap_extension_penalty: i32,
+
+    /// the max distance (wavefront length) of the WFA alignments, segments need a longer wavefront are reported as SV candidates
+    #[clap(long, default_value_t = 384)]
+    wfa_max_distance: u32,
+
+    /// extend the alignments at the contig ends into the unanchored terminal sequences up to this length, "0" to disable
+    #[clap(long, default_value_t = 0)]
+    end_ext_max_len: u32,
+
+    /// the min length of the query intervals without alignments to be written to <OUTPUT_PREFIX>.unaligned.fa
+    #[clap(long, default_value_t = 500)]
+    unaligned_min_len: u32,
+
     /// the gap penalty factor for sparse alignments in the SHIMMER space
     #[clap(long, default_value_t = 0.025)]
     gap_penalty_factor: f32,
 
+    /// the gap length beyond which the long gap penalty factor is used for the sparse alignments, "0" for the
+    /// linear gap cost, a smaller long gap penalty factor keeps the chains from breaking at the SVs
+    #[clap(long, default_value_t = 0)]
+    long_gap_break_len: u32,
+
+    /// the gap penalty factor for the gap bases beyond the long gap break length
+    #[clap(long, default_value_t = 0.0025)]
+    long_gap_penalty_factor: f32,
+
     /// the max gap length allowed in the alignment blocks
     #[clap(long, default_value_t = 100000)]
     max_gap: u32,
@@ -75,9 +125,70 @@ struct CmdOptions {
     #[clap(long, default_value_t = 8)]
     max_aln_chain_span: u32,
 
+    /// skip the anchors of the shimmer pairs occurring more than this number of times in the reference index (e.g., in satellite arrays), "0" for no limit
+    #[clap(long, default_value_t = 0)]
+    max_index_count: u32,
+
+    /// the min length of the query intervals without alignments to re-align with a denser shimmer spec in a second pass, "0" to skip the second pass,
+    /// the second pass builds a second index of the reference sequences aligned by the contigs with the unaligned intervals
+    #[clap(long, default_value_t = 0)]
+    rescue_min_len: u32,
+
+    /// minimizer window size for the second pass alignment
+    #[clap(long, default_value_t = 24)]
+    rescue_w: u32,
+
+    /// sparse minimizer (shimmer) reduction factor for the second pass alignment
+    #[clap(long, default_value_t = 1)]
+    rescue_r: u32,
+
+    /// only index and align to the reference sequences listed in the file (one name per line) to partition a run by the reference
+    /// sequences, the outputs of the partitions can be merged with pgr-alnmap-merge
+    #[clap(long, default_value = None)]
+    targets: Option<String>,
+
+    /// re-align the SV candidate segments failing the base level alignment with the unique k-mer anchors
+    #[clap(long, default_value_t = false)]
+    realign_sv_cnd: bool,
+
+    /// merge the SV candidates of a contig separated by no more than this distance on both the target and the query, "0" for no merging
+    #[clap(long, default_value_t = 0)]
+    sv_cnd_merge_distance: u32,
+
+    /// polish the alternative alleles of the SV candidates with the query segments (and the reads with
+    /// `--sv-polish-reads`), the polished alleles are written to the VCF output as the sequence alleles
+    #[clap(long, default_value_t = false)]
+    polish_sv_cnd: bool,
+
+    /// the reads (fasta/fastq) for polishing the SV candidates, the reads sharing at least `--sv-polish-min-cov`
+    /// shimmer pairs with the query segment of a SV candidate are used
+    #[clap(long, default_value = None)]
+    sv_polish_reads: Option<String>,
+
+    /// the min coverage of the consensus nodes for polishing the SV candidates with the reads
+    #[clap(long, default_value_t = 2)]
+    sv_polish_min_cov: u32,
+
+    /// skip polishing the SV candidates longer than this length on the target or the query
+    #[clap(long, default_value_t = 50000)]
+    sv_polish_max_len: u32,
+
     /// if specified, generate fasta files for the sequence covering the SV candidates
     #[clap(long, short, default_value_t = false)]
     skip_uncalled_sv_seq_file: bool,
+
+    /// write the alignments of the completed contigs to the checkpoint file <OUTPUT_PREFIX>.ckpt
+    #[clap(long, default_value_t = false)]
+    checkpoint: bool,
+
+    /// skip the contigs already aligned in the checkpoint file of a previous run, and continue the checkpoint
+    #[clap(long, default_value_t = false)]
+    resume: bool,
+
+    /// the GFF3/GTF annotation file of the reference, the small variants in the VCF output are annotated with
+    /// the consequences (intergenic, intronic, exonic or the CDS changes) and the genes in the INFO fields
+    #[clap(long, default_value = None)]
+    gff: Option<String>,
 }
 
 struct Parameters {
@@ -88,9 +199,99 @@ struct Parameters {
     max_sw_aln_size: u32,
 }
 
+// the effective parameter set of a run, it can be loaded from a configuration file and
+// it is recorded in the ctgmap.json output file
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+struct AlnMapConfig {
+    w: u32,
+    k: u32,
+    r: u32,
+    min_span: u32,
+    max_sw_aln_size: u32,
+    sw_band_width: u32,
+    match_score: i32,
+    mismatch_penalty: i32,
+    gap_open_penalty: i32,
+    gap_extension_penalty: i32,
+    wfa_max_distance: u32,
+    gap_penalty_factor: f32,
+    long_gap_break_len: u32,
+    long_gap_penalty_factor: f32,
+    max_gap: u32,
+    max_aln_chain_span: u32,
+    max_index_count: u32,
+    rescue_min_len: u32,
+    rescue_w: u32,
+    rescue_r: u32,
+    end_ext_max_len: u32,
+    unaligned_min_len: u32,
+    realign_sv_cnd: bool,
+    sv_cnd_merge_distance: u32,
+    polish_sv_cnd: bool,
+    sv_polish_min_cov: u32,
+    sv_polish_max_len: u32,
+    skip_uncalled_sv_seq_file: bool,
+}
+
+impl Default for AlnMapConfig {
+    fn default() -> Self {
+        // the default preset
+        AlnMapConfig {
+            w: 48,
+            k: 55,
+            r: 2,
+            min_span: 16,
+            max_sw_aln_size: 1 << 10,
+            sw_band_width: 64,
+            match_score: 0,
+            mismatch_penalty: 4,
+            gap_open_penalty: 4,
+            gap_extension_penalty: 1,
+            wfa_max_distance: 384,
+            gap_penalty_factor: 0.025,
+            long_gap_break_len: 0,
+            long_gap_penalty_factor: 0.0025,
+            max_gap: 100000,
+            max_aln_chain_span: 8,
+            max_index_count: 0,
+            rescue_min_len: 0,
+            rescue_w: 24,
+            rescue_r: 1,
+            end_ext_max_len: 0,
+            unaligned_min_len: 500,
+            realign_sv_cnd: false,
+            sv_cnd_merge_distance: 0,
+            polish_sv_cnd: false,
+            sv_polish_min_cov: 2,
+            sv_polish_max_len: 50000,
+            skip_uncalled_sv_seq_file: false,
+        }
+    }
+}
+
+fn load_config(path: &str) -> Result<AlnMapConfig, std::io::Error> {
+    let config = std::fs::read_to_string(path)?;
+    if path.ends_with(".toml") {
+        toml::from_str(&config)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))
+    } else {
+        serde_json::from_str(&config)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))
+    }
+}
+
+// the parameters of the base level alignments between the sparse alignment hits
+struct BaseAlnParameters {
+    max_sw_aln_size: u32,
+    sw_band_width: u32,
+    aln_params: aln::AlnParams,
+    realign_sv_cnd: bool,
+}
+
 type ShimmerMatchBlock = (u32, u32, u32, u32, u32, u32, u32); //t_idx, ts, ted, q_idx, ts, te, orientation
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 enum Record {
     Bgn(ShimmerMatchBlock, u32, u32), // MatchBlock, q_len, ctg_aln_orientation
     End(ShimmerMatchBlock, u32, u32), // MatchBlock, q_len, ctg_aln_orientation
@@ -106,7 +307,7 @@ type AlignSegments = Vec<AlignSegment>;
 
 type AlignmentResult = Vec<(u32, u32, char, String, String)>;
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 enum AlnDiff {
     Aligned(AlignmentResult),
     FailAln,
@@ -137,6 +338,7 @@ struct CtgMapSet {
     records: Vec<CtgMapRec>,
     target_length: Vec<(u32, String, u32)>,
     query_length: Vec<(u32, String, u32)>,
+    config: AlnMapConfig,
 }
 
 fn filter_aln(aln_segs: &AlignSegments) -> Vec<((u32, u32), (u32, u32))> {
@@ -208,6 +410,963 @@ fn filter_aln_rev(aln_segs: &AlignSegments) -> Vec<((u32, u32), (u32, u32))> {
     rtn
 }
 
+// the max overlap between the inverted block and the flanking blocks on the target for an inversion call
+const INV_MAX_FLANK_OVERLAP: u32 = 1000;
+// the window size for refining the inversion breakpoints
+const INV_BREAKPOINT_WINDOW: u32 = 2048;
+// the x-drop and the band width of the gapped extensions refining the inversion breakpoints
+const INV_EXT_X_DROP: i32 = 16;
+const INV_EXT_BAND_WIDTH: usize = 32;
+
+struct InversionCall {
+    t_idx: u32,
+    bgn: u32,
+    end: u32,
+    bgn_ci: u32,
+    end_ci: u32,
+    inverted_block: ShimmerMatchBlock,
+}
+
+// identify the inversions from the match blocks of a query contig sorted by the query coordinates,
+// two flanking blocks with the same orientation and an intervening block with the opposite orientation
+// on the same target, the breakpoints are refined by extending the local alignments around the junctions
+fn call_inversions(
+    match_blocks: &[(usize, ShimmerMatchBlock, u32, u32)],
+    query_seq: &[u8],
+    ref_seq_index_db: &SeqIndexDB,
+    max_gap: u32,
+) -> Vec<InversionCall> {
+    let q_len = query_seq.len() as u32;
+    let mut rc_query_seq = None;
+    match_blocks
+        .windows(3)
+        .filter_map(|blocks| {
+            let (_, b0, _, _) = blocks[0];
+            let (_, b1, _, _) = blocks[1];
+            let (_, b2, _, _) = blocks[2];
+            if b0.0 != b1.0 || b1.0 != b2.0 || b0.6 != b2.6 || b0.6 == b1.6 {
+                return None;
+            }
+            // normalize the blocks such that the flanking blocks are in the forward orientation
+            // (ts, te, qs, qe)
+            let coords = |b: ShimmerMatchBlock| (b.1, b.2, b.4.min(b.5), b.4.max(b.5));
+            let rc_coords = |b: ShimmerMatchBlock| {
+                let (ts, te, qs, qe) = coords(b);
+                (ts, te, q_len - qe, q_len - qs)
+            };
+            let (left, mid, right, query_seq) = if b0.6 == 0 {
+                (coords(b0), coords(b1), coords(b2), query_seq)
+            } else {
+                let rc_query_seq =
+                    rc_query_seq.get_or_insert_with(|| reverse_complement(query_seq));
+                (
+                    rc_coords(b2),
+                    rc_coords(b1),
+                    rc_coords(b0),
+                    &rc_query_seq[..],
+                )
+            };
+            if left.0 >= mid.0
+                || mid.1 >= right.1
+                || left.1 > mid.0 + INV_MAX_FLANK_OVERLAP
+                || mid.1 > right.0 + INV_MAX_FLANK_OVERLAP
+                || mid.0 > left.1 + max_gap
+                || right.0 > mid.1 + max_gap
+            {
+                return None;
+            }
+
+            let t_len = ref_seq_index_db
+                .seq_info
+                .as_ref()
+                .unwrap()
+                .get(&b0.0)
+                .unwrap()
+                .2;
+            let w = INV_BREAKPOINT_WINDOW;
+            let ref_sub_seq = |bgn: u32, end: u32| {
+                let end = std::cmp::min(end, t_len);
+                if bgn >= end {
+                    vec![]
+                } else {
+                    ref_seq_index_db
+                        .get_sub_seq_by_id(b0.0, bgn as usize, end as usize)
+                        .unwrap()
+                }
+            };
+            let query_sub_seq = |bgn: u32, end: u32| {
+                let end = std::cmp::min(end, q_len);
+                if bgn >= end {
+                    vec![]
+                } else {
+                    query_seq[bgn as usize..end as usize].to_vec()
+                }
+            };
+            let reversed = |mut s: Vec<u8>| {
+                s.reverse();
+                s
+            };
+            // the extended length of the reference
+            let x_drop_extend = |t_seq: &[u8], q_seq: &[u8]| {
+                aln::x_drop_extend(t_seq, q_seq, INV_EXT_X_DROP, INV_EXT_BAND_WIDTH).0
+            };
+
+            // the left breakpoint from the left flank and the inverted block
+            let bgn_l = left.1
+                + x_drop_extend(
+                    &ref_sub_seq(left.1, left.1 + w),
+                    &query_sub_seq(left.3, left.3 + w),
+                );
+            let bgn_r = mid.0
+                - x_drop_extend(
+                    &reverse_complement(&ref_sub_seq(mid.0.saturating_sub(w), mid.0)),
+                    &query_sub_seq(mid.3, mid.3 + w),
+                )
+                .min(mid.0);
+            // the right breakpoint from the inverted block and the right flank
+            let end_l = mid.1
+                + x_drop_extend(
+                    &ref_sub_seq(mid.1, mid.1 + w),
+                    &reverse_complement(&query_sub_seq(mid.2.saturating_sub(w), mid.2)),
+                );
+            let end_r = right.0
+                - x_drop_extend(
+                    &reversed(ref_sub_seq(right.0.saturating_sub(w), right.0)),
+                    &reversed(query_sub_seq(right.2.saturating_sub(w), right.2)),
+                )
+                .min(right.0);
+
+            let bgn = (bgn_l + bgn_r) / 2;
+            let end = (end_l + end_r) / 2;
+            if bgn == 0 || bgn >= end {
+                return None;
+            }
+            Some(InversionCall {
+                t_idx: b0.0,
+                bgn,
+                end,
+                bgn_ci: (bgn_l as i64 - bgn_r as i64).unsigned_abs() as u32 / 2,
+                end_ci: (end_l as i64 - end_r as i64).unsigned_abs() as u32 / 2,
+                inverted_block: b1,
+            })
+        })
+        .collect::<Vec<_>>()
+}
+
+// the k-mer size of the anchors for re-aligning the SV candidate segments
+const SV_REALIGN_ANCHOR_K: usize = 24;
+
+// the min overlap between the target intervals of two consecutive blocks to be considered as tandem copies
+const DUP_MIN_UNIT_OVERLAP: u32 = 256;
+
+struct DuplicationCall {
+    t_idx: u32,
+    bgn: u32,
+    end: u32,
+    copy_number: u32,
+    unit_len: u32,
+    q_idx: u32,
+    qs: u32,
+    qe: u32,
+}
+
+// estimate the repeat unit length from the offsets between the repeated shimmer pairs in the sequence
+fn estimate_unit_length(seq: Vec<u8>, ref_seq_index_db: &SeqIndexDB) -> Option<u32> {
+    let shmmr_spec = ref_seq_index_db.shmmr_spec.as_ref().unwrap();
+    let mut smp_positions = FxHashMap::<(u64, u64), Vec<u32>>::default();
+    ref_seq_index_db
+        .get_smps(seq, shmmr_spec)
+        .into_iter()
+        .for_each(|(s0, s1, p0, _, _)| smp_positions.entry((s0, s1)).or_default().push(p0));
+    let mut offsets = smp_positions
+        .into_values()
+        .flat_map(|mut positions| {
+            positions.sort();
+            positions
+                .windows(2)
+                .map(|p| p[1] - p[0])
+                .collect::<Vec<u32>>()
+        })
+        .collect::<Vec<u32>>();
+    if offsets.is_empty() {
+        return None;
+    }
+    offsets.sort();
+    Some(offsets[offsets.len() / 2])
+}
+
+// identify the tandem duplications from the match blocks of a query contig sorted by the query coordinates,
+// consecutive blocks on the same target with the same orientation restarting inside the target interval of
+// the previous block are counted as the copies of the duplicated unit
+fn call_tandem_duplications(
+    match_blocks: &[(usize, ShimmerMatchBlock, u32, u32)],
+    query_seq: &[u8],
+    ref_seq_index_db: &SeqIndexDB,
+) -> Vec<DuplicationCall> {
+    let mut calls = Vec::<DuplicationCall>::new();
+    let mut idx = 0;
+    while idx < match_blocks.len() {
+        let mut dup_interval: Option<(u32, u32)> = None;
+        let mut next_idx = idx + 1;
+        while next_idx < match_blocks.len() {
+            let (_, b0, _, _) = match_blocks[next_idx - 1];
+            let (_, b1, _, _) = match_blocks[next_idx];
+            if b0.0 != b1.0 || b0.6 != b1.6 {
+                break;
+            }
+            // the target interval covered by both copies
+            let (bgn, end) = if b0.6 == 0 {
+                (b1.1, b0.2)
+            } else {
+                (b0.1, b1.2)
+            };
+            if end < bgn + DUP_MIN_UNIT_OVERLAP {
+                break;
+            }
+            if let Some((d_bgn, d_end)) = dup_interval {
+                // the copies should cover the same unit, require 50% reciprocal overlap
+                let ovlp = std::cmp::min(end, d_end).saturating_sub(std::cmp::max(bgn, d_bgn));
+                if 2 * ovlp < end - bgn || 2 * ovlp < d_end - d_bgn {
+                    break;
+                }
+            } else {
+                dup_interval = Some((bgn, end));
+            }
+            next_idx += 1;
+        }
+        if let Some((bgn, end)) = dup_interval {
+            let blocks = &match_blocks[idx..next_idx];
+            let qs = blocks.iter().map(|v| v.1 .4.min(v.1 .5)).min().unwrap();
+            let qe = blocks
+                .iter()
+                .map(|v| v.1 .4.max(v.1 .5))
+                .max()
+                .unwrap()
+                .min(query_seq.len() as u32);
+            let unit_len = estimate_unit_length(
+                query_seq[qs as usize..qe as usize].to_vec(),
+                ref_seq_index_db,
+            )
+            .unwrap_or(end - bgn);
+            calls.push(DuplicationCall {
+                t_idx: blocks[0].1 .0,
+                bgn,
+                end,
+                copy_number: blocks.len() as u32,
+                unit_len,
+                q_idx: blocks[0].1 .3,
+                qs,
+                qe,
+            });
+        }
+        idx = next_idx;
+    }
+    calls
+}
+
+// the min target span of the blocks flanking a translocation junction
+const TRA_MIN_FLANK_LEN: u32 = 10000;
+
+// (t_idx, pos, the ref base is before the joined piece, the joined piece extends to the right of the mate position)
+type BreakEnd = (u32, u32, bool, bool);
+
+struct TranslocationCall {
+    q_idx: u32,
+    q_bgn: u32,
+    q_end: u32,
+    break_ends: [BreakEnd; 2],
+    match_blocks: [ShimmerMatchBlock; 2],
+}
+
+// identify the translocations from the match blocks of a query contig sorted by the query coordinates,
+// two adjacent blocks on the query mapped to different targets make a pair of break ends, the positions
+// are where the query leaves the first block and enters the second block on the targets (1-based)
+fn call_translocations(
+    match_blocks: &[(usize, ShimmerMatchBlock, u32, u32)],
+    max_gap: u32,
+) -> Vec<TranslocationCall> {
+    match_blocks
+        .windows(2)
+        .filter_map(|blocks| {
+            let (_, b0, _, _) = blocks[0];
+            let (_, b1, _, _) = blocks[1];
+            if b0.0 == b1.0 || b0.2 < b0.1 + TRA_MIN_FLANK_LEN || b1.2 < b1.1 + TRA_MIN_FLANK_LEN {
+                return None;
+            }
+            let qe0 = b0.4.max(b0.5);
+            let qs1 = b1.4.min(b1.5);
+            let (q_bgn, q_end) = if qe0 < qs1 { (qe0, qs1) } else { (qs1, qe0) };
+            if q_end - q_bgn > max_gap {
+                return None;
+            }
+            let pos0 = if b0.6 == 0 { b0.2 } else { b0.1 + 1 };
+            let pos1 = if b1.6 == 0 { b1.1 + 1 } else { b1.2 };
+            Some(TranslocationCall {
+                q_idx: b0.3,
+                q_bgn,
+                q_end,
+                break_ends: [
+                    (b0.0, pos0, b0.6 == 0, b1.6 == 0),
+                    (b1.0, pos1, b1.6 == 1, b0.6 == 1),
+                ],
+                match_blocks: [b0, b1],
+            })
+        })
+        .collect::<Vec<_>>()
+}
+
+// the ALT field of a break end in the VCF notation, e.g. "t[p[" or "]p]t"
+fn break_end_alt(
+    ref_base: &str,
+    mate_name: &str,
+    mate_pos: u32,
+    ref_base_first: bool,
+    mate_extends_right: bool,
+) -> String {
+    let bracket = if mate_extends_right { '[' } else { ']' };
+    if ref_base_first {
+        format!(
+            "{}{}{}:{}{}",
+            ref_base, bracket, mate_name, mate_pos, bracket
+        )
+    } else {
+        format!(
+            "{}{}:{}{}{}",
+            bracket, mate_name, mate_pos, bracket, ref_base
+        )
+    }
+}
+
+// a merged SV candidate: the interval spanning the candidates, the diff types of the candidates,
+// the contig orientation and the number of the candidates merged
+type MergedSvCandidate = (ShimmerMatchBlock, String, u32, usize);
+
+// merge the SV candidates of the same target, query contig and orientations within `max_distance`
+// of each other on both the target and the query, the diff types of the merged candidates are concatenated
+fn merge_sv_candidates(
+    sv_cnd_records: &[(ShimmerMatchBlock, char, u32)],
+    max_distance: u32,
+) -> Vec<MergedSvCandidate> {
+    let mut sv_cnd_records = sv_cnd_records.to_vec();
+    sv_cnd_records.sort_by_key(
+        |((t_idx, ts, te, q_idx, _, _, orientation), _, ctg_orientation)| {
+            (*t_idx, *q_idx, *orientation, *ctg_orientation, *ts, *te)
+        },
+    );
+    let mut merged = Vec::<MergedSvCandidate>::new();
+    sv_cnd_records
+        .into_iter()
+        .for_each(|(block, diff_type, ctg_orientation)| {
+            let (t_idx, ts, te, q_idx, qs, qe, orientation) = block;
+            if let Some((m_block, m_diff_types, m_ctg_orientation, count)) = merged.last_mut() {
+                let (m_t_idx, m_ts, m_te, m_q_idx, m_qs, m_qe, m_orientation) = *m_block;
+                if (m_t_idx, m_q_idx, m_orientation, *m_ctg_orientation)
+                    == (t_idx, q_idx, orientation, ctg_orientation)
+                    && ts <= m_te + max_distance
+                    && qs <= m_qe + max_distance
+                    && m_qs <= qe + max_distance
+                {
+                    *m_block = (
+                        t_idx,
+                        m_ts.min(ts),
+                        m_te.max(te),
+                        q_idx,
+                        m_qs.min(qs),
+                        m_qe.max(qe),
+                        orientation,
+                    );
+                    if !m_diff_types.contains(diff_type) {
+                        m_diff_types.push(diff_type);
+                    }
+                    *count += 1;
+                    return;
+                }
+            }
+            merged.push((block, diff_type.to_string(), ctg_orientation, 1));
+        });
+    merged.sort();
+    merged
+}
+
+// convert the sparse alignment hits of a query sequence to the alignment records of the chains,
+// the base level alignments are computed for the segments between the hits
+fn query_hits_to_records(
+    q_idx: usize,
+    query_seq: &[u8],
+    qr: Vec<(u32, Vec<(f32, Vec<aln::HitPair>)>)>,
+    ref_seq_index_db: &SeqIndexDB,
+    kmer_size: u32,
+    base_aln_parameters: &BaseAlnParameters,
+) -> Vec<Vec<Record>> {
+    let BaseAlnParameters {
+        max_sw_aln_size,
+        sw_band_width,
+        aln_params,
+        realign_sv_cnd,
+    } = *base_aln_parameters;
+    let q_len: usize = query_seq.len();
+    let mut target_id_to_mapped_regions = FxHashMap::default();
+    let mut target_id_to_orientation_len_count = FxHashMap::default();
+    qr.into_iter().for_each(|(t_idx, mapped_segments)| {
+        let mut aln_lens = vec![];
+        let mut ctg_orientation_count = (0_usize, 0_usize); // ctg level orientation count: (fwd_count, rev_count)
+        mapped_segments.into_iter().for_each(|(_score, aln)| {
+            let mut segment_orientation_count = (0_usize, 0_usize); // ctg level orientation count: (fwd_count, rev_count)
+            if aln.len() > 2 {
+                aln_lens.push(aln.len());
+                for hp in &aln {
+                    let seg_len = (hp.0 .1 - hp.0 .0) as usize;
+                    if hp.0 .2 == hp.1 .2 {
+                        ctg_orientation_count.0 += seg_len;
+                        segment_orientation_count.0 += seg_len;
+                    } else {
+                        ctg_orientation_count.1 += seg_len;
+                        segment_orientation_count.1 += seg_len;
+                    }
+                }
+                let seg_orientation = if segment_orientation_count.0 > segment_orientation_count.1 {
+                    0_u32
+                } else {
+                    1_u32
+                };
+
+                let e = target_id_to_mapped_regions
+                    .entry(t_idx)
+                    .or_insert_with(Vec::new);
+                e.push((aln, seg_orientation))
+            }
+            let ctg_orientation = if ctg_orientation_count.0 > ctg_orientation_count.1 {
+                0_u32
+            } else {
+                1_u32
+            };
+            let e = target_id_to_orientation_len_count
+                .entry(t_idx)
+                .or_insert(((0, 0), 0));
+            *e = (ctg_orientation_count, ctg_orientation);
+        })
+    });
+
+    target_id_to_mapped_regions
+        .into_iter()
+        .flat_map(|(t_idx, mapped_regions)| {
+            let ref_seq = ref_seq_index_db.get_seq_by_id(t_idx).unwrap();
+            let mapped_region_aln = mapped_regions
+                .into_par_iter()
+                .map(|(aln_segs, orientation)| {
+                    let aln_segs = if orientation == 0 {
+                        filter_aln(&aln_segs)
+                    } else {
+                        filter_aln_rev(&aln_segs)
+                    };
+
+                    aln_segs
+                        .into_iter()
+                        .map(|((ts, te), (qs, qe))| {
+                            let ts = ts - kmer_size; // add one to ensure a match base if the first call is deletion
+                                                     //let te = te;
+                            let qs = if orientation == 0 { qs - kmer_size } else { qs };
+                            let qe = if orientation == 0 { qe } else { qe + kmer_size };
+                            let s0str = ref_seq[ts as usize..te as usize].to_vec();
+                            let s1str = if orientation == 0 {
+                                query_seq[qs as usize..qe as usize].to_vec()
+                            } else {
+                                reverse_complement(
+                                    &query_seq
+                                        [(qs - kmer_size) as usize..(qe - kmer_size) as usize],
+                                )
+                            };
+
+                            let wf_aln_diff: AlnDiff = if s0str.len() <= 16 || s1str.len() <= 16 {
+                                AlnDiff::FailShortSeq
+                            } else if s0str[..16] != s1str[..16]
+                                || s0str[s0str.len() - 16..] != s1str[s1str.len() - 16..]
+                            {
+                                AlnDiff::FailEndMatch
+                            } else if (s0str.len() as isize - s1str.len() as isize).abs() >= 128 {
+                                // AlnDiff::FailLengthDiff
+                                if s0str.len() < max_sw_aln_size as usize
+                                    && s1str.len() < max_sw_aln_size as usize
+                                {
+                                    if let Some(aln_res) =
+                                        aln::get_sw_variant_segments(&s0str, &s1str, 1, &aln_params)
+                                    {
+                                        AlnDiff::Aligned(aln_res)
+                                    } else {
+                                        AlnDiff::FailAln
+                                    }
+                                } else if sw_band_width > 0
+                                    && aln::banded_sw_cell_count(
+                                        s0str.len(),
+                                        s1str.len(),
+                                        sw_band_width as usize,
+                                    ) < (max_sw_aln_size as usize).pow(2)
+                                {
+                                    if let Some(aln_res) = aln::get_banded_sw_variant_segments(
+                                        &s0str,
+                                        &s1str,
+                                        1,
+                                        sw_band_width as usize,
+                                        &aln_params,
+                                    ) {
+                                        AlnDiff::Aligned(aln_res)
+                                    } else {
+                                        AlnDiff::FailAln
+                                    }
+                                } else {
+                                    AlnDiff::FailLengthDiff
+                                }
+                            } else if let Some(aln_res) =
+                                aln::get_wfa_variant_segments(&s0str, &s1str, 1, &aln_params)
+                            {
+                                AlnDiff::Aligned(aln_res)
+                            } else {
+                                AlnDiff::FailAln
+                            };
+                            let wf_aln_diff = match wf_aln_diff {
+                                diff @ (AlnDiff::FailAln | AlnDiff::FailLengthDiff)
+                                    if realign_sv_cnd =>
+                                {
+                                    // the segments are flanked by the shimmer matches, so the ends are identical
+                                    if let Some(aln_res) = aln::get_anchored_variant_segments(
+                                        &s0str,
+                                        &s1str,
+                                        SV_REALIGN_ANCHOR_K,
+                                        max_sw_aln_size as usize,
+                                        &aln_params,
+                                    ) {
+                                        AlnDiff::Aligned(aln_res)
+                                    } else {
+                                        diff
+                                    }
+                                }
+                                diff => diff,
+                            };
+                            ((ts, te), (qs, qe), orientation, wf_aln_diff)
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .filter(|v| !v.is_empty())
+                .collect::<Vec<_>>();
+
+            let (_, ctg_orientation) = target_id_to_orientation_len_count.get(&t_idx).unwrap();
+
+            mapped_region_aln
+                .into_iter()
+                .map(|v| {
+                    let mut output_records = Vec::<Record>::new();
+                    let ((ts, te), (qs, qe), orientation, _diff) = v[0].clone();
+                    let qs = if orientation == 0 { qs } else { qs - kmer_size };
+                    let qe = if orientation == 0 { qe } else { qe - kmer_size };
+                    output_records.push(Record::Bgn(
+                        (t_idx, ts, te, q_idx as u32, qs, qe, orientation),
+                        q_len as u32,
+                        *ctg_orientation,
+                    ));
+                    let v_last = v.last().unwrap().clone();
+                    v.into_iter()
+                        .for_each(|((ts, te), (qs, qe), orientation, diff)| {
+                            let qs = if orientation == 0 { qs } else { qs - kmer_size };
+                            let qe = if orientation == 0 { qe } else { qe - kmer_size };
+                            if let AlnDiff::Aligned(diff) = diff {
+                                if diff.is_empty() {
+                                    output_records.push(Record::Match((
+                                        t_idx,
+                                        ts,
+                                        te,
+                                        q_idx as u32,
+                                        qs,
+                                        qe,
+                                        orientation,
+                                    )))
+                                } else {
+                                    diff.into_iter().for_each(|(td, qd, vt, t_str, q_str)| {
+                                        output_records.push(Record::Variant(
+                                            (t_idx, ts, te, q_idx as u32, qs, qe, orientation),
+                                            td,
+                                            qd,
+                                            ts + td,
+                                            vt,
+                                            t_str,
+                                            q_str,
+                                        ));
+                                    })
+                                }
+                            } else {
+                                output_records.push(Record::SvCnd((
+                                    (t_idx, ts, te, q_idx as u32, qs, qe, orientation),
+                                    diff,
+                                    *ctg_orientation,
+                                )));
+                            }
+                        });
+
+                    let ((ts, te), (qs, qe), orientation, _diff) = v_last;
+                    let qs = if orientation == 0 { qs } else { qs - kmer_size };
+                    let qe = if orientation == 0 { qe } else { qe - kmer_size };
+                    output_records.push(Record::End(
+                        (t_idx, ts, te, q_idx as u32, qs, qe, orientation),
+                        q_len as u32,
+                        *ctg_orientation,
+                    ));
+                    output_records
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>()
+}
+
+// query the reference index for the sparse alignments, the anchors of the shimmer pairs occurring
+// more than `max_index_count` times in the index are skipped to avoid the spurious chains in the repeats
+fn query_reference_index(
+    ref_seq_index_db: &SeqIndexDB,
+    query_seq: &Vec<u8>,
+    config: &AlnMapConfig,
+) -> Option<aln::TargetHitPairLists> {
+    let gap_cost = if config.long_gap_break_len == 0 {
+        aln::ChainGapCost::Linear(config.gap_penalty_factor)
+    } else {
+        aln::ChainGapCost::Piecewise {
+            penalty: config.gap_penalty_factor,
+            break_len: config.long_gap_break_len,
+            long_penalty: config.long_gap_penalty_factor,
+        }
+    };
+    if config.max_index_count == 0 {
+        return ref_seq_index_db.query_fragment_to_hps(
+            query_seq,
+            gap_cost,
+            Some(1),
+            Some(1),
+            Some(1),
+            Some(config.max_aln_chain_span),
+            Some(config.max_gap),
+            true,
+        );
+    }
+    let shmmr_spec = ref_seq_index_db.shmmr_spec.as_ref()?;
+    let frag_map = ref_seq_index_db.get_shmmr_map_internal()?;
+    let raw_query_hits = seq_db::raw_query_fragment(frag_map, query_seq, shmmr_spec);
+    // the hits of the repetitive shimmer pairs are not used
+    let (hit_pair_lists, _) = aln::query_fragment_to_unique_anchor_hps(
+        raw_query_hits,
+        query_seq,
+        shmmr_spec,
+        gap_cost,
+        Some(config.max_index_count),
+        Some(1),
+        Some(1),
+        Some(1),
+        Some(config.max_aln_chain_span),
+        Some(config.max_gap),
+        true,
+    );
+    Some(hit_pair_lists)
+}
+
+// the query interval (q_idx, qs, qe) covered by the records of an alignment chain
+fn aln_query_interval(records: &[Record]) -> Option<(u32, u32, u32)> {
+    let bgn = records.iter().find_map(|r| match r {
+        Record::Bgn(match_block, _, _) => Some(*match_block),
+        _ => None,
+    })?;
+    let end = records.iter().rev().find_map(|r| match r {
+        Record::End(match_block, _, _) => Some(*match_block),
+        _ => None,
+    })?;
+    let (qs, qe) = if bgn.6 == 0 {
+        (bgn.4, end.5)
+    } else {
+        (end.4, bgn.5)
+    };
+    Some((bgn.3, qs.min(qe), qs.max(qe)))
+}
+
+// the x-drop and the band width of the gapped extensions into the unanchored contig ends
+const END_EXT_X_DROP: i32 = 32;
+const END_EXT_BAND_WIDTH: usize = 64;
+
+// the target and the query intervals extending the alignment from the terminal block of an alignment chain
+// into the unanchored contig end, both ends of the intervals are matched bases
+fn end_extension(
+    block: ShimmerMatchBlock,
+    right_end: bool,
+    query_seq: &[u8],
+    ref_seq_index_db: &SeqIndexDB,
+    max_len: u32,
+) -> Option<((u32, u32), (u32, u32))> {
+    let (t_idx, ts, te, _q_idx, qs, qe, orientation) = block;
+    let t_len = ref_seq_index_db
+        .seq_info
+        .as_ref()
+        .unwrap()
+        .get(&t_idx)
+        .unwrap()
+        .2;
+    let q_len = query_seq.len() as u32;
+    let reversed = |mut s: Vec<u8>| {
+        s.reverse();
+        s
+    };
+    // extending toward the end of the target
+    let t_forward = right_end == (orientation == 0);
+    let t_seq = if t_forward {
+        ref_seq_index_db
+            .get_sub_seq_by_id(t_idx, te as usize, (te + max_len).min(t_len) as usize)
+            .unwrap()
+    } else {
+        reversed(
+            ref_seq_index_db
+                .get_sub_seq_by_id(t_idx, ts.saturating_sub(max_len) as usize, ts as usize)
+                .unwrap(),
+        )
+    };
+    let q_seq = if right_end {
+        &query_seq[qe as usize..(qe + max_len).min(q_len) as usize]
+    } else {
+        &query_seq[qs.saturating_sub(max_len) as usize..qs as usize]
+    };
+    let q_seq = match (right_end, orientation == 0) {
+        (true, true) => q_seq.to_vec(),
+        (true, false) => reversed(reverse_complement(q_seq)),
+        (false, true) => reversed(q_seq.to_vec()),
+        (false, false) => reverse_complement(q_seq),
+    };
+    let (t_ext, q_ext) = aln::x_drop_extend(&t_seq, &q_seq, END_EXT_X_DROP, END_EXT_BAND_WIDTH);
+    if t_ext == 0 || q_ext == 0 {
+        return None;
+    }
+    let t_interval = if t_forward {
+        (te - 1, te + t_ext)
+    } else {
+        (ts - t_ext, ts + 1)
+    };
+    let q_interval = if right_end {
+        (qe - 1, qe + q_ext)
+    } else {
+        (qs - q_ext, qs + 1)
+    };
+    Some((t_interval, q_interval))
+}
+
+// extend the alignments at both ends of each contig, the extensions are returned as new alignment chains
+fn extend_contig_ends(
+    all_records: &[Vec<Vec<Record>>],
+    query_seqs: &[SeqRec],
+    ref_seq_index_db: &SeqIndexDB,
+    max_len: u32,
+    base_aln_parameters: &BaseAlnParameters,
+) -> Vec<Vec<Record>> {
+    // q_idx -> (the left most chain, the right most chain)
+    let mut terminal_chains = FxHashMap::<u32, (&Vec<Record>, u32, &Vec<Record>, u32)>::default();
+    all_records.iter().flatten().for_each(|records| {
+        if let Some((q_idx, qs, qe)) = aln_query_interval(records) {
+            let e = terminal_chains
+                .entry(q_idx)
+                .or_insert((records, qs, records, qe));
+            if qs < e.1 {
+                (e.0, e.1) = (records, qs);
+            }
+            if qe > e.3 {
+                (e.2, e.3) = (records, qe);
+            }
+        }
+    });
+
+    let mut terminal_chains = terminal_chains.into_iter().collect::<Vec<_>>();
+    terminal_chains.sort_by_key(|v| v.0);
+    terminal_chains
+        .into_par_iter()
+        .flat_map(|(q_idx, (left_chain, _, right_chain, _))| {
+            let query_seq = &query_seqs[q_idx as usize].seq;
+            [(left_chain, false), (right_chain, true)]
+                .into_iter()
+                .filter_map(|(records, right_end)| {
+                    let (bgn_block, q_len, ctg_orientation) = match records.first() {
+                        Some(Record::Bgn(block, q_len, ctg_orientation)) => {
+                            (*block, *q_len, *ctg_orientation)
+                        }
+                        _ => return None,
+                    };
+                    let end_block = match records.last() {
+                        Some(Record::End(block, _, _)) => *block,
+                        _ => return None,
+                    };
+                    let orientation = bgn_block.6;
+                    // the terminal block on the query side to extend
+                    let block = if right_end == (orientation == 0) {
+                        end_block
+                    } else {
+                        bgn_block
+                    };
+                    let ((t0, t1), (q0, q1)) =
+                        end_extension(block, right_end, query_seq, ref_seq_index_db, max_len)?;
+                    let s0str = ref_seq_index_db
+                        .get_sub_seq_by_id(block.0, t0 as usize, t1 as usize)
+                        .unwrap();
+                    let s1str = if orientation == 0 {
+                        query_seq[q0 as usize..q1 as usize].to_vec()
+                    } else {
+                        reverse_complement(&query_seq[q0 as usize..q1 as usize])
+                    };
+                    let diff = aln::get_sw_variant_segments(
+                        &s0str,
+                        &s1str,
+                        1,
+                        &base_aln_parameters.aln_params,
+                    )?;
+                    let ext_block = (block.0, t0, t1, q_idx, q0, q1, orientation);
+                    let mut output_records = vec![Record::Bgn(ext_block, q_len, ctg_orientation)];
+                    if diff.is_empty() {
+                        output_records.push(Record::Match(ext_block));
+                    } else {
+                        diff.into_iter().for_each(|(td, qd, vt, t_str, q_str)| {
+                            output_records.push(Record::Variant(
+                                ext_block,
+                                td,
+                                qd,
+                                t0 + td,
+                                vt,
+                                t_str,
+                                q_str,
+                            ));
+                        });
+                    }
+                    output_records.push(Record::End(ext_block, q_len, ctg_orientation));
+                    Some(output_records)
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>()
+}
+
+// the query intervals (q_idx, bgn, end) not covered by any alignment chain and longer than min_len
+fn unaligned_query_intervals(
+    all_records: &[Vec<Vec<Record>>],
+    query_len: &FxHashMap<u32, usize>,
+    min_len: u32,
+) -> Vec<(u32, u32, u32)> {
+    let mut aligned_intervals = FxHashMap::<u32, Vec<(u32, u32)>>::default();
+    all_records.iter().flatten().for_each(|records| {
+        if let Some((q_idx, qs, qe)) = aln_query_interval(records) {
+            aligned_intervals.entry(q_idx).or_default().push((qs, qe));
+        }
+    });
+    let mut unaligned_intervals = Vec::<(u32, u32, u32)>::new();
+    let mut q_idxs = query_len.keys().copied().collect::<Vec<u32>>();
+    q_idxs.sort();
+    q_idxs.into_iter().for_each(|q_idx| {
+        let q_len = *query_len.get(&q_idx).unwrap() as u32;
+        let mut intervals = aligned_intervals.remove(&q_idx).unwrap_or_default();
+        intervals.sort();
+        intervals.push((q_len, q_len));
+        let mut last_end = 0_u32;
+        intervals.into_iter().for_each(|(qs, qe)| {
+            if qs > last_end && qs >= last_end + min_len {
+                unaligned_intervals.push((q_idx, last_end, qs));
+            }
+            last_end = last_end.max(qe);
+        });
+    });
+    unaligned_intervals
+}
+
+// the names of the reference sequences aligned in the first pass by the contigs with the unaligned intervals,
+// `None` for all the reference sequences if one of the contigs has no alignment
+fn rescue_targets(
+    all_records: &[Vec<Vec<Record>>],
+    unaligned_intervals: &[(u32, u32, u32)],
+    target_name: &FxHashMap<u32, String>,
+) -> Option<FxHashSet<String>> {
+    let mut aligned_targets = FxHashMap::<u32, FxHashSet<u32>>::default();
+    all_records.iter().flatten().flatten().for_each(|r| {
+        if let Record::Bgn(match_block, _, _) = r {
+            aligned_targets
+                .entry(match_block.3)
+                .or_default()
+                .insert(match_block.0);
+        }
+    });
+    let mut targets = FxHashSet::<String>::default();
+    for (q_idx, _, _) in unaligned_intervals {
+        targets.extend(
+            aligned_targets
+                .get(q_idx)?
+                .iter()
+                .map(|t_idx| target_name.get(t_idx).unwrap().clone()),
+        );
+    }
+    Some(targets)
+}
+
+// shift the query coordinates of the sparse alignment hits of a sub-sequence to the coordinates of the full query sequence
+fn shift_query_hits(
+    qr: Vec<(u32, Vec<(f32, Vec<aln::HitPair>)>)>,
+    offset: u32,
+) -> Vec<(u32, Vec<(f32, Vec<aln::HitPair>)>)> {
+    qr.into_iter()
+        .map(|(t_idx, mapped_segments)| {
+            let mapped_segments = mapped_segments
+                .into_iter()
+                .map(|(score, aln)| {
+                    let aln = aln
+                        .into_iter()
+                        .map(|((qs, qe, qo), t)| ((qs + offset, qe + offset, qo), t))
+                        .collect::<Vec<_>>();
+                    (score, aln)
+                })
+                .collect::<Vec<_>>();
+            (t_idx, mapped_segments)
+        })
+        .collect::<Vec<_>>()
+}
+
+// load the reference sequences and build the index, only the sequences in `targets` are loaded if it is specified
+fn load_reference_index(
+    ref_seq_index_db: &mut SeqIndexDB,
+    reference_fasta_path: &str,
+    targets: Option<&FxHashSet<String>>,
+    parameters: &Parameters,
+) -> Result<(), std::io::Error> {
+    let (w, k, r, min_span) = (
+        parameters.w,
+        parameters.k,
+        parameters.r,
+        parameters.min_span,
+    );
+    if let Some(targets) = targets {
+        let mut seq_list = Vec::<(String, Vec<u8>)>::new();
+        let mut add_seqs = |seq_iter: &mut dyn Iterator<Item = io::Result<SeqRec>>| {
+            seq_iter.into_iter().for_each(|r| {
+                if let Ok(r) = r {
+                    let name = String::from_utf8_lossy(&r.id[..]).to_string();
+                    if targets.contains(&name) {
+                        seq_list.push((name, r.seq.to_ascii_uppercase()));
+                    }
+                };
+            });
+        };
+
+        match get_fastx_reader(reference_fasta_path.to_string(), true)? {
+            #[allow(clippy::useless_conversion)] // the into_iter() is necessary for dyn patching
+            GZFastaReader::GZFile(reader) => add_seqs(&mut reader.into_iter()),
+
+            #[allow(clippy::useless_conversion)] // the into_iter() is necessary for dyn patching
+            GZFastaReader::RegularFile(reader) => add_seqs(&mut reader.into_iter()),
+        };
+
+        if seq_list.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "none of the target sequences is found in the reference file",
+            ));
+        }
+        ref_seq_index_db.load_from_seq_list(seq_list, None, w, k, r, min_span)
+    } else {
+        ref_seq_index_db.load_from_fastx(reference_fasta_path.to_string(), w, k, r, min_span, true)
+    }
+}
+
 fn main() -> Result<(), std::io::Error> {
     CmdOptions::command().version(VERSION_STRING).get_matches();
     let args = CmdOptions::parse();
@@ -250,15 +1409,72 @@ fn main() -> Result<(), std::io::Error> {
         },
     };
 
-    ref_seq_index_db.load_from_fastx(
-        args.reference_fasta_path,
-        parameters.w,
-        parameters.k,
-        parameters.r,
-        parameters.min_span,
-        true,
+    let config = if let Some(config_path) = args.config.as_ref() {
+        load_config(config_path)?
+    } else {
+        AlnMapConfig {
+            w: parameters.w,
+            k: parameters.k,
+            r: parameters.r,
+            min_span: parameters.min_span,
+            max_sw_aln_size: parameters.max_sw_aln_size,
+            sw_band_width: args.sw_band_width,
+            match_score: args.match_score,
+            mismatch_penalty: args.mismatch_penalty,
+            gap_open_penalty: args.gap_open_penalty,
+            gap_extension_penalty: args.gap_extension_penalty,
+            wfa_max_distance: args.wfa_max_distance,
+            gap_penalty_factor: args.gap_penalty_factor,
+            long_gap_break_len: args.long_gap_break_len,
+            long_gap_penalty_factor: args.long_gap_penalty_factor,
+            max_gap: args.max_gap,
+            max_aln_chain_span: args.max_aln_chain_span,
+            max_index_count: args.max_index_count,
+            rescue_min_len: args.rescue_min_len,
+            rescue_w: args.rescue_w,
+            rescue_r: args.rescue_r,
+            end_ext_max_len: args.end_ext_max_len,
+            unaligned_min_len: args.unaligned_min_len,
+            realign_sv_cnd: args.realign_sv_cnd,
+            sv_cnd_merge_distance: args.sv_cnd_merge_distance,
+            polish_sv_cnd: args.polish_sv_cnd,
+            sv_polish_min_cov: args.sv_polish_min_cov,
+            sv_polish_max_len: args.sv_polish_max_len,
+            skip_uncalled_sv_seq_file: args.skip_uncalled_sv_seq_file,
+        }
+    };
+    let parameters = Parameters {
+        w: config.w,
+        k: config.k,
+        r: config.r,
+        min_span: config.min_span,
+        max_sw_aln_size: config.max_sw_aln_size,
+    };
+
+    let targets = args.targets.as_ref().map(|targets| {
+        let targets =
+            BufReader::new(File::open(Path::new(targets)).expect("can't open the targets file"));
+        targets
+            .lines()
+            .map(|line| line.expect("can't read the targets file"))
+            .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+            .map(|line| line.split_whitespace().next().unwrap().to_string())
+            .collect::<FxHashSet<String>>()
+    });
+
+    load_reference_index(
+        &mut ref_seq_index_db,
+        &args.reference_fasta_path,
+        targets.as_ref(),
+        &parameters,
     )?;
 
+    let gff = if let Some(gff) = args.gff.as_ref() {
+        Some(GFFDB::from_file(Path::new(gff))?)
+    } else {
+        None
+    };
+
     let mut out_alnmap = BufWriter::new(
         File::create(Path::new(&args.output_prefix).with_extension("alnmap")).unwrap(),
     );
@@ -289,7 +1505,26 @@ fn main() -> Result<(), std::io::Error> {
     let mut out_ctgsv = BufWriter::new(
         File::create(Path::new(&args.output_prefix).with_extension("ctgsv.bed")).unwrap(),
     );
-    let mut out_sv_seq_file = if !args.skip_uncalled_sv_seq_file {
+
+    let mut out_inv = BufWriter::new(
+        File::create(Path::new(&args.output_prefix).with_extension("inv.bed")).unwrap(),
+    );
+
+    let mut out_dup = BufWriter::new(
+        File::create(Path::new(&args.output_prefix).with_extension("dup.bed")).unwrap(),
+    );
+
+    let mut out_tra = BufWriter::new(
+        File::create(Path::new(&args.output_prefix).with_extension("tra.bed")).unwrap(),
+    );
+
+    let mut out_unaligned = FastxWriter::new(
+        BufWriter::new(
+            File::create(Path::new(&args.output_prefix).with_extension("unaligned.fa")).unwrap(),
+        ),
+        0,
+    );
+    let mut out_sv_seq_file = if !config.skip_uncalled_sv_seq_file {
         Some(BufWriter::new(
             File::create(Path::new(&args.output_prefix).with_extension("svcnd.seqs")).unwrap(),
         ))
@@ -315,6 +1550,18 @@ fn main() -> Result<(), std::io::Error> {
     };
 
     let kmer_size = parameters.k;
+    let base_aln_parameters = BaseAlnParameters {
+        max_sw_aln_size: parameters.max_sw_aln_size,
+        sw_band_width: config.sw_band_width,
+        aln_params: aln::AlnParams {
+            match_score: config.match_score,
+            mismatch_penalty: config.mismatch_penalty,
+            gap_open_penalty: config.gap_open_penalty,
+            gap_extension_penalty: config.gap_extension_penalty,
+            max_wf_length: Some(config.wfa_max_distance),
+        },
+        realign_sv_cnd: config.realign_sv_cnd,
+    };
 
     let query_name = query_seqs
         .iter()
@@ -349,233 +1596,188 @@ fn main() -> Result<(), std::io::Error> {
         .map(|(k, v)| (*k, v.2))
         .collect::<FxHashMap<_, _>>();
 
+    // (q_idx, q_name, alignment records) of the contigs completed in a previous run
+    type CheckpointRec = (u32, String, Option<Vec<Vec<Record>>>);
+    let checkpoint_path = Path::new(&args.output_prefix).with_extension("ckpt");
+    let mut completed = FxHashMap::<u32, Option<Vec<Vec<Record>>>>::default();
+    if args.resume && checkpoint_path.exists() {
+        let checkpoint_file = BufReader::new(File::open(&checkpoint_path)?);
+        checkpoint_file
+            .lines()
+            .try_for_each(|line| -> Result<(), std::io::Error> {
+                // the last line can be incomplete if the previous run is interrupted
+                if let Ok((q_idx, q_name, records)) = serde_json::from_str::<CheckpointRec>(&line?)
+                {
+                    if query_name.get(&q_idx) == Some(&q_name) {
+                        completed.insert(q_idx, records);
+                    }
+                }
+                Ok(())
+            })?;
+    }
+    let checkpoint_file = if args.checkpoint || args.resume {
+        // rewrite the completed records to drop the incomplete lines
+        let mut checkpoint_file = BufWriter::new(File::create(&checkpoint_path)?);
+        let mut q_idxs = completed.keys().copied().collect::<Vec<u32>>();
+        q_idxs.sort();
+        q_idxs
+            .into_iter()
+            .try_for_each(|q_idx| -> Result<(), std::io::Error> {
+                let records = (
+                    q_idx,
+                    query_name.get(&q_idx).unwrap(),
+                    completed.get(&q_idx).unwrap(),
+                );
+                let line =
+                    serde_json::to_string(&records).expect("fail to serialize the checkpoint");
+                writeln!(checkpoint_file, "{}", line)
+            })?;
+        checkpoint_file.flush()?;
+        Some(checkpoint_file)
+    } else {
+        None
+    };
+
+    // (number of the completed contigs, checkpoint file)
+    let progress = Mutex::new((completed.len(), checkpoint_file));
+    let n_queries = query_seqs.len();
     let all_records = query_seqs
         .par_iter()
         .enumerate()
         .map(|(q_idx, seq_rec)| {
-            // let q_name = String::from_utf8_lossy(&seq_rec.id);
-            let query_seq = seq_rec.seq.clone();
-            //let q_len = query_seq.len();
-            let max_gap = args.max_gap;
-            let query_results = ref_seq_index_db.query_fragment_to_hps(
-                &query_seq,
-                args.gap_penalty_factor,
-                Some(1),
-                Some(1),
-                Some(1),
-                Some(args.max_aln_chain_span),
-                Some(max_gap),
-                true,
+            if let Some(records) = completed.get(&(q_idx as u32)) {
+                return records.clone();
+            }
+            let query_results = query_reference_index(&ref_seq_index_db, &seq_rec.seq, &config);
+            let records = query_results.map(|qr| {
+                query_hits_to_records(
+                    q_idx,
+                    &seq_rec.seq,
+                    qr,
+                    &ref_seq_index_db,
+                    kmer_size,
+                    &base_aln_parameters,
+                )
+            });
+
+            let mut progress = progress.lock().unwrap();
+            progress.0 += 1;
+            let q_name = query_name.get(&(q_idx as u32)).unwrap();
+            if let Some(checkpoint_file) = progress.1.as_mut() {
+                let line = serde_json::to_string(&(q_idx as u32, q_name, &records))
+                    .expect("fail to serialize the checkpoint");
+                writeln!(checkpoint_file, "{}", line).expect("fail to write the checkpoint file");
+                checkpoint_file
+                    .flush()
+                    .expect("fail to write the checkpoint file");
+            }
+            let stderr = io::stderr();
+            let mut handle = stderr.lock();
+            let _ = writeln!(
+                handle,
+                "[{}/{}] contig {} ({} bp) aligned",
+                progress.0,
+                n_queries,
+                q_name,
+                seq_rec.seq.len()
             );
-            (q_idx, seq_rec, query_results)
+            records
         })
-        .flat_map(|(q_idx, seq_rec, query_results)| {
-            if let Some(qr) = query_results {
-                let query_seq = &seq_rec.seq;
-                let q_len: usize = query_seq.len();
-                let mut target_id_to_mapped_regions = FxHashMap::default();
-                let mut target_id_to_orientation_len_count = FxHashMap::default();
-                qr.into_iter().for_each(|(t_idx, mapped_segments)| {
-                    let mut aln_lens = vec![];
-                    let mut ctg_orientation_count = (0_usize, 0_usize); // ctg level orientation count: (fwd_count, rev_count)
-                    mapped_segments.into_iter().for_each(|(_score, aln)| {
-                        let mut segment_orientation_count = (0_usize, 0_usize); // ctg level orientation count: (fwd_count, rev_count)
-                        if aln.len() > 2 {
-                            aln_lens.push(aln.len());
-                            for hp in &aln {
-                                let seg_len = (hp.0 .1 - hp.0 .0) as usize;
-                                if hp.0 .2 == hp.1 .2 {
-                                    ctg_orientation_count.0 += seg_len;
-                                    segment_orientation_count.0 += seg_len;
-                                } else {
-                                    ctg_orientation_count.1 += seg_len;
-                                    segment_orientation_count.1 += seg_len;
-                                }
-                            }
-                            let seg_orientation =
-                                if segment_orientation_count.0 > segment_orientation_count.1 {
-                                    0_u32
-                                } else {
-                                    1_u32
-                                };
-
-                            let e = target_id_to_mapped_regions
-                                .entry(t_idx)
-                                .or_insert_with(Vec::new);
-                            e.push((aln, seg_orientation))
-                        }
-                        let ctg_orientation = if ctg_orientation_count.0 > ctg_orientation_count.1 {
-                            0_u32
-                        } else {
-                            1_u32
-                        };
-                        let e = target_id_to_orientation_len_count
-                            .entry(t_idx)
-                            .or_insert(((0, 0), 0));
-                        *e = (ctg_orientation_count, ctg_orientation);
-                    })
-                });
+        .flatten()
+        .collect::<Vec<_>>();
 
-                let rtn = target_id_to_mapped_regions
-                    .into_iter()
-                    .flat_map(|(t_idx, mapped_regions)| {
-                        let ref_seq = ref_seq_index_db.get_seq_by_id(t_idx).unwrap();
-                        let mapped_region_aln = mapped_regions
-                            .into_par_iter()
-                            .map(|(aln_segs, orientation)| {
-                                let aln_segs = if orientation == 0 {
-                                    filter_aln(&aln_segs)
-                                } else {
-                                    filter_aln_rev(&aln_segs)
-                                };
-
-                                aln_segs
-                                    .into_iter()
-                                    .map(|((ts, te), (qs, qe))| {
-                                        let ts = ts - kmer_size; // add one to ensure a match base if the first call is deletion
-                                                                 //let te = te;
-                                        let qs = if orientation == 0 { qs - kmer_size } else { qs };
-                                        let qe = if orientation == 0 { qe } else { qe + kmer_size };
-                                        let s0str = ref_seq[ts as usize..te as usize].to_vec();
-                                        let s1str = if orientation == 0 {
-                                            query_seq[qs as usize..qe as usize].to_vec()
-                                        } else {
-                                            reverse_complement(
-                                                &query_seq[(qs - kmer_size) as usize
-                                                    ..(qe - kmer_size) as usize],
-                                            )
-                                        };
-
-                                        let wf_aln_diff: AlnDiff = if s0str.len() <= 16
-                                            || s1str.len() <= 16
-                                        {
-                                            AlnDiff::FailShortSeq
-                                        } else if s0str[..16] != s1str[..16]
-                                            || s0str[s0str.len() - 16..]
-                                                != s1str[s1str.len() - 16..]
-                                        {
-                                            AlnDiff::FailEndMatch
-                                        } else if (s0str.len() as isize - s1str.len() as isize)
-                                            .abs()
-                                            >= 128
-                                        {
-                                            // AlnDiff::FailLengthDiff
-                                            if s0str.len() < parameters.max_sw_aln_size as usize
-                                                && s1str.len() < parameters.max_sw_aln_size as usize
-                                            {
-                                                if let Some(aln_res) = aln::get_sw_variant_segments(
-                                                    &s0str, &s1str, 1, 4, 4, 1,
-                                                ) {
-                                                    AlnDiff::Aligned(aln_res)
-                                                } else {
-                                                    AlnDiff::FailAln
-                                                }
-                                            } else {
-                                                AlnDiff::FailLengthDiff
-                                            }
-                                        } else if let Some(aln_res) = aln::get_wfa_variant_segments(
-                                            &s0str,
-                                            &s1str,
-                                            1,
-                                            Some(384),
-                                            4,
-                                            4,
-                                            1,
-                                        ) {
-                                            AlnDiff::Aligned(aln_res)
-                                        } else {
-                                            AlnDiff::FailAln
-                                        };
-                                        ((ts, te), (qs, qe), orientation, wf_aln_diff)
-                                    })
-                                    .collect::<Vec<_>>()
+    // the second pass to re-align the query intervals without alignments with a denser shimmer spec, the
+    // denser index is built only for the reference sequences aligned by the contigs with the unaligned
+    // intervals and dropped after the pass, the hits are mapped back to the sequence ids of the first index
+    let all_records = if config.rescue_min_len > 0 {
+        let mut all_records = all_records;
+        let unaligned_intervals =
+            unaligned_query_intervals(&all_records, &query_len, config.rescue_min_len);
+        if !unaligned_intervals.is_empty() {
+            let rescue_targets = rescue_targets(&all_records, &unaligned_intervals, &target_name);
+            let mut rescue_seq_index_db = SeqIndexDB::new();
+            load_reference_index(
+                &mut rescue_seq_index_db,
+                &args.reference_fasta_path,
+                rescue_targets.as_ref().or(targets.as_ref()),
+                &Parameters {
+                    w: config.rescue_w,
+                    r: config.rescue_r,
+                    ..parameters
+                },
+            )?;
+            let target_id = target_name
+                .iter()
+                .map(|(t_idx, name)| (name, *t_idx))
+                .collect::<FxHashMap<_, _>>();
+            let rescue_to_target_id = rescue_seq_index_db
+                .seq_info
+                .as_ref()
+                .unwrap()
+                .iter()
+                .map(|(sid, (name, _, _))| (*sid, *target_id.get(name).unwrap()))
+                .collect::<FxHashMap<_, _>>();
+            let rescued_records = unaligned_intervals
+                .par_iter()
+                .flat_map(|&(q_idx, bgn, end)| {
+                    let query_seq = &query_seqs[q_idx as usize].seq;
+                    let query_results = query_reference_index(
+                        &rescue_seq_index_db,
+                        &query_seq[bgn as usize..end as usize].to_vec(),
+                        &config,
+                    );
+                    query_results.map(|qr| {
+                        let qr = qr
+                            .into_iter()
+                            .map(|(t_idx, mapped_segments)| {
+                                (*rescue_to_target_id.get(&t_idx).unwrap(), mapped_segments)
                             })
-                            .filter(|v| !v.is_empty())
                             .collect::<Vec<_>>();
+                        query_hits_to_records(
+                            q_idx as usize,
+                            query_seq,
+                            shift_query_hits(qr, bgn),
+                            &ref_seq_index_db,
+                            kmer_size,
+                            &base_aln_parameters,
+                        )
+                    })
+                })
+                .collect::<Vec<_>>();
+            all_records.extend(rescued_records);
+        }
+        all_records
+    } else {
+        all_records
+    };
 
-                        let (_, ctg_orientation) =
-                            target_id_to_orientation_len_count.get(&t_idx).unwrap();
+    let all_records = if config.end_ext_max_len > 0 {
+        let mut all_records = all_records;
+        let end_ext_max_len = config
+            .end_ext_max_len
+            .min(parameters.max_sw_aln_size.saturating_sub(1));
+        let extended_records = extend_contig_ends(
+            &all_records,
+            &query_seqs,
+            &ref_seq_index_db,
+            end_ext_max_len,
+            &base_aln_parameters,
+        );
+        all_records.push(extended_records);
+        all_records
+    } else {
+        all_records
+    };
 
-                        mapped_region_aln
-                            .into_iter()
-                            .map(|v| {
-                                let mut output_records = Vec::<Record>::new();
-                                let ((ts, te), (qs, qe), orientation, _diff) = v[0].clone();
-                                let qs = if orientation == 0 { qs } else { qs - kmer_size };
-                                let qe = if orientation == 0 { qe } else { qe - kmer_size };
-                                output_records.push(Record::Bgn(
-                                    (t_idx, ts, te, q_idx as u32, qs, qe, orientation),
-                                    q_len as u32,
-                                    *ctg_orientation,
-                                ));
-                                let v_last = v.last().unwrap().clone();
-                                v.into_iter().for_each(
-                                    |((ts, te), (qs, qe), orientation, diff)| {
-                                        let qs = if orientation == 0 { qs } else { qs - kmer_size };
-                                        let qe = if orientation == 0 { qe } else { qe - kmer_size };
-                                        if let AlnDiff::Aligned(diff) = diff {
-                                            if diff.is_empty() {
-                                                output_records.push(Record::Match((
-                                                    t_idx,
-                                                    ts,
-                                                    te,
-                                                    q_idx as u32,
-                                                    qs,
-                                                    qe,
-                                                    orientation,
-                                                )))
-                                            } else {
-                                                diff.into_iter().for_each(
-                                                    |(td, qd, vt, t_str, q_str)| {
-                                                        output_records.push(Record::Variant(
-                                                            (
-                                                                t_idx,
-                                                                ts,
-                                                                te,
-                                                                q_idx as u32,
-                                                                qs,
-                                                                qe,
-                                                                orientation,
-                                                            ),
-                                                            td,
-                                                            qd,
-                                                            ts + td,
-                                                            vt,
-                                                            t_str,
-                                                            q_str,
-                                                        ));
-                                                    },
-                                                )
-                                            }
-                                        } else {
-                                            output_records.push(Record::SvCnd((
-                                                (t_idx, ts, te, q_idx as u32, qs, qe, orientation),
-                                                diff,
-                                                *ctg_orientation,
-                                            )));
-                                        }
-                                    },
-                                );
-
-                                let ((ts, te), (qs, qe), orientation, _diff) = v_last;
-                                let qs = if orientation == 0 { qs } else { qs - kmer_size };
-                                let qe = if orientation == 0 { qe } else { qe - kmer_size };
-                                output_records.push(Record::End(
-                                    (t_idx, ts, te, q_idx as u32, qs, qe, orientation),
-                                    q_len as u32,
-                                    *ctg_orientation,
-                                ));
-                                output_records
-                            })
-                            .collect::<Vec<_>>()
-                    })
-                    .collect::<Vec<_>>();
-                Some(rtn)
-            } else {
-                None
-            }
-        })
-        .collect::<Vec<_>>();
+    unaligned_query_intervals(&all_records, &query_len, config.unaligned_min_len)
+        .into_iter()
+        .for_each(|(q_idx, bgn, end)| {
+            let q_name = query_name.get(&q_idx).unwrap();
+            let seq = &query_seqs[q_idx as usize].seq[bgn as usize..end as usize];
+            out_unaligned
+                .write_fasta(&format!("{}:{}-{}", q_name, bgn, end), seq)
+                .expect("fail to write the unaligned sequence file");
+        });
 
     let mut in_aln_sv_cnd_records = Vec::<(ShimmerMatchBlock, char, u32)>::new();
     let mut target_aln_blocks =
@@ -775,6 +1977,49 @@ fn main() -> Result<(), std::io::Error> {
             query_aln_bed_records.push((q_name.clone(), cqe, q_len, bed_annotation));
         });
 
+    let mut inversion_calls = query_aln_blocks
+        .par_iter()
+        .flat_map(|(q_idx, match_blocks)| {
+            let match_blocks = match_blocks
+                .iter()
+                .filter(|v| !query_duplicate_blocks.contains(&v.1))
+                .cloned()
+                .collect::<Vec<_>>();
+            call_inversions(
+                &match_blocks,
+                &query_seqs[*q_idx as usize].seq,
+                &ref_seq_index_db,
+                config.max_gap,
+            )
+        })
+        .collect::<Vec<_>>();
+    inversion_calls.sort_by_key(|v| (v.t_idx, v.bgn, v.end));
+
+    let mut duplication_calls = query_aln_blocks
+        .par_iter()
+        .flat_map(|(q_idx, match_blocks)| {
+            call_tandem_duplications(
+                match_blocks,
+                &query_seqs[*q_idx as usize].seq,
+                &ref_seq_index_db,
+            )
+        })
+        .collect::<Vec<_>>();
+    duplication_calls.sort_by_key(|v| (v.t_idx, v.bgn, v.end));
+
+    let mut translocation_calls = query_aln_blocks
+        .par_iter()
+        .flat_map(|(_q_idx, match_blocks)| {
+            let match_blocks = match_blocks
+                .iter()
+                .filter(|v| !query_duplicate_blocks.contains(&v.1))
+                .cloned()
+                .collect::<Vec<_>>();
+            call_translocations(&match_blocks, config.max_gap)
+        })
+        .collect::<Vec<_>>();
+    translocation_calls.sort_by_key(|v| (v.q_idx, v.q_bgn, v.q_end));
+
     let mut target_duplicate_intervals = FxHashMap::<u32, IntervalSet<u32>>::default();
     target_duplicate_blocks
         .iter()
@@ -796,9 +2041,19 @@ fn main() -> Result<(), std::io::Error> {
         });
 
     let mut in_aln_sv_and_bed_records = Vec::<(String, u32, u32, String)>::new();
-    in_aln_sv_cnd_records.sort();
+    let in_aln_sv_cnd_records = if config.sv_cnd_merge_distance > 0 {
+        merge_sv_candidates(&in_aln_sv_cnd_records, config.sv_cnd_merge_distance)
+    } else {
+        in_aln_sv_cnd_records.sort();
+        in_aln_sv_cnd_records
+            .into_iter()
+            .map(|(block, diff_type, ctg_orientation)| {
+                (block, diff_type.to_string(), ctg_orientation, 1)
+            })
+            .collect::<Vec<_>>()
+    };
     in_aln_sv_cnd_records.iter().for_each(
-        |((t_idx, ts, te, q_idx, qs, qe, orientation), diff_type, ctg_orientation)| {
+        |((t_idx, ts, te, q_idx, qs, qe, orientation), diff_type, ctg_orientation, count)| {
             let q_name = query_name.get(q_idx).unwrap();
             let dup =
                 if let Some(target_duplicate_intervals) = target_duplicate_intervals.get(t_idx) {
@@ -828,10 +2083,17 @@ fn main() -> Result<(), std::io::Error> {
                 "SVC"
             };
 
-            let bed_annotation = format!(
-                "{}:{}:{}-{}:{}:{}:{}",
-                svc_type, q_name, qs, qe, orientation, ctg_orientation, diff_type
-            );
+            let bed_annotation = if config.sv_cnd_merge_distance > 0 {
+                format!(
+                    "{}:{}:{}-{}:{}:{}:{}:{}",
+                    svc_type, q_name, qs, qe, orientation, ctg_orientation, diff_type, count
+                )
+            } else {
+                format!(
+                    "{}:{}:{}-{}:{}:{}:{}",
+                    svc_type, q_name, qs, qe, orientation, ctg_orientation, diff_type
+                )
+            };
             let t_name = target_name.get(t_idx).unwrap();
             in_aln_sv_and_bed_records.push((t_name.clone(), ts + 1, te + 1, bed_annotation));
         },
@@ -928,6 +2190,7 @@ fn main() -> Result<(), std::io::Error> {
         records: ctgmap_records,
         query_length,
         target_length,
+        config,
     };
 
     let ctgmap_json =
@@ -948,7 +2211,187 @@ fn main() -> Result<(), std::io::Error> {
             .expect("fail to write the 'in-alignment' sv candidate bed file");
     });
 
-    let mut vcf_records = Vec::<(u32, u32, String, String, ShimmerMatchBlock)>::new();
+    // (t_idx, pos, id, ref, alt, info, match block for checking the duplicated / overlapped target regions)
+    #[allow(clippy::type_complexity)]
+    let mut vcf_records = Vec::<(
+        u32,
+        u32,
+        String,
+        String,
+        String,
+        String,
+        Option<ShimmerMatchBlock>,
+    )>::new();
+
+    inversion_calls.iter().for_each(|inv| {
+        let (t_idx, _ts, _te, q_idx, qs, qe, _orientation) = inv.inverted_block;
+        let t_name = target_name.get(&t_idx).unwrap();
+        let q_name = query_name.get(&q_idx).unwrap();
+        writeln!(
+            out_inv,
+            "{}\t{}\t{}\tINV:{}:{}-{}:{}:{}",
+            t_name, inv.bgn, inv.end, q_name, qs, qe, inv.bgn_ci, inv.end_ci
+        )
+        .expect("fail to write the inversion bed file");
+        // the VCF POS of a symbolic allele is the base before the event
+        let ref_base = ref_seq_index_db
+            .get_sub_seq_by_id(t_idx, inv.bgn as usize - 1, inv.bgn as usize)
+            .unwrap();
+        vcf_records.push((
+            t_idx,
+            inv.bgn,
+            ".".to_string(),
+            String::from_utf8_lossy(&ref_base[..]).to_string(),
+            "<INV>".to_string(),
+            format!(
+                "SVTYPE=INV;END={};SVLEN={};CIPOS=-{},{};CIEND=-{},{}",
+                inv.end,
+                inv.end - inv.bgn,
+                inv.bgn_ci,
+                inv.bgn_ci,
+                inv.end_ci,
+                inv.end_ci
+            ),
+            Some(inv.inverted_block),
+        ));
+    });
+
+    duplication_calls.iter().for_each(|dup| {
+        let t_name = target_name.get(&dup.t_idx).unwrap();
+        let q_name = query_name.get(&dup.q_idx).unwrap();
+        writeln!(
+            out_dup,
+            "{}\t{}\t{}\tDUP:{}:{}-{}:{}:{}",
+            t_name, dup.bgn, dup.end, q_name, dup.qs, dup.qe, dup.copy_number, dup.unit_len
+        )
+        .expect("fail to write the duplication bed file");
+        if dup.bgn == 0 {
+            return;
+        }
+        let ref_base = ref_seq_index_db
+            .get_sub_seq_by_id(dup.t_idx, dup.bgn as usize - 1, dup.bgn as usize)
+            .unwrap();
+        // the duplicated region is the signal itself, so it is not filtered by the target duplication check
+        vcf_records.push((
+            dup.t_idx,
+            dup.bgn,
+            ".".to_string(),
+            String::from_utf8_lossy(&ref_base[..]).to_string(),
+            "<DUP>".to_string(),
+            format!(
+                "SVTYPE=DUP;END={};SVLEN={};CN={};UNITLEN={}",
+                dup.end,
+                dup.end - dup.bgn,
+                dup.copy_number,
+                dup.unit_len
+            ),
+            None,
+        ));
+    });
+
+    translocation_calls
+        .iter()
+        .enumerate()
+        .for_each(|(tra_idx, tra)| {
+            let q_name = query_name.get(&tra.q_idx).unwrap();
+            let ids = [
+                format!("BND{:06}_1", tra_idx),
+                format!("BND{:06}_2", tra_idx),
+            ];
+            (0..2).for_each(|i| {
+                let (t_idx, pos, ref_base_first, mate_extends_right) = tra.break_ends[i];
+                let (mate_t_idx, mate_pos, _, _) = tra.break_ends[1 - i];
+                let t_name = target_name.get(&t_idx).unwrap();
+                let mate_name = target_name.get(&mate_t_idx).unwrap();
+                writeln!(
+                    out_tra,
+                    "{}\t{}\t{}\tTRA:{}:{}-{}:{}:{}:{}",
+                    t_name,
+                    pos - 1,
+                    pos,
+                    q_name,
+                    tra.q_bgn,
+                    tra.q_end,
+                    mate_name,
+                    mate_pos,
+                    ids[i]
+                )
+                .expect("fail to write the translocation bed file");
+                let ref_base = ref_seq_index_db
+                    .get_sub_seq_by_id(t_idx, pos as usize - 1, pos as usize)
+                    .unwrap();
+                let ref_base = String::from_utf8_lossy(&ref_base[..]).to_string();
+                let alt = break_end_alt(
+                    &ref_base,
+                    mate_name,
+                    mate_pos,
+                    ref_base_first,
+                    mate_extends_right,
+                );
+                vcf_records.push((
+                    t_idx,
+                    pos,
+                    ids[i].clone(),
+                    ref_base,
+                    alt,
+                    format!("SVTYPE=BND;MATEID={}", ids[1 - i]),
+                    Some(tra.match_blocks[i]),
+                ));
+            });
+        });
+
+    // the polished alleles of the SV candidates, the segments start with the reference base before the candidates
+    let polished_sv_alleles = if config.polish_sv_cnd {
+        let sv_cnd_blocks = all_records
+            .iter()
+            .flatten()
+            .flatten()
+            .filter_map(|r| match r {
+                Record::SvCnd((match_block, _, _)) => Some(*match_block),
+                _ => None,
+            })
+            .filter(|&(_, ts, te, _, qs, qe, _)| {
+                ts > 0 && te - ts <= config.sv_polish_max_len && qe - qs <= config.sv_polish_max_len
+            })
+            .collect::<Vec<ShimmerMatchBlock>>();
+        let candidates = sv_cnd_blocks
+            .iter()
+            .map(|&(t_idx, ts, te, q_idx, qs, qe, orientation)| {
+                let t_seq = ref_seq_index_db
+                    .get_sub_seq_by_id(t_idx, ts as usize - 1, te as usize)
+                    .unwrap();
+                let q_seq = &query_seqs[q_idx as usize].seq[(qs as usize)..(qe as usize)];
+                let mut padded_q_seq = vec![t_seq[0]];
+                if orientation == 0 {
+                    padded_q_seq.extend_from_slice(q_seq);
+                } else {
+                    padded_q_seq.extend(reverse_complement(q_seq));
+                }
+                (t_seq, padded_q_seq)
+            })
+            .collect::<Vec<_>>();
+
+        let mut reads = Vec::<Vec<u8>>::new();
+        if let Some(sv_polish_reads) = args.sv_polish_reads.as_ref() {
+            let mut add_reads = |seq_iter: &mut dyn Iterator<Item = io::Result<SeqRec>>| {
+                seq_iter.flatten().for_each(|r| reads.push(r.seq));
+            };
+            match get_fastx_reader(sv_polish_reads.clone(), true)? {
+                GZFastaReader::GZFile(mut reader) => add_reads(&mut reader),
+                GZFastaReader::RegularFile(mut reader) => add_reads(&mut reader),
+            };
+        }
+
+        let alleles =
+            ec::polish_sv_candidates(&candidates, &reads, &None, config.sv_polish_min_cov);
+        sv_cnd_blocks
+            .into_iter()
+            .zip(alleles)
+            .filter_map(|(match_block, allele)| allele.map(|allele| (match_block, allele)))
+            .collect::<FxHashMap<ShimmerMatchBlock, PolishedSvAllele>>()
+    } else {
+        FxHashMap::<ShimmerMatchBlock, PolishedSvAllele>::default()
+    };
 
     // the second round loop through all_records to output and tagged variant from duplicate / overlapped blocks
     all_records
@@ -1106,6 +2549,24 @@ fn main() -> Result<(), std::io::Error> {
                             diff_type
                         );
 
+                        let match_block = (t_idx, ts, te, q_idx, qs, qe, orientation);
+                        if let Some(allele) = polished_sv_alleles.get(&match_block) {
+                            // the POS of the padding base at `t_offset` from the base before the candidate
+                            vcf_records.push((
+                                t_idx,
+                                ts + allele.t_offset as u32,
+                                ".".to_string(),
+                                String::from_utf8_lossy(&allele.ref_allele[..]).to_string(),
+                                String::from_utf8_lossy(&allele.alt_allele[..]).to_string(),
+                                format!(
+                                    "SVCND;SVLEN={};SUPPORT={}",
+                                    allele.alt_allele.len() as i64 - allele.ref_allele.len() as i64,
+                                    allele.support
+                                ),
+                                Some(match_block),
+                            ));
+                        }
+
                         if let Some(out_sv_seq_file) = out_sv_seq_file.as_mut() {
                             let t_seq_slice = &ref_seq_index_db
                                 .get_sub_seq_by_id(t_idx, ts as usize, te as usize)
@@ -1129,7 +2590,15 @@ fn main() -> Result<(), std::io::Error> {
                     }
                     Record::Variant(match_block, td, qd, tc, vt, tvs, qvs) => {
                         let (t_idx, ts, te, q_idx, qs, qe, orientation) = match_block;
-                        vcf_records.push((t_idx, tc + 1, tvs.clone(), qvs.clone(), match_block));
+                        vcf_records.push((
+                            t_idx,
+                            tc + 1,
+                            ".".to_string(),
+                            tvs.clone(),
+                            qvs.clone(),
+                            ".".to_string(),
+                            Some(match_block),
+                        ));
                         let tn = target_name.get(&t_idx).unwrap();
                         let qn = query_name.get(&q_idx).unwrap();
 
@@ -1202,17 +2671,50 @@ fn main() -> Result<(), std::io::Error> {
         r#"##FILTER=<ID=to,Description="variant from overlapped contig alignment on query">"#
     )
     .expect("fail to write the vcf file");
+    [
+        r#"##ALT=<ID=INV,Description="Inversion">"#,
+        r#"##ALT=<ID=DUP,Description="Tandem duplication">"#,
+        r#"##ALT=<ID=BND,Description="Break end of a translocation">"#,
+        r#"##INFO=<ID=SVTYPE,Number=1,Type=String,Description="Type of structural variant">"#,
+        r#"##INFO=<ID=END,Number=1,Type=Integer,Description="End position of the structural variant">"#,
+        r#"##INFO=<ID=SVLEN,Number=1,Type=Integer,Description="Length of the structural variant">"#,
+        r#"##INFO=<ID=CIPOS,Number=2,Type=Integer,Description="Confidence interval around POS">"#,
+        r#"##INFO=<ID=CIEND,Number=2,Type=Integer,Description="Confidence interval around END">"#,
+        r#"##INFO=<ID=CN,Number=1,Type=Integer,Description="Number of copies of the duplicated unit in the query contig">"#,
+        r#"##INFO=<ID=MATEID,Number=1,Type=String,Description="ID of the mate break end">"#,
+        r#"##INFO=<ID=UNITLEN,Number=1,Type=Integer,Description="Estimated length of the duplicated unit from the median offset between the repeated shimmer pairs in the query copies">"#,
+    ]
+    .iter()
+    .for_each(|h| writeln!(out_vcf, "{}", h).expect("fail to write the vcf file"));
+    if config.polish_sv_cnd {
+        [
+            r#"##INFO=<ID=SVCND,Number=0,Type=Flag,Description="Polished allele of a SV candidate region">"#,
+            r#"##INFO=<ID=SUPPORT,Number=1,Type=Integer,Description="Min coverage of the polished allele from the query segment and the reads">"#,
+        ]
+        .iter()
+        .for_each(|h| writeln!(out_vcf, "{}", h).expect("fail to write the vcf file"));
+    }
+    if gff.is_some() {
+        [
+            r#"##INFO=<ID=CONSEQ,Number=1,Type=String,Description="Consequence of the variant from the reference annotation: intergenic, intronic, exonic, cds_substitution, cds_inframe_indel or cds_frameshift">"#,
+            r#"##INFO=<ID=GENE,Number=.,Type=String,Description="Genes with the consequence of the variant">"#,
+        ]
+        .iter()
+        .for_each(|h| writeln!(out_vcf, "{}", h).expect("fail to write the vcf file"));
+    }
     writeln!(out_vcf, "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO")
         .expect("fail to write the vcf file");
 
     vcf_records.sort();
     vcf_records
         .into_iter()
-        .for_each(|(t_idx, tc, tvs, qvs, match_block)| {
+        .for_each(|(t_idx, tc, id, tvs, qvs, info, match_block)| {
             let tn = target_name.get(&t_idx).unwrap();
 
-            let dup =
-                if let Some(target_duplicate_intervals) = target_duplicate_intervals.get(&t_idx) {
+            let (dup, ovlp) = if let Some(match_block) = match_block {
+                let dup = if let Some(target_duplicate_intervals) =
+                    target_duplicate_intervals.get(&t_idx)
+                {
                     if match_block.2 > match_block.1 {
                         target_duplicate_intervals.has_overlap(match_block.1..match_block.2)
                     } else {
@@ -1222,15 +2724,19 @@ fn main() -> Result<(), std::io::Error> {
                     false
                 };
 
-            let ovlp = if let Some(target_overlap_intervals) = target_overlap_intervals.get(&t_idx)
-            {
-                if match_block.2 > match_block.1 {
-                    target_overlap_intervals.has_overlap(match_block.1..match_block.2)
-                } else {
-                    false
-                }
+                let ovlp =
+                    if let Some(target_overlap_intervals) = target_overlap_intervals.get(&t_idx) {
+                        if match_block.2 > match_block.1 {
+                            target_overlap_intervals.has_overlap(match_block.1..match_block.2)
+                        } else {
+                            false
+                        }
+                    } else {
+                        false
+                    };
+                (dup, ovlp)
             } else {
-                false
+                (false, false)
             };
             let filter = if dup {
                 "DUP"
@@ -1240,18 +2746,246 @@ fn main() -> Result<(), std::io::Error> {
                 "PASS"
             };
             let qv: u32 = if filter != "PASS" { 10 } else { 60 };
+            let (tvs, qvs) = (tvs.trim_end_matches('-'), qvs.trim_end_matches('-'));
+            // only the small variants (not the symbolic or the break end alleles) are annotated
+            let info = match gff.as_ref() {
+                Some(gff) if !qvs.contains(['<', '[', ']']) => {
+                    let (consequence, genes) = gff.variant_consequence(tn, tc - 1, tvs, qvs);
+                    let mut annotation = format!("CONSEQ={}", consequence);
+                    if !genes.is_empty() {
+                        annotation.push_str(&format!(";GENE={}", genes.join(",")));
+                    }
+                    if info == "." {
+                        annotation
+                    } else {
+                        format!("{};{}", info, annotation)
+                    }
+                }
+                _ => info,
+            };
             writeln!(
                 out_vcf,
-                "{}\t{}\t.\t{}\t{}\t{}\t{}\t.",
-                tn,
-                tc,
-                tvs.trim_end_matches('-'),
-                qvs.trim_end_matches('-'),
-                qv,
-                filter
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                tn, tc, id, tvs, qvs, qv, filter, info
             )
             .expect("fail to write the vcf file");
         });
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a deterministic pseudo-random sequence for the tests
+    fn random_seq(len: usize, seed: u64) -> Vec<u8> {
+        let mut x = seed;
+        (0..len)
+            .map(|_| {
+                x = x
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                b"ACGT"[(x >> 62) as usize]
+            })
+            .collect()
+    }
+
+    fn test_index_db() -> SeqIndexDB {
+        let mut seq_index_db = SeqIndexDB::new();
+        seq_index_db
+            .load_from_seq_list(
+                vec![("ref".to_string(), random_seq(20000, 1))],
+                None,
+                16,
+                21,
+                1,
+                0,
+            )
+            .unwrap();
+        seq_index_db
+    }
+
+    // 10kb flank, 3 copies of a 3kb unit and 10kb flank
+    fn tandem_query() -> Vec<u8> {
+        let unit = random_seq(3000, 2);
+        let mut seq = random_seq(10000, 3);
+        (0..3).for_each(|_| seq.extend_from_slice(&unit));
+        seq.extend(random_seq(10000, 4));
+        seq
+    }
+
+    #[test]
+    fn test_estimate_unit_length() {
+        let seq_index_db = test_index_db();
+        assert_eq!(
+            estimate_unit_length(tandem_query(), &seq_index_db),
+            Some(3000)
+        );
+        assert_eq!(
+            estimate_unit_length(random_seq(20000, 5), &seq_index_db),
+            None
+        );
+    }
+
+    #[test]
+    fn test_call_tandem_duplications() {
+        let seq_index_db = test_index_db();
+        let query_seq = tandem_query();
+        // three copies of the target interval [10000, 13000) followed by a block on another target
+        let match_blocks = vec![
+            (0, (0, 0, 13000, 0, 0, 13000, 0), 0, 0),
+            (0, (0, 10000, 13000, 0, 13000, 16000, 0), 0, 0),
+            (0, (0, 10000, 23000, 0, 16000, 29000, 0), 0, 0),
+            (0, (1, 0, 5000, 0, 29000, 34000, 0), 0, 0),
+        ];
+        let calls = call_tandem_duplications(&match_blocks, &query_seq, &seq_index_db);
+        assert_eq!(calls.len(), 1);
+        let call = &calls[0];
+        assert_eq!((call.t_idx, call.bgn, call.end), (0, 10000, 13000));
+        assert_eq!((call.copy_number, call.unit_len), (3, 3000));
+        assert_eq!((call.q_idx, call.qs, call.qe), (0, 0, 29000));
+
+        // no call for the blocks with different orientations or too short overlaps
+        let match_blocks = vec![
+            (0, (0, 0, 13000, 0, 0, 13000, 0), 0, 0),
+            (0, (0, 10000, 13000, 0, 13000, 16000, 1), 0, 0),
+            (0, (0, 12900, 23000, 0, 16000, 26100, 0), 0, 0),
+            (0, (0, 22900, 30000, 0, 26100, 33200, 0), 0, 0),
+        ];
+        assert!(call_tandem_duplications(&match_blocks, &query_seq, &seq_index_db).is_empty());
+    }
+
+    // the reference of test_index_db with the interval [inv_bgn, inv_end) inverted
+    fn inverted_query(inv_bgn: usize, inv_end: usize) -> Vec<u8> {
+        let ref_seq = random_seq(20000, 1);
+        let mut seq = ref_seq[..inv_bgn].to_vec();
+        seq.extend(reverse_complement(&ref_seq[inv_bgn..inv_end]));
+        seq.extend_from_slice(&ref_seq[inv_end..]);
+        seq
+    }
+
+    #[test]
+    fn test_call_inversions() {
+        let seq_index_db = test_index_db();
+        let query_seq = inverted_query(8000, 12000);
+        // the blocks end 100bp away from the breakpoints, which are refined by the extensions
+        let match_blocks = vec![
+            (0, (0, 0, 7900, 0, 0, 7900, 0), 0, 0),
+            (0, (0, 8100, 11900, 0, 8100, 11900, 1), 0, 0),
+            (0, (0, 12100, 20000, 0, 12100, 20000, 0), 0, 0),
+        ];
+        let calls = call_inversions(&match_blocks, &query_seq, &seq_index_db, 1000);
+        assert_eq!(calls.len(), 1);
+        let call = &calls[0];
+        assert_eq!((call.t_idx, call.bgn, call.end), (0, 8000, 12000));
+        assert_eq!((call.bgn_ci, call.end_ci), (0, 0));
+        assert_eq!(call.inverted_block, match_blocks[1].1);
+        // the gaps between the blocks are longer than max_gap
+        assert!(call_inversions(&match_blocks, &query_seq, &seq_index_db, 100).is_empty());
+
+        // the same inversion on the reverse strand of the query
+        let match_blocks = vec![
+            (0, (0, 12100, 20000, 0, 0, 7900, 1), 0, 0),
+            (0, (0, 8100, 11900, 0, 8100, 11900, 0), 0, 0),
+            (0, (0, 0, 7900, 0, 12100, 20000, 1), 0, 0),
+        ];
+        let calls = call_inversions(
+            &match_blocks,
+            &reverse_complement(&query_seq),
+            &seq_index_db,
+            1000,
+        );
+        assert_eq!(calls.len(), 1);
+        assert_eq!((calls[0].bgn, calls[0].end), (8000, 12000));
+    }
+
+    #[test]
+    fn test_call_inversions_at_ref_start() {
+        let seq_index_db = test_index_db();
+        // the inversion from the start of the reference, the left breakpoint is refined to 0 and
+        // there is no padding base before it for a VCF record
+        let query_seq = inverted_query(0, 4000);
+        let match_blocks = vec![
+            (0, (0, 0, 1, 0, 0, 1, 0), 0, 0),
+            (0, (0, 1, 3900, 0, 100, 3999, 1), 0, 0),
+            (0, (0, 4100, 20000, 0, 4100, 20000, 0), 0, 0),
+        ];
+        assert!(call_inversions(&match_blocks, &query_seq, &seq_index_db, 1000).is_empty());
+    }
+
+    // a 20kb block on chr1 followed by a 20kb block on chr2 with a 100bp gap on the query
+    fn translocation_blocks(
+        orientation0: u32,
+        orientation1: u32,
+    ) -> Vec<(usize, ShimmerMatchBlock, u32, u32)> {
+        vec![
+            (0, (0, 100000, 120000, 0, 0, 20000, orientation0), 0, 0),
+            (0, (1, 50000, 70000, 0, 20100, 40100, orientation1), 0, 0),
+        ]
+    }
+
+    // the ALT fields of the two break ends of a translocation call
+    fn break_end_alts(call: &TranslocationCall) -> [String; 2] {
+        let names = ["chr1", "chr2"];
+        [0, 1].map(|i| {
+            let (_, _, ref_base_first, mate_extends_right) = call.break_ends[i];
+            let (_, mate_pos, _, _) = call.break_ends[1 - i];
+            break_end_alt(
+                "A",
+                names[1 - i],
+                mate_pos,
+                ref_base_first,
+                mate_extends_right,
+            )
+        })
+    }
+
+    #[test]
+    fn test_call_translocations_orientations() {
+        // (orientations of the blocks, positions of the break ends, ALT fields of the break ends)
+        let expected = [
+            ((0, 0), (120000, 50001), ["A[chr2:50001[", "]chr1:120000]A"]),
+            ((0, 1), (120000, 70000), ["A]chr2:70000]", "A]chr1:120000]"]),
+            ((1, 0), (100001, 50001), ["[chr2:50001[A", "[chr1:100001[A"]),
+            ((1, 1), (100001, 70000), ["]chr2:70000]A", "A[chr1:100001["]),
+        ];
+        expected
+            .iter()
+            .for_each(|&((orientation0, orientation1), (pos0, pos1), alts)| {
+                let calls =
+                    call_translocations(&translocation_blocks(orientation0, orientation1), 1000);
+                assert_eq!(calls.len(), 1);
+                let call = &calls[0];
+                assert_eq!((call.q_idx, call.q_bgn, call.q_end), (0, 20000, 20100));
+                assert_eq!((call.break_ends[0].0, call.break_ends[0].1), (0, pos0));
+                assert_eq!((call.break_ends[1].0, call.break_ends[1].1), (1, pos1));
+                assert_eq!(break_end_alts(call), alts);
+            });
+    }
+
+    #[test]
+    fn test_call_translocations_cutoffs() {
+        assert_eq!(
+            call_translocations(&translocation_blocks(0, 0), 1000).len(),
+            1
+        );
+        // the query gap is longer than max_gap
+        assert!(call_translocations(&translocation_blocks(0, 0), 99).is_empty());
+        // the blocks on the same target
+        let mut match_blocks = translocation_blocks(0, 0);
+        match_blocks[1].1 .0 = 0;
+        assert!(call_translocations(&match_blocks, 1000).is_empty());
+        // the flanking blocks of exactly TRA_MIN_FLANK_LEN are kept, the shorter ones are skipped
+        let mut match_blocks = translocation_blocks(0, 0);
+        match_blocks[0].1 .1 = match_blocks[0].1 .2 - TRA_MIN_FLANK_LEN;
+        match_blocks[1].1 .2 = match_blocks[1].1 .1 + TRA_MIN_FLANK_LEN;
+        assert_eq!(call_translocations(&match_blocks, 1000).len(), 1);
+        let mut short_blocks = match_blocks.clone();
+        short_blocks[0].1 .1 += 1;
+        assert!(call_translocations(&short_blocks, 1000).is_empty());
+        let mut short_blocks = match_blocks;
+        short_blocks[1].1 .2 -= 1;
+        assert!(call_translocations(&short_blocks, 1000).is_empty());
+    }
+}
@@ -134,13 +134,7 @@ fn output_cov_bed(
 }
 
 fn generate_bed_graph_from_fastx_files(args: &CmdOptions) {
-    let shmmr_spec = ShmmrSpec {
-        w: args.w,
-        k: args.k,
-        r: args.r,
-        min_span: args.min_span,
-        sketch: false,
-    };
+    let shmmr_spec = ShmmrSpec::new(args.w, args.k, args.r, args.min_span, false);
     let mut sdb0 = SeqIndexDB::new();
     let input_files = BufReader::new(
         File::open(Path::new(&args.filepath0))
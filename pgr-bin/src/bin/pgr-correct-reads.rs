@@ -0,0 +1,219 @@
+const VERSION_STRING: &str = env!("VERSION_STRING");
+use clap::{self, CommandFactory, Parser};
+use pgr_db::aln;
+use pgr_db::ec::{correct_read_with_segments, read_target_segments};
+use pgr_db::ext::{get_fastx_reader, GZFastaReader, SeqIndexDB};
+use pgr_db::fasta_io::{reverse_complement, FastxWriter, SeqRec, SeqRecChunks};
+use rayon::prelude::*;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+/// Correct the noisy long reads with the matching haplotype segments in a PGR-TK pangenome database,
+/// the reads are anchored to the haplotypes by the shimmer pair hits and corrected by the consensus
+/// of the read and the segments guided by the read
+#[derive(Parser, Debug)]
+#[clap(name = "pgr-correct-reads")]
+#[clap(author, version)]
+#[clap(about, long_about = None)]
+struct CmdOptions {
+    /// the prefix to a PGR-TK sequence database
+    pgr_db_prefix: String,
+
+    /// the path to the fasta/fastq file of the reads (can be gzipped)
+    fastx_path: String,
+
+    /// the output fasta file of the corrected reads
+    output_path: String,
+
+    /// using the frg format for the sequence database (default to the AGC backend database if not specified)
+    #[clap(long, default_value_t = false)]
+    frg_file: bool,
+
+    /// the gap penalty factor for sparse alignments in the SHIMMER space
+    #[clap(long, default_value_t = 0.025)]
+    gap_penalty_factor: f32,
+
+    /// the max count of SHIMMER used for the sparse alignment
+    #[clap(long, default_value_t = 128)]
+    max_count: u32,
+
+    /// the span of the chain for building the sparse alignment directed acyclic graph
+    #[clap(long, default_value_t = 8)]
+    max_aln_chain_span: u32,
+
+    /// the min number of the anchor hits (shimmer pairs) of a haplotype segment to be used for the correction
+    #[clap(long, default_value_t = 10)]
+    min_anchor_count: usize,
+
+    /// the max number of the haplotype segments (with the most anchor hits) used for correcting a read
+    #[clap(long, default_value_t = 4)]
+    max_segments: usize,
+
+    /// the extra bases of the haplotype segments beyond the ends of the reads
+    #[clap(long, default_value_t = 200)]
+    flank: u32,
+
+    /// the min number of the sequences (the read and the segments) supporting the bases of the read to keep them
+    #[clap(long, default_value_t = 2)]
+    min_cov: u32,
+
+    /// the max difference rate between a read and the corrected read, the read is not corrected if it is exceeded
+    #[clap(long, default_value_t = 0.15)]
+    max_diff_rate: f64,
+
+    /// only output the corrected reads, the reads can't be corrected are written as they are by default
+    #[clap(long, default_value_t = false)]
+    corrected_only: bool,
+
+    /// write the correction summary (read name, length, segment count, corrected length, differences, status) to a tsv file
+    #[clap(long, default_value = None)]
+    report_file: Option<String>,
+
+    /// the number of the reads processed in a batch
+    #[clap(long, default_value_t = 256)]
+    batch_size: usize,
+
+    /// number of threads used in parallel (more memory usage), default to "0" using all CPUs available or the number set by RAYON_NUM_THREADS
+    #[clap(long, default_value_t = 0)]
+    number_of_thread: usize,
+}
+
+// (corrected read, the number of the segments, the number of the differences, status)
+type CorrectionResult = (Option<Vec<u8>>, usize, u32, &'static str);
+
+fn correct_read(seq_index_db: &SeqIndexDB, args: &CmdOptions, read: &SeqRec) -> CorrectionResult {
+    let read_len = read.seq.len() as u32;
+    let hits = seq_index_db.query_fragment_to_hps_from_mmap_file(
+        &read.seq,
+        aln::ChainGapCost::Linear(args.gap_penalty_factor),
+        Some(args.max_count),
+        Some(args.max_count),
+        Some(args.max_count),
+        Some(args.max_aln_chain_span),
+        None,
+        false,
+    );
+    let segments = hits
+        .map(|hits| {
+            read_target_segments(
+                &hits,
+                read_len,
+                args.min_anchor_count,
+                args.max_segments,
+                args.flank,
+            )
+        })
+        .unwrap_or_default();
+    if segments.is_empty() {
+        return (None, 0, 0, "no_hit");
+    }
+    let seq_info = seq_index_db.seq_info.as_ref().unwrap();
+    let segment_seqs = segments
+        .iter()
+        .filter_map(|&(sid, bgn, end, orientation, _)| {
+            let (_, _, t_len) = seq_info.get(&sid)?;
+            let end = end.min(*t_len);
+            if bgn >= end {
+                return None;
+            }
+            let seq = seq_index_db
+                .get_sub_seq_by_id(sid, bgn as usize, end as usize)
+                .ok()?;
+            if orientation == 0 {
+                Some(seq)
+            } else {
+                Some(reverse_complement(&seq))
+            }
+        })
+        .collect::<Vec<Vec<u8>>>();
+    let n_segments = segment_seqs.len();
+    match correct_read_with_segments(
+        &read.seq,
+        segment_seqs,
+        &None,
+        args.min_cov,
+        args.max_diff_rate,
+    ) {
+        Some((corrected, n_diffs)) => (Some(corrected), n_segments, n_diffs, "corrected"),
+        None => (None, n_segments, 0, "uncorrected"),
+    }
+}
+
+fn main() -> Result<(), std::io::Error> {
+    CmdOptions::command().version(VERSION_STRING).get_matches();
+    let args = CmdOptions::parse();
+
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(args.number_of_thread)
+        .build_global()
+        .unwrap();
+
+    let mut seq_index_db = SeqIndexDB::new();
+    #[cfg(feature = "with_agc")]
+    if args.frg_file {
+        seq_index_db.load_from_frg_index(args.pgr_db_prefix.clone())?;
+    } else {
+        seq_index_db.load_from_agc_index(args.pgr_db_prefix.clone())?;
+    }
+    #[cfg(not(feature = "with_agc"))]
+    if args.frg_file {
+        seq_index_db.load_from_frg_index(args.pgr_db_prefix.clone())?;
+    } else {
+        panic!("This command is compiled with only frg file support, please specify `--frg-file");
+    }
+
+    let mut out = FastxWriter::new(BufWriter::new(File::create(&args.output_path)?), 0);
+    let mut report = if let Some(report_file) = args.report_file.as_ref() {
+        let mut f = BufWriter::new(File::create(report_file)?);
+        writeln!(
+            f,
+            "#read_name\tread_len\tn_segments\tcorrected_len\tn_diffs\tstatus"
+        )?;
+        Some(f)
+    } else {
+        None
+    };
+
+    let mut correct_reads =
+        |seq_iter: &mut dyn Iterator<Item = io::Result<SeqRec>>| -> Result<(), std::io::Error> {
+            SeqRecChunks::new(seq_iter, args.batch_size, 0).try_for_each(|reads| {
+                let reads = reads?;
+                let results = reads
+                    .par_iter()
+                    .map(|read| correct_read(&seq_index_db, &args, read))
+                    .collect::<Vec<_>>();
+                reads.iter().zip(results).try_for_each(
+                    |(read, (corrected, n_segments, n_diffs, status))| {
+                        let read_name = String::from_utf8_lossy(&read.id);
+                        if let Some(report) = report.as_mut() {
+                            writeln!(
+                                report,
+                                "{}\t{}\t{}\t{}\t{}\t{}",
+                                read_name,
+                                read.seq.len(),
+                                n_segments,
+                                corrected.as_ref().map_or(0, |seq| seq.len()),
+                                n_diffs,
+                                status
+                            )?;
+                        }
+                        match corrected {
+                            Some(seq) => out.write_fasta(&read_name, &seq),
+                            None if !args.corrected_only => out.write_fasta(&read_name, &read.seq),
+                            None => Ok(()),
+                        }
+                    },
+                )
+            })
+        };
+
+    match get_fastx_reader(args.fastx_path.clone(), true)? {
+        GZFastaReader::GZFile(mut reader) => correct_reads(&mut reader)?,
+        GZFastaReader::RegularFile(mut reader) => correct_reads(&mut reader)?,
+    };
+    out.flush()?;
+    if let Some(mut report) = report {
+        report.flush()?;
+    }
+    Ok(())
+}
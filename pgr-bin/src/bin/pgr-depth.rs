@@ -0,0 +1,162 @@
+const VERSION_STRING: &str = env!("VERSION_STRING");
+use clap::{self, CommandFactory, Parser};
+use pgr_db::ext::SeqIndexDB;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Report the number of haplotypes traversing each vertex and link (and principal bundle) of the MAP graph
+/// built from a fasta file, output as the "depth" and "haplotypes" columns of the MAP graph coverage TSV file
+/// and as the "DP:i" and "HP:Z" tags in a GFA file
+#[derive(Parser, Debug)]
+#[clap(name = "pgr-depth")]
+#[clap(author, version)]
+#[clap(about, long_about = None)]
+struct CmdOptions {
+    /// the path to the input fasta file of the haplotype sequences
+    fastx_path: String,
+    /// the prefix of the output files
+    output_prefix: String,
+    /// the SHIMMER parameter w
+    #[clap(short, default_value_t = 48)]
+    w: u32,
+    /// the SHIMMER parameter k
+    #[clap(short, default_value_t = 56)]
+    k: u32,
+    /// the SHIMMER parameter r
+    #[clap(short, default_value_t = 4)]
+    r: u32,
+    /// the SHIMMER parameter minimum span length
+    #[clap(long, default_value_t = 12)]
+    min_span: u32,
+    /// vertex minimum coverage in MAP-graph to be reported
+    #[clap(long, default_value_t = 0)]
+    min_cov: usize,
+    /// use the "sample#haplotype" prefix of the PanSN contig names (sample#haplotype#contig) as the haplotype names
+    #[clap(long, default_value_t = false)]
+    pansn: bool,
+    /// report the haplotypes traversing each principal bundle
+    #[clap(long, default_value_t = false)]
+    bundle: bool,
+    /// the minimum branch length in MAP-graph to be included in the principal bundles
+    #[clap(long, default_value_t = 8)]
+    min_branch_size: usize,
+    /// the minimum fraction of the bundle vertices a haplotype needs to hit to be counted as traversing the bundle
+    #[clap(long, default_value_t = 0.5)]
+    min_bundle_vertex_fraction: f32,
+}
+
+fn main() -> Result<(), std::io::Error> {
+    CmdOptions::command().version(VERSION_STRING).get_matches();
+    let args = CmdOptions::parse();
+    let mut seq_index_db = SeqIndexDB::new();
+    seq_index_db
+        .load_from_fastx(
+            args.fastx_path.clone(),
+            args.w,
+            args.k,
+            args.r,
+            args.min_span,
+            true,
+        )
+        .unwrap_or_else(|_| panic!("can't read file {}", args.fastx_path));
+
+    let sid_to_haplotype = seq_index_db
+        .seq_info
+        .as_ref()
+        .unwrap()
+        .iter()
+        .map(|(sid, (ctg, _, _))| {
+            let haplotype = if args.pansn {
+                ctg.split('#').take(2).collect::<Vec<&str>>().join("#")
+            } else {
+                ctg.clone()
+            };
+            (*sid, haplotype)
+        })
+        .collect::<FxHashMap<u32, String>>();
+
+    // the segments and the links with the number and the names of the haplotypes traversing them, with
+    // the same tags and columns as the coverage outputs of the other MAP graph tools
+    let output_prefix_path = Path::new(&args.output_prefix);
+    seq_index_db.write_mapg_coverage_tsv(
+        args.min_cov,
+        output_prefix_path
+            .with_extension("mapg.cov.tsv")
+            .to_str()
+            .unwrap(),
+        "from_fragmap",
+        None,
+        Some(&sid_to_haplotype),
+    )?;
+    seq_index_db.generate_mapg_gfa_with_haplotypes(
+        args.min_cov,
+        output_prefix_path
+            .with_extension("depth.mapg.gfa")
+            .to_str()
+            .unwrap(),
+        "from_fragmap",
+        None,
+        Some(&sid_to_haplotype),
+    )?;
+
+    if args.bundle {
+        let frag_map = seq_index_db.get_shmmr_map_internal().unwrap();
+        // vertex -> sorted haplotype names
+        let vertex_haplotypes = frag_map
+            .iter()
+            .filter(|(_, frags)| frags.len() >= args.min_cov)
+            .map(|(smp, frags)| {
+                let mut haplotypes = frags
+                    .iter()
+                    .map(|v| sid_to_haplotype.get(&v.1).unwrap().clone())
+                    .collect::<FxHashSet<String>>()
+                    .into_iter()
+                    .collect::<Vec<String>>();
+                haplotypes.sort();
+                (*smp, haplotypes)
+            })
+            .collect::<FxHashMap<(u64, u64), Vec<String>>>();
+
+        let (principal_bundles_with_id, _) =
+            seq_index_db.get_principal_bundles_with_id(args.min_cov, args.min_branch_size, None);
+        let mut bundle_depth_file = BufWriter::new(File::create(
+            output_prefix_path.with_extension("bundle_depth.tsv"),
+        )?);
+        writeln!(
+            bundle_depth_file,
+            "#{}",
+            ["bundle_id", "vertex_count", "depth", "haplotypes"].join("\t")
+        )?;
+        principal_bundles_with_id.iter().try_for_each(
+            |(bundle_id, _, vertices)| -> Result<(), std::io::Error> {
+                let mut haplotype_vertex_count = FxHashMap::<&String, usize>::default();
+                vertices.iter().for_each(|v| {
+                    if let Some(haplotypes) = vertex_haplotypes.get(&(v.0, v.1)) {
+                        haplotypes.iter().for_each(|h| {
+                            *haplotype_vertex_count.entry(h).or_insert(0) += 1;
+                        });
+                    }
+                });
+                let mut haplotypes = haplotype_vertex_count
+                    .into_iter()
+                    .filter(|(_, c)| {
+                        *c as f32 >= args.min_bundle_vertex_fraction * vertices.len() as f32
+                    })
+                    .map(|(h, c)| format!("{}:{}", h, c))
+                    .collect::<Vec<String>>();
+                haplotypes.sort();
+                writeln!(
+                    bundle_depth_file,
+                    "{}\t{}\t{}\t{}",
+                    bundle_id,
+                    vertices.len(),
+                    haplotypes.len(),
+                    haplotypes.join(",")
+                )
+            },
+        )?;
+    }
+    Ok(())
+}
@@ -1,7 +1,9 @@
 const VERSION_STRING: &str = env!("VERSION_STRING");
 use clap::{self, CommandFactory, Parser};
 use pgr_db::ext::SeqIndexDB;
-use pgr_db::fasta_io;
+use pgr_db::fasta_io::{self, FastxWriter};
+use pgr_db::gff_db::GFFDB;
+use rustc_hash::FxHashMap;
 use std::fs::File;
 use std::io::{self, BufRead, BufReader, BufWriter, Write};
 use std::path::Path;
@@ -19,6 +21,11 @@ struct CmdOptions {
     #[clap(long, default_value_t = false)]
     frg_file: bool,
 
+    /// using the indexed fasta files for the sequence database, the index files and the `.fasta_manifest` file
+    /// are created by `pgr-make-db --indexed-fasta`
+    #[clap(long, default_value_t = false)]
+    indexed_fasta: bool,
+
     /// the regions file path
     #[clap(short, long, default_value=None)]
     region_file: Option<String>,
@@ -30,6 +37,15 @@ struct CmdOptions {
     /// list all sequence source, contig names in the database
     #[clap(long, default_value_t = false)]
     list: bool,
+
+    /// a tab-separated manifest of the sample (source) names and the paths to their GFF3/GTF annotation files,
+    /// the features in the fetched regions are written to the GFF output file
+    #[clap(long, requires = "gff_output")]
+    gff_manifest: Option<String>,
+
+    /// the GFF3 output file of the features in the fetched regions, with the coordinates on the region sequences
+    #[clap(long)]
+    gff_output: Option<String>,
 }
 
 fn main() -> Result<(), std::io::Error> {
@@ -41,14 +57,18 @@ fn main() -> Result<(), std::io::Error> {
     #[cfg(feature = "with_agc")]
     if args.frg_file {
         let _ = seq_index_db.load_from_frg_index(args.pgr_db_prefix);
+    } else if args.indexed_fasta {
+        seq_index_db.load_from_indexed_fasta(args.pgr_db_prefix)?;
     } else {
         let _ = seq_index_db.load_from_agc_index(args.pgr_db_prefix);
     }
     #[cfg(not(feature = "with_agc"))]
     if args.frg_file {
         let _ = seq_index_db.load_from_frg_index(args.pgr_db_prefix);
+    } else if args.indexed_fasta {
+        seq_index_db.load_from_indexed_fasta(args.pgr_db_prefix)?;
     } else {
-        panic!("This command is compiled with only frg file support, please specify `--frg-file");
+        panic!("This command is compiled without the AGC support, please specify `--frg-file` or `--indexed-fasta`");
     }
 
     if args.list {
@@ -80,7 +100,7 @@ fn main() -> Result<(), std::io::Error> {
     let region_file =
         BufReader::new(File::open(Path::new(&region_file)).expect("can't open the region file"));
 
-    let mut out = if args.output_file.is_some() {
+    let out = if args.output_file.is_some() {
         let f = BufWriter::new(
             File::create(args.output_file.unwrap()).expect("can't open the ouptfile"),
         );
@@ -88,27 +108,88 @@ fn main() -> Result<(), std::io::Error> {
     } else {
         Box::new(io::stdout())
     };
+    let mut out = FastxWriter::new(out, 0);
 
-    region_file.lines().for_each(|line| {
-        let line = line.expect("fail to get a line in the region file");
+    let mut gffs = FxHashMap::<String, GFFDB>::default();
+    if let Some(gff_manifest) = args.gff_manifest {
+        let gff_manifest = BufReader::new(File::open(Path::new(&gff_manifest))?);
+        for line in gff_manifest.lines() {
+            let line = line?;
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields = line.trim().split('\t').collect::<Vec<&str>>();
+            if fields.len() < 2 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("gff manifest parsing error on the line: {}", line),
+                ));
+            }
+            gffs.insert(
+                fields[0].to_string(),
+                GFFDB::from_file(Path::new(fields[1]))?,
+            );
+        }
+    }
+    let mut gff_out = if let Some(gff_output) = args.gff_output {
+        let mut f = BufWriter::new(File::create(gff_output)?);
+        writeln!(f, "##gff-version 3")?;
+        Some(f)
+    } else {
+        None
+    };
+
+    for (line_idx, line) in region_file.lines().enumerate() {
+        let line = line?;
+        let parse_error = |msg: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "region file parsing error on the line {}, {}: {}",
+                    line_idx + 1,
+                    msg,
+                    line
+                ),
+            )
+        };
         let fields = line.split('\t').collect::<Vec<&str>>();
+        if fields.len() < 6 {
+            return Err(parse_error(
+                "6 columns (label, source, contig, bgn, end, strand) are expected",
+            ));
+        }
         let label = fields[0].to_string();
         let src = fields[1].to_string();
         let ctg = fields[2].to_string();
-        let bgn: usize = fields[3].parse().expect("can't parse bgn");
-        let end: usize = fields[4].parse().expect("can't parse end");
-        let reversed: bool = fields[5].parse::<u32>().expect("can't parse strand") == 1;
-        let mut seq = seq_index_db
-            .get_sub_seq(src, ctg, bgn, end)
-            .expect("fail to fetch sequence");
+        let bgn: usize = fields[3]
+            .parse()
+            .map_err(|_| parse_error("can't parse bgn"))?;
+        let end: usize = fields[4]
+            .parse()
+            .map_err(|_| parse_error("can't parse end"))?;
+        let reversed: bool = fields[5]
+            .parse::<u32>()
+            .map_err(|_| parse_error("can't parse strand"))?
+            == 1;
+        if bgn >= end {
+            return Err(parse_error("bgn should be less than end"));
+        }
+        if let (Some(gff), Some(gff_out)) = (gffs.get(&src), gff_out.as_mut()) {
+            writeln!(gff_out, "##sequence-region {} 1 {}", label, end - bgn)?;
+            gff.region_records(&ctg, bgn as u32, end as u32, &label, reversed)
+                .into_iter()
+                .try_for_each(|rec| writeln!(gff_out, "{}", rec))?;
+        }
+        let mut seq = seq_index_db.get_sub_seq(src, ctg, bgn, end)?;
         if reversed {
             seq = fasta_io::reverse_complement(&seq);
         }
 
-        writeln!(out, ">{}", label).expect("fail to write the sequences");
-        writeln!(out, "{}", String::from_utf8_lossy(&seq[..]))
-            .expect("fail to write the sequences");
-    });
+        out.write_fasta(&label, &seq)?;
+    }
 
+    if let Some(mut gff_out) = gff_out {
+        gff_out.flush()?;
+    }
     Ok(())
 }
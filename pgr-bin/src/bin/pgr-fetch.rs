@@ -0,0 +1,282 @@
+const VERSION_STRING: &str = env!("VERSION_STRING");
+use clap::{self, CommandFactory, Parser};
+use pgr_db::ext::SeqIndexDB;
+use pgr_db::fasta_io::{self, BgzfWriter, FastxCompression, FastxWriter, IndexedFastaReader};
+use rustc_hash::FxHashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Fetch sequences or sub-sequences from a PGR-TK database of any backend (AGC, FRG or fasta)
+///
+/// A region is specified as "NAME" or "NAME:BGN-END" with 0-based, end-exclusive coordinates.
+/// NAME can be "SOURCE::CONTIG", a PanSN name "SAMPLE#HAPLOTYPE#CONTIG" or a contig name
+/// that is unique in the database.
+#[derive(Parser, Debug)]
+#[clap(name = "pgr-fetch")]
+#[clap(author, version)]
+#[clap(about, long_about = None)]
+struct CmdOptions {
+    /// the prefix to a PGR-TK sequence database, or the path to a fasta file with `--fastx-file`
+    pgr_db_prefix: String,
+
+    /// the regions to fetch
+    regions: Vec<String>,
+
+    /// using the frg format for the sequence database (default to the AGC backend database if not specified)
+    #[clap(long, default_value_t = false)]
+    frg_file: bool,
+
+    /// using a fasta file (plain or bgzipped) as the sequence database, the sequences are fetched with the `.fai`
+    /// index of the file, which is created next to the file if it does not exist
+    #[clap(long, default_value_t = false)]
+    fastx_file: bool,
+
+    /// a bed file of the regions to fetch, the optional 4th column is used as the output name and the optional 6th column ("+"/"-") for the strand
+    #[clap(long, default_value = None)]
+    region_file: Option<String>,
+
+    /// output the reverse complement of the sequences
+    #[clap(short = 'R', long, default_value_t = false)]
+    reverse_complement: bool,
+
+    /// the delimiter of the PanSN names
+    #[clap(long, default_value_t = '#')]
+    pansn_delimiter: char,
+
+    /// output file name, the output is bgzipped if the file name ends with ".gz" or ".bgz"
+    #[clap(short, long, default_value = None)]
+    output_file: Option<String>,
+
+    /// the number of bases per line of the output sequences, "0" for writing each sequence in one line
+    #[clap(long, default_value_t = 0)]
+    line_width: usize,
+
+    /// list all sequences in the database, one "SID<TAB>SOURCE::CONTIG<TAB>LENGTH" line per sequence, the
+    /// "SOURCE::CONTIG" names can be used as the regions
+    #[clap(long, default_value_t = false)]
+    list: bool,
+}
+
+/// resolve a sequence name to the sequence id in the database
+fn resolve_name(
+    name: &str,
+    seq_index: &FxHashMap<(String, Option<String>), (u32, u32)>,
+    ctg_to_sids: &FxHashMap<String, Vec<u32>>,
+    pansn_delimiter: char,
+) -> Option<u32> {
+    if let Some((src, ctg)) = name.split_once("::") {
+        return seq_index
+            .get(&(ctg.to_string(), Some(src.to_string())))
+            .map(|v| v.0);
+    }
+    if let Some(sids) = ctg_to_sids.get(name) {
+        if sids.len() == 1 {
+            return Some(sids[0]);
+        }
+    }
+    // PanSN: sample#haplotype#contig, the source can be "sample#haplotype", "sample.haplotype" or "sample"
+    let fields = name.splitn(3, pansn_delimiter).collect::<Vec<&str>>();
+    if fields.len() == 3 {
+        let (sample, haplotype, ctg) = (fields[0], fields[1], fields[2]);
+        [
+            format!("{}{}{}", sample, pansn_delimiter, haplotype),
+            format!("{}.{}", sample, haplotype),
+            sample.to_string(),
+        ]
+        .into_iter()
+        .find_map(|src| seq_index.get(&(ctg.to_string(), Some(src))).map(|v| v.0))
+    } else {
+        None
+    }
+}
+
+/// split "NAME:BGN-END" into the name and the optional range
+fn parse_region(region: &str) -> (String, Option<(usize, usize)>) {
+    if let Some((name, range)) = region.rsplit_once(':') {
+        if let Some((bgn, end)) = range.split_once('-') {
+            if let (Ok(bgn), Ok(end)) = (
+                bgn.replace(',', "").parse::<usize>(),
+                end.replace(',', "").parse::<usize>(),
+            ) {
+                return (name.to_string(), Some((bgn, end)));
+            }
+        }
+    }
+    (region.to_string(), None)
+}
+
+fn main() -> Result<(), std::io::Error> {
+    CmdOptions::command().version(VERSION_STRING).get_matches();
+    let args = CmdOptions::parse();
+
+    let mut seq_index_db = SeqIndexDB::new();
+    // a fasta file is read through its .fai index without indexing the shimmers of the sequences, the
+    // sequence ids are the indices of the sequences in the file and the source is the file path
+    let mut fasta_file = None;
+    if args.frg_file {
+        seq_index_db.load_from_frg_index(args.pgr_db_prefix)?;
+    } else if args.fastx_file {
+        let reader = IndexedFastaReader::new(&args.pgr_db_prefix)?;
+        let mut seq_index = FxHashMap::<(String, Option<String>), (u32, u32)>::default();
+        let mut seq_info = FxHashMap::<u32, (String, Option<String>, u32)>::default();
+        reader
+            .fai_records()
+            .iter()
+            .enumerate()
+            .for_each(|(sid, rec)| {
+                let source = Some(args.pgr_db_prefix.clone());
+                seq_index.insert(
+                    (rec.name.clone(), source.clone()),
+                    (sid as u32, rec.length as u32),
+                );
+                seq_info.insert(sid as u32, (rec.name.clone(), source, rec.length as u32));
+            });
+        seq_index_db.seq_index = Some(seq_index);
+        seq_index_db.seq_info = Some(seq_info);
+        fasta_file = Some(reader);
+    } else {
+        #[cfg(feature = "with_agc")]
+        seq_index_db.load_from_agc_index(args.pgr_db_prefix)?;
+
+        #[cfg(not(feature = "with_agc"))]
+        panic!("This command is compiled with only frg file support, please specify `--frg-file");
+    }
+
+    let mut out = if let Some(output_file) = args.output_file {
+        let f = BufWriter::new(File::create(&output_file).expect("can't open the output file"));
+        if FastxCompression::from_path(&output_file) == FastxCompression::Bgzf {
+            Box::new(BgzfWriter::new(f)) as Box<dyn Write>
+        } else {
+            Box::new(f) as Box<dyn Write>
+        }
+    } else {
+        Box::new(io::stdout())
+    };
+
+    let seq_index = seq_index_db.seq_index.as_ref().unwrap();
+    let seq_info = seq_index_db.seq_info.as_ref().unwrap();
+
+    if args.list {
+        let mut sids = seq_info.keys().copied().collect::<Vec<u32>>();
+        sids.sort();
+        sids.into_iter().for_each(|sid| {
+            let (ctg, src, len) = seq_info.get(&sid).unwrap();
+            let src = src.clone().unwrap_or_else(|| "None".to_string());
+            writeln!(out, "{}\t{}::{}\t{}", sid, src, ctg, len).expect("can't write output file")
+        });
+        return Ok(());
+    }
+
+    let mut out = FastxWriter::new(out, args.line_width);
+
+    // the bases [bgn, end) of a sequence, the bases from a fasta file are in the upper case as the ones in the databases
+    let get_sub_seq = |sid: u32, bgn: usize, end: usize| -> Result<Vec<u8>, std::io::Error> {
+        if let Some(fasta_file) = fasta_file.as_ref() {
+            let (ctg, _, _) = seq_info.get(&sid).unwrap();
+            Ok(fasta_file.get_sub_seq(ctg, bgn, end)?.to_ascii_uppercase())
+        } else {
+            seq_index_db.get_sub_seq_by_id(sid, bgn, end)
+        }
+    };
+
+    let mut ctg_to_sids = FxHashMap::<String, Vec<u32>>::default();
+    seq_info.iter().for_each(|(sid, (ctg, _, _))| {
+        ctg_to_sids.entry(ctg.clone()).or_default().push(*sid);
+    });
+
+    // (output name, sequence name, range, reverse complement)
+    let mut requests = args
+        .regions
+        .iter()
+        .map(|region| {
+            let (name, range) = parse_region(region);
+            (region.clone(), name, range, args.reverse_complement)
+        })
+        .collect::<Vec<_>>();
+
+    if let Some(region_file) = args.region_file {
+        let region_file = BufReader::new(File::open(Path::new(&region_file))?);
+        for (line_idx, line) in region_file.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let parse_error = |msg: &str| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!(
+                        "region file parsing error on the line {}, {}: {}",
+                        line_idx + 1,
+                        msg,
+                        line
+                    ),
+                )
+            };
+            let fields = line.split('\t').collect::<Vec<&str>>();
+            if fields.len() < 3 {
+                return Err(parse_error("at least 3 columns are expected"));
+            }
+            let name = fields[0].to_string();
+            let bgn: usize = fields[1]
+                .parse()
+                .map_err(|_| parse_error("can't parse bgn"))?;
+            let end: usize = fields[2]
+                .parse()
+                .map_err(|_| parse_error("can't parse end"))?;
+            let label = fields
+                .get(3)
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| format!("{}:{}-{}", name, bgn, end));
+            let reversed = fields.get(5) == Some(&"-");
+            requests.push((
+                label,
+                name,
+                Some((bgn, end)),
+                reversed ^ args.reverse_complement,
+            ));
+        }
+    }
+
+    requests.into_iter().try_for_each(
+        |(label, name, range, reversed)| -> Result<(), std::io::Error> {
+            let sid = resolve_name(&name, seq_index, &ctg_to_sids, args.pansn_delimiter)
+                .ok_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::Other,
+                        format!("can't find the sequence {} in the database", name),
+                    )
+                })?;
+            let mut seq = if let Some((bgn, end)) = range {
+                let (_, _, len) = seq_info.get(&sid).unwrap();
+                if bgn >= end || bgn >= *len as usize {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!(
+                            "invalid region {}:{}-{} of the sequence with length {}",
+                            name, bgn, end, len
+                        ),
+                    ));
+                }
+                let end = if end > *len as usize {
+                    *len as usize
+                } else {
+                    end
+                };
+                get_sub_seq(sid, bgn, end)?
+            } else {
+                let (_, _, len) = seq_info.get(&sid).unwrap();
+                get_sub_seq(sid, 0, *len as usize)?
+            };
+            let label = if reversed {
+                seq = fasta_io::reverse_complement(&seq);
+                format!("{}/rc", label)
+            } else {
+                label
+            };
+            out.write_fasta(&label, &seq)
+        },
+    )?;
+
+    Ok(())
+}
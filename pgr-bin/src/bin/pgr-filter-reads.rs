@@ -0,0 +1,304 @@
+const VERSION_STRING: &str = env!("VERSION_STRING");
+use clap::{self, CommandFactory, Parser};
+use pgr_db::ext::{get_fastx_reader, GZFastaReader, SeqIndexDB};
+use pgr_db::fasta_io::{FastaReader, FastxWriter, SeqRec, SeqRecChunks};
+use pgr_db::kmer_filter::{CountingMinimizerFilter, ShmmrPairFilter};
+use pgr_db::seq_db::{self, ShmmrPair};
+use rayon::prelude::*;
+use rustc_hash::FxHashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+
+/// Screen the reads against a PGR-TK index, keeping the reads sharing enough shimmer pairs with the pangenome,
+/// or the reads not sharing them (with `--inverse`) for removing the contamination; with `--locus-region-file`
+/// or `--locus-fastx`, only the shimmer pairs of the locus are used for recruiting the reads for a local assembly
+#[derive(Parser, Debug)]
+#[clap(name = "pgr-filter-reads")]
+#[clap(author, version)]
+#[clap(about, long_about = None)]
+struct CmdOptions {
+    /// the prefix to a PGR-TK sequence database, the shimmer pairs and parameters are read from `<prefix>.mdb`
+    pgr_db_prefix: String,
+
+    /// the path to the fasta/fastq file of the reads (can be gzipped), "-" for reading from the standard input
+    fastx_path: String,
+
+    /// output file name, the reads are written to the standard output if not specified
+    #[clap(short, long, default_value=None)]
+    output_file: Option<String>,
+
+    /// the min number of the shimmer pairs of a read in the index to keep the read
+    #[clap(long, default_value_t = 1)]
+    min_hits: usize,
+
+    /// keep the reads with fewer than `--min-hits` shimmer pairs in the index instead
+    #[clap(long, default_value_t = false)]
+    inverse: bool,
+
+    /// the regions of a targeted locus in the database, in the region file format of `pgr-fetch-seqs`
+    /// (label, source, contig, bgn, end, strand), the reads are screened by the shimmer pairs of the regions only
+    #[clap(long, default_value=None)]
+    locus_region_file: Option<String>,
+
+    /// the fasta file of the sequences of a targeted locus, e.g., the sequences from `pgr-fetch-seqs`, the reads
+    /// are screened by the shimmer pairs of the sequences only
+    #[clap(long, default_value=None)]
+    locus_fastx: Option<String>,
+
+    /// drop the shimmer pairs of the targeted locus with more than this number of the fragments in the whole index,
+    /// so the reads of the repeats elsewhere are not recruited, "0" for no limit
+    #[clap(long, default_value_t = 0)]
+    max_index_count: usize,
+
+    /// drop the reads with more than half of their shimmers seen more than this number of times in all the reads,
+    /// e.g., the reads of the ultra-high-copy satellites, "0" for no limit; the reads are read twice for counting
+    /// the shimmers first, so it can't be used with the standard input
+    #[clap(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..255))]
+    max_abundance: u8,
+
+    /// the expected number of the distinct shimmers of the reads for sizing the counting filter of `--max-abundance`
+    #[clap(long, default_value_t = 100_000_000)]
+    abundance_n_shmmrs: usize,
+
+    /// the false-positive rate of the counting filter of `--max-abundance`, a smaller rate uses more memory
+    #[clap(long, default_value_t = 0.01)]
+    abundance_fp_rate: f64,
+
+    /// using the frg format for the sequence database (default to the AGC backend database if not specified)
+    #[clap(long, default_value_t = false)]
+    frg_file: bool,
+
+    /// write the number of the shimmer pairs and the hits of each read to a tsv file
+    #[clap(long, default_value=None)]
+    report_file: Option<String>,
+
+    /// the number of the reads processed in a batch
+    #[clap(long, default_value_t = 4096)]
+    batch_size: usize,
+
+    /// the max number of the bases in a batch, "0" for no limit
+    #[clap(long, default_value_t = 0)]
+    batch_max_bases: usize,
+
+    /// number of threads used in parallel (more memory usage), default to "0" using all CPUs available or the number set by RAYON_NUM_THREADS
+    #[clap(long, default_value_t = 0)]
+    number_of_thread: usize,
+}
+
+/// build the filter from the shimmer pairs of the targeted locus, with the shimmer parameters of the index
+fn load_locus_filter(args: &CmdOptions) -> Result<ShmmrPairFilter, std::io::Error> {
+    let (shmmr_spec, frag_locations) =
+        seq_db::read_mdb_file_to_frag_locations(args.pgr_db_prefix.clone() + ".mdb")?;
+    let mut filter = ShmmrPairFilter::new(shmmr_spec);
+
+    if let Some(locus_region_file) = args.locus_region_file.as_ref() {
+        let mut seq_index_db = SeqIndexDB::new();
+        #[cfg(feature = "with_agc")]
+        if args.frg_file {
+            seq_index_db.load_from_frg_index(args.pgr_db_prefix.clone())?;
+        } else {
+            seq_index_db.load_from_agc_index(args.pgr_db_prefix.clone())?;
+        }
+        #[cfg(not(feature = "with_agc"))]
+        if args.frg_file {
+            seq_index_db.load_from_frg_index(args.pgr_db_prefix.clone())?;
+        } else {
+            panic!(
+                "This command is compiled with only frg file support, please specify `--frg-file"
+            );
+        }
+
+        let region_file = BufReader::new(File::open(locus_region_file)?);
+        for line in region_file.lines() {
+            let line = line?;
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields = line.trim().split('\t').collect::<Vec<&str>>();
+            if fields.len() < 5 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("locus region file parsing error on the line: {}", line),
+                ));
+            }
+            let parse_pos = |field: &str| {
+                field.parse::<usize>().map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("can't parse the position on the line: {}", line),
+                    )
+                })
+            };
+            let bgn = parse_pos(fields[3])?;
+            let end = parse_pos(fields[4])?;
+            let seq =
+                seq_index_db.get_sub_seq(fields[1].to_string(), fields[2].to_string(), bgn, end)?;
+            filter.add_seq(&seq);
+        }
+    }
+
+    if let Some(locus_fastx) = args.locus_fastx.as_ref() {
+        let mut add_seqs = |seq_iter: &mut dyn Iterator<Item = io::Result<SeqRec>>| {
+            seq_iter.try_for_each(|rec| -> Result<(), std::io::Error> {
+                filter.add_seq(&rec?.seq);
+                Ok(())
+            })
+        };
+        match get_fastx_reader(locus_fastx.clone(), true)? {
+            GZFastaReader::GZFile(mut reader) => add_seqs(&mut reader)?,
+            GZFastaReader::RegularFile(mut reader) => add_seqs(&mut reader)?,
+        };
+    }
+
+    if args.max_index_count > 0 {
+        let index_counts = frag_locations
+            .into_iter()
+            .map(|(shmmr_pair, (_, count))| (shmmr_pair, count))
+            .collect::<FxHashMap<ShmmrPair, usize>>();
+        filter.retain(|shmmr_pair| {
+            !index_counts
+                .get(shmmr_pair)
+                .is_some_and(|&count| count > args.max_index_count)
+        });
+    }
+    Ok(filter)
+}
+
+/// count the shimmers of all reads in a counting filter, with the shimmer parameters of the index
+fn count_read_shmmrs(
+    args: &CmdOptions,
+    filter: &ShmmrPairFilter,
+) -> Result<CountingMinimizerFilter, std::io::Error> {
+    let mut abundance_filter = CountingMinimizerFilter::new(
+        filter.shmmr_spec().clone(),
+        args.abundance_n_shmmrs,
+        args.abundance_fp_rate,
+    );
+    let mut add_seqs = |seq_iter: &mut dyn Iterator<Item = io::Result<SeqRec>>| {
+        seq_iter.try_for_each(|rec| -> Result<(), std::io::Error> {
+            abundance_filter.add_seq_mmers(&rec?.seq);
+            Ok(())
+        })
+    };
+    match get_fastx_reader(args.fastx_path.clone(), false)? {
+        GZFastaReader::GZFile(mut reader) => add_seqs(&mut reader)?,
+        GZFastaReader::RegularFile(mut reader) => add_seqs(&mut reader)?,
+    };
+    Ok(abundance_filter)
+}
+
+fn main() -> Result<(), std::io::Error> {
+    CmdOptions::command().version(VERSION_STRING).get_matches();
+    let args = CmdOptions::parse();
+
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(args.number_of_thread)
+        .build_global()
+        .unwrap();
+
+    let filter = if args.locus_region_file.is_some() || args.locus_fastx.is_some() {
+        load_locus_filter(&args)?
+    } else {
+        ShmmrPairFilter::from_mdb_file(&args.pgr_db_prefix)?
+    };
+
+    let abundance_filter = if args.max_abundance > 0 {
+        if args.fastx_path == "-" {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "`--max-abundance` reads the reads twice, it can't be used with the standard input",
+            ));
+        }
+        Some(count_read_shmmrs(&args, &filter)?)
+    } else {
+        None
+    };
+
+    let out = if let Some(output_file) = args.output_file.as_ref() {
+        Box::new(BufWriter::new(File::create(output_file)?)) as Box<dyn Write>
+    } else {
+        Box::new(BufWriter::new(io::stdout()))
+    };
+    let mut out = FastxWriter::new(out, 0);
+    let mut report = if let Some(report_file) = args.report_file.as_ref() {
+        let mut f = BufWriter::new(File::create(report_file)?);
+        writeln!(f, "#read_name\tn_shmmr_pairs\tn_hits\tkept")?;
+        Some(f)
+    } else {
+        None
+    };
+
+    // the reads are streamed in batches, the shimmer pairs of the reads in a batch are checked in parallel
+    let mut n_reads = 0_usize;
+    let mut n_kept = 0_usize;
+    let mut filter_reads =
+        |seq_iter: &mut dyn Iterator<Item = io::Result<SeqRec>>| -> Result<(), std::io::Error> {
+            SeqRecChunks::new(seq_iter, args.batch_size, args.batch_max_bases).try_for_each(
+                |recs| {
+                    let recs = recs?;
+                    let hits = recs
+                        .par_iter()
+                        .map(|rec| {
+                            let (n_pairs, n_hits) = filter.check_seq(&rec.seq);
+                            let abundant = abundance_filter.as_ref().is_some_and(|f| {
+                                let (n_shmmrs, n_abundant) =
+                                    f.check_seq_abundance(&rec.seq, args.max_abundance + 1);
+                                n_abundant * 2 > n_shmmrs
+                            });
+                            let kept = !abundant && (n_hits >= args.min_hits) != args.inverse;
+                            (n_pairs, n_hits, kept)
+                        })
+                        .collect::<Vec<_>>();
+                    n_reads += recs.len();
+                    recs.iter()
+                        .zip(hits)
+                        .try_for_each(|(rec, (n_pairs, n_hits, kept))| {
+                            if let Some(report) = report.as_mut() {
+                                writeln!(
+                                    report,
+                                    "{}\t{}\t{}\t{}",
+                                    String::from_utf8_lossy(&rec.id),
+                                    n_pairs,
+                                    n_hits,
+                                    kept as u8
+                                )?;
+                            }
+                            if kept {
+                                n_kept += 1;
+                                out.write_rec(rec)?;
+                            }
+                            Ok(())
+                        })
+                },
+            )
+        };
+
+    if args.fastx_path == "-" {
+        let mut reader = FastaReader::new(
+            BufReader::new(io::stdin().lock()),
+            &"stdin".to_string(),
+            1 << 14,
+            false,
+            false,
+        )?;
+        reader.set_keep_qual(true);
+        filter_reads(&mut reader)?;
+    } else {
+        match get_fastx_reader(args.fastx_path.clone(), false)? {
+            GZFastaReader::GZFile(mut reader) => {
+                reader.set_keep_qual(true);
+                filter_reads(&mut reader)?
+            }
+            GZFastaReader::RegularFile(mut reader) => {
+                reader.set_keep_qual(true);
+                filter_reads(&mut reader)?
+            }
+        };
+    }
+    out.flush()?;
+    if let Some(mut report) = report {
+        report.flush()?;
+    }
+    log::info!("{} of {} reads are kept", n_kept, n_reads);
+    Ok(())
+}
@@ -309,8 +309,15 @@ fn get_wf_aln_diff(s0str: &[u8], s1str: &[u8]) -> AlnDiff {
         AlnDiff::FailShortSeq
     //} else if (s0str.len() as isize - s1str.len() as isize).abs() >= 128 {
     //    AlnDiff::FailLengthDiff
-    } else if let Some(aln_res) = aln::get_wfa_variant_segments(s0str, s1str, 1, Some(384), 4, 4, 1)
-    {
+    } else if let Some(aln_res) = aln::get_wfa_variant_segments(
+        s0str,
+        s1str,
+        1,
+        &aln::AlnParams {
+            max_wf_length: Some(384),
+            ..Default::default()
+        },
+    ) {
         AlnDiff::Aligned(aln_res)
     } else {
         AlnDiff::FailAln
@@ -323,7 +330,9 @@ fn get_sw_aln_diff(s0str: &[u8], s1str: &[u8]) -> AlnDiff {
         AlnDiff::FailShortSeq
     //} else if (s0str.len() as isize - s1str.len() as isize).abs() >= 128 {
     //    AlnDiff::FailLengthDiff
-    } else if let Some(aln_res) = aln::get_sw_variant_segments(s0str, s1str, 1, 4, 4, 1) {
+    } else if let Some(aln_res) =
+        aln::get_sw_variant_segments(s0str, s1str, 1, &aln::AlnParams::default())
+    {
         AlnDiff::Aligned(aln_res)
     } else {
         AlnDiff::FailAln
@@ -0,0 +1,294 @@
+const VERSION_STRING: &str = env!("VERSION_STRING");
+use clap::{self, CommandFactory, Parser};
+use pgr_db::ext::{
+    get_fastx_reader, get_principal_bundle_decomposition, GZFastaReader, SeqIndexDB,
+};
+use pgr_db::fasta_io::SeqRec;
+use rayon::prelude::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Paint the windows of a query assembly with the closest haplotype in a panel of haplotype sequences
+///
+/// The panel and the query are decomposed with the principal bundles of the panel MAP graph, the links
+/// between the consecutive bundle vertices along a sequence describe its bundle structure (the order and the
+/// orientation of the bundles), and each query window is painted with the haplotype sharing the most links
+#[derive(Parser, Debug)]
+#[clap(name = "pgr-haplotype-paint")]
+#[clap(author, version)]
+#[clap(about, long_about = None)]
+struct CmdOptions {
+    /// the path to the fasta file of the panel haplotype sequences
+    panel_fastx_path: String,
+    /// the path to the fasta file of the query assembly
+    query_fastx_path: String,
+    /// the prefix of the output files
+    output_prefix: String,
+    /// the SHIMMER parameter w
+    #[clap(short, default_value_t = 48)]
+    w: u32,
+    /// the SHIMMER parameter k
+    #[clap(short, default_value_t = 56)]
+    k: u32,
+    /// the SHIMMER parameter r
+    #[clap(short, default_value_t = 4)]
+    r: u32,
+    /// the SHIMMER parameter minimum span length
+    #[clap(long, default_value_t = 12)]
+    min_span: u32,
+    /// vertex minimum coverage in the panel MAP graph to be included in the principal bundles
+    #[clap(long, default_value_t = 0)]
+    min_cov: usize,
+    /// the minimum branch length in the panel MAP graph to be included in the principal bundles
+    #[clap(long, default_value_t = 8)]
+    min_branch_size: usize,
+    /// the window size for painting
+    #[clap(long, default_value_t = 100000, value_parser = clap::value_parser!(u32).range(1..))]
+    window_size: u32,
+    /// the bundle links found more than this number of times in the panel (e.g., in the repeats) are ignored
+    #[clap(long, default_value_t = 128)]
+    max_count: usize,
+    /// use the "sample#haplotype" prefix of the PanSN contig names (sample#haplotype#contig) as the haplotype names
+    #[clap(long, default_value_t = false)]
+    pansn: bool,
+    /// number of threads used in parallel (more memory usage), default to "0" using all CPUs available or the number set by RAYON_NUM_THREADS
+    #[clap(long, default_value_t = 0)]
+    number_of_thread: usize,
+}
+
+// (bundle id, the direction relative to the bundle, the position in the bundle)
+type BundleVertex = (usize, u32, usize);
+type BundleLink = (BundleVertex, BundleVertex);
+
+// the links between the consecutive principal bundle vertices along a sequence with the positions of the
+// first vertices, the shimmer pairs not in the principal bundles are skipped, a link and its reverse
+// complement are represented by the smaller one so the links match on both strands
+#[allow(clippy::type_complexity)]
+fn get_bundle_links(
+    smps: &[((u64, u64, u32, u32, u8), Option<(usize, u8, usize)>)],
+) -> Vec<(u32, BundleLink)> {
+    let vertices = smps
+        .iter()
+        .filter_map(|&(smp, bundle_info)| {
+            let (bid, direction, bpos) = bundle_info?;
+            let d = if smp.4 == direction { 0_u32 } else { 1_u32 };
+            Some((smp.2, (bid, d, bpos)))
+        })
+        .collect::<Vec<_>>();
+    vertices
+        .windows(2)
+        .map(|w| {
+            let (v0, v1) = (w[0].1, w[1].1);
+            let reverse = ((v1.0, 1 - v1.1, v1.2), (v0.0, 1 - v0.1, v0.2));
+            (w[0].0, (v0, v1).min(reverse))
+        })
+        .collect()
+}
+
+fn main() -> Result<(), std::io::Error> {
+    CmdOptions::command().version(VERSION_STRING).get_matches();
+    let args = CmdOptions::parse();
+
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(args.number_of_thread)
+        .build_global()
+        .unwrap();
+
+    let mut seq_index_db = SeqIndexDB::new();
+    seq_index_db
+        .load_from_fastx(
+            args.panel_fastx_path.clone(),
+            args.w,
+            args.k,
+            args.r,
+            args.min_span,
+            true,
+        )
+        .unwrap_or_else(|_| panic!("can't read file {}", args.panel_fastx_path));
+    let shmmr_spec = seq_index_db.shmmr_spec.clone().unwrap();
+
+    let sid_to_haplotype = seq_index_db
+        .seq_info
+        .as_ref()
+        .unwrap()
+        .iter()
+        .map(|(sid, (ctg, _, _))| {
+            let haplotype = if args.pansn {
+                ctg.split('#').take(2).collect::<Vec<&str>>().join("#")
+            } else {
+                ctg.clone()
+            };
+            (*sid, haplotype)
+        })
+        .collect::<FxHashMap<u32, String>>();
+
+    let mut query_seqs: Vec<SeqRec> = vec![];
+    let mut add_seqs = |seq_iter: &mut dyn Iterator<Item = io::Result<SeqRec>>| {
+        seq_iter.into_iter().for_each(|r| {
+            if let Ok(r) = r {
+                query_seqs.push(r);
+            };
+        });
+    };
+
+    match get_fastx_reader(args.query_fastx_path, true)? {
+        #[allow(clippy::useless_conversion)] // the into_iter() is necessary for dyn patching
+        GZFastaReader::GZFile(reader) => add_seqs(&mut reader.into_iter()),
+
+        #[allow(clippy::useless_conversion)] // the into_iter() is necessary for dyn patching
+        GZFastaReader::RegularFile(reader) => add_seqs(&mut reader.into_iter()),
+    };
+
+    let (_, vertex_to_bundle_id_direction_pos) =
+        seq_index_db.get_principal_bundles_with_id(args.min_cov, args.min_branch_size, None);
+    // the number of the occurrences of each bundle link in the panel and the haplotypes with the link
+    let mut panel_links = FxHashMap::<BundleLink, (usize, FxHashSet<&String>)>::default();
+    get_principal_bundle_decomposition(&vertex_to_bundle_id_direction_pos, &seq_index_db)
+        .iter()
+        .for_each(|(sid, smps)| {
+            let haplotype = sid_to_haplotype.get(sid).unwrap();
+            get_bundle_links(smps).into_iter().for_each(|(_, link)| {
+                let e = panel_links.entry(link).or_default();
+                e.0 += 1;
+                e.1.insert(haplotype);
+            });
+        });
+
+    // for each query contig, a list of (bgn, end, number of bundle links, haplotype counts sorted by the counts)
+    #[allow(clippy::type_complexity)]
+    let paintings: Vec<(String, Vec<(u32, u32, usize, Vec<(usize, String)>)>)> = query_seqs
+        .par_iter()
+        .map(|seq_rec| {
+            let ctg = String::from_utf8_lossy(&seq_rec.id).to_string();
+            let seq_len = seq_rec.seq.len() as u32;
+            let smps = seq_index_db
+                .get_smps(seq_rec.seq.clone(), &shmmr_spec)
+                .into_iter()
+                .map(|v| {
+                    (
+                        v,
+                        vertex_to_bundle_id_direction_pos.get(&(v.0, v.1)).copied(),
+                    )
+                })
+                .collect::<Vec<_>>();
+            let n_window = seq_len.div_ceil(args.window_size);
+            let mut window_links = vec![vec![]; n_window as usize];
+            get_bundle_links(&smps).into_iter().for_each(|(p0, link)| {
+                window_links[((p0 - 1) / args.window_size) as usize].push(link);
+            });
+            let windows = window_links
+                .into_iter()
+                .enumerate()
+                .map(|(idx, links)| {
+                    let bgn = idx as u32 * args.window_size;
+                    let end = if bgn + args.window_size > seq_len {
+                        seq_len
+                    } else {
+                        bgn + args.window_size
+                    };
+                    let mut haplotype_count = FxHashMap::<&String, usize>::default();
+                    links.iter().for_each(|link| {
+                        if let Some((count, haplotypes)) = panel_links.get(link) {
+                            if *count > args.max_count {
+                                return;
+                            }
+                            haplotypes
+                                .iter()
+                                .for_each(|h| *haplotype_count.entry(*h).or_insert(0) += 1);
+                        }
+                    });
+                    let mut haplotype_count = haplotype_count
+                        .into_iter()
+                        .map(|(h, c)| (c, h.clone()))
+                        .collect::<Vec<(usize, String)>>();
+                    haplotype_count.sort_by(|a, b| b.cmp(a));
+                    (bgn, end, links.len(), haplotype_count)
+                })
+                .collect::<Vec<_>>();
+            (ctg, windows)
+        })
+        .collect();
+
+    let output_prefix_path = Path::new(&args.output_prefix);
+    let mut window_bed_file = BufWriter::new(File::create(
+        output_prefix_path.with_extension("paint.windows.bed"),
+    )?);
+    writeln!(
+        window_bed_file,
+        "#{}",
+        [
+            "ctg",
+            "bgn",
+            "end",
+            "best_haplotype",
+            "best_count",
+            "second_haplotype",
+            "second_count",
+            "n_bundle_links"
+        ]
+        .join("\t")
+    )?;
+    let mut paint_bed_file = BufWriter::new(File::create(
+        output_prefix_path.with_extension("paint.bed"),
+    )?);
+    writeln!(
+        paint_bed_file,
+        "#{}",
+        ["ctg", "bgn", "end", "haplotype", "n_windows"].join("\t")
+    )?;
+
+    paintings
+        .into_iter()
+        .try_for_each(|(ctg, windows)| -> Result<(), std::io::Error> {
+            // consecutive windows painted with the same haplotype are merged, (bgn, end, haplotype, n_windows)
+            let mut segment: Option<(u32, u32, String, usize)> = None;
+            windows
+                .into_iter()
+                .try_for_each(|(bgn, end, n_links, haplotype_count)| {
+                    let (best_count, best) = haplotype_count
+                        .first()
+                        .cloned()
+                        .unwrap_or((0, ".".to_string()));
+                    let (second_count, second) = haplotype_count
+                        .get(1)
+                        .cloned()
+                        .unwrap_or((0, ".".to_string()));
+                    // ambiguous windows are not painted
+                    let best = if best_count == 0 || best_count == second_count {
+                        ".".to_string()
+                    } else {
+                        best
+                    };
+                    writeln!(
+                        window_bed_file,
+                        "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                        ctg, bgn, end, best, best_count, second, second_count, n_links
+                    )?;
+                    segment = match segment.take() {
+                        Some(s) if s.2 == best => Some((s.0, end, best, s.3 + 1)),
+                        Some(s) => {
+                            writeln!(
+                                paint_bed_file,
+                                "{}\t{}\t{}\t{}\t{}",
+                                ctg, s.0, s.1, s.2, s.3
+                            )?;
+                            Some((bgn, end, best, 1))
+                        }
+                        None => Some((bgn, end, best, 1)),
+                    };
+                    Ok::<(), std::io::Error>(())
+                })?;
+            if let Some(s) = segment {
+                writeln!(
+                    paint_bed_file,
+                    "{}\t{}\t{}\t{}\t{}",
+                    ctg, s.0, s.1, s.2, s.3
+                )?;
+            }
+            Ok(())
+        })?;
+
+    Ok(())
+}
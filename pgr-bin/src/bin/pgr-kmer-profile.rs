@@ -0,0 +1,193 @@
+const VERSION_STRING: &str = env!("VERSION_STRING");
+use clap::{self, CommandFactory, Parser};
+use pgr_db::ext::{get_fastx_reader, GZFastaReader, SeqIndexDB};
+use pgr_db::fasta_io::SeqRec;
+use rayon::prelude::*;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Profile the shimmer pair multiplicity along the input sequences against a PGR-TK database,
+/// output a bedgraph file and a bed file of the regions with high shimmer pair counts
+#[derive(Parser, Debug)]
+#[clap(name = "pgr-kmer-profile")]
+#[clap(author, version)]
+#[clap(about, long_about = None)]
+struct CmdOptions {
+    /// the prefix to a PGR-TK sequence database
+    pgr_db_prefix: String,
+    /// the path to the fasta file of the sequences to profile
+    query_fastx_path: String,
+    /// the prefix of the output files
+    output_prefix: String,
+
+    /// using the frg format for the sequence database (default to the AGC backend database if not specified)
+    #[clap(long, default_value_t = false)]
+    frg_file: bool,
+
+    /// using a fasta file as the sequence database, the index is built on the fly with the shimmer parameters below
+    #[clap(long, default_value_t = false)]
+    fastx_file: bool,
+
+    /// minimizer window size
+    #[clap(long, short, default_value_t = 80)]
+    w: u32,
+    /// minimizer k-mer size
+    #[clap(long, short, default_value_t = 56)]
+    k: u32,
+    /// sparse minimizer (shimmer) reduction factor
+    #[clap(long, short, default_value_t = 4)]
+    r: u32,
+    /// min span for neighboring minimizers
+    #[clap(long, short, default_value_t = 64)]
+    min_span: u32,
+
+    /// the fragment count of a shimmer pair above which the interval is flagged as a high count region
+    #[clap(long, short, default_value_t = 128)]
+    count_threshold: usize,
+
+    /// merge flagged intervals within the specified distance
+    #[clap(long, default_value_t = 0)]
+    merge_range_tol: u32,
+
+    /// number of threads used in parallel (more memory usage), default to "0" using all CPUs available or the number set by RAYON_NUM_THREADS
+    #[clap(long, default_value_t = 0)]
+    number_of_thread: usize,
+}
+
+fn main() -> Result<(), std::io::Error> {
+    CmdOptions::command().version(VERSION_STRING).get_matches();
+    let args = CmdOptions::parse();
+
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(args.number_of_thread)
+        .build_global()
+        .unwrap();
+
+    let mut query_seqs: Vec<SeqRec> = vec![];
+    let mut add_seqs = |seq_iter: &mut dyn Iterator<Item = io::Result<SeqRec>>| {
+        seq_iter.into_iter().for_each(|r| {
+            if let Ok(r) = r {
+                query_seqs.push(r);
+            };
+        });
+    };
+
+    match get_fastx_reader(args.query_fastx_path, true)? {
+        #[allow(clippy::useless_conversion)] // the into_iter() is necessary for dyn patching
+        GZFastaReader::GZFile(reader) => add_seqs(&mut reader.into_iter()),
+
+        #[allow(clippy::useless_conversion)] // the into_iter() is necessary for dyn patching
+        GZFastaReader::RegularFile(reader) => add_seqs(&mut reader.into_iter()),
+    };
+
+    let mut seq_index_db = SeqIndexDB::new();
+    if args.frg_file {
+        let stderr = io::stderr();
+        let mut handle = stderr.lock();
+        let _ = handle.write_all(b"the option `--frg_file` is specified, read the input file as a FRG backed index database files.\n");
+        let _ = seq_index_db.load_from_frg_index(args.pgr_db_prefix);
+    } else if args.fastx_file {
+        let stderr = io::stderr();
+        let mut handle = stderr.lock();
+        let _ = handle.write_all(
+            b"the option `--fastx_file` is specified, read the input file as a fastx file.\n",
+        );
+        let _ = seq_index_db.load_from_fastx(
+            args.pgr_db_prefix,
+            args.w,
+            args.k,
+            args.r,
+            args.min_span,
+            true,
+        );
+    } else {
+        #[cfg(feature = "with_agc")]
+        {
+            let stderr = io::stderr();
+            let mut handle = stderr.lock();
+            let _ = handle.write_all(b"Read the input as a AGC backed index database files.\n");
+            let _ = seq_index_db.load_from_agc_index(args.pgr_db_prefix);
+        }
+
+        #[cfg(not(feature = "with_agc"))]
+        panic!("This command is compiled with only frg file support, please specify `--frg-file");
+    }
+
+    let shmmr_spec = seq_index_db.shmmr_spec.clone().unwrap();
+    let prefix = Path::new(&args.output_prefix);
+
+    // for each query sequence, the list of (bgn, end, count) of the consecutive shimmer pairs
+    let profiles = query_seqs
+        .par_iter()
+        .map(|seq_rec| {
+            let mut smps = seq_index_db.get_smps(seq_rec.seq.clone(), &shmmr_spec);
+            smps.sort_by_key(|v| (v.2, v.3));
+            let profile = smps
+                .into_iter()
+                .map(|(s0, s1, p0, p1, _)| {
+                    let count = seq_index_db.get_shmmr_pair_count((s0, s1));
+                    (p0, p1, count)
+                })
+                .collect::<Vec<(u32, u32, usize)>>();
+            (String::from_utf8_lossy(&seq_rec.id).to_string(), profile)
+        })
+        .collect::<Vec<_>>();
+
+    let mut bedgraph_file =
+        BufWriter::new(File::create(prefix.with_extension("bedgraph")).unwrap());
+    writeln!(
+        bedgraph_file,
+        "track type=bedGraph name=\"shimmer pair count\""
+    )
+    .expect("writing bedgraph fail\n");
+
+    let mut bed_file =
+        BufWriter::new(File::create(prefix.with_extension("high_count.bed")).unwrap());
+    writeln!(
+        bed_file,
+        "#{}",
+        ["ctg", "bgn", "end", "max_count", "n_shmmr_pairs"].join("\t")
+    )
+    .expect("writing bed file fail\n");
+
+    profiles.into_iter().for_each(|(ctg, profile)| {
+        // (bgn, end, max_count, number of shimmer pairs)
+        let mut flagged_rgn: Option<(u32, u32, usize, usize)> = None;
+        profile.into_iter().for_each(|(bgn, end, count)| {
+            writeln!(bedgraph_file, "{}\t{}\t{}\t{}", ctg, bgn, end, count)
+                .expect("writing bedgraph fail\n");
+            if count <= args.count_threshold {
+                return;
+            }
+            flagged_rgn = match flagged_rgn {
+                Some(rgn) if bgn <= rgn.1 + args.merge_range_tol => Some((
+                    rgn.0,
+                    if end > rgn.1 { end } else { rgn.1 },
+                    if count > rgn.2 { count } else { rgn.2 },
+                    rgn.3 + 1,
+                )),
+                Some(rgn) => {
+                    writeln!(
+                        bed_file,
+                        "{}\t{}\t{}\t{}\t{}",
+                        ctg, rgn.0, rgn.1, rgn.2, rgn.3
+                    )
+                    .expect("writing bed file fail\n");
+                    Some((bgn, end, count, 1))
+                }
+                None => Some((bgn, end, count, 1)),
+            };
+        });
+        if let Some(rgn) = flagged_rgn {
+            writeln!(
+                bed_file,
+                "{}\t{}\t{}\t{}\t{}",
+                ctg, rgn.0, rgn.1, rgn.2, rgn.3
+            )
+            .expect("writing bed file fail\n");
+        }
+    });
+
+    Ok(())
+}
@@ -0,0 +1,225 @@
+const VERSION_STRING: &str = env!("VERSION_STRING");
+use bincode::config;
+use clap::{self, CommandFactory, Parser};
+use pgr_db::ext::{read_fastx_manifest, SeqIndexDB};
+use pgr_db::fasta_io::IndexedFastaReader;
+use pgr_db::seq_db;
+use pgr_db::shmmrutils::ShmmrSpec;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+#[cfg(feature = "with_agc")]
+use pgr_db::agc_io::AGCFile;
+
+#[cfg(feature = "with_agc")]
+use std::process::Command;
+
+/// Build a PGR-TK database (the AGC or frg sequence archive, the .mdb and .midx index files
+/// and optionally the principal bundle .pdb file) from a manifest of fasta files in one run,
+/// or only the index files for using the indexed fasta files as the sequence store
+#[derive(Parser, Debug)]
+#[clap(name = "pgr-make-db")]
+#[clap(author, version)]
+#[clap(about, long_about = None)]
+struct CmdOptions {
+    /// the path to the manifest file, each line contains a sample name and the path to a fasta file separated by a tab
+    manifest: String,
+    /// the prefix of the output database files
+    prefix: String,
+    /// minimizer window size
+    #[clap(long, short, default_value_t = 80)]
+    w: u32,
+    /// minimizer k-mer size
+    #[clap(long, short, default_value_t = 56)]
+    k: u32,
+    /// sparse minimizer (shimmer) reduction factor
+    #[clap(long, short, default_value_t = 4)]
+    r: u32,
+    /// min span for neighboring minimiers
+    #[clap(long, short, default_value_t = 64)]
+    min_span: u32,
+    /// using the frg format for the sequence archive (default to the AGC backend)
+    #[clap(long, default_value_t = false)]
+    frg_file: bool,
+    /// use the fasta files (plain or bgzipped) with the .fai indexes as the sequence store rather than creating
+    /// an archive, the paths of the fasta files are written to <PREFIX>.fasta_manifest
+    #[clap(long, default_value_t = false, conflicts_with = "frg_file")]
+    indexed_fasta: bool,
+    /// the path to the agc executable used for creating the AGC archive
+    #[clap(long, default_value = "agc")]
+    agc_bin: String,
+    /// number of parallel agc reader threads (more memory usage)
+    #[clap(long, default_value_t = 4)]
+    number_of_readers: usize,
+    /// generate the principal bundle file <PREFIX>.pdb, all sequences are loaded into memory for this
+    #[clap(long, default_value_t = false)]
+    pdb: bool,
+    /// vertex minimum coverage in MAP-graph to be included in principal bundles
+    #[clap(long, default_value_t = 0)]
+    min_cov: usize,
+    /// the minimum branch length in MAP-graph to be included in the principal bundles
+    #[clap(long, default_value_t = 8)]
+    min_branch_size: usize,
+    /// number of threads used in parallel (more memory usage), default to "0" using all CPUs available or the number set by RAYON_NUM_THREADS
+    #[clap(long, default_value_t = 0)]
+    number_of_thread: usize,
+}
+
+/// create the AGC archive with the external agc tool, the fasta files are linked with the sample names as
+/// the file names so the AGC sample names match the ones in the manifest
+#[cfg(feature = "with_agc")]
+fn create_agc_archive(
+    agc_bin: &str,
+    fastx_files: &[(String, String)],
+    prefix: &str,
+    number_of_thread: usize,
+) -> Result<(), std::io::Error> {
+    let input_dir = prefix.to_string() + ".agc_input";
+    std::fs::create_dir_all(&input_dir)?;
+    let links = fastx_files
+        .iter()
+        .map(|(sample, filepath)| -> Result<String, std::io::Error> {
+            let ext = if filepath.ends_with(".gz") {
+                "fa.gz"
+            } else {
+                "fa"
+            };
+            let link = format!("{}/{}.{}", input_dir, sample, ext);
+            std::os::unix::fs::symlink(std::fs::canonicalize(filepath)?, &link)?;
+            Ok(link)
+        })
+        .collect::<Result<Vec<String>, std::io::Error>>()?;
+
+    let mut cmd = Command::new(agc_bin);
+    cmd.arg("create").arg("-o").arg(prefix.to_string() + ".agc");
+    if number_of_thread > 0 {
+        cmd.arg("-t").arg(format!("{}", number_of_thread));
+    }
+    let status = cmd.args(&links).status()?;
+    std::fs::remove_dir_all(&input_dir)?;
+    if !status.success() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!("fail to create the AGC archive with `{}`", agc_bin),
+        ));
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), std::io::Error> {
+    CmdOptions::command().version(VERSION_STRING).get_matches();
+    let args = CmdOptions::parse();
+
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(args.number_of_thread)
+        .build_global()
+        .unwrap();
+
+    // (sample name, fasta file path)
+    let fastx_files = read_fastx_manifest(&args.manifest)?;
+
+    let stderr = io::stderr();
+    let mut handle = stderr.lock();
+
+    let mut seq_index_db = SeqIndexDB::new();
+    if args.frg_file {
+        let _ = handle.write_all(b"build the frg archive and the index files\n");
+        seq_index_db.load_from_fastx_with_sources(
+            fastx_files.clone(),
+            args.w,
+            args.k,
+            args.r,
+            args.min_span,
+            true,
+        )?;
+        seq_index_db.write_frag_and_index_files(args.prefix.clone());
+    } else if args.indexed_fasta {
+        let _ = handle.write_all(b"build the index files and the fasta manifest\n");
+        let mut fasta_manifest =
+            BufWriter::new(File::create(args.prefix.clone() + ".fasta_manifest")?);
+        fastx_files
+            .iter()
+            .try_for_each(|(sample, filepath)| -> Result<(), std::io::Error> {
+                // the .fai (and .gzi) indexes are built here, so the files not supported are rejected early
+                let _ = IndexedFastaReader::new(filepath)?;
+                let filepath = std::fs::canonicalize(filepath)?;
+                writeln!(fasta_manifest, "{}\t{}", sample, filepath.to_string_lossy())?;
+                Ok(())
+            })?;
+        fasta_manifest.flush()?;
+
+        let shmmr_spec = ShmmrSpec::new(args.w, args.k, args.r, args.min_span, false);
+        let mut sdb = seq_db::CompactSeqDB::new(shmmr_spec);
+        fastx_files.iter().try_for_each(|(sample, filepath)| {
+            sdb.load_index_from_fastx_with_source(filepath.clone(), sample.clone(), true)
+        })?;
+        sdb.write_shmmr_map_index(args.prefix.clone())?;
+    } else {
+        #[cfg(feature = "with_agc")]
+        {
+            let _ = handle.write_all(b"build the AGC archive\n");
+            create_agc_archive(
+                &args.agc_bin,
+                &fastx_files[..],
+                &args.prefix,
+                args.number_of_thread,
+            )?;
+
+            let _ = handle.write_all(b"build the index files from the AGC archive\n");
+            let shmmr_spec = ShmmrSpec::new(args.w, args.k, args.r, args.min_span, false);
+            let mut sdb = seq_db::CompactSeqDB::new(shmmr_spec);
+            let mut agcfile = AGCFile::new(args.prefix.clone() + ".agc")?;
+            agcfile.set_iter_thread(args.number_of_readers);
+            sdb.load_index_from_agcfile(agcfile)?;
+            sdb.write_shmmr_map_index(args.prefix.clone())?;
+        }
+
+        #[cfg(not(feature = "with_agc"))]
+        panic!("This command is compiled with only frg file support, please specify `--frg-file");
+    }
+
+    if args.pdb {
+        let _ = handle.write_all(b"build the principal bundles\n");
+        if !args.frg_file {
+            seq_index_db.load_from_fastx_with_sources(
+                fastx_files,
+                args.w,
+                args.k,
+                args.r,
+                args.min_span,
+                true,
+            )?;
+        }
+        let (principal_bundles_with_id, vertex_to_bundle_id_direction_pos) =
+            seq_index_db.get_principal_bundles_with_id(args.min_cov, args.min_branch_size, None);
+
+        let mut pdb_output_file = BufWriter::new(
+            File::create(Path::new(&(args.prefix.clone() + ".pdb")))
+                .expect("pdb file creating error"),
+        );
+        pdb_output_file
+            .write_all("PDB:0.5".as_bytes())
+            .expect("pdb file writing error");
+        let config = config::standard();
+        let bincode_vec = bincode::encode_to_vec(
+            (
+                args.w,
+                args.k,
+                args.r,
+                args.min_span,
+                args.min_branch_size,
+                args.min_cov,
+                principal_bundles_with_id,
+                vertex_to_bundle_id_direction_pos,
+            ),
+            config,
+        )
+        .unwrap();
+        pdb_output_file
+            .write_all(&bincode_vec[..])
+            .expect("pdb file writing error");
+    }
+
+    Ok(())
+}
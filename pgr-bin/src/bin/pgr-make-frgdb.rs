@@ -37,13 +37,8 @@ fn main() {
     // TODO: to log file
     //println!("read data from files in {:?}", args.filepath);
     //println!("output prefix {:?}", args.prefix);
-    let _shmmr_spec = pgr_db::shmmrutils::ShmmrSpec {
-        w: args.w,
-        k: args.k,
-        r: args.r,
-        min_span: args.min_span,
-        sketch: false,
-    };
+    let _shmmr_spec =
+        pgr_db::shmmrutils::ShmmrSpec::new(args.w, args.k, args.r, args.min_span, false);
     let mut sdb = SeqIndexDB::new();
     let input_files = BufReader::new(
         File::open(Path::new(&args.filepath))
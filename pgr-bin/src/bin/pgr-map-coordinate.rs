@@ -1,7 +1,7 @@
 const VERSION_STRING: &str = env!("VERSION_STRING");
 use clap::{self, CommandFactory, Parser};
 use iset::IntervalMap;
-use pgr_db::aln::{wfa_align_bases, aln_pair_map};
+use pgr_db::aln::{wfa_align_bases, aln_pair_map, AlnParams};
 // use rayon::prelude::*;
 use pgr_db::ext::{get_fastx_reader, GZFastaReader};
 use pgr_db::fasta_io::{reverse_complement, SeqRec};
@@ -183,8 +183,12 @@ fn main() -> Result<(), std::io::Error> {
                 };
                 let t_str = String::from_utf8_lossy(&t_sub_seq[..]);
                 let q_str = String::from_utf8_lossy(&q_sub_seq[..]);
+                let aln_params = AlnParams {
+                    max_wf_length: Some(384),
+                    ..Default::default()
+                };
                 if let Some((aln_target_str, aln_query_str)) =
-                    wfa_align_bases(&t_str, &q_str, 384, 4, 4, 1)
+                    wfa_align_bases(&t_str, &q_str, &aln_params)
                 {
                     let mut q_pos_to_t_pos_map = FxHashMap::<u32, u32>::default();
                     aln_pair_map(&aln_target_str, &aln_query_str)
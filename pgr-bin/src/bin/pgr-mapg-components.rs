@@ -0,0 +1,178 @@
+const VERSION_STRING: &str = env!("VERSION_STRING");
+use clap::{self, CommandFactory, Parser};
+use pgr_db::ext::SeqIndexDB;
+use pgr_db::graph_utils::GraphSimplification;
+use rustc_hash::FxHashMap;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Split the MAP graph of the input sequences into the connected components, report the size and the sample
+/// composition of each component and get the principal bundles of the components in parallel, the cycles (e.g.,
+/// the VNTR-like loops) in the graph are reported with the copy numbers of the repeat units in the sequences
+#[derive(Parser, Debug)]
+#[clap(name = "pgr-mapg-components")]
+#[clap(author, version)]
+#[clap(about, long_about = None)]
+struct CmdOptions {
+    /// the prefix of the output files
+    output_prefix: String,
+    /// the paths to the input fasta files, or the patterns of the file names with the "*" and "?" wildcards,
+    /// the sequences are labeled by the sample names from the file names
+    #[clap(required = true)]
+    fastx_paths: Vec<String>,
+    /// the SHIMMER parameter w
+    #[clap(short, default_value_t = 48)]
+    w: u32,
+    /// the SHIMMER parameter k
+    #[clap(short, default_value_t = 56)]
+    k: u32,
+    /// the SHIMMER parameter r
+    #[clap(short, default_value_t = 4)]
+    r: u32,
+    /// the SHIMMER parameter minimum span length
+    #[clap(long, default_value_t = 12)]
+    min_span: u32,
+    /// vertex minimum coverage in MAP-graph to be included in the components
+    #[clap(long, default_value_t = 0)]
+    min_cov: usize,
+    /// the minimum branch length in MAP-graph to be included in the principal bundles
+    #[clap(long, default_value_t = 8)]
+    min_branch_size: usize,
+    /// clip the tips (dead-end branches) of up to this number of vertices in MAP-graph before splitting the graph, "0" for not clipping the tips
+    #[clap(long, default_value_t = 0)]
+    max_tip_len: usize,
+    /// only clip the tips walked by at most this number of sequences
+    #[clap(long, default_value_t = 1)]
+    max_tip_coverage: usize,
+    /// pop the bubbles of up to this number of vertices in MAP-graph before splitting the graph, "0" for not popping the bubbles
+    #[clap(long, default_value_t = 0)]
+    max_bubble_size: usize,
+    /// report the cycles of up to this number of vertices in MAP-graph to <OUTPUT_PREFIX>.cycles.tsv, "0" for not reporting the cycles
+    #[clap(long, default_value_t = 64)]
+    max_cycle_size: usize,
+    /// number of threads used in parallel (more memory usage), default to "0" using all CPUs available or the number set by RAYON_NUM_THREADS
+    #[clap(long, default_value_t = 0)]
+    number_of_thread: usize,
+}
+
+fn main() -> Result<(), std::io::Error> {
+    CmdOptions::command().version(VERSION_STRING).get_matches();
+    let args = CmdOptions::parse();
+    let cmd_string = std::env::args().collect::<Vec<String>>().join(" ");
+
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(args.number_of_thread)
+        .build_global()
+        .unwrap();
+
+    let mut seq_index_db = SeqIndexDB::new();
+    seq_index_db.load_from_fastx_files(
+        &args.fastx_paths,
+        args.w,
+        args.k,
+        args.r,
+        args.min_span,
+        true,
+    )?;
+    if args.max_tip_len > 0 || args.max_bubble_size > 0 {
+        seq_index_db.graph_simplification = Some(GraphSimplification {
+            max_tip_len: args.max_tip_len,
+            max_tip_coverage: args.max_tip_coverage,
+            max_bubble_size: args.max_bubble_size,
+        });
+    }
+
+    let components = seq_index_db.get_mapg_components(args.min_cov, None);
+    let component_bundles =
+        seq_index_db.get_principal_bundles_by_component(args.min_cov, args.min_branch_size, None);
+    let seq_info = seq_index_db.seq_info.clone().unwrap_or_default();
+
+    let output_prefix_path = Path::new(&args.output_prefix);
+    let mut component_file = BufWriter::new(
+        File::create(output_prefix_path.with_extension("components.tsv"))
+            .expect("can't create the component file"),
+    );
+    writeln!(component_file, "# cmd: {}", cmd_string)?;
+    writeln!(
+        component_file,
+        "#component_id\tn_vertices\tn_edges\tn_seqs\tn_bundles\tn_bundle_vertices\tsamples"
+    )?;
+    let mut seq_components = FxHashMap::<u32, Vec<usize>>::default();
+    for (cid, ((n_vertices, n_edges, sids), bundles)) in
+        components.iter().zip(component_bundles.iter()).enumerate()
+    {
+        // the number of the sequences from each sample in the component
+        let mut samples = BTreeMap::<String, usize>::new();
+        sids.iter().for_each(|sid| {
+            let source = seq_info
+                .get(sid)
+                .and_then(|(_, source, _)| source.clone())
+                .unwrap_or_else(|| "-".to_string());
+            *samples.entry(source).or_default() += 1;
+            seq_components.entry(*sid).or_default().push(cid);
+        });
+        let samples = samples
+            .iter()
+            .map(|(source, count)| format!("{}:{}", source, count))
+            .collect::<Vec<_>>()
+            .join(",");
+        writeln!(
+            component_file,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            cid,
+            n_vertices,
+            n_edges,
+            sids.len(),
+            bundles.len(),
+            bundles.iter().map(|b| b.len()).sum::<usize>(),
+            samples
+        )?;
+    }
+
+    let mut seq_component_file = BufWriter::new(
+        File::create(output_prefix_path.with_extension("seq_components.tsv"))
+            .expect("can't create the sequence component file"),
+    );
+    writeln!(seq_component_file, "# cmd: {}", cmd_string)?;
+    writeln!(
+        seq_component_file,
+        "#sid\tsample\tctg\tctg_len\tcomponent_ids"
+    )?;
+    let mut sids = seq_info.keys().copied().collect::<Vec<_>>();
+    sids.sort();
+    for sid in sids {
+        let (ctg, source, ctg_len) = seq_info.get(&sid).unwrap();
+        let component_ids = match seq_components.get(&sid) {
+            Some(cids) => cids
+                .iter()
+                .map(|cid| format!("{}", cid))
+                .collect::<Vec<_>>()
+                .join(","),
+            None => "-".to_string(),
+        };
+        writeln!(
+            seq_component_file,
+            "{}\t{}\t{}\t{}\t{}",
+            sid,
+            source.as_deref().unwrap_or("-"),
+            ctg,
+            ctg_len,
+            component_ids
+        )?;
+    }
+
+    if args.max_cycle_size > 0 {
+        seq_index_db.write_mapg_cycles_tsv(
+            args.min_cov,
+            None,
+            args.max_cycle_size,
+            output_prefix_path
+                .with_extension("cycles.tsv")
+                .to_str()
+                .unwrap(),
+        )?;
+    }
+    Ok(())
+}
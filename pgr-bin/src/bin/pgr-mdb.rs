@@ -7,29 +7,41 @@ use clap::{self, CommandFactory, Parser};
 use pgr_db::agc_io::AGCFile;
 
 #[cfg(feature = "with_agc")]
-use pgr_db::shmmrutils::ShmmrSpec;
+use pgr_db::shmmrutils::{seed_mask_from_str, tie_break_from_str, ShmmrSpec};
 
 #[cfg(feature = "with_agc")]
 use std::fs::File;
 
 #[cfg(feature = "with_agc")]
-use std::io::{BufRead, BufReader};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+
+#[cfg(feature = "with_agc")]
+use rustc_hash::{FxHashMap, FxHashSet};
+
+#[cfg(feature = "with_agc")]
+use std::time::Instant;
 
 #[cfg(feature = "with_agc")]
 use pgr_db::seq_db;
 
+#[cfg(feature = "with_agc")]
+use rayon::prelude::*;
+
 /// Create pgr minimizer database with AGC backend
 #[derive(Parser, Debug)]
 #[clap(name = "pgr-mdb")]
 #[clap(author, version)]
 #[clap(about, long_about = None)]
 struct CmdOptions {
+    /// an AGC file, or a file listing the AGC files (one per line) or a manifest of the samples to index with
+    /// three tab-separated columns: the AGC file path, the sample name in the AGC file and the haplotype
     filepath: String,
+    /// the prefix of the output index files
     prefix: String,
     /// minimizer window size
     #[clap(long, short, default_value_t = 80)]
     w: u32,
-    /// minimizer k-mer size
+    /// minimizer k-mer size, up to 256 (the k-mers longer than 56 are only hashed, no spaced seeds)
     #[clap(long, short, default_value_t = 56)]
     k: u32,
     /// sparse minimizer (shimmer) reduction factor
@@ -41,43 +53,375 @@ struct CmdOptions {
     /// using sketch k-mer than minimizer
     #[clap(short, long)]
     sketch: bool,
+    /// using order-2 randstrobes linking each shimmer to a neighboring one as the anchors,
+    /// more robust than the shimmer pairs for the indel-rich sequences
+    #[clap(long)]
+    randstrobe: bool,
+    /// spaced seed pattern for the k-mers, e.g., "11011...11011" with the length of k, "0" for the don't-care positions
+    /// (e.g., for the SNP-dense regions), the pattern should be palindromic
+    #[clap(long)]
+    seed_mask: Option<String>,
+    /// the seed of the k-mer hashes (recorded in the .mdb header), for building the decorrelated replicate sketches
+    /// or matching the seed of another index, default to the built-in seed
+    #[clap(long)]
+    hash_seed: Option<u64>,
+    /// the policy selecting the minimizers with the same hash in a window: "all", "lexicographic", "position"
+    /// or "random", keeping all of them gives the same anchors from both strands in the low-complexity sequences
+    #[clap(long, default_value = "all")]
+    tie_break: String,
+    /// the seed of the "random" tie-breaking policy
+    #[clap(long, default_value_t = 0)]
+    tie_break_seed: u64,
     /// set to use agc prefecting feature (more memory usage but faster, useful for agcfile with many small contigs)
     #[clap(short, long)]
     prefetching: bool,
     /// number of parallel agc reader threads (more memory usage)
     #[clap(long, short, default_value_t = 4)]
     number_of_readers: usize,
+    /// number of the decoded sequence chunks (up to 129 sequences each) buffered between the agc readers and the indexing
+    #[clap(long, default_value_t = 4)]
+    channel_size: usize,
+    /// number of threads used in parallel for the indexing (more memory usage), default to "0" using all CPUs available or the number set by RAYON_NUM_THREADS
+    #[clap(long, default_value_t = 0)]
+    number_of_thread: usize,
+    /// drop the shimmer pairs with more than this number of fragments (e.g., from the centromeric satellites) from the index,
+    /// the dropped shimmer pairs are written to <PREFIX>.dropped.tsv, "0" for no cap
+    #[clap(long, default_value_t = 0)]
+    max_frag_count: usize,
+    /// a BED file of the regions (e.g., the known rDNA arrays) skipped in the indexing, the sequence names
+    /// should match the contig names in the AGC files
+    #[clap(long)]
+    mask_bed: Option<String>,
+    /// report the shimmer statistics for a grid of the (w, k, r) values to <PREFIX>.sweep.tsv without building the index
+    #[clap(long, default_value_t = false)]
+    sweep: bool,
+    /// the comma-separated minimizer window sizes of the sweep
+    #[clap(long, value_delimiter = ',', default_values_t = [48, 64, 80])]
+    sweep_w: Vec<u32>,
+    /// the comma-separated minimizer k-mer sizes of the sweep
+    #[clap(long, value_delimiter = ',', default_values_t = [32, 56])]
+    sweep_k: Vec<u32>,
+    /// the comma-separated shimmer reduction factors of the sweep
+    #[clap(long, value_delimiter = ',', default_values_t = [2, 4, 8])]
+    sweep_r: Vec<u32>,
+    /// the number of bases sampled from the AGC files for the sweep, "0" to use all sequences
+    #[clap(long, default_value_t = 100_000_000)]
+    sweep_max_bases: usize,
 }
 
+// the AGC files and the (sample, haplotype) to index from each file, all samples are indexed if none is listed
+#[cfg(feature = "with_agc")]
+type AGCManifest = Vec<(String, Vec<(String, Option<String>)>)>;
+
 #[cfg(feature = "with_agc")]
+fn read_agc_manifest(path: &str) -> Result<AGCManifest, std::io::Error> {
+    if path.ends_with(".agc") {
+        return Ok(vec![(path.to_string(), vec![])]);
+    }
+    let mut manifest = AGCManifest::new();
+    BufReader::new(File::open(path)?).lines().try_for_each(
+        |line| -> Result<(), std::io::Error> {
+            let line = line?;
+            if line.trim().is_empty() || line.starts_with('#') {
+                return Ok(());
+            }
+            let fields = line.trim().split('\t').collect::<Vec<&str>>();
+            let agc_path = fields[0].to_string();
+            if manifest.last().map(|(p, _)| p) != Some(&agc_path) {
+                manifest.push((agc_path, vec![]));
+            }
+            if fields.len() > 1 {
+                let haplotype = fields.get(2).map(|h| h.to_string());
+                manifest
+                    .last_mut()
+                    .unwrap()
+                    .1
+                    .push((fields[1].to_string(), haplotype));
+            }
+            Ok(())
+        },
+    )?;
+    Ok(manifest)
+}
+
+#[cfg(feature = "with_agc")]
+#[allow(clippy::too_many_arguments)]
 fn load_write_index_from_agcfile(
     path: String,
     prefix: String,
     shmmr_spec: &ShmmrSpec,
     prefetching: bool,
     number_of_readers: usize,
+    channel_size: usize,
+    max_frag_count: usize,
+    masked_intervals: FxHashMap<String, Vec<(u32, u32)>>,
 ) -> Result<(), std::io::Error> {
     let mut sdb = seq_db::CompactSeqDB::new(shmmr_spec.clone());
-    let filelist = File::open(path)?;
-
-    BufReader::new(filelist)
-        .lines()
-        .try_for_each(|fp| -> Result<(), std::io::Error> {
-            let fp = fp.unwrap();
-            //println!("load file {}", fp);
-            let mut agcfile: AGCFile = AGCFile::new(fp)?;
+    sdb.set_masked_intervals(masked_intervals);
+    let manifest = read_agc_manifest(&path)?;
+    let stderr = io::stderr();
+    // (source, AGC file, haplotype) of the indexed samples
+    let mut sources = Vec::<(String, String, Option<String>)>::new();
+    let mut source_names = FxHashSet::<String>::default();
+    // the shimmer pairs over the fragment count cap and their fragment counts
+    let mut dropped_shmmr_pairs = FxHashMap::<seq_db::ShmmrPair, usize>::default();
+
+    manifest
+        .into_iter()
+        .try_for_each(|(fp, listed_samples)| -> Result<(), std::io::Error> {
+            let mut agcfile: AGCFile = AGCFile::new(fp.clone())?;
             agcfile.set_iter_thread(number_of_readers);
             agcfile.set_prefetching(prefetching);
-            //println!("start to load index");
-            let _ = sdb.load_index_from_agcfile(agcfile);
+
+            let listed_samples = if listed_samples.is_empty() {
+                agcfile
+                    .samples
+                    .iter()
+                    .map(|sample| (sample.name.clone(), None))
+                    .collect::<Vec<_>>()
+            } else {
+                listed_samples
+            };
+            let samples = listed_samples
+                .iter()
+                .map(|(sample, _)| sample.clone())
+                .collect::<FxHashSet<String>>();
+            // the source names are the AGC sample names, they need to be unique in the unified index
+            listed_samples.into_iter().try_for_each(
+                |(sample, haplotype)| -> Result<(), std::io::Error> {
+                    if !agcfile.samples.iter().any(|s| s.name == sample) {
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::NotFound,
+                            format!("sample {} is not in {}", sample, fp),
+                        ));
+                    }
+                    if !source_names.insert(sample.clone()) {
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::InvalidInput,
+                            format!("sample {} is listed more than once", sample),
+                        ));
+                    }
+                    sources.push((sample, fp.clone(), haplotype));
+                    Ok(())
+                },
+            )?;
+
+            let n_samples = samples.len();
+            let total_bases = agcfile
+                .ctg_lens
+                .iter()
+                .filter(|((sample, _), _)| samples.contains(sample))
+                .map(|(_, len)| *len)
+                .sum::<usize>();
+            let _ = writeln!(
+                stderr.lock(),
+                "index {} ({} samples, {} bp)",
+                fp,
+                n_samples,
+                total_bases
+            );
+            let start_time = Instant::now();
+            let mut indexed_bases = 0_usize;
+            let mut indexed_samples = 0_usize;
+            let mut current_sample: Option<String> = None;
+            // report a sample when the sequences of the next sample arrive, the contigs of a sample are decoded together
+            let report_sample = |sample: &str, indexed_bases: usize, indexed_samples: usize| {
+                let elapsed = start_time.elapsed().as_secs_f64();
+                let eta = if indexed_bases > 0 {
+                    elapsed * (total_bases.saturating_sub(indexed_bases)) as f64
+                        / indexed_bases as f64
+                } else {
+                    0.0
+                };
+                let _ = writeln!(
+                    stderr.lock(),
+                    "[{}/{}] sample {} indexed, {:.1}% of the bases, elapsed {:.0}s, ETA {:.0}s",
+                    indexed_samples,
+                    n_samples,
+                    sample,
+                    100.0 * indexed_bases as f64 / total_bases.max(1) as f64,
+                    elapsed,
+                    eta
+                );
+            };
+            sdb.load_index_from_agcfile_pipelined(
+                &agcfile,
+                Some(&samples),
+                channel_size,
+                if max_frag_count > 0 {
+                    Some((max_frag_count, &mut dropped_shmmr_pairs))
+                } else {
+                    None
+                },
+                |seqs| {
+                    seqs.iter().for_each(|(_sid, source, _name, seq)| {
+                        if current_sample.is_some() && current_sample != *source {
+                            indexed_samples += 1;
+                            report_sample(
+                                current_sample.as_deref().unwrap(),
+                                indexed_bases,
+                                indexed_samples,
+                            );
+                        }
+                        current_sample.clone_from(source);
+                        indexed_bases += seq.len();
+                    });
+                },
+            )?;
+            if let Some(sample) = current_sample.as_deref() {
+                report_sample(sample, indexed_bases, indexed_samples + 1);
+            }
             Ok(())
         })?;
 
     //seq_db::write_shmr_map_file(&sdb.frag_map, "test.db".to_string());
+    let mut sources_file = BufWriter::new(File::create(prefix.clone() + ".sources.tsv")?);
+    sources
+        .iter()
+        .try_for_each(|(source, agc_path, haplotype)| {
+            writeln!(
+                sources_file,
+                "{}\t{}\t{}",
+                source,
+                agc_path,
+                haplotype.as_deref().unwrap_or("NA")
+            )
+        })?;
+    if max_frag_count > 0 {
+        let mut dropped_file = BufWriter::new(File::create(prefix.clone() + ".dropped.tsv")?);
+        let mut dropped_shmmr_pairs = dropped_shmmr_pairs.into_iter().collect::<Vec<_>>();
+        dropped_shmmr_pairs.sort();
+        dropped_shmmr_pairs
+            .iter()
+            .try_for_each(|((s0, s1), count)| {
+                writeln!(dropped_file, "{:016x}\t{:016x}\t{}", s0, s1, count)
+            })?;
+        let _ = writeln!(
+            stderr.lock(),
+            "{} shimmer pairs with more than {} fragments dropped",
+            dropped_shmmr_pairs.len(),
+            max_frag_count
+        );
+    }
     sdb.write_shmmr_map_index(prefix)?;
     Ok(())
 }
 
+// compute the shimmer pair statistics of the sampled sequences for each (w, k, r) without keeping the fragments,
+// the estimated index size counts the shimmer pair keys (16 bytes and the list length) and the fragment
+// signatures (17 bytes each) written to the .mdb file
+#[cfg(feature = "with_agc")]
+fn shimmer_parameter_sweep(args: &CmdOptions) -> Result<(), std::io::Error> {
+    let manifest = read_agc_manifest(&args.filepath)?;
+    let max_bases = if args.sweep_max_bases == 0 {
+        usize::MAX
+    } else {
+        args.sweep_max_bases
+    };
+    let mut seqs = Vec::<Vec<u8>>::new();
+    let mut sampled_bases = 0_usize;
+    'sampling: for (fp, listed_samples) in manifest {
+        let mut agcfile: AGCFile = AGCFile::new(fp)?;
+        agcfile.set_iter_thread(args.number_of_readers);
+        agcfile.set_prefetching(args.prefetching);
+        let samples = listed_samples
+            .into_iter()
+            .map(|(sample, _)| sample)
+            .collect::<FxHashSet<String>>();
+        for rec in &agcfile {
+            let rec = rec?;
+            if !samples.is_empty() && !rec.source.as_ref().is_some_and(|s| samples.contains(s)) {
+                continue;
+            }
+            sampled_bases += rec.seq.len();
+            seqs.push(rec.seq);
+            if sampled_bases >= max_bases {
+                break 'sampling;
+            }
+        }
+    }
+
+    let stderr = io::stderr();
+    let _ = writeln!(
+        stderr.lock(),
+        "sweep the shimmer parameters with {} sequences ({} bp)",
+        seqs.len(),
+        sampled_bases
+    );
+
+    let grid = args
+        .sweep_w
+        .iter()
+        .flat_map(|&w| {
+            args.sweep_k
+                .iter()
+                .flat_map(move |&k| args.sweep_r.iter().map(move |&r| (w, k, r)))
+        })
+        .collect::<Vec<_>>();
+    let sweep_stats = grid
+        .par_iter()
+        .map(|&(w, k, r)| {
+            let shmmr_spec = ShmmrSpec {
+                randstrobe: args.randstrobe,
+                seed_mask: args.seed_mask.as_ref().map(|pattern| {
+                    seed_mask_from_str(pattern, k).expect("fail to parse the spaced seed pattern")
+                }),
+                hash_seed: args.hash_seed,
+                tie_break: tie_break_from_str(&args.tie_break, args.tie_break_seed)
+                    .expect("fail to parse the tie-breaking policy"),
+                ..ShmmrSpec::new(w, k, r, args.min_span, args.sketch)
+            };
+            let mut pair_counts = FxHashMap::<(u64, u64), usize>::default();
+            let mut frag_len_sum = 0_usize;
+            seqs.iter().for_each(|seq| {
+                let shmmrs = pgr_db::shmmrutils::sequence_to_shmmrs(0, seq, &shmmr_spec, false);
+                seq_db::pair_shmmrs(&shmmrs).iter().for_each(|(s0, s1)| {
+                    frag_len_sum += (s1.pos() - s0.pos()) as usize + k as usize;
+                    let (s0, s1) = (s0.hash(), s1.hash());
+                    let shmmr_pair = if s0 <= s1 { (s0, s1) } else { (s1, s0) };
+                    *pair_counts.entry(shmmr_pair).or_insert(0) += 1;
+                });
+            });
+            let n_pairs = pair_counts.len();
+            let n_frags = pair_counts.values().sum::<usize>();
+            // the fraction of the fragments sharing the shimmer pair with other fragments
+            let n_repetitive_frags = pair_counts.values().filter(|&&c| c > 1).sum::<usize>();
+            (
+                (w, k, r),
+                n_pairs,
+                n_frags,
+                frag_len_sum as f64 / n_frags.max(1) as f64,
+                n_repetitive_frags as f64 / n_frags.max(1) as f64,
+                n_pairs * 24 + n_frags * 17,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let mut sweep_file = BufWriter::new(File::create(args.prefix.clone() + ".sweep.tsv")?);
+    writeln!(
+        sweep_file,
+        "w\tk\tr\tmin_span\tshmmr_pairs\tfragments\tmean_frag_len\trepetitive_fraction\test_index_bytes"
+    )?;
+    sweep_stats.iter().try_for_each(
+        |((w, k, r), n_pairs, n_frags, mean_frag_len, repetitive_fraction, index_bytes)| {
+            writeln!(
+                sweep_file,
+                "{}\t{}\t{}\t{}\t{}\t{}\t{:.1}\t{:.4}\t{}",
+                w,
+                k,
+                r,
+                args.min_span,
+                n_pairs,
+                n_frags,
+                mean_frag_len,
+                repetitive_fraction,
+                index_bytes
+            )
+        },
+    )?;
+    Ok(())
+}
+
 fn main() {
     CmdOptions::command().version(VERSION_STRING).get_matches();
 
@@ -87,15 +431,40 @@ fn main() {
     //println!("read data from files in {:?}", args.filepath);
     //println!("output prefix {:?}", args.prefix);
 
+    #[cfg(feature = "with_agc")]
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(args.number_of_thread)
+        .build_global()
+        .unwrap();
+
+    #[cfg(feature = "with_agc")]
+    if args.sweep {
+        shimmer_parameter_sweep(&args).unwrap();
+        return;
+    }
+
     #[cfg(feature = "with_agc")]
     let shmmr_spec = pgr_db::shmmrutils::ShmmrSpec {
-        w: args.w,
-        k: args.k,
-        r: args.r,
-        min_span: args.min_span,
-        sketch: args.sketch,
+        randstrobe: args.randstrobe,
+        seed_mask: args.seed_mask.as_ref().map(|pattern| {
+            seed_mask_from_str(pattern, args.k).expect("fail to parse the spaced seed pattern")
+        }),
+        hash_seed: args.hash_seed,
+        tie_break: tie_break_from_str(&args.tie_break, args.tie_break_seed)
+            .expect("fail to parse the tie-breaking policy"),
+        ..pgr_db::shmmrutils::ShmmrSpec::new(args.w, args.k, args.r, args.min_span, args.sketch)
     };
 
+    #[cfg(feature = "with_agc")]
+    let masked_intervals = args
+        .mask_bed
+        .as_ref()
+        .map(|bed_path| {
+            seq_db::read_masked_intervals_from_bed(bed_path)
+                .expect("fail to read the mask bed file")
+        })
+        .unwrap_or_default();
+
     #[cfg(feature = "with_agc")]
     load_write_index_from_agcfile(
         args.filepath,
@@ -103,6 +472,9 @@ fn main() {
         &shmmr_spec,
         args.prefetching,
         args.number_of_readers,
+        args.channel_size,
+        args.max_frag_count,
+        masked_intervals,
     )
     .unwrap();
 
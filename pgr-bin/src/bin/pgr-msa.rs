@@ -0,0 +1,330 @@
+const VERSION_STRING: &str = env!("VERSION_STRING");
+use clap::{self, CommandFactory, Parser};
+use pgr_db::ext::{get_fastx_reader, GZFastaReader};
+use pgr_db::fasta_io::{reverse_complement, SeqRec};
+use pgr_db::poa::PoaGraph;
+use rayon::prelude::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Generate a multiple sequence alignment using the shared principal bundles as anchors
+/// and partial order alignment within each inter-anchor interval
+#[derive(Parser, Debug)]
+#[clap(name = "pgr-msa")]
+#[clap(author, version)]
+#[clap(about, long_about = None)]
+struct CmdOptions {
+    /// the path to the fasta file of the sequences
+    fastx_path: String,
+    /// the path to the principal bundle bed file generated from the sequences by pgr-pbundle-decomp
+    bed_file_path: String,
+    /// the prefix of the output files
+    output_prefix: String,
+
+    /// the path to a file that contains a list of contig names to be aligned, the first one sets the orientation, default to all contigs in the bed file
+    #[clap(long, short, default_value = None)]
+    include: Option<String>,
+
+    /// intervals with a sequence longer than this are not aligned but padded with gaps
+    #[clap(long, default_value_t = 4000)]
+    max_poa_length: usize,
+
+    /// the match score for partial order alignment
+    #[clap(long, default_value_t = 2)]
+    match_score: i32,
+
+    /// the mismatch penalty for partial order alignment
+    #[clap(long, default_value_t = 4)]
+    mismatch_penalty: i32,
+
+    /// the gap penalty for partial order alignment
+    #[clap(long, default_value_t = 4)]
+    gap_penalty: i32,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct BundleSegment {
+    bgn: u32,
+    end: u32,
+    bundle_id: u32,
+    bundle_dir: u32,
+}
+
+fn main() -> Result<(), std::io::Error> {
+    CmdOptions::command().version(VERSION_STRING).get_matches();
+    let args = CmdOptions::parse();
+
+    let bed_file = BufReader::new(
+        File::open(Path::new(&args.bed_file_path)).expect("can't open the bed file"),
+    );
+    let mut ctg_data = FxHashMap::<String, Vec<BundleSegment>>::default();
+    let bed_file_parse_err_msg = "bed file parsing error";
+    bed_file.lines().for_each(|line| {
+        let line = line.unwrap().trim().to_string();
+        if line.is_empty() || &line[0..1] == "#" {
+            return;
+        }
+        let bed_fields = line.split('\t').collect::<Vec<&str>>();
+        let ctg: String = bed_fields[0].to_string();
+        let bgn: u32 = bed_fields[1].parse().expect(bed_file_parse_err_msg);
+        let end: u32 = bed_fields[2].parse().expect(bed_file_parse_err_msg);
+        let pbundle_fields = bed_fields[3].split(':').collect::<Vec<&str>>();
+        let bundle_id: u32 = pbundle_fields[0].parse().expect(bed_file_parse_err_msg);
+        let bundle_dir: u32 = pbundle_fields[2].parse().expect(bed_file_parse_err_msg);
+        ctg_data.entry(ctg).or_default().push(BundleSegment {
+            bgn,
+            end,
+            bundle_id,
+            bundle_dir,
+        });
+    });
+
+    let ctgs = if let Some(include) = args.include {
+        let f =
+            BufReader::new(File::open(Path::new(&include)).expect("can't open the include file"));
+        f.lines()
+            .map(|c| c.unwrap().trim().to_string())
+            .filter(|c| !c.is_empty())
+            .collect::<Vec<String>>()
+    } else {
+        let mut ctgs = ctg_data.keys().cloned().collect::<Vec<String>>();
+        ctgs.sort();
+        ctgs
+    };
+    if ctgs.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "no contig to align, the bed file or the include file is empty",
+        ));
+    }
+    let mut listed_ctgs = FxHashSet::<&String>::default();
+    for ctg in ctgs.iter() {
+        if !ctg_data.contains_key(ctg) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("contig {} is not in the bed file", ctg),
+            ));
+        }
+        if !listed_ctgs.insert(ctg) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("contig {} is listed more than once", ctg),
+            ));
+        }
+    }
+
+    let mut seqs = FxHashMap::<String, Vec<u8>>::default();
+    let mut add_seqs = |seq_iter: &mut dyn Iterator<Item = io::Result<SeqRec>>| {
+        seq_iter.into_iter().for_each(|r| {
+            if let Ok(r) = r {
+                let ctg = String::from_utf8_lossy(&r.id).to_string();
+                if ctg_data.contains_key(&ctg) {
+                    seqs.insert(ctg, r.seq);
+                }
+            };
+        });
+    };
+
+    match get_fastx_reader(args.fastx_path, true)? {
+        #[allow(clippy::useless_conversion)] // the into_iter() is necessary for dyn patching
+        GZFastaReader::GZFile(reader) => add_seqs(&mut reader.into_iter()),
+
+        #[allow(clippy::useless_conversion)] // the into_iter() is necessary for dyn patching
+        GZFastaReader::RegularFile(reader) => add_seqs(&mut reader.into_iter()),
+    };
+    if let Some(ctg) = ctgs.iter().find(|ctg| !seqs.contains_key(*ctg)) {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("the sequence of contig {} is not in the fasta file", ctg),
+        ));
+    }
+
+    // orient the sequences to the first one using the directions of the shared bundles, all contigs are
+    // checked to be in the bed file and the fasta file above
+    let ref_bundle_dir = ctg_data
+        .get(&ctgs[0])
+        .unwrap()
+        .iter()
+        .map(|s| (s.bundle_id, s.bundle_dir))
+        .collect::<FxHashMap<u32, u32>>();
+    let oriented_ctg_data = ctgs
+        .iter()
+        .map(|ctg| {
+            let segments = ctg_data.get(ctg).unwrap();
+            let seq = seqs.remove(ctg).unwrap();
+            let mut same_dir_len = 0_u32;
+            let mut diff_dir_len = 0_u32;
+            segments.iter().for_each(|s| {
+                if let Some(&d) = ref_bundle_dir.get(&s.bundle_id) {
+                    if d == s.bundle_dir {
+                        same_dir_len += s.end - s.bgn;
+                    } else {
+                        diff_dir_len += s.end - s.bgn;
+                    }
+                }
+            });
+            if diff_dir_len > same_dir_len {
+                let len = seq.len() as u32;
+                let mut segments = segments
+                    .iter()
+                    .map(|s| BundleSegment {
+                        bgn: len - s.end,
+                        end: len - s.bgn,
+                        bundle_id: s.bundle_id,
+                        bundle_dir: 1 - s.bundle_dir,
+                    })
+                    .collect::<Vec<BundleSegment>>();
+                segments.reverse();
+                (ctg.clone(), '-', reverse_complement(&seq), segments)
+            } else {
+                (ctg.clone(), '+', seq, segments.clone())
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // the anchors are the bundles appearing once in every sequence with a consistent order
+    let mut bundle_count = FxHashMap::<(u32, u32), Vec<usize>>::default();
+    oriented_ctg_data.iter().for_each(|(_, _, _, segments)| {
+        let mut count = FxHashMap::<(u32, u32), usize>::default();
+        segments.iter().for_each(|s| {
+            *count.entry((s.bundle_id, s.bundle_dir)).or_insert(0) += 1;
+        });
+        count.into_iter().for_each(|(k, c)| {
+            bundle_count.entry(k).or_default().push(c);
+        });
+    });
+    let n_ctg = oriented_ctg_data.len();
+    let candidates = oriented_ctg_data[0]
+        .3
+        .iter()
+        .filter(|s| {
+            if let Some(c) = bundle_count.get(&(s.bundle_id, s.bundle_dir)) {
+                c.len() == n_ctg && c.iter().all(|&c| c == 1)
+            } else {
+                false
+            }
+        })
+        .map(|s| (s.bundle_id, s.bundle_dir))
+        .collect::<Vec<(u32, u32)>>();
+    let ctg_anchor_segments = oriented_ctg_data
+        .iter()
+        .map(|(_, _, _, segments)| {
+            segments
+                .iter()
+                .map(|s| ((s.bundle_id, s.bundle_dir), *s))
+                .collect::<FxHashMap<(u32, u32), BundleSegment>>()
+        })
+        .collect::<Vec<_>>();
+    let mut last_anchor_end = vec![0_u32; n_ctg];
+    let mut anchors = Vec::<(u32, u32)>::new();
+    candidates.into_iter().for_each(|key| {
+        if ctg_anchor_segments
+            .iter()
+            .zip(last_anchor_end.iter())
+            .all(|(m, &e)| m.get(&key).unwrap().bgn >= e)
+        {
+            ctg_anchor_segments
+                .iter()
+                .zip(last_anchor_end.iter_mut())
+                .for_each(|(m, e)| *e = m.get(&key).unwrap().end);
+            anchors.push(key);
+        }
+    });
+
+    // (bgn, end) of the anchors and the intervals between them for each sequence
+    let ctg_intervals = oriented_ctg_data
+        .iter()
+        .zip(ctg_anchor_segments.iter())
+        .map(|((_, _, seq, _), m)| {
+            let mut intervals = Vec::<(u32, u32)>::new();
+            let mut p_end = 0_u32;
+            anchors.iter().for_each(|key| {
+                let s = m.get(key).unwrap();
+                intervals.push((p_end, s.bgn));
+                intervals.push((s.bgn, s.end));
+                p_end = s.end;
+            });
+            intervals.push((p_end, seq.len() as u32));
+            intervals
+        })
+        .collect::<Vec<_>>();
+
+    let n_interval = anchors.len() * 2 + 1;
+    let interval_alignments = (0..n_interval)
+        .into_par_iter()
+        .map(|idx| {
+            let sub_seqs = oriented_ctg_data
+                .iter()
+                .zip(ctg_intervals.iter())
+                .map(|((_, _, seq, _), intervals)| {
+                    let (bgn, end) = intervals[idx];
+                    seq[bgn as usize..end as usize].to_vec()
+                })
+                .collect::<Vec<Vec<u8>>>();
+            let max_len = sub_seqs.iter().map(|s| s.len()).max().unwrap_or(0);
+            if max_len > args.max_poa_length {
+                sub_seqs
+                    .into_iter()
+                    .map(|mut s| {
+                        s.resize(max_len, b'-');
+                        s
+                    })
+                    .collect::<Vec<Vec<u8>>>()
+            } else {
+                let mut poa_graph =
+                    PoaGraph::new(args.match_score, args.mismatch_penalty, args.gap_penalty);
+                sub_seqs.iter().for_each(|s| poa_graph.add_seq(s));
+                poa_graph.get_msa()
+            }
+        })
+        .collect::<Vec<Vec<Vec<u8>>>>();
+
+    let prefix = Path::new(&args.output_prefix);
+    let mut fasta_out = BufWriter::new(File::create(prefix.with_extension("msa.fa"))?);
+    oriented_ctg_data
+        .iter()
+        .enumerate()
+        .for_each(|(ctg_idx, (ctg, _, _, _))| {
+            writeln!(fasta_out, ">{}", ctg).expect("can't write the msa fasta file");
+            interval_alignments.iter().for_each(|rows| {
+                fasta_out
+                    .write_all(&rows[ctg_idx])
+                    .expect("can't write the msa fasta file");
+            });
+            writeln!(fasta_out).expect("can't write the msa fasta file");
+        });
+
+    let mut maf_out = BufWriter::new(File::create(prefix.with_extension("maf"))?);
+    writeln!(maf_out, "##maf version=1").expect("can't write the maf file");
+    interval_alignments
+        .iter()
+        .enumerate()
+        .for_each(|(idx, rows)| {
+            if rows.is_empty() || rows[0].is_empty() {
+                return;
+            }
+            writeln!(maf_out, "a").expect("can't write the maf file");
+            oriented_ctg_data
+                .iter()
+                .enumerate()
+                .for_each(|(ctg_idx, (ctg, strand, seq, _))| {
+                    let (bgn, end) = ctg_intervals[ctg_idx][idx];
+                    writeln!(
+                        maf_out,
+                        "s {} {} {} {} {} {}",
+                        ctg,
+                        bgn,
+                        end - bgn,
+                        strand,
+                        seq.len(),
+                        String::from_utf8_lossy(&rows[ctg_idx])
+                    )
+                    .expect("can't write the maf file");
+                });
+            writeln!(maf_out).expect("can't write the maf file");
+        });
+
+    Ok(())
+}
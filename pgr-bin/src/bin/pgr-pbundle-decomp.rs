@@ -4,6 +4,8 @@ use clap::{self, CommandFactory, Parser};
 use pgr_db::ext::{
     get_principal_bundle_decomposition, PrincipalBundlesWithId, SeqIndexDB, VertexToBundleIdMap,
 };
+use pgr_db::gff_db::GFFDB;
+use pgr_db::graph_utils::GraphSimplification;
 use rustc_hash::{FxHashMap, FxHashSet};
 //use std::fs::File;
 use std::{
@@ -49,12 +51,25 @@ struct CmdOptions {
     /// the minimum branch length in MAP-graph to be included in the principal bundles
     #[clap(long, default_value_t = 8)]
     min_branch_size: usize,
+    /// clip the tips (dead-end branches) of up to this number of vertices in MAP-graph before getting the principal bundles, "0" for not clipping the tips
+    #[clap(long, default_value_t = 0)]
+    max_tip_len: usize,
+    /// only clip the tips walked by at most this number of sequences
+    #[clap(long, default_value_t = 1)]
+    max_tip_coverage: usize,
+    /// pop the bubbles of up to this number of vertices in MAP-graph to the allele walked by the most sequences before getting the principal bundles, "0" for not popping the bubbles
+    #[clap(long, default_value_t = 0)]
+    max_bubble_size: usize,
     /// the minimum local project bundle size to includes
     #[clap(long, default_value_t = 2500)]
     bundle_length_cutoff: usize,
     /// merge two bundles with the same id with the specified length
     #[clap(long, default_value_t = 10000)]
     bundle_merge_distance: usize,
+    /// the GFF3/GTF annotation file of the sequences (e.g., of the reference region) in the decomposition, the genes
+    /// overlapped by each principal bundle are written to <OUTPUT_PREFIX>.bundle.genes.tsv
+    #[clap(long, default_value = None)]
+    gff: Option<String>,
 }
 
 #[allow(clippy::type_complexity)]
@@ -223,6 +238,13 @@ fn main() -> Result<(), std::io::Error> {
             seq_index_db
                 .load_from_fastx(fastx_path.clone(), args.w, args.k, args.r, args.min_span, true)
                 .unwrap_or_else(|_| panic!("can't read file {}", fastx_path));
+            if args.max_tip_len > 0 || args.max_bubble_size > 0 {
+                seq_index_db.graph_simplification = Some(GraphSimplification {
+                    max_tip_len: args.max_tip_len,
+                    max_tip_coverage: args.max_tip_coverage,
+                    max_bubble_size: args.max_bubble_size,
+                });
+            }
 
             let (principal_bundles_with_id, vertex_to_bundle_id_direction_pos) = seq_index_db
                 .get_principal_bundles_with_id(args.min_cov, args.min_branch_size, None);
@@ -304,6 +326,17 @@ fn main() -> Result<(), std::io::Error> {
             None,
         )?;
 
+        seq_index_db.write_mapg_coverage_tsv(
+            0,
+            output_prefix_path
+                .with_extension("mapg.cov.tsv")
+                .to_str()
+                .unwrap(),
+            "from_fragmap",
+            None,
+            None,
+        )?;
+
         seq_index_db.write_mapg_idx(
             output_prefix_path
                 .with_extension("mapg.idx")
@@ -331,6 +364,14 @@ fn main() -> Result<(), std::io::Error> {
 
     writeln!(outpu_bed_file, "# cmd: {}", cmd_string).expect("bed file write error");
 
+    let gff = if let Some(gff) = args.gff.as_ref() {
+        Some(GFFDB::from_file(Path::new(gff))?)
+    } else {
+        None
+    };
+    // the intervals of the bundles on the annotated sequences
+    let mut ctg_bundle_intervals = FxHashMap::<(String, usize), Vec<(u32, u32)>>::default();
+
     let mut repeat_count = FxHashMap::<u32, Vec<u32>>::default();
     let mut non_repeat_count = FxHashMap::<u32, Vec<u32>>::default();
 
@@ -399,6 +440,15 @@ fn main() -> Result<(), std::io::Error> {
             let e = p[p.len() - 1].0 .3;
             let bid = p[0].1;
             let direction = p[0].2;
+            if gff
+                .as_ref()
+                .is_some_and(|gff| gff.intervals.contains_key(ctg))
+            {
+                ctg_bundle_intervals
+                    .entry((ctg.clone(), bid))
+                    .or_default()
+                    .push((b, e));
+            }
             let is_repeat = if *ctg_bundle_count.get(&bid).unwrap_or(&0) > 1 {
                 repeat_count
                     .entry(*sid)
@@ -427,6 +477,34 @@ fn main() -> Result<(), std::io::Error> {
             );
         });
     });
+    if let Some(gff) = gff {
+        let mut output_bundle_gene_file = BufWriter::new(File::create(
+            output_prefix_path.with_extension("bundle.genes.tsv"),
+        )?);
+        writeln!(
+            output_bundle_gene_file,
+            "#bundle_id\tctg\tgene\tgene_bgn\tgene_end\tcovered_bases\tcovered_fraction"
+        )?;
+        let mut ctg_bundle_intervals = ctg_bundle_intervals.into_iter().collect::<Vec<_>>();
+        ctg_bundle_intervals.sort_by(|a, b| (a.0 .1, &a.0 .0).cmp(&(b.0 .1, &b.0 .0)));
+        for ((ctg, bid), intervals) in ctg_bundle_intervals {
+            for (gene, covered) in gff.genes_covered(&ctg, &intervals) {
+                let gene_len = gene.end - gene.bgn + 1;
+                writeln!(
+                    output_bundle_gene_file,
+                    "{}\t{}\t{}\t{}\t{}\t{}\t{:.4}",
+                    bid,
+                    ctg,
+                    gene.name().or_else(|| gene.id()).unwrap_or("."),
+                    gene.bgn - 1,
+                    gene.end,
+                    covered,
+                    covered as f32 / gene_len as f32
+                )?;
+            }
+        }
+    }
+
     #[allow(clippy::write_literal)]
     let _ = writeln!(
         output_ctg_summary_file,
@@ -1,12 +1,23 @@
 const VERSION_STRING: &str = env!("VERSION_STRING");
 use clap::{self, CommandFactory, Parser};
+use pgr_db::aln;
 use pgr_db::ext::{get_fastx_reader, GZFastaReader, SeqIndexDB};
-use pgr_db::fasta_io::SeqRec;
+use pgr_db::fasta_io::{create_fastx_writer, FastxCompression, FastxWriter, SeqRec, SeqRecChunks};
 use rayon::prelude::*;
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::Serialize;
 use std::fs::File;
-use std::io::{self, BufWriter, Write};
-use std::path::Path;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, clap::ValueEnum, Default, Debug)]
+enum OptFormat {
+    #[default]
+    Hit,
+    Bed,
+    Paf,
+    Json,
+}
 
 /// Query a PGR-TK pangenome sequence database,
 /// output the hit summary and generate fasta files from the target sequences
@@ -26,6 +37,11 @@ struct CmdOptions {
     #[clap(long, default_value_t = false)]
     frg_file: bool,
 
+    /// using the indexed fasta files for the sequence database, the index files and the `.fasta_manifest` file
+    /// are created by `pgr-make-db --indexed-fasta`
+    #[clap(long, default_value_t = false)]
+    indexed_fasta: bool,
+
     #[clap(long, default_value_t = false)]
     fastx_file: bool,
 
@@ -45,6 +61,15 @@ struct CmdOptions {
     #[clap(long, short, default_value_t = 0.025)]
     gap_penalty_factor: f32,
 
+    /// the gap length beyond which the long gap penalty factor is used for the sparse alignments, "0" for the
+    /// linear gap cost, a smaller long gap penalty factor keeps the chains from breaking at the SVs
+    #[clap(long, default_value_t = 0)]
+    long_gap_break_len: u32,
+
+    /// the gap penalty factor for the gap bases beyond the long gap break length
+    #[clap(long, default_value_t = 0.0025)]
+    long_gap_penalty_factor: f32,
+
     /// merge hits with the specified distance
     #[clap(long, short, default_value_t = 100000)]
     merge_range_tol: usize,
@@ -65,19 +90,475 @@ struct CmdOptions {
     #[clap(long, default_value_t = 8)]
     max_aln_chain_span: u32,
 
+    /// the min score of the sparse alignment chains
+    #[clap(long, default_value_t = 0.0)]
+    min_score: f32,
+
+    /// the min number of the anchor hits (shimmer pairs) of the sparse alignment chains, the chains with less than 3 anchors are always skipped
+    #[clap(long, default_value_t = 3)]
+    min_anchor_count: usize,
+
+    /// the min target span of the merged hit regions
+    #[clap(long, default_value_t = 0)]
+    min_target_span: u32,
+
     /// option only to output summaries
     #[clap(long, default_value_t = false)]
     only_summary: bool,
 
-    /// output summaries in the bed format
+    /// output summaries in the bed format (the same as `--format bed`)
     #[clap(long, default_value_t = false)]
     bed_summary: bool,
 
+    /// the format of the hit summary files, one record per merged hit region
+    #[clap(long, default_value_t, value_enum)]
+    format: OptFormat,
+
+    /// the max number of the secondary hits (overlapping a better hit on the query) reported for each query, all the
+    /// secondary hits are reported if not specified
+    #[clap(long, default_value = None)]
+    max_secondary: Option<usize>,
+
+    /// write the output files of each query to the directory <OUTPUT_PREFIX>/ as <QUERY_IDX>_<QUERY_NAME>.<EXT>
+    #[clap(long, default_value_t = false)]
+    query_dir: bool,
+
+    /// only report and fetch the hits to these sources (sample names), a comma separated list or a file with one source per line
+    #[clap(long, default_value = None)]
+    include_sources: Option<String>,
+
+    /// skip the hits to these sources (sample names), a comma separated list or a file with one source per line
+    #[clap(long, default_value = None)]
+    exclude_sources: Option<String>,
+
+    /// check that every sequence in the index resolves to a contig in the sequence store (.agc or .frg) before querying
+    #[clap(long, default_value_t = false)]
+    check: bool,
+
+    /// skip and log the hits to the sequences that can't be resolved in the sequence store instead of aborting the run
+    #[clap(long, default_value_t = false)]
+    skip_unresolved: bool,
+
+    /// refine the boundaries of the hit regions with a WFA alignment of the padded query and target segments, and report the identity
+    #[clap(long, default_value_t = false)]
+    refine: bool,
+
+    /// the padding added to both ends of the query and target segments for the refinement
+    #[clap(long, default_value_t = 500)]
+    refine_padding: u32,
+
+    /// the max distance of the WFA alignment for the refinement, the boundaries of the hits failing the alignment are
+    /// extended into the padding with an x-drop extension instead
+    #[clap(long, default_value_t = 4096)]
+    refine_max_distance: u32,
+
+    /// write the padded target sequences of the merged hits (reverse complemented for the hits on the reverse strand) to <OUTPUT_PREFIX>.target.fa
+    #[clap(long, default_value_t = false)]
+    emit_target_fasta: bool,
+
+    /// the number of bases per line of the output fasta files, "0" for writing each sequence in one line
+    #[clap(long, default_value_t = 0)]
+    fasta_line_width: usize,
+
+    /// the padding added to both ends of the target sequences with `--emit-target-fasta`
+    #[clap(long, default_value_t = 0)]
+    target_padding: u32,
+
+    /// process the queries in batches of this number of sequences while streaming the query file, the results
+    /// are written after each batch ("0" to load all query sequences before processing)
+    #[clap(long, default_value_t = 0)]
+    batch_size: usize,
+
+    /// the max number of the query bases in a batch, "0" for no limit
+    #[clap(long, default_value_t = 0)]
+    batch_max_bases: usize,
+
     /// number of threads used in parallel (more memory usage), default to "0" using all CPUs available or the number set by RAYON_NUM_THREADS
     #[clap(long, default_value_t = 0)]
     number_of_thread: usize,
 }
 
+// a merged hit region of a query
+#[derive(Serialize, Clone)]
+struct HitRecord {
+    q_idx: usize,
+    q_name: String,
+    q_bgn: u32,
+    q_end: u32,
+    q_len: usize,
+    sid: u32,
+    src: String,
+    ctg: String,
+    ctg_len: u32,
+    t_bgn: u32,
+    t_end: u32,
+    orientation: u32,
+    hit_count: usize,
+    score: f32,
+    // the MAPQ-like confidences among the overlapping hits to the same target and to all targets
+    target_mapq: u8,
+    region_mapq: u8,
+    label: aln::ChainLabel,
+    target_seq_name: String,
+    n_matches: Option<u32>,
+    aln_block_len: Option<u32>,
+    identity: Option<f32>,
+    // the CIGAR string of the refined alignment (the target on the forward strand)
+    cigar: Option<String>,
+}
+
+// the best local segment of a global alignment (match: +1, mismatch or gap: -2),
+// returns (t_bgn, t_end, q_bgn, q_end, n_matches, aln_block_len) in the aligned sequences
+fn best_aln_segment(
+    aln_target_str: &str,
+    aln_query_str: &str,
+) -> Option<(u32, u32, u32, u32, u32, u32)> {
+    let mut t_pos = 0_u32;
+    let mut q_pos = 0_u32;
+    let mut score = 0_i64;
+    let mut n_matches = 0_u32;
+    let mut bgn = (0_u32, 0_u32, 0_usize);
+    let mut best: Option<(i64, (u32, u32, u32, u32, u32, u32))> = None;
+    std::iter::zip(aln_target_str.as_bytes(), aln_query_str.as_bytes())
+        .enumerate()
+        .for_each(|(col, (&tb, &qb))| {
+            if score <= 0 {
+                score = 0;
+                n_matches = 0;
+                bgn = (t_pos, q_pos, col);
+            }
+            if tb != b'-' {
+                t_pos += 1;
+            }
+            if qb != b'-' {
+                q_pos += 1;
+            }
+            if tb == qb {
+                score += 1;
+                n_matches += 1;
+            } else {
+                score -= 2;
+            }
+            if score > best.map_or(0, |(best_score, _)| best_score) {
+                let aln_block_len = (col + 1 - bgn.2) as u32;
+                best = Some((
+                    score,
+                    (bgn.0, t_pos, bgn.1, q_pos, n_matches, aln_block_len),
+                ));
+            }
+        });
+    best.map(|(_, segment)| segment)
+}
+
+// the x-drop and the band width of the extensions refining the boundaries of the hits failing the WFA alignment
+const REFINE_X_DROP: i32 = 32;
+const REFINE_BAND_WIDTH: usize = 64;
+
+// refine the boundaries of a hit region with a WFA alignment of the padded query and target segments,
+// the boundaries of the hits failing the alignment are refined with the x-drop extensions from the hit ends
+fn refine_hit(
+    seq_index_db: &SeqIndexDB,
+    query_seq: &[u8],
+    hit: &mut HitRecord,
+    padding: u32,
+    max_distance: u32,
+) {
+    let qb = hit.q_bgn.saturating_sub(padding);
+    let qe = (hit.q_end + padding).min(query_seq.len() as u32);
+    let tb = hit.t_bgn.saturating_sub(padding);
+    let te = (hit.t_end + padding).min(hit.ctg_len);
+    if qb >= qe || tb >= te {
+        return;
+    }
+    let target_seq =
+        if let Ok(target_seq) = seq_index_db.get_sub_seq_by_id(hit.sid, tb as usize, te as usize) {
+            target_seq
+        } else {
+            return;
+        };
+    let target_seq = if hit.orientation == 1 {
+        pgr_db::fasta_io::reverse_complement(&target_seq)
+    } else {
+        target_seq
+    };
+    let query_seq = &query_seq[qb as usize..qe as usize];
+    let target_str = String::from_utf8_lossy(&target_seq);
+    let query_str = String::from_utf8_lossy(query_seq);
+    let aln_params = aln::AlnParams {
+        max_wf_length: Some(max_distance),
+        ..Default::default()
+    };
+    let aln_segment = aln::wfa_align_bases(&target_str, &query_str, &aln_params).and_then(
+        |(aln_target_str, aln_query_str)| best_aln_segment(&aln_target_str, &aln_query_str),
+    );
+    // the hit interval in the padded (and oriented) target and query segments
+    let (t_bgn, t_end, q_bgn, q_end) = if let Some(aln_segment) = aln_segment {
+        let (t_bgn, t_end, q_bgn, q_end, n_matches, aln_block_len) = aln_segment;
+        hit.n_matches = Some(n_matches);
+        hit.aln_block_len = Some(aln_block_len);
+        hit.identity = Some(n_matches as f32 / aln_block_len as f32);
+        // the aligned segment starts with a match
+        hit.cigar = aln::get_wfa_variant_segments(
+            &target_seq[t_bgn as usize..t_end as usize],
+            &query_seq[q_bgn as usize..q_end as usize],
+            1,
+            &aln_params,
+        )
+        .and_then(|variants| {
+            aln::variant_segments_to_cigar_ops(&variants, t_end - t_bgn, q_end - q_bgn)
+        })
+        .map(|mut ops| {
+            if hit.orientation == 1 {
+                ops.reverse();
+            }
+            aln::cigar_ops_to_string(&ops, true)
+        });
+        (t_bgn, t_end, q_bgn, q_end)
+    } else {
+        let (t_bgn, t_end) = if hit.orientation == 0 {
+            (hit.t_bgn - tb, hit.t_end - tb)
+        } else {
+            (te - hit.t_end, te - hit.t_bgn)
+        };
+        let (q_bgn, q_end) = (hit.q_bgn - qb, hit.q_end - qb);
+        if t_bgn >= t_end || q_bgn >= q_end {
+            return;
+        }
+        let reversed = |s: &[u8]| s.iter().rev().copied().collect::<Vec<u8>>();
+        let (t_left, q_left) = aln::x_drop_extend(
+            &reversed(&target_seq[..t_bgn as usize]),
+            &reversed(&query_seq[..q_bgn as usize]),
+            REFINE_X_DROP,
+            REFINE_BAND_WIDTH,
+        );
+        let (t_right, q_right) = aln::x_drop_extend(
+            &target_seq[t_end as usize..],
+            &query_seq[q_end as usize..],
+            REFINE_X_DROP,
+            REFINE_BAND_WIDTH,
+        );
+        (
+            t_bgn - t_left,
+            t_end + t_right,
+            q_bgn - q_left,
+            q_end + q_right,
+        )
+    };
+    hit.q_bgn = qb + q_bgn;
+    hit.q_end = qb + q_end;
+    (hit.t_bgn, hit.t_end) = if hit.orientation == 0 {
+        (tb + t_bgn, tb + t_end)
+    } else {
+        (te - t_end, te - t_bgn)
+    };
+    let base = Path::new(&hit.src).file_stem().unwrap().to_string_lossy();
+    hit.target_seq_name = format!(
+        "{}::{}_{}_{}_{}",
+        base, hit.ctg, hit.t_bgn, hit.t_end, hit.orientation
+    );
+}
+
+fn write_hit_records(
+    out: &mut impl Write,
+    hit_records: &[HitRecord],
+    format: OptFormat,
+    refine: bool,
+) -> Result<(), std::io::Error> {
+    // the identity column is appended to the hit and bed summaries with `--refine`
+    let identity_column = |r: &HitRecord| -> String {
+        if !refine {
+            String::new()
+        } else if let Some(identity) = r.identity {
+            format!("\t{:.4}", identity)
+        } else {
+            "\tNA".to_string()
+        }
+    };
+    let identity_header = if refine { "\tidentity" } else { "" };
+    match format {
+        OptFormat::Hit => {
+            writeln!(
+                out,
+                "#{}",
+                [
+                    "idx",
+                    "q_ctg_name",
+                    "q_ctg_bgn",
+                    "q_ctg_end",
+                    "q_ctg_len",
+                    "aln_anchor_count",
+                    "src",
+                    "ctg",
+                    "ctg_bgn",
+                    "ctg_end",
+                    "orientation",
+                    "ctg_name",
+                    "target_mapq",
+                    "region_mapq",
+                    "label"
+                ]
+                .join("\t")
+                    + identity_header
+            )?;
+            hit_records.iter().try_for_each(|r| {
+                writeln!(
+                    out,
+                    "{:03}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}{}",
+                    r.q_idx,
+                    r.q_name,
+                    r.q_bgn,
+                    r.q_end,
+                    r.q_len,
+                    r.hit_count,
+                    r.src,
+                    r.ctg,
+                    r.t_bgn,
+                    r.t_end,
+                    r.orientation,
+                    r.target_seq_name,
+                    r.target_mapq,
+                    r.region_mapq,
+                    r.label,
+                    identity_column(r)
+                )
+            })
+        }
+        OptFormat::Bed => {
+            writeln!(
+                out,
+                "#{}",
+                [
+                    "target",
+                    "bgn",
+                    "end",
+                    "query",
+                    "color",
+                    "orientation",
+                    "q_len",
+                    "aln_anchor_count",
+                    "q_idx",
+                    "src",
+                    "ctg_bgn",
+                    "ctg_end",
+                    "target_seq_name",
+                    "score",
+                    "target_mapq",
+                    "region_mapq",
+                    "label",
+                ]
+                .join("\t")
+                    + identity_header
+            )?;
+            hit_records.iter().try_for_each(|r| {
+                writeln!(
+                    out,
+                    "{}\t{}\t{}\t{}\t#AAAAAA\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}{}",
+                    r.ctg,
+                    r.t_bgn,
+                    r.t_end,
+                    r.q_name,
+                    r.orientation,
+                    r.q_len,
+                    r.hit_count,
+                    r.q_idx,
+                    r.src,
+                    r.q_bgn,
+                    r.q_end,
+                    r.target_seq_name,
+                    r.score,
+                    r.target_mapq,
+                    r.region_mapq,
+                    r.label,
+                    identity_column(r)
+                )
+            })
+        }
+        OptFormat::Paf => hit_records.iter().try_for_each(|r| {
+            // without `--refine`, the residue matches are not computed from the sparse alignments
+            // and the block length is the longer span, the mapping quality is the confidence among
+            // the hits to all targets and the confidence among the hits to the same target is in the tq tag,
+            // the hit type tag is "P" for the primary and the supplementary hits and "S" for the secondary hits,
+            // the CIGAR of the refined alignment is in the cg tag
+            let base = Path::new(&r.src).file_stem().unwrap().to_string_lossy();
+            let n_matches = r.n_matches.unwrap_or(0);
+            let block_len = r
+                .aln_block_len
+                .unwrap_or((r.q_end - r.q_bgn).max(r.t_end - r.t_bgn));
+            writeln!(
+                out,
+                "{}\t{}\t{}\t{}\t{}\t{}::{}\t{}\t{}\t{}\t{}\t{}\t{}\thc:i:{}\tsc:f:{}\ttq:i:{}\ttp:A:{}\tlb:Z:{}{}",
+                r.q_name,
+                r.q_len,
+                r.q_bgn,
+                r.q_end,
+                if r.orientation == 0 { '+' } else { '-' },
+                base,
+                r.ctg,
+                r.ctg_len,
+                r.t_bgn,
+                r.t_end,
+                n_matches,
+                block_len,
+                r.region_mapq,
+                r.hit_count,
+                r.score,
+                r.target_mapq,
+                if r.label == aln::ChainLabel::Secondary {
+                    'S'
+                } else {
+                    'P'
+                },
+                r.label,
+                r.cigar
+                    .as_ref()
+                    .map_or(String::new(), |cigar| format!("\tcg:Z:{}", cigar))
+            )
+        }),
+        OptFormat::Json => {
+            let json = serde_json::to_string(hit_records).expect("fail to construct json for hits");
+            writeln!(out, "{}", json)
+        }
+    }
+}
+
+// the source names from a comma separated list or a file with one source per line
+fn load_sources(sources: &str) -> Result<FxHashSet<String>, std::io::Error> {
+    if Path::new(sources).is_file() {
+        BufReader::new(File::open(sources)?)
+            .lines()
+            .filter_map(|line| match line {
+                Ok(line) if line.trim().is_empty() || line.starts_with('#') => None,
+                line => Some(line.map(|line| line.trim().to_string())),
+            })
+            .collect()
+    } else {
+        Ok(sources
+            .split(',')
+            .filter(|source| !source.is_empty())
+            .map(|source| source.to_string())
+            .collect())
+    }
+}
+
+// the path of an output file of a query, in the per-query directory if `--query-dir` is specified
+fn query_output_path(args: &CmdOptions, idx: usize, q_name: &str, ext: &str) -> PathBuf {
+    if args.query_dir {
+        let q_name = q_name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+        Path::new(&args.output_prefix).join(format!("{:03}_{}.{}", idx, q_name, ext))
+    } else {
+        Path::new(&args.output_prefix).with_extension(format!("{:03}.{}", idx, ext))
+    }
+}
+
 fn main() -> Result<(), std::io::Error> {
     CmdOptions::command().version(VERSION_STRING).get_matches();
     let args = CmdOptions::parse();
@@ -87,29 +568,17 @@ fn main() -> Result<(), std::io::Error> {
         .build_global()
         .unwrap();
 
-    let mut query_seqs: Vec<SeqRec> = vec![];
-    let mut add_seqs = |seq_iter: &mut dyn Iterator<Item = io::Result<SeqRec>>| {
-        seq_iter.into_iter().for_each(|r| {
-            if let Ok(r) = r {
-                query_seqs.push(r);
-            };
-        });
-    };
-
-    match get_fastx_reader(args.query_fastx_path, true)? {
-        #[allow(clippy::useless_conversion)] // the into_iter() is necessary for dyn patching
-        GZFastaReader::GZFile(reader) => add_seqs(&mut reader.into_iter()),
-
-        #[allow(clippy::useless_conversion)] // the into_iter() is necessary for dyn patching
-        GZFastaReader::RegularFile(reader) => add_seqs(&mut reader.into_iter()),
-    };
-
     let mut seq_index_db = SeqIndexDB::new();
     if args.frg_file {
         let stderr = io::stderr();
         let mut handle = stderr.lock();
         let _ = handle.write_all(b"the option `--frg_file` is specified, read the input file as a FRG backed index database files.\n");
-        let _ = seq_index_db.load_from_frg_index(args.pgr_db_prefix);
+        let _ = seq_index_db.load_from_frg_index(args.pgr_db_prefix.clone());
+    } else if args.indexed_fasta {
+        let stderr = io::stderr();
+        let mut handle = stderr.lock();
+        let _ = handle.write_all(b"the option `--indexed-fasta` is specified, read the input file as a indexed fasta backed index database files.\n");
+        seq_index_db.load_from_indexed_fasta(args.pgr_db_prefix.clone())?;
     } else if args.fastx_file {
         let stderr = io::stderr();
         let mut handle = stderr.lock();
@@ -117,7 +586,7 @@ fn main() -> Result<(), std::io::Error> {
             b"the option `--fastx_file` is specified, read the input file as a fastx file.\n",
         );
         let _ = seq_index_db.load_from_fastx(
-            args.pgr_db_prefix,
+            args.pgr_db_prefix.clone(),
             args.w,
             args.k,
             args.r,
@@ -130,313 +599,608 @@ fn main() -> Result<(), std::io::Error> {
             let stderr = io::stderr();
             let mut handle = stderr.lock();
             let _ = handle.write_all(b"Read the input as a AGC backed index database files.\n");
-            let _ = seq_index_db.load_from_agc_index(args.pgr_db_prefix);
+            seq_index_db.load_from_agc_index(args.pgr_db_prefix.clone())?;
         }
 
         #[cfg(not(feature = "with_agc"))]
         panic!("This command is compiled with only frg file support, please specify `--frg-file");
     }
-    let prefix = Path::new(&args.output_prefix);
 
-    query_seqs
-        .into_par_iter()
-        .enumerate()
-        .for_each(|(idx, seq_rec)| {
-            let q_name = String::from_utf8_lossy(&seq_rec.id);
-            let query_seq = seq_rec.seq;
-            let q_len = query_seq.len();
-
-            let query_results = if !args.fastx_file {
-                seq_index_db.query_fragment_to_hps_from_mmap_file(
-                    &query_seq,
-                    args.gap_penalty_factor,
-                    Some(args.max_count),
-                    Some(args.max_query_count),
-                    Some(args.max_target_count),
-                    Some(args.max_aln_chain_span),
-                    None,
-                    false,
-                )
-            } else {
-                seq_index_db.query_fragment_to_hps(
-                    &query_seq,
-                    args.gap_penalty_factor,
-                    Some(args.max_count),
-                    Some(args.max_query_count),
-                    Some(args.max_target_count),
-                    Some(args.max_aln_chain_span),
-                    None,
-                    false,
-                )
-            };
+    if args.check {
+        let stderr = io::stderr();
+        let mut handle = stderr.lock();
+        let mut sids = seq_index_db
+            .seq_info
+            .as_ref()
+            .map(|seq_info| seq_info.keys().copied().collect::<Vec<u32>>())
+            .unwrap_or_default();
+        sids.sort();
+        let unresolved = sids
+            .iter()
+            .filter_map(|sid| seq_index_db.check_seq_by_id(*sid).err())
+            .collect::<Vec<_>>();
+        unresolved.iter().for_each(|e| {
+            let _ = writeln!(handle, "check: {}", e);
+        });
+        let _ = writeln!(
+            handle,
+            "check: {} of {} sequences can't be resolved in the sequence store",
+            unresolved.len(),
+            sids.len()
+        );
+        if !unresolved.is_empty() && !args.skip_unresolved {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "inconsistent database, use `--skip-unresolved` to skip the hits to the unresolved sequences",
+            ));
+        }
+    }
 
-            if let Some(qr) = query_results {
-                let mut sid_to_alns = FxHashMap::default();
-                qr.into_iter().for_each(|(sid, alns)| {
-                    let mut aln_lens = vec![];
-                    let mut f_count = 0_usize;
-                    let mut r_count = 0_usize;
-                    alns.into_iter().for_each(|(_score, aln)| {
-                        if aln.len() > 2 {
-                            aln_lens.push(aln.len());
-                            for hp in &aln {
-                                if hp.0 .2 == hp.1 .2 {
-                                    f_count += 1;
-                                } else {
-                                    r_count += 1;
-                                }
+    if args.query_dir {
+        std::fs::create_dir_all(&args.output_prefix)?;
+    }
+    // the sequence ids of the selected sources, the sources are matched with the names or the file stems
+    let selected_sids = if args.include_sources.is_some() || args.exclude_sources.is_some() {
+        let include_sources = args
+            .include_sources
+            .as_deref()
+            .map(load_sources)
+            .transpose()?;
+        let exclude_sources = args
+            .exclude_sources
+            .as_deref()
+            .map(load_sources)
+            .transpose()?;
+        let in_sources = |sources: &FxHashSet<String>, src: &str| {
+            let base = Path::new(src)
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy();
+            sources.contains(src) || sources.contains(base.as_ref())
+        };
+        let selected_sids = seq_index_db
+            .seq_info
+            .as_ref()
+            .unwrap()
+            .iter()
+            .filter(|(_, (_, src, _))| {
+                let src = src.as_deref().unwrap_or("N/A");
+                let included = match include_sources.as_ref() {
+                    Some(sources) => in_sources(sources, src),
+                    None => true,
+                };
+                let excluded = match exclude_sources.as_ref() {
+                    Some(sources) => in_sources(sources, src),
+                    None => false,
+                };
+                included && !excluded
+            })
+            .map(|(sid, _)| *sid)
+            .collect::<FxHashSet<u32>>();
+        let stderr = io::stderr();
+        let mut handle = stderr.lock();
+        let _ = writeln!(
+            handle,
+            "{} of {} sequences selected by the sources",
+            selected_sids.len(),
+            seq_index_db.seq_info.as_ref().unwrap().len()
+        );
+        Some(selected_sids)
+    } else {
+        None
+    };
+
+    let format = if args.bed_summary {
+        OptFormat::Bed
+    } else {
+        args.format
+    };
+
+    let summary_path = if args.query_dir {
+        Path::new(&args.output_prefix).join("summary.tsv")
+    } else {
+        Path::new(&args.output_prefix).with_extension("summary.tsv")
+    };
+    let mut summary_file = BufWriter::new(File::create(summary_path)?);
+    writeln!(
+        summary_file,
+        "#{}",
+        [
+            "q_idx",
+            "q_name",
+            "q_len",
+            "n_hits",
+            "best_src",
+            "best_ctg",
+            "best_ctg_bgn",
+            "best_ctg_end",
+            "best_orientation",
+            "best_q_bgn",
+            "best_q_end",
+            "best_aln_anchor_count",
+            "best_score",
+        ]
+        .join("\t")
+    )?;
+
+    let gap_cost = if args.long_gap_break_len == 0 {
+        aln::ChainGapCost::Linear(args.gap_penalty_factor)
+    } else {
+        aln::ChainGapCost::Piecewise {
+            penalty: args.gap_penalty_factor,
+            break_len: args.long_gap_break_len,
+            long_penalty: args.long_gap_penalty_factor,
+        }
+    };
+
+    // (q_idx, q_name, q_len, number of the hit regions, the best hit region)
+    let process_query = |(idx, seq_rec): (usize, SeqRec)| {
+        let q_name = String::from_utf8_lossy(&seq_rec.id);
+        let query_seq = seq_rec.seq;
+        let q_len = query_seq.len();
+
+        let query_results = if !args.fastx_file {
+            seq_index_db.query_fragment_to_hps_from_mmap_file(
+                &query_seq,
+                gap_cost,
+                Some(args.max_count),
+                Some(args.max_query_count),
+                Some(args.max_target_count),
+                Some(args.max_aln_chain_span),
+                None,
+                false,
+            )
+        } else {
+            seq_index_db.query_fragment_to_hps(
+                &query_seq,
+                gap_cost,
+                Some(args.max_count),
+                Some(args.max_query_count),
+                Some(args.max_target_count),
+                Some(args.max_aln_chain_span),
+                None,
+                false,
+            )
+        };
+
+        let mut n_hits = 0_usize;
+        let mut best_hit = None;
+        let mut target_fasta = Vec::<(String, Vec<u8>)>::new();
+        let query_results = match (query_results, selected_sids.as_ref()) {
+            (Some(qr), Some(selected_sids)) => Some(
+                qr.into_iter()
+                    .filter(|(sid, _)| selected_sids.contains(sid))
+                    .collect::<Vec<_>>(),
+            ),
+            (query_results, _) => query_results,
+        };
+
+        if let Some(qr) = query_results {
+            let mut sid_to_alns = FxHashMap::default();
+            qr.into_iter().for_each(|(sid, alns)| {
+                let mut aln_lens = vec![];
+                let mut f_count = 0_usize;
+                let mut r_count = 0_usize;
+                alns.into_iter().for_each(|(score, aln)| {
+                    if aln.len() >= args.min_anchor_count.max(3) && score >= args.min_score {
+                        aln_lens.push(aln.len());
+                        for hp in &aln {
+                            if hp.0 .2 == hp.1 .2 {
+                                f_count += 1;
+                            } else {
+                                r_count += 1;
                             }
-                            let orientation = if f_count > r_count { 0_u32 } else { 1_u32 };
-                            let e = sid_to_alns.entry(sid).or_insert_with(Vec::new);
-                            e.push((aln, orientation))
                         }
-                    })
-                });
+                        let orientation = if f_count > r_count { 0_u32 } else { 1_u32 };
+                        let e = sid_to_alns.entry(sid).or_insert_with(Vec::new);
+                        e.push((aln, orientation, score))
+                    }
+                })
+            });
 
-                let mut aln_range = FxHashMap::default();
-                sid_to_alns.into_iter().for_each(|(sid, alns)| {
-                    alns.into_iter().for_each(|(aln, orientation)| {
-                        let mut target_coordinates = aln
-                            .iter()
-                            .map(|v| (v.1 .0, v.1 .1))
-                            .collect::<Vec<(u32, u32)>>();
-                        target_coordinates.sort();
-                        let bgn = target_coordinates[0].0;
-                        let end = target_coordinates[target_coordinates.len() - 1].1;
-                        let e = aln_range.entry(sid).or_insert_with(Vec::new);
-                        e.push((bgn, end, end - bgn, orientation, aln));
-                    })
-                });
+            let mut aln_range = FxHashMap::default();
+            sid_to_alns.into_iter().for_each(|(sid, alns)| {
+                alns.into_iter().for_each(|(aln, orientation, score)| {
+                    let mut target_coordinates = aln
+                        .iter()
+                        .map(|v| (v.1 .0, v.1 .1))
+                        .collect::<Vec<(u32, u32)>>();
+                    target_coordinates.sort();
+                    let bgn = target_coordinates[0].0;
+                    let end = target_coordinates[target_coordinates.len() - 1].1;
+                    let e = aln_range.entry(sid).or_insert_with(Vec::new);
+                    e.push((bgn, end, end - bgn, orientation, aln, score));
+                })
+            });
 
-                // TODO: merge aln_range
-                let aln_range = aln_range
-                    .into_iter()
-                    .map(|(sid, rgns)| {
-                        let mut f_rgns = rgns
-                            .iter()
-                            .filter(|&v| v.3 == 0)
-                            .cloned()
-                            .collect::<Vec<_>>();
-
-                        let mut r_rgns = rgns
-                            .iter()
-                            .filter(|&v| v.3 == 1)
-                            .cloned()
-                            .collect::<Vec<_>>();
-
-                        f_rgns.sort();
-                        r_rgns.sort();
-
-                        let mut out_rgns = vec![];
-                        let mut last_rgn: (u32, u32, u32, u32, Vec<_>) = (0, 0, 0, 0, vec![]);
-                        f_rgns.into_iter().for_each(|r| {
-                            if last_rgn.4.is_empty() {
-                                last_rgn = r;
+            // TODO: merge aln_range
+            let aln_range = aln_range
+                .into_iter()
+                .map(|(sid, rgns)| {
+                    let mut f_rgns = rgns
+                        .iter()
+                        .filter(|&v| v.3 == 0)
+                        .cloned()
+                        .collect::<Vec<_>>();
+
+                    let mut r_rgns = rgns
+                        .iter()
+                        .filter(|&v| v.3 == 1)
+                        .cloned()
+                        .collect::<Vec<_>>();
+
+                    f_rgns.sort();
+                    r_rgns.sort();
+
+                    let mut out_rgns = vec![];
+                    let mut last_rgn: (u32, u32, u32, u32, Vec<_>, f32) = (0, 0, 0, 0, vec![], 0.0);
+                    f_rgns.into_iter().for_each(|r| {
+                        if last_rgn.4.is_empty() {
+                            last_rgn = r;
+                        } else {
+                            let l_bgn = last_rgn.0;
+                            let l_end = last_rgn.1;
+                            assert!(l_end > l_bgn);
+                            let r_bgn = r.0;
+                            let r_end = r.1;
+                            if (r_bgn as i64) - (l_end as i64) < args.merge_range_tol as i64 {
+                                let bgn = l_bgn;
+                                let end = if r_end > l_end { r_end } else { l_end };
+                                let len = end - bgn;
+                                let orientation = last_rgn.3;
+                                let mut aln = last_rgn.4.clone();
+                                aln.extend(r.4);
+                                let score = last_rgn.5 + r.5;
+                                last_rgn = (bgn, end, len, orientation, aln, score);
                             } else {
-                                let l_bgn = last_rgn.0;
-                                let l_end = last_rgn.1;
-                                assert!(l_end > l_bgn);
-                                let r_bgn = r.0;
-                                let r_end = r.1;
-                                if (r_bgn as i64) - (l_end as i64) < args.merge_range_tol as i64 {
-                                    let bgn = l_bgn;
-                                    let end = if r_end > l_end { r_end } else { l_end };
-                                    let len = end - bgn;
-                                    let orientation = last_rgn.3;
-                                    let mut aln = last_rgn.4.clone();
-                                    aln.extend(r.4);
-                                    last_rgn = (bgn, end, len, orientation, aln);
-                                } else {
-                                    out_rgns.push(last_rgn.clone());
-                                    last_rgn = r;
-                                }
+                                out_rgns.push(last_rgn.clone());
+                                last_rgn = r;
                             }
-                        });
-                        if last_rgn.2 > 0 {
-                            //not empty
-                            out_rgns.push(last_rgn);
-                        };
+                        }
+                    });
+                    if last_rgn.2 > 0 {
+                        //not empty
+                        out_rgns.push(last_rgn);
+                    };
 
-                        let mut last_rgn: (u32, u32, u32, u32, Vec<_>) = (0, 0, 0, 0, vec![]);
-                        r_rgns.into_iter().for_each(|r| {
-                            if last_rgn.4.is_empty() {
-                                last_rgn = r;
+                    let mut last_rgn: (u32, u32, u32, u32, Vec<_>, f32) = (0, 0, 0, 0, vec![], 0.0);
+                    r_rgns.into_iter().for_each(|r| {
+                        if last_rgn.4.is_empty() {
+                            last_rgn = r;
+                        } else {
+                            let l_bgn = last_rgn.0;
+                            let l_end = last_rgn.1;
+                            assert!(l_end > l_bgn);
+                            let r_bgn = r.0;
+                            let r_end = r.1;
+                            if (r_bgn as i64) - (l_end as i64) < args.merge_range_tol as i64 {
+                                let bgn = l_bgn;
+                                let end = if r_end > l_end { r_end } else { l_end };
+                                let len = end - bgn;
+                                let orientation = last_rgn.3;
+                                let mut aln = last_rgn.4.clone();
+                                aln.extend(r.4);
+                                let score = last_rgn.5 + r.5;
+                                last_rgn = (bgn, end, len, orientation, aln, score);
                             } else {
-                                let l_bgn = last_rgn.0;
-                                let l_end = last_rgn.1;
-                                assert!(l_end > l_bgn);
-                                let r_bgn = r.0;
-                                let r_end = r.1;
-                                if (r_bgn as i64) - (l_end as i64) < args.merge_range_tol as i64 {
-                                    let bgn = l_bgn;
-                                    let end = if r_end > l_end { r_end } else { l_end };
-                                    let len = end - bgn;
-                                    let orientation = last_rgn.3;
-                                    let mut aln = last_rgn.4.clone();
-                                    aln.extend(r.4);
-                                    last_rgn = (bgn, end, len, orientation, aln);
-                                } else {
-                                    out_rgns.push(last_rgn.clone());
-                                    last_rgn = r;
-                                }
+                                out_rgns.push(last_rgn.clone());
+                                last_rgn = r;
                             }
+                        }
+                    });
+                    if last_rgn.2 > 0 {
+                        //not empty
+                        out_rgns.push(last_rgn);
+                    };
+
+                    (sid, out_rgns)
+                })
+                .collect::<FxHashMap<_, _>>();
+
+            let mut fasta_out = None;
+            if !args.only_summary {
+                let fasta_buf = BufWriter::new(
+                    File::create(query_output_path(&args, idx, &q_name, "fa")).unwrap(),
+                );
+                fasta_out = Some(FastxWriter::new(fasta_buf, args.fasta_line_width));
+            };
+
+            let mut hit_records = Vec::<HitRecord>::new();
+            aln_range.into_iter().for_each(|(sid, rgns)| {
+                if args.skip_unresolved {
+                    if let Err(e) = seq_index_db.check_seq_by_id(sid) {
+                        let stderr = io::stderr();
+                        let mut handle = stderr.lock();
+                        let _ = writeln!(
+                            handle,
+                            "skip {} hit(s) of the query {}: {}",
+                            rgns.len(),
+                            q_name,
+                            e
+                        );
+                        return;
+                    }
+                }
+                let (ctg, src, ctg_len) =
+                    seq_index_db.seq_info.as_ref().unwrap().get(&sid).unwrap();
+                let src = (*src).as_ref().unwrap_or(&"N/A".to_string()).clone();
+                rgns.into_iter()
+                    .for_each(|(b, e, _, orientation, mut aln, score)| {
+                        aln.sort();
+                        let q_bgn = aln[0].0 .0;
+                        let q_end = aln[aln.len() - 1].0 .1;
+                        let base = Path::new(&src).file_stem().unwrap().to_string_lossy();
+                        let target_seq_name =
+                            format!("{}::{}_{}_{}_{}", base, ctg, b, e, orientation);
+                        hit_records.push(HitRecord {
+                            q_idx: idx,
+                            q_name: q_name.to_string(),
+                            q_bgn,
+                            q_end,
+                            q_len,
+                            sid,
+                            src: src.clone(),
+                            ctg: ctg.clone(),
+                            ctg_len: *ctg_len,
+                            t_bgn: b,
+                            t_end: e,
+                            orientation,
+                            hit_count: aln.len(),
+                            score,
+                            target_mapq: 0,
+                            region_mapq: 0,
+                            label: aln::ChainLabel::Primary,
+                            target_seq_name,
+                            n_matches: None,
+                            aln_block_len: None,
+                            identity: None,
+                            cigar: None,
                         });
-                        if last_rgn.2 > 0 {
-                            //not empty
-                            out_rgns.push(last_rgn);
+                    });
+            });
+            hit_records.retain(|r| r.t_end - r.t_bgn >= args.min_target_span);
+            let mapqs = aln::hit_chain_mapqs(
+                &hit_records
+                    .iter()
+                    .map(|r| (r.sid, r.score, r.q_bgn, r.q_end))
+                    .collect::<Vec<_>>(),
+            );
+            hit_records
+                .iter_mut()
+                .zip(mapqs)
+                .for_each(|(r, (target_mapq, region_mapq))| {
+                    r.target_mapq = target_mapq;
+                    r.region_mapq = region_mapq;
+                });
+            let mut labels = aln::label_hit_chains(
+                &hit_records
+                    .iter()
+                    .map(|r| (r.score, r.q_bgn, r.q_end))
+                    .collect::<Vec<_>>(),
+                args.max_secondary,
+            )
+            .into_iter();
+            hit_records.retain_mut(|r| {
+                if let Some(label) = labels.next().unwrap() {
+                    r.label = label;
+                    true
+                } else {
+                    false
+                }
+            });
+            if args.refine {
+                hit_records.par_iter_mut().for_each(|r| {
+                    refine_hit(
+                        &seq_index_db,
+                        &query_seq,
+                        r,
+                        args.refine_padding,
+                        args.refine_max_distance,
+                    )
+                });
+            }
+
+            let hit_file_ext = match format {
+                OptFormat::Hit => "hit",
+                OptFormat::Bed => "hit.bed",
+                OptFormat::Paf => "hit.paf",
+                OptFormat::Json => "hit.json",
+            };
+            let mut hit_file = BufWriter::new(
+                File::create(query_output_path(&args, idx, &q_name, hit_file_ext)).unwrap(),
+            );
+            write_hit_records(&mut hit_file, &hit_records, format, args.refine)
+                .expect("writing hit summary fail\n");
+            n_hits = hit_records.len();
+            best_hit = hit_records
+                .iter()
+                .max_by(|r0, r1| r0.score.total_cmp(&r1.score))
+                .cloned();
+            if args.emit_target_fasta {
+                target_fasta = hit_records
+                    .par_iter()
+                    .filter_map(|r| {
+                        let bgn = r.t_bgn.saturating_sub(args.target_padding);
+                        let end = (r.t_end + args.target_padding).min(r.ctg_len);
+                        let base = Path::new(&r.src).file_stem().unwrap().to_string_lossy();
+                        let header = format!(
+                            "{}::{}:{}-{}:{} query={}:{}-{} hit={}-{} anchors={} score={}",
+                            base,
+                            r.ctg,
+                            bgn,
+                            end,
+                            if r.orientation == 0 { '+' } else { '-' },
+                            r.q_name,
+                            r.q_bgn,
+                            r.q_end,
+                            r.t_bgn,
+                            r.t_end,
+                            r.hit_count,
+                            r.score
+                        );
+                        let target_seq =
+                            match seq_index_db.get_sub_seq_by_id(r.sid, bgn as usize, end as usize)
+                            {
+                                Ok(target_seq) => target_seq,
+                                Err(err) if args.skip_unresolved => {
+                                    let stderr = io::stderr();
+                                    let mut handle = stderr.lock();
+                                    let _ = writeln!(
+                                        handle,
+                                        "skip the target sequence {}: {}",
+                                        header, err
+                                    );
+                                    return None;
+                                }
+                                Err(err) => {
+                                    panic!("fail to fetch the target sequence {}: {}", header, err)
+                                }
+                            };
+                        let target_seq = if r.orientation == 1 {
+                            pgr_db::fasta_io::reverse_complement(&target_seq)
+                        } else {
+                            target_seq
                         };
+                        Some((header, target_seq))
+                    })
+                    .collect::<Vec<_>>();
+            }
 
-                        (sid, out_rgns)
+            let sub_seq_range_for_fasta = hit_records
+                .into_iter()
+                .map(|r| (r.sid, r.t_bgn, r.t_end, r.orientation, r.target_seq_name))
+                .collect::<Vec<(u32, u32, u32, u32, String)>>();
+            if let Some(fasta_out) = fasta_out.as_mut() {
+                sub_seq_range_for_fasta
+                    .par_iter()
+                    .filter_map(|(sid, b, e, orientation, target_seq_name)| {
+                        let target_seq =
+                            match seq_index_db.get_sub_seq_by_id(*sid, *b as usize, *e as usize) {
+                                Ok(target_seq) => target_seq,
+                                Err(err) if args.skip_unresolved => {
+                                    let stderr = io::stderr();
+                                    let mut handle = stderr.lock();
+                                    let _ = writeln!(
+                                        handle,
+                                        "skip the sequence {} of the query {}: {}",
+                                        target_seq_name, q_name, err
+                                    );
+                                    return None;
+                                }
+                                Err(err) => panic!(
+                                    "fail to fetch the sequence {}: {}",
+                                    target_seq_name, err
+                                ),
+                            };
+                        let target_seq = if *orientation == 1 {
+                            pgr_db::fasta_io::reverse_complement(&target_seq)
+                        } else {
+                            target_seq
+                        };
+                        Some((target_seq_name.into(), target_seq))
                     })
-                    .collect::<FxHashMap<_, _>>();
-
-                let mut fasta_out = None;
-                let fasta_buf: BufWriter<File>;
-                if !args.only_summary {
-                    let ext = format!("{:03}.fa", idx);
-                    fasta_buf = BufWriter::new(File::create(prefix.with_extension(ext)).unwrap());
-                    fasta_out = Some(fasta_buf);
-                };
+                    .collect::<Vec<(String, Vec<u8>)>>()
+                    .into_iter()
+                    .for_each(|(target_seq_name, target_seq)| {
+                        fasta_out
+                            .write_fasta(&target_seq_name, &target_seq)
+                            .expect("can't write the query output fasta file\n");
+                    });
+            };
+        };
+        (
+            (idx, q_name.to_string(), q_len, n_hits, best_hit),
+            target_fasta,
+        )
+    };
 
-                let mut sub_seq_range_for_fasta = Vec::<(u32, u32, u32, u32, String)>::new();
-                let mut hit_file = if args.bed_summary {
-                    BufWriter::new(
-                        File::create(prefix.with_extension(format!("{:03}.hit.bed", idx))).unwrap(),
-                    )
-                } else {
-                    BufWriter::new(
-                        File::create(prefix.with_extension(format!("{:03}.hit", idx))).unwrap(),
-                    )
-                };
-                if args.bed_summary {
+    let mut target_fasta_file = if args.emit_target_fasta {
+        let target_fasta_path = if args.query_dir {
+            Path::new(&args.output_prefix).join("target.fa")
+        } else {
+            Path::new(&args.output_prefix).with_extension("target.fa")
+        };
+        Some(create_fastx_writer(
+            target_fasta_path,
+            FastxCompression::Plain,
+            args.fasta_line_width,
+        )?)
+    } else {
+        None
+    };
+
+    let mut process_batch = |query_seqs: Vec<(usize, SeqRec)>| -> Result<(), std::io::Error> {
+        let (query_summaries, target_fasta): (Vec<_>, Vec<_>) =
+            query_seqs.into_par_iter().map(&process_query).unzip();
+        if let Some(target_fasta_file) = target_fasta_file.as_mut() {
+            target_fasta
+                .into_iter()
+                .flatten()
+                .try_for_each(|(header, target_seq)| {
+                    target_fasta_file.write_fasta(&header, &target_seq)
+                })?;
+            target_fasta_file.flush()?;
+        }
+        query_summaries.into_iter().try_for_each(
+            |(idx, q_name, q_len, n_hits, best_hit)| -> Result<(), std::io::Error> {
+                if let Some(r) = best_hit {
                     writeln!(
-                        hit_file,
-                        "#{}",
-                        [
-                            "target",
-                            "bgn",
-                            "end",
-                            "query",
-                            "color",
-                            "orientation",
-                            "q_len",
-                            "aln_anchor_count",
-                            "q_idx",
-                            "src",
-                            "ctg_bgn",
-                            "ctg_end",
-                        ]
-                        .join("\t")
+                        summary_file,
+                        "{:03}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                        idx,
+                        q_name,
+                        q_len,
+                        n_hits,
+                        r.src,
+                        r.ctg,
+                        r.t_bgn,
+                        r.t_end,
+                        r.orientation,
+                        r.q_bgn,
+                        r.q_end,
+                        r.hit_count,
+                        r.score
                     )
-                    .expect("writing bed summary fail\n");
                 } else {
                     writeln!(
-                        hit_file,
-                        "#{}",
-                        [
-                            "idx",
-                            "q_ctg_name",
-                            "q_ctg_bgn",
-                            "q_ctg_end",
-                            "q_ctg_len",
-                            "aln_anchor_count",
-                            "src",
-                            "ctg",
-                            "ctg_bgn",
-                            "ctg_end",
-                            "orientation",
-                            "ctg_name"
-                        ]
-                        .join("\t")
+                        summary_file,
+                        "{:03}\t{}\t{}\t0\tNA\tNA\tNA\tNA\tNA\tNA\tNA\tNA\tNA",
+                        idx, q_name, q_len
                     )
-                    .expect("writing hit summary fail\n");
-                };
-                aln_range.into_iter().for_each(|(sid, rgns)| {
-                    let (ctg, src, _ctg_len) =
-                        seq_index_db.seq_info.as_ref().unwrap().get(&sid).unwrap();
-                    //let src = *src.unwrap_or("N/A".to_string()).to_string();
-                    let src = (*src).as_ref().unwrap_or(&"N/A".to_string()).clone();
-                    rgns.into_iter()
-                        .for_each(|(b, e, _, orientation, mut aln)| {
-                            aln.sort();
-                            let q_bgn = aln[0].0 .0;
-                            let q_end = aln[aln.len() - 1].0 .1;
-                            let base = Path::new(&src).file_stem().unwrap().to_string_lossy();
-                            let target_seq_name =
-                                format!("{}::{}_{}_{}_{}", base, ctg, b, e, orientation);
-                            #[allow(clippy::write_literal)]
-                            if args.bed_summary {
-                                writeln!(
-                                    hit_file,
-                                    "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
-                                    ctg,
-                                    b,
-                                    e,
-                                    q_name,
-                                    "#AAAAAA",
-                                    orientation,
-                                    q_len,
-                                    aln.len(),
-                                    idx,
-                                    src,
-                                    q_bgn,
-                                    q_end,
-                                    target_seq_name
-                                )
-                                .expect("writing hit summary fail\n");
-                            } else {
-                                writeln!(
-                                    hit_file,
-                                    "{:03}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
-                                    idx,
-                                    q_name,
-                                    q_bgn,
-                                    q_end,
-                                    q_len,
-                                    aln.len(),
-                                    src,
-                                    ctg,
-                                    b,
-                                    e,
-                                    orientation,
-                                    target_seq_name
-                                )
-                                .expect("writing hit summary fail\n");
-                            }
-                            sub_seq_range_for_fasta.push((
-                                sid,
-                                b,
-                                e,
-                                orientation,
-                                target_seq_name.clone(),
-                            ));
-                            //println!("DBG: {}", seq_id);
-                        });
-                });
-                if let Some(fasta_out) = fasta_out.as_mut() {
-                    sub_seq_range_for_fasta
-                        .par_iter()
-                        .map(|(sid, b, e, orientation, target_seq_name)| {
-                            let target_seq = seq_index_db
-                                .get_sub_seq_by_id(*sid, *b as usize, *e as usize)
-                                .unwrap();
-                            let target_seq = if *orientation == 1 {
-                                pgr_db::fasta_io::reverse_complement(&target_seq)
-                            } else {
-                                target_seq
-                            };
-                            (target_seq_name.into(), target_seq)
-                        })
-                        .collect::<Vec<(String, Vec<u8>)>>()
-                        .into_iter()
-                        .for_each(|(target_seq_name, target_seq)| {
-                            writeln!(fasta_out, ">{}", target_seq_name)
-                                .expect("can't write the query output fasta file\n");
-                            writeln!(fasta_out, "{}", String::from_utf8_lossy(&target_seq))
-                                .expect("can't write the query output fasta file\n");
-                        });
-                };
-            };
-        });
+                }
+            },
+        )?;
+        summary_file.flush()
+    };
+
+    // with `--batch-size`, the query file is streamed and the queries are processed batch by batch
+    let mut n_queries = 0_usize;
+    let mut add_seqs =
+        |seq_iter: &mut dyn Iterator<Item = io::Result<SeqRec>>| -> Result<(), std::io::Error> {
+            SeqRecChunks::new(
+                seq_iter.filter(|r| r.is_ok()),
+                args.batch_size,
+                args.batch_max_bases,
+            )
+            .flatten()
+            .try_for_each(|recs| {
+                let query_seqs = recs
+                    .into_iter()
+                    .enumerate()
+                    .map(|(idx, r)| (n_queries + idx, r))
+                    .collect::<Vec<_>>();
+                n_queries += query_seqs.len();
+                process_batch(query_seqs)
+            })
+        };
+
+    match get_fastx_reader(args.query_fastx_path.clone(), true)? {
+        #[allow(clippy::useless_conversion)] // the into_iter() is necessary for dyn patching
+        GZFastaReader::GZFile(reader) => add_seqs(&mut reader.into_iter())?,
+
+        #[allow(clippy::useless_conversion)] // the into_iter() is necessary for dyn patching
+        GZFastaReader::RegularFile(reader) => add_seqs(&mut reader.into_iter())?,
+    };
+
     Ok(())
 }
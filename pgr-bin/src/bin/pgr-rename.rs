@@ -0,0 +1,194 @@
+const VERSION_STRING: &str = env!("VERSION_STRING");
+use clap::{self, CommandFactory, Parser};
+use pgr_db::frag_file_io::CompactSeqFragFileStorage;
+use pgr_db::seq_db;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Rename the sequences in a PGR-TK database and/or subset the database by the contig lengths or a contig list
+///
+/// The sequence ids are kept, so the hits and the coordinates reported with the original database stay valid.
+/// The contigs are kept or dropped as a whole, they are not cut into intervals.
+/// The sequence archive (.agc or .frg) is linked to the output prefix, the .midx, .mdb (and .sdx) files are rewritten.
+#[derive(Parser, Debug)]
+#[clap(name = "pgr-rename")]
+#[clap(author, version)]
+#[clap(about, long_about = None)]
+struct CmdOptions {
+    /// the prefix to the input PGR-TK sequence database
+    pgr_db_prefix: String,
+    /// the prefix of the output database
+    output_prefix: String,
+
+    /// using the frg format for the sequence database (default to the AGC backend database if not specified)
+    #[clap(long, default_value_t = false)]
+    frg_file: bool,
+
+    /// a tab separated file with four columns: source, contig, new source, new contig (only for the frg backend)
+    #[clap(long, default_value = None)]
+    rename_file: Option<String>,
+
+    /// drop the contigs shorter than this length
+    #[clap(long, default_value_t = 0)]
+    min_length: u32,
+
+    /// keep only the whole contigs in the first column of the file (e.g., a list of the names or a bed file, the
+    /// intervals are ignored), specified as a contig name or "SOURCE::CONTIG"
+    #[clap(long, default_value = None)]
+    contig_list: Option<String>,
+}
+
+// the error of a malformed line of an input file, the line number is 1-based
+fn parse_error(file_kind: &str, line_idx: usize, line: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "{} parsing error on the line {}: {}",
+            file_kind,
+            line_idx + 1,
+            line
+        ),
+    )
+}
+
+fn main() -> Result<(), std::io::Error> {
+    CmdOptions::command().version(VERSION_STRING).get_matches();
+    let args = CmdOptions::parse();
+
+    let mut frg_db = if args.frg_file {
+        Some(CompactSeqFragFileStorage::new(args.pgr_db_prefix.clone()))
+    } else {
+        None
+    };
+
+    // sid -> (len, ctg, source)
+    let mut seqs = FxHashMap::<u32, (u32, String, String)>::default();
+    let midx_file = BufReader::new(File::open(args.pgr_db_prefix.clone() + ".midx")?);
+    for (line_idx, line) in midx_file.lines().enumerate() {
+        let line = line?;
+        let fields = line.split('\t').collect::<Vec<&str>>();
+        if fields.len() < 4 {
+            return Err(parse_error("midx file", line_idx, &line));
+        }
+        let sid = fields[0]
+            .parse::<u32>()
+            .map_err(|_| parse_error("midx file", line_idx, &line))?;
+        let len = fields[1]
+            .parse::<u32>()
+            .map_err(|_| parse_error("midx file", line_idx, &line))?;
+        seqs.insert(sid, (len, fields[2].to_string(), fields[3].to_string()));
+    }
+
+    let mut keeps = seqs
+        .iter()
+        .filter(|(_, (len, _, _))| *len >= args.min_length)
+        .map(|(sid, _)| *sid)
+        .collect::<FxHashSet<u32>>();
+
+    if let Some(contig_list) = args.contig_list {
+        let contig_list = BufReader::new(File::open(Path::new(&contig_list))?);
+        let mut names = FxHashSet::<String>::default();
+        for (line_idx, line) in contig_list.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let name = line.split('\t').next().unwrap_or_default().trim();
+            if name.is_empty() {
+                return Err(parse_error("contig list file", line_idx, &line));
+            }
+            names.insert(name.to_string());
+        }
+        keeps.retain(|sid| {
+            let (_, ctg, src) = seqs.get(sid).unwrap();
+            names.contains(ctg) || names.contains(&format!("{}::{}", src, ctg))
+        });
+    }
+
+    if let Some(rename_file) = args.rename_file {
+        if frg_db.is_none() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "renaming is only supported for the frg backend, the AGC archive is accessed with the original names",
+            ));
+        }
+        let rename_file = BufReader::new(File::open(Path::new(&rename_file))?);
+        let mut new_names = FxHashMap::<(String, String), (String, String)>::default();
+        for (line_idx, line) in rename_file.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields = line.split('\t').collect::<Vec<&str>>();
+            if fields.len() < 4 {
+                return Err(parse_error("rename file", line_idx, &line));
+            }
+            new_names.insert(
+                (fields[0].to_string(), fields[1].to_string()),
+                (fields[2].to_string(), fields[3].to_string()),
+            );
+        }
+        seqs.values_mut().for_each(|(_, ctg, src)| {
+            if let Some((new_src, new_ctg)) = new_names.get(&(src.clone(), ctg.clone())) {
+                *src = new_src.clone();
+                *ctg = new_ctg.clone();
+            }
+        });
+        frg_db.as_mut().unwrap().seqs.iter_mut().for_each(|s| {
+            if let Some((_, ctg, src)) = seqs.get(&s.id) {
+                s.name = ctg.clone();
+                s.source = Some(src.clone());
+            }
+        });
+    }
+
+    // the same name can't point to two sequences after renaming
+    let mut seen = FxHashSet::<(&String, &String)>::default();
+    for sid in keeps.iter() {
+        let (_, ctg, src) = seqs.get(sid).unwrap();
+        if !seen.insert((src, ctg)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("duplicated sequence name after renaming: {}::{}", src, ctg),
+            ));
+        }
+    }
+
+    let mut sids = keeps.iter().copied().collect::<Vec<u32>>();
+    sids.sort();
+    let mut midx_file = BufWriter::new(File::create(args.output_prefix.clone() + ".midx")?);
+    sids.iter()
+        .try_for_each(|sid| -> Result<(), std::io::Error> {
+            let (len, ctg, src) = seqs.get(sid).unwrap();
+            writeln!(midx_file, "{}\t{}\t{}\t{}", sid, len, ctg, src)
+        })?;
+
+    let (shmmr_spec, mut frag_map) =
+        seq_db::read_mdb_file_parallel(args.pgr_db_prefix.clone() + ".mdb")?;
+    if keeps.len() < seqs.len() {
+        frag_map.values_mut().for_each(|frags| {
+            frags.retain(|v| keeps.contains(&v.1));
+        });
+        frag_map.retain(|_, frags| !frags.is_empty());
+    }
+    seq_db::write_shmmr_map_file(&shmmr_spec, &frag_map, args.output_prefix.clone() + ".mdb")?;
+
+    let stderr = io::stderr();
+    let mut handle = stderr.lock();
+    let _ = writeln!(handle, "{} of {} sequences kept", keeps.len(), seqs.len());
+
+    let data_file_ext = if let Some(frg_db) = frg_db {
+        frg_db.write_sdx_file(args.output_prefix.clone())?;
+        ".frg"
+    } else {
+        ".agc"
+    };
+    std::os::unix::fs::symlink(
+        std::fs::canonicalize(args.pgr_db_prefix + data_file_ext)?,
+        args.output_prefix + data_file_ext,
+    )?;
+
+    Ok(())
+}
@@ -6,7 +6,7 @@ use std::fs::File;
 use std::io::{self, BufReader, BufWriter, Read, Write};
 
 use flate2::bufread::MultiGzDecoder;
-use pgr_db::fasta_io::{FastaReader, SeqRec};
+use pgr_db::fasta_io::{FastaReader, SeqRec, SeqRecChunks};
 #[allow(clippy::large_enum_variant)]
 enum GZFastaReader {
     GZFile(FastaReader<BufReader<MultiGzDecoder<BufReader<File>>>>),
@@ -229,18 +229,9 @@ fn main() -> Result<(), std::io::Error> {
     };
 
     let mut count_read_seq_shmmrs = |seq_iter: &mut dyn Iterator<Item = io::Result<SeqRec>>| {
-        let mut read_seqs: Vec<SeqRec> = vec![];
-        seq_iter.into_iter().for_each(|r| {
-            if let Ok(r) = r {
-                read_seqs.push(r);
-                if read_seqs.len() >= 128 {
-                    count_seq_group_shmmrs(&read_seqs);
-                    read_seqs.clear();
-                }
-            };
-        });
-        count_seq_group_shmmrs(&read_seqs);
-        read_seqs.clear();
+        SeqRecChunks::new(seq_iter.filter(|r| r.is_ok()), 128, 0)
+            .flatten()
+            .for_each(|read_seqs| count_seq_group_shmmrs(&read_seqs));
     };
 
     match get_fastx_reader(args.read_fastx)? {
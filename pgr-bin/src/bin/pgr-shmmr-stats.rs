@@ -0,0 +1,145 @@
+const VERSION_STRING: &str = env!("VERSION_STRING");
+use clap::{self, CommandFactory, Parser};
+use pgr_db::ext::{get_fastx_reader, GZFastaReader};
+use pgr_db::fasta_io::SeqRec;
+use pgr_db::shmmrutils::{
+    seed_mask_from_str, sequence_to_shmmrs, sketch_stats_from_shmmrs, tie_break_from_str, ShmmrSpec,
+};
+use rayon::prelude::*;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+/// Report the shimmer (anchor) count and spacing statistics of the input sequences,
+/// for checking why some regions get too few anchors with a set of shimmer parameters
+#[derive(Parser, Debug)]
+#[clap(name = "pgr-shmmr-stats")]
+#[clap(author, version)]
+#[clap(about, long_about = None)]
+struct CmdOptions {
+    /// the path to the fasta file of the sequences
+    fastx_path: String,
+    /// the output tsv file path
+    output_path: String,
+
+    /// minimizer window size
+    #[clap(long, short, default_value_t = 80)]
+    w: u32,
+    /// minimizer k-mer size, up to 256 (the k-mers longer than 56 are only hashed, no spaced seeds)
+    #[clap(long, short, default_value_t = 56)]
+    k: u32,
+    /// sparse minimizer (shimmer) reduction factor
+    #[clap(long, short, default_value_t = 4)]
+    r: u32,
+    /// min span for neighboring minimizers
+    #[clap(long, short, default_value_t = 64)]
+    min_span: u32,
+    /// using sketch k-mer than minimizer
+    #[clap(short, long)]
+    sketch: bool,
+    /// using order-2 randstrobes as the anchors
+    #[clap(long)]
+    randstrobe: bool,
+    /// spaced seed pattern for the k-mers, "0" for the don't-care positions
+    #[clap(long)]
+    seed_mask: Option<String>,
+    /// the seed of the k-mer hashes, for the decorrelated replicate sketches
+    #[clap(long)]
+    hash_seed: Option<u64>,
+    /// the policy selecting the minimizers with the same hash in a window: "all", "lexicographic", "position"
+    /// or "random", keeping all of them gives the same anchors from both strands in the low-complexity sequences
+    #[clap(long, default_value = "all")]
+    tie_break: String,
+    /// the seed of the "random" tie-breaking policy
+    #[clap(long, default_value_t = 0)]
+    tie_break_seed: u64,
+
+    /// report the statistics of the windows of this size along the sequences, "0" for the whole sequences
+    #[clap(long, default_value_t = 0)]
+    window_size: u32,
+
+    /// number of threads used in parallel (more memory usage), default to "0" using all CPUs available or the number set by RAYON_NUM_THREADS
+    #[clap(long, default_value_t = 0)]
+    number_of_thread: usize,
+}
+
+fn main() -> Result<(), std::io::Error> {
+    CmdOptions::command().version(VERSION_STRING).get_matches();
+    let args = CmdOptions::parse();
+
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(args.number_of_thread)
+        .build_global()
+        .unwrap();
+
+    let shmmr_spec = ShmmrSpec {
+        randstrobe: args.randstrobe,
+        seed_mask: args.seed_mask.as_ref().map(|pattern| {
+            seed_mask_from_str(pattern, args.k).expect("fail to parse the spaced seed pattern")
+        }),
+        hash_seed: args.hash_seed,
+        tie_break: tie_break_from_str(&args.tie_break, args.tie_break_seed)
+            .expect("fail to parse the tie-breaking policy"),
+        ..ShmmrSpec::new(args.w, args.k, args.r, args.min_span, args.sketch)
+    };
+
+    let mut seqs: Vec<SeqRec> = vec![];
+    let mut add_seqs = |seq_iter: &mut dyn Iterator<Item = io::Result<SeqRec>>| {
+        seq_iter.into_iter().for_each(|r| {
+            if let Ok(r) = r {
+                seqs.push(r);
+            };
+        });
+    };
+
+    match get_fastx_reader(args.fastx_path, true)? {
+        #[allow(clippy::useless_conversion)] // the into_iter() is necessary for dyn patching
+        GZFastaReader::GZFile(reader) => add_seqs(&mut reader.into_iter()),
+
+        #[allow(clippy::useless_conversion)] // the into_iter() is necessary for dyn patching
+        GZFastaReader::RegularFile(reader) => add_seqs(&mut reader.into_iter()),
+    };
+
+    let stats_lines = seqs
+        .par_iter()
+        .map(|rec| {
+            let seq_name = String::from_utf8_lossy(&rec.id);
+            let seq_len = rec.seq.len() as u32;
+            let shmmrs = sequence_to_shmmrs(0, &rec.seq, &shmmr_spec, false);
+            let window_size = if args.window_size == 0 {
+                seq_len.max(1)
+            } else {
+                args.window_size
+            };
+            (0..seq_len)
+                .step_by(window_size as usize)
+                .map(|bgn| {
+                    let end = (bgn + window_size).min(seq_len);
+                    let stats = sketch_stats_from_shmmrs(&shmmrs, bgn, end);
+                    format!(
+                        "{}\t{}\t{}\t{}\t{:.2}\t{}\t{}\t{:.4}\n",
+                        seq_name,
+                        bgn,
+                        end,
+                        stats.n_anchors,
+                        stats.mean_spacing,
+                        stats.median_spacing,
+                        stats.max_gap,
+                        stats.span_coverage
+                    )
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let mut out_file =
+        BufWriter::new(File::create(args.output_path).expect("can't create the output file"));
+    writeln!(
+        out_file,
+        "#seq_name\tbgn\tend\tn_anchors\tmean_spacing\tmedian_spacing\tmax_gap\tspan_coverage"
+    )?;
+    stats_lines
+        .into_iter()
+        .flatten()
+        .try_for_each(|line| out_file.write_all(line.as_bytes()))?;
+    Ok(())
+}
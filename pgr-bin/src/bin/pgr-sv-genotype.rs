@@ -0,0 +1,349 @@
+const VERSION_STRING: &str = env!("VERSION_STRING");
+use clap::{self, CommandFactory, Parser};
+use pgr_db::ext::{get_fastx_reader, GZFastaReader, SeqIndexDB};
+use pgr_db::fasta_io::{reverse_complement, SeqRec};
+use rayon::prelude::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Genotype known SVs in a VCF file against a PGR-TK sequence database built from a read set or assemblies
+#[derive(Parser, Debug)]
+#[clap(name = "pgr-sv-genotype")]
+#[clap(author, version)]
+#[clap(about, long_about = None)]
+struct CmdOptions {
+    /// the path to the reference fasta file of the VCF file
+    ref_fasta_path: String,
+    /// the path to the VCF file of the SVs, the REF and ALT alleles should be explicit sequences, or "<DEL>" and "<INV>"
+    /// ALT alleles with the END or SVLEN INFO field; the other symbolic alleles and the breakends are not genotyped
+    /// (the "SYMB" filter)
+    vcf_path: String,
+    /// the prefix to a PGR-TK sequence database
+    pgr_db_prefix: String,
+    /// the path to the output VCF file
+    output_path: String,
+
+    /// using the frg format for the sequence database (default to the AGC backend database if not specified)
+    #[clap(long, default_value_t = false)]
+    frg_file: bool,
+
+    /// using a fasta file as the sequence database, the index is built on the fly with the shimmer parameters below
+    #[clap(long, default_value_t = false)]
+    fastx_file: bool,
+
+    /// minimizer window size
+    #[clap(long, short, default_value_t = 80)]
+    w: u32,
+    /// minimizer k-mer size
+    #[clap(long, short, default_value_t = 56)]
+    k: u32,
+    /// sparse minimizer (shimmer) reduction factor
+    #[clap(long, short, default_value_t = 4)]
+    r: u32,
+    /// min span for neighboring minimizers
+    #[clap(long, short, default_value_t = 64)]
+    min_span: u32,
+
+    /// the length of the reference sequences padded on both sides of the SV for building the breakpoint-spanning sequences
+    #[clap(long, default_value_t = 2000)]
+    flank_size: usize,
+
+    /// the minimum fraction of the allele specific shimmer pairs found in the database to call an allele present
+    #[clap(long, default_value_t = 0.5)]
+    min_allele_fraction: f32,
+
+    /// sample name used in the output VCF file
+    #[clap(long, default_value = "SAMPLE")]
+    sample_name: String,
+
+    /// number of threads used in parallel (more memory usage), default to "0" using all CPUs available or the number set by RAYON_NUM_THREADS
+    #[clap(long, default_value_t = 0)]
+    number_of_thread: usize,
+}
+
+struct SvRecord {
+    chr: String,
+    pos: usize,
+    id: String,
+    ref_allele: String,
+    alt_allele: String,
+    // the end (1-based, inclusive) of a symbolic SV allele from the END or SVLEN INFO field
+    sv_end: Option<usize>,
+}
+
+// the end of a symbolic SV allele from the INFO field, END is used if it is present, otherwise POS + |SVLEN|
+fn parse_sv_end(pos: usize, info: &str) -> Option<usize> {
+    let get_value = |key: &str| {
+        info.split(';')
+            .find_map(|kv| kv.strip_prefix(key)?.strip_prefix('='))
+            .and_then(|v| v.split(',').next()?.parse::<i64>().ok())
+    };
+    get_value("END")
+        .map(|end| end as usize)
+        .or_else(|| get_value("SVLEN").map(|svlen| pos + svlen.unsigned_abs() as usize))
+}
+
+fn main() -> Result<(), std::io::Error> {
+    CmdOptions::command().version(VERSION_STRING).get_matches();
+    let args = CmdOptions::parse();
+
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(args.number_of_thread)
+        .build_global()
+        .unwrap();
+
+    let mut ref_seqs = FxHashMap::<String, Vec<u8>>::default();
+    let mut add_seqs = |seq_iter: &mut dyn Iterator<Item = io::Result<SeqRec>>| {
+        seq_iter.into_iter().for_each(|r| {
+            if let Ok(r) = r {
+                ref_seqs.insert(String::from_utf8_lossy(&r.id).to_string(), r.seq);
+            };
+        });
+    };
+
+    match get_fastx_reader(args.ref_fasta_path, true)? {
+        #[allow(clippy::useless_conversion)] // the into_iter() is necessary for dyn patching
+        GZFastaReader::GZFile(reader) => add_seqs(&mut reader.into_iter()),
+
+        #[allow(clippy::useless_conversion)] // the into_iter() is necessary for dyn patching
+        GZFastaReader::RegularFile(reader) => add_seqs(&mut reader.into_iter()),
+    };
+
+    let mut seq_index_db = SeqIndexDB::new();
+    if args.frg_file {
+        let stderr = io::stderr();
+        let mut handle = stderr.lock();
+        let _ = handle.write_all(b"the option `--frg_file` is specified, read the input file as a FRG backed index database files.\n");
+        let _ = seq_index_db.load_from_frg_index(args.pgr_db_prefix);
+    } else if args.fastx_file {
+        let stderr = io::stderr();
+        let mut handle = stderr.lock();
+        let _ = handle.write_all(
+            b"the option `--fastx_file` is specified, read the input file as a fastx file.\n",
+        );
+        let _ = seq_index_db.load_from_fastx(
+            args.pgr_db_prefix,
+            args.w,
+            args.k,
+            args.r,
+            args.min_span,
+            true,
+        );
+    } else {
+        #[cfg(feature = "with_agc")]
+        {
+            let stderr = io::stderr();
+            let mut handle = stderr.lock();
+            let _ = handle.write_all(b"Read the input as a AGC backed index database files.\n");
+            let _ = seq_index_db.load_from_agc_index(args.pgr_db_prefix);
+        }
+
+        #[cfg(not(feature = "with_agc"))]
+        panic!("This command is compiled with only frg file support, please specify `--frg-file");
+    }
+    let shmmr_spec = seq_index_db.shmmr_spec.clone().unwrap();
+
+    let vcf_reader = BufReader::new(File::open(Path::new(&args.vcf_path))?);
+    let mut sv_records = Vec::<SvRecord>::new();
+    vcf_reader.lines().enumerate().try_for_each(
+        |(line_idx, line)| -> Result<(), std::io::Error> {
+            let line = line?;
+            if line.starts_with('#') || line.trim().is_empty() {
+                return Ok(());
+            }
+            let parse_error = || {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!(
+                        "fail to parse the line {} of the vcf file: {}",
+                        line_idx + 1,
+                        line
+                    ),
+                )
+            };
+            let fields = line.split('\t').collect::<Vec<&str>>();
+            if fields.len() < 5 {
+                return Err(parse_error());
+            }
+            // the POS field is 1-based
+            let pos = fields[1]
+                .parse::<usize>()
+                .ok()
+                .filter(|pos| *pos > 0)
+                .ok_or_else(parse_error)?;
+            sv_records.push(SvRecord {
+                chr: fields[0].to_string(),
+                pos,
+                id: fields[2].to_string(),
+                ref_allele: fields[3].to_uppercase(),
+                // only the first ALT allele is genotyped
+                alt_allele: fields[4].split(',').next().unwrap().to_uppercase(),
+                sv_end: fields.get(7).and_then(|info| parse_sv_end(pos, info)),
+            });
+            Ok(())
+        },
+    )?;
+
+    // (filter, ref allele specific pair count, alt allele specific pair count, ref support, alt support, ref hit count, alt hit count)
+    let genotype_results = sv_records
+        .par_iter()
+        .map(|rec| {
+            let symbolic_sv = matches!(rec.alt_allele.as_str(), "<DEL>" | "<INV>");
+            if (rec.alt_allele.starts_with('<') && !symbolic_sv)
+                || rec.alt_allele.contains('[')
+                || rec.alt_allele.contains(']')
+                || (symbolic_sv && rec.sv_end.is_none())
+            {
+                return ("SYMB", 0, 0, 0, 0, 0, 0);
+            }
+            let ref_seq = if let Some(ref_seq) = ref_seqs.get(&rec.chr) {
+                ref_seq
+            } else {
+                return ("NOREF", 0, 0, 0, 0, 0, 0);
+            };
+            let bgn = rec.pos - 1;
+            // the soft-masked (lower case) reference bases match the REF allele
+            let ref_allele_end = bgn + rec.ref_allele.len();
+            if ref_allele_end > ref_seq.len()
+                || ref_seq[bgn..ref_allele_end].to_ascii_uppercase() != rec.ref_allele.as_bytes()
+            {
+                return ("REFMISMATCH", 0, 0, 0, 0, 0, 0);
+            }
+            // the explicit ALT allele, or the one built from the reference for a <DEL> or <INV> allele, which
+            // replaces the reference from the padding base at POS to END
+            let (end, alt_allele) = if symbolic_sv {
+                let end = rec.sv_end.unwrap();
+                if end <= bgn || end > ref_seq.len() {
+                    return ("REFMISMATCH", 0, 0, 0, 0, 0, 0);
+                }
+                let mut alt_allele = ref_seq[bgn..bgn + 1].to_ascii_uppercase();
+                if rec.alt_allele == "<INV>" {
+                    alt_allele.extend(reverse_complement(
+                        &ref_seq[bgn + 1..end].to_ascii_uppercase(),
+                    ));
+                }
+                (end, alt_allele)
+            } else {
+                (ref_allele_end, rec.alt_allele.as_bytes().to_vec())
+            };
+            let flank_bgn = if bgn > args.flank_size {
+                bgn - args.flank_size
+            } else {
+                0
+            };
+            let flank_end = if end + args.flank_size < ref_seq.len() {
+                end + args.flank_size
+            } else {
+                ref_seq.len()
+            };
+            let ref_hap = ref_seq[flank_bgn..flank_end].to_ascii_uppercase();
+            let mut alt_hap = ref_seq[flank_bgn..bgn].to_ascii_uppercase();
+            alt_hap.extend(alt_allele);
+            alt_hap.extend(ref_seq[end..flank_end].to_ascii_uppercase());
+
+            let ref_smps = seq_index_db
+                .get_smps(ref_hap, &shmmr_spec)
+                .into_iter()
+                .map(|v| (v.0, v.1))
+                .collect::<FxHashSet<(u64, u64)>>();
+            let alt_smps = seq_index_db
+                .get_smps(alt_hap, &shmmr_spec)
+                .into_iter()
+                .map(|v| (v.0, v.1))
+                .collect::<FxHashSet<(u64, u64)>>();
+
+            // count the allele specific shimmer pairs found in the database and the total hits
+            let get_support = |smps: &FxHashSet<(u64, u64)>, other: &FxHashSet<(u64, u64)>| {
+                let mut n_specific = 0_usize;
+                let mut n_support = 0_usize;
+                let mut n_hit = 0_usize;
+                smps.difference(other).for_each(|&smp| {
+                    n_specific += 1;
+                    let count = seq_index_db.get_shmmr_pair_count(smp);
+                    if count > 0 {
+                        n_support += 1;
+                        n_hit += count;
+                    }
+                });
+                (n_specific, n_support, n_hit)
+            };
+            let (ref_n, ref_support, ref_hit) = get_support(&ref_smps, &alt_smps);
+            let (alt_n, alt_support, alt_hit) = get_support(&alt_smps, &ref_smps);
+            let filter = if ref_n == 0 || alt_n == 0 {
+                "NOSIG"
+            } else {
+                "PASS"
+            };
+            (
+                filter,
+                ref_n,
+                alt_n,
+                ref_support,
+                alt_support,
+                ref_hit,
+                alt_hit,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let mut out_vcf = BufWriter::new(File::create(Path::new(&args.output_path))?);
+    writeln!(out_vcf, "##fileformat=VCFv4.2").expect("fail to write the vcf file");
+    [
+        r#"##FILTER=<ID=SYMB,Description="symbolic ALT allele other than <DEL> and <INV> with END or SVLEN, or breakend ALT allele, not genotyped">"#,
+        r#"##FILTER=<ID=NOREF,Description="the CHROM is not found in the reference">"#,
+        r#"##FILTER=<ID=REFMISMATCH,Description="the REF allele does not match the reference">"#,
+        r#"##FILTER=<ID=NOSIG,Description="no allele specific shimmer pair">"#,
+        r#"##FORMAT=<ID=GT,Number=1,Type=String,Description="Genotype">"#,
+        r#"##FORMAT=<ID=RN,Number=1,Type=Integer,Description="Number of REF allele specific shimmer pairs">"#,
+        r#"##FORMAT=<ID=AN,Number=1,Type=Integer,Description="Number of ALT allele specific shimmer pairs">"#,
+        r#"##FORMAT=<ID=RS,Number=1,Type=Integer,Description="Number of REF allele specific shimmer pairs found in the database">"#,
+        r#"##FORMAT=<ID=AS,Number=1,Type=Integer,Description="Number of ALT allele specific shimmer pairs found in the database">"#,
+        r#"##FORMAT=<ID=RC,Number=1,Type=Integer,Description="Total hit count of the REF allele specific shimmer pairs">"#,
+        r#"##FORMAT=<ID=AC,Number=1,Type=Integer,Description="Total hit count of the ALT allele specific shimmer pairs">"#,
+    ]
+    .iter()
+    .for_each(|h| writeln!(out_vcf, "{}", h).expect("fail to write the vcf file"));
+    writeln!(
+        out_vcf,
+        "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\t{}",
+        args.sample_name
+    )
+    .expect("fail to write the vcf file");
+
+    sv_records.iter().zip(genotype_results).for_each(
+        |(rec, (filter, ref_n, alt_n, ref_support, alt_support, ref_hit, alt_hit))| {
+            let ref_present =
+                ref_n > 0 && ref_support as f32 / ref_n as f32 >= args.min_allele_fraction;
+            let alt_present =
+                alt_n > 0 && alt_support as f32 / alt_n as f32 >= args.min_allele_fraction;
+            let gt = match (filter, ref_present, alt_present) {
+                ("PASS", true, true) => "0/1",
+                ("PASS", true, false) => "0/0",
+                ("PASS", false, true) => "1/1",
+                _ => "./.",
+            };
+            writeln!(
+                out_vcf,
+                "{}\t{}\t{}\t{}\t{}\t.\t{}\t.\tGT:RN:AN:RS:AS:RC:AC\t{}:{}:{}:{}:{}:{}:{}",
+                rec.chr,
+                rec.pos,
+                rec.id,
+                rec.ref_allele,
+                rec.alt_allele,
+                filter,
+                gt,
+                ref_n,
+                alt_n,
+                ref_support,
+                alt_support,
+                ref_hit,
+                alt_hit
+            )
+            .expect("fail to write the vcf file");
+        },
+    );
+
+    Ok(())
+}
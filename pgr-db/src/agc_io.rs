@@ -4,6 +4,8 @@ use crate::bindings::{
 };
 use crate::fasta_io::SeqRec;
 use crate::frag_file_io::ShmmrToFragMapLocation;
+use crate::lru_cache::LruCache;
+use crate::remote_io::DataFile;
 use libc::strlen;
 use rayon::iter::IntoParallelRefIterator;
 use rayon::iter::ParallelIterator;
@@ -14,8 +16,10 @@ use std::cell::RefCell;
 use std::ffi::CString;
 use std::io;
 use std::mem;
-use memmap2::Mmap;
-//use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+
+/// a sub-sequence request: (sample_name, ctg_name, bgn, end)
+pub type SubSeqRequest = (String, String, usize, usize);
 
 #[derive(Debug, Clone)]
 pub struct AGCHandle(*mut agc_t);
@@ -38,12 +42,16 @@ pub struct AGCFile {
     sample_ctg: Vec<(String, String)>,
     pub prefetching: bool,
     pub number_iter_thread: usize,
+    // the idle AGC handles for the fetches in parallel, a handle is used by one fetch at a time
+    fetch_handles: Arc<Mutex<Vec<AGCHandle>>>,
+    // the recently decompressed contigs keyed by (sample_name, ctg_name), disabled (zero capacity) by default
+    ctg_cache: Arc<Mutex<LruCache<(String, String), Arc<Vec<u8>>>>>,
 }
 
 pub struct AGCSeqDB {
     pub agc_file: AGCFile,
     pub frag_location_map: ShmmrToFragMapLocation,
-    pub frag_map_file: Mmap,
+    pub frag_map_file: DataFile,
 }
 
 pub struct AGCFileIter<'a> {
@@ -58,6 +66,36 @@ fn cstr_to_string(cstr_ptr: *mut i8) -> String {
     unsafe { String::from_raw_parts(cstr_ptr as *mut u8, strlen(cstr_ptr), strlen(cstr_ptr)) }
 }
 
+// fetch the bases `[bgn, end)` of a contig with an AGC handle
+fn fetch_sub_seq(
+    agc_handle: &AGCHandle,
+    sample_name: &str,
+    ctg_name: &str,
+    bgn: usize,
+    end: usize,
+) -> Vec<u8> {
+    let c_sample_name: *mut i8 = CString::new(sample_name).unwrap().into_raw();
+    let c_ctg_name: *mut i8 = CString::new(ctg_name).unwrap().into_raw();
+    let seq;
+    let ctg_len = end - bgn + 1;
+
+    unsafe {
+        let seq_buf: *mut i8 = libc::malloc(mem::size_of::<i8>() * ctg_len) as *mut i8;
+        agc_get_ctg_seq(
+            agc_handle.0,
+            c_sample_name,
+            c_ctg_name,
+            bgn as i32,
+            end as i32 - 1,
+            seq_buf,
+        );
+        seq = <Vec<u8>>::from_raw_parts(seq_buf as *mut u8, ctg_len - 1, ctg_len);
+        drop(CString::from_raw(c_sample_name));
+        drop(CString::from_raw(c_ctg_name));
+    }
+    seq
+}
+
 impl AGCFile {
     pub fn new(filepath: String) -> Result<Self, std::io::Error> {
         if !std::path::Path::new(&filepath).exists() {
@@ -125,6 +163,8 @@ impl AGCFile {
             sample_ctg,
             prefetching,
             number_iter_thread,
+            fetch_handles: Arc::new(Mutex::new(vec![])),
+            ctg_cache: Arc::new(Mutex::new(LruCache::new(0))),
         })
     }
 
@@ -136,6 +176,14 @@ impl AGCFile {
         self.prefetching = prefetching;
     }
 
+    fn check_sub_seq_range(&self, sample_name: &str, ctg_name: &str, bgn: usize, end: usize) {
+        let key = (sample_name.to_string(), ctg_name.to_string());
+        assert!(self.ctg_lens.contains_key(&key));
+        assert!(*self.ctg_lens.get(&key).unwrap() >= end);
+        assert!(*self.ctg_lens.get(&key).unwrap() >= bgn);
+        assert!(bgn < end);
+    }
+
     pub fn get_sub_seq(
         &self,
         sample_name: String,
@@ -143,33 +191,122 @@ impl AGCFile {
         bgn: usize,
         end: usize,
     ) -> Vec<u8> {
-        let key = (sample_name.clone(), ctg_name.clone());
-        assert!(self.ctg_lens.contains_key(&key));
-        assert!(*self.ctg_lens.get(&key).unwrap() >= end);
-        assert!(*self.ctg_lens.get(&key).unwrap() >= bgn);
-        assert!(bgn < end);
+        self.check_sub_seq_range(&sample_name, &ctg_name, bgn, end);
+        self.fetch_sub_seq_cached(&self.agc_handle, &sample_name, &ctg_name, bgn, end)
+    }
 
-        let c_sample_name: *mut i8 = CString::new(sample_name).unwrap().into_raw();
-        let c_ctg_name: *mut i8 = CString::new(ctg_name).unwrap().into_raw();
-        let seq;
-        let ctg_len = end - bgn + 1;
+    /// set the max number of the decompressed contigs kept in memory, the contigs are decompressed
+    /// as a whole and the sub-sequences are sliced from them while they are cached, so the repeated
+    /// fetches from the same contigs are not decompressed again, "0" disables the cache
+    ///
+    /// the cache is shared with the clones of the `AGCFile`
+    pub fn set_ctg_cache_size(&self, n_ctgs: usize) {
+        self.ctg_cache.lock().unwrap().set_capacity(n_ctgs);
+    }
 
-        unsafe {
-            let seq_buf: *mut i8 = libc::malloc(mem::size_of::<i8>() * ctg_len) as *mut i8;
-            agc_get_ctg_seq(
-                self.agc_handle.0,
-                c_sample_name,
-                c_ctg_name,
-                bgn as i32,
-                end as i32 - 1,
-                seq_buf,
-            );
-            seq = <Vec<u8>>::from_raw_parts(seq_buf as *mut u8, ctg_len - 1, ctg_len);
-            //check this, it takes over the pointer? we don't need to free the point manually?
+    /// the number of the decompressed contigs in the cache
+    pub fn cached_ctg_count(&self) -> usize {
+        self.ctg_cache.lock().unwrap().len()
+    }
+
+    // fetch a sub-sequence through the contig cache if it is enabled
+    fn fetch_sub_seq_cached(
+        &self,
+        agc_handle: &AGCHandle,
+        sample_name: &str,
+        ctg_name: &str,
+        bgn: usize,
+        end: usize,
+    ) -> Vec<u8> {
+        let key = (sample_name.to_string(), ctg_name.to_string());
+        let cached_seq = {
+            let mut ctg_cache = self.ctg_cache.lock().unwrap();
+            if ctg_cache.capacity() == 0 {
+                None
+            } else {
+                Some(ctg_cache.get(&key))
+            }
+        };
+        match cached_seq {
+            None => fetch_sub_seq(agc_handle, sample_name, ctg_name, bgn, end),
+            Some(Some(ctg_seq)) => ctg_seq[bgn..end].to_vec(),
+            Some(None) => {
+                // the lock is not held while decompressing, so other contigs can be fetched in parallel
+                let ctg_len = *self.ctg_lens.get(&key).unwrap();
+                let ctg_seq =
+                    Arc::new(fetch_sub_seq(agc_handle, sample_name, ctg_name, 0, ctg_len));
+                let seq = ctg_seq[bgn..end].to_vec();
+                self.ctg_cache.lock().unwrap().insert(key, ctg_seq);
+                seq
+            }
         }
+    }
+
+    // fetch a sub-sequence with an idle handle in the pool, a new handle is opened if all handles are in use
+    fn fetch_sub_seq_with_pooled_handle(&self, request: &SubSeqRequest) -> Vec<u8> {
+        let (sample_name, ctg_name, bgn, end) = request;
+        self.check_sub_seq_range(sample_name, ctg_name, *bgn, *end);
+        let agc_handle = self
+            .fetch_handles
+            .lock()
+            .unwrap()
+            .pop()
+            .unwrap_or_else(|| unsafe {
+                AGCHandle(agc_open(
+                    CString::new(self.filepath.clone()).unwrap().into_raw(),
+                    self.prefetching as i32,
+                ))
+            });
+        let seq = self.fetch_sub_seq_cached(&agc_handle, sample_name, ctg_name, *bgn, *end);
+        self.fetch_handles.lock().unwrap().push(agc_handle);
         seq
     }
 
+    /// fetch a batch of the sub-sequences in parallel, each fetch uses its own AGC handle from a pool
+    /// kept with the file, the sequences are returned in the order of the requests
+    pub fn get_sub_seqs(&self, requests: Vec<SubSeqRequest>) -> Vec<Vec<u8>> {
+        requests
+            .par_iter()
+            .map(|request| self.fetch_sub_seq_with_pooled_handle(request))
+            .collect()
+    }
+
+    /// fetch the sub-sequences in the batches of `batch_size` in a background thread and call `f` with
+    /// each request and its sequence in the order of the requests, up to `queue_size` batches are fetched
+    /// ahead of `f`, so the fetching overlaps with the processing of the sequences with bounded memory
+    pub fn for_each_sub_seq_prefetched<F>(
+        &self,
+        requests: Vec<SubSeqRequest>,
+        batch_size: usize,
+        queue_size: usize,
+        mut f: F,
+    ) where
+        F: FnMut(SubSeqRequest, Vec<u8>),
+    {
+        let (sender, receiver) = std::sync::mpsc::sync_channel(queue_size);
+        std::thread::scope(|scope| {
+            let fetcher = scope.spawn(move || {
+                for batch in requests.chunks(batch_size.max(1)) {
+                    let seqs = batch
+                        .par_iter()
+                        .map(|request| self.fetch_sub_seq_with_pooled_handle(request))
+                        .collect::<Vec<Vec<u8>>>();
+                    if sender.send((batch.to_vec(), seqs)).is_err() {
+                        // the receiver is gone
+                        return;
+                    }
+                }
+            });
+            receiver.iter().for_each(|(batch, seqs)| {
+                batch
+                    .into_iter()
+                    .zip(seqs)
+                    .for_each(|(request, seq)| f(request, seq))
+            });
+            fetcher.join().expect("the AGC fetcher thread panicked");
+        })
+    }
+
     pub fn get_seq(&self, sample_name: String, ctg_name: String) -> Vec<u8> {
         let key = (sample_name.clone(), ctg_name.clone());
         assert!(self.ctg_lens.contains_key(&key));
@@ -186,6 +323,14 @@ impl Drop for AGCFile {
         unsafe {
             agc_close(self.agc_handle.0);
         }
+        // the pooled handles are shared by the clones, they are closed with the last one
+        if let Some(fetch_handles) = Arc::get_mut(&mut self.fetch_handles) {
+            fetch_handles
+                .get_mut()
+                .unwrap()
+                .drain(..)
+                .for_each(|agc_handle| unsafe { agc_close(agc_handle.0) });
+        }
     }
 }
 
@@ -311,6 +456,7 @@ impl<'a> Iterator for AGCFileIter<'a> {
                                 source: Some(s.clone()),
                                 id: c.as_bytes().to_vec(),
                                 seq,
+                                qual: None,
                             }
                         })
                         //let seq = self.get_seq(s.clone(), c.clone());
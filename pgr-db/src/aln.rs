@@ -3,16 +3,128 @@ use crate::seq_db::{self, FragmentHit};
 use crate::shmmrutils::{self, ShmmrSpec};
 use log::debug;
 use rustc_hash::{FxHashMap, FxHashSet};
+use serde::Serialize;
 use std::cmp::Ordering;
 use std::collections::HashSet;
+use std::fmt;
+use std::io;
 use wavefront_aln::*;
 
 pub type HitPair = ((u32, u32, u8), (u32, u32, u8)); //(bgn1, end1, orientation1),  (bgn2, end2, orientation2)
 
+/// the scoring parameters of the base level (WFA and SW) alignments, the penalties are positive and a gap of
+/// length `l` costs `gap_open_penalty + l * gap_extension_penalty`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AlnParams {
+    /// the score of a matched base, only used by the SW alignments, the WFA alignments always use zero
+    pub match_score: i32,
+    pub mismatch_penalty: i32,
+    pub gap_open_penalty: i32,
+    pub gap_extension_penalty: i32,
+    /// the max distance (wavefront length) of the WFA alignments, `None` to set it from the length difference
+    /// of the sequences with [default_max_wf_length]
+    pub max_wf_length: Option<u32>,
+}
+
+impl Default for AlnParams {
+    fn default() -> Self {
+        AlnParams {
+            match_score: 0,
+            mismatch_penalty: 4,
+            gap_open_penalty: 4,
+            gap_extension_penalty: 1,
+            max_wf_length: None,
+        }
+    }
+}
+
+impl AlnParams {
+    /// the max wavefront length for aligning the two sequences
+    pub fn max_wf_length_for(&self, target_len: usize, query_len: usize) -> u32 {
+        self.max_wf_length
+            .unwrap_or_else(|| default_max_wf_length(target_len, query_len))
+    }
+}
+
+/// the max wavefront length from the length difference of the sequences, at least 128
+pub fn default_max_wf_length(target_len: usize, query_len: usize) -> u32 {
+    let set_len_diff = (query_len as i64 - target_len as i64).unsigned_abs() as u32;
+    std::cmp::max(2 * set_len_diff, 128_u32)
+}
+
+/// the end gap semantics of the base level (SW) alignments
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AlnMode {
+    /// end-to-end alignments of both sequences
+    Global,
+    /// the whole query is aligned to a substring of the target, the unaligned target bases on both
+    /// ends are not penalized
+    QueryGlocal,
+}
+
+impl Default for AlnMode {
+    fn default() -> Self {
+        AlnMode::Global
+    }
+}
+
+/// parse an alignment mode name, "global" or "query-glocal"
+pub fn aln_mode_from_str(mode: &str) -> Result<AlnMode, io::Error> {
+    match mode {
+        "global" => Ok(AlnMode::Global),
+        "query-glocal" => Ok(AlnMode::QueryGlocal),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unknown alignment mode: {}", mode),
+        )),
+    }
+}
+
+/// the gap cost of chaining two hits in the sparse alignments, from the sum of the gaps between the hits
+/// in the two sequences
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChainGapCost {
+    /// `penalty * gap`
+    Linear(f32),
+    /// `penalty * gap` up to `break_len`, and `long_penalty` for each base beyond `break_len`, a smaller
+    /// `long_penalty` keeps the long gaps (e.g., from the SVs) from breaking the chains
+    Piecewise {
+        penalty: f32,
+        break_len: u32,
+        long_penalty: f32,
+    },
+}
+
+impl From<f32> for ChainGapCost {
+    fn from(penalty: f32) -> Self {
+        ChainGapCost::Linear(penalty)
+    }
+}
+
+impl ChainGapCost {
+    pub fn cost(&self, gap: f32) -> f32 {
+        match *self {
+            ChainGapCost::Linear(penalty) => penalty * gap,
+            ChainGapCost::Piecewise {
+                penalty,
+                break_len,
+                long_penalty,
+            } => {
+                let break_len = break_len as f32;
+                if gap <= break_len {
+                    penalty * gap
+                } else {
+                    penalty * break_len + long_penalty * (gap - break_len)
+                }
+            }
+        }
+    }
+}
+
 pub fn sparse_aln(
     sp_hits: &mut Vec<HitPair>,
     max_span: u32,
-    penalty: f32,
+    gap_cost: ChainGapCost,
     max_gap: Option<u32>,
     orientated: bool,
 ) -> Vec<(f32, Vec<HitPair>)> {
@@ -73,14 +185,16 @@ pub fn sparse_aln(
 
             if hp.0 .2 == hp.1 .2 {
                 // same orientation
-                s -= penalty
-                    * ((hp.0 .0 as f32 - pre_hp.0 .1 as f32).abs()
-                        + (hp.1 .0 as f32 - pre_hp.1 .1 as f32).abs());
+                s -= gap_cost.cost(
+                    (hp.0 .0 as f32 - pre_hp.0 .1 as f32).abs()
+                        + (hp.1 .0 as f32 - pre_hp.1 .1 as f32).abs(),
+                );
             } else {
                 // opposite orientation
-                s -= penalty
-                    * ((hp.0 .0 as f32 - pre_hp.0 .1 as f32).abs()
-                        + (hp.1 .1 as f32 - pre_hp.1 .0 as f32).abs());
+                s -= gap_cost.cost(
+                    (hp.0 .0 as f32 - pre_hp.0 .1 as f32).abs()
+                        + (hp.1 .1 as f32 - pre_hp.1 .0 as f32).abs(),
+                );
             }
 
             if s > best_s {
@@ -148,7 +262,7 @@ pub fn query_fragment_to_hps(
     raw_query_hits: Vec<FragmentHit>,
     frag: &Vec<u8>,
     shmmr_spec: &ShmmrSpec,
-    penalty: f32,
+    gap_cost: ChainGapCost,
     max_count: Option<u32>,
     query_max_count: Option<u32>,
     target_max_count: Option<u32>,
@@ -156,6 +270,42 @@ pub fn query_fragment_to_hps(
     max_gap: Option<u32>,
     oriented: bool,
 ) -> TargetHitPairLists {
+    query_fragment_to_unique_anchor_hps(
+        raw_query_hits,
+        frag,
+        shmmr_spec,
+        gap_cost,
+        None,
+        max_count,
+        query_max_count,
+        target_max_count,
+        max_aln_span,
+        max_gap,
+        oriented,
+    )
+    .0
+}
+
+pub type TargetHitPairs = Vec<(u32, Vec<HitPair>)>; // target_id, HitPairs
+
+/// the sparse alignments of [query_fragment_to_hps] only using the shimmer pairs occurring at most
+/// `max_anchor_count` times in the index (the fragment map) as the anchors of the chains, the hits of the more
+/// repetitive shimmer pairs are returned separately as the second item, sorted by the target id and the query
+/// position; all the shimmer pairs are used as anchors if `max_anchor_count` is None
+#[allow(clippy::too_many_arguments)]
+pub fn query_fragment_to_unique_anchor_hps(
+    raw_query_hits: Vec<FragmentHit>,
+    frag: &Vec<u8>,
+    shmmr_spec: &ShmmrSpec,
+    gap_cost: ChainGapCost,
+    max_anchor_count: Option<u32>,
+    max_count: Option<u32>,
+    query_max_count: Option<u32>,
+    target_max_count: Option<u32>,
+    max_aln_span: Option<u32>,
+    max_gap: Option<u32>,
+    oriented: bool,
+) -> (TargetHitPairLists, TargetHitPairs) {
     let mut shmmr_pair_hash_count = FxHashMap::<(u64, u64), u32>::default();
     let mut query_shmmr_pair_hash_count = FxHashMap::<(u64, u64), u32>::default();
     let mut target_shmer_pair_count = FxHashMap::<(u64, u64, u32), u32>::default();
@@ -194,6 +344,7 @@ pub fn query_fragment_to_hps(
 
     let mut target_squence_id_to_hits =
         FxHashMap::<u32, Vec<((u32, u32, u8), (u32, u32, u8))>>::default();
+    let mut target_squence_id_to_repeat_hits = FxHashMap::<u32, Vec<HitPair>>::default();
     raw_query_hits.into_iter().for_each(
         |(shmmr_pair, query_position, frag_signature): (
             (u64, u64),
@@ -210,6 +361,9 @@ pub fn query_fragment_to_hps(
                 return;
             };
             let left_frag_coordinate = query_position;
+            let is_repeat = max_anchor_count
+                .map(|max_anchor_count| frag_signature.len() > max_anchor_count as usize)
+                .unwrap_or(false);
             frag_signature
                 .iter()
                 .for_each(|&(_frg_id, sid, pos0, pos1, orientation)| {
@@ -220,7 +374,11 @@ pub fn query_fragment_to_hps(
                     if count > max_count_target {
                         return;
                     };
-                    let e = target_squence_id_to_hits.entry(sid).or_default();
+                    let e = if is_repeat {
+                        target_squence_id_to_repeat_hits.entry(sid).or_default()
+                    } else {
+                        target_squence_id_to_hits.entry(sid).or_default()
+                    };
                     let right_frag_coordinate = (pos0, pos1, orientation);
                     e.push((left_frag_coordinate, right_frag_coordinate));
                 });
@@ -229,34 +387,150 @@ pub fn query_fragment_to_hps(
 
     let max_aln_span = max_aln_span.unwrap_or(8);
 
-    target_squence_id_to_hits
+    let hit_pair_lists = target_squence_id_to_hits
         .into_iter()
         .filter(|(_sid, hps)| hps.len() > 1)
         .map(|(sid, mut hps)| {
             (
                 sid,
-                sparse_aln(&mut hps, max_aln_span, penalty, max_gap, oriented),
+                sparse_aln(&mut hps, max_aln_span, gap_cost, max_gap, oriented),
             )
         })
-        .collect::<Vec<_>>()
+        .collect::<Vec<_>>();
+
+    let mut repeat_hit_pairs = target_squence_id_to_repeat_hits
+        .into_iter()
+        .map(|(sid, mut hps)| {
+            hps.sort();
+            (sid, hps)
+        })
+        .collect::<Vec<_>>();
+    repeat_hit_pairs.sort_by_key(|(sid, _)| *sid);
+
+    (hit_pair_lists, repeat_hit_pairs)
+}
+
+/// the MAPQ-like confidence of a hit chain from its score and the best score of the competing chains,
+/// `60 * (1 - second_best_score / score)` in [0, 60]
+pub fn chain_mapq(score: f32, second_best_score: f32) -> u8 {
+    if score <= 0.0 {
+        return 0;
+    }
+    (60.0 * (1.0 - second_best_score.max(0.0) / score))
+        .clamp(0.0, 60.0)
+        .round() as u8
+}
+
+/// the MAPQ-like confidences of the hit chains `(target_id, score, query_bgn, query_end)` of a query, the
+/// competing chains of a chain are the other chains overlapping at least half of its query interval; it returns
+/// (the confidence among the chains to the same target, the confidence among the chains to all targets)
+/// for each chain in the input order
+pub fn hit_chain_mapqs(chains: &[(u32, f32, u32, u32)]) -> Vec<(u8, u8)> {
+    let mut order = (0..chains.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&idx| chains[idx].2);
+    chains
+        .iter()
+        .enumerate()
+        .map(|(idx, &(sid, score, q_bgn, q_end))| {
+            let min_overlap = (q_end.saturating_sub(q_bgn) + 1) / 2;
+            let mut target_second_best = 0_f32;
+            let mut region_second_best = 0_f32;
+            let n_candidates = order.partition_point(|&idx| chains[idx].2 < q_end);
+            order[..n_candidates]
+                .iter()
+                .filter(|&&other_idx| other_idx != idx)
+                .for_each(|&other_idx| {
+                    let (other_sid, other_score, other_bgn, other_end) = chains[other_idx];
+                    let overlap = q_end.min(other_end).saturating_sub(q_bgn.max(other_bgn));
+                    if overlap == 0 || overlap < min_overlap {
+                        return;
+                    }
+                    region_second_best = region_second_best.max(other_score);
+                    if other_sid == sid {
+                        target_second_best = target_second_best.max(other_score);
+                    }
+                });
+            (
+                chain_mapq(score, target_second_best),
+                chain_mapq(score, region_second_best),
+            )
+        })
+        .collect()
+}
+
+/// the label of a hit chain of a query
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChainLabel {
+    /// the best chain of the query
+    Primary,
+    /// a chain overlapping a better primary or supplementary chain on the query, e.g., another copy of a duplication
+    Secondary,
+    /// a chain of another part of the query not covered by the better chains
+    Supplementary,
+}
+
+impl fmt::Display for ChainLabel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChainLabel::Primary => write!(f, "primary"),
+            ChainLabel::Secondary => write!(f, "secondary"),
+            ChainLabel::Supplementary => write!(f, "supplementary"),
+        }
+    }
+}
+
+/// label the hit chains `(score, query_bgn, query_end)` of a query from the best one, a chain overlapping a better
+/// primary or supplementary chain by at least half of its query interval is secondary; it returns the labels in the
+/// input order, `None` for the secondary chains beyond the best `max_secondary` ones
+pub fn label_hit_chains(
+    chains: &[(f32, u32, u32)],
+    max_secondary: Option<usize>,
+) -> Vec<Option<ChainLabel>> {
+    let mut order = (0..chains.len()).collect::<Vec<_>>();
+    order.sort_by(|&idx0, &idx1| chains[idx1].0.total_cmp(&chains[idx0].0));
+    let mut labels = vec![None; chains.len()];
+    let mut placed_intervals = Vec::<(u32, u32)>::new();
+    let mut n_secondary = 0_usize;
+    order.into_iter().for_each(|idx| {
+        let (_, q_bgn, q_end) = chains[idx];
+        let min_overlap = (q_end.saturating_sub(q_bgn) + 1) / 2;
+        let is_secondary = placed_intervals.iter().any(|&(bgn, end)| {
+            let overlap = q_end.min(end).saturating_sub(q_bgn.max(bgn));
+            overlap > 0 && overlap >= min_overlap
+        });
+        labels[idx] = if is_secondary {
+            n_secondary += 1;
+            if n_secondary > max_secondary.unwrap_or(usize::MAX) {
+                None
+            } else {
+                Some(ChainLabel::Secondary)
+            }
+        } else {
+            placed_intervals.push((q_bgn, q_end));
+            if placed_intervals.len() == 1 {
+                Some(ChainLabel::Primary)
+            } else {
+                Some(ChainLabel::Supplementary)
+            }
+        };
+    });
+    labels
 }
 
 pub fn wfa_align_bases(
     target_str: &str,
     query_str: &str,
-    max_wf_length: u32,
-    mismatch_penalty: i32,
-    open_penalty: i32,
-    extension_penalty: i32,
+    aln_params: &AlnParams,
 ) -> Option<(String, String)> {
     let capacity = std::cmp::max(1024, std::cmp::max(target_str.len(), query_str.len()) >> 5);
     let mut wfs = WaveFronts::new_with_capacity(
         target_str,
         query_str,
-        max_wf_length,
-        mismatch_penalty,
-        open_penalty,
-        extension_penalty,
+        aln_params.max_wf_length_for(target_str.len(), query_str.len()),
+        aln_params.mismatch_penalty,
+        aln_params.gap_open_penalty,
+        aln_params.gap_extension_penalty,
         capacity,
     );
     if wfs.step_all(Some(1024)) == WaveFrontStepResult::ReachEnd {
@@ -386,21 +660,121 @@ pub fn get_variants_from_aln_pair_map(
     variants.into_iter().flatten().collect::<Vec<_>>()
 }
 
+/// the CIGAR operations `(length, op)` of the alignment of the variant segments (from [get_wfa_variant_segments]
+/// or [get_sw_variant_segments]) between the whole target and query sequences, '=' for the matches, 'X' for the
+/// mismatches, 'I' and 'D' for the indels; the bases of a complex variant segment are aligned without gaps followed
+/// by an indel, it returns None if the segments are not consistent with the sequence lengths
+pub fn variant_segments_to_cigar_ops(
+    variants: &[(u32, u32, char, String, String)],
+    target_len: u32,
+    query_len: u32,
+) -> Option<Vec<(u32, char)>> {
+    let mut ops = Vec::<(u32, char)>::new();
+    let mut push_op = |len: u32, op: char| {
+        if len == 0 {
+            return;
+        }
+        match ops.last_mut() {
+            Some(last) if last.1 == op => last.0 += len,
+            _ => ops.push((len, op)),
+        }
+    };
+    // the next target and query positions to align
+    let mut t_pos = 0_u32;
+    let mut q_pos = 0_u32;
+    for (td, qd, vt, t_variant, q_variant) in variants {
+        // the indels are anchored at the matched base before them
+        let (t_bgn, q_bgn, t_variant, q_variant) = if *vt == 'X' {
+            (*td, *qd, t_variant.as_bytes(), q_variant.as_bytes())
+        } else {
+            (
+                td + 1,
+                qd + 1,
+                t_variant.as_bytes().get(1..)?,
+                q_variant.as_bytes().get(1..)?,
+            )
+        };
+        if t_bgn < t_pos || q_bgn < q_pos || t_bgn - t_pos != q_bgn - q_pos {
+            return None;
+        }
+        push_op(t_bgn - t_pos, '=');
+        let t_len = t_variant.len() as u32;
+        let q_len = q_variant.len() as u32;
+        std::iter::zip(t_variant, q_variant).for_each(|(t_base, q_base)| {
+            push_op(1, if t_base == q_base { '=' } else { 'X' });
+        });
+        push_op(t_len.saturating_sub(q_len), 'D');
+        push_op(q_len.saturating_sub(t_len), 'I');
+        t_pos = t_bgn + t_len;
+        q_pos = q_bgn + q_len;
+    }
+    if target_len < t_pos || query_len < q_pos || target_len - t_pos != query_len - q_pos {
+        return None;
+    }
+    push_op(target_len - t_pos, '=');
+    Some(ops)
+}
+
+/// the CIGAR string of the CIGAR operations, the matches and the mismatches are merged into 'M' if `use_match_op`
+pub fn cigar_ops_to_string(ops: &[(u32, char)], use_match_op: bool) -> String {
+    let mut merged_ops = Vec::<(u32, char)>::new();
+    ops.iter().for_each(|&(len, op)| {
+        let op = if use_match_op && (op == '=' || op == 'X') {
+            'M'
+        } else {
+            op
+        };
+        match merged_ops.last_mut() {
+            Some(last) if last.1 == op => last.0 += len,
+            _ => merged_ops.push((len, op)),
+        }
+    });
+    merged_ops
+        .into_iter()
+        .map(|(len, op)| format!("{}{}", len, op))
+        .collect()
+}
+
+/// the MD tag of the CIGAR operations for the target sequence of the alignment
+pub fn cigar_ops_to_md_tag(ops: &[(u32, char)], target_str: &[u8]) -> String {
+    let mut md_tag = String::new();
+    let mut n_matches = 0_u32;
+    let mut t_pos = 0_usize;
+    ops.iter().for_each(|&(len, op)| match op {
+        '=' | 'M' => {
+            n_matches += len;
+            t_pos += len as usize;
+        }
+        'X' => {
+            (0..len).for_each(|_| {
+                md_tag.push_str(&format!("{}{}", n_matches, target_str[t_pos] as char));
+                n_matches = 0;
+                t_pos += 1;
+            });
+        }
+        'D' => {
+            let deleted = String::from_utf8_lossy(&target_str[t_pos..t_pos + len as usize]);
+            md_tag.push_str(&format!("{}^{}", n_matches, deleted));
+            n_matches = 0;
+            t_pos += len as usize;
+        }
+        _ => {}
+    });
+    md_tag.push_str(&format!("{}", n_matches));
+    md_tag
+}
+
 type AlignmentResult = Vec<(u32, u32, char, String, String)>;
 pub fn get_wfa_variant_segments(
     target_str: &[u8],
     query_str: &[u8],
     left_padding: usize,
-    max_wf_length: Option<u32>,
-    mismatch_penalty: i32,
-    open_penalty: i32,
-    extension_penalty: i32,
+    aln_params: &AlnParams,
 ) -> Option<AlignmentResult> {
-    let set_len_diff = (query_str.len() as i64 - target_str.len() as i64).unsigned_abs() as u32;
-    let max_wf_length = if let Some(max_wf_length) = max_wf_length {
-        max_wf_length
-    } else {
-        std::cmp::max(2 * set_len_diff, 128_u32)
+    // the max wavefront length is from the length difference of the full sequences
+    let aln_params = AlnParams {
+        max_wf_length: Some(aln_params.max_wf_length_for(target_str.len(), query_str.len())),
+        ..*aln_params
     };
 
     // we need to reverse the string for alignment such the the gaps are on the left
@@ -415,14 +789,8 @@ pub fn get_wfa_variant_segments(
     let t_len_minus_one = left_padding as u32 + r_t_str.len() as u32 - 1;
     let q_len_minus_one = left_padding as u32 + r_q_str.len() as u32 - 1;
 
-    if let Some((aln_target_str, aln_query_str)) = wfa_align_bases(
-        &r_t_str,
-        &r_q_str,
-        max_wf_length,
-        mismatch_penalty,
-        open_penalty,
-        extension_penalty,
-    ) {
+    if let Some((aln_target_str, aln_query_str)) = wfa_align_bases(&r_t_str, &r_q_str, &aln_params)
+    {
         /*
         // print out the alignment string for debugging
 
@@ -468,10 +836,47 @@ pub fn get_wfa_variant_segments(
 pub fn sw_align_bases(
     target_str: &str,
     query_str: &str,
-    mismatch_penalty: i32,
-    open_penalty: i32,
-    extension_penalty: i32,
+    aln_params: &AlnParams,
 ) -> Option<(String, String)> {
+    let (_, _, aln_target_str, aln_query_str) =
+        sw_align_bases_with_mode(target_str, query_str, AlnMode::Global, aln_params)?;
+    Some((aln_target_str, aln_query_str))
+}
+
+/// the alignment of [sw_align_bases] with the end gap semantics of `mode`, it returns the aligned target
+/// interval `(t_bgn, t_end)` and the alignment strings of the target bases in the interval and the query,
+/// the interval is the whole target for [AlnMode::Global]; the vectorized implementation
+/// [simd_sw_align_bases_with_mode] is used if the `simd_sw` feature is enabled
+pub fn sw_align_bases_with_mode(
+    target_str: &str,
+    query_str: &str,
+    mode: AlnMode,
+    aln_params: &AlnParams,
+) -> Option<(u32, u32, String, String)> {
+    #[cfg(feature = "simd_sw")]
+    {
+        simd_sw_align_bases_with_mode(target_str, query_str, mode, aln_params)
+    }
+    #[cfg(not(feature = "simd_sw"))]
+    {
+        scalar_sw_align_bases_with_mode(target_str, query_str, mode, aln_params)
+    }
+}
+
+#[cfg_attr(feature = "simd_sw", allow(dead_code))]
+fn scalar_sw_align_bases_with_mode(
+    target_str: &str,
+    query_str: &str,
+    mode: AlnMode,
+    aln_params: &AlnParams,
+) -> Option<(u32, u32, String, String)> {
+    let AlnParams {
+        match_score,
+        mismatch_penalty,
+        gap_open_penalty: open_penalty,
+        gap_extension_penalty: extension_penalty,
+        ..
+    } = *aln_params;
     let mut target_str = (*target_str).as_bytes().to_vec();
     let mut query_str = (*query_str).as_bytes().to_vec();
     target_str.reverse();
@@ -479,10 +884,10 @@ pub fn sw_align_bases(
     let t_len = target_str.len();
     let q_len = query_str.len();
 
-    // initial condition for j = 0
+    // initial condition for j = 0, the target bases before the query are free in the query-glocal mode
     let mut match_scores = (0..t_len + 1)
         .map(|i| {
-            if i == 0 {
+            if i == 0 || mode == AlnMode::QueryGlocal {
                 0
             } else {
                 -open_penalty - (i as i32) * extension_penalty
@@ -519,10 +924,10 @@ pub fn sw_align_bases(
 
         for i in 1..t_len + 1 {
             let s = p_match_score[i - 1]
-                - (if target_str[i - 1] == query_str[j - 1] {
-                    0
+                + (if target_str[i - 1] == query_str[j - 1] {
+                    match_score
                 } else {
-                    mismatch_penalty
+                    -mismatch_penalty
                 });
 
             let e = if e_scores[i - 1] == i32::MIN {
@@ -552,12 +957,23 @@ pub fn sw_align_bases(
             f_scores[i] = if o > f { o } else { f }
         }
     }
-    let mut t_pos = t_len;
+    // the query-glocal alignments end at the best scoring target position, the target bases after it are free
+    let mut t_pos = match mode {
+        AlnMode::Global => t_len,
+        AlnMode::QueryGlocal => (0..t_len + 1).fold(0, |best, i| {
+            if match_scores[i] > match_scores[best] {
+                i
+            } else {
+                best
+            }
+        }),
+    };
+    let t_aln_end = t_pos;
     let mut q_pos = q_len;
     let mut aln_t = Vec::<u8>::new();
     let mut aln_q = Vec::<u8>::new();
 
-    while t_pos != 0 || q_pos != 0 {
+    while q_pos != 0 || (t_pos != 0 && mode == AlnMode::Global) {
         let d = trace_back[t_pos][q_pos];
         if d.0 != 0 {
             t_pos -= 1;
@@ -575,7 +991,211 @@ pub fn sw_align_bases(
     //aln_t.reverse();
     //aln_q.reverse();
 
+    // the sequences are reversed, so the trace back ends at the beginning of the alignment
     Some((
+        (t_len - t_aln_end) as u32,
+        (t_len - t_pos) as u32,
+        String::from_utf8_lossy(&aln_t[..]).to_string(),
+        String::from_utf8_lossy(&aln_q[..]).to_string(),
+    ))
+}
+
+// the score of the cells out of the dynamic programming matrix, it is far from i32::MIN, so subtracting
+// the penalties from it does not overflow
+#[cfg(feature = "simd_sw")]
+const SIMD_SW_NEG_INF: i32 = i32::MIN / 2;
+
+/// the alignment of [sw_align_bases_with_mode] computed along the anti-diagonals of the dynamic programming
+/// matrix, the cells on an anti-diagonal are independent of each other, so the inner loop is vectorized, with
+/// AVX2 if the CPU supports it at runtime; the alignments are the same as the ones of the scalar implementation
+#[cfg(feature = "simd_sw")]
+pub fn simd_sw_align_bases_with_mode(
+    target_str: &str,
+    query_str: &str,
+    mode: AlnMode,
+    aln_params: &AlnParams,
+) -> Option<(u32, u32, String, String)> {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") {
+        // safe as the CPU supports AVX2
+        return unsafe {
+            anti_diagonal_sw_align_bases_avx2(
+                target_str.as_bytes(),
+                query_str.as_bytes(),
+                mode,
+                aln_params,
+            )
+        };
+    }
+    anti_diagonal_sw_align_bases(
+        target_str.as_bytes(),
+        query_str.as_bytes(),
+        mode,
+        aln_params,
+    )
+}
+
+#[cfg(all(feature = "simd_sw", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn anti_diagonal_sw_align_bases_avx2(
+    target_str: &[u8],
+    query_str: &[u8],
+    mode: AlnMode,
+    aln_params: &AlnParams,
+) -> Option<(u32, u32, String, String)> {
+    anti_diagonal_sw_align_bases(target_str, query_str, mode, aln_params)
+}
+
+// the same recurrences and tie-breaking as `scalar_sw_align_bases_with_mode()`, the cell (i, j) is on the
+// anti-diagonal k = i + j, the scores of the last three anti-diagonals are kept in the vectors indexed by i
+#[cfg(feature = "simd_sw")]
+#[inline(always)]
+fn anti_diagonal_sw_align_bases(
+    target_str: &[u8],
+    query_str: &[u8],
+    mode: AlnMode,
+    aln_params: &AlnParams,
+) -> Option<(u32, u32, String, String)> {
+    let AlnParams {
+        match_score,
+        mismatch_penalty,
+        gap_open_penalty: open_penalty,
+        gap_extension_penalty: extension_penalty,
+        ..
+    } = *aln_params;
+    // the target is reversed as the scalar implementation does, the query base of the cell (i, j) is
+    // query_str[q_len - j], so it is at q_len - k + i in the original query
+    let target_rev = target_str.iter().rev().copied().collect::<Vec<u8>>();
+    let t_len = target_str.len();
+    let q_len = query_str.len();
+    let n_diagonals = t_len + q_len + 1;
+    let diagonal_range = |k: usize| (k.saturating_sub(q_len), k.min(t_len));
+
+    let mut diagonal_offsets = Vec::<usize>::with_capacity(n_diagonals + 1);
+    diagonal_offsets.push(0);
+    (0..n_diagonals).for_each(|k| {
+        let (lo, hi) = diagonal_range(k);
+        diagonal_offsets.push(diagonal_offsets[k] + hi - lo + 1);
+    });
+    // the trace back codes: 0 for (-1, -1), 1 for (-1, 0) and 2 for (0, -1)
+    let mut trace_back = vec![0_u8; diagonal_offsets[n_diagonals]];
+
+    // the scores of the anti-diagonals k, k - 1 and k - 2
+    let mut h0 = vec![SIMD_SW_NEG_INF; t_len + 1];
+    let mut h1 = vec![SIMD_SW_NEG_INF; t_len + 1];
+    let mut h2 = vec![SIMD_SW_NEG_INF; t_len + 1];
+    let mut e0 = vec![SIMD_SW_NEG_INF; t_len + 1];
+    let mut e1 = vec![SIMD_SW_NEG_INF; t_len + 1];
+    let mut f0 = vec![SIMD_SW_NEG_INF; t_len + 1];
+    let mut f1 = vec![SIMD_SW_NEG_INF; t_len + 1];
+    // the scores of the cells (i, q_len) for the query-glocal alignments
+    let mut last_column = vec![SIMD_SW_NEG_INF; t_len + 1];
+    h1[0] = 0;
+    if q_len == 0 {
+        last_column[0] = 0;
+    }
+
+    for k in 1..n_diagonals {
+        let (lo, hi) = diagonal_range(k);
+        let offset = diagonal_offsets[k];
+        let gap_score = -open_penalty - (k as i32) * extension_penalty;
+        if lo == 0 {
+            // the cell (0, k)
+            h0[0] = gap_score;
+            e0[0] = SIMD_SW_NEG_INF;
+            f0[0] = gap_score;
+            trace_back[offset] = 2;
+        }
+        if hi == k {
+            // the cell (k, 0)
+            h0[k] = if mode == AlnMode::QueryGlocal {
+                0
+            } else {
+                gap_score
+            };
+            e0[k] = gap_score;
+            f0[k] = SIMD_SW_NEG_INF;
+            trace_back[offset + k - lo] = 1;
+        }
+
+        let bgn = lo.max(1);
+        let end = hi.min(k - 1) + 1;
+        if bgn < end {
+            let len = end - bgn;
+            let t_bases = &target_rev[bgn - 1..end - 1];
+            let q_bases = &query_str[q_len + bgn - k..q_len + end - k];
+            let p_h = &h2[bgn - 1..end - 1];
+            let p_e = &e1[bgn - 1..end - 1];
+            let p_f = &f1[bgn..end];
+            let h = &mut h0[bgn..end];
+            let e_out = &mut e0[bgn..end];
+            let f_out = &mut f0[bgn..end];
+            let codes = &mut trace_back[offset + bgn - lo..offset + end - lo];
+            assert!(t_bases.len() == len && q_bases.len() == len && codes.len() == len);
+            for n in 0..len {
+                let s = p_h[n]
+                    + if t_bases[n] == q_bases[n] {
+                        match_score
+                    } else {
+                        -mismatch_penalty
+                    };
+                let e = p_e[n] - extension_penalty;
+                let f = p_f[n] - extension_penalty;
+                // s > e && s > f is the same as s > max(e, f)
+                let (ef_code, ef) = if e > f { (1, e) } else { (2, f) };
+                let (code, score) = if s > ef { (0, s) } else { (ef_code, ef) };
+                codes[n] = code;
+                h[n] = score;
+                let o = score - open_penalty;
+                e_out[n] = if o > e { o } else { e };
+                f_out[n] = if o > f { o } else { f };
+            }
+        }
+        if k >= q_len {
+            last_column[lo] = h0[lo];
+        }
+
+        std::mem::swap(&mut h2, &mut h1);
+        std::mem::swap(&mut h1, &mut h0);
+        std::mem::swap(&mut e1, &mut e0);
+        std::mem::swap(&mut f1, &mut f0);
+    }
+
+    let mut t_pos = match mode {
+        AlnMode::Global => t_len,
+        AlnMode::QueryGlocal => (0..t_len + 1).fold(0, |best, i| {
+            if last_column[i] > last_column[best] {
+                i
+            } else {
+                best
+            }
+        }),
+    };
+    let t_aln_end = t_pos;
+    let mut q_pos = q_len;
+    let mut aln_t = Vec::<u8>::new();
+    let mut aln_q = Vec::<u8>::new();
+
+    while q_pos != 0 || (t_pos != 0 && mode == AlnMode::Global) {
+        let k = t_pos + q_pos;
+        let d = trace_back[diagonal_offsets[k] + t_pos - diagonal_range(k).0];
+        if d != 2 {
+            t_pos -= 1;
+            aln_t.push(target_rev[t_pos]);
+        } else {
+            aln_t.push(b'-');
+        };
+        if d != 1 {
+            aln_q.push(query_str[q_len - q_pos]);
+            q_pos -= 1;
+        } else {
+            aln_q.push(b'-');
+        }
+    }
+
+    Some((
+        (t_len - t_aln_end) as u32,
+        (t_len - t_pos) as u32,
         String::from_utf8_lossy(&aln_t[..]).to_string(),
         String::from_utf8_lossy(&aln_q[..]).to_string(),
     ))
@@ -585,57 +1205,383 @@ pub fn get_sw_variant_segments(
     target_str: &[u8],
     query_str: &[u8],
     left_padding: usize,
-    mismatch_penalty: i32,
-    open_penalty: i32,
-    extension_penalty: i32,
+    aln_params: &AlnParams,
 ) -> Option<AlignmentResult> {
     let t_str = target_str[left_padding..].to_vec();
     let q_str = query_str[left_padding..].to_vec();
     let t_str = String::from_utf8_lossy(&t_str[..]);
     let q_str = String::from_utf8_lossy(&q_str[..]);
 
-    if let Some((aln_target_str, aln_query_str)) = sw_align_bases(
-        &t_str,
-        &q_str,
+    let (aln_target_str, aln_query_str) = sw_align_bases(&t_str, &q_str, aln_params)?;
+    /*
+    // print out the alignment string for debugging
+
+    // let mut r_aln_target_str = aln_target_str.clone().as_bytes().to_owned();
+    // let mut r_aln_query_str = aln_query_str.clone().as_bytes().to_owned();
+    // r_aln_target_str.reverse();
+    // r_aln_query_str.reverse();
+    // let r_aln_target_str = String::from_utf8_lossy(&r_aln_target_str[..]);
+    // let r_aln_query_str = String::from_utf8_lossy(&r_aln_query_str[..]);
+    // println!("XX: {}", r_aln_target_str);
+    // println!("XX: {}", r_aln_query_str);
+    */
+    Some(padded_aln_to_variant_segments(
+        target_str,
+        query_str,
+        left_padding,
+        &aln_target_str,
+        &aln_query_str,
+    ))
+}
+
+// the variant segments of an alignment of the sequences without the first `left_padding` bases
+fn padded_aln_to_variant_segments(
+    target_str: &[u8],
+    query_str: &[u8],
+    left_padding: usize,
+    aln_target_str: &str,
+    aln_query_str: &str,
+) -> AlignmentResult {
+    let mut aln_pairs = Vec::<_>::new();
+    // assume the base on the left are identical  ( # of base = left_padding)
+    (0..left_padding).for_each(|delta| {
+        aln_pairs.push((delta as u32, delta as u32, 'M'));
+    });
+    aln_pairs.extend(
+        aln_pair_map(aln_target_str, aln_query_str)
+            .into_iter()
+            .map(|v| (v.0 + left_padding as u32, v.1 + left_padding as u32, v.2)),
+    );
+
+    // compute the VCF like variant representation
+    let target_str = String::from_utf8_lossy(target_str);
+    let query_str = String::from_utf8_lossy(query_str);
+    get_variants_from_aln_pair_map(&aln_pairs, &target_str, &query_str)
+}
+
+/// the number of the dynamic programming cells of [banded_sw_align_bases], the trace back uses one byte per cell
+pub fn banded_sw_cell_count(target_len: usize, query_len: usize, band_width: usize) -> usize {
+    (query_len + 1) * (target_len.abs_diff(query_len) + 2 * band_width + 1)
+}
+
+/// the alignment of [sw_align_bases] restricted to a band of the diagonals, the band covers the diagonals
+/// between the start and the end of the sequences and `band_width` more diagonals on each side, so a long
+/// indel is aligned as long as the rest of the alignment stays in the band; the memory usage is
+/// [banded_sw_cell_count] bytes instead of the product of the sequence lengths
+pub fn banded_sw_align_bases(
+    target_str: &str,
+    query_str: &str,
+    band_width: usize,
+    aln_params: &AlnParams,
+) -> Option<(String, String)> {
+    let AlnParams {
+        match_score,
         mismatch_penalty,
-        open_penalty,
-        extension_penalty,
-    ) {
-        /*
-        // print out the alignment string for debugging
+        gap_open_penalty: open_penalty,
+        gap_extension_penalty: extension_penalty,
+        ..
+    } = *aln_params;
+    let mut target_str = (*target_str).as_bytes().to_vec();
+    let mut query_str = (*query_str).as_bytes().to_vec();
+    target_str.reverse();
+    query_str.reverse();
+    let t_len = target_str.len();
+    let q_len = query_str.len();
 
-        // let mut r_aln_target_str = aln_target_str.clone().as_bytes().to_owned();
-        // let mut r_aln_query_str = aln_query_str.clone().as_bytes().to_owned();
-        // r_aln_target_str.reverse();
-        // r_aln_query_str.reverse();
-        // let r_aln_target_str = String::from_utf8_lossy(&r_aln_target_str[..]);
-        // let r_aln_query_str = String::from_utf8_lossy(&r_aln_query_str[..]);
-        // println!("XX: {}", r_aln_target_str);
-        // println!("XX: {}", r_aln_query_str);
-        */
+    // the band of the diagonals i - j in [d_min, d_max] for the target position i and the query position j
+    let d_min = (t_len as i64 - q_len as i64).min(0) - band_width as i64;
+    let d_max = (t_len as i64 - q_len as i64).max(0) + band_width as i64;
+    let n_diagonals = (d_max - d_min + 1) as usize;
+    let band_idx = |i: usize, j: usize| (i as i64 - j as i64 - d_min) as usize;
+    let i_range = |j: usize| {
+        (j as i64 + d_min).max(0) as usize..=(j as i64 + d_max).min(t_len as i64) as usize
+    };
 
-        let mut aln_pairs = Vec::<_>::new();
-        // assume the base on the left are identical  ( # of base = left_padding)
-        (0..left_padding).for_each(|delta| {
-            aln_pairs.push((delta as u32, delta as u32, 'M'));
+    // the trace back codes: 0 for (-1, -1), 1 for (-1, 0) and 2 for (0, -1)
+    let mut trace_back = vec![vec![0_u8; n_diagonals]; q_len + 1];
+    // one more cell at the end for the diagonal out of the band
+    let mut match_scores = vec![i32::MIN; n_diagonals + 1];
+    let mut e_scores = vec![i32::MIN; n_diagonals + 1];
+    let mut f_scores = vec![i32::MIN; n_diagonals + 1];
+
+    // initial condition for j = 0
+    i_range(0).for_each(|i| {
+        let b = band_idx(i, 0);
+        if i != 0 {
+            match_scores[b] = -open_penalty - (i as i32) * extension_penalty;
+            e_scores[b] = -open_penalty - (i as i32) * extension_penalty;
+            trace_back[0][b] = 1;
+        } else {
+            match_scores[b] = 0;
+        }
+    });
+
+    for j in 1..q_len + 1 {
+        let p_match_scores = std::mem::replace(&mut match_scores, vec![i32::MIN; n_diagonals + 1]);
+        let p_f_scores = std::mem::replace(&mut f_scores, vec![i32::MIN; n_diagonals + 1]);
+        e_scores.fill(i32::MIN);
+
+        for i in i_range(j) {
+            let b = band_idx(i, j);
+            if i == 0 {
+                match_scores[b] = -open_penalty - (j as i32) * extension_penalty;
+                f_scores[b] = -open_penalty - (j as i32) * extension_penalty;
+                trace_back[j][b] = 2;
+                continue;
+            }
+            // (i - 1, j - 1) is on the same diagonal, (i - 1, j) and (i, j - 1) are on the neighboring ones
+            let s = p_match_scores[b]
+                + (if target_str[i - 1] == query_str[j - 1] {
+                    match_score
+                } else {
+                    -mismatch_penalty
+                });
+
+            let e = if b == 0 || e_scores[b - 1] == i32::MIN {
+                i32::MIN
+            } else {
+                e_scores[b - 1] - extension_penalty
+            };
+
+            let f = if p_f_scores[b + 1] == i32::MIN {
+                i32::MIN
+            } else {
+                p_f_scores[b + 1] - extension_penalty
+            };
+
+            (trace_back[j][b], match_scores[b]) = if s > e && s > f {
+                (0, s)
+            } else if e > f {
+                (1, e)
+            } else {
+                (2, f)
+            };
+
+            let o = match_scores[b] - open_penalty;
+
+            e_scores[b] = if o > e { o } else { e };
+
+            f_scores[b] = if o > f { o } else { f }
+        }
+    }
+
+    let mut t_pos = t_len;
+    let mut q_pos = q_len;
+    let mut aln_t = Vec::<u8>::new();
+    let mut aln_q = Vec::<u8>::new();
+
+    while t_pos != 0 || q_pos != 0 {
+        let d = trace_back[q_pos][band_idx(t_pos, q_pos)];
+        if d != 2 {
+            t_pos -= 1;
+            aln_t.push(target_str[t_pos]);
+        } else {
+            aln_t.push(b'-');
+        };
+        if d != 1 {
+            q_pos -= 1;
+            aln_q.push(query_str[q_pos]);
+        } else {
+            aln_q.push(b'-');
+        }
+    }
+
+    Some((
+        String::from_utf8_lossy(&aln_t[..]).to_string(),
+        String::from_utf8_lossy(&aln_q[..]).to_string(),
+    ))
+}
+
+/// the variant segments from [banded_sw_align_bases], see [get_sw_variant_segments]
+pub fn get_banded_sw_variant_segments(
+    target_str: &[u8],
+    query_str: &[u8],
+    left_padding: usize,
+    band_width: usize,
+    aln_params: &AlnParams,
+) -> Option<AlignmentResult> {
+    let t_str = String::from_utf8_lossy(&target_str[left_padding..]);
+    let q_str = String::from_utf8_lossy(&query_str[left_padding..]);
+    let (aln_target_str, aln_query_str) =
+        banded_sw_align_bases(&t_str, &q_str, band_width, aln_params)?;
+    Some(padded_aln_to_variant_segments(
+        target_str,
+        query_str,
+        left_padding,
+        &aln_target_str,
+        &aln_query_str,
+    ))
+}
+
+/// align two sequences with the exact unique k-mer matches as anchors, the segments between
+/// the anchors are aligned with [sw_align_bases]; it returns None if the first bases are not
+/// identical or a segment between two anchors is longer than `max_gap_aln_size`
+pub fn get_anchored_variant_segments(
+    target_str: &[u8],
+    query_str: &[u8],
+    anchor_k: usize,
+    max_gap_aln_size: usize,
+    aln_params: &AlnParams,
+) -> Option<AlignmentResult> {
+    if target_str.is_empty() || query_str.is_empty() || target_str[0] != query_str[0] {
+        return None;
+    }
+
+    let unique_kmers = |s: &[u8]| {
+        let mut kmers = FxHashMap::<Vec<u8>, (u32, u32)>::default(); // kmer -> (position, count)
+        if s.len() >= anchor_k {
+            (0..=s.len() - anchor_k).for_each(|pos| {
+                let e = kmers
+                    .entry(s[pos..pos + anchor_k].to_vec())
+                    .or_insert((pos as u32, 0));
+                e.1 += 1;
+            });
+        }
+        kmers.retain(|_, (_, count)| *count == 1);
+        kmers
+    };
+    let t_kmers = unique_kmers(target_str);
+    let q_kmers = unique_kmers(query_str);
+    let mut anchors = t_kmers
+        .iter()
+        .filter_map(|(kmer, (t_pos, _))| q_kmers.get(kmer).map(|(q_pos, _)| (*t_pos, *q_pos)))
+        .collect::<Vec<(u32, u32)>>();
+    anchors.sort();
+
+    // the longest chain of the anchors increasing in both sequences
+    let mut tails = Vec::<usize>::new();
+    let mut previous = vec![None; anchors.len()];
+    anchors.iter().enumerate().for_each(|(idx, &(_, q_pos))| {
+        let p = tails.partition_point(|&i| anchors[i].1 < q_pos);
+        if p > 0 {
+            previous[idx] = Some(tails[p - 1]);
+        }
+        if p == tails.len() {
+            tails.push(idx);
+        } else {
+            tails[p] = idx;
+        }
+    });
+    let mut chain = Vec::<(u32, u32)>::new();
+    let mut idx = tails.last().copied();
+    while let Some(i) = idx {
+        chain.push(anchors[i]);
+        idx = previous[i];
+    }
+    chain.reverse();
+
+    let t_len = target_str.len() as u32;
+    let q_len = query_str.len() as u32;
+    let k = anchor_k as u32;
+    let mut aln_pairs = vec![(0_u32, 0_u32, 'M')];
+    let mut t_pos = 1_u32;
+    let mut q_pos = 1_u32;
+    for (t_anchor, q_anchor, anchor_len) in chain
+        .into_iter()
+        .map(|(t_anchor, q_anchor)| (t_anchor, q_anchor, k))
+        .chain(std::iter::once((t_len, q_len, 0)))
+    {
+        let (t_anchor, q_anchor, anchor_len) = if t_anchor < t_pos || q_anchor < q_pos {
+            // extend the matches if the anchor overlaps the last one on the same diagonal
+            if t_anchor as i64 - q_anchor as i64 == t_pos as i64 - q_pos as i64
+                && t_anchor + anchor_len > t_pos
+            {
+                (t_pos, q_pos, t_anchor + anchor_len - t_pos)
+            } else {
+                continue;
+            }
+        } else {
+            (t_anchor, q_anchor, anchor_len)
+        };
+
+        if t_anchor > t_pos || q_anchor > q_pos {
+            if (t_anchor - t_pos) as usize > max_gap_aln_size
+                || (q_anchor - q_pos) as usize > max_gap_aln_size
+            {
+                return None;
+            }
+            let t_str = String::from_utf8_lossy(&target_str[t_pos as usize..t_anchor as usize]);
+            let q_str = String::from_utf8_lossy(&query_str[q_pos as usize..q_anchor as usize]);
+            let (aln_target_str, aln_query_str) = sw_align_bases(&t_str, &q_str, aln_params)?;
+            aln_pairs.extend(
+                aln_pair_map(&aln_target_str, &aln_query_str)
+                    .into_iter()
+                    .map(|v| (v.0 + t_pos, v.1 + q_pos, v.2)),
+            );
+        }
+        (0..anchor_len).for_each(|delta| {
+            aln_pairs.push((t_anchor + delta, q_anchor + delta, 'M'));
         });
-        aln_pairs.extend(
-            aln_pair_map(&aln_target_str, &aln_query_str)
-                .into_iter()
-                .map(|v| (v.0 + left_padding as u32, v.1 + left_padding as u32, v.2)),
-        );
+        t_pos = t_anchor + anchor_len;
+        q_pos = q_anchor + anchor_len;
+    }
 
-        // compute the VCF like variant representation
-        let target_str = String::from_utf8_lossy(target_str);
-        let query_str = String::from_utf8_lossy(query_str);
-        Some(get_variants_from_aln_pair_map(
-            &aln_pairs,
-            &target_str,
-            &query_str,
-        ))
-    } else {
-        None
+    // compute the VCF like variant representation
+    let target_str = String::from_utf8_lossy(target_str);
+    let query_str = String::from_utf8_lossy(query_str);
+    Some(get_variants_from_aln_pair_map(
+        &aln_pairs,
+        &target_str,
+        &query_str,
+    ))
+}
+
+/// extend a gapped alignment from the beginning of the two sequences with an x-drop rule (match: +1, mismatch or
+/// gap: -2) in a band of `band_width` diagonals on each side of the main diagonal, the extension stops once the score
+/// drops more than `x_drop` below the best score; it returns the extended lengths of the target and the query,
+/// the extension always ends with a match
+pub fn x_drop_extend(
+    target_str: &[u8],
+    query_str: &[u8],
+    x_drop: i32,
+    band_width: usize,
+) -> (u32, u32) {
+    let gap = 2_i32;
+    let neg_inf = i32::MIN / 2;
+    let width = 2 * band_width + 1;
+    // the cell (i, j) is stored at row[j + band_width - i]
+    let mut prev = vec![neg_inf; width];
+    (0..=band_width.min(target_str.len())).for_each(|j| prev[band_width + j] = -gap * j as i32);
+    let mut best = (0_i32, 0_u32, 0_u32);
+    for i in 1..=query_str.len() {
+        let mut cur = vec![neg_inf; width];
+        let mut row_max = neg_inf;
+        for k in 0..width {
+            let j = i as i64 + k as i64 - band_width as i64;
+            if j < 0 || j > target_str.len() as i64 {
+                continue;
+            }
+            let j = j as usize;
+            let mut score = neg_inf;
+            if j > 0 {
+                let s = if target_str[j - 1] == query_str[i - 1] {
+                    1
+                } else {
+                    -2
+                };
+                score = score.max(prev[k] + s);
+            }
+            if k + 1 < width {
+                score = score.max(prev[k + 1] - gap);
+            }
+            if k > 0 {
+                score = score.max(cur[k - 1] - gap);
+            }
+            if score < best.0 - x_drop {
+                score = neg_inf;
+            }
+            cur[k] = score;
+            row_max = row_max.max(score);
+            if score > best.0 {
+                best = (score, j as u32, i as u32);
+            }
+        }
+        if row_max == neg_inf {
+            break;
+        }
+        prev = cur;
     }
+    (best.1, best.2)
 }
 
 #[cfg(test)]
@@ -665,14 +1611,42 @@ mod test {
         });
         let oriented = false;
         let max_gap = None;
-        let out = sparse_aln(&mut hp, 8, 0.5_f32, max_gap, oriented);
+        let out = sparse_aln(&mut hp, 8, 0.5_f32.into(), max_gap, oriented);
         out.iter().for_each(|(s, v)| println!("{} {}", s, v.len()));
         // TODO: Test the output properly
     }
 
+    #[test]
+    fn test_piecewise_chain_gap_cost() {
+        use crate::aln::{sparse_aln, ChainGapCost, HitPair};
+        let gap_cost = ChainGapCost::Piecewise {
+            penalty: 0.5,
+            break_len: 100,
+            long_penalty: 0.01,
+        };
+        assert_eq!(gap_cost.cost(50.0), 25.0);
+        assert_eq!(gap_cost.cost(5100.0), 100.0);
+        assert_eq!(ChainGapCost::from(0.5).cost(5100.0), 2550.0);
+
+        // two runs of hits separated by a 5 kb deletion in the query
+        let hp = (0..20_u32)
+            .map(|i| {
+                let t_bgn = if i < 10 { i * 100 } else { i * 100 + 5000 };
+                ((i * 100, i * 100 + 100, 0), (t_bgn, t_bgn + 100, 0))
+            })
+            .collect::<Vec<HitPair>>();
+        let out = sparse_aln(&mut hp.clone(), 8, 0.5.into(), None, true);
+        assert_eq!(out.len(), 2);
+        let out = sparse_aln(&mut hp.clone(), 8, gap_cost, None, true);
+        assert_eq!(out.len(), 1);
+        assert_eq!(out[0].1.len(), 20);
+    }
+
     #[test]
     fn test_wfa_align_bases() {
-        use crate::aln::{aln_pair_map, get_variants_from_aln_pair_map, wfa_align_bases};
+        use crate::aln::{
+            aln_pair_map, get_variants_from_aln_pair_map, wfa_align_bases, AlnParams,
+        };
         use log::debug;
         //use simple_logger::SimpleLogger;
         //SimpleLogger::new().init().unwrap();
@@ -680,7 +1654,14 @@ mod test {
         let q_str = "ACATACATGTGAAATATAATAAAAGTTGCATGAAAAAACATACATGAAAGTTGCATGAAACATACATGAAAAAAGTTGCAAAAGTTGCATGAAACATACATGAAAATGAAAAAACATACATGAAAGTTGCATGAA";
         
         // Test that alignment works and produces a result
-        let result = wfa_align_bases(t_str, q_str, 20, 2, 2, 1);
+        let aln_params = AlnParams {
+            mismatch_penalty: 2,
+            gap_open_penalty: 2,
+            gap_extension_penalty: 1,
+            max_wf_length: Some(20),
+            ..AlnParams::default()
+        };
+        let result = wfa_align_bases(t_str, q_str, &aln_params);
         assert!(result.is_some(), "WFA alignment should produce a result");
         
         if let Some((t_aln_str, q_aln_str)) = result {
@@ -719,7 +1700,8 @@ mod test {
     #[test]
     fn test_wfa_aggreate_variant() {
         use crate::aln::{
-            aln_pair_map, get_variants_from_aln_pair_map, get_wfa_variant_segments, wfa_align_bases,
+            aln_pair_map, get_variants_from_aln_pair_map, get_wfa_variant_segments,
+            wfa_align_bases, AlnParams,
         };
         use log::debug;
         //use simple_logger::SimpleLogger;
@@ -729,8 +1711,15 @@ mod test {
         let q_str =
             "ACGGAGGTGAGCCTGGGAGCATAGAGGTGGGCCTGGGAGCATGGCGGTGGGGGGGGGCCTGGGAGCACAGGGCGGGCC";
 
+        let aln_params = AlnParams {
+            mismatch_penalty: 3,
+            gap_open_penalty: 3,
+            gap_extension_penalty: 1,
+            max_wf_length: Some(128),
+            ..AlnParams::default()
+        };
         if let Some(aln_res) =
-            get_wfa_variant_segments(t_str.as_bytes(), q_str.as_bytes(), 1, Some(128), 3, 3, 1)
+            get_wfa_variant_segments(t_str.as_bytes(), q_str.as_bytes(), 1, &aln_params)
         {
             aln_res
                 .into_iter()
@@ -741,13 +1730,19 @@ mod test {
 
     #[test]
     fn test_sw_align_bases() {
-        use crate::aln::{aln_pair_map, get_variants_from_aln_pair_map, sw_align_bases};
+        use crate::aln::{aln_pair_map, get_variants_from_aln_pair_map, sw_align_bases, AlnParams};
         use log::debug;
         //use simple_logger::SimpleLogger;
         //SimpleLogger::new().init().unwrap();
         let t_str = "ACATACATGTGTGTGAAAAATATATAAGTAAAAAAAATGCATGAAACCCCAAAAGTTGCATGAAACATACATGAAAATACATGAAAGTTGCATGAAACATACATGAAAAAAGTTGCATGAAACCCCATACATGAAAGTTGCATGAA";
         let q_str = "ACATACATGTGAAATATAATAAAAGTTGCATGAAAAAACATACATGAAAGTTGCATGAAACATACATGAAAAAAGTTGCAAAAGTTGCATGAAACATACATGAAAATGAAAAAACATACATGAAAGTTGCATGAA";
-        if let Some((t_aln_str, q_aln_str)) = sw_align_bases(t_str, q_str, 2, 2, 1) {
+        let aln_params = AlnParams {
+            mismatch_penalty: 2,
+            gap_open_penalty: 2,
+            gap_extension_penalty: 1,
+            ..AlnParams::default()
+        };
+        if let Some((t_aln_str, q_aln_str)) = sw_align_bases(t_str, q_str, &aln_params) {
             println!("{}", t_aln_str);
             println!("{}", q_aln_str);
             let aln_pairs = aln_pair_map(&t_aln_str, &q_aln_str);
@@ -759,10 +1754,98 @@ mod test {
         // TODO: Test the output properly
     }
 
+    #[test]
+    fn test_sw_align_bases_with_mode() {
+        use crate::aln::{aln_mode_from_str, sw_align_bases_with_mode, AlnMode, AlnParams};
+        let mut state = 13579_u64;
+        let t_str = (0..300)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                b"ACGT"[(state >> 62) as usize]
+            })
+            .collect::<Vec<u8>>();
+        // a query from the middle of the target with a mismatch and a 2 base insertion
+        let mut q_str = t_str[100..200].to_vec();
+        q_str[30] = if q_str[30] == b'A' { b'C' } else { b'A' };
+        q_str.splice(60..60, b"GG".iter().copied());
+        let t_str = String::from_utf8(t_str).unwrap();
+        let q_str = String::from_utf8(q_str).unwrap();
+        let aln_params = AlnParams::default();
+
+        let (t_bgn, t_end, t_aln_str, q_aln_str) = sw_align_bases_with_mode(
+            &t_str,
+            &q_str,
+            aln_mode_from_str("query-glocal").unwrap(),
+            &aln_params,
+        )
+        .unwrap();
+        assert_eq!((t_bgn, t_end), (100, 200));
+        assert_eq!(t_aln_str.replace('-', ""), &t_str[100..200]);
+        assert_eq!(q_aln_str.replace('-', ""), q_str);
+        assert_eq!(t_aln_str.matches('-').count(), 2);
+
+        let (t_bgn, t_end, t_aln_str, q_aln_str) =
+            sw_align_bases_with_mode(&t_str, &q_str, AlnMode::Global, &aln_params).unwrap();
+        assert_eq!((t_bgn, t_end), (0, 300));
+        assert_eq!(t_aln_str.replace('-', ""), t_str);
+        assert_eq!(q_aln_str.replace('-', ""), q_str);
+        assert!(aln_mode_from_str("local").is_err());
+    }
+
+    #[cfg(feature = "simd_sw")]
+    #[test]
+    fn test_simd_sw_align_bases() {
+        use crate::aln::{
+            scalar_sw_align_bases_with_mode, simd_sw_align_bases_with_mode, AlnMode, AlnParams,
+        };
+        let mut state = 24680_u64;
+        let mut random_number = || {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 33) as usize
+        };
+        let aln_params = AlnParams {
+            match_score: 1,
+            ..AlnParams::default()
+        };
+        (0..100).for_each(|_| {
+            let t_len = random_number() % 200;
+            let t_str = (0..t_len)
+                .map(|_| b"ACGT"[random_number() % 4])
+                .collect::<Vec<u8>>();
+            // a mutated copy of a part of the target as the query
+            let bgn = random_number() % (t_len + 1);
+            let end = bgn + random_number() % (t_len - bgn + 1);
+            let mut q_str = Vec::<u8>::new();
+            t_str[bgn..end]
+                .iter()
+                .for_each(|&c| match random_number() % 20 {
+                    0 => q_str.push(b"ACGT"[random_number() % 4]),
+                    1 => (),
+                    2 => q_str.extend([c, b"ACGT"[random_number() % 4]]),
+                    _ => q_str.push(c),
+                });
+            let t_str = String::from_utf8(t_str).unwrap();
+            let q_str = String::from_utf8(q_str).unwrap();
+            [AlnMode::Global, AlnMode::QueryGlocal]
+                .into_iter()
+                .for_each(|mode| {
+                    assert_eq!(
+                        simd_sw_align_bases_with_mode(&t_str, &q_str, mode, &aln_params),
+                        scalar_sw_align_bases_with_mode(&t_str, &q_str, mode, &aln_params)
+                    );
+                });
+        });
+    }
+
     #[test]
     fn test_sw_aggreate_variant() {
         use crate::aln::{
             aln_pair_map, get_sw_variant_segments, get_variants_from_aln_pair_map, sw_align_bases,
+            AlnParams,
         };
         use log::debug;
         //use simple_logger::SimpleLogger;
@@ -772,8 +1855,14 @@ mod test {
         let q_str =
             "ACGGAGGTGAGCCTGGGAGCATAGAGGTGGGCCTGGGAGCATGGCGGTGGGGGGGGGCCTGGGAGCACAGGGCGGGCC";
 
+        let aln_params = AlnParams {
+            mismatch_penalty: 3,
+            gap_open_penalty: 3,
+            gap_extension_penalty: 1,
+            ..AlnParams::default()
+        };
         if let Some(aln_res) =
-            get_sw_variant_segments(t_str.as_bytes(), q_str.as_bytes(), 1, 3, 3, 1)
+            get_sw_variant_segments(t_str.as_bytes(), q_str.as_bytes(), 1, &aln_params)
         {
             aln_res
                 .into_iter()
@@ -781,4 +1870,200 @@ mod test {
         };
         // TODO: Test the output properly
     }
+
+    #[test]
+    fn test_anchored_variant_segments() {
+        use crate::aln::{get_anchored_variant_segments, AlnParams};
+        // a pseudo-random sequence such that the k-mers are unique
+        let mut state = 12345_u64;
+        let mut random_seq = |len: usize| {
+            (0..len)
+                .map(|_| {
+                    state = state
+                        .wrapping_mul(6364136223846793005)
+                        .wrapping_add(1442695040888963407);
+                    b"ACGT"[(state >> 62) as usize]
+                })
+                .collect::<Vec<u8>>()
+        };
+        let t_str = random_seq(2000);
+        let mut insertion = random_seq(300);
+        // keep the insertion boundaries unambiguous
+        insertion[0] = if t_str[1000] == b'A' { b'C' } else { b'A' };
+        insertion[299] = if t_str[999] == b'A' { b'C' } else { b'A' };
+        let mut q_str = t_str[..1000].to_vec();
+        q_str.extend(insertion.iter());
+        q_str.extend(t_str[1000..].iter());
+        q_str[1500] = if q_str[1500] == b'A' { b'C' } else { b'A' };
+
+        let variants =
+            get_anchored_variant_segments(&t_str, &q_str, 24, 1024, &AlnParams::default()).unwrap();
+        assert_eq!(variants.len(), 2);
+        let (td, qd, vt, t_variant, q_variant) = &variants[0];
+        assert_eq!((*td, *qd, *vt), (999, 999, 'I'));
+        assert_eq!(t_variant.len(), 1);
+        assert_eq!(q_variant.len(), 301);
+        let (td, qd, vt, t_variant, q_variant) = &variants[1];
+        assert_eq!((*td, *qd, *vt), (1200, 1500, 'X'));
+        assert_eq!(t_variant.as_bytes()[0], t_str[1200]);
+        assert_eq!(q_variant.as_bytes()[0], q_str[1500]);
+    }
+
+    #[test]
+    fn test_banded_sw_variant_segments() {
+        use crate::aln::{
+            banded_sw_align_bases, get_banded_sw_variant_segments, sw_align_bases, AlnParams,
+        };
+        let mut state = 54321_u64;
+        let mut random_seq = |len: usize| {
+            (0..len)
+                .map(|_| {
+                    state = state
+                        .wrapping_mul(6364136223846793005)
+                        .wrapping_add(1442695040888963407);
+                    b"ACGT"[(state >> 62) as usize]
+                })
+                .collect::<Vec<u8>>()
+        };
+
+        // the banded alignment is the same as the full one if the band covers the whole matrix
+        let t_str = String::from_utf8(random_seq(120)).unwrap();
+        let q_str = format!(
+            "{}{}{}",
+            &t_str[..40],
+            String::from_utf8(random_seq(30)).unwrap(),
+            &t_str[43..]
+        );
+        let aln_params = AlnParams::default();
+        assert_eq!(
+            banded_sw_align_bases(&t_str, &q_str, 200, &aln_params),
+            sw_align_bases(&t_str, &q_str, &aln_params)
+        );
+
+        let t_str = random_seq(10000);
+        let mut insertion = random_seq(1000);
+        // keep the insertion boundaries unambiguous
+        insertion[0] = if t_str[4000] == b'A' { b'C' } else { b'A' };
+        insertion[999] = if t_str[3999] == b'A' { b'C' } else { b'A' };
+        let mut q_str = t_str[..4000].to_vec();
+        q_str.extend(insertion.iter());
+        q_str.extend(t_str[4000..].iter());
+        q_str[8000] = if q_str[8000] == b'A' { b'C' } else { b'A' };
+
+        let variants = get_banded_sw_variant_segments(&t_str, &q_str, 1, 32, &aln_params).unwrap();
+        assert_eq!(variants.len(), 2);
+        let (td, qd, vt, t_variant, q_variant) = &variants[0];
+        assert_eq!((*td, *qd, *vt), (3999, 3999, 'I'));
+        assert_eq!(t_variant.len(), 1);
+        assert_eq!(q_variant.len(), 1001);
+        let (td, qd, vt, _, _) = &variants[1];
+        assert_eq!((*td, *qd, *vt), (7000, 8000, 'X'));
+    }
+
+    #[test]
+    fn test_x_drop_extend() {
+        use crate::aln::x_drop_extend;
+        let mut state = 2024_u64;
+        let mut random_seq = |len: usize| {
+            (0..len)
+                .map(|_| {
+                    state = state
+                        .wrapping_mul(6364136223846793005)
+                        .wrapping_add(1442695040888963407);
+                    b"ACGT"[(state >> 62) as usize]
+                })
+                .collect::<Vec<u8>>()
+        };
+        let shared = random_seq(600);
+        let mut t_str = shared.clone();
+        t_str.extend(random_seq(400));
+        // a deletion and a mismatch in the shared part of the query
+        let mut q_str = shared[..200].to_vec();
+        q_str.extend(shared[210..].iter());
+        q_str[300] = if q_str[300] == b'A' { b'C' } else { b'A' };
+        q_str.extend(random_seq(400));
+
+        let (t_ext, q_ext) = x_drop_extend(&t_str, &q_str, 32, 64);
+        assert!((595..620).contains(&t_ext));
+        assert_eq!(t_ext - q_ext, 10);
+        assert_eq!(t_str[t_ext as usize - 1], q_str[q_ext as usize - 1]);
+
+        // nothing to extend without a match
+        assert_eq!(x_drop_extend(b"AAAA", b"CCCC", 32, 64), (0, 0));
+    }
+
+    #[test]
+    fn test_hit_chain_mapqs() {
+        use crate::aln::{chain_mapq, hit_chain_mapqs};
+        assert_eq!(chain_mapq(100.0, 0.0), 60);
+        assert_eq!(chain_mapq(100.0, 50.0), 30);
+        assert_eq!(chain_mapq(100.0, 100.0), 0);
+        assert_eq!(chain_mapq(0.0, 0.0), 0);
+
+        // (target_id, score, query_bgn, query_end)
+        let chains = [
+            (0, 100.0, 0, 1000),
+            (0, 25.0, 100, 900),
+            (1, 50.0, 0, 1000),
+            (1, 80.0, 5000, 6000),
+            (2, 40.0, 5900, 7000),
+        ];
+        assert_eq!(
+            hit_chain_mapqs(&chains),
+            vec![(45, 30), (0, 0), (60, 0), (60, 60), (60, 60)]
+        );
+    }
+
+    #[test]
+    fn test_label_hit_chains() {
+        use crate::aln::{label_hit_chains, ChainLabel};
+        // (score, query_bgn, query_end)
+        let chains = [
+            (50.0, 0, 1000),
+            (100.0, 100, 1100),
+            (80.0, 5000, 6000),
+            (30.0, 900, 1800),
+            (40.0, 0, 1000),
+        ];
+        assert_eq!(
+            label_hit_chains(&chains, None),
+            vec![
+                Some(ChainLabel::Secondary),
+                Some(ChainLabel::Primary),
+                Some(ChainLabel::Supplementary),
+                Some(ChainLabel::Supplementary),
+                Some(ChainLabel::Secondary),
+            ]
+        );
+        assert_eq!(
+            label_hit_chains(&chains, Some(1)),
+            vec![
+                Some(ChainLabel::Secondary),
+                Some(ChainLabel::Primary),
+                Some(ChainLabel::Supplementary),
+                Some(ChainLabel::Supplementary),
+                None,
+            ]
+        );
+        assert_eq!(ChainLabel::Supplementary.to_string(), "supplementary");
+    }
+
+    #[test]
+    fn test_variant_segments_to_cigar() {
+        use crate::aln::{
+            cigar_ops_to_md_tag, cigar_ops_to_string, get_sw_variant_segments,
+            variant_segments_to_cigar_ops, AlnParams,
+        };
+        let t_str = b"ACGTACGTTTGCAGGCATCGATCGGATCGA";
+        // a mismatch, a 3 base insertion and a 1 base deletion
+        let q_str = b"ACGTACCTTTGCAGGAAACATCGATCGATCGA";
+        let variants = get_sw_variant_segments(t_str, q_str, 1, &AlnParams::default()).unwrap();
+        let ops = variant_segments_to_cigar_ops(&variants, t_str.len() as u32, q_str.len() as u32)
+            .unwrap();
+        let cigar = cigar_ops_to_string(&ops, false);
+        assert_eq!(cigar, "6=1X8=3I8=1D6=");
+        assert_eq!(cigar_ops_to_string(&ops, true), "15M3I8M1D6M");
+        assert_eq!(cigar_ops_to_md_tag(&ops, t_str), "6G16^G6");
+        assert!(variant_segments_to_cigar_ops(&variants, t_str.len() as u32, 10).is_none());
+    }
 }
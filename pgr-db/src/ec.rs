@@ -1,15 +1,15 @@
 #![warn(missing_docs)]
 //! function for error correction
 
-use crate::aln::query_fragment_to_hps;
+use crate::aln::{query_fragment_to_hps, HitPair};
 use crate::fasta_io::reverse_complement;
 use crate::graph_utils::{ShmmrGraphNode, WeightedNode};
 use crate::seq_db::{self, raw_query_fragment, CompactSeqDB, GetSeq};
-use crate::shmmrutils::{sequence_to_shmmrs, ShmmrSpec};
+use crate::shmmrutils::{match_reads, sequence_to_shmmrs, ShmmrSpec};
 use petgraph::algo::toposort;
 use petgraph::EdgeDirection::Outgoing;
 use petgraph::{graphmap::DiGraphMap, EdgeDirection::Incoming};
-use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+use rayon::prelude::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
 use rustc_hash::{FxHashMap, FxHashSet};
 
 /// perform error correction using de Bruijn graph
@@ -156,13 +156,8 @@ pub fn shmmr_dbg_consensus(
     seqs: Vec<Vec<u8>>,
     shmmr_spec: &Option<ShmmrSpec>,
 ) -> Result<Vec<(Vec<u8>, Vec<u32>)>, &'static str> {
-    let shmmr_spec = shmmr_spec.as_ref().unwrap_or(&ShmmrSpec {
-        w: 31,
-        k: 31,
-        r: 1,
-        min_span: 0,
-        sketch: false,
-    });
+    let default_spec = ShmmrSpec::new(31, 31, 1, 0, false);
+    let shmmr_spec = shmmr_spec.as_ref().unwrap_or(&default_spec);
     assert!(shmmr_spec.k % 2 == 1); // the k needs to odd to break symmetry
     assert!(shmmr_spec.min_span == 0); // if min_span != 0, we don't get consistent path
     let mut sdb = seq_db::CompactSeqDB::new(shmmr_spec.clone());
@@ -275,13 +270,8 @@ pub fn guided_shmmr_dbg_consensus(
     shmmr_spec: &Option<ShmmrSpec>,
     min_cov: u32,
 ) -> Result<(Vec<u8>, Vec<u32>), &'static str> {
-    let shmmr_spec = shmmr_spec.as_ref().unwrap_or(&ShmmrSpec {
-        w: 31,
-        k: 31,
-        r: 1,
-        min_span: 0,
-        sketch: false,
-    });
+    let default_spec = ShmmrSpec::new(31, 31, 1, 0, false);
+    let shmmr_spec = shmmr_spec.as_ref().unwrap_or(&default_spec);
     assert!(shmmr_spec.k % 2 == 1); // the k needs to odd to break symmetry
     assert!(shmmr_spec.min_span == 0); // if min_span != 0, we don't get consistent path
     let mut sdb = seq_db::CompactSeqDB::new(shmmr_spec.clone());
@@ -480,13 +470,8 @@ pub fn shmmr_sparse_aln_consensus(
     shmmr_spec: &Option<ShmmrSpec>,
     min_cov: u32,
 ) -> Result<Vec<(Vec<u8>, Vec<u32>)>, &'static str> {
-    let shmmr_spec = shmmr_spec.as_ref().unwrap_or(&ShmmrSpec {
-        w: 33,
-        k: 33,
-        r: 1,
-        min_span: 0,
-        sketch: false,
-    });
+    let default_spec = ShmmrSpec::new(33, 33, 1, 0, false);
+    let shmmr_spec = shmmr_spec.as_ref().unwrap_or(&default_spec);
     assert!(shmmr_spec.k % 2 == 1); // the k needs to odd to break symmetry
     assert!(shmmr_spec.min_span == 0); // if min_span != 0, we don't get consistent path
     let mut sdb = seq_db::CompactSeqDB::new(shmmr_spec.clone());
@@ -534,7 +519,7 @@ pub fn shmmr_sparse_aln_consensus_with_sdb(
             raw_query_hits,
             &seq0,
             shmmr_spec,
-            0.1,
+            0.1.into(),
             Some(32),
             Some(32),
             Some(32),
@@ -681,10 +666,274 @@ pub fn shmmr_sparse_aln_consensus_with_sdb(
     Ok(out)
 }
 
+// the default shimmer spec of the guided consensus for polishing the SV candidates and correcting the reads
+const GUIDED_CONSENSUS_SHMMR_SPEC: ShmmrSpec = ShmmrSpec::new(31, 31, 1, 0, false);
+
+/// the polished alleles of a SV candidate, both alleles start with the same padding base as the VCF alleles
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PolishedSvAllele {
+    /// the offset of the padding base in the reference segment
+    pub t_offset: usize,
+    /// the reference allele
+    pub ref_allele: Vec<u8>,
+    /// the polished alternative allele
+    pub alt_allele: Vec<u8>,
+    /// the min coverage of the consensus of the query segment and the reads, "1" if no read is used
+    pub support: u32,
+}
+
+// replace the part of the guide sequence covered by the consensus, the consensus may start and end
+// out of the guide sequence, so it is clipped to the first and the last k-mers shared with the guide
+// sequence, only the k-mers unique in the guide sequence are used, `None` if there is no shared k-mer
+fn splice_consensus(guide: &[u8], consensus: &[u8], k: usize) -> Option<Vec<u8>> {
+    if consensus.len() < k || guide.len() < k {
+        return None;
+    }
+    // the positions of the k-mers in the guide sequence, `None` for the repetitive ones
+    let mut kmer_positions = FxHashMap::<&[u8], Option<usize>>::default();
+    guide.windows(k).enumerate().for_each(|(pos, kmer)| {
+        kmer_positions
+            .entry(kmer)
+            .and_modify(|e| *e = None)
+            .or_insert(Some(pos));
+    });
+    let guide_pos = |kmer: &[u8]| kmer_positions.get(kmer).copied().flatten();
+    let (c_bgn, g_bgn) = consensus
+        .windows(k)
+        .enumerate()
+        .find_map(|(pos, kmer)| guide_pos(kmer).map(|g_pos| (pos, g_pos)))?;
+    let (c_end, g_end) = consensus
+        .windows(k)
+        .enumerate()
+        .rev()
+        .find_map(|(pos, kmer)| guide_pos(kmer).map(|g_pos| (pos + k, g_pos + k)))?;
+    if c_bgn >= c_end || g_bgn >= g_end {
+        return None;
+    }
+    let mut seq = guide[..g_bgn].to_vec();
+    seq.extend_from_slice(&consensus[c_bgn..c_end]);
+    seq.extend_from_slice(&guide[g_end..]);
+    Some(seq)
+}
+
+/// polish the alternative allele of a SV candidate with the guided shimmer de Bruijn graph consensus
+/// of the query segment and the reads, and trim the alleles to the shared flanking sequences of the
+/// polished allele and the reference segment
+///
+/// the reference segment `t_seq` and the query segment `q_seq` are expected to start with the same base
+/// (e.g., the reference base before the SV candidate) used as the padding base of the alleles, the query
+/// segment is used as it is if there is no read or the consensus can't be placed in the query segment
+///
+/// return `None` if the segments do not start with the same base or the alleles are the same after polishing
+pub fn polish_sv_candidate(
+    t_seq: &[u8],
+    q_seq: &[u8],
+    reads: Vec<Vec<u8>>,
+    shmmr_spec: &Option<ShmmrSpec>,
+    min_cov: u32,
+) -> Option<PolishedSvAllele> {
+    if t_seq.is_empty() || q_seq.is_empty() || t_seq[0] != q_seq[0] {
+        return None;
+    }
+    let shmmr_spec = shmmr_spec.clone().unwrap_or(GUIDED_CONSENSUS_SHMMR_SPEC);
+    let k = shmmr_spec.k as usize;
+    // the guided consensus needs at least two shimmer pairs in the query segment to start
+    let n_shmmrs = sequence_to_shmmrs(0, &q_seq.to_vec(), &shmmr_spec, false).len();
+    let consensus = if !reads.is_empty() && n_shmmrs > 2 {
+        let mut seqs = vec![q_seq.to_vec()];
+        seqs.extend(reads);
+        guided_shmmr_dbg_consensus(seqs, &Some(shmmr_spec), min_cov).ok()
+    } else {
+        None
+    };
+    let (alt_seq, support) = consensus
+        .and_then(|(seq, cov)| {
+            let support = cov.iter().min().copied().unwrap_or(1);
+            splice_consensus(q_seq, &seq, k).map(|seq| (seq, support))
+        })
+        .unwrap_or_else(|| (q_seq.to_vec(), 1));
+
+    // the shared prefix includes the padding base at least
+    let prefix_len = t_seq
+        .iter()
+        .zip(alt_seq.iter())
+        .take_while(|(t, a)| t == a)
+        .count();
+    let max_suffix_len = t_seq.len().min(alt_seq.len()) - prefix_len;
+    let suffix_len = t_seq
+        .iter()
+        .rev()
+        .zip(alt_seq.iter().rev())
+        .take(max_suffix_len)
+        .take_while(|(t, a)| t == a)
+        .count();
+    let t_offset = prefix_len - 1;
+    let ref_allele = t_seq[t_offset..t_seq.len() - suffix_len].to_vec();
+    let alt_allele = alt_seq[t_offset..alt_seq.len() - suffix_len].to_vec();
+    if ref_allele == alt_allele {
+        return None;
+    }
+    Some(PolishedSvAllele {
+        t_offset,
+        ref_allele,
+        alt_allele,
+        support,
+    })
+}
+
+/// polish a list of the SV candidates `(t_seq, q_seq)` with [polish_sv_candidate] in parallel, the reads
+/// sharing at least `min_cov` shimmer pairs with the query segment of a candidate are used for the candidate
+pub fn polish_sv_candidates(
+    candidates: &[(Vec<u8>, Vec<u8>)],
+    reads: &[Vec<u8>],
+    shmmr_spec: &Option<ShmmrSpec>,
+    min_cov: u32,
+) -> Vec<Option<PolishedSvAllele>> {
+    let spec = shmmr_spec.clone().unwrap_or(GUIDED_CONSENSUS_SHMMR_SPEC);
+    let shmmr_pairs = |seq: &[u8]| {
+        let shmmrs = sequence_to_shmmrs(0, &seq.to_vec(), &spec, false);
+        seq_db::pair_shmmrs(&shmmrs)
+            .into_iter()
+            .map(|(s0, s1)| {
+                let (s0, s1) = (s0.hash(), s1.hash());
+                if s0 <= s1 {
+                    (s0, s1)
+                } else {
+                    (s1, s0)
+                }
+            })
+            .collect::<FxHashSet<(u64, u64)>>()
+    };
+
+    let mut candidate_reads = vec![Vec::<usize>::new(); candidates.len()];
+    if !reads.is_empty() {
+        let mut pair_to_candidates = FxHashMap::<(u64, u64), Vec<usize>>::default();
+        candidates.iter().enumerate().for_each(|(cid, (_, q_seq))| {
+            shmmr_pairs(q_seq).into_iter().for_each(|pair| {
+                pair_to_candidates.entry(pair).or_default().push(cid);
+            })
+        });
+        reads
+            .par_iter()
+            .enumerate()
+            .map(|(rid, read)| {
+                let mut hits = FxHashMap::<usize, u32>::default();
+                shmmr_pairs(read).into_iter().for_each(|pair| {
+                    if let Some(cids) = pair_to_candidates.get(&pair) {
+                        cids.iter()
+                            .for_each(|&cid| *hits.entry(cid).or_default() += 1);
+                    }
+                });
+                hits.into_iter()
+                    .filter(|&(_, count)| count >= min_cov)
+                    .map(|(cid, _)| (cid, rid))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .flatten()
+            .for_each(|(cid, rid)| candidate_reads[cid].push(rid));
+    }
+
+    candidates
+        .par_iter()
+        .zip(candidate_reads.par_iter())
+        .map(|((t_seq, q_seq), rids)| {
+            let reads = rids.iter().map(|&rid| reads[rid].clone()).collect();
+            polish_sv_candidate(t_seq, q_seq, reads, shmmr_spec, min_cov)
+        })
+        .collect()
+}
+
+/// a target segment matching a read: (sid, bgn, end, orientation, the number of the anchors)
+pub type ReadTargetSegment = (u32, u32, u32, u32, usize);
+
+/// select the target segments matching a read from the sparse alignment chains of the read to the targets,
+/// e.g., from `SeqIndexDB::query_fragment_to_hps()`
+///
+/// the chain with the most anchors of each target is used if it has at least `min_anchor_count` anchors, the
+/// target interval of the chain is extended by the unaligned ends of the read and `flank` bases, the `end`
+/// may be beyond the target sequence, and the `max_segments` segments with the most anchors are returned
+pub fn read_target_segments(
+    hits: &[(u32, Vec<(f32, Vec<HitPair>)>)],
+    read_len: u32,
+    min_anchor_count: usize,
+    max_segments: usize,
+    flank: u32,
+) -> Vec<ReadTargetSegment> {
+    let mut segments = hits
+        .iter()
+        .filter_map(|(sid, chains)| {
+            chains
+                .iter()
+                .filter(|(_, chain)| !chain.is_empty() && chain.len() >= min_anchor_count)
+                .max_by_key(|(_, chain)| chain.len())
+                .map(|(_, chain)| {
+                    let n_forward = chain.iter().filter(|hp| hp.0 .2 == hp.1 .2).count();
+                    let orientation = if n_forward * 2 >= chain.len() { 0 } else { 1 };
+                    let q_bgn = chain.iter().map(|hp| hp.0 .0).min().unwrap();
+                    let q_end = chain.iter().map(|hp| hp.0 .1).max().unwrap();
+                    let t_bgn = chain.iter().map(|hp| hp.1 .0).min().unwrap();
+                    let t_end = chain.iter().map(|hp| hp.1 .1).max().unwrap();
+                    let (left_ext, right_ext) = if orientation == 0 {
+                        (q_bgn, read_len.saturating_sub(q_end))
+                    } else {
+                        (read_len.saturating_sub(q_end), q_bgn)
+                    };
+                    (
+                        *sid,
+                        t_bgn.saturating_sub(left_ext + flank),
+                        t_end + right_ext + flank,
+                        orientation,
+                        chain.len(),
+                    )
+                })
+        })
+        .collect::<Vec<ReadTargetSegment>>();
+    segments.sort_by(|a, b| b.4.cmp(&a.4).then(a.0.cmp(&b.0)));
+    segments.truncate(max_segments);
+    segments
+}
+
+/// correct a noisy read with the target segments matching it (in the read orientation) by the guided shimmer
+/// de Bruijn graph consensus with the read as the guide, the bases of the read are kept if there are at least
+/// `min_cov` sequences (the read and the segments) supporting them
+///
+/// the corrected read is checked with the alignment to the read by [crate::shmmrutils::match_reads], return the
+/// corrected read and the number of the differences to the read, or `None` if the read can not be corrected or the
+/// corrected read is not aligned within the `max_diff_rate`
+pub fn correct_read_with_segments(
+    read: &[u8],
+    segments: Vec<Vec<u8>>,
+    shmmr_spec: &Option<ShmmrSpec>,
+    min_cov: u32,
+    max_diff_rate: f64,
+) -> Option<(Vec<u8>, u32)> {
+    if segments.is_empty() {
+        return None;
+    }
+    let shmmr_spec = shmmr_spec.clone().unwrap_or(GUIDED_CONSENSUS_SHMMR_SPEC);
+    let k = shmmr_spec.k as usize;
+    let read = read.to_vec();
+    // the guided consensus needs at least two shimmer pairs in the read to start
+    if sequence_to_shmmrs(0, &read, &shmmr_spec, false).len() < 3 {
+        return None;
+    }
+    let mut seqs = vec![read.clone()];
+    seqs.extend(segments);
+    let (consensus, _) = guided_shmmr_dbg_consensus(seqs, &Some(shmmr_spec), min_cov).ok()?;
+    let corrected = splice_consensus(&read, &consensus, k)?;
+    let m = match_reads(&read, &corrected, true, max_diff_rate, 0, 0, 128)?;
+    Some((corrected, m.dist))
+}
+
 #[cfg(test)]
 mod test {
+    use crate::ec::correct_read_with_segments;
     use crate::ec::guided_shmmr_dbg_consensus;
     use crate::ec::naive_dbg_consensus;
+    use crate::ec::polish_sv_candidate;
+    use crate::ec::read_target_segments;
     use crate::ec::shmmr_dbg_consensus;
     use crate::ec::shmmr_sparse_aln_consensus;
     use crate::ec::shmmr_sparse_aln_consensus_with_sdb;
@@ -692,13 +941,7 @@ mod test {
     use crate::shmmrutils::ShmmrSpec;
     #[test]
     fn test_naive_dbg_consensus() {
-        let spec = ShmmrSpec {
-            w: 24,
-            k: 24,
-            r: 12,
-            min_span: 12,
-            sketch: false,
-        };
+        let spec = ShmmrSpec::new(24, 24, 12, 12, false);
         let mut sdb = CompactSeqDB::new(spec);
         let _ = sdb.load_seqs_from_fastx("test/test_data/consensus_test.fa".to_string(), true);
         let seqs = (0..sdb.seqs.len())
@@ -711,13 +954,7 @@ mod test {
 
     #[test]
     fn test_shmmr_dbg_consensus() {
-        let spec = ShmmrSpec {
-            w: 24,
-            k: 24,
-            r: 12,
-            min_span: 12,
-            sketch: false,
-        };
+        let spec = ShmmrSpec::new(24, 24, 12, 12, false);
         let mut sdb = CompactSeqDB::new(spec);
         let _ = sdb.load_seqs_from_fastx("test/test_data/consensus_test3.fa".to_string(), true);
         let seqs = (0..sdb.seqs.len())
@@ -733,13 +970,7 @@ mod test {
 
     #[test]
     fn test_guided_shmmr_dbg_consensus() {
-        let spec = ShmmrSpec {
-            w: 24,
-            k: 24,
-            r: 12,
-            min_span: 12,
-            sketch: false,
-        };
+        let spec = ShmmrSpec::new(24, 24, 12, 12, false);
         let mut sdb = CompactSeqDB::new(spec);
         let _ = sdb.load_seqs_from_fastx("test/test_data/consensus_test.fa".to_string(), true);
         let seqs = (0..sdb.seqs.len())
@@ -753,13 +984,7 @@ mod test {
 
     #[test]
     fn test_shmmr_sparse_aln_consensus() {
-        let spec = ShmmrSpec {
-            w: 24,
-            k: 24,
-            r: 12,
-            min_span: 12,
-            sketch: false,
-        };
+        let spec = ShmmrSpec::new(24, 24, 12, 12, false);
         let mut sdb = CompactSeqDB::new(spec);
         let _ = sdb.load_seqs_from_fastx("test/test_data/consensus_test5.fa".to_string(), true);
         let seqs = (0..sdb.seqs.len())
@@ -775,13 +1000,7 @@ mod test {
 
     #[test]
     fn test_shmmr_sparse_aln_consensus_with_sdb() {
-        let spec = ShmmrSpec {
-            w: 31,
-            k: 31,
-            r: 1,
-            min_span: 0,
-            sketch: false,
-        };
+        let spec = ShmmrSpec::new(31, 31, 1, 0, false);
         let mut sdb = CompactSeqDB::new(spec);
         let _ = sdb.load_seqs_from_fastx("test/test_data/consensus_test5.fa".to_string(), true);
 
@@ -791,4 +1010,87 @@ mod test {
             println!("{:?}", c);
         }
     }
+
+    #[test]
+    fn test_polish_sv_candidate() {
+        let mut sdb = CompactSeqDB::new(crate::seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/consensus_test.fa".to_string(), true);
+        let t_seq = sdb.get_seq_by_id(0);
+        // a 300 bp deletion
+        let mut q_seq = t_seq[..800].to_vec();
+        q_seq.extend_from_slice(&t_seq[1100..]);
+
+        let allele = polish_sv_candidate(&t_seq, &q_seq, vec![], &None, 2).unwrap();
+        assert_eq!(allele.support, 1);
+        assert_eq!(allele.ref_allele.len(), allele.alt_allele.len() + 300);
+        assert_eq!(allele.ref_allele[0], allele.alt_allele[0]);
+        let mut seq = t_seq[..allele.t_offset].to_vec();
+        seq.extend_from_slice(&allele.alt_allele);
+        seq.extend_from_slice(&t_seq[allele.t_offset + allele.ref_allele.len()..]);
+        assert_eq!(seq, q_seq);
+
+        // the query segment with an error, polished with the reads
+        let mut q_seq_err = q_seq.clone();
+        q_seq_err[1500] = if q_seq_err[1500] == b'A' { b'C' } else { b'A' };
+        let reads = vec![q_seq.clone(); 3];
+        let allele = polish_sv_candidate(&t_seq, &q_seq_err, reads, &None, 2).unwrap();
+        assert_eq!(allele.ref_allele.len(), allele.alt_allele.len() + 300);
+
+        assert!(polish_sv_candidate(&t_seq, &t_seq, vec![], &None, 2).is_none());
+    }
+
+    #[test]
+    fn test_read_target_segments() {
+        // two targets, the reverse complement hits to the target 1 with less anchors
+        let hits = vec![
+            (
+                0_u32,
+                vec![(
+                    1.0_f32,
+                    (0..12)
+                        .map(|i| {
+                            (
+                                (100 + i * 50, 150 + i * 50, 0),
+                                (1100 + i * 50, 1150 + i * 50, 0),
+                            )
+                        })
+                        .collect::<Vec<_>>(),
+                )],
+            ),
+            (
+                1_u32,
+                vec![(
+                    1.0_f32,
+                    (0..10)
+                        .map(|i| {
+                            (
+                                (100 + i * 50, 150 + i * 50, 0),
+                                (5550 - i * 50, 5600 - i * 50, 1),
+                            )
+                        })
+                        .collect::<Vec<_>>(),
+                )],
+            ),
+        ];
+        let segments = read_target_segments(&hits, 1000, 10, 4, 10);
+        assert_eq!(segments, [(0, 990, 2010, 0, 12), (1, 4690, 5710, 1, 10)]);
+        let segments = read_target_segments(&hits, 1000, 11, 4, 10);
+        assert_eq!(segments.len(), 1);
+        let segments = read_target_segments(&hits, 1000, 10, 1, 10);
+        assert_eq!(segments[0].0, 0);
+    }
+
+    #[test]
+    fn test_correct_read_with_segments() {
+        let mut sdb = CompactSeqDB::new(crate::seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/consensus_test.fa".to_string(), true);
+        let seqs = (0..sdb.seqs.len())
+            .map(|sid| sdb.get_seq_by_id(sid as u32))
+            .collect::<Vec<Vec<u8>>>();
+        let (corrected, n_diffs) =
+            correct_read_with_segments(&seqs[0], seqs[1..].to_vec(), &None, 2, 0.2).unwrap();
+        assert!(!corrected.is_empty());
+        assert!((n_diffs as usize) < seqs[0].len() / 5);
+        assert!(correct_read_with_segments(&seqs[0], vec![], &None, 2, 0.2).is_none());
+    }
 }
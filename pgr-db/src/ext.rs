@@ -1,14 +1,15 @@
 use flate2::bufread::MultiGzDecoder;
 
-#[cfg(feature = "with_agc")]
-use memmap2::Mmap;
-
-use crate::fasta_io::FastaReader;
-use crate::frag_file_io;
-use crate::graph_utils::{AdjList, ShmmrGraphNode};
+use crate::fasta_io::{reverse_complement, FastaReader, IndexedFastaReader};
+use crate::frag_file_io::{self, ShmmrToFragMapLocation};
+use crate::graph_utils::{
+    self, AdjList, AdjPair, BiDiNode, BubbleAllele, GraphSimplification, ShmmrGraphNode,
+    SuperBubble,
+};
+use crate::remote_io::{self, DataFile};
 pub use crate::seq_db::pair_shmmrs;
 use crate::seq_db::{self, raw_query_fragment, raw_query_fragment_from_mmap_midx, GetSeq};
-pub use crate::shmmrutils::{sequence_to_shmmrs, ShmmrSpec};
+pub use crate::shmmrutils::{sequence_to_shmmrs, ShmmrSpec, ShmmrTieBreak};
 use crate::{aln, frag_file_io::CompactSeqFragFileStorage};
 
 #[cfg(feature = "with_agc")]
@@ -17,17 +18,15 @@ use crate::agc_io::{self, AGCSeqDB};
 use rayon::prelude::*;
 use rustc_hash::{FxHashMap, FxHashSet};
 use std::fs::File;
-
-#[cfg(feature = "with_agc")]
-use std::io::BufRead;
-
-use std::io::{BufReader, BufWriter, Read, Write};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
 
 pub type PrincipalBundles = Vec<Vec<(u64, u64, u8)>>; //shimmer pair vector
 pub type PrincipalBundlesWithId = Vec<(usize, usize, Vec<(u64, u64, u8)>)>; //vector of "bundle_id, mean_order, shimmer pair vector"
 type ShmmrPair = (u64, u64);
 type ShmmrPairAndBundleVertices = Vec<((u64, u64, u32, u32, u8), Option<(usize, u8, usize)>)>; // Vector of ( sequence_id, vector of (shimmer pair, optional bundle vertex)
 pub type VertexToBundleIdMap = FxHashMap<ShmmrPair, (usize, u8, usize)>;
+pub type MapGraphCycle = (Vec<ShmmrGraphNode>, u32, Vec<(u32, usize)>); // (vertices, length of the repeat unit, (sid, traversal count))
+pub type MapGraphComponentSummary = (usize, usize, Vec<u32>); // (number of vertices, number of edges, sequence ids in the component)
 
 #[allow(clippy::large_enum_variant)]
 pub enum GZFastaReader {
@@ -40,11 +39,52 @@ pub enum Backend {
     #[cfg(feature = "with_agc")]
     AGC,
     FRG,
+    FAI,
     FASTX,
     MEMORY,
     UNKNOWN,
 }
 
+/// the index (`.mdb`) and the fasta files (with the `.fai` indexes) of the sources for the indexed fasta backend,
+/// the sequences are fetched from the fasta files on demand
+pub struct IndexedFastaSeqDB {
+    /// a dictionary maps source -> the reader of the fasta file of the source
+    pub fasta_files: FxHashMap<String, IndexedFastaReader>,
+    pub frag_location_map: ShmmrToFragMapLocation,
+    pub frag_map_file: DataFile,
+}
+
+impl IndexedFastaSeqDB {
+    fn get_fasta_file(&self, source: &str) -> Result<&IndexedFastaReader, std::io::Error> {
+        self.fasta_files.get(source).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("no fasta file of the source {}", source),
+            )
+        })
+    }
+
+    pub fn get_sub_seq(
+        &self,
+        source: &str,
+        ctg_name: &str,
+        bgn: usize,
+        end: usize,
+    ) -> Result<Vec<u8>, std::io::Error> {
+        self.get_fasta_file(source)?.get_sub_seq(ctg_name, bgn, end)
+    }
+
+    pub fn get_seq(&self, source: &str, ctg_name: &str) -> Result<Vec<u8>, std::io::Error> {
+        self.get_fasta_file(source)?.get_seq(ctg_name)
+    }
+
+    pub fn get_seq_len(&self, source: &str, ctg_name: &str) -> Option<usize> {
+        self.fasta_files
+            .get(source)
+            .and_then(|fasta_file| fasta_file.get_seq_len(ctg_name))
+    }
+}
+
 pub struct SeqIndexDB {
     /// Rust internal: store the specification of the shmmr_spec
     pub shmmr_spec: Option<ShmmrSpec>,
@@ -54,6 +94,8 @@ pub struct SeqIndexDB {
     /// Rust internal: store the agc file and the index
     pub agc_db: Option<AGCSeqDB>,
     pub frg_db: Option<CompactSeqFragFileStorage>,
+    /// Rust internal: store the indexed fasta files and the index
+    pub fai_db: Option<IndexedFastaSeqDB>,
     /// a dictionary maps (ctg_name, source) -> (id, len)
     #[allow(clippy::type_complexity)]
     pub seq_index: Option<FxHashMap<(String, Option<String>), (u32, u32)>>,
@@ -61,6 +103,8 @@ pub struct SeqIndexDB {
     #[allow(clippy::type_complexity)]
     pub seq_info: Option<FxHashMap<u32, (String, Option<String>, u32)>>,
     pub backend: Backend,
+    /// simplify the MAP graph (clipping the tips and popping the bubbles) before extracting the principal bundles
+    pub graph_simplification: Option<GraphSimplification>,
 }
 
 impl Default for SeqIndexDB {
@@ -74,6 +118,8 @@ impl SeqIndexDB {
         SeqIndexDB {
             seq_db: None,
             frg_db: None,
+            fai_db: None,
+            graph_simplification: None,
             #[cfg(feature = "with_agc")]
             agc_db: None,
             shmmr_spec: None,
@@ -86,16 +132,16 @@ impl SeqIndexDB {
     #[cfg(feature = "with_agc")]
     pub fn load_from_agc_index(&mut self, prefix: String) -> Result<(), std::io::Error> {
         let (shmmr_spec, frag_location_map) =
-            seq_db::read_mdb_file_to_frag_locations(prefix.to_string() + ".mdb").unwrap();
+            seq_db::read_mdb_file_to_frag_locations(prefix.to_string() + ".mdb")?;
 
         let frag_location_map =
             FxHashMap::<(u64, u64), (usize, usize)>::from_iter(frag_location_map);
 
-        let agc_file = agc_io::AGCFile::new(prefix.to_string() + ".agc")?;
+        // the AGC library reads a local file, a remote AGC file is rejected
+        let agc_file =
+            agc_io::AGCFile::new(remote_io::local_file_path(&(prefix.clone() + ".agc"))?)?;
 
-        let fmap_file = File::open(prefix.clone() + ".mdb").expect("frag map file open fail");
-        let frag_map_file =
-            unsafe { Mmap::map(&fmap_file).expect("frag map file memory map creation fail") };
+        let frag_map_file = DataFile::open(&(prefix.clone() + ".mdb"))?;
 
         self.agc_db = Some(agc_io::AGCSeqDB {
             agc_file,
@@ -108,7 +154,7 @@ impl SeqIndexDB {
         let mut seq_index = FxHashMap::<(String, Option<String>), (u32, u32)>::default();
         let mut seq_info = FxHashMap::<u32, (String, Option<String>, u32)>::default();
 
-        let midx_file = BufReader::new(File::open(prefix + ".midx")?);
+        let midx_file = BufReader::new(remote_io::open_file(&(prefix + ".midx"))?);
         midx_file
             .lines()
             .try_for_each(|line| -> Result<(), std::io::Error> {
@@ -149,6 +195,80 @@ impl SeqIndexDB {
         Ok(())
     }
 
+    /// use the indexed fasta files for the sequences with the index files (`.mdb` and `.midx`) built from them,
+    /// the fasta file of each source is listed in `<prefix>.fasta_manifest` with the source name and the path
+    /// separated by a tab, and the sequences are fetched with the `.fai` (and `.gzi` for the bgzipped files)
+    /// indexes on demand, the indexes are built next to the fasta files if they do not exist
+    pub fn load_from_indexed_fasta(&mut self, prefix: String) -> Result<(), std::io::Error> {
+        let (shmmr_spec, frag_location_map) =
+            seq_db::read_mdb_file_to_frag_locations(prefix.clone() + ".mdb")?;
+        let frag_location_map =
+            FxHashMap::<(u64, u64), (usize, usize)>::from_iter(frag_location_map);
+        let frag_map_file = DataFile::open(&(prefix.clone() + ".mdb"))?;
+
+        let fasta_files = read_fastx_manifest(&(prefix.clone() + ".fasta_manifest"))?
+            .into_iter()
+            .map(|(source, filepath)| Ok((source, IndexedFastaReader::new(&filepath)?)))
+            .collect::<Result<FxHashMap<_, _>, std::io::Error>>()?;
+        let fai_db = IndexedFastaSeqDB {
+            fasta_files,
+            frag_location_map,
+            frag_map_file,
+        };
+
+        let mut seq_index = FxHashMap::<(String, Option<String>), (u32, u32)>::default();
+        let mut seq_info = FxHashMap::<u32, (String, Option<String>, u32)>::default();
+        let midx_file = BufReader::new(File::open(prefix + ".midx")?);
+        for line in midx_file.lines() {
+            let line = line?;
+            let fields = line.split('\t').collect::<Vec<&str>>();
+            let parse_error = || {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("midx file parsing error on the line: {}", line),
+                )
+            };
+            if fields.len() < 4 {
+                return Err(parse_error());
+            }
+            let sid = fields[0].parse::<u32>().map_err(|_| parse_error())?;
+            let len = fields[1].parse::<u32>().map_err(|_| parse_error())?;
+            let ctg_name = fields[2].to_string();
+            let source = fields[3].to_string();
+            // the index and the fasta files should agree, otherwise the hits point to the wrong bases
+            match fai_db.get_seq_len(&source, &ctg_name) {
+                Some(fai_len) if fai_len == len as usize => (),
+                Some(fai_len) => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!(
+                            "sequence {}::{} has {} bp in the index but {} bp in the fasta file",
+                            source, ctg_name, len, fai_len
+                        ),
+                    ))
+                }
+                None => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        format!(
+                            "sequence {}::{} is not found in the fasta files",
+                            source, ctg_name
+                        ),
+                    ))
+                }
+            }
+            seq_index.insert((ctg_name.clone(), Some(source.clone())), (sid, len));
+            seq_info.insert(sid, (ctg_name, Some(source), len));
+        }
+
+        self.fai_db = Some(fai_db);
+        self.backend = Backend::FAI;
+        self.shmmr_spec = Some(shmmr_spec);
+        self.seq_index = Some(seq_index);
+        self.seq_info = Some(seq_info);
+        Ok(())
+    }
+
     pub fn load_from_fastx(
         &mut self,
         filepath: String,
@@ -158,13 +278,7 @@ impl SeqIndexDB {
         min_span: u32,
         to_upper_case: bool,
     ) -> Result<(), std::io::Error> {
-        let spec = ShmmrSpec {
-            w,
-            k,
-            r,
-            min_span,
-            sketch: false,
-        };
+        let spec = ShmmrSpec::new(w, k, r, min_span, false);
         let mut sdb = seq_db::CompactSeqDB::new(spec.clone());
 
         sdb.load_seqs_from_fastx(filepath, to_upper_case)?;
@@ -204,6 +318,53 @@ impl SeqIndexDB {
         Ok(())
     }
 
+    // load a list of (source, fastx file path), the sequences are labeled by the sources rather than the file paths
+    pub fn load_from_fastx_with_sources(
+        &mut self,
+        fastx_files: Vec<(String, String)>,
+        w: u32,
+        k: u32,
+        r: u32,
+        min_span: u32,
+        to_upper_case: bool,
+    ) -> Result<(), std::io::Error> {
+        let spec = ShmmrSpec::new(w, k, r, min_span, false);
+        let mut sdb = seq_db::CompactSeqDB::new(spec.clone());
+
+        fastx_files
+            .into_iter()
+            .try_for_each(|(source, filepath)| {
+                sdb.load_seqs_from_fastx_with_source(filepath, source, to_upper_case)
+            })?;
+        self.shmmr_spec = Some(spec);
+        let mut seq_index = FxHashMap::<(String, Option<String>), (u32, u32)>::default();
+        let mut seq_info = FxHashMap::<u32, (String, Option<String>, u32)>::default();
+        sdb.seqs.iter().for_each(|v| {
+            seq_index.insert((v.name.clone(), v.source.clone()), (v.id, v.len as u32));
+            seq_info.insert(v.id, (v.name.clone(), v.source.clone(), v.len as u32));
+        });
+        self.seq_index = Some(seq_index);
+        self.seq_info = Some(seq_info);
+        self.seq_db = Some(sdb);
+        self.backend = Backend::FASTX;
+        Ok(())
+    }
+
+    /// load a list of fastx files, or the patterns of the file names with the `*` and `?` wildcards, the
+    /// sequences are labeled by the source names from the file names, see [fastx_files_with_sources]
+    pub fn load_from_fastx_files(
+        &mut self,
+        paths: &[String],
+        w: u32,
+        k: u32,
+        r: u32,
+        min_span: u32,
+        to_upper_case: bool,
+    ) -> Result<(), std::io::Error> {
+        let fastx_files = fastx_files_with_sources(paths)?;
+        self.load_from_fastx_with_sources(fastx_files, w, k, r, min_span, to_upper_case)
+    }
+
     pub fn write_frag_and_index_files(&self, file_prefix: String) {
         if self.seq_db.is_some() {
             let internal = self.seq_db.as_ref().unwrap();
@@ -224,13 +385,17 @@ impl SeqIndexDB {
         r: u32,
         min_span: u32,
     ) -> Result<(), std::io::Error> {
-        let spec = ShmmrSpec {
-            w,
-            k,
-            r,
-            min_span,
-            sketch: false,
-        };
+        let spec = ShmmrSpec::new(w, k, r, min_span, false);
+        self.load_from_seq_list_with_spec(seq_list, source, spec)
+    }
+
+    /// load the sequences into memory and index them with a shimmer spec (e.g., for using sketch k-mers)
+    pub fn load_from_seq_list_with_spec(
+        &mut self,
+        seq_list: Vec<(String, Vec<u8>)>,
+        source: Option<&str>,
+        spec: ShmmrSpec,
+    ) -> Result<(), std::io::Error> {
         self.backend = Backend::MEMORY;
         let source = if let Some(source) = source {
             Some(source.to_string())
@@ -262,7 +427,7 @@ impl SeqIndexDB {
     pub fn query_fragment_to_hps(
         &self,
         seq: &Vec<u8>,
-        penalty: f32,
+        gap_cost: aln::ChainGapCost,
         max_count: Option<u32>,
         max_count_query: Option<u32>,
         max_count_target: Option<u32>,
@@ -277,7 +442,7 @@ impl SeqIndexDB {
                 raw_query_hits,
                 seq,
                 shmmr_spec,
-                penalty,
+                gap_cost,
                 max_count,
                 max_count_query,
                 max_count_target,
@@ -295,7 +460,7 @@ impl SeqIndexDB {
     pub fn query_fragment_to_hps_from_mmap_file(
         &self,
         seq: &Vec<u8>,
-        penalty: f32,
+        gap_cost: aln::ChainGapCost,
         max_count: Option<u32>,
         max_count_query: Option<u32>,
         max_count_target: Option<u32>,
@@ -316,9 +481,14 @@ impl SeqIndexDB {
                 &self.frg_db.as_ref().unwrap().frag_location_map,
                 &self.frg_db.as_ref().unwrap().frag_map_file,
             )
+        } else if self.backend == Backend::FAI {
+            (
+                &self.fai_db.as_ref().unwrap().frag_location_map,
+                &self.fai_db.as_ref().unwrap().frag_map_file,
+            )
         } else {
             panic!(
-                "the call query_fragment_to_hps_from_mmap_file() needs AGC or FRAG backend file"
+                "the call query_fragment_to_hps_from_mmap_file() needs AGC, FRAG or FAI backend file"
             );
         };
 
@@ -328,9 +498,14 @@ impl SeqIndexDB {
                 &self.frg_db.as_ref().unwrap().frag_location_map,
                 &self.frg_db.as_ref().unwrap().frag_map_file,
             )
+        } else if self.backend == Backend::FAI {
+            (
+                &self.fai_db.as_ref().unwrap().frag_location_map,
+                &self.fai_db.as_ref().unwrap().frag_map_file,
+            )
         } else {
             panic!(
-                "the call query_fragment_to_hps_from_mmap_file() needs AGC or FRAG backend file"
+                "the call query_fragment_to_hps_from_mmap_file() needs AGC, FRAG or FAI backend file"
             );
         };
 
@@ -340,7 +515,7 @@ impl SeqIndexDB {
             raw_query_hits,
             &seq,
             shmmr_spec,
-            penalty,
+            gap_cost,
             max_count,
             max_count_query,
             max_count_target,
@@ -392,6 +567,12 @@ impl SeqIndexDB {
                     .unwrap()
                     .get_sub_seq_by_id(sid, bgn as u32, end as u32))
             }
+            Backend::FAI => {
+                self.fai_db
+                    .as_ref()
+                    .unwrap()
+                    .get_sub_seq(&sample_name, &ctg_name, bgn, end)
+            }
             Backend::UNKNOWN => Err(std::io::Error::new(
                 std::io::ErrorKind::Other,
                 "fetching sequence fail, database type in not determined",
@@ -430,6 +611,11 @@ impl SeqIndexDB {
                     .unwrap();
                 Ok(self.frg_db.as_ref().unwrap().get_seq_by_id(sid))
             }
+            Backend::FAI => self
+                .fai_db
+                .as_ref()
+                .unwrap()
+                .get_seq(&sample_name, &ctg_name),
             Backend::UNKNOWN => Err(std::io::Error::new(
                 std::io::ErrorKind::Other,
                 "fetching sequence fail, database type in not determined",
@@ -455,6 +641,10 @@ impl SeqIndexDB {
                 Ok(self.seq_db.as_ref().unwrap().get_seq_by_id(sid))
             }
             Backend::FRG => Ok(self.frg_db.as_ref().unwrap().get_seq_by_id(sid)),
+            Backend::FAI => {
+                let (ctg_name, sample_name) = self.get_ctg_name_and_source_by_id(sid)?;
+                self.fai_db.as_ref().unwrap().get_seq(sample_name, ctg_name)
+            }
             Backend::UNKNOWN => Err(std::io::Error::new(
                 std::io::ErrorKind::Other,
                 "fetching sequence fail, database type in not determined",
@@ -471,7 +661,18 @@ impl SeqIndexDB {
         match self.backend {
             #[cfg(feature = "with_agc")]
             Backend::AGC => {
-                let (ctg_name, sample_name, _) = self.seq_info.as_ref().unwrap().get(&sid).unwrap(); //TODO: handle Option unwrap properly
+                self.check_seq_by_id(sid)?;
+                let (ctg_name, sample_name, len) =
+                    self.seq_info.as_ref().unwrap().get(&sid).unwrap();
+                if bgn >= end || end > *len as usize {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!(
+                            "invalid interval {}-{} of the sequence id {} ({} bp)",
+                            bgn, end, sid, len
+                        ),
+                    ));
+                }
                 let ctg_name = ctg_name.clone();
                 let sample_name = sample_name.as_ref().unwrap().clone();
                 Ok(self.agc_db.as_ref().unwrap().agc_file.get_sub_seq(
@@ -491,6 +692,13 @@ impl SeqIndexDB {
                 .as_ref()
                 .unwrap()
                 .get_sub_seq_by_id(sid, bgn as u32, end as u32)),
+            Backend::FAI => {
+                let (ctg_name, sample_name) = self.get_ctg_name_and_source_by_id(sid)?;
+                self.fai_db
+                    .as_ref()
+                    .unwrap()
+                    .get_sub_seq(sample_name, ctg_name, bgn, end)
+            }
             Backend::UNKNOWN => Err(std::io::Error::new(
                 std::io::ErrorKind::Other,
                 "fetching sequence fail, database type in not determined",
@@ -498,6 +706,128 @@ impl SeqIndexDB {
         }
     }
 
+    /// fetch a batch of the sub-sequences `(sid, bgn, end)` in parallel, the sequences are returned in the order
+    /// of the requests, with the AGC backend, the fetches use separate AGC handles instead of sharing one
+    pub fn get_sub_seqs_by_id(
+        &self,
+        sub_seqs: Vec<(u32, usize, usize)>,
+    ) -> Result<Vec<Vec<u8>>, std::io::Error> {
+        match self.backend {
+            #[cfg(feature = "with_agc")]
+            Backend::AGC => {
+                let requests = sub_seqs
+                    .into_iter()
+                    .map(|(sid, bgn, end)| {
+                        self.check_seq_by_id(sid)?;
+                        let (ctg_name, sample_name, len) =
+                            self.seq_info.as_ref().unwrap().get(&sid).unwrap();
+                        if bgn >= end || end > *len as usize {
+                            return Err(std::io::Error::new(
+                                std::io::ErrorKind::InvalidInput,
+                                format!(
+                                    "invalid interval {}-{} of the sequence id {} ({} bp)",
+                                    bgn, end, sid, len
+                                ),
+                            ));
+                        }
+                        Ok((
+                            sample_name.as_ref().unwrap().clone(),
+                            ctg_name.clone(),
+                            bgn,
+                            end,
+                        ))
+                    })
+                    .collect::<Result<Vec<agc_io::SubSeqRequest>, std::io::Error>>()?;
+                Ok(self
+                    .agc_db
+                    .as_ref()
+                    .unwrap()
+                    .agc_file
+                    .get_sub_seqs(requests))
+            }
+            _ => sub_seqs
+                .par_iter()
+                .map(|&(sid, bgn, end)| self.get_sub_seq_by_id(sid, bgn, end))
+                .collect(),
+        }
+    }
+
+    /// check that a sequence id in the index resolves to a contig of the same length in
+    /// the backing sequence store, an error is returned if the index files and the sequence store disagree
+    pub fn check_seq_by_id(&self, sid: u32) -> Result<(), std::io::Error> {
+        let (ctg_name, sample_name, len) = self
+            .seq_info
+            .as_ref()
+            .and_then(|seq_info| seq_info.get(&sid))
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("sequence id {} is not in the index", sid),
+                )
+            })?;
+        let store_len = match self.backend {
+            #[cfg(feature = "with_agc")]
+            Backend::AGC => {
+                let sample_name = sample_name.as_ref().ok_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        format!("sequence id {} ({}) has no sample name", sid, ctg_name),
+                    )
+                })?;
+                self.agc_db
+                    .as_ref()
+                    .unwrap()
+                    .agc_file
+                    .ctg_lens
+                    .get(&(sample_name.clone(), ctg_name.clone()))
+                    .copied()
+            }
+            Backend::FRG => self
+                .frg_db
+                .as_ref()
+                .unwrap()
+                .seqs
+                .get(sid as usize)
+                .map(|seq| seq.len),
+            Backend::FAI => self
+                .fai_db
+                .as_ref()
+                .unwrap()
+                .get_seq_len(sample_name.as_deref().unwrap_or("-"), ctg_name),
+            Backend::MEMORY | Backend::FASTX => self
+                .seq_db
+                .as_ref()
+                .unwrap()
+                .seqs
+                .get(sid as usize)
+                .map(|seq| seq.len),
+            Backend::UNKNOWN => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    "checking sequence fail, database type in not determined",
+                ))
+            }
+        };
+        let source = sample_name.as_deref().unwrap_or("N/A");
+        match store_len {
+            Some(store_len) if store_len == *len as usize => Ok(()),
+            Some(store_len) => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "sequence id {} ({}::{}) has {} bp in the index but {} bp in the sequence store",
+                    sid, source, ctg_name, len, store_len
+                ),
+            )),
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!(
+                    "sequence id {} ({}::{}) is not found in the sequence store",
+                    sid, source, ctg_name
+                ),
+            )),
+        }
+    }
+
     pub fn get_principal_bundles(
         &self,
         min_count: usize,
@@ -505,7 +835,7 @@ impl SeqIndexDB {
         keeps: Option<Vec<u32>>,
     ) -> PrincipalBundles {
         if let Some(frag_map) = self.get_shmmr_map_internal() {
-            let adj_list = seq_db::frag_map_to_adj_list(frag_map, min_count, keeps);
+            let adj_list = self.get_principal_bundle_adj_list(frag_map, min_count, keeps);
             if adj_list.is_empty() {
                 return vec![];
             }
@@ -519,6 +849,198 @@ impl SeqIndexDB {
         }
     }
 
+    /// get the principal bundles of each connected component of the MAP graph in parallel, the components are
+    /// in the same order as [`SeqIndexDB::get_mapg_components`]
+    pub fn get_principal_bundles_by_component(
+        &self,
+        min_count: usize,
+        path_len_cutoff: usize,
+        keeps: Option<Vec<u32>>,
+    ) -> Vec<PrincipalBundles> {
+        if let Some(frag_map) = self.get_shmmr_map_internal() {
+            let adj_list = self.get_principal_bundle_adj_list(frag_map, min_count, keeps);
+            graph_utils::split_adj_list_by_component(&adj_list)
+                .par_iter()
+                .map(|component| {
+                    seq_db::get_principal_bundles_from_adj_list(
+                        frag_map,
+                        component,
+                        path_len_cutoff,
+                    )
+                    .0
+                    .into_iter()
+                    .map(|p| p.into_iter().map(|v| (v.0, v.1, v.2)).collect())
+                    .collect::<PrincipalBundles>()
+                })
+                .collect()
+        } else {
+            vec![]
+        }
+    }
+
+    /// summarize the connected components of the MAP graph used for the principal bundles, the largest first,
+    /// a vertex and an edge are counted once for both orientations
+    pub fn get_mapg_components(
+        &self,
+        min_count: usize,
+        keeps: Option<Vec<u32>>,
+    ) -> Vec<MapGraphComponentSummary> {
+        if let Some(frag_map) = self.get_shmmr_map_internal() {
+            let adj_list = self.get_principal_bundle_adj_list(frag_map, min_count, keeps);
+            graph_utils::split_adj_list_by_component(&adj_list)
+                .par_iter()
+                .map(|component| {
+                    let mut vertices = FxHashSet::<ShmmrPair>::default();
+                    let mut edges = FxHashSet::<(ShmmrGraphNode, ShmmrGraphNode)>::default();
+                    let mut sids = FxHashSet::<u32>::default();
+                    component.iter().for_each(|&(sid, v, w)| {
+                        vertices.insert((v.0, v.1));
+                        vertices.insert((w.0, w.1));
+                        edges.insert((v, w).min((w.reverse(), v.reverse())));
+                        sids.insert(sid);
+                    });
+                    let mut sids = sids.into_iter().collect::<Vec<_>>();
+                    sids.sort();
+                    (vertices.len(), edges.len(), sids)
+                })
+                .collect()
+        } else {
+            vec![]
+        }
+    }
+
+    // the adjacency list of the MAP graph for the principal bundles, simplified if `graph_simplification` is set
+    fn get_principal_bundle_adj_list(
+        &self,
+        frag_map: &seq_db::ShmmrToFrags,
+        min_count: usize,
+        keeps: Option<Vec<u32>>,
+    ) -> AdjList {
+        let adj_list = seq_db::frag_map_to_adj_list(frag_map, min_count, keeps);
+        match self.graph_simplification.as_ref() {
+            Some(simplification) => graph_utils::simplify_adj_list(&adj_list, simplification),
+            None => adj_list,
+        }
+    }
+
+    /// find the superbubbles in the MAP graph with the alleles walked by the sequences, see
+    /// [`graph_utils::find_superbubbles`]
+    pub fn get_superbubbles(
+        &self,
+        min_count: usize,
+        keeps: Option<Vec<u32>>,
+        max_bubble_size: usize,
+    ) -> Vec<(SuperBubble, Vec<BubbleAllele>)> {
+        if let Some(frag_map) = self.get_shmmr_map_internal() {
+            let adj_list = seq_db::frag_map_to_adj_list(frag_map, min_count, keeps);
+            graph_utils::find_superbubbles(&adj_list, max_bubble_size)
+        } else {
+            vec![]
+        }
+    }
+
+    /// find the cycles (e.g., the VNTR-like loops) in the MAP graph with the traversal counts of the sequences,
+    /// see [`graph_utils::find_cycles`], the length of the repeat unit of a cycle is the sum of the average
+    /// fragment lengths of its vertices
+    pub fn get_mapg_cycles(
+        &self,
+        min_count: usize,
+        keeps: Option<Vec<u32>>,
+        max_cycle_size: usize,
+    ) -> Vec<MapGraphCycle> {
+        if let Some(frag_map) = self.get_shmmr_map_internal() {
+            let adj_list = seq_db::frag_map_to_adj_list(frag_map, min_count, keeps);
+            graph_utils::find_cycles(&adj_list, max_cycle_size)
+                .into_iter()
+                .map(|(vertices, _, traversals)| {
+                    let unit_len = vertices
+                        .iter()
+                        .map(|v| {
+                            let hits = frag_map.get(&(v.0, v.1)).unwrap();
+                            // summed as u64, the spans of a highly repetitive vertex can overflow u32
+                            (hits.iter().map(|h| (h.3 - h.2) as u64).sum::<u64>()
+                                / hits.len() as u64) as u32
+                        })
+                        .sum::<u32>();
+                    (vertices, unit_len, traversals)
+                })
+                .collect()
+        } else {
+            vec![]
+        }
+    }
+
+    /// write the cycles of the MAP graph to a TSV file, one line per cycle with the length of the repeat unit
+    /// and the traversal counts (the copy numbers of the repeat unit) of the sequences and of the samples
+    pub fn write_mapg_cycles_tsv(
+        &self,
+        min_count: usize,
+        keeps: Option<Vec<u32>>,
+        max_cycle_size: usize,
+        filepath: &str,
+    ) -> Result<(), std::io::Error> {
+        let seq_info = self.seq_info.clone().unwrap_or_default();
+        let mut out_file = BufWriter::new(File::create(filepath)?);
+        writeln!(
+            out_file,
+            "#cycle_id\tn_vertices\tunit_len\tn_seqs\tmin_copies\tmax_copies\tsample_copies\tseq_copies\tvertices"
+        )?;
+        let cycles = self.get_mapg_cycles(min_count, keeps, max_cycle_size);
+        for (cid, (vertices, unit_len, traversals)) in cycles.iter().enumerate() {
+            // the traversal counts of the sequences from each sample
+            let mut sample_copies = FxHashMap::<String, Vec<usize>>::default();
+            traversals.iter().for_each(|(sid, count)| {
+                let source = seq_info
+                    .get(sid)
+                    .and_then(|(_, source, _)| source.clone())
+                    .unwrap_or_else(|| "-".to_string());
+                sample_copies.entry(source).or_default().push(*count);
+            });
+            let mut sample_copies = sample_copies.into_iter().collect::<Vec<_>>();
+            sample_copies.sort();
+            let sample_copies = sample_copies
+                .into_iter()
+                .map(|(source, mut counts)| {
+                    counts.sort();
+                    let counts = counts
+                        .iter()
+                        .map(|c| format!("{}", c))
+                        .collect::<Vec<_>>()
+                        .join("/");
+                    format!("{}:{}", source, counts)
+                })
+                .collect::<Vec<_>>()
+                .join(",");
+            let seq_copies = traversals
+                .iter()
+                .map(|(sid, count)| format!("{}:{}", sid, count))
+                .collect::<Vec<_>>()
+                .join(",");
+            let vertices_str = vertices
+                .iter()
+                .map(|v| {
+                    let orientation = if v.2 == 0 { "+" } else { "-" };
+                    format!("{:016x}_{:016x}{}", v.0, v.1, orientation)
+                })
+                .collect::<Vec<_>>()
+                .join(",");
+            writeln!(
+                out_file,
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                cid,
+                vertices.len(),
+                unit_len,
+                traversals.len(),
+                traversals.iter().map(|t| t.1).min().unwrap_or(0),
+                traversals.iter().map(|t| t.1).max().unwrap_or(0),
+                sample_copies,
+                seq_copies,
+                vertices_str
+            )?;
+        }
+        Ok(())
+    }
+
     fn get_vertex_map_from_principal_bundles(&self, pb: PrincipalBundles) -> VertexToBundleIdMap {
         // count segment for filtering, some unidirectional seg may have both forward and reverse in the principle bundles
         // let mut seg_count = FxHashMap::<(u64, u64), usize>::default();
@@ -540,7 +1062,7 @@ impl SeqIndexDB {
             .collect()
     }
 
-    fn get_smps(&self, seq: Vec<u8>, shmmr_spec: &ShmmrSpec) -> Vec<(u64, u64, u32, u32, u8)> {
+    pub fn get_smps(&self, seq: Vec<u8>, shmmr_spec: &ShmmrSpec) -> Vec<(u64, u64, u32, u32, u8)> {
         let shmmrs = sequence_to_shmmrs(0, &seq, shmmr_spec, false);
         seq_db::pair_shmmrs(&shmmrs)
             .par_iter()
@@ -666,6 +1188,128 @@ impl SeqIndexDB {
         method: &str,
         keeps: Option<Vec<u32>>,
     ) -> Result<(), std::io::Error> {
+        self.generate_mapg_gfa_with_haplotypes(min_count, filepath, method, keeps, None)
+    }
+
+    /// generate the MAP graph GFA file, the segments and the links are also tagged with the number ("DP:i")
+    /// and the names ("HP:Z") of the haplotypes traversing them if the haplotypes of the sequences are given
+    pub fn generate_mapg_gfa_with_haplotypes(
+        &self,
+        min_count: usize,
+        filepath: &str,
+        method: &str,
+        keeps: Option<Vec<u32>>,
+        sid_to_haplotype: Option<&FxHashMap<u32, String>>,
+    ) -> Result<(), std::io::Error> {
+        let mut out_file = BufWriter::new(File::create(filepath)?);
+        self.write_mapg_gfa(
+            min_count,
+            method,
+            keeps,
+            sid_to_haplotype,
+            false,
+            &mut out_file,
+        )
+    }
+
+    /// write the MAP graph GFA to a writer, the segment sequences are taken from the first fragment of each
+    /// shimmer pair (in the orientation of the shimmer pair) if `with_seq` is set, otherwise "*" is written,
+    /// the segments and the links are tagged with their coverage ("SC:i") and the ids of the sequences
+    /// traversing them ("SI:Z"), and with the number ("DP:i") and the names ("HP:Z") of the haplotypes
+    /// traversing them if the haplotypes of the sequences are given
+    pub fn write_mapg_gfa<W: Write>(
+        &self,
+        min_count: usize,
+        method: &str,
+        keeps: Option<Vec<u32>>,
+        sid_to_haplotype: Option<&FxHashMap<u32, String>>,
+        with_seq: bool,
+        out_file: &mut W,
+    ) -> Result<(), std::io::Error> {
+        let frag_map = self.get_shmmr_map_internal();
+        if frag_map.is_none() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "can get frag_map",
+            ));
+        }
+        let frag_map = frag_map.unwrap();
+        let adj_list = self.get_mapg_adj_list(frag_map, min_count, method, keeps);
+        let (frag_id, overlaps) = get_mapg_segments_and_links(&adj_list);
+
+        let kmer_size = self.shmmr_spec.as_ref().unwrap().k;
+        out_file
+            .write_all("H\tVN:Z:1.0\tCM:Z:Sparse Genome Graph Generated By pgr-tk\n".as_bytes())?;
+        frag_id
+            .iter()
+            .try_for_each(|(smp, id)| -> Result<(), std::io::Error> {
+                let hits = frag_map.get(smp).unwrap();
+                let ave_len =
+                    hits.iter().fold(0_u32, |len_sum, &s| len_sum + s.3 - s.2) / hits.len() as u32;
+                let seg_seq = if with_seq {
+                    let (_, sid, bgn, end, orientation) = hits[0];
+                    let seq =
+                        self.get_sub_seq_by_id(sid, (bgn - kmer_size) as usize, end as usize)?;
+                    let seq = if orientation == 1 {
+                        reverse_complement(&seq)
+                    } else {
+                        seq
+                    };
+                    String::from_utf8_lossy(&seq[..]).into_owned()
+                } else {
+                    "*".to_string()
+                };
+                let sids = sorted_sids(hits.iter().map(|h| h.1));
+                let seg_line = format!(
+                    "S\t{}\t{}\tLN:i:{}\tSN:Z:{:016x}_{:016x}\tSC:i:{}\tSI:Z:{}{}\n",
+                    id,
+                    seg_seq,
+                    ave_len + kmer_size,
+                    smp.0,
+                    smp.1,
+                    hits.len(),
+                    sids_to_tag_value(&sids),
+                    haplotype_tags(&sids, sid_to_haplotype)
+                );
+                out_file.write_all(seg_line.as_bytes())?;
+                Ok(())
+            })?;
+
+        overlaps
+            .into_iter()
+            .try_for_each(|(op, vs)| -> Result<(), std::io::Error> {
+                let o1 = if op.0 .2 == 0 { "+" } else { "-" };
+                let o2 = if op.1 .2 == 0 { "+" } else { "-" };
+                let id0 = frag_id.get(&(op.0 .0, op.0 .1)).unwrap();
+                let id1 = frag_id.get(&(op.1 .0, op.1 .1)).unwrap();
+                let sids = sorted_sids(vs.iter().map(|v| v.0));
+                let overlap_line = format!(
+                    "L\t{}\t{}\t{}\t{}\t{}M\tSC:i:{}\tSI:Z:{}{}\n",
+                    id0,
+                    o1,
+                    id1,
+                    o2,
+                    kmer_size,
+                    vs.len(),
+                    sids_to_tag_value(&sids),
+                    haplotype_tags(&sids, sid_to_haplotype)
+                );
+                out_file.write_all(overlap_line.as_bytes())?;
+                Ok(())
+            })?;
+
+        Ok(())
+    }
+
+    // the adjacency list of the MAP graph, from the shimmer pairs in the fragment map ("from_fragmap") or
+    // from the shimmer pairs of each sequence
+    fn get_mapg_adj_list(
+        &self,
+        frag_map: &seq_db::ShmmrToFrags,
+        min_count: usize,
+        method: &str,
+        keeps: Option<Vec<u32>>,
+    ) -> AdjList {
         let get_seq_by_id = |sid| -> Vec<u8> {
             match self.backend {
                 #[cfg(feature = "with_agc")]
@@ -683,25 +1327,12 @@ impl SeqIndexDB {
                 Backend::MEMORY => self.seq_db.as_ref().unwrap().get_seq_by_id(sid),
                 Backend::FASTX => self.seq_db.as_ref().unwrap().get_seq_by_id(sid),
                 Backend::FRG => self.frg_db.as_ref().unwrap().get_seq_by_id(sid),
+                Backend::FAI => self.get_seq_by_id(sid).unwrap_or_default(),
                 Backend::UNKNOWN => vec![],
             }
         };
 
-        let frag_map = self.get_shmmr_map_internal();
-        if frag_map.is_none() {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "can get frag_map",
-            ));
-        }
-        let mut overlaps =
-            FxHashMap::<(ShmmrGraphNode, ShmmrGraphNode), Vec<(u32, u8, u8)>>::default();
-        let mut frag_id = FxHashMap::<(u64, u64), usize>::default();
-        let mut id = 0_usize;
-
-        let frag_map = frag_map.unwrap();
-
-        let adj_list = if method == "from_fragmap" {
+        if method == "from_fragmap" {
             seq_db::frag_map_to_adj_list(frag_map, min_count, keeps)
         } else {
             let keeps = keeps.map(FxHashSet::<u32>::from_iter);
@@ -733,68 +1364,87 @@ impl SeqIndexDB {
                     )
                 })
                 .collect::<AdjList>()
-        };
+        }
+    }
 
-        adj_list.iter().for_each(|(k, v, w)| {
-            if v.0 <= w.0 {
-                let key = (*v, *w);
-                let val = (*k, v.2, w.2);
-                overlaps.entry(key).or_insert_with(Vec::new).push(val);
-                frag_id.entry((v.0, v.1)).or_insert_with(|| {
-                    let c_id = id;
-                    id += 1;
-                    c_id
-                });
-                frag_id.entry((w.0, w.1)).or_insert_with(|| {
-                    let c_id = id;
-                    id += 1;
-                    c_id
-                });
+    /// write the coverage of the segments and the links of the MAP graph GFA (with the same segment ids as
+    /// [`SeqIndexDB::write_mapg_gfa`]) to a TSV file for filtering the graph by the support, the coverage of a
+    /// segment is the number of the fragments of its shimmer pair, and the coverage of a link is the number of
+    /// the traversals of the sequences, followed by the number and the ids of the sequences traversing them,
+    /// and by the number ("depth") and the names of the haplotypes traversing them if the haplotypes of the
+    /// sequences are given
+    pub fn write_mapg_coverage_tsv(
+        &self,
+        min_count: usize,
+        filepath: &str,
+        method: &str,
+        keeps: Option<Vec<u32>>,
+        sid_to_haplotype: Option<&FxHashMap<u32, String>>,
+    ) -> Result<(), std::io::Error> {
+        let frag_map = self.get_shmmr_map_internal();
+        if frag_map.is_none() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "can get frag_map",
+            ));
+        }
+        let frag_map = frag_map.unwrap();
+        let adj_list = self.get_mapg_adj_list(frag_map, min_count, method, keeps);
+        let (frag_id, overlaps) = get_mapg_segments_and_links(&adj_list);
+
+        let mut out_file = BufWriter::new(File::create(filepath)?);
+        writeln!(
+            out_file,
+            "#type\tsegment\tlink_to\tshmmr_pair\tcoverage\tn_seqs\tsids{}",
+            if sid_to_haplotype.is_some() {
+                "\tdepth\thaplotypes"
+            } else {
+                ""
             }
-        });
-
-        let mut out_file = BufWriter::new(File::create(filepath).unwrap());
-
-        let kmer_size = self.shmmr_spec.as_ref().unwrap().k;
-        out_file
-            .write_all("H\tVN:Z:1.0\tCM:Z:Sparse Genome Graph Generated By pgr-tk\n".as_bytes())?;
-        frag_id
+        )?;
+        let mut segments = frag_id.iter().collect::<Vec<_>>();
+        segments.sort_by_key(|(_, id)| **id);
+        for (smp, id) in segments {
+            let hits = frag_map.get(smp).unwrap();
+            let sids = sorted_sids(hits.iter().map(|h| h.1));
+            writeln!(
+                out_file,
+                "S\t{}\t*\t{:016x}_{:016x}\t{}\t{}\t{}{}",
+                id,
+                smp.0,
+                smp.1,
+                hits.len(),
+                sids.len(),
+                sids_to_tag_value(&sids),
+                haplotype_columns(&sids, sid_to_haplotype)
+            )?;
+        }
+        let mut links = overlaps
             .iter()
-            .try_for_each(|(smp, id)| -> Result<(), std::io::Error> {
-                let hits = frag_map.get(smp).unwrap();
-                let ave_len =
-                    hits.iter().fold(0_u32, |len_sum, &s| len_sum + s.3 - s.2) / hits.len() as u32;
-                let seg_line = format!(
-                    "S\t{}\t*\tLN:i:{}\tSN:Z:{:016x}_{:016x}\n",
-                    id,
-                    ave_len + kmer_size,
-                    smp.0,
-                    smp.1
-                );
-                out_file.write_all(seg_line.as_bytes())?;
-                Ok(())
-            })?;
-
-        overlaps
-            .into_iter()
-            .try_for_each(|(op, vs)| -> Result<(), std::io::Error> {
-                let o1 = if op.0 .2 == 0 { "+" } else { "-" };
-                let o2 = if op.1 .2 == 0 { "+" } else { "-" };
+            .map(|(op, vs)| {
                 let id0 = frag_id.get(&(op.0 .0, op.0 .1)).unwrap();
                 let id1 = frag_id.get(&(op.1 .0, op.1 .1)).unwrap();
-                let overlap_line = format!(
-                    "L\t{}\t{}\t{}\t{}\t{}M\tSC:i:{}\n",
-                    id0,
-                    o1,
-                    id1,
-                    o2,
-                    kmer_size,
-                    vs.len()
-                );
-                out_file.write_all(overlap_line.as_bytes())?;
-                Ok(())
-            })?;
-
+                ((*id0, op.0 .2, *id1, op.1 .2), vs)
+            })
+            .collect::<Vec<_>>();
+        links.sort_by_key(|(link, _)| *link);
+        for ((id0, o1, id1, o2), vs) in links {
+            let o1 = if o1 == 0 { "+" } else { "-" };
+            let o2 = if o2 == 0 { "+" } else { "-" };
+            let sids = sorted_sids(vs.iter().map(|v| v.0));
+            writeln!(
+                out_file,
+                "L\t{}{}\t{}{}\t*\t{}\t{}\t{}{}",
+                id0,
+                o1,
+                id1,
+                o2,
+                vs.len(),
+                sids.len(),
+                sids_to_tag_value(&sids),
+                haplotype_columns(&sids, sid_to_haplotype)
+            )?;
+        }
         Ok(())
     }
 
@@ -871,14 +1521,10 @@ impl SeqIndexDB {
             ));
         };
         let frag_map = frag_map.unwrap();
-        let adj_list = seq_db::frag_map_to_adj_list(frag_map, min_count, keeps);
+        let adj_list = self.get_principal_bundle_adj_list(frag_map, min_count, keeps);
 
         // println!("DBG: adj_list len {:?}", adj_list.len());
 
-        let mut overlaps =
-            FxHashMap::<(ShmmrGraphNode, ShmmrGraphNode), Vec<(u32, u8, u8)>>::default();
-        let mut frag_id = FxHashMap::<(u64, u64), usize>::default();
-        let mut id = 0_usize;
         let (pb, filtered_adj_list) =
             seq_db::get_principal_bundles_from_adj_list(frag_map, &adj_list, path_len_cutoff);
 
@@ -892,23 +1538,7 @@ impl SeqIndexDB {
 
         let vertex_to_bundle_id_direction_pos = self.get_vertex_map_from_principal_bundles(pb);
 
-        filtered_adj_list.iter().for_each(|(k, v, w)| {
-            if v.0 <= w.0 {
-                let key = (*v, *w);
-                let val = (*k, v.2, w.2);
-                overlaps.entry(key).or_insert_with(Vec::new).push(val);
-                frag_id.entry((v.0, v.1)).or_insert_with(|| {
-                    let c_id = id;
-                    id += 1;
-                    c_id
-                });
-                frag_id.entry((w.0, w.1)).or_insert_with(|| {
-                    let c_id = id;
-                    id += 1;
-                    c_id
-                });
-            }
-        });
+        let (frag_id, overlaps) = get_mapg_segments_and_links(&filtered_adj_list);
 
         let mut out_file = BufWriter::new(File::create(filepath).unwrap());
 
@@ -921,24 +1551,29 @@ impl SeqIndexDB {
                 let hits = frag_map.get(smp).unwrap();
                 let ave_len =
                     hits.iter().fold(0_u32, |len_sum, &s| len_sum + s.3 - s.2) / hits.len() as u32;
+                let sids = sorted_sids(hits.iter().map(|h| h.1));
                 let seg_line;
                 if let Some(bundle_id) = vertex_to_bundle_id_direction_pos.get(smp) {
                     seg_line = format!(
-                        "S\t{}\t*\tLN:i:{}\tSN:Z:{:016x}_{:016x}\tBN:i:{}\tBP:i:{}\n",
+                        "S\t{}\t*\tLN:i:{}\tSN:Z:{:016x}_{:016x}\tSC:i:{}\tSI:Z:{}\tBN:i:{}\tBP:i:{}\n",
                         id,
                         ave_len + kmer_size,
                         smp.0,
                         smp.1,
+                        hits.len(),
+                        sids_to_tag_value(&sids),
                         bundle_id.0,
                         bundle_id.2
                     );
                 } else {
                     seg_line = format!(
-                        "S\t{}\t*\tLN:i:{}\tSN:Z:{:016x}_{:016x}\n",
+                        "S\t{}\t*\tLN:i:{}\tSN:Z:{:016x}_{:016x}\tSC:i:{}\tSI:Z:{}\n",
                         id,
                         ave_len + kmer_size,
                         smp.0,
-                        smp.1
+                        smp.1,
+                        hits.len(),
+                        sids_to_tag_value(&sids)
                     );
                 }
                 out_file.write_all(seg_line.as_bytes())?;
@@ -952,14 +1587,16 @@ impl SeqIndexDB {
                 let o2 = if op.1 .2 == 0 { "+" } else { "-" };
                 let id0 = frag_id.get(&(op.0 .0, op.0 .1)).unwrap();
                 let id1 = frag_id.get(&(op.1 .0, op.1 .1)).unwrap();
+                let sids = sorted_sids(vs.iter().map(|v| v.0));
                 let overlap_line = format!(
-                    "L\t{}\t{}\t{}\t{}\t{}M\tSC:i:{}\n",
+                    "L\t{}\t{}\t{}\t{}\t{}M\tSC:i:{}\tSI:Z:{}\n",
                     id0,
                     o1,
                     id1,
                     o2,
                     kmer_size,
-                    vs.len()
+                    vs.len(),
+                    sids_to_tag_value(&sids)
                 );
                 out_file.write_all(overlap_line.as_bytes())?;
                 Ok(())
@@ -970,6 +1607,21 @@ impl SeqIndexDB {
 }
 
 impl SeqIndexDB {
+    // the (ctg_name, source) of a sequence id, for fetching the sequence from the backends keyed by the names
+    fn get_ctg_name_and_source_by_id(&self, sid: u32) -> Result<(&str, &str), std::io::Error> {
+        let (ctg_name, source, _) = self
+            .seq_info
+            .as_ref()
+            .and_then(|seq_info| seq_info.get(&sid))
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("sequence id {} is not in the index", sid),
+                )
+            })?;
+        Ok((ctg_name, source.as_deref().unwrap_or("-")))
+    }
+
     // depending on the storage type, return the corresponded index
     pub fn get_shmmr_map_internal(&self) -> Option<&seq_db::ShmmrToFrags> {
         match self.backend {
@@ -978,9 +1630,44 @@ impl SeqIndexDB {
             Backend::FASTX => Some(&self.seq_db.as_ref().unwrap().frag_map),
             Backend::MEMORY => Some(&self.seq_db.as_ref().unwrap().frag_map),
             Backend::FRG => None,
+            Backend::FAI => None,
             Backend::UNKNOWN => None,
         }
     }
+
+    // return the number of fragments in the database associated with a shimmer pair
+    pub fn get_shmmr_pair_count(&self, shmmr_pair: ShmmrPair) -> usize {
+        match self.backend {
+            #[cfg(feature = "with_agc")]
+            Backend::AGC => self
+                .agc_db
+                .as_ref()
+                .unwrap()
+                .frag_location_map
+                .get(&shmmr_pair)
+                .map_or(0, |&(_, vec_len)| vec_len),
+            Backend::FRG => self
+                .frg_db
+                .as_ref()
+                .unwrap()
+                .frag_location_map
+                .get(&shmmr_pair)
+                .map_or(0, |&(_, vec_len)| vec_len),
+            Backend::FAI => self
+                .fai_db
+                .as_ref()
+                .unwrap()
+                .frag_location_map
+                .get(&shmmr_pair)
+                .map_or(0, |&(_, vec_len)| vec_len),
+            Backend::FASTX | Backend::MEMORY => self
+                .get_shmmr_map_internal()
+                .unwrap()
+                .get(&shmmr_pair)
+                .map_or(0, |v| v.len()),
+            Backend::UNKNOWN => 0,
+        }
+    }
 }
 #[allow(clippy::type_complexity)] // TODO: Define the type for readability
 pub fn get_principal_bundle_decomposition(
@@ -1024,6 +1711,92 @@ pub fn get_principal_bundle_decomposition(
     seqid_smps_with_bundle_id_seg_direction
 }
 
+// the segment ids of the shimmer pairs and the traversals (sid, orientation, orientation) of the links in
+// the MAP graph GFA, a link and its reverse complement are kept once
+#[allow(clippy::type_complexity)]
+fn get_mapg_segments_and_links(
+    adj_list: &[AdjPair],
+) -> (
+    FxHashMap<ShmmrPair, usize>,
+    FxHashMap<(ShmmrGraphNode, ShmmrGraphNode), Vec<(u32, u8, u8)>>,
+) {
+    let mut overlaps = FxHashMap::<(ShmmrGraphNode, ShmmrGraphNode), Vec<(u32, u8, u8)>>::default();
+    let mut frag_id = FxHashMap::<(u64, u64), usize>::default();
+    let mut id = 0_usize;
+    adj_list.iter().for_each(|(k, v, w)| {
+        if v.0 <= w.0 {
+            let key = (*v, *w);
+            let val = (*k, v.2, w.2);
+            overlaps.entry(key).or_insert_with(Vec::new).push(val);
+            frag_id.entry((v.0, v.1)).or_insert_with(|| {
+                let c_id = id;
+                id += 1;
+                c_id
+            });
+            frag_id.entry((w.0, w.1)).or_insert_with(|| {
+                let c_id = id;
+                id += 1;
+                c_id
+            });
+        }
+    });
+    (frag_id, overlaps)
+}
+
+// the distinct sequence ids, sorted
+fn sorted_sids<I: Iterator<Item = u32>>(sids: I) -> Vec<u32> {
+    let mut sids = sids.collect::<Vec<_>>();
+    sids.sort();
+    sids.dedup();
+    sids
+}
+
+// the sequence ids as the value of a "SI:Z" tag, "*" if there is none
+fn sids_to_tag_value(sids: &[u32]) -> String {
+    if sids.is_empty() {
+        return "*".to_string();
+    }
+    sids.iter()
+        .map(|sid| format!("{}", sid))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// the number and the names of the distinct haplotypes of the sequences, "*" for the names if there is none
+fn haplotype_depth(sids: &[u32], sid_to_haplotype: &FxHashMap<u32, String>) -> (usize, String) {
+    let mut haplotypes = sids
+        .iter()
+        .filter_map(|sid| sid_to_haplotype.get(sid).map(|h| h.as_str()))
+        .collect::<Vec<_>>();
+    haplotypes.sort();
+    haplotypes.dedup();
+    if haplotypes.is_empty() {
+        (0, "*".to_string())
+    } else {
+        (haplotypes.len(), haplotypes.join(","))
+    }
+}
+
+// the "DP:i" and "HP:Z" tags of the haplotypes of the sequences, empty if the haplotypes are not given
+fn haplotype_tags(sids: &[u32], sid_to_haplotype: Option<&FxHashMap<u32, String>>) -> String {
+    sid_to_haplotype
+        .map(|sid_to_haplotype| {
+            let (depth, haplotypes) = haplotype_depth(sids, sid_to_haplotype);
+            format!("\tDP:i:{}\tHP:Z:{}", depth, haplotypes)
+        })
+        .unwrap_or_default()
+}
+
+// the "depth" and "haplotypes" columns of the coverage TSV file, empty if the haplotypes are not given
+fn haplotype_columns(sids: &[u32], sid_to_haplotype: Option<&FxHashMap<u32, String>>) -> String {
+    sid_to_haplotype
+        .map(|sid_to_haplotype| {
+            let (depth, haplotypes) = haplotype_depth(sids, sid_to_haplotype);
+            format!("\t{}\t{}", depth, haplotypes)
+        })
+        .unwrap_or_default()
+}
+
 pub fn get_fastx_reader(
     filepath: String,
     to_upper_case: bool,
@@ -1062,3 +1835,122 @@ pub fn get_fastx_reader(
         ))
     }
 }
+
+/// the source name of a fastx file, the file name without the directory and the fasta/fastq (and ".gz") extensions
+pub fn fastx_source_name(filepath: &str) -> String {
+    let name = std::path::Path::new(filepath)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| filepath.to_string());
+    let name = name
+        .strip_suffix(".gz")
+        .or_else(|| name.strip_suffix(".bgz"))
+        .unwrap_or(&name);
+    [".fa", ".fasta", ".fna", ".fq", ".fastq"]
+        .iter()
+        .find_map(|ext| name.strip_suffix(ext))
+        .unwrap_or(name)
+        .to_string()
+}
+
+/// match a file name with a pattern of the `*` and `?` wildcards
+fn match_wildcard(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0_usize, 0_usize);
+    // the positions after the last `*` in the pattern and of the name matched by it
+    let mut star = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == b'?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == b'*' {
+            p += 1;
+            star = Some((p, n));
+        } else if let Some((star_p, star_n)) = star {
+            p = star_p;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+/// expand a list of the fastx file paths to the (source, path) list, the `*` and `?` wildcards in the file
+/// names are matched against the files in the directories, and each file is labeled by its source name from
+/// [fastx_source_name], the files with the same source name are rejected as their provenance is ambiguous
+pub fn fastx_files_with_sources(paths: &[String]) -> Result<Vec<(String, String)>, std::io::Error> {
+    let mut fastx_files = Vec::<(String, String)>::new();
+    for path in paths {
+        let file_path = std::path::Path::new(path);
+        let file_name = file_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if !file_name.contains(['*', '?']) {
+            fastx_files.push((fastx_source_name(path), path.clone()));
+            continue;
+        }
+        let dir = match file_path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => std::path::PathBuf::from("."),
+        };
+        let mut matched = std::fs::read_dir(&dir)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_file())
+            .filter(|entry| {
+                match_wildcard(
+                    file_name.as_bytes(),
+                    entry.file_name().to_string_lossy().as_bytes(),
+                )
+            })
+            .map(|entry| file_path.with_file_name(entry.file_name()))
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect::<Vec<String>>();
+        if matched.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("no fastx file matches {}", path),
+            ));
+        }
+        matched.sort();
+        fastx_files.extend(
+            matched
+                .into_iter()
+                .map(|path| (fastx_source_name(&path), path)),
+        );
+    }
+    let mut sources = FxHashSet::<&str>::default();
+    if let Some((source, _)) = fastx_files
+        .iter()
+        .find(|(source, _)| !sources.insert(source.as_str()))
+    {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("more than one fastx files with the source name {}", source),
+        ));
+    }
+    Ok(fastx_files)
+}
+
+/// read a manifest of the fastx files, each line contains a source (sample) name and the path to a fastx file
+/// separated by a tab, the empty lines and the lines starting with "#" are skipped
+pub fn read_fastx_manifest(manifest_path: &str) -> Result<Vec<(String, String)>, std::io::Error> {
+    let manifest = BufReader::new(File::open(manifest_path)?);
+    let mut fastx_files = Vec::<(String, String)>::new();
+    for line in manifest.lines() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields = line.trim().split('\t').collect::<Vec<&str>>();
+        if fields.len() < 2 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("manifest parsing error on the line: {}", line),
+            ));
+        }
+        fastx_files.push((fields[0].to_string(), fields[1].to_string()));
+    }
+    Ok(fastx_files)
+}
@@ -1,14 +1,19 @@
 #![allow(dead_code)]
 
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
 use flate2::bufread::MultiGzDecoder;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::prelude::*;
-use std::io::{self, BufReader, SeekFrom};
+use std::io::{self, BufReader, BufWriter, SeekFrom};
+use std::path::Path;
 #[derive(Debug, Clone)]
 pub struct SeqRec {
     pub source: Option<String>,
     pub id: Vec<u8>,
     pub seq: Vec<u8>,
+    /// the base qualities of the FASTQ records (the phred+33 characters), only kept if the reader is set to keep them
+    pub qual: Option<Vec<u8>>,
 }
 
 enum Fastx {
@@ -22,6 +27,7 @@ pub struct FastaReader<R> {
     seq_capacity: usize,
     keep_source: bool,
     to_upper_case: bool,
+    keep_qual: bool,
 }
 
 pub fn reverse_complement(seq: &[u8]) -> Vec<u8> {
@@ -76,9 +82,22 @@ impl<R: BufRead> FastaReader<R> {
             seq_capacity,
             keep_source,
             to_upper_case,
+            keep_qual: false,
         })
     }
 
+    /// keep the base qualities of the FASTQ records in [SeqRec::qual]
+    pub fn set_keep_qual(&mut self, keep_qual: bool) {
+        self.keep_qual = keep_qual;
+    }
+
+    /// iterate the records in batches of up to `batch_size` records and `max_bases` bases ("0" for no limit),
+    /// a record longer than `max_bases` is in a batch by itself, a record that would take a batch over
+    /// `max_bases` starts the next batch
+    pub fn chunks(self, batch_size: usize, max_bases: usize) -> SeqRecChunks<Self> {
+        SeqRecChunks::new(self, batch_size, max_bases)
+    }
+
     pub fn next_rec(&mut self) -> Option<io::Result<SeqRec>> {
         match self.t {
             Fastx::FastA => self.fasta_next_rec(),
@@ -119,54 +138,111 @@ impl<R: BufRead> FastaReader<R> {
         } else {
             None
         };
-        let rec = SeqRec { source, id, seq };
+        let rec = SeqRec {
+            source,
+            id,
+            seq,
+            qual: None,
+        };
 
         Some(Ok(rec))
     }
 
     pub fn fastq_next_rec(&mut self) -> Option<io::Result<SeqRec>> {
-        let mut id_tmp = Vec::<u8>::with_capacity(128);
-        let mut seq = Vec::<u8>::with_capacity(self.seq_capacity);
-
-        let _res = self.inner.read_until(b'\n', &mut id_tmp); //read id
+        let truncated_rec_error = |filename: &String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("truncated fastq record in file: {}", filename),
+            )
+        };
+        let trim_line_end = |line: &mut Vec<u8>| {
+            while line.last() == Some(&b'\n') || line.last() == Some(&b'\r') {
+                line.pop();
+            }
+        };
 
-        // fetch the first id up to the first space, strip '\n'
-        let mut r = BufReader::new(&id_tmp[..]);
-        let mut id = Vec::<u8>::with_capacity(128);
-        let _res = r.read_until(b' ', &mut id);
-        let id = id
-            .into_iter()
-            .filter(|c| *c != b'\n' && *c != b' ' && *c != b'\r')
-            .collect::<Vec<u8>>();
+        // read the id line, skipping the empty lines, the '@' of the first record is consumed by `new()`
+        let mut id_line = Vec::<u8>::with_capacity(128);
+        loop {
+            match self.inner.read_until(b'\n', &mut id_line) {
+                Ok(0) => return None,
+                Ok(_) => (),
+                Err(e) => return Some(Err(e)),
+            }
+            trim_line_end(&mut id_line);
+            if !id_line.is_empty() {
+                break;
+            }
+        }
+        // fetch the first id up to the first space or tab
+        let id = id_line
+            .strip_prefix(b"@")
+            .unwrap_or(&id_line[..])
+            .split(|c| *c == b' ' || *c == b'\t')
+            .next()
+            .unwrap_or_default()
+            .to_vec();
 
-        // get the seq
-        let _res = self.inner.read_until(b'\n', &mut seq);
+        // get the seq, it may be wrapped into multiple lines until the '+' line
+        let mut seq = Vec::<u8>::with_capacity(self.seq_capacity);
+        let mut line = Vec::<u8>::with_capacity(1024);
+        loop {
+            line.clear();
+            match self.inner.read_until(b'\n', &mut line) {
+                Ok(0) => return Some(Err(truncated_rec_error(&self.filename))),
+                Ok(_) => (),
+                Err(e) => return Some(Err(e)),
+            }
+            if line.first() == Some(&b'+') {
+                break;
+            }
+            trim_line_end(&mut line);
+            seq.extend_from_slice(&line[..]);
+        }
 
-        let mut seq = seq
-            .drain(..)
-            .filter(|c| *c != b'\n' && *c != b'\r')
-            .collect::<Vec<u8>>();
+        // get the QVs, the lines may start with '@' or '+', so they are read up to the length of the seq
+        let mut qual = Vec::<u8>::with_capacity(seq.len());
+        while qual.len() < seq.len() {
+            line.clear();
+            match self.inner.read_until(b'\n', &mut line) {
+                Ok(0) => return Some(Err(truncated_rec_error(&self.filename))),
+                Ok(_) => (),
+                Err(e) => return Some(Err(e)),
+            }
+            trim_line_end(&mut line);
+            qual.extend_from_slice(&line[..]);
+        }
+        if qual.len() != seq.len() {
+            return Some(Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "the lengths of the seq and the QVs are different for the fastq record {} in file: {}",
+                    String::from_utf8_lossy(&id),
+                    self.filename
+                ),
+            )));
+        }
 
         if seq.capacity() as f32 > seq.len() as f32 * 1.2 {
             seq.shrink_to_fit();
         }
+        if self.to_upper_case {
+            seq[..].make_ascii_uppercase();
+        }
 
         let source = if self.keep_source {
             Some(self.filename.to_string())
         } else {
             None
         };
+        let qual = if self.keep_qual { Some(qual) } else { None };
 
-        let rec = SeqRec { source, id, seq };
-        // ignore QV
-        let mut buf = Vec::<u8>::with_capacity(1024);
-        let _res = self.inner.read_until(b'+', &mut buf);
-        let _res = self.inner.read_until(b'\n', &mut buf);
-        let _res = self.inner.read_until(b'\n', &mut buf);
-        let res = self.inner.read_until(b'@', &mut buf); //get to id line
-        if res.ok() == Some(0) {
-            return None;
-        }
+        let rec = SeqRec {
+            source,
+            id,
+            seq,
+            qual,
+        };
         Some(Ok(rec))
     }
 }
@@ -178,6 +254,77 @@ impl<R: BufRead> Iterator for FastaReader<R> {
     }
 }
 
+/// the batches of the records with bounded record and base counts, see [FastaReader::chunks]
+pub struct SeqRecChunks<I> {
+    inner: I,
+    batch_size: usize,
+    max_bases: usize,
+    // the record held back from the last batch to keep it within the base budget
+    pending_rec: Option<SeqRec>,
+    // the error following the records of the last batch, it is returned after the batch
+    pending_error: Option<io::Error>,
+}
+
+impl<I: Iterator<Item = io::Result<SeqRec>>> SeqRecChunks<I> {
+    /// batch the records of any record iterator, e.g., the readers of the gzipped files or the AGC files
+    pub fn new(inner: I, batch_size: usize, max_bases: usize) -> Self {
+        let batch_size = if batch_size == 0 {
+            usize::MAX
+        } else {
+            batch_size
+        };
+        let max_bases = if max_bases == 0 {
+            usize::MAX
+        } else {
+            max_bases
+        };
+        SeqRecChunks {
+            inner,
+            batch_size,
+            max_bases,
+            pending_rec: None,
+            pending_error: None,
+        }
+    }
+}
+
+impl<I: Iterator<Item = io::Result<SeqRec>>> Iterator for SeqRecChunks<I> {
+    type Item = io::Result<Vec<SeqRec>>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut batch = Vec::<SeqRec>::new();
+        let mut n_bases = 0_usize;
+        if let Some(rec) = self.pending_rec.take() {
+            n_bases += rec.seq.len();
+            batch.push(rec);
+        } else if let Some(e) = self.pending_error.take() {
+            return Some(Err(e));
+        }
+        while batch.len() < self.batch_size && n_bases < self.max_bases {
+            match self.inner.next() {
+                Some(Ok(rec)) => {
+                    if !batch.is_empty() && n_bases + rec.seq.len() > self.max_bases {
+                        self.pending_rec = Some(rec);
+                        break;
+                    }
+                    n_bases += rec.seq.len();
+                    batch.push(rec);
+                }
+                Some(Err(e)) if batch.is_empty() => return Some(Err(e)),
+                Some(Err(e)) => {
+                    self.pending_error = Some(e);
+                    break;
+                }
+                None => break,
+            }
+        }
+        if batch.is_empty() {
+            None
+        } else {
+            Some(Ok(batch))
+        }
+    }
+}
+
 pub struct FastqStreamReader {
     inner: std::io::Stdin,
     seq_capacity: usize,
@@ -218,7 +365,12 @@ impl Iterator for FastqStreamReader {
                         return None;
                     };
                     let source = None;
-                    let rec = SeqRec { source, id, seq };
+                    let rec = SeqRec {
+                        source,
+                        id,
+                        seq,
+                        qual: None,
+                    };
                     Some(Ok(rec))
                 } else {
                     None
@@ -282,7 +434,12 @@ impl Iterator for FastaStreamReader {
             }
             let seq = seq[..].as_bytes().to_vec();
             let source = None;
-            let rec = SeqRec { source, id, seq };
+            let rec = SeqRec {
+                source,
+                id,
+                seq,
+                qual: None,
+            };
             Some(Ok(rec))
         } else {
             None
@@ -417,3 +574,540 @@ pub fn build(seq_list_file: &String, out_prefix: &String) -> Result<usize, io::E
     log::info!("average read length: {}", start as f32 / seq_id as f32);
     Ok(start)
 }
+
+/// a record of the faidx (`.fai`) index of a fasta file, the offset is the one of the first base in the
+/// (uncompressed) file, the line width includes the line end
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaiRecord {
+    pub name: String,
+    pub length: u64,
+    pub offset: u64,
+    pub line_bases: u64,
+    pub line_width: u64,
+}
+
+/// a random access reader of the sub-sequences of a plain or bgzipped fasta file with the faidx (`.fai`)
+/// index and, for the bgzipped file, the index of the BGZF blocks (`.gzi`), the indexes are built and
+/// written next to the fasta file if they do not exist; the file is opened for each fetch, so the reader
+/// can be shared by the threads
+pub struct IndexedFastaReader {
+    filepath: String,
+    fai_records: Vec<FaiRecord>,
+    name_to_fai_idx: HashMap<String, usize>,
+    // the (compressed, uncompressed) offsets of the BGZF blocks, None for the plain fasta file
+    gzi_offsets: Option<Vec<(u64, u64)>>,
+}
+
+impl IndexedFastaReader {
+    pub fn new(filepath: &str) -> Result<Self, io::Error> {
+        let mut magic = Vec::<u8>::new();
+        File::open(filepath)?.take(4).read_to_end(&mut magic)?;
+        let gzi_offsets = if magic.starts_with(&[0x1F, 0x8B]) {
+            if magic != [0x1F, 0x8B, 0x08, 0x04] {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("the gz-compressed file is not bgzipped: {}", filepath),
+                ));
+            }
+            let gzi_path = format!("{}.gzi", filepath);
+            let offsets = if Path::new(&gzi_path).exists() {
+                read_gzi_file(&gzi_path)?
+            } else {
+                let offsets = build_gzi_offsets(filepath)?;
+                if let Err(e) = write_gzi_file(&gzi_path, &offsets) {
+                    log::warn!("fail to write the gzi index {}: {}", gzi_path, e);
+                }
+                offsets
+            };
+            Some(offsets)
+        } else {
+            None
+        };
+
+        let fai_path = format!("{}.fai", filepath);
+        let fai_records = if Path::new(&fai_path).exists() {
+            read_fai_file(&fai_path)?
+        } else {
+            let reader = BufReader::new(File::open(filepath)?);
+            let fai_records = if gzi_offsets.is_some() {
+                build_fai_records(BufReader::new(MultiGzDecoder::new(reader)))?
+            } else {
+                build_fai_records(reader)?
+            };
+            if let Err(e) = write_fai_file(&fai_path, &fai_records) {
+                log::warn!("fail to write the fai index {}: {}", fai_path, e);
+            }
+            fai_records
+        };
+        let name_to_fai_idx = fai_records
+            .iter()
+            .enumerate()
+            .map(|(idx, rec)| (rec.name.clone(), idx))
+            .collect::<HashMap<_, _>>();
+
+        Ok(IndexedFastaReader {
+            filepath: filepath.to_string(),
+            fai_records,
+            name_to_fai_idx,
+            gzi_offsets,
+        })
+    }
+
+    /// the fai records of the sequences in the file order
+    pub fn fai_records(&self) -> &[FaiRecord] {
+        &self.fai_records[..]
+    }
+
+    pub fn get_seq_len(&self, name: &str) -> Option<usize> {
+        self.name_to_fai_idx
+            .get(name)
+            .map(|idx| self.fai_records[*idx].length as usize)
+    }
+
+    /// fetch the bases `[bgn, end)` of a sequence
+    pub fn get_sub_seq(&self, name: &str, bgn: usize, end: usize) -> Result<Vec<u8>, io::Error> {
+        let rec = self
+            .name_to_fai_idx
+            .get(name)
+            .map(|idx| &self.fai_records[*idx])
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("sequence {} not found in {}", name, self.filepath),
+                )
+            })?;
+        let (bgn, end) = (bgn as u64, end as u64);
+        if bgn > end || end > rec.length {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "invalid range {}-{} of the sequence {} (length = {})",
+                    bgn, end, name, rec.length
+                ),
+            ));
+        }
+        if bgn == end {
+            return Ok(vec![]);
+        }
+        // the positions of the bases in the (uncompressed) file
+        let file_pos =
+            |pos: u64| rec.offset + pos / rec.line_bases * rec.line_width + pos % rec.line_bases;
+        let file_bgn = file_pos(bgn);
+        let file_end = file_pos(end - 1) + 1;
+        let mut seq = self.read_uncompressed(file_bgn, file_end - file_bgn)?;
+        seq.retain(|c| *c != b'\n' && *c != b'\r');
+        if seq.len() as u64 != end - bgn {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "the fai index is not consistent with the sequence {} in {}",
+                    name, self.filepath
+                ),
+            ));
+        }
+        Ok(seq)
+    }
+
+    pub fn get_seq(&self, name: &str) -> Result<Vec<u8>, io::Error> {
+        let seq_len = self.get_seq_len(name).unwrap_or(0);
+        self.get_sub_seq(name, 0, seq_len)
+    }
+
+    fn read_uncompressed(&self, bgn: u64, len: u64) -> Result<Vec<u8>, io::Error> {
+        let mut file = File::open(&self.filepath)?;
+        let mut buf = Vec::<u8>::with_capacity(len as usize);
+        if let Some(gzi_offsets) = self.gzi_offsets.as_ref() {
+            // start decompressing from the last block beginning before the range
+            let block_idx = gzi_offsets
+                .partition_point(|(_, u_offset)| *u_offset <= bgn)
+                .saturating_sub(1);
+            let (c_offset, u_offset) = gzi_offsets.get(block_idx).copied().unwrap_or((0, 0));
+            file.seek(SeekFrom::Start(c_offset))?;
+            let mut reader = MultiGzDecoder::new(BufReader::new(file));
+            io::copy(&mut reader.by_ref().take(bgn - u_offset), &mut io::sink())?;
+            reader.take(len).read_to_end(&mut buf)?;
+        } else {
+            file.seek(SeekFrom::Start(bgn))?;
+            BufReader::new(file).take(len).read_to_end(&mut buf)?;
+        }
+        if buf.len() as u64 != len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("fail to read {} bytes at {} of {}", len, bgn, self.filepath),
+            ));
+        }
+        Ok(buf)
+    }
+}
+
+/// build the fai records from an (uncompressed) fasta stream, every line of a sequence except the last one
+/// should have the same length
+pub fn build_fai_records<R: BufRead>(mut reader: R) -> Result<Vec<FaiRecord>, io::Error> {
+    let mut fai_records = Vec::<FaiRecord>::new();
+    let mut offset = 0_u64;
+    let mut line = Vec::<u8>::with_capacity(1024);
+    // the last line of a sequence can be shorter than the others
+    let mut last_line = false;
+    loop {
+        line.clear();
+        let n_bytes = reader.read_until(b'\n', &mut line)? as u64;
+        if n_bytes == 0 {
+            break;
+        }
+        offset += n_bytes;
+        if line[0] == b'>' {
+            let name = String::from_utf8_lossy(&line[1..])
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_string();
+            fai_records.push(FaiRecord {
+                name,
+                length: 0,
+                offset,
+                line_bases: 0,
+                line_width: 0,
+            });
+            last_line = false;
+            continue;
+        }
+        let n_bases = line.iter().filter(|c| **c != b'\n' && **c != b'\r').count() as u64;
+        if n_bases == 0 {
+            last_line = true;
+            continue;
+        }
+        let rec = fai_records.last_mut().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the fasta file does not start with a header line",
+            )
+        })?;
+        if last_line
+            || (rec.line_bases != 0
+                && (n_bases > rec.line_bases
+                    || (n_bases == rec.line_bases && n_bytes > rec.line_width)))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("different line lengths in the sequence {}", rec.name),
+            ));
+        }
+        if rec.line_bases == 0 {
+            rec.line_bases = n_bases;
+            rec.line_width = n_bytes;
+        } else if n_bases < rec.line_bases || n_bytes < rec.line_width {
+            last_line = true;
+        }
+        rec.length += n_bases;
+    }
+    Ok(fai_records)
+}
+
+pub fn read_fai_file(fai_path: &str) -> Result<Vec<FaiRecord>, io::Error> {
+    let reader = BufReader::new(File::open(fai_path)?);
+    reader
+        .lines()
+        .map(|line| {
+            let line = line?;
+            let fields = line.split('\t').collect::<Vec<_>>();
+            let parse = |idx: usize| fields.get(idx).and_then(|field| field.parse::<u64>().ok());
+            match (parse(1), parse(2), parse(3), parse(4)) {
+                (Some(length), Some(offset), Some(line_bases), Some(line_width)) => Ok(FaiRecord {
+                    name: fields[0].to_string(),
+                    length,
+                    offset,
+                    line_bases,
+                    line_width,
+                }),
+                _ => Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("fail to parse the fai record \"{}\" in {}", line, fai_path),
+                )),
+            }
+        })
+        .collect()
+}
+
+pub fn write_fai_file(fai_path: &str, fai_records: &[FaiRecord]) -> Result<(), io::Error> {
+    let mut writer = BufWriter::new(File::create(fai_path)?);
+    fai_records.iter().try_for_each(|rec| {
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}",
+            rec.name, rec.length, rec.offset, rec.line_bases, rec.line_width
+        )
+    })
+}
+
+/// scan the BGZF block headers for the (compressed, uncompressed) offsets of the blocks
+pub fn build_gzi_offsets(filepath: &str) -> Result<Vec<(u64, u64)>, io::Error> {
+    let mut reader = BufReader::new(File::open(filepath)?);
+    let mut offsets = Vec::<(u64, u64)>::new();
+    let (mut c_offset, mut u_offset) = (0_u64, 0_u64);
+    let mut header = [0_u8; 12];
+    let not_bgzf_error = |c_offset: u64| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("no BGZF block at {} of {}", c_offset, filepath),
+        )
+    };
+    loop {
+        match reader.read_exact(&mut header) {
+            Ok(()) => (),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+        if header[..4] != [0x1F, 0x8B, 0x08, 0x04] {
+            return Err(not_bgzf_error(c_offset));
+        }
+        // the block size is in the "BC" subfield of the extra field
+        let xlen = LittleEndian::read_u16(&header[10..12]) as usize;
+        let mut extra = vec![0_u8; xlen];
+        reader.read_exact(&mut extra)?;
+        let mut block_size = None;
+        let mut i = 0_usize;
+        while i + 4 <= xlen {
+            let subfield_len = LittleEndian::read_u16(&extra[i + 2..i + 4]) as usize;
+            if extra[i] == b'B' && extra[i + 1] == b'C' && subfield_len == 2 && i + 6 <= xlen {
+                block_size = Some(LittleEndian::read_u16(&extra[i + 4..i + 6]) as u64 + 1);
+            }
+            i += 4 + subfield_len;
+        }
+        let block_size = block_size.ok_or_else(|| not_bgzf_error(c_offset))?;
+        // skip the compressed data and the CRC32 to the uncompressed size
+        let data_len = block_size
+            .checked_sub(12 + xlen as u64 + 4)
+            .ok_or_else(|| not_bgzf_error(c_offset))?;
+        if io::copy(&mut reader.by_ref().take(data_len), &mut io::sink())? != data_len {
+            return Err(not_bgzf_error(c_offset));
+        }
+        let mut isize = [0_u8; 4];
+        reader.read_exact(&mut isize)?;
+        offsets.push((c_offset, u_offset));
+        c_offset += block_size;
+        u_offset += LittleEndian::read_u32(&isize) as u64;
+    }
+    Ok(offsets)
+}
+
+/// read a bgzip (`.gzi`) index, the first block at (0, 0) is not in the file
+pub fn read_gzi_file(gzi_path: &str) -> Result<Vec<(u64, u64)>, io::Error> {
+    let mut reader = BufReader::new(File::open(gzi_path)?);
+    let n_entries = reader.read_u64::<LittleEndian>()?;
+    let mut offsets = vec![(0_u64, 0_u64)];
+    for _ in 0..n_entries {
+        let c_offset = reader.read_u64::<LittleEndian>()?;
+        let u_offset = reader.read_u64::<LittleEndian>()?;
+        offsets.push((c_offset, u_offset));
+    }
+    Ok(offsets)
+}
+
+pub fn write_gzi_file(gzi_path: &str, offsets: &[(u64, u64)]) -> Result<(), io::Error> {
+    let mut writer = BufWriter::new(File::create(gzi_path)?);
+    let offsets = offsets
+        .iter()
+        .filter(|offset| **offset != (0, 0))
+        .collect::<Vec<_>>();
+    writer.write_u64::<LittleEndian>(offsets.len() as u64)?;
+    offsets.into_iter().try_for_each(|(c_offset, u_offset)| {
+        writer.write_u64::<LittleEndian>(*c_offset)?;
+        writer.write_u64::<LittleEndian>(*u_offset)
+    })
+}
+
+/// the compression of the fasta/fastq output files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FastxCompression {
+    Plain,
+    Gzip,
+    Bgzf,
+}
+
+impl FastxCompression {
+    /// the compression by the file extension, the ".gz" and ".bgz" files are bgzipped so they can be
+    /// indexed with `samtools faidx` or the `IndexedFastaReader`
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        match path.as_ref().extension().and_then(|ext| ext.to_str()) {
+            Some("gz") | Some("bgz") => FastxCompression::Bgzf,
+            _ => FastxCompression::Plain,
+        }
+    }
+}
+
+// the max size of the uncompressed data of a BGZF block, the same as the one used by bgzip
+const BGZF_BLOCK_SIZE: usize = 0xff00;
+// the empty block marking the end of a BGZF file
+const BGZF_EOF_BLOCK: [u8; 28] = [
+    0x1F, 0x8B, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00,
+    0x1B, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// a writer compressing the data into BGZF blocks, the end-of-file block is written by `finish()`
+/// or when the writer is dropped
+pub struct BgzfWriter<W: Write> {
+    inner: Option<W>,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> BgzfWriter<W> {
+    pub fn new(inner: W) -> Self {
+        BgzfWriter {
+            inner: Some(inner),
+            buf: Vec::with_capacity(BGZF_BLOCK_SIZE),
+        }
+    }
+
+    fn write_block(&mut self) -> Result<(), io::Error> {
+        let inner = self
+            .inner
+            .as_mut()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "the BGZF writer is finished"))?;
+        let mut encoder = flate2::write::DeflateEncoder::new(
+            Vec::with_capacity(self.buf.len()),
+            flate2::Compression::default(),
+        );
+        encoder.write_all(&self.buf)?;
+        let compressed = encoder.finish()?;
+        let mut crc = flate2::Crc::new();
+        crc.update(&self.buf);
+        // the gzip header with the "BC" extra subfield of the block size minus 1, the same as the one of the EOF block
+        inner.write_all(&BGZF_EOF_BLOCK[..16])?;
+        inner.write_u16::<LittleEndian>((compressed.len() + 25) as u16)?;
+        inner.write_all(&compressed)?;
+        inner.write_u32::<LittleEndian>(crc.sum())?;
+        inner.write_u32::<LittleEndian>(self.buf.len() as u32)?;
+        self.buf.clear();
+        Ok(())
+    }
+
+    fn try_finish(&mut self) -> Result<(), io::Error> {
+        if self.inner.is_none() {
+            return Ok(());
+        }
+        if !self.buf.is_empty() {
+            self.write_block()?;
+        }
+        let inner = self.inner.as_mut().unwrap();
+        inner.write_all(&BGZF_EOF_BLOCK)?;
+        inner.flush()
+    }
+
+    /// write the pending block and the end-of-file block and return the underlying writer
+    pub fn finish(mut self) -> Result<W, io::Error> {
+        self.try_finish()?;
+        Ok(self.inner.take().unwrap())
+    }
+}
+
+impl<W: Write> Write for BgzfWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(BGZF_BLOCK_SIZE - self.buf.len());
+        self.buf.extend_from_slice(&buf[..len]);
+        if self.buf.len() == BGZF_BLOCK_SIZE {
+            self.write_block()?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buf.is_empty() {
+            self.write_block()?;
+        }
+        match self.inner.as_mut() {
+            Some(inner) => inner.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<W: Write> Drop for BgzfWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.try_finish();
+    }
+}
+
+/// a writer of the fasta and fastq records, the fasta sequences are wrapped at `line_width` bases
+/// per line, or written in one line if `line_width` is zero
+pub struct FastxWriter<W: Write> {
+    inner: W,
+    line_width: usize,
+}
+
+impl<W: Write> FastxWriter<W> {
+    pub fn new(inner: W, line_width: usize) -> Self {
+        FastxWriter { inner, line_width }
+    }
+
+    pub fn write_fasta(&mut self, header: &str, seq: &[u8]) -> Result<(), io::Error> {
+        writeln!(self.inner, ">{}", header)?;
+        if self.line_width == 0 {
+            self.inner.write_all(seq)?;
+            writeln!(self.inner)
+        } else {
+            seq.chunks(self.line_width).try_for_each(|line| {
+                self.inner.write_all(line)?;
+                writeln!(self.inner)
+            })
+        }
+    }
+
+    pub fn write_fastq(&mut self, header: &str, seq: &[u8], qual: &[u8]) -> Result<(), io::Error> {
+        if seq.len() != qual.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "the sequence and quality lengths ({} and {}) of {} are different",
+                    seq.len(),
+                    qual.len(),
+                    header
+                ),
+            ));
+        }
+        writeln!(self.inner, "@{}", header)?;
+        self.inner.write_all(seq)?;
+        writeln!(self.inner, "\n+")?;
+        self.inner.write_all(qual)?;
+        writeln!(self.inner)
+    }
+
+    /// write a record as a fastq record if it has the base qualities, otherwise as a fasta record
+    pub fn write_rec(&mut self, rec: &SeqRec) -> Result<(), io::Error> {
+        let header = String::from_utf8_lossy(&rec.id);
+        match rec.qual.as_ref() {
+            Some(qual) => self.write_fastq(&header, &rec.seq, qual),
+            None => self.write_fasta(&header, &rec.seq),
+        }
+    }
+
+    pub fn flush(&mut self) -> Result<(), io::Error> {
+        self.inner.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+/// create a fasta/fastq output file, the compressed streams are finished when the writer is dropped
+pub fn create_fastx_writer<P: AsRef<Path>>(
+    path: P,
+    compression: FastxCompression,
+    line_width: usize,
+) -> Result<FastxWriter<Box<dyn Write + Send>>, io::Error> {
+    let file = BufWriter::new(File::create(path)?);
+    let inner: Box<dyn Write + Send> = match compression {
+        FastxCompression::Plain => Box::new(file),
+        FastxCompression::Gzip => Box::new(flate2::write::GzEncoder::new(
+            file,
+            flate2::Compression::default(),
+        )),
+        FastxCompression::Bgzf => Box::new(BgzfWriter::new(file)),
+    };
+    Ok(FastxWriter::new(inner, line_width))
+}
+
+/// the PanSN name (`sample#haplotype#contig`) of a contig with the delimiter, usually `#`
+pub fn pansn_name(sample: &str, haplotype: &str, ctg: &str, delimiter: char) -> String {
+    format!("{}{}{}{}{}", sample, delimiter, haplotype, delimiter, ctg)
+}
@@ -1,23 +1,23 @@
+use crate::remote_io::{self, DataFile};
 use crate::seq_db::{
     self, read_mdb_file_to_frag_locations, CompactSeq, Fragment, Fragments, GetSeq,
 };
 use crate::shmmrutils::ShmmrSpec;
 use bincode::config;
 use flate2::read::DeflateDecoder;
-use memmap2::Mmap;
 use rayon::prelude::*;
 use rustc_hash::FxHashMap;
 use std::fs::File;
-use std::io::{BufRead, BufReader, Read};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
 pub type ShmmrToFragMapLocation = FxHashMap<(u64, u64), (usize, usize)>;
 
 pub struct CompactSeqFragFileStorage {
     pub shmmr_spec: ShmmrSpec,
     pub seqs: Vec<CompactSeq>,
     pub frag_location_map: ShmmrToFragMapLocation,
-    pub frag_map_file: Mmap,
+    pub frag_map_file: DataFile,
     pub frag_file_prefix: String,
-    pub frag_file: Mmap,
+    pub frag_file: DataFile,
     pub frag_addr_offsets: Vec<(usize, usize, u32)>, //offset, compress_chunk_size, frag_len_in_bases
     pub frag_compress_chunk_size: usize,
     pub seq_index: FxHashMap<(String, Option<String>), (u32, u32)>,
@@ -26,14 +26,12 @@ pub struct CompactSeqFragFileStorage {
 }
 
 impl CompactSeqFragFileStorage {
+    /// open the frg backend database files with the prefix, which can be a URL with the `remote` feature
     pub fn new(prefix: String) -> Self {
         let frag_file_prefix = prefix;
 
-        let fmap_file =
-            File::open(frag_file_prefix.clone() + ".mdb").expect("frag map file open fail");
-
         let frag_map_file =
-            unsafe { Mmap::map(&fmap_file).expect("frag map file memory map creation fail") };
+            DataFile::open(&(frag_file_prefix.clone() + ".mdb")).expect("frag map file open fail");
 
         let (shmmr_spec, frag_location_map) =
             read_mdb_file_to_frag_locations(frag_file_prefix.clone() + ".mdb").unwrap();
@@ -42,7 +40,8 @@ impl CompactSeqFragFileStorage {
             FxHashMap::<(u64, u64), (usize, usize)>::from_iter(frag_location_map);
 
         let mut sdx_file = BufReader::new(
-            File::open(frag_file_prefix.clone() + ".sdx").expect("sdx file open error"),
+            remote_io::open_file(&(frag_file_prefix.clone() + ".sdx"))
+                .expect("sdx file open error"),
         );
         let mut sdx_version_string = [0_u8;7];
         sdx_file.read_exact(&mut sdx_version_string).expect("sdx file reading error");
@@ -52,14 +51,15 @@ impl CompactSeqFragFileStorage {
             Vec<(usize, usize, u32)>,
             Vec<CompactSeq>,
         ) = bincode::decode_from_std_read(&mut sdx_file, config).expect("read sdx file error");
-        let f_file = File::open(frag_file_prefix.clone() + ".frg").expect("frag file open fail");
-        let frag_file = unsafe { Mmap::map(&f_file).expect("frag file memory map creation fail") };
+        let frag_file =
+            DataFile::open(&(frag_file_prefix.clone() + ".frg")).expect("frag file open fail");
 
         let mut seq_index = FxHashMap::<(String, Option<String>), (u32, u32)>::default();
         let mut seq_info = FxHashMap::<u32, (String, Option<String>, u32)>::default();
 
         let midx_file = BufReader::new(
-            File::open(frag_file_prefix.clone() + ".midx").expect("open midx file fail"),
+            remote_io::open_file(&(frag_file_prefix.clone() + ".midx"))
+                .expect("open midx file fail"),
         );
         midx_file
             .lines()
@@ -117,6 +117,16 @@ impl CompactSeqFragFileStorage {
                             //println!("p: {} {}", p, p + b.len());
                             _p += b.len();
                         }
+                        Fragment::PackedPrefix(b) | Fragment::PackedSuffix(b) => {
+                            reconstructed_seq.extend_from_slice(&b.to_seq());
+                            _p += b.len();
+                        }
+                        Fragment::PackedInternal(b) => {
+                            reconstructed_seq.extend_from_slice(
+                                &b.sub_seq(self.shmmr_spec.k as usize, b.len()),
+                            );
+                            _p += b.len();
+                        }
                         Fragment::AlnSegments((frag_id, reversed, _length, a)) => {
                             let frag_group_id = *frag_id / self.frag_compress_chunk_size as u32;
                             let frag_group =
@@ -128,9 +138,9 @@ impl CompactSeqFragFileStorage {
                                     )
                                 });
 
-                            if let Fragment::Internal(base_seq) = frag_group
+                            if let Some(base_seq) = frag_group
                                 [*frag_id as usize % self.frag_compress_chunk_size]
-                                .clone()
+                                .internal_bases()
                             {
                                 let mut seq = seq_db::reconstruct_seq_from_aln_segs(&base_seq, a);
                                 if *reversed {
@@ -170,6 +180,24 @@ impl CompactSeqFragFileStorage {
 
         self.reconstruct_sequence_from_frags(frags)
     }
+
+    /// write the sequence index (.sdx) file, the fragment addresses point to the existing .frg file
+    pub fn write_sdx_file(&self, file_prefix: String) -> Result<(), std::io::Error> {
+        let mut sdx_file = BufWriter::new(File::create(file_prefix + ".sdx")?);
+        sdx_file.write_all("SDX:0.5".as_bytes())?;
+        let config = config::standard();
+        bincode::encode_into_std_write(
+            (
+                self.frag_compress_chunk_size,
+                &self.frag_addr_offsets,
+                &self.seqs,
+            ),
+            &mut sdx_file,
+            config,
+        )
+        .expect("sdx file writing error\n");
+        Ok(())
+    }
 }
 
 impl GetSeq for CompactSeqFragFileStorage {
@@ -231,13 +259,15 @@ impl GetSeq for CompactSeqFragFileStorage {
 fn fetch_frag_group(
     frag_group_id: u32,
     frag_addr_offsets: &[(usize, usize, u32)],
-    frag_file: &Mmap,
+    frag_file: &DataFile,
 ) -> Fragments {
     let config = config::standard();
     let (offset, size, _) = frag_addr_offsets[frag_group_id as usize];
     let version_string_offset = 7;
     let offset = offset + version_string_offset; 
-    let compress_chunk = frag_file[offset..(offset + size)].to_vec();
+    let compress_chunk = frag_file
+        .read_at(offset, size)
+        .expect("frag file reading error");
     let mut deflater = DeflateDecoder::new(&compress_chunk[..]);
     let mut s: Vec<u8> = vec![];
     deflater.read_to_end(&mut s).expect("decompression error");
@@ -1,14 +1,147 @@
 use bgzip::BGZFReader;
-use rustc_hash::FxHashMap;
+use flate2::bufread::MultiGzDecoder;
+use rustc_hash::{FxHashMap, FxHashSet};
 use serde::{Deserialize, Serialize};
-use serde_json;
-use std::char;
 use std::fmt;
 use std::fs::File;
-use std::io::BufRead;
+use std::io::{self, BufRead, BufReader};
 use std::path::Path;
 use std::rc::Rc;
 
+/// the annotation file formats, the GTF records are normalized with the GFF3 "ID", "Parent" and "Name"
+/// attributes derived from the "gene_id" and "transcript_id" attributes, so the feature hierarchy
+/// (gene -> transcript -> exon/CDS) is built in the same way for both formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum GFFFormat {
+    GFF3,
+    GTF,
+}
+
+impl GFFFormat {
+    /// guess the format from the file extension, GTF for ".gtf" (or ".gtf.gz") and GFF3 otherwise
+    pub fn from_path(filepath: &Path) -> Self {
+        let name = filepath
+            .file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let name = name
+            .strip_suffix(".gz")
+            .or_else(|| name.strip_suffix(".bgz"))
+            .unwrap_or(&name);
+        if name.ends_with(".gtf") {
+            GFFFormat::GTF
+        } else {
+            GFFFormat::GFF3
+        }
+    }
+}
+
+/// decode the "%XX" escapes in the GFF3 attributes
+fn percent_decode(s: &str) -> String {
+    if !s.contains('%') {
+        return s.to_string();
+    }
+    let bytes = s.as_bytes();
+    let mut out = Vec::<u8>::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let code = if bytes[i] == b'%' && i + 2 < bytes.len() {
+            std::str::from_utf8(&bytes[i + 1..i + 3])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        } else {
+            None
+        };
+        match code {
+            Some(c) => {
+                out.push(c);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// escape the characters reserved in the GFF3 attributes
+fn percent_encode(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '%' | ';' | '=' | '\t' | '\n' | '\r' => format!("%{:02X}", c as u32),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+/// parse the GFF3 attributes `key=value;key=value`
+fn parse_gff3_attributes(s: &str) -> io::Result<FxHashMap<String, String>> {
+    let mut attributes = FxHashMap::<String, String>::default();
+    for kv in s.split(';').map(|kv| kv.trim()) {
+        if kv.is_empty() || kv == "." {
+            continue;
+        }
+        let (k, v) = kv.split_once('=').ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("fail to parse the attribute {}", kv),
+            )
+        })?;
+        attributes.insert(percent_decode(k.trim()), percent_decode(v.trim()));
+    }
+    Ok(attributes)
+}
+
+/// parse the GTF attributes `key "value"; key "value";`, the values of a repeated key (e.g., "tag") are
+/// joined by commas as the multiple values of a GFF3 attribute
+fn parse_gtf_attributes(s: &str) -> io::Result<FxHashMap<String, String>> {
+    let mut attributes = FxHashMap::<String, String>::default();
+    for kv in s.split(';').map(|kv| kv.trim()) {
+        if kv.is_empty() {
+            continue;
+        }
+        let (k, v) = kv.split_once(char::is_whitespace).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("fail to parse the attribute {}", kv),
+            )
+        })?;
+        let v = v.trim().trim_matches('"');
+        attributes
+            .entry(k.to_string())
+            .and_modify(|e| {
+                e.push(',');
+                e.push_str(v);
+            })
+            .or_insert_with(|| v.to_string());
+    }
+    Ok(attributes)
+}
+
+/// add the GFF3 "ID", "Parent" and "Name" attributes of a GTF record, the existing ones are kept
+fn normalize_gtf_attributes(type_name: &str, attributes: &mut FxHashMap<String, String>) {
+    let gene_id = attributes.get("gene_id").cloned();
+    let transcript_id = attributes.get("transcript_id").cloned();
+    let (id, parent, name) = match type_name {
+        "gene" => (gene_id, None, attributes.get("gene_name").cloned()),
+        "transcript" | "mRNA" => (
+            transcript_id,
+            gene_id,
+            attributes.get("transcript_name").cloned(),
+        ),
+        _ => (None, transcript_id.or(gene_id), None),
+    };
+    [("ID", id), ("Parent", parent), ("Name", name)]
+        .into_iter()
+        .for_each(|(k, v)| {
+            if let Some(v) = v {
+                attributes.entry(k.to_string()).or_insert(v);
+            }
+        });
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct GFFRecord {
     pub seqid: String,
@@ -25,64 +158,107 @@ pub struct GFFRecord {
 
 impl GFFRecord {
     pub fn from_line(line: &str) -> GFFRecord {
-        let fields = line
-            .trim_end()
-            .split('\t')
-            .into_iter()
-            .map(|s| s.to_string())
-            .collect::<Vec<String>>();
-        GFFRecord::from_fields(&fields)
+        GFFRecord::parse_line(line, GFFFormat::GFF3).unwrap_or_else(|e| panic!("{}", e))
     }
 
     pub fn from_fields(fields: &[String]) -> GFFRecord {
-        let seqid = fields[0].clone();
-        let source = fields[1].clone();
-        let type_ = fields[2].clone();
-        let bgn = fields[3]
+        GFFRecord::parse_fields(fields, GFFFormat::GFF3).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// parse a tab-separated line of a GFF3 or GTF file
+    pub fn parse_line(line: &str, format: GFFFormat) -> io::Result<GFFRecord> {
+        let fields = line.trim_end().split('\t').collect::<Vec<&str>>();
+        GFFRecord::parse_fields(&fields, format)
+    }
+
+    /// parse the nine fields of a GFF3 or GTF record, the coordinates are kept as 1-based and end-inclusive
+    pub fn parse_fields<S: AsRef<str>>(fields: &[S], format: GFFFormat) -> io::Result<GFFRecord> {
+        let parse_error = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        if fields.len() < 8 {
+            return Err(parse_error(format!(
+                "expect 9 tab-separated fields, got {}",
+                fields.len()
+            )));
+        }
+        let field = |i: usize| fields.get(i).map_or("", |s| s.as_ref());
+        let seqid = field(0).to_string();
+        let source = field(1).to_string();
+        let type_name = field(2).to_string();
+        let bgn = field(3)
             .parse::<u32>()
-            .expect("can't parse the start coordinate");
-        let end = fields[4]
+            .map_err(|_| parse_error(format!("fail to parse the start coordinate {}", field(3))))?;
+        let end = field(4)
             .parse::<u32>()
-            .expect("can't parse the end coordinate");
-        let score = match fields[5].as_str() {
+            .map_err(|_| parse_error(format!("fail to parse the end coordinate {}", field(4))))?;
+        if bgn == 0 || bgn > end {
+            return Err(parse_error(format!(
+                "invalid feature interval {}-{}",
+                bgn, end
+            )));
+        }
+        let score = match field(5) {
             "." => None,
-            s => Some(s.parse::<f32>().expect("can't parse score")),
+            s => Some(
+                s.parse::<f32>()
+                    .map_err(|_| parse_error(format!("fail to parse the score {}", s)))?,
+            ),
         };
-
-        let strand = fields[6][0..1].chars().next().unwrap();
-        let phase = match fields[7].as_str() {
+        let strand = match field(6) {
+            s @ ("+" | "-" | "." | "?") => s.chars().next().unwrap(),
+            s => return Err(parse_error(format!("fail to parse the strand {}", s))),
+        };
+        let phase = match field(7) {
             "." => None,
-            s => Some(s.parse::<u8>().unwrap_or_else(|_| {
-                panic!("fail to parse the phase field {}", fields[6].as_str())
-            })),
+            s => Some(
+                s.parse::<u8>()
+                    .ok()
+                    .filter(|phase| *phase < 3)
+                    .ok_or_else(|| parse_error(format!("fail to parse the phase {}", s)))?,
+            ),
+        };
+        let attributes = match format {
+            GFFFormat::GFF3 => parse_gff3_attributes(field(8))?,
+            GFFFormat::GTF => {
+                let mut attributes = parse_gtf_attributes(field(8))?;
+                normalize_gtf_attributes(&type_name, &mut attributes);
+                attributes
+            }
         };
-        let attributes = fields[8]
-            .split(';')
-            .into_iter()
-            .map(|s| {
-                let kv = s
-                    .split('=')
-                    .into_iter()
-                    .map(|s| s.to_string())
-                    .collect::<Vec<String>>();
-                if kv.len() != 2 {
-                    panic!("error parsing attributes")
-                };
-                (kv[0].clone(), kv[1].clone())
-            })
-            .collect::<FxHashMap<String, String>>();
 
-        Self {
+        Ok(Self {
             seqid,
             source,
-            type_name: type_,
+            type_name,
             bgn,
             end,
             score,
             strand,
             phase,
             attributes,
-        }
+        })
+    }
+
+    /// the feature ID (the "ID" attribute)
+    pub fn id(&self) -> Option<&str> {
+        self.attributes.get("ID").map(|id| id.as_str())
+    }
+
+    /// the feature name (the "Name" attribute)
+    pub fn name(&self) -> Option<&str> {
+        self.attributes.get("Name").map(|name| name.as_str())
+    }
+
+    /// the IDs of the parent features, a feature (e.g., a shared exon) may have multiple parents separated by commas
+    pub fn parent_ids(&self) -> Vec<&str> {
+        self.attributes
+            .get("Parent")
+            .map(|parents| parents.split(',').filter(|id| !id.is_empty()).collect())
+            .unwrap_or_default()
+    }
+
+    /// whether the feature overlaps the 0-based half-open interval `[bgn, end)` as the BED coordinates
+    pub fn overlaps(&self, bgn: u32, end: u32) -> bool {
+        self.bgn - 1 < end && bgn < self.end
     }
 }
 
@@ -94,24 +270,30 @@ impl fmt::Display for GFFRecord {
             self.seqid, self.source, self.type_name, self.bgn, self.end
         ));
 
-        out.push(if self.score.is_none() {
-            ".".to_string()
-        } else {
-            format!("{}", self.score.unwrap())
+        out.push(match self.score {
+            Some(score) => format!("{}", score),
+            None => ".".to_string(),
         });
 
         out.push(format!("{}", self.strand));
 
-        out.push(if self.phase.is_none() {
-            ".".to_string()
-        } else {
-            format!("{}", self.phase.unwrap())
+        out.push(match self.phase {
+            Some(phase) => format!("{}", phase),
+            None => ".".to_string(),
         });
 
+        // "ID" and "Parent" first, and then the other attributes in a fixed order
+        let mut keys = self.attributes.keys().collect::<Vec<_>>();
+        keys.sort_by_key(|k| (k.as_str() != "ID", k.as_str() != "Parent", *k));
         out.push(
-            self.attributes
-                .iter()
-                .map(|(k, v)| format!("{}={}", k, v))
+            keys.into_iter()
+                .map(|k| {
+                    format!(
+                        "{}={}",
+                        percent_encode(k),
+                        percent_encode(&self.attributes[k])
+                    )
+                })
                 .collect::<Vec<String>>()
                 .join(";"),
         );
@@ -120,10 +302,133 @@ impl fmt::Display for GFFRecord {
     }
 }
 
+/// the simple consequences of the variants, in the order of the severity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub enum VariantConsequence {
+    Intergenic,
+    Intronic,
+    Exonic,
+    CdsSubstitution,
+    CdsInframeIndel,
+    CdsFrameshift,
+}
+
+impl VariantConsequence {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VariantConsequence::Intergenic => "intergenic",
+            VariantConsequence::Intronic => "intronic",
+            VariantConsequence::Exonic => "exonic",
+            VariantConsequence::CdsSubstitution => "cds_substitution",
+            VariantConsequence::CdsInframeIndel => "cds_inframe_indel",
+            VariantConsequence::CdsFrameshift => "cds_frameshift",
+        }
+    }
+}
+
+impl fmt::Display for VariantConsequence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// an implicit interval tree (as cgranges) of the intervals sorted by the start coordinates, the node at the
+/// position `i` is on the level of the number of the trailing "1" bits of `i` and keeps the max end coordinate
+/// of its subtree
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct IntervalIndex {
+    // the (bgn, end, record index) of the 0-based half-open intervals
+    intervals: Vec<(u32, u32, u32)>,
+    max_ends: Vec<u32>,
+    max_level: u32,
+}
+
+impl IntervalIndex {
+    pub fn new(mut intervals: Vec<(u32, u32, u32)>) -> Self {
+        intervals.sort_unstable();
+        let n = intervals.len();
+        let mut max_ends = intervals.iter().map(|iv| iv.1).collect::<Vec<u32>>();
+        if n == 0 {
+            return IntervalIndex::default();
+        }
+        // the max end of the last subtree, for the nodes whose right subtrees are partially out of the range
+        let mut last_i = (n - 1) & !1;
+        let mut last = max_ends[last_i];
+        let mut k = 1_u32;
+        while (1 << k) <= n {
+            let x = 1_usize << (k - 1);
+            for i in ((x << 1) - 1..n).step_by(x << 2) {
+                let left = max_ends[i - x];
+                let right = if i + x < n { max_ends[i + x] } else { last };
+                max_ends[i] = max_ends[i].max(left).max(right);
+            }
+            last_i = if (last_i >> k) & 1 == 1 {
+                last_i - x
+            } else {
+                last_i + x
+            };
+            if last_i < n && max_ends[last_i] > last {
+                last = max_ends[last_i];
+            }
+            k += 1;
+        }
+        IntervalIndex {
+            intervals,
+            max_ends,
+            max_level: k - 1,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.intervals.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+
+    /// the record indices of the intervals overlapping `[bgn, end)`, in the order of the start coordinates
+    pub fn overlap(&self, bgn: u32, end: u32) -> Vec<u32> {
+        let n = self.intervals.len();
+        let mut out = Vec::<u32>::new();
+        if n == 0 {
+            return out;
+        }
+        // (node, level, whether the left subtree is visited)
+        let mut stack = vec![((1_usize << self.max_level) - 1, self.max_level, false)];
+        while let Some((x, k, left_visited)) = stack.pop() {
+            if k <= 3 {
+                // scan the small subtree linearly
+                let i0 = x >> k << k;
+                let i1 = (i0 + (1 << (k + 1)) - 1).min(n);
+                self.intervals[i0..i1]
+                    .iter()
+                    .take_while(|iv| iv.0 < end)
+                    .filter(|iv| bgn < iv.1)
+                    .for_each(|iv| out.push(iv.2));
+            } else if !left_visited {
+                let y = x - (1 << (k - 1));
+                stack.push((x, k, true));
+                // the left child can be out of the range
+                if y >= n || self.max_ends[y] > bgn {
+                    stack.push((y, k - 1, false));
+                }
+            } else if x < n && self.intervals[x].0 < end {
+                if bgn < self.intervals[x].1 {
+                    out.push(self.intervals[x].2);
+                }
+                stack.push((x + (1 << (k - 1)), k - 1, false));
+            }
+        }
+        out
+    }
+}
+
 type IdToGffRec = FxHashMap<String, Rc<GFFRecord>>;
 type IdToChildren = FxHashMap<String, Vec<Rc<GFFRecord>>>;
 type NameToGffRec = FxHashMap<String, Rc<GFFRecord>>;
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct GFFDB {
     pub header: Vec<String>,
@@ -131,123 +436,342 @@ pub struct GFFDB {
     pub id_to_rec: IdToGffRec,
     pub name_to_rec: NameToGffRec,
     pub children: IdToChildren,
+    /// the interval index of the records on each sequence
+    pub intervals: FxHashMap<String, IntervalIndex>,
 }
 
 impl GFFDB {
-    pub fn from_bgzip_file(filepath: &Path) -> std::io::Result<GFFDB> {
+    pub fn from_bgzip_file(filepath: &Path) -> io::Result<GFFDB> {
         let file = BGZFReader::new(File::open(filepath)?);
+        GFFDB::from_reader(file, GFFFormat::from_path(filepath))
+    }
+
+    /// load a GFF3 or GTF file (plain, gzipped or bgzipped), the format is guessed from the file extension
+    pub fn from_file(filepath: &Path) -> io::Result<GFFDB> {
+        let format = GFFFormat::from_path(filepath);
+        let mut reader = BufReader::new(File::open(filepath)?);
+        if reader.fill_buf()?.starts_with(&[0x1F_u8, 0x8B_u8]) {
+            GFFDB::from_reader(BufReader::new(MultiGzDecoder::new(reader)), format)
+        } else {
+            GFFDB::from_reader(reader, format)
+        }
+    }
+
+    /// parse the records from a reader, the comment and directive lines are kept as the header and the
+    /// sequences after the "##FASTA" directive are skipped
+    pub fn from_reader<R: BufRead>(reader: R, format: GFFFormat) -> io::Result<GFFDB> {
         let mut header = Vec::<String>::new();
-        let mut records = Vec::<Rc<GFFRecord>>::new();
-        let mut id_to_rec = IdToGffRec::default();
-        let mut name_to_rec = NameToGffRec::default();
-        let mut children = IdToChildren::default();
-        file.lines().into_iter().for_each(|line| {
-            let line = line.unwrap();
-            if &line[0..1] != "#" {
-                let rec = Rc::new(GFFRecord::from_line(&line));
-                records.push(rec.clone());
-
-                if rec.attributes.contains_key("ID") {
-                    let id = rec.attributes.get("ID").unwrap();
-                    id_to_rec.insert(id.clone(), rec.clone());
-                }
-                if rec.attributes.contains_key("Name") {
-                    let name = rec.attributes.get("Name").unwrap();
-                    name_to_rec.insert(name.clone(), rec.clone());
-                }
-                if rec.attributes.contains_key("Parent") {
-                    let parent_id = rec.attributes.get("Parent").unwrap();
-                    children
-                        .entry(parent_id.clone())
-                        .or_insert_with(Vec::new)
-                        .push(rec.clone());
-                }
-            } else {
+        let mut records = Vec::<GFFRecord>::new();
+        for (line_number, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.starts_with("##FASTA") {
+                break;
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            if line.starts_with('#') {
                 header.push(line);
+            } else {
+                let rec = GFFRecord::parse_line(&line, format).map_err(|e| {
+                    io::Error::new(e.kind(), format!("{} at line {}", e, line_number + 1))
+                })?;
+                records.push(rec);
             }
-        });
-        Ok(GFFDB {
-            header,
-            records,
-            id_to_rec,
-            name_to_rec,
-            children,
-        })
+        }
+        Ok(GFFDB::from_records(header, records))
     }
 
     pub fn from_list_of_fields(list_of_fields: &[Vec<String>]) -> GFFDB {
-        let header = Vec::<String>::new();
-        let mut records = Vec::<Rc<GFFRecord>>::new();
+        let records = list_of_fields
+            .iter()
+            .map(|fields| GFFRecord::from_fields(fields))
+            .collect();
+        GFFDB::from_records(Vec::<String>::new(), records)
+    }
+
+    /// build the feature hierarchy from the "ID" and "Parent" attributes and the interval indexes of the records
+    pub fn from_records(header: Vec<String>, records: Vec<GFFRecord>) -> GFFDB {
+        let records = records.into_iter().map(Rc::new).collect::<Vec<_>>();
         let mut id_to_rec = IdToGffRec::default();
         let mut name_to_rec = NameToGffRec::default();
         let mut children = IdToChildren::default();
+        let mut ctg_intervals = FxHashMap::<String, Vec<(u32, u32, u32)>>::default();
 
-        list_of_fields.iter().for_each(|fields| {
-            let rec = Rc::new(GFFRecord::from_fields(fields));
-            records.push(rec.clone());
-
-            if rec.attributes.contains_key("ID") {
-                let id = rec.attributes.get("ID").unwrap();
-                id_to_rec.insert(id.clone(), rec.clone());
+        records.iter().enumerate().for_each(|(idx, rec)| {
+            if let Some(id) = rec.id() {
+                id_to_rec.insert(id.to_string(), rec.clone());
             }
-            if rec.attributes.contains_key("Name") {
-                let name = rec.attributes.get("Name").unwrap();
-                name_to_rec.insert(name.clone(), rec.clone());
+            if let Some(name) = rec.name() {
+                name_to_rec.insert(name.to_string(), rec.clone());
             }
-            if rec.attributes.contains_key("Parent") {
-                let parent_id = rec.attributes.get("Parent").unwrap();
+            rec.parent_ids().into_iter().for_each(|parent_id| {
                 children
-                    .entry(parent_id.clone())
-                    .or_insert_with(Vec::new)
+                    .entry(parent_id.to_string())
+                    .or_default()
                     .push(rec.clone());
-            }
+            });
+            ctg_intervals.entry(rec.seqid.clone()).or_default().push((
+                rec.bgn.saturating_sub(1),
+                rec.end,
+                idx as u32,
+            ));
         });
 
+        let intervals = ctg_intervals
+            .into_iter()
+            .map(|(ctg, intervals)| (ctg, IntervalIndex::new(intervals)))
+            .collect();
+
         GFFDB {
             header,
             records,
             id_to_rec,
             name_to_rec,
             children,
+            intervals,
         }
     }
 
+    /// the record of a feature ID, or of a feature name if there is no feature with the ID
+    pub fn get_rec(&self, id_or_name: &str) -> Option<&Rc<GFFRecord>> {
+        self.id_to_rec
+            .get(id_or_name)
+            .or_else(|| self.name_to_rec.get(id_or_name))
+    }
+
+    /// the direct children of a feature, e.g., the transcripts of a gene or the exons and CDSs of a transcript
+    pub fn get_children(&self, id: &str) -> &[Rc<GFFRecord>] {
+        self.children
+            .get(id)
+            .map_or(&[], |children| children.as_slice())
+    }
+
+    /// the parents of a record
+    pub fn get_parents(&self, rec: &GFFRecord) -> Vec<Rc<GFFRecord>> {
+        rec.parent_ids()
+            .into_iter()
+            .filter_map(|id| self.id_to_rec.get(id).cloned())
+            .collect()
+    }
+
+    /// the top-level features (e.g., the genes) of a record by following its parents, a record without
+    /// any parent is its own top-level feature
+    pub fn get_roots(&self, rec: &Rc<GFFRecord>) -> Vec<Rc<GFFRecord>> {
+        let mut roots = Vec::<Rc<GFFRecord>>::new();
+        // guard against the cycles in a malformed hierarchy
+        let mut visited = FxHashSet::<String>::default();
+        let mut stack = vec![rec.clone()];
+        while let Some(rec) = stack.pop() {
+            let parents = self.get_parents(&rec);
+            if parents.is_empty() {
+                if !roots.iter().any(|r| Rc::ptr_eq(r, &rec)) {
+                    roots.push(rec);
+                }
+                continue;
+            }
+            parents.into_iter().for_each(|parent| {
+                if visited.insert(parent.id().unwrap_or_default().to_string()) {
+                    stack.push(parent);
+                }
+            });
+        }
+        roots
+    }
+
     pub fn get_all_offspring(
         &self,
-        id_or_name: &String,
+        id_or_name: &str,
         recusive: bool,
     ) -> Option<Vec<Rc<GFFRecord>>> {
         let mut all_offspring = Vec::<Rc<GFFRecord>>::new();
 
         let id = if self.id_to_rec.contains_key(id_or_name) {
             Some(id_or_name)
-        } else if self.name_to_rec.contains_key(id_or_name) {
-            let r = self.name_to_rec.get(id_or_name).unwrap();
-            r.attributes.get("ID")
         } else {
-            None
-        };
+            self.name_to_rec.get(id_or_name).and_then(|r| r.id())
+        }?;
 
-        id?;
-
-        let id = id.unwrap();
-        match self.children.get(id) {
-            Some(children) => {
-                children.iter().for_each(|r| {
-                    if recusive && r.attributes.contains_key("ID") {
-                        let id = r.attributes.get("ID").unwrap();
-                        if let Some(more_offsprings) = self.get_all_offspring(id, recusive) {
-                            more_offsprings.iter().for_each(|r| {
-                                all_offspring.push(r.clone());
-                            });
-                        }
+        let children = self.children.get(id)?;
+        children.iter().for_each(|r| {
+            if recusive {
+                if let Some(more_offsprings) =
+                    r.id().and_then(|id| self.get_all_offspring(id, recusive))
+                {
+                    more_offsprings.iter().for_each(|r| {
+                        all_offspring.push(r.clone());
+                    });
+                }
+            }
+            all_offspring.push(r.clone());
+        });
+        Some(all_offspring)
+    }
+
+    /// the features on the sequence `ctg` overlapping the 0-based half-open interval `[bgn, end)` (as the BED
+    /// coordinates), in the order of the start coordinates
+    pub fn features_overlapping(&self, ctg: &str, bgn: u32, end: u32) -> Vec<Rc<GFFRecord>> {
+        self.intervals
+            .get(ctg)
+            .map(|index| {
+                index
+                    .overlap(bgn, end)
+                    .into_iter()
+                    .map(|idx| self.records[idx as usize].clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// the features overlapping the window `[bgn, end)` (0-based) of the sequence `ctg` with the coordinates
+    /// relative to the window on the sequence `seqid`, e.g., for the annotation of a region sequence fetched from
+    /// a database, the coordinates and the strands are flipped if the region sequence is reverse complemented,
+    /// the features partially in the window are clipped with the "partial=true" attribute and the phases of the
+    /// clipped CDSs are adjusted
+    pub fn region_records(
+        &self,
+        ctg: &str,
+        bgn: u32,
+        end: u32,
+        seqid: &str,
+        reversed: bool,
+    ) -> Vec<GFFRecord> {
+        self.features_overlapping(ctg, bgn, end)
+            .into_iter()
+            .map(|rec| {
+                let (f_bgn, f_end) = (rec.bgn - 1, rec.end);
+                let (c_bgn, c_end) = (f_bgn.max(bgn), f_end.min(end));
+                let mut rec = (*rec).clone();
+                if (c_bgn, c_end) != (f_bgn, f_end) {
+                    rec.attributes
+                        .insert("partial".to_string(), "true".to_string());
+                }
+                // the number of the bases clipped from the 5' end of the feature
+                let clipped = if rec.strand == '-' {
+                    f_end - c_end
+                } else {
+                    c_bgn - f_bgn
+                };
+                rec.phase = rec
+                    .phase
+                    .map(|phase| ((phase as u32 + 3 - clipped % 3) % 3) as u8);
+                let (c_bgn, c_end) = if reversed {
+                    rec.strand = match rec.strand {
+                        '+' => '-',
+                        '-' => '+',
+                        strand => strand,
+                    };
+                    (end - c_end, end - c_bgn)
+                } else {
+                    (c_bgn - bgn, c_end - bgn)
+                };
+                rec.seqid = seqid.to_string();
+                rec.bgn = c_bgn + 1;
+                rec.end = c_end;
+                rec
+            })
+            .collect()
+    }
+
+    /// the genes (the top-level features with children or the features of the "gene" types) overlapping the
+    /// 0-based half-open intervals on the sequence `ctg` and the numbers of their bases covered by the intervals,
+    /// in the order of the first intervals overlapping the genes
+    pub fn genes_covered(&self, ctg: &str, intervals: &[(u32, u32)]) -> Vec<(Rc<GFFRecord>, u32)> {
+        let mut intervals = intervals.to_vec();
+        intervals.sort_unstable();
+        let mut merged_intervals = Vec::<(u32, u32)>::new();
+        intervals
+            .into_iter()
+            .for_each(|(bgn, end)| match merged_intervals.last_mut() {
+                Some(last) if bgn <= last.1 => last.1 = last.1.max(end),
+                _ => merged_intervals.push((bgn, end)),
+            });
+
+        let mut genes = Vec::<Rc<GFFRecord>>::new();
+        merged_intervals.iter().for_each(|&(bgn, end)| {
+            self.features_overlapping(ctg, bgn, end)
+                .iter()
+                .flat_map(|rec| self.get_roots(rec))
+                .filter(|rec| {
+                    rec.type_name.ends_with("gene")
+                        || rec.id().is_some_and(|id| self.children.contains_key(id))
+                })
+                .for_each(|gene| {
+                    if !genes.iter().any(|g| Rc::ptr_eq(g, &gene)) {
+                        genes.push(gene);
                     }
-                    all_offspring.push(r.clone());
                 });
-                Some(all_offspring)
-            }
-            None => None,
-        }
+        });
+        genes
+            .into_iter()
+            .map(|gene| {
+                let covered = merged_intervals
+                    .iter()
+                    .map(|&(bgn, end)| end.min(gene.end).saturating_sub(bgn.max(gene.bgn - 1)))
+                    .sum::<u32>();
+                (gene, covered)
+            })
+            .collect()
+    }
+
+    /// the most severe consequence of a VCF-like variant at the 0-based position `pos` of the sequence `ctg` and the
+    /// names (or IDs) of the genes with the consequence, the changed bases after the padding base of an indel
+    /// (the base after the padding base for an insertion) are intersected with the features, the exons and UTRs
+    /// are exonic and the other features with children (e.g., the genes and the transcripts) are intronic
+    pub fn variant_consequence(
+        &self,
+        ctg: &str,
+        pos: u32,
+        ref_allele: &str,
+        alt_allele: &str,
+    ) -> (VariantConsequence, Vec<String>) {
+        let (ref_len, alt_len) = (ref_allele.len() as u32, alt_allele.len() as u32);
+        let padded =
+            ref_len != alt_len && ref_allele.as_bytes().first() == alt_allele.as_bytes().first();
+        let (bgn, end) = if padded {
+            (pos + 1, pos + ref_len.max(2))
+        } else {
+            (pos, pos + ref_len.max(1))
+        };
+        let len_diff = ref_len.abs_diff(alt_len);
+        let mut consequence = VariantConsequence::Intergenic;
+        let mut features = Vec::<Rc<GFFRecord>>::new();
+        self.features_overlapping(ctg, bgn, end)
+            .into_iter()
+            .for_each(|rec| {
+                let c = match rec.type_name.as_str() {
+                    "CDS" if len_diff % 3 != 0 => VariantConsequence::CdsFrameshift,
+                    "CDS" if len_diff > 0 => VariantConsequence::CdsInframeIndel,
+                    "CDS" => VariantConsequence::CdsSubstitution,
+                    "exon" | "five_prime_UTR" | "three_prime_UTR" | "UTR" | "start_codon"
+                    | "stop_codon" => VariantConsequence::Exonic,
+                    _ if rec.id().is_some_and(|id| self.children.contains_key(id)) => {
+                        VariantConsequence::Intronic
+                    }
+                    _ => return,
+                };
+                if c > consequence {
+                    consequence = c;
+                    features.clear();
+                }
+                if c == consequence {
+                    features.push(rec);
+                }
+            });
+        let mut genes = Vec::<String>::new();
+        features.iter().for_each(|rec| {
+            self.get_roots(rec).into_iter().for_each(|gene| {
+                let name = gene
+                    .name()
+                    .or_else(|| gene.id())
+                    .unwrap_or_default()
+                    .to_string();
+                if !genes.contains(&name) {
+                    genes.push(name);
+                }
+            })
+        });
+        (consequence, genes)
     }
 
     pub fn dump_json(&self) {
@@ -260,6 +784,313 @@ impl GFFDB {
     }
 }
 
+// the aligned (target offset, target length, query offset, query length) of the bases different in a variant
+// of an alnmap block, the first bases of the deletions and insertions are the identical padding bases
+fn variant_span(variant: &(u32, u32, char, String, String)) -> (u32, u32, u32, u32) {
+    let (td, qd, vt, t_str, q_str) = variant;
+    let (t_len, q_len) = (t_str.len() as u32, q_str.len() as u32);
+    if *vt == 'X' {
+        (*td, t_len, *qd, q_len)
+    } else {
+        (
+            td + 1,
+            t_len.saturating_sub(1),
+            qd + 1,
+            q_len.saturating_sub(1),
+        )
+    }
+}
+
+/// an aligned block of an alnmap file from pgr-alnmap, the target interval `[ts, te)` is aligned to the query
+/// interval `[qs, qe)` (reverse complemented if `orientation` is 1), the variants are the (target offset,
+/// query offset, variant type, target allele, query allele) in the block
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AlnMapBlock {
+    pub aln_idx: u32,
+    pub t_name: String,
+    pub ts: u32,
+    pub te: u32,
+    pub q_name: String,
+    pub qs: u32,
+    pub qe: u32,
+    pub orientation: u32,
+    pub variants: Vec<(u32, u32, char, String, String)>,
+}
+
+impl AlnMapBlock {
+    /// map a target offset in the block to the query offset in the block orientation
+    fn query_offset(&self, t_offset: u32) -> u32 {
+        let q_len = self.qe - self.qs;
+        let mut shift = 0_i64;
+        for variant in self.variants.iter() {
+            let (v_t_bgn, v_t_len, v_q_bgn, v_q_len) = variant_span(variant);
+            if t_offset < v_t_bgn {
+                break;
+            }
+            if t_offset < v_t_bgn + v_t_len {
+                return (v_q_bgn + (t_offset - v_t_bgn).min(v_q_len)).min(q_len);
+            }
+            shift = (v_q_bgn + v_q_len) as i64 - (v_t_bgn + v_t_len) as i64;
+        }
+        (t_offset as i64 + shift).clamp(0, q_len as i64) as u32
+    }
+
+    /// the query interval aligned to the target interval `[bgn, end)` clipped to the block
+    pub fn query_interval(&self, bgn: u32, end: u32) -> (u32, u32) {
+        let bgn = bgn.clamp(self.ts, self.te) - self.ts;
+        let end = end.clamp(self.ts, self.te) - self.ts;
+        let (q_bgn, q_end) = (self.query_offset(bgn), self.query_offset(end));
+        if self.orientation == 0 {
+            (self.qs + q_bgn, self.qs + q_end)
+        } else {
+            (self.qe - q_end, self.qe - q_bgn)
+        }
+    }
+
+    /// the number of the different bases (the mismatches and the indel bases) in the target interval `[bgn, end)`
+    pub fn diff_count(&self, bgn: u32, end: u32) -> u32 {
+        let bgn = bgn.clamp(self.ts, self.te) - self.ts;
+        let end = end.clamp(self.ts, self.te) - self.ts;
+        self.variants
+            .iter()
+            .map(|variant| {
+                let (v_t_bgn, v_t_len, _, v_q_len) = variant_span(variant);
+                let t_diff = (v_t_bgn + v_t_len)
+                    .min(end)
+                    .saturating_sub(v_t_bgn.max(bgn));
+                // the inserted bases are counted if the insertion point is inside the interval
+                let q_diff = if v_q_len > v_t_len && bgn < v_t_bgn && v_t_bgn < end {
+                    v_q_len - v_t_len
+                } else {
+                    0
+                };
+                t_diff + q_diff
+            })
+            .sum()
+    }
+}
+
+/// the aligned blocks of an alnmap file indexed by the target coordinates
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct AlnMap {
+    pub blocks: Vec<AlnMapBlock>,
+    /// the interval index of the blocks on each target sequence
+    pub intervals: FxHashMap<String, IntervalIndex>,
+}
+
+impl AlnMap {
+    pub fn from_file(filepath: &Path) -> io::Result<AlnMap> {
+        AlnMap::from_reader(BufReader::new(File::open(filepath)?))
+    }
+
+    /// parse the match ("M") and variant ("V") records of an alnmap file, the variant records of the same
+    /// block are merged, the other records (e.g., the SV candidates) are not aligned and skipped
+    pub fn from_reader<R: BufRead>(reader: R) -> io::Result<AlnMap> {
+        let mut blocks = Vec::<AlnMapBlock>::new();
+        for (line_number, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() || line.trim().starts_with('#') {
+                continue;
+            }
+            let fields = line.split('\t').collect::<Vec<&str>>();
+            let rec_type = fields.get(1).copied().unwrap_or_default();
+            if !rec_type.starts_with('M') && !rec_type.starts_with('V') {
+                continue;
+            }
+            let parse_error = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "fail to parse the alnmap record at line {}",
+                        line_number + 1
+                    ),
+                )
+            };
+            let n_fields = if rec_type.starts_with('V') { 15 } else { 9 };
+            if fields.len() < n_fields {
+                return Err(parse_error());
+            }
+            let parse_u32 = |i: usize| fields[i].parse::<u32>().map_err(|_| parse_error());
+            let block = AlnMapBlock {
+                aln_idx: parse_u32(0)?,
+                t_name: fields[2].to_string(),
+                ts: parse_u32(3)?,
+                te: parse_u32(4)?,
+                q_name: fields[5].to_string(),
+                qs: parse_u32(6)?,
+                qe: parse_u32(7)?,
+                orientation: parse_u32(8)?,
+                variants: vec![],
+            };
+            if block.ts > block.te || block.qs > block.qe {
+                return Err(parse_error());
+            }
+            if rec_type.starts_with('M') {
+                blocks.push(block);
+                continue;
+            }
+            let variant = (
+                parse_u32(9)?,
+                parse_u32(10)?,
+                fields[12].chars().next().ok_or_else(parse_error)?,
+                fields[13].to_string(),
+                fields[14].to_string(),
+            );
+            match blocks.last_mut() {
+                Some(last)
+                    if last.aln_idx == block.aln_idx
+                        && (last.ts, last.te, last.qs, last.qe)
+                            == (block.ts, block.te, block.qs, block.qe)
+                        && last.t_name == block.t_name
+                        && last.q_name == block.q_name =>
+                {
+                    last.variants.push(variant)
+                }
+                _ => blocks.push(AlnMapBlock {
+                    variants: vec![variant],
+                    ..block
+                }),
+            }
+        }
+        Ok(AlnMap::from_blocks(blocks))
+    }
+
+    pub fn from_blocks(mut blocks: Vec<AlnMapBlock>) -> AlnMap {
+        let mut t_intervals = FxHashMap::<String, Vec<(u32, u32, u32)>>::default();
+        blocks.iter_mut().enumerate().for_each(|(idx, block)| {
+            block.variants.sort_by_key(|v| (v.0, v.1));
+            t_intervals
+                .entry(block.t_name.clone())
+                .or_default()
+                .push((block.ts, block.te, idx as u32));
+        });
+        let intervals = t_intervals
+            .into_iter()
+            .map(|(t_name, intervals)| (t_name, IntervalIndex::new(intervals)))
+            .collect();
+        AlnMap { blocks, intervals }
+    }
+
+    /// the blocks overlapping the target interval `[bgn, end)`, in the order of the target start coordinates
+    pub fn blocks_overlapping(&self, t_name: &str, bgn: u32, end: u32) -> Vec<&AlnMapBlock> {
+        self.intervals
+            .get(t_name)
+            .map(|index| {
+                index
+                    .overlap(bgn, end)
+                    .into_iter()
+                    .map(|idx| &self.blocks[idx as usize])
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// a feature projected from the target (reference) to a query sequence through an alignment of an alnmap file
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ProjectedFeature {
+    pub feature: Rc<GFFRecord>,
+    pub aln_idx: u32,
+    pub q_name: String,
+    /// the 0-based half-open query interval spanned by the aligned parts of the feature
+    pub q_bgn: u32,
+    pub q_end: u32,
+    pub orientation: u32,
+    /// the fraction of the feature bases in the aligned blocks
+    pub completeness: f32,
+    /// the fraction of the identical bases in the aligned parts of the feature
+    pub identity: f32,
+}
+
+impl ProjectedFeature {
+    /// the record of the feature on the query sequence, the strand is flipped for the reverse complemented
+    /// alignment and the stats are added as the "completeness" and "identity" attributes
+    pub fn to_record(&self) -> GFFRecord {
+        let strand = match (self.orientation, self.feature.strand) {
+            (1, '+') => '-',
+            (1, '-') => '+',
+            (_, strand) => strand,
+        };
+        let mut attributes = self.feature.attributes.clone();
+        attributes.insert(
+            "completeness".to_string(),
+            format!("{:.4}", self.completeness),
+        );
+        attributes.insert("identity".to_string(), format!("{:.4}", self.identity));
+        GFFRecord {
+            seqid: self.q_name.clone(),
+            bgn: self.q_bgn + 1,
+            end: self.q_end.max(self.q_bgn + 1),
+            strand,
+            attributes,
+            ..(*self.feature).clone()
+        }
+    }
+}
+
+/// project the features to the query sequences aligned in the alnmap, a feature is projected once for each
+/// alignment (the blocks with the same alignment index) overlapping it, the features without any aligned
+/// block are not projected
+pub fn project(gff: &GFFDB, alnmap: &AlnMap) -> Vec<ProjectedFeature> {
+    gff.records
+        .iter()
+        .flat_map(|rec| {
+            let (bgn, end) = (rec.bgn - 1, rec.end);
+            // group the blocks by the alignments in the order of the first blocks
+            let mut alignments = Vec::<(u32, &str, u32, Vec<&AlnMapBlock>)>::new();
+            alnmap
+                .blocks_overlapping(&rec.seqid, bgn, end)
+                .into_iter()
+                .for_each(|block| {
+                    let key = (block.aln_idx, block.q_name.as_str(), block.orientation);
+                    match alignments.iter_mut().find(|a| (a.0, a.1, a.2) == key) {
+                        Some(a) => a.3.push(block),
+                        None => alignments.push((key.0, key.1, key.2, vec![block])),
+                    }
+                });
+            alignments
+                .into_iter()
+                .map(|(aln_idx, q_name, orientation, blocks)| {
+                    let mut covered = 0_u32;
+                    let mut diff_count = 0_u32;
+                    let mut q_bgn = u32::MAX;
+                    let mut q_end = 0_u32;
+                    // the blocks are sorted by the target coordinates, the bases in the overlapping blocks are counted once
+                    let mut covered_end = bgn;
+                    blocks.iter().for_each(|block| {
+                        let b_bgn = block.ts.max(bgn).max(covered_end);
+                        let b_end = block.te.min(end);
+                        if b_bgn < b_end {
+                            covered += b_end - b_bgn;
+                            diff_count += block.diff_count(b_bgn, b_end);
+                            covered_end = b_end;
+                        }
+                        let (qb, qe) = block.query_interval(bgn, end);
+                        q_bgn = q_bgn.min(qb);
+                        q_end = q_end.max(qe);
+                    });
+                    let completeness = covered as f32 / (end - bgn) as f32;
+                    let identity = if covered > 0 {
+                        (1.0 - diff_count as f32 / covered as f32).max(0.0)
+                    } else {
+                        0.0
+                    };
+                    ProjectedFeature {
+                        feature: rec.clone(),
+                        aln_idx,
+                        q_name: q_name.to_string(),
+                        q_bgn,
+                        q_end,
+                        orientation,
+                        completeness,
+                        identity,
+                    }
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct QueryOut {
     parent: Rc<GFFRecord>,
@@ -272,7 +1103,7 @@ mod test {
 
     #[test]
     fn test_gff_to_db() {
-        let res = super::GFFDB::from_bgzip_file(&Path::new("./test/test_data/test.gff3.gz"));
+        let res = super::GFFDB::from_bgzip_file(Path::new("./test/test_data/test.gff3.gz"));
         let gdb = res.unwrap();
         println!("{}", gdb.header.join("\n"));
         let r = gdb.name_to_rec.get(&"FLG".to_string()).unwrap();
@@ -289,4 +1120,172 @@ mod test {
         let qr = QueryOut { parent, offspring };
         println!("{}", serde_json::to_string(&qr).unwrap());
     }
+
+    #[test]
+    fn test_gff_hierarchy_and_overlap() {
+        let gdb = GFFDB::from_file(Path::new("./test/test_data/test.gff3.gz")).unwrap();
+        let gene = gdb.get_rec("FLG").unwrap().clone();
+        assert_eq!(gene.id(), Some("gene:ENSG00000143631"));
+        assert_eq!(
+            gene.attributes.get("description").unwrap(),
+            "filaggrin [Source:HGNC Symbol;Acc:HGNC:3748]"
+        );
+        let transcripts = gdb.get_children(gene.id().unwrap());
+        assert_eq!(transcripts.len(), 1);
+        assert_eq!(transcripts[0].type_name, "mRNA");
+        assert_eq!(gdb.get_children(transcripts[0].id().unwrap()).len(), 8);
+
+        // the last base of the intron and the first base of the second exon in the BED coordinates
+        let types = |bgn: u32, end: u32| {
+            gdb.features_overlapping("1", bgn, end)
+                .into_iter()
+                .map(|r| r.type_name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(types(152315317, 152315318), vec!["gene", "mRNA"]);
+        assert_eq!(
+            types(152315318, 152315319),
+            vec!["gene", "mRNA", "CDS", "exon"]
+        );
+        assert!(gdb
+            .features_overlapping("2", 152315318, 152315319)
+            .is_empty());
+        gdb.features_overlapping("1", 152315318, 152315319)
+            .iter()
+            .for_each(|r| {
+                let roots = gdb.get_roots(r);
+                assert_eq!(roots.len(), 1);
+                assert!(Rc::ptr_eq(&roots[0], &gene));
+            });
+    }
+
+    #[test]
+    fn test_region_records() {
+        let gdb = GFFDB::from_file(Path::new("./test/test_data/test.gff3.gz")).unwrap();
+        // the 5' end of the CDS on the reverse strand is clipped by 5 bases
+        let recs = gdb.region_records("1", 152315300, 152315451, "region", false);
+        assert_eq!(
+            recs.iter()
+                .map(|r| r.type_name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["gene", "mRNA", "CDS", "exon"]
+        );
+        let cds = &recs[2];
+        assert_eq!((cds.seqid.as_str(), cds.bgn, cds.end), ("region", 19, 151));
+        assert_eq!((cds.strand, cds.phase), ('-', Some(1)));
+        assert_eq!(cds.attributes.get("partial").unwrap(), "true");
+
+        let recs = gdb.region_records("1", 152315300, 152315451, "region", true);
+        let cds = &recs[2];
+        assert_eq!((cds.bgn, cds.end, cds.strand), (1, 133, '+'));
+        assert!(gdb
+            .region_records("1", 152325203, 152325300, "region", false)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_variant_consequence() {
+        let gdb = GFFDB::from_file(Path::new("./test/test_data/test.gff3.gz")).unwrap();
+        let consequence = |pos: u32, ref_allele: &str, alt_allele: &str| {
+            gdb.variant_consequence("1", pos, ref_allele, alt_allele).0
+        };
+        assert_eq!(
+            consequence(152315350, "A", "G"),
+            VariantConsequence::CdsSubstitution
+        );
+        assert_eq!(
+            consequence(152315350, "A", "AT"),
+            VariantConsequence::CdsFrameshift
+        );
+        assert_eq!(
+            consequence(152315350, "AGTC", "A"),
+            VariantConsequence::CdsInframeIndel
+        );
+        assert_eq!(consequence(152315460, "A", "G"), VariantConsequence::Exonic);
+        assert_eq!(
+            consequence(152315000, "A", "G"),
+            VariantConsequence::Intronic
+        );
+        assert_eq!(
+            consequence(152300000, "A", "G"),
+            VariantConsequence::Intergenic
+        );
+        // the padding base of the deletion is in the intron
+        assert_eq!(
+            consequence(152315317, "AC", "A"),
+            VariantConsequence::CdsFrameshift
+        );
+        let (_, genes) = gdb.variant_consequence("1", 152315350, "A", "G");
+        assert_eq!(genes, vec!["FLG".to_string()]);
+    }
+
+    #[test]
+    fn test_genes_covered() {
+        let gdb = GFFDB::from_file(Path::new("./test/test_data/test.gff3.gz")).unwrap();
+        // two overlapping intervals and one interval in the gene
+        let intervals = [
+            (152300000, 152303000),
+            (152302000, 152303175),
+            (152325000, 152325100),
+        ];
+        let genes = gdb.genes_covered("1", &intervals);
+        assert_eq!(genes.len(), 1);
+        let (gene, covered) = &genes[0];
+        assert_eq!(gene.name(), Some("FLG"));
+        assert_eq!(*covered, 1101);
+        assert!(gdb.genes_covered("1", &[(152300000, 152302174)]).is_empty());
+    }
+
+    #[test]
+    fn test_gtf_to_db() {
+        let gtf = [
+            "chr1\tHAVANA\tgene\t11869\t14409\t.\t+\t.\tgene_id \"G1\"; gene_name \"DDX11L1\";",
+            "chr1\tHAVANA\ttranscript\t11869\t14409\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\"; tag \"basic\"; tag \"CCDS\";",
+            "chr1\tHAVANA\texon\t11869\t12227\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\"; exon_number 1;",
+            "chr1\tHAVANA\texon\t12613\t12721\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\"; exon_number 2;",
+        ]
+        .join("\n");
+        let gdb = GFFDB::from_reader(std::io::Cursor::new(gtf), GFFFormat::GTF).unwrap();
+        let transcript = gdb.get_rec("T1").unwrap();
+        assert_eq!(transcript.parent_ids(), vec!["G1"]);
+        assert_eq!(transcript.attributes.get("tag").unwrap(), "basic,CCDS");
+        let offspring = gdb.get_all_offspring("DDX11L1", true).unwrap();
+        assert_eq!(offspring.len(), 3);
+        assert_eq!(gdb.features_overlapping("chr1", 12227, 12612).len(), 2);
+
+        let bad_rec = "chr1\tHAVANA\tgene\t14409\t11869\t.\t+\t.\tgene_id \"G1\";";
+        assert!(GFFDB::from_reader(std::io::Cursor::new(bad_rec), GFFFormat::GTF).is_err());
+    }
+
+    #[test]
+    fn test_project_features() {
+        let gff =
+            "chr1\tt\tgene\t101\t300\t.\t+\t.\tID=g1\nchr1\tt\tgene\t3001\t3100\t.\t+\t.\tID=g2\n";
+        let gdb = GFFDB::from_reader(std::io::Cursor::new(gff), GFFFormat::GFF3).unwrap();
+        let alnmap = [
+            "000000\tB\tchr1\t0\t200\tq1\t1000\t1200\t0\t5000\t0\t0\t0\t0\t0",
+            "000000\tM\tchr1\t0\t200\tq1\t1000\t1200\t0",
+            "000000\tV\tchr1\t200\t400\tq1\t1200\t1398\t0\t10\t10\t210\tX\tA\tG",
+            "000000\tV\tchr1\t200\t400\tq1\t1200\t1398\t0\t50\t50\t250\tD\tACG\tA",
+            "000000\tS\tchr1\t400\t900\tq1\t1398\t1400\t0\t0\tFailAln",
+            "000001\tM_D\tchr1\t0\t400\tq2\t500\t900\t1",
+        ]
+        .join("\n");
+        let alnmap = AlnMap::from_reader(std::io::Cursor::new(alnmap)).unwrap();
+        assert_eq!(alnmap.blocks.len(), 3);
+        let projected = project(&gdb, &alnmap);
+        assert_eq!(projected.len(), 2);
+
+        // one mismatch and a 2-base deletion in the second block
+        let p = &projected[0];
+        assert_eq!((p.q_name.as_str(), p.q_bgn, p.q_end), ("q1", 1100, 1298));
+        assert_eq!(p.completeness, 1.0);
+        assert!((p.identity - 0.985).abs() < 1e-6);
+
+        let p = &projected[1];
+        assert_eq!((p.q_name.as_str(), p.q_bgn, p.q_end), ("q2", 600, 800));
+        let rec = p.to_record();
+        assert_eq!((rec.bgn, rec.end, rec.strand), (601, 800, '-'));
+        assert_eq!(rec.attributes.get("identity").unwrap(), "1.0000");
+    }
 }
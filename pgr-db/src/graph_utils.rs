@@ -1,7 +1,9 @@
 use core::cmp::Ord;
+use petgraph::algo::tarjan_scc;
+use petgraph::graphmap::DiGraphMap;
 use petgraph::visit::{GraphRef, IntoNeighbors, IntoNeighborsDirected, VisitMap, Visitable};
 use petgraph::EdgeDirection::{Incoming, Outgoing};
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
 use std::collections::BinaryHeap;
 use std::fmt::Debug;
 use std::hash::Hash;
@@ -57,6 +59,385 @@ impl BiDiNode for ShmmrGraphNode {
     }
 }
 
+pub type SuperBubble = (ShmmrGraphNode, ShmmrGraphNode, Vec<ShmmrGraphNode>); // (entrance, exit, internal nodes)
+
+pub type BubbleAllele = (Vec<ShmmrGraphNode>, Vec<u32>); // (path from the entrance to the exit, sids walking the path)
+
+pub type GraphCycle = (Vec<ShmmrGraphNode>, usize, Vec<(u32, usize)>); // (vertices, number of edges, (sid, traversal count))
+
+/// find the superbubbles in the MAP graph of an adjacency list, a superbubble is a subgraph with one entrance
+/// and one exit where all paths from the entrance reach the exit without a cycle or a tip, the alleles of each
+/// bubble are the distinct paths from the entrance to the exit walked by the sequences, sorted by the number
+/// of the sequences walking them
+///
+/// the search from an entrance is abandoned if more than `max_bubble_size` nodes are visited, a bubble and its
+/// reverse complement in the other strand are reported once, and the nested bubbles are reported separately
+pub fn find_superbubbles(
+    adj_list: &[AdjPair],
+    max_bubble_size: usize,
+) -> Vec<(SuperBubble, Vec<BubbleAllele>)> {
+    let mut g = DiGraphMap::<ShmmrGraphNode, ()>::new();
+    let mut edge_sids = FxHashMap::<(ShmmrGraphNode, ShmmrGraphNode), FxHashSet<u32>>::default();
+    adj_list.iter().for_each(|&(sid, v, w)| {
+        g.add_edge(v, w, ());
+        edge_sids.entry((v, w)).or_default().insert(sid);
+    });
+
+    let mut entrances = g
+        .nodes()
+        .filter(|&v| g.neighbors_directed(v, Outgoing).count() > 1)
+        .collect::<Vec<_>>();
+    entrances.sort();
+    entrances
+        .into_iter()
+        .filter_map(|s| find_superbubble_from(&g, s, max_bubble_size))
+        .filter(|(s, t, internal)| !internal.is_empty() && (*s, *t) <= (t.reverse(), s.reverse()))
+        .map(|bubble| {
+            let alleles = get_bubble_alleles(&g, &edge_sids, &bubble);
+            (bubble, alleles)
+        })
+        .collect()
+}
+
+/// the parameters for simplifying the MAP graph before extracting the principal bundles
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GraphSimplification {
+    /// remove the tips (the dead-end branches) of up to `max_tip_len` nodes, "0" for not clipping the tips
+    pub max_tip_len: usize,
+    /// only remove the tips walked by at most `max_tip_coverage` sequences
+    pub max_tip_coverage: usize,
+    /// collapse the bubbles of up to `max_bubble_size` nodes to their allele walked by the most sequences,
+    /// "0" for not popping the bubbles
+    pub max_bubble_size: usize,
+}
+
+impl Default for GraphSimplification {
+    fn default() -> Self {
+        GraphSimplification {
+            max_tip_len: 4,
+            max_tip_coverage: 1,
+            max_bubble_size: 16,
+        }
+    }
+}
+
+/// simplify the MAP graph of an adjacency list by clipping the low coverage tips and then popping the small
+/// bubbles, so the noise in the single sequences does not fragment the principal bundles, the pairs of the
+/// adjacency list on the removed branches are dropped
+pub fn simplify_adj_list(adj_list: &[AdjPair], simplification: &GraphSimplification) -> AdjList {
+    let mut adj_list = adj_list.to_vec();
+    if simplification.max_tip_len > 0 {
+        let tip_nodes = find_tip_nodes(
+            &adj_list,
+            simplification.max_tip_len,
+            simplification.max_tip_coverage,
+        );
+        adj_list.retain(|(_, v, w)| {
+            !tip_nodes.contains(&(v.0, v.1)) && !tip_nodes.contains(&(w.0, w.1))
+        });
+    }
+    if simplification.max_bubble_size > 0 {
+        let popped_edges = find_popped_bubble_edges(&adj_list, simplification.max_bubble_size);
+        adj_list.retain(|(_, v, w)| !popped_edges.contains(&(*v, *w)));
+    }
+    adj_list
+}
+
+// the nodes, as (hash0, hash1), on the dead-end branches of up to `max_tip_len` nodes from a junction walked
+// by at most `max_tip_coverage` sequences and fewer sequences than the junction; the linear paths without
+// a junction are kept, so the short sequences are not removed
+fn find_tip_nodes(
+    adj_list: &[AdjPair],
+    max_tip_len: usize,
+    max_tip_coverage: usize,
+) -> FxHashSet<(u64, u64)> {
+    let mut g = DiGraphMap::<ShmmrGraphNode, ()>::new();
+    let mut node_sids = FxHashMap::<(u64, u64), FxHashSet<u32>>::default();
+    adj_list.iter().for_each(|&(sid, v, w)| {
+        g.add_edge(v, w, ());
+        node_sids.entry((v.0, v.1)).or_default().insert(sid);
+        node_sids.entry((w.0, w.1)).or_default().insert(sid);
+    });
+    let coverage = |v: &ShmmrGraphNode| node_sids.get(&(v.0, v.1)).unwrap().len();
+
+    let mut tip_nodes = FxHashSet::<(u64, u64)>::default();
+    g.nodes()
+        .filter(|&v| g.neighbors_directed(v, Outgoing).next().is_none())
+        .for_each(|v| {
+            let mut tip = vec![v];
+            let mut junction = None;
+            while tip.len() <= max_tip_len {
+                let mut predecessors = g.neighbors_directed(tip[tip.len() - 1], Incoming);
+                let p = match (predecessors.next(), predecessors.next()) {
+                    (Some(p), None) => p,
+                    _ => break,
+                };
+                if g.neighbors_directed(p, Outgoing).nth(1).is_some() {
+                    junction = Some(p);
+                    break;
+                }
+                tip.push(p);
+            }
+            if let Some(junction) = junction {
+                let tip_coverage = tip.iter().map(coverage).max().unwrap();
+                if tip.len() <= max_tip_len
+                    && tip_coverage <= max_tip_coverage
+                    && tip_coverage < coverage(&junction)
+                {
+                    tip.iter().for_each(|v| {
+                        tip_nodes.insert((v.0, v.1));
+                    });
+                }
+            }
+        });
+    tip_nodes
+}
+
+// the edges, in both strands, of the bubbles of up to `max_bubble_size` nodes not on the allele walked by
+// the most sequences, the edges on the kept allele of any bubble are not removed, so popping a bubble nested
+// in another one does not break the kept allele of the outer one
+fn find_popped_bubble_edges(
+    adj_list: &[AdjPair],
+    max_bubble_size: usize,
+) -> FxHashSet<(ShmmrGraphNode, ShmmrGraphNode)> {
+    let mut g = DiGraphMap::<ShmmrGraphNode, ()>::new();
+    adj_list.iter().for_each(|&(_, v, w)| {
+        g.add_edge(v, w, ());
+    });
+    let mut kept_edges = FxHashSet::<(ShmmrGraphNode, ShmmrGraphNode)>::default();
+    let mut bubble_edges = FxHashSet::<(ShmmrGraphNode, ShmmrGraphNode)>::default();
+    find_superbubbles(adj_list, max_bubble_size)
+        .into_iter()
+        .filter(|(_, alleles)| !alleles.is_empty())
+        .for_each(|((s, t, internal), alleles)| {
+            alleles[0].0.windows(2).for_each(|e| {
+                kept_edges.insert((e[0], e[1]));
+                kept_edges.insert((e[1].reverse(), e[0].reverse()));
+            });
+            let internal_or_exit = internal
+                .iter()
+                .chain(std::iter::once(&t))
+                .collect::<FxHashSet<_>>();
+            std::iter::once(&s).chain(internal.iter()).for_each(|&v| {
+                g.neighbors_directed(v, Outgoing)
+                    .filter(|w| internal_or_exit.contains(w))
+                    .for_each(|w| {
+                        bubble_edges.insert((v, w));
+                        bubble_edges.insert((w.reverse(), v.reverse()));
+                    });
+            });
+        });
+    bubble_edges
+        .into_iter()
+        .filter(|e| !kept_edges.contains(e))
+        .collect()
+}
+
+/// split an adjacency list into the connected components of the MAP graph, the two orientations of a vertex
+/// are in the same component, the components are sorted by the number of their vertices, the largest first
+pub fn split_adj_list_by_component(adj_list: &[AdjPair]) -> Vec<AdjList> {
+    let mut vertex_index = FxHashMap::<(u64, u64), usize>::default();
+    let mut parent = Vec::<usize>::new();
+    let mut get_index = |v: &ShmmrGraphNode, parent: &mut Vec<usize>| {
+        *vertex_index.entry((v.0, v.1)).or_insert_with(|| {
+            parent.push(parent.len());
+            parent.len() - 1
+        })
+    };
+    let pair_indices = adj_list
+        .iter()
+        .map(|(_, v, w)| {
+            let vi = get_index(v, &mut parent);
+            let wi = get_index(w, &mut parent);
+            let (rv, rw) = (find_root(&mut parent, vi), find_root(&mut parent, wi));
+            // keep the smallest index as the root so the component order is stable
+            match rv.cmp(&rw) {
+                std::cmp::Ordering::Less => parent[rw] = rv,
+                std::cmp::Ordering::Greater => parent[rv] = rw,
+                std::cmp::Ordering::Equal => (),
+            }
+            vi
+        })
+        .collect::<Vec<_>>();
+
+    let mut vertex_count = FxHashMap::<usize, usize>::default();
+    (0..parent.len()).for_each(|i| {
+        *vertex_count.entry(find_root(&mut parent, i)).or_default() += 1;
+    });
+    let mut components = FxHashMap::<usize, AdjList>::default();
+    adj_list.iter().zip(pair_indices).for_each(|(&pair, vi)| {
+        let root = find_root(&mut parent, vi);
+        components.entry(root).or_default().push(pair);
+    });
+    let mut components = components.into_iter().collect::<Vec<_>>();
+    components.sort_by_key(|(root, _)| (std::cmp::Reverse(vertex_count[root]), *root));
+    components
+        .into_iter()
+        .map(|(_, component)| component)
+        .collect()
+}
+
+/// find the cycles (the loops, e.g., from the tandem repeats) in the MAP graph of an adjacency list, a cycle
+/// is a strongly connected component of up to `max_cycle_size` vertices with more than one vertex or with
+/// a self-loop, a cycle and its reverse complement in the other strand are reported once
+///
+/// the traversal count of a sequence is the number of times it walks the most visited vertex of the cycle
+/// through the edges in the cycle, an estimate of the copy number of the repeat unit in the sequence
+pub fn find_cycles(adj_list: &[AdjPair], max_cycle_size: usize) -> Vec<GraphCycle> {
+    let mut g = DiGraphMap::<ShmmrGraphNode, ()>::new();
+    adj_list.iter().for_each(|&(_, v, w)| {
+        g.add_edge(v, w, ());
+    });
+
+    let mut cycles = tarjan_scc(&g)
+        .into_iter()
+        .filter(|scc| {
+            scc.len() <= max_cycle_size && (scc.len() > 1 || g.contains_edge(scc[0], scc[0]))
+        })
+        .map(|mut scc| {
+            scc.sort();
+            scc
+        })
+        .filter(|scc| {
+            let mut rev_scc = scc.iter().map(|v| v.reverse()).collect::<Vec<_>>();
+            rev_scc.sort();
+            *scc <= rev_scc
+        })
+        .collect::<Vec<_>>();
+    cycles.sort();
+
+    let node_to_cycle = cycles
+        .iter()
+        .enumerate()
+        .flat_map(|(cid, scc)| scc.iter().map(move |&v| (v, cid)))
+        .collect::<FxHashMap<_, _>>();
+    let mut cycle_edges =
+        vec![FxHashSet::<(ShmmrGraphNode, ShmmrGraphNode)>::default(); cycles.len()];
+    let mut visits = FxHashMap::<(usize, u32, ShmmrGraphNode), usize>::default();
+    adj_list.iter().for_each(|&(sid, v, w)| {
+        if let (Some(&cid), Some(&cid_w)) = (node_to_cycle.get(&v), node_to_cycle.get(&w)) {
+            if cid == cid_w {
+                cycle_edges[cid].insert((v, w));
+                *visits.entry((cid, sid, v)).or_default() += 1;
+            }
+        }
+    });
+    let mut traversals = vec![FxHashMap::<u32, usize>::default(); cycles.len()];
+    visits.into_iter().for_each(|((cid, sid, _), count)| {
+        let max_count = traversals[cid].entry(sid).or_default();
+        *max_count = (*max_count).max(count);
+    });
+
+    cycles
+        .into_iter()
+        .zip(cycle_edges)
+        .zip(traversals)
+        .map(|((scc, edges), traversals)| {
+            let mut traversals = traversals.into_iter().collect::<Vec<_>>();
+            traversals.sort();
+            (scc, edges.len(), traversals)
+        })
+        .collect()
+}
+
+// the root of a vertex in the union-find forest with the path halving
+fn find_root(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+// find the superbubble with the entrance `s` (Onodera et al., 2013), the nodes are visited once all their
+// parents are visited until a single node is reached by all the paths, which is the exit
+fn find_superbubble_from(
+    g: &DiGraphMap<ShmmrGraphNode, ()>,
+    s: ShmmrGraphNode,
+    max_bubble_size: usize,
+) -> Option<SuperBubble> {
+    let mut stack = vec![s];
+    let mut visited = FxHashSet::<ShmmrGraphNode>::default();
+    let mut seen = FxHashSet::<ShmmrGraphNode>::default();
+    seen.insert(s);
+    while let Some(v) = stack.pop() {
+        visited.insert(v);
+        seen.remove(&v);
+        if visited.len() > max_bubble_size {
+            return None;
+        }
+        let mut is_tip = true;
+        for u in g.neighbors_directed(v, Outgoing) {
+            // a cycle through the entrance or a self-loop
+            if u == s || u == v {
+                return None;
+            }
+            is_tip = false;
+            seen.insert(u);
+            if g.neighbors_directed(u, Incoming)
+                .all(|p| visited.contains(&p))
+            {
+                stack.push(u);
+            }
+        }
+        if is_tip {
+            return None;
+        }
+        if stack.len() == 1 && seen.len() == 1 && seen.contains(&stack[0]) {
+            let t = stack[0];
+            if g.contains_edge(t, s) {
+                return None;
+            }
+            visited.remove(&s);
+            let mut internal = visited.into_iter().collect::<Vec<_>>();
+            internal.sort();
+            return Some((s, t, internal));
+        }
+    }
+    None
+}
+
+// the paths through a bubble walked by the sequences, a path is extended with an edge only if some of
+// the sequences walking the path so far also walk the edge
+fn get_bubble_alleles(
+    g: &DiGraphMap<ShmmrGraphNode, ()>,
+    edge_sids: &FxHashMap<(ShmmrGraphNode, ShmmrGraphNode), FxHashSet<u32>>,
+    (s, t, internal): &SuperBubble,
+) -> Vec<BubbleAllele> {
+    let bubble_nodes = internal
+        .iter()
+        .chain(std::iter::once(t))
+        .collect::<FxHashSet<_>>();
+    let mut alleles = Vec::<BubbleAllele>::new();
+    let mut stack = vec![(vec![*s], None::<FxHashSet<u32>>)];
+    while let Some((path, sids)) = stack.pop() {
+        let v = *path.last().unwrap();
+        if v == *t {
+            let mut sids = sids.unwrap_or_default().into_iter().collect::<Vec<_>>();
+            sids.sort();
+            alleles.push((path, sids));
+            continue;
+        }
+        for u in g.neighbors_directed(v, Outgoing) {
+            if !bubble_nodes.contains(&u) {
+                continue;
+            }
+            let edge_sids = edge_sids.get(&(v, u)).unwrap();
+            let sids = match sids.as_ref() {
+                Some(sids) => sids.intersection(edge_sids).copied().collect(),
+                None => edge_sids.clone(),
+            };
+            if !sids.is_empty() {
+                let mut path = path.clone();
+                path.push(u);
+                stack.push((path, Some(sids)));
+            }
+        }
+    }
+    alleles.sort_by(|a, b| b.1.len().cmp(&a.1.len()).then_with(|| a.0.cmp(&b.0)));
+    alleles
+}
+
 /// Code adapted from Petgraph's DFS
 ///
 #[derive(Clone, Debug)]
@@ -1,6 +1,9 @@
+use crate::seq_db::{self, pair_shmmrs, ShmmrPair};
+use crate::shmmrutils::{sequence_to_shmmrs, u64hash, ShmmrSpec};
 use cuckoofilter::CuckooFilter;
 use rustc_hash::FxHashSet;
 use std::collections::hash_map::DefaultHasher;
+use std::io;
 
 pub struct KmerFilter {
     filter: CuckooFilter<DefaultHasher>,
@@ -97,3 +100,186 @@ impl MinimizerFilter {
         (shmmrs.len(), count)
     }
 }
+
+/// the shimmer pairs of a pangenome index for screening the reads, a read "hits" the index for each
+/// of its shimmer pairs in the set
+pub struct ShmmrPairFilter {
+    filter: FxHashSet<ShmmrPair>,
+    shmmr_spec: ShmmrSpec,
+}
+
+impl ShmmrPairFilter {
+    pub fn new(shmmr_spec: ShmmrSpec) -> Self {
+        let filter = FxHashSet::default();
+        ShmmrPairFilter { filter, shmmr_spec }
+    }
+
+    /// load the shimmer pairs and the shimmer parameters from the SHIMMER map file (`<prefix>.mdb`) of an index
+    pub fn from_mdb_file(prefix: &str) -> Result<Self, io::Error> {
+        let (shmmr_spec, frag_locations) =
+            seq_db::read_mdb_file_to_frag_locations(prefix.to_string() + ".mdb")?;
+        let filter = frag_locations
+            .into_iter()
+            .map(|(shmmr_pair, _)| shmmr_pair)
+            .collect::<FxHashSet<ShmmrPair>>();
+        Ok(ShmmrPairFilter { filter, shmmr_spec })
+    }
+
+    pub fn shmmr_spec(&self) -> &ShmmrSpec {
+        &self.shmmr_spec
+    }
+
+    pub fn len(&self) -> usize {
+        self.filter.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filter.is_empty()
+    }
+
+    pub fn add_shmmr_pair(&mut self, shmmr_pair: ShmmrPair) {
+        self.filter.insert(shmmr_pair);
+    }
+
+    /// keep only the shimmer pairs satisfying the predicate, e.g., dropping the repetitive ones of a targeted locus
+    pub fn retain<F: FnMut(&ShmmrPair) -> bool>(&mut self, f: F) {
+        self.filter.retain(f);
+    }
+
+    fn seq_to_shmmr_pairs(&self, seq: &[u8]) -> Vec<ShmmrPair> {
+        let shmmrs = sequence_to_shmmrs(0, &seq.to_vec(), &self.shmmr_spec, false);
+        pair_shmmrs(&shmmrs)
+            .into_iter()
+            .map(|(s0, s1)| {
+                let s0 = s0.hash();
+                let s1 = s1.hash();
+                if s0 <= s1 {
+                    (s0, s1)
+                } else {
+                    (s1, s0)
+                }
+            })
+            .collect()
+    }
+
+    pub fn add_seq(&mut self, seq: &[u8]) {
+        self.seq_to_shmmr_pairs(seq)
+            .into_iter()
+            .for_each(|shmmr_pair| {
+                self.filter.insert(shmmr_pair);
+            });
+    }
+
+    /// return the number of the shimmer pairs of the sequence and the number of them in the filter
+    pub fn check_seq(&self, seq: &[u8]) -> (usize, usize) {
+        let shmmr_pairs = self.seq_to_shmmr_pairs(seq);
+        let count = shmmr_pairs
+            .iter()
+            .filter(|shmmr_pair| self.filter.contains(shmmr_pair))
+            .count();
+        (shmmr_pairs.len(), count)
+    }
+}
+
+/// a counting Bloom filter of the minimizers for the approximate minimizer abundance, the counters are saturated at 255
+///
+/// the count of a minimizer is never under-estimated, it is over-estimated with the probability about the
+/// false-positive rate configured for the expected number of the distinct minimizers
+pub struct CountingMinimizerFilter {
+    counters: Vec<u8>,
+    n_hashes: u32,
+    shmmr_spec: ShmmrSpec,
+}
+
+impl CountingMinimizerFilter {
+    /// create a filter for `n_items` distinct minimizers (the shimmers of `shmmr_spec`) with the false-positive rate `fp_rate`
+    pub fn new(shmmr_spec: ShmmrSpec, n_items: usize, fp_rate: f64) -> Self {
+        let fp_rate = fp_rate.clamp(1e-9, 0.5);
+        let ln2 = std::f64::consts::LN_2;
+        let n_counters = (-(n_items.max(1) as f64) * fp_rate.ln() / (ln2 * ln2)).ceil() as usize;
+        let n_counters = n_counters.max(64);
+        let n_hashes = ((n_counters as f64 / n_items.max(1) as f64) * ln2).round() as u32;
+        let n_hashes = n_hashes.clamp(1, 16);
+        CountingMinimizerFilter {
+            counters: vec![0_u8; n_counters],
+            n_hashes,
+            shmmr_spec,
+        }
+    }
+
+    pub fn n_counters(&self) -> usize {
+        self.counters.len()
+    }
+
+    pub fn n_hashes(&self) -> u32 {
+        self.n_hashes
+    }
+
+    // the counter indices of a key by double hashing
+    fn counter_indices(&self, key: u64) -> impl Iterator<Item = usize> {
+        let h0 = u64hash(key);
+        let h1 = u64hash(h0 ^ 0x9E37_79B9_7F4A_7C15) | 1;
+        let n_counters = self.counters.len() as u64;
+        (0..self.n_hashes as u64)
+            .map(move |i| (h0.wrapping_add(i.wrapping_mul(h1)) % n_counters) as usize)
+    }
+
+    pub fn add(&mut self, key: u64) {
+        // only the smallest counters are incremented (conservative update), it reduces the over-estimation
+        let indices = self.counter_indices(key).collect::<Vec<usize>>();
+        let count = indices
+            .iter()
+            .map(|&idx| self.counters[idx])
+            .min()
+            .unwrap_or(0);
+        if count == u8::MAX {
+            return;
+        }
+        indices.into_iter().for_each(|idx| {
+            if self.counters[idx] == count {
+                self.counters[idx] = count + 1;
+            }
+        });
+    }
+
+    /// the approximate count of a key, never smaller than the true count unless it is saturated
+    pub fn count(&self, key: u64) -> u8 {
+        self.counter_indices(key)
+            .map(|idx| self.counters[idx])
+            .min()
+            .unwrap_or(0)
+    }
+
+    pub fn shmmr_spec(&self) -> &ShmmrSpec {
+        &self.shmmr_spec
+    }
+
+    fn seq_mmers(&self, seq: &[u8]) -> Vec<u64> {
+        sequence_to_shmmrs(0, &seq.to_vec(), &self.shmmr_spec, false)
+            .into_iter()
+            .map(|mmer| mmer.hash())
+            .collect()
+    }
+
+    pub fn add_seq_mmers(&mut self, seq: &[u8]) {
+        self.seq_mmers(seq).into_iter().for_each(|mmer| {
+            self.add(mmer);
+        })
+    }
+
+    /// the approximate counts of the minimizers of a sequence, in the order of the minimizer positions
+    pub fn seq_mmer_counts(&self, seq: &[u8]) -> Vec<u8> {
+        self.seq_mmers(seq)
+            .into_iter()
+            .map(|mmer| self.count(mmer))
+            .collect()
+    }
+
+    /// return the number of the minimizers of a sequence and the number of them with the counts of `min_count` or more,
+    /// e.g., for dropping the reads of the ultra-high-copy satellites before indexing
+    pub fn check_seq_abundance(&self, seq: &[u8], min_count: u8) -> (usize, usize) {
+        let counts = self.seq_mmer_counts(seq);
+        let n_abundant = counts.iter().filter(|&&c| c >= min_count).count();
+        (counts.len(), n_abundant)
+    }
+}
@@ -7,9 +7,13 @@ pub mod bindings;
 pub mod ec;
 pub mod fasta_io;
 pub mod frag_file_io;
-//pub mod gff_db;
+pub mod gff_db;
 pub mod graph_utils;
 pub mod kmer_filter;
+pub mod lru_cache;
+pub mod packed_seq;
+pub mod poa;
+pub mod remote_io;
 pub mod seq_db;
 //pub mod seqs2variants;
 pub mod ext;
@@ -76,6 +80,179 @@ mod tests {
         println!("{:?}", sdb.seqs[0].seq_frag_range);
     }
 
+    #[test]
+    fn gz_fastq_read_test() {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+        let seqs = load_seqs();
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        let mut seq_names = seqs.keys().collect::<Vec<_>>();
+        seq_names.sort();
+        seq_names.iter().for_each(|name| {
+            let seq = seqs.get(*name).unwrap();
+            // the QVs starting with '@' to check they are not taken as the id lines
+            let qual = vec![b'@'; seq.len()];
+            writeln!(encoder, "@{} test", name).unwrap();
+            encoder.write_all(seq).unwrap();
+            writeln!(encoder, "\n+").unwrap();
+            encoder.write_all(&qual).unwrap();
+            writeln!(encoder).unwrap();
+        });
+        let gz_data = encoder.finish().unwrap();
+
+        let gz_buf = BufReader::new(MultiGzDecoder::new(&gz_data[..]));
+        let mut fastx_reader =
+            FastaReader::new(gz_buf, &"test.fq.gz".to_string(), 1 << 12, false, false).unwrap();
+        fastx_reader.set_keep_qual(true);
+        let recs = fastx_reader.map(|rec| rec.unwrap()).collect::<Vec<_>>();
+        assert_eq!(recs.len(), seq_names.len());
+        recs.iter().zip(seq_names).for_each(|(rec, name)| {
+            assert_eq!(String::from_utf8_lossy(&rec.id), *name);
+            assert_eq!(rec.seq, *seqs.get(name).unwrap());
+            assert_eq!(rec.qual.as_ref().unwrap().len(), rec.seq.len());
+        });
+    }
+
+    #[test]
+    fn fasta_chunks_test() {
+        use std::io::Write;
+        let seqs = load_seqs();
+        let mut seq_names = seqs.keys().collect::<Vec<_>>();
+        seq_names.sort();
+        let mut fasta = Vec::<u8>::new();
+        seq_names.iter().for_each(|name| {
+            writeln!(fasta, ">{}", name).unwrap();
+            fasta.extend_from_slice(seqs.get(*name).unwrap());
+            writeln!(fasta).unwrap();
+        });
+        let max_seq_len = seqs.values().map(|seq| seq.len()).max().unwrap();
+        let min_seq_len = seqs.values().map(|seq| seq.len()).min().unwrap();
+        for (batch_size, max_bases) in [
+            (3, 0),
+            (0, max_seq_len * 2),
+            (0, max_seq_len - 1),
+            (0, min_seq_len * 2),
+            (5, 1),
+            (0, 0),
+        ] {
+            let fastx_reader =
+                FastaReader::new(&fasta[..], &"test.fa".to_string(), 1 << 12, false, false)
+                    .unwrap();
+            let batches = fastx_reader
+                .chunks(batch_size, max_bases)
+                .map(|recs| recs.unwrap())
+                .collect::<Vec<_>>();
+            batches.iter().for_each(|recs| {
+                let n_bases = recs.iter().map(|rec| rec.seq.len()).sum::<usize>();
+                assert!(batch_size == 0 || recs.len() <= batch_size);
+                // a batch only exceeds the base budget with a single record longer than the budget
+                assert!(max_bases == 0 || n_bases <= max_bases || recs.len() == 1);
+            });
+            if max_bases == 1 {
+                assert!(batches.iter().all(|recs| recs.len() == 1));
+            }
+            if (batch_size, max_bases) == (0, 0) {
+                assert_eq!(batches.len(), 1);
+            }
+            let names = batches
+                .into_iter()
+                .flatten()
+                .map(|rec| String::from_utf8_lossy(&rec.id).into_owned())
+                .collect::<Vec<_>>();
+            assert_eq!(names.iter().collect::<Vec<_>>(), seq_names);
+        }
+    }
+
+    #[test]
+    fn indexed_fasta_read_test() -> Result<(), std::io::Error> {
+        use crate::fasta_io::IndexedFastaReader;
+        use flate2::write::DeflateEncoder;
+        use std::io::Write;
+        let seqs = load_seqs();
+        let mut seq_names = seqs.keys().collect::<Vec<_>>();
+        seq_names.sort();
+        let mut fasta = Vec::<u8>::new();
+        seq_names.iter().for_each(|name| {
+            writeln!(fasta, ">{} test", name).unwrap();
+            seqs.get(*name).unwrap().chunks(60).for_each(|line| {
+                fasta.extend_from_slice(line);
+                fasta.push(b'\n');
+            });
+        });
+        // the BGZF blocks of 4096 bases
+        let mut bgzf = Vec::<u8>::new();
+        fasta.chunks(4096).chain([&[][..]]).for_each(|data| {
+            let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(data).unwrap();
+            let compressed = encoder.finish().unwrap();
+            let mut crc = flate2::Crc::new();
+            crc.update(data);
+            let block_size = (compressed.len() + 25) as u16;
+            bgzf.extend_from_slice(&[
+                0x1F, 0x8B, 8, 4, 0, 0, 0, 0, 0, 0xFF, 6, 0, b'B', b'C', 2, 0,
+            ]);
+            bgzf.extend_from_slice(&block_size.to_le_bytes());
+            bgzf.extend_from_slice(&compressed);
+            bgzf.extend_from_slice(&crc.sum().to_le_bytes());
+            bgzf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        });
+
+        let fasta_path = std::env::temp_dir().join("pgr_db_indexed_fasta_test.fa");
+        let bgzf_path = std::env::temp_dir().join("pgr_db_indexed_fasta_test.fa.gz");
+        std::fs::write(&fasta_path, &fasta)?;
+        std::fs::write(&bgzf_path, &bgzf)?;
+        for path in [&fasta_path, &bgzf_path] {
+            let path = path.to_str().unwrap();
+            for ext in ["fai", "gzi"] {
+                let _ = std::fs::remove_file(format!("{}.{}", path, ext));
+            }
+            // the indexes are built in the first round and loaded in the second round
+            for _ in 0..2 {
+                let reader = IndexedFastaReader::new(path)?;
+                assert_eq!(reader.fai_records().len(), seq_names.len());
+                for name in seq_names.iter() {
+                    let seq = seqs.get(*name).unwrap();
+                    assert_eq!(reader.get_seq_len(name), Some(seq.len()));
+                    assert_eq!(reader.get_seq(name)?, *seq);
+                    for (bgn, end) in [(0, 1), (59, 61), (100, 3000), (seq.len() - 7, seq.len())] {
+                        assert_eq!(reader.get_sub_seq(name, bgn, end)?, seq[bgn..end]);
+                    }
+                }
+                assert!(reader.get_sub_seq(seq_names[0], 0, usize::MAX).is_err());
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn fastx_writer_test() -> Result<(), std::io::Error> {
+        use crate::fasta_io::{create_fastx_writer, FastxCompression, IndexedFastaReader};
+        let seqs = load_seqs();
+        let mut seq_names = seqs.keys().collect::<Vec<_>>();
+        seq_names.sort();
+        let bgzf_path = std::env::temp_dir().join("pgr_db_fastx_writer_test.fa.gz");
+        let mut writer =
+            create_fastx_writer(&bgzf_path, FastxCompression::from_path(&bgzf_path), 70)?;
+        seq_names
+            .iter()
+            .try_for_each(|name| writer.write_fasta(name, seqs.get(*name).unwrap()))?;
+        drop(writer);
+
+        let path = bgzf_path.to_str().unwrap();
+        for ext in ["fai", "gzi"] {
+            let _ = std::fs::remove_file(format!("{}.{}", path, ext));
+        }
+        let reader = IndexedFastaReader::new(path)?;
+        assert_eq!(reader.fai_records().len(), seq_names.len());
+        assert!(reader.fai_records().iter().all(|r| r.line_bases == 70));
+        for name in seq_names.iter() {
+            let seq = seqs.get(*name).unwrap();
+            assert_eq!(reader.get_seq(name)?, *seq);
+            assert_eq!(reader.get_sub_seq(name, 69, 141)?, seq[69..141]);
+        }
+        Ok(())
+    }
+
     #[test]
     fn load_seq_test() {
         let seqs = load_seqs();
@@ -103,6 +280,170 @@ mod tests {
         }
     }
 
+    #[test]
+    fn load_fastx_files_test() -> Result<(), std::io::Error> {
+        use crate::ext::{fastx_files_with_sources, fastx_source_name, SeqIndexDB};
+        assert_eq!(fastx_source_name("data/HG002.1.fa.gz"), "HG002.1");
+        assert_eq!(fastx_source_name("HG002.fastq"), "HG002");
+        assert_eq!(fastx_source_name("HG002.txt"), "HG002.txt");
+
+        let dir = std::env::temp_dir().join("pgr_db_load_fastx_files_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir)?;
+        std::fs::copy("test/test_data/test_seqs.fa", dir.join("sample1.fa"))?;
+        std::fs::copy("test/test_data/test_seqs2.fa.gz", dir.join("sample2.fa.gz"))?;
+        std::fs::write(dir.join("sample3.txt"), "not a fasta file")?;
+        let pattern = dir.join("sample?.fa*").to_string_lossy().into_owned();
+        let fastx_files = fastx_files_with_sources(&[pattern.clone()])?;
+        assert_eq!(
+            fastx_files.iter().map(|v| v.0.as_str()).collect::<Vec<_>>(),
+            vec!["sample1", "sample2"]
+        );
+        // the same source from two files is rejected
+        let sample1 = dir.join("sample1.fa").to_string_lossy().into_owned();
+        assert!(fastx_files_with_sources(&[pattern.clone(), sample1]).is_err());
+        let no_match = dir.join("*.fq").to_string_lossy().into_owned();
+        assert!(fastx_files_with_sources(&[no_match]).is_err());
+
+        let seqs = load_seqs();
+        let mut seq_index_db = SeqIndexDB::new();
+        seq_index_db.load_from_fastx_files(&[pattern], 80, 56, 4, 64, true)?;
+        let seq_info = seq_index_db.seq_info.as_ref().unwrap();
+        assert_eq!(seq_info.len(), seqs.len() * 2);
+        for sample in ["sample1", "sample2"] {
+            assert_eq!(
+                seq_info
+                    .values()
+                    .filter(|(_, src, _)| src.as_deref() == Some(sample))
+                    .count(),
+                seqs.len()
+            );
+        }
+
+        // the sequence ids of the indexes of multiple files continue from the sequences already indexed
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        fastx_files.into_iter().try_for_each(|(source, filepath)| {
+            sdb.load_index_from_fastx_with_source(filepath, source, true)
+        })?;
+        assert_eq!(sdb.seqs.len(), seqs.len() * 2);
+        sdb.seqs.iter().enumerate().for_each(|(sid, seq)| {
+            assert_eq!(seq.id as usize, sid);
+            let sample = if sid < seqs.len() {
+                "sample1"
+            } else {
+                "sample2"
+            };
+            assert_eq!(seq.source.as_deref(), Some(sample));
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn indexed_fasta_backend_test() -> Result<(), std::io::Error> {
+        use crate::aln::ChainGapCost;
+        use crate::ext::SeqIndexDB;
+        let dir = std::env::temp_dir().join("pgr_db_indexed_fasta_backend_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir)?;
+        let fasta_path = dir.join("sample1.fa");
+        std::fs::copy("test/test_data/test_seqs.fa", &fasta_path)?;
+        let fasta_path = fasta_path.to_string_lossy().into_owned();
+        let prefix = dir.join("test_db").to_string_lossy().into_owned();
+
+        let mut fastx_db = SeqIndexDB::new();
+        fastx_db.load_from_fastx_with_sources(
+            vec![("sample1".to_string(), fasta_path.clone())],
+            80,
+            56,
+            4,
+            64,
+            false,
+        )?;
+        let sdb = fastx_db.seq_db.as_ref().unwrap();
+        sdb.write_shmmr_map_index(prefix.clone())?;
+
+        // a source not in the fasta manifest is rejected
+        std::fs::write(
+            prefix.clone() + ".fasta_manifest",
+            format!("sample2\t{}\n", fasta_path),
+        )?;
+        assert!(SeqIndexDB::new()
+            .load_from_indexed_fasta(prefix.clone())
+            .is_err());
+
+        std::fs::write(
+            prefix.clone() + ".fasta_manifest",
+            format!("sample1\t{}\n", fasta_path),
+        )?;
+        let mut fai_db = SeqIndexDB::new();
+        fai_db.load_from_indexed_fasta(prefix)?;
+        assert_eq!(fai_db.seq_info, fastx_db.seq_info);
+        for (sid, (ctg_name, _, len)) in fastx_db.seq_info.as_ref().unwrap().iter() {
+            let seq = fastx_db.get_seq_by_id(*sid)?;
+            assert_eq!(seq.len(), *len as usize);
+            fai_db.check_seq_by_id(*sid)?;
+            assert_eq!(fai_db.get_seq_by_id(*sid)?, seq);
+            assert_eq!(fai_db.get_sub_seq_by_id(*sid, 100, 3000)?, seq[100..3000]);
+            assert_eq!(
+                fai_db.get_sub_seq("sample1".to_string(), ctg_name.clone(), 100, 3000)?,
+                seq[100..3000]
+            );
+            // the sequence is found by the query with the memory-mapped index
+            let hits = fai_db
+                .query_fragment_to_hps_from_mmap_file(
+                    &seq,
+                    ChainGapCost::Linear(0.025),
+                    Some(128),
+                    Some(128),
+                    Some(128),
+                    Some(8),
+                    None,
+                    false,
+                )
+                .unwrap();
+            assert!(hits.iter().any(|(t_sid, _)| t_sid == sid));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn packed_seq_test() {
+        use crate::packed_seq::PackedSeq;
+        use crate::seq_db::GetSeq;
+        let seqs = load_seqs();
+        for seq in seqs.values() {
+            let mut seq = seq.clone();
+            // with the soft-masked bases and the N's
+            seq[100..200].make_ascii_lowercase();
+            seq[150..160].fill(b'n');
+            seq[500..600].fill(b'N');
+            let packed_seq = PackedSeq::new(&seq);
+            assert_eq!(packed_seq.to_seq(), seq);
+            assert_eq!(packed_seq.sub_seq(95, 555), seq[95..555]);
+            assert!(packed_seq.mem_size() < seq.len() / 3);
+            let rc_seq = crate::fasta_io::reverse_complement(&seq);
+            assert_eq!(packed_seq.reverse_complement().to_seq(), rc_seq);
+            assert_eq!(packed_seq.reverse_complement(), PackedSeq::new(&rc_seq));
+        }
+
+        // packing the loaded fragments and packing the fragments while loading give the same sequences
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdb.set_pack_frags(true);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs2.fa.gz".to_string(), false);
+        let mut sdb2 = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb2.load_seqs_from_fastx("test/test_data/test_seqs2.fa.gz".to_string(), false);
+        sdb2.set_pack_frags(true);
+        for seq in sdb.seqs.iter() {
+            let orig_seq = seqs.get(&seq.name).unwrap();
+            assert_eq!(sdb.get_seq(seq), *orig_seq);
+            assert_eq!(sdb2.get_seq_by_id(seq.id), *orig_seq);
+            assert_eq!(
+                sdb.get_sub_seq_by_id(seq.id, 100, 2000),
+                orig_seq[100..2000]
+            );
+        }
+    }
+
     #[test]
     fn reconstruct_test1() {
         let base_frg = "TATTTATATTTATTTATATATATTTATATATTTATATATATATTTATATATAAATAT"
@@ -179,6 +520,296 @@ mod tests {
         assert_eq!(shmmr0, shmmr1);
     }
 
+    #[test]
+    fn rc_randstrobe_match() {
+        use seq_db::GetSeq;
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_rev.fa".to_string(), false);
+        let cs0 = sdb.get_seq_by_id(0);
+        let cs1 = sdb.get_seq_by_id(1);
+        let shmmr_spec = shmmrutils::ShmmrSpec {
+            sketch: false,
+            randstrobe: true,
+            ..seq_db::SHMMRSPEC
+        };
+        let strobes0 = shmmrutils::sequence_to_shmmrs(0, &cs0, &shmmr_spec, false);
+        let strobes1 = shmmrutils::sequence_to_shmmrs(0, &cs1, &shmmr_spec, false);
+        let strobes0 = strobes0.iter().map(|m| m.hash()).collect::<Vec<u64>>();
+        let strobes1 = strobes1
+            .iter()
+            .rev()
+            .map(|m| m.hash())
+            .collect::<Vec<u64>>();
+        assert!(!strobes0.is_empty());
+        assert_eq!(strobes0, strobes1);
+    }
+
+    #[test]
+    fn rc_long_kmer_match() {
+        use seq_db::GetSeq;
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_rev.fa".to_string(), false);
+        let cs0 = sdb.get_seq_by_id(0);
+        let cs1 = sdb.get_seq_by_id(1);
+        let shmmr_spec = shmmrutils::ShmmrSpec {
+            k: 101,
+            ..seq_db::SHMMRSPEC
+        };
+        let shmmr0 = shmmrutils::sequence_to_shmmrs(0, &cs0, &shmmr_spec, false);
+        let shmmr1 = shmmrutils::sequence_to_shmmrs(0, &cs1, &shmmr_spec, false);
+        let shmmr0 = shmmr0.iter().map(|m| m.hash()).collect::<Vec<u64>>();
+        let shmmr1 = shmmr1.iter().rev().map(|m| m.hash()).collect::<Vec<u64>>();
+        assert!(!shmmr0.is_empty());
+        assert_eq!(shmmr0, shmmr1);
+    }
+
+    #[test]
+    fn sketch_stats_test() {
+        let seqs = load_seqs();
+        let seq = seqs.values().max_by_key(|seq| seq.len()).unwrap();
+        let spec = shmmrutils::ShmmrSpec::new(24, 24, 1, 8, false);
+        let shmmrs = shmmrutils::sequence_to_shmmrs(0, seq, &spec, false);
+        let stats = shmmrutils::sketch_stats(seq, &spec);
+        assert_eq!(stats.n_anchors, shmmrs.len());
+        assert!(stats.n_anchors > 2);
+        assert!(stats.median_spacing as f64 <= stats.max_gap as f64);
+        assert!(stats.mean_spacing <= stats.max_gap as f64);
+        assert!(stats.span_coverage > 0.0 && stats.span_coverage <= 1.0);
+        // the windows split the anchors of the whole sequence
+        let half = seq.len() as u32 / 2;
+        let stats0 = shmmrutils::sketch_stats_from_shmmrs(&shmmrs, 0, half);
+        let stats1 = shmmrutils::sketch_stats_from_shmmrs(&shmmrs, half, seq.len() as u32);
+        assert_eq!(stats0.n_anchors + stats1.n_anchors, stats.n_anchors);
+        // a window between two neighboring anchors of the sequence has no anchor and no coverage
+        let gap = shmmrs
+            .windows(2)
+            .find(|w| w[1].pos() > w[0].pos() + 1)
+            .unwrap();
+        let stats = shmmrutils::sketch_stats_from_shmmrs(&shmmrs, gap[0].pos() + 1, gap[1].pos());
+        assert_eq!(stats.n_anchors, 0);
+        assert_eq!(stats.span_coverage, 0.0);
+        // no anchor in an empty region
+        let stats = shmmrutils::sketch_stats_from_shmmrs(&[], 0, 100);
+        assert_eq!((stats.n_anchors, stats.max_gap), (0, 100));
+    }
+
+    #[test]
+    fn spaced_seed_kmer_hash() {
+        let k = 24_u32;
+        let pattern = "110110110110011011011011";
+        let seed_mask = shmmrutils::seed_mask_from_str(pattern, k).unwrap();
+        assert!(shmmrutils::seed_mask_from_str(&pattern[1..], k).is_err());
+        assert!(shmmrutils::seed_mask_from_str("110110110110011011011010", k).is_err());
+        // the hash of the k-mer of the bases 1 to 24
+        let kmer_hash = |seq: &[u8]| {
+            let mut kmer_hash = None;
+            shmmrutils::for_each_kmer_hash(seq, k, Some(seed_mask), None, |pos, hash, _| {
+                if pos == k as usize {
+                    kmer_hash = Some(hash)
+                }
+            });
+            kmer_hash.unwrap()
+        };
+        let seq = b"ACGGTCATTGCAAGTCCTAGGATCAG".to_vec();
+        // a SNP at a don't-care position (the 3rd base of the k-mer) and at a position in the seed
+        let mut seq_snp = seq.clone();
+        seq_snp[3] = b'C';
+        assert_eq!(kmer_hash(&seq), kmer_hash(&seq_snp));
+        let mut seq_snp = seq.clone();
+        seq_snp[2] = b'C';
+        assert_ne!(kmer_hash(&seq), kmer_hash(&seq_snp));
+        // the same hash from the reverse complement strand
+        let rc_seq = seq
+            .iter()
+            .rev()
+            .map(|c| match c {
+                b'A' => b'T',
+                b'C' => b'G',
+                b'G' => b'C',
+                _ => b'A',
+            })
+            .collect::<Vec<u8>>();
+        assert_eq!(kmer_hash(&seq), kmer_hash(&rc_seq));
+    }
+
+    #[test]
+    fn hash_seed_in_mdb_header() -> Result<(), std::io::Error> {
+        use seq_db::{read_mdb_file, read_mdb_file_parallel, write_shmmr_map_file};
+        let seed_mask = shmmrutils::seed_mask_from_str("110110110110011011011011", 24)?;
+        let shmmr_spec = shmmrutils::ShmmrSpec {
+            seed_mask: Some(seed_mask),
+            hash_seed: Some(0x5EED),
+            ..shmmrutils::ShmmrSpec::new(24, 24, 2, 8, false)
+        };
+        let seqs = load_seqs();
+        let seq = seqs.values().next().unwrap();
+        let shmmrs0 = shmmrutils::sequence_to_shmmrs(0, seq, &shmmr_spec, false);
+        let shmmrs1 = shmmrutils::sequence_to_shmmrs(
+            0,
+            seq,
+            &shmmrutils::ShmmrSpec {
+                hash_seed: Some(0x5EED + 1),
+                ..shmmr_spec.clone()
+            },
+            false,
+        );
+        let hashes0 = shmmrs0.iter().map(|m| m.hash()).collect::<Vec<u64>>();
+        let hashes1 = shmmrs1.iter().map(|m| m.hash()).collect::<Vec<u64>>();
+        assert_ne!(hashes0, hashes1);
+
+        let mut sdb = seq_db::CompactSeqDB::new(shmmr_spec.clone());
+        sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string(), false)?;
+        let mdb_path = std::env::temp_dir().join("pgr_db_hash_seed_test.mdb");
+        let mdb_path = mdb_path.to_string_lossy().to_string();
+        write_shmmr_map_file(&sdb.shmmr_spec, &sdb.frag_map, mdb_path.clone())?;
+        let (spec, frag_map) = read_mdb_file(mdb_path.clone())?;
+        assert_eq!(spec.seed_mask, shmmr_spec.seed_mask);
+        assert_eq!(spec.hash_seed, shmmr_spec.hash_seed);
+        assert_eq!(frag_map.len(), sdb.frag_map.len());
+        let (spec, frag_map) = read_mdb_file_parallel(mdb_path.clone())?;
+        assert_eq!(spec.seed_mask, shmmr_spec.seed_mask);
+        assert_eq!(spec.hash_seed, shmmr_spec.hash_seed);
+        assert_eq!(frag_map.len(), sdb.frag_map.len());
+        std::fs::remove_file(mdb_path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn tie_break_policies() -> Result<(), std::io::Error> {
+        use seq_db::{read_mdb_file, read_mdb_file_parallel, write_shmmr_map_file};
+        use shmmrutils::{reduce_shmmr_with_tie_break, ShmmrTieBreak, MM128};
+        // the two shimmers of the hash 3 are in the same window
+        let mers = [3_u64, 9, 3, 8, 1, 7, 6, 5, 9]
+            .iter()
+            .enumerate()
+            .map(|(pos, &hash)| MM128 {
+                x: hash << 8 | 24,
+                y: (pos as u64) << 1,
+            })
+            .collect::<Vec<_>>();
+        let reduce = |tie_break: ShmmrTieBreak| {
+            reduce_shmmr_with_tie_break(mers.clone(), 4, false, &tie_break)
+                .iter()
+                .map(|m| (m.hash(), m.pos()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(reduce(ShmmrTieBreak::All), [(3, 0), (3, 2), (1, 4), (5, 7)]);
+        assert_eq!(reduce(ShmmrTieBreak::Position), [(3, 0), (1, 4), (5, 7)]);
+        assert_eq!(
+            reduce(ShmmrTieBreak::Lexicographic),
+            [(3, 0), (1, 4), (5, 7)]
+        );
+        (0..16).for_each(|seed| {
+            let shmmrs = reduce(ShmmrTieBreak::Random(seed));
+            assert_eq!(shmmrs.iter().filter(|&&(hash, _)| hash == 3).count(), 1);
+        });
+
+        let shmmr_spec = shmmrutils::ShmmrSpec {
+            tie_break: ShmmrTieBreak::Random(0x5EED),
+            ..seq_db::SHMMRSPEC
+        };
+        let mut sdb = seq_db::CompactSeqDB::new(shmmr_spec.clone());
+        sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string(), false)?;
+        let mdb_path = std::env::temp_dir().join("pgr_db_tie_break_test.mdb");
+        let mdb_path = mdb_path.to_string_lossy().to_string();
+        write_shmmr_map_file(&sdb.shmmr_spec, &sdb.frag_map, mdb_path.clone())?;
+        let (spec, frag_map) = read_mdb_file(mdb_path.clone())?;
+        assert_eq!(spec.tie_break, shmmr_spec.tie_break);
+        assert_eq!(frag_map.len(), sdb.frag_map.len());
+        let (spec, frag_map) = read_mdb_file_parallel(mdb_path.clone())?;
+        assert_eq!(spec.tie_break, shmmr_spec.tie_break);
+        assert_eq!(frag_map.len(), sdb.frag_map.len());
+        std::fs::remove_file(mdb_path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn masked_intervals_sketch() -> Result<(), std::io::Error> {
+        let seqs = load_seqs();
+        let (seq_name, seq) = seqs.iter().max_by_key(|(_, seq)| seq.len()).unwrap();
+        let shmmr_spec = shmmrutils::ShmmrSpec::new(24, 24, 2, 8, false);
+        let masked_intervals =
+            shmmrutils::merge_intervals(vec![(1500, 2000), (1000, 1600), (500, 500)]);
+        assert_eq!(masked_intervals, [(1000, 2000)]);
+        let shmmrs = shmmrutils::sequence_to_shmmrs(0, seq, &shmmr_spec, false);
+        let masked_shmmrs =
+            shmmrutils::sequence_to_shmmrs_masked(0, seq, &shmmr_spec, false, &masked_intervals);
+        let unmasked_pos = shmmrs
+            .iter()
+            .map(|m| m.pos())
+            .filter(|&pos| pos < 1000 || pos + 1 >= 2000 + shmmr_spec.k)
+            .collect::<Vec<_>>();
+        assert!(unmasked_pos.len() < shmmrs.len());
+        assert_eq!(
+            masked_shmmrs.iter().map(|m| m.pos()).collect::<Vec<_>>(),
+            unmasked_pos
+        );
+
+        // no fragment of a fully masked sequence is indexed
+        let mut sdb = seq_db::CompactSeqDB::new(shmmr_spec);
+        sdb.set_masked_intervals(
+            [(seq_name.clone(), vec![(0, seq.len() as u32)])]
+                .into_iter()
+                .collect(),
+        );
+        sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string(), false)?;
+        let sid = sdb.seqs.iter().find(|cs| &cs.name == seq_name).unwrap().id;
+        assert!(!sdb.frag_map.is_empty());
+        assert!(sdb
+            .frag_map
+            .values()
+            .all(|frags| frags.iter().all(|frag| frag.1 != sid)));
+        Ok(())
+    }
+
+    #[test]
+    fn simd_kmer_hash_match() {
+        // the length is not a multiple of the SIMD lanes to cover the scalar tail
+        let mut key = 0x5EED_u64;
+        let (planes0, planes1): (Vec<u64>, Vec<u64>) = (0..1027)
+            .map(|_| {
+                key = shmmrutils::u64hash(key);
+                (key >> 8, key & 0xFFFF_FFFF_FFFF)
+            })
+            .unzip();
+        let mut hashes = vec![0_u64; planes0.len()];
+        let mut scalar_hashes = vec![0_u64; planes0.len()];
+        shmmrutils::hash_kmer_planes(&planes0, &planes1, 0xAD12CF59, &mut hashes);
+        shmmrutils::hash_kmer_planes_scalar(&planes0, &planes1, 0xAD12CF59, &mut scalar_hashes);
+        assert_eq!(hashes, scalar_hashes);
+        (0..planes0.len()).for_each(|i| {
+            assert_eq!(
+                hashes[i],
+                shmmrutils::u64hash(planes0[i]) ^ shmmrutils::u64hash(planes1[i] ^ 0xAD12CF59)
+            );
+        });
+    }
+
+    #[test]
+    fn batched_kmer_hash_match() {
+        // the rolling k-mers hashed in batches should be the same as the ones hashed from scratch
+        let seqs = load_seqs();
+        let k = 56_u32;
+        let seq = seqs.values().max_by_key(|seq| seq.len()).unwrap();
+        assert!(seq.len() > 2 * shmmrutils::KMER_HASH_BATCH_SIZE);
+        let mut kmer_hashes = Vec::<(usize, u64, u64)>::new();
+        shmmrutils::for_each_kmer_hash(seq, k, None, None, |pos, hash, strand| {
+            kmer_hashes.push((pos, hash, strand))
+        });
+        assert!(!kmer_hashes.is_empty());
+        for &(pos, hash, strand) in kmer_hashes.iter().step_by(7) {
+            let kmer_seq = &seq[pos - k as usize..=pos];
+            if !kmer_seq.iter().all(|c| b"ACGTacgt".contains(c)) {
+                continue;
+            }
+            let mut last = None;
+            shmmrutils::for_each_kmer_hash(kmer_seq, k, None, None, |_, hash, strand| {
+                last = Some((hash, strand))
+            });
+            assert_eq!(last, Some((hash, strand)));
+        }
+    }
+
     #[test]
     #[cfg(feature = "with_agc")]
     fn raw_agc_test() {
@@ -258,6 +889,76 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    #[cfg(feature = "with_agc")]
+    fn agc_batched_fetch_test() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::agc_io::AGCFile;
+
+        let agcfile = AGCFile::new(String::from("test/test_data/test.agc"))?;
+        let requests = agcfile
+            .ctg_lens
+            .iter()
+            .flat_map(|((sample, ctg), &len)| {
+                (0..len)
+                    .step_by(5000)
+                    .map(|bgn| (sample.clone(), ctg.clone(), bgn, (bgn + 7000).min(len)))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let expected = requests
+            .iter()
+            .map(|(sample, ctg, bgn, end)| {
+                agcfile.get_sub_seq(sample.clone(), ctg.clone(), *bgn, *end)
+            })
+            .collect::<Vec<_>>();
+
+        let seqs = agcfile.get_sub_seqs(requests.clone());
+        assert_eq!(seqs, expected);
+
+        let mut prefetched = vec![];
+        agcfile.for_each_sub_seq_prefetched(requests.clone(), 3, 2, |request, seq| {
+            prefetched.push((request, seq));
+        });
+        assert_eq!(
+            prefetched,
+            requests.into_iter().zip(expected).collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "with_agc")]
+    fn agc_ctg_cache_test() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::agc_io::AGCFile;
+
+        let agcfile = AGCFile::new(String::from("test/test_data/test.agc"))?;
+        let mut ctgs = agcfile.ctg_lens.keys().cloned().collect::<Vec<_>>();
+        ctgs.sort();
+        let expected = ctgs
+            .iter()
+            .map(|(sample, ctg)| agcfile.get_sub_seq(sample.clone(), ctg.clone(), 100, 900))
+            .collect::<Vec<_>>();
+        assert_eq!(agcfile.cached_ctg_count(), 0);
+
+        agcfile.set_ctg_cache_size(2);
+        for _ in 0..2 {
+            for ((sample, ctg), seq) in ctgs.iter().zip(expected.iter()) {
+                let cached_seq = agcfile.get_sub_seq(sample.clone(), ctg.clone(), 100, 900);
+                assert_eq!(cached_seq, *seq);
+            }
+        }
+        assert_eq!(agcfile.cached_ctg_count(), ctgs.len().min(2));
+        let (sample, ctg) = &ctgs[0];
+        assert_eq!(
+            agcfile.get_seq(sample.clone(), ctg.clone()).len(),
+            agcfile.ctg_lens[&(sample.clone(), ctg.clone())]
+        );
+
+        agcfile.set_ctg_cache_size(0);
+        assert_eq!(agcfile.cached_ctg_count(), 0);
+        Ok(())
+    }
+
     #[test]
     #[cfg(feature = "with_agc")]
     fn query_frag_test() -> Result<(), std::io::Error> {
@@ -339,6 +1040,351 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn superbubble_test() {
+        use crate::graph_utils::{find_superbubbles, AdjList, BiDiNode, ShmmrGraphNode};
+        let node = |i: u64| ShmmrGraphNode(i, i + 100, 0);
+        let add_seq = |adj_list: &mut AdjList, sid: u32, path: &[u64]| {
+            path.windows(2).for_each(|w| {
+                adj_list.push((sid, node(w[0]), node(w[1])));
+                adj_list.push((sid, node(w[1]).reverse(), node(w[0]).reverse()));
+            })
+        };
+        let mut adj_list = AdjList::new();
+        add_seq(&mut adj_list, 0, &[1, 2, 3, 5, 6]);
+        add_seq(&mut adj_list, 1, &[1, 2, 4, 5, 6]);
+        add_seq(&mut adj_list, 2, &[1, 2, 4, 5, 6]);
+        add_seq(&mut adj_list, 3, &[1, 2, 5, 6]);
+
+        // the bubble is reported once for both strands
+        let bubbles = find_superbubbles(&adj_list, 1024);
+        assert_eq!(bubbles.len(), 1);
+        let ((entrance, exit, internal), alleles) = &bubbles[0];
+        assert_eq!((*entrance, *exit), (node(2), node(5)));
+        assert_eq!(*internal, vec![node(3), node(4)]);
+        assert_eq!(
+            *alleles,
+            vec![
+                (vec![node(2), node(4), node(5)], vec![1, 2]),
+                (vec![node(2), node(3), node(5)], vec![0]),
+                (vec![node(2), node(5)], vec![3]),
+            ]
+        );
+        assert!(find_superbubbles(&adj_list, 2).is_empty());
+
+        // no bubble with a tip or a cycle
+        let mut adj_list = AdjList::new();
+        add_seq(&mut adj_list, 0, &[1, 2, 3]);
+        add_seq(&mut adj_list, 1, &[1, 4]);
+        add_seq(&mut adj_list, 2, &[5, 6, 7, 8, 6]);
+        assert!(find_superbubbles(&adj_list, 1024).is_empty());
+
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string(), false);
+        let adj_list = seq_db::frag_map_to_adj_list(&sdb.frag_map, 2, None);
+        for ((entrance, exit, internal), alleles) in find_superbubbles(&adj_list, 1024) {
+            assert!(!alleles.is_empty());
+            for (path, sids) in alleles {
+                assert!(!sids.is_empty());
+                assert_eq!((path[0], path[path.len() - 1]), (entrance, exit));
+                assert!(path[1..path.len() - 1]
+                    .iter()
+                    .all(|v| internal.binary_search(v).is_ok()));
+            }
+        }
+    }
+
+    #[test]
+    fn graph_simplification_test() {
+        use crate::graph_utils::{
+            find_superbubbles, simplify_adj_list, AdjList, BiDiNode, GraphSimplification,
+            ShmmrGraphNode,
+        };
+        use std::collections::BTreeSet;
+        let node = |i: u64| ShmmrGraphNode(i, i + 100, 0);
+        let add_seq = |adj_list: &mut AdjList, sid: u32, path: &[u64]| {
+            path.windows(2).for_each(|w| {
+                adj_list.push((sid, node(w[0]), node(w[1])));
+                adj_list.push((sid, node(w[1]).reverse(), node(w[0]).reverse()));
+            })
+        };
+        let nodes = |adj_list: &AdjList| {
+            adj_list
+                .iter()
+                .flat_map(|(_, v, w)| [v.0, w.0])
+                .collect::<BTreeSet<u64>>()
+        };
+        let simplification = GraphSimplification::default();
+
+        // the tips at both ends of the sequences
+        let mut adj_list = AdjList::new();
+        add_seq(&mut adj_list, 0, &[1, 2, 3, 4, 5, 6]);
+        add_seq(&mut adj_list, 1, &[1, 2, 3, 4, 5, 6]);
+        add_seq(&mut adj_list, 2, &[20, 21, 3, 4, 10, 11]);
+        let simplified = simplify_adj_list(&adj_list, &simplification);
+        assert_eq!(nodes(&simplified), BTreeSet::from([1, 2, 3, 4, 5, 6]));
+        let no_tip_clipping = GraphSimplification {
+            max_tip_len: 1,
+            ..simplification
+        };
+        assert_eq!(simplify_adj_list(&adj_list, &no_tip_clipping), adj_list);
+
+        // a linear sequence is not a tip
+        let mut adj_list = AdjList::new();
+        add_seq(&mut adj_list, 0, &[1, 2, 3]);
+        assert_eq!(simplify_adj_list(&adj_list, &simplification), adj_list);
+
+        // the bubble is popped to the allele walked by the most sequences
+        let mut adj_list = AdjList::new();
+        add_seq(&mut adj_list, 0, &[1, 2, 3, 5, 6]);
+        add_seq(&mut adj_list, 1, &[1, 2, 4, 5, 6]);
+        add_seq(&mut adj_list, 2, &[1, 2, 4, 5, 6]);
+        let simplified = simplify_adj_list(&adj_list, &simplification);
+        assert_eq!(nodes(&simplified), BTreeSet::from([1, 2, 4, 5, 6]));
+        assert!(find_superbubbles(&simplified, 1024).is_empty());
+        let no_bubble_popping = GraphSimplification {
+            max_bubble_size: 0,
+            ..simplification
+        };
+        assert_eq!(simplify_adj_list(&adj_list, &no_bubble_popping), adj_list);
+    }
+
+    #[test]
+    fn mapg_component_test() {
+        use crate::ext::SeqIndexDB;
+        use crate::graph_utils::{split_adj_list_by_component, AdjList, ShmmrGraphNode};
+        let node = |i: u64| ShmmrGraphNode(i, i + 100, 0);
+        let adj_list: AdjList = vec![
+            (0, node(1), node(2)),
+            (1, node(10), node(11)),
+            (1, node(11), node(12)),
+            (2, node(3), node(2)),
+        ];
+        let components = split_adj_list_by_component(&adj_list);
+        assert_eq!(components.len(), 2);
+        assert_eq!(components[0], vec![adj_list[0], adj_list[3]]);
+        assert_eq!(components[1], vec![adj_list[1], adj_list[2]]);
+
+        // two unrelated pseudo-random sequences, the first one has two copies
+        let mut state = 0x5EED_u64;
+        let mut random_seq = |len: usize| {
+            (0..len)
+                .map(|_| {
+                    state = state
+                        .wrapping_mul(6364136223846793005)
+                        .wrapping_add(1442695040888963407);
+                    b"ACGT"[(state >> 62) as usize]
+                })
+                .collect::<Vec<u8>>()
+        };
+        let seq0 = random_seq(20000);
+        let seq1 = random_seq(8000);
+        let seq_list = vec![
+            ("seq0_0".to_string(), seq0.clone()),
+            ("seq0_1".to_string(), seq0),
+            ("seq1".to_string(), seq1),
+        ];
+        let mut seq_index_db = SeqIndexDB::new();
+        seq_index_db
+            .load_from_seq_list(seq_list, None, 48, 56, 4, 12)
+            .unwrap();
+        let components = seq_index_db.get_mapg_components(0, None);
+        assert_eq!(components.len(), 2);
+        assert_eq!(components[0].2, vec![0, 1]);
+        assert_eq!(components[1].2, vec![2]);
+        assert!(components[0].0 > components[1].0);
+        // the vertices in a component are connected
+        components
+            .iter()
+            .for_each(|(n_vertices, n_edges, _)| assert!(*n_edges + 1 >= *n_vertices));
+
+        let bundles = seq_index_db.get_principal_bundles_by_component(0, 8, None);
+        assert_eq!(bundles.len(), 2);
+        bundles.iter().for_each(|b| assert!(!b.is_empty()));
+    }
+
+    #[test]
+    fn mapg_coverage_test() -> Result<(), std::io::Error> {
+        use crate::ext::SeqIndexDB;
+        use rustc_hash::FxHashMap;
+        let mut state = 0x5EED_u64;
+        let mut random_seq = |len: usize| {
+            (0..len)
+                .map(|_| {
+                    state = state
+                        .wrapping_mul(6364136223846793005)
+                        .wrapping_add(1442695040888963407);
+                    b"ACGT"[(state >> 62) as usize]
+                })
+                .collect::<Vec<u8>>()
+        };
+        let seq0 = random_seq(20000);
+        let seq1 = random_seq(8000);
+        let seq_list = vec![
+            ("seq0_0".to_string(), seq0.clone()),
+            ("seq0_1".to_string(), seq0),
+            ("seq1".to_string(), seq1),
+        ];
+        let mut seq_index_db = SeqIndexDB::new();
+        seq_index_db.load_from_seq_list(seq_list, None, 48, 56, 4, 12)?;
+
+        let mut gfa = Vec::<u8>::new();
+        seq_index_db.write_mapg_gfa(0, "from_fragmap", None, None, false, &mut gfa)?;
+        let gfa = String::from_utf8(gfa).unwrap();
+        let get_tag = |fields: &[&str], tag: &str| {
+            fields
+                .iter()
+                .find_map(|f| f.strip_prefix(tag))
+                .unwrap()
+                .to_string()
+        };
+        let mut n_segments = 0;
+        let mut n_links = 0;
+        for line in gfa.lines() {
+            let fields = line.split('\t').collect::<Vec<_>>();
+            if fields[0] != "S" && fields[0] != "L" {
+                continue;
+            }
+            if fields[0] == "S" {
+                n_segments += 1;
+            } else {
+                n_links += 1;
+            }
+            let sids = get_tag(&fields, "SI:Z:");
+            assert!(sids == "0,1" || sids == "2");
+            let coverage = get_tag(&fields, "SC:i:").parse::<usize>().unwrap();
+            assert!(coverage >= sids.split(',').count());
+        }
+        assert!(n_segments > 0 && n_links > 0);
+
+        let tsv_path = std::env::temp_dir().join("pgr_db_mapg_coverage_test.tsv");
+        let tsv_path = tsv_path.to_str().unwrap();
+        seq_index_db.write_mapg_coverage_tsv(0, tsv_path, "from_fragmap", None, None)?;
+        let tsv = std::fs::read_to_string(tsv_path)?;
+        std::fs::remove_file(tsv_path)?;
+        let rows = tsv
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| line.split('\t').collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(rows.iter().filter(|r| r[0] == "S").count(), n_segments);
+        assert_eq!(rows.iter().filter(|r| r[0] == "L").count(), n_links);
+        rows.iter().for_each(|r| {
+            let n_seqs = r[5].parse::<usize>().unwrap();
+            assert_eq!(r[6].split(',').count(), n_seqs);
+            assert!(r[4].parse::<usize>().unwrap() >= n_seqs);
+        });
+
+        // the two copies of seq0 are from the same haplotype
+        let sid_to_haplotype = FxHashMap::from_iter([
+            (0, "h0".to_string()),
+            (1, "h0".to_string()),
+            (2, "h1".to_string()),
+        ]);
+        let mut gfa = Vec::<u8>::new();
+        seq_index_db.write_mapg_gfa(
+            0,
+            "from_fragmap",
+            None,
+            Some(&sid_to_haplotype),
+            false,
+            &mut gfa,
+        )?;
+        let gfa = String::from_utf8(gfa).unwrap();
+        for line in gfa.lines() {
+            let fields = line.split('\t').collect::<Vec<_>>();
+            if fields[0] != "S" && fields[0] != "L" {
+                continue;
+            }
+            let haplotypes = if get_tag(&fields, "SI:Z:") == "2" {
+                "h1"
+            } else {
+                "h0"
+            };
+            assert_eq!(get_tag(&fields, "DP:i:"), "1");
+            assert_eq!(get_tag(&fields, "HP:Z:"), haplotypes);
+        }
+
+        seq_index_db.write_mapg_coverage_tsv(
+            0,
+            tsv_path,
+            "from_fragmap",
+            None,
+            Some(&sid_to_haplotype),
+        )?;
+        let tsv = std::fs::read_to_string(tsv_path)?;
+        std::fs::remove_file(tsv_path)?;
+        tsv.lines().skip(1).for_each(|line| {
+            let r = line.split('\t').collect::<Vec<_>>();
+            assert_eq!(r.len(), 9);
+            assert_eq!(r[7], "1");
+            assert_eq!(r[8], if r[6] == "2" { "h1" } else { "h0" });
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn mapg_cycle_test() {
+        use crate::ext::SeqIndexDB;
+        use crate::graph_utils::{find_cycles, AdjList, BiDiNode, ShmmrGraphNode};
+        let node = |i: u64| ShmmrGraphNode(i, i + 100, 0);
+        let add_seq = |adj_list: &mut AdjList, sid: u32, path: &[u64]| {
+            path.windows(2).for_each(|w| {
+                adj_list.push((sid, node(w[0]), node(w[1])));
+                adj_list.push((sid, node(w[1]).reverse(), node(w[0]).reverse()));
+            })
+        };
+        let mut adj_list = AdjList::new();
+        add_seq(&mut adj_list, 0, &[1, 2, 3, 4, 2, 3, 4, 2, 3, 4, 5]);
+        add_seq(&mut adj_list, 1, &[1, 2, 3, 4, 5]);
+        add_seq(&mut adj_list, 2, &[1, 2, 3, 4, 2, 3, 4, 5]);
+        // the loop is reported once for both strands
+        let cycles = find_cycles(&adj_list, 16);
+        assert_eq!(cycles.len(), 1);
+        assert_eq!(cycles[0].0, vec![node(2), node(3), node(4)]);
+        assert_eq!(cycles[0].1, 3);
+        assert_eq!(cycles[0].2, vec![(0, 3), (1, 1), (2, 2)]);
+        assert!(find_cycles(&adj_list, 2).is_empty());
+
+        // a tandem repeat with 3 and 5 copies of the repeat unit between the unique flanking sequences
+        let mut state = 0x5EED_u64;
+        let mut random_seq = |len: usize| {
+            (0..len)
+                .map(|_| {
+                    state = state
+                        .wrapping_mul(6364136223846793005)
+                        .wrapping_add(1442695040888963407);
+                    b"ACGT"[(state >> 62) as usize]
+                })
+                .collect::<Vec<u8>>()
+        };
+        let left = random_seq(5000);
+        let unit = random_seq(3000);
+        let right = random_seq(5000);
+        let seq_with_copies = |n_copies: usize| {
+            let mut seq = left.clone();
+            (0..n_copies).for_each(|_| seq.extend_from_slice(&unit));
+            seq.extend_from_slice(&right);
+            seq
+        };
+        let seq_list = vec![
+            ("vntr_3".to_string(), seq_with_copies(3)),
+            ("vntr_5".to_string(), seq_with_copies(5)),
+        ];
+        let mut seq_index_db = SeqIndexDB::new();
+        seq_index_db
+            .load_from_seq_list(seq_list, None, 48, 56, 4, 12)
+            .unwrap();
+        let cycles = seq_index_db.get_mapg_cycles(0, None, 256);
+        let (_, unit_len, traversals) = cycles
+            .iter()
+            .max_by_key(|(vertices, _, _)| vertices.len())
+            .unwrap();
+        assert!(*unit_len > 2500 && *unit_len < 3500);
+        assert_eq!(traversals.len(), 2);
+        assert!(traversals[0].1 < traversals[1].1);
+    }
+
     #[test]
     fn test_shmmr_reduction_boundary_condition() {
         // In some cases, the numnber of shimmers might be less than the number of the reducetion factor.
@@ -347,13 +1393,7 @@ mod tests {
         let seq2 = b"CCAGTTGTATCCATGACAAAGATGAGGCCGCGAGGAGGGCGAGTGGGTTTGGGGGCAGGCAGAGTGCCTTGGAGAACTTACAGGTCCTGCCACAATCCTAATGCAAGGATGGAGCTGCAAGTTCAGTTTGGGAATCATCAGCCTGGATTGGTTTGGTGGAAGCCAGGGAGTGGTTGAGACCCCCACAGGGGAGCTCTGAGGAAGGAAGTTCCGAAGGAGGGAACGTAAGAAATGACCAGGTCAGAACCAAGGGTGGTCCAGAAGCTAACCCTTAGCTTAGGGACAGTTTCACAGAGAACACGTCCATGATGCAAGACTCTGCTGAGGGCCTGGAGCAGTGAAGACTGGGGCAAGGTCACCCTCTGGGAAGTGAAGTCACCAGAGACCTTGCGGAGCAGCTTTGAGAGTTCTCTGAGTAGGAAGGTAACAGAATGTGAAGGACACTGGAGAGAAGGCCAATAGGAAGCAAACAAAAACAGGCCAAGGAAACCCAGTACAGGGGGCTGCAGGGCCCAGGGAGTGGGTCCCTCATCTCTCCTCCCCACGCTTGGCCAGGTCCCCACCTCCCGGGAGTGCGTGGGCTTTGAGGCTGTGCAGGAAGTGCCGGTGGGGCTGGTGCAGCCGGCCAGCGCAACCCTGTACGACTACTACAACCCCGGTGAGCACTGCAGGACACCCTGAAATTCAGGAGAACTTTGGCATAGGTGCCCTCCTATGGGACAATGGACACCGGGGTAGTGAGGGGGCAGAGAGCCCTGGGGCTCCCTGGGACTGAGGAGGCAGAATGGAGGGGCCTGTGCCCTAACTCCTCTCTGTTCTCCAGAGCGCAGATGTTCTGTGTTTTACGGGGCACCAAGTAAGAGCAGACTCTTGGCCACCTTGTGTTCTGCTGAAGTCTGCCAGTGTGCTGAGGGTGAGACTGAGGGCCTGGGGCGGGGCAGT";
         use shmmrutils::sequence_to_shmmrs;
         use shmmrutils::ShmmrSpec;
-        let spec = ShmmrSpec {
-            w: 24,
-            k: 24,
-            r: 12,
-            min_span: 24,
-            sketch: false,
-        };
+        let spec = ShmmrSpec::new(24, 24, 12, 24, false);
         let out1 = sequence_to_shmmrs(0, &seq.to_vec(), &spec, true);
         println!("out1: {} {:?}", out1.len(), out1);
         let out2 = sequence_to_shmmrs(0, &seq2.to_vec(), &spec, true);
@@ -362,6 +1402,77 @@ mod tests {
         assert!(out2.len() == 2);
     }
 
+    #[test]
+    fn shmmr_pair_filter_test() -> Result<(), std::io::Error> {
+        use crate::kmer_filter::ShmmrPairFilter;
+        use seq_db::write_shmmr_map_file;
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string(), false)?;
+        let mdb_prefix = std::env::temp_dir().join("pgr_db_shmmr_pair_filter_test");
+        let mdb_prefix = mdb_prefix.to_string_lossy().to_string();
+        write_shmmr_map_file(&sdb.shmmr_spec, &sdb.frag_map, mdb_prefix.clone() + ".mdb")?;
+        let filter = ShmmrPairFilter::from_mdb_file(&mdb_prefix)?;
+        std::fs::remove_file(mdb_prefix + ".mdb")?;
+        assert_eq!(filter.len(), sdb.frag_map.len());
+
+        let seqs = load_seqs();
+        seqs.values().for_each(|seq| {
+            let (n_pairs, n_hits) = filter.check_seq(seq);
+            assert_eq!(n_pairs, n_hits);
+        });
+
+        // a pseudo-random sequence shares no shimmer pair with the index
+        let mut state = 0x5EED_u64;
+        let seq = (0..20000)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                b"ACGT"[(state >> 62) as usize]
+            })
+            .collect::<Vec<u8>>();
+        let (n_pairs, n_hits) = filter.check_seq(&seq);
+        assert!(n_pairs > 0);
+        assert_eq!(n_hits, 0);
+
+        let mut filter = ShmmrPairFilter::new(seq_db::SHMMRSPEC);
+        filter.add_seq(&seq);
+        let (n_pairs, n_hits) = filter.check_seq(&seq);
+        assert_eq!(n_pairs, n_hits);
+        Ok(())
+    }
+
+    #[test]
+    fn counting_minimizer_filter_test() {
+        use crate::kmer_filter::CountingMinimizerFilter;
+        let seqs = load_seqs();
+        let mut seqs = seqs.into_iter().collect::<Vec<_>>();
+        seqs.sort();
+        let shmmr_spec = shmmrutils::ShmmrSpec::new(16, 32, 1, 0, false);
+        let mut filter = CountingMinimizerFilter::new(shmmr_spec, 200000, 0.01);
+        // the first sequence is added three times as a "high-copy" sequence
+        (0..3).for_each(|_| filter.add_seq_mmers(&seqs[0].1));
+        seqs[1..]
+            .iter()
+            .for_each(|(_, seq)| filter.add_seq_mmers(seq));
+
+        let (n_mmers, n_abundant) = filter.check_seq_abundance(&seqs[0].1, 3);
+        assert!(n_mmers > 0);
+        assert_eq!(n_mmers, n_abundant);
+
+        // the counts are never under-estimated
+        let mut exact_counts = HashMap::<u64, u8>::new();
+        (0..1000_u64).for_each(|key| {
+            (0..key % 5).for_each(|_| {
+                filter.add(key);
+                *exact_counts.entry(key).or_default() += 1;
+            })
+        });
+        exact_counts
+            .iter()
+            .for_each(|(&key, &count)| assert!(filter.count(key) >= count));
+    }
+
     #[test]
     fn test_open_compact_seq_db_storage() {
         use crate::frag_file_io::CompactSeqFragFileStorage;
@@ -419,6 +1530,181 @@ mod tests {
         assert_eq!(seq[250..1423], sub_seq[..]);
     }
 
+    #[test]
+    fn data_file_test() -> Result<(), std::io::Error> {
+        use crate::remote_io::{self, DataFile};
+        let filepath = "test/test_data/test_seqs_frag.mdb";
+        let bytes = std::fs::read(filepath)?;
+        let data_file = DataFile::open(filepath)?;
+        assert_eq!(data_file.len(), bytes.len());
+        assert_eq!(data_file.read_at(3, 20)?[..], bytes[3..23]);
+        assert!(data_file.read_at(bytes.len() - 10, 20).is_err());
+
+        let mut read_bytes = vec![];
+        remote_io::open_file(filepath)?.read_to_end(&mut read_bytes)?;
+        assert_eq!(read_bytes, bytes);
+
+        assert!(remote_io::is_remote_path("https://example.com/db"));
+        assert!(remote_io::is_remote_path("s3://bucket/db"));
+        assert!(!remote_io::is_remote_path(filepath));
+        Ok(())
+    }
+
+    // a minimal HTTP server answering the range requests with the files in the test data directory
+    #[cfg(feature = "remote")]
+    fn serve_test_data() -> Result<std::net::SocketAddr, std::io::Error> {
+        use std::io::Write;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                std::thread::spawn(move || {
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let path = line.split(' ').nth(1).unwrap().to_string();
+                    let mut range = None;
+                    loop {
+                        line.clear();
+                        reader.read_line(&mut line).unwrap();
+                        if line.trim().is_empty() {
+                            break;
+                        }
+                        if let Some(r) = line.to_lowercase().trim().strip_prefix("range: bytes=") {
+                            let (bgn, end) = r.split_once('-').unwrap();
+                            range = Some((
+                                bgn.parse::<usize>().unwrap(),
+                                end.parse::<usize>().unwrap(),
+                            ));
+                        }
+                    }
+                    let data = std::fs::read(format!("test/test_data{}", path)).unwrap();
+                    let (bgn, end) = range.unwrap();
+                    let end = end.min(data.len() - 1);
+                    write!(
+                        stream,
+                        "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        bgn,
+                        end,
+                        data.len(),
+                        end - bgn + 1
+                    )
+                    .unwrap();
+                    stream.write_all(&data[bgn..=end]).unwrap();
+                });
+            }
+        });
+
+        Ok(addr)
+    }
+
+    #[test]
+    #[cfg(feature = "remote")]
+    fn remote_frg_db_test() -> Result<(), std::io::Error> {
+        use crate::frag_file_io::CompactSeqFragFileStorage;
+        use seq_db::GetSeq;
+
+        let addr = serve_test_data()?;
+        let local_storage =
+            CompactSeqFragFileStorage::new("test/test_data/test_seqs_frag".to_string());
+        let remote_storage =
+            CompactSeqFragFileStorage::new(format!("http://{}/test_seqs_frag", addr));
+        assert_eq!(remote_storage.seq_info, local_storage.seq_info);
+        assert_eq!(
+            remote_storage.frag_location_map,
+            local_storage.frag_location_map
+        );
+        for sid in 0..local_storage.seqs.len() as u32 {
+            assert_eq!(
+                remote_storage.get_seq_by_id(sid),
+                local_storage.get_seq_by_id(sid)
+            );
+        }
+        assert_eq!(
+            remote_storage.get_sub_seq_by_id(0, 250, 1423),
+            local_storage.get_sub_seq_by_id(0, 250, 1423)
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "remote")]
+    fn remote_agc_rejected_test() -> Result<(), std::io::Error> {
+        use crate::remote_io;
+
+        let addr = serve_test_data()?;
+        let url = format!("http://{}/test.agc", addr);
+        let err = remote_io::local_file_path(&url).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+        assert_eq!(
+            remote_io::local_file_path("test/test_data/test.agc")?,
+            "test/test_data/test.agc"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn query_frag_unique_anchor_test() {
+        use crate::aln::{query_fragment_to_hps, query_fragment_to_unique_anchor_hps};
+        use seq_db::{raw_query_fragment, GetSeq};
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string(), false);
+        let seq = sdb.get_seq_by_id(0);
+        let shmmr_spec = seq_db::SHMMRSPEC;
+        let raw_query_hits = raw_query_fragment(&sdb.frag_map, &seq, &shmmr_spec);
+        let query = |max_anchor_count| {
+            query_fragment_to_unique_anchor_hps(
+                raw_query_hits.clone(),
+                &seq,
+                &shmmr_spec,
+                0.025.into(),
+                max_anchor_count,
+                Some(u32::MAX),
+                Some(u32::MAX),
+                Some(u32::MAX),
+                None,
+                None,
+                false,
+            )
+        };
+
+        // all the hits are used as the anchors without the limit
+        let (mut hit_pair_lists, repeat_hit_pairs) = query(None);
+        assert!(repeat_hit_pairs.is_empty());
+        let mut expected = query_fragment_to_hps(
+            raw_query_hits.clone(),
+            &seq,
+            &shmmr_spec,
+            0.025.into(),
+            Some(u32::MAX),
+            Some(u32::MAX),
+            Some(u32::MAX),
+            None,
+            None,
+            false,
+        );
+        hit_pair_lists.sort_by_key(|(sid, _)| *sid);
+        expected.sort_by_key(|(sid, _)| *sid);
+        assert_eq!(hit_pair_lists, expected);
+
+        // the hits of the shimmer pairs in more than one fragment are reported separately
+        let (_, repeat_hit_pairs) = query(Some(1));
+        let n_repeat_hits = raw_query_hits
+            .iter()
+            .filter(|(_, _, frag_signatures)| frag_signatures.len() > 1)
+            .map(|(_, _, frag_signatures)| frag_signatures.len())
+            .sum::<usize>();
+        assert!(n_repeat_hits > 0);
+        assert_eq!(
+            repeat_hit_pairs
+                .iter()
+                .map(|(_, hps)| hps.len())
+                .sum::<usize>(),
+            n_repeat_hits
+        );
+    }
+
     #[test]
     fn test_seq_db_get_sub_read() {
         use seq_db::GetSeq;
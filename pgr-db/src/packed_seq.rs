@@ -0,0 +1,184 @@
+use crate::fasta_io::reverse_complement;
+use bincode::{Decode, Encode};
+
+// the number of bases in a word of the 2-bit codes
+const BASES_PER_WORD: usize = 32;
+
+#[inline]
+fn base_to_code(b: u8) -> Option<u64> {
+    match b {
+        b'A' | b'a' => Some(0),
+        b'C' | b'c' => Some(1),
+        b'G' | b'g' => Some(2),
+        b'T' | b't' => Some(3),
+        _ => None,
+    }
+}
+
+/// complement the 2-bit codes of a word and reverse their order
+#[inline]
+fn reverse_complement_word(w: u64) -> u64 {
+    let w = !w;
+    let w = ((w >> 2) & 0x3333_3333_3333_3333) | ((w & 0x3333_3333_3333_3333) << 2);
+    let w = ((w >> 4) & 0x0F0F_0F0F_0F0F_0F0F) | ((w & 0x0F0F_0F0F_0F0F_0F0F) << 4);
+    w.swap_bytes()
+}
+
+/// add the position `pos` to the runs `(bgn, len, base)`, extending the last run if it ends at `pos` with the same base
+#[inline]
+fn push_run(runs: &mut Vec<(u32, u32, u8)>, pos: u32, base: u8) {
+    match runs.last_mut() {
+        Some((bgn, len, b)) if *bgn + *len == pos && *b == base => *len += 1,
+        _ => runs.push((pos, 1, base)),
+    }
+}
+
+/// a 2-bit packed sequence, the bases other than "ACGT" (e.g., "N" and the other IUPAC codes) are kept
+/// in an exception list of runs and the soft-masked (lower case) bases are kept as the intervals, so
+/// the original sequence is fully recovered
+#[derive(Debug, Clone, PartialEq, Eq, Default, Decode, Encode)]
+pub struct PackedSeq {
+    len: usize,
+    // the 2-bit codes (A:0, C:1, G:2, T:3) of 32 bases per word, the first base in the lowest bits
+    words: Vec<u64>,
+    // the runs (bgn, len, base) of the bases other than "ACGT"
+    exceptions: Vec<(u32, u32, u8)>,
+    // the intervals (bgn, len) of the lower case "acgt"
+    lower_case: Vec<(u32, u32)>,
+}
+
+impl PackedSeq {
+    pub fn new(seq: &[u8]) -> Self {
+        let mut words = vec![0_u64; seq.len().div_ceil(BASES_PER_WORD)];
+        let mut exceptions = Vec::<(u32, u32, u8)>::new();
+        let mut lower_case = Vec::<(u32, u32, u8)>::new();
+        seq.iter().enumerate().for_each(|(pos, &b)| {
+            if let Some(code) = base_to_code(b) {
+                words[pos / BASES_PER_WORD] |= code << ((pos % BASES_PER_WORD) << 1);
+                if b.is_ascii_lowercase() {
+                    push_run(&mut lower_case, pos as u32, 0);
+                }
+            } else {
+                push_run(&mut exceptions, pos as u32, b);
+            }
+        });
+        let lower_case = lower_case
+            .into_iter()
+            .map(|(bgn, len, _)| (bgn, len))
+            .collect();
+        PackedSeq {
+            len: seq.len(),
+            words,
+            exceptions,
+            lower_case,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// the memory used by the packed sequence in bytes
+    pub fn mem_size(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.words.len() * std::mem::size_of::<u64>()
+            + self.exceptions.len() * std::mem::size_of::<(u32, u32, u8)>()
+            + self.lower_case.len() * std::mem::size_of::<(u32, u32)>()
+    }
+
+    /// unpack the sub-sequence `[bgn, end)`
+    pub fn sub_seq(&self, bgn: usize, end: usize) -> Vec<u8> {
+        assert!(bgn <= end && end <= self.len);
+        let mut seq = (bgn..end)
+            .map(|pos| {
+                let code = self.words[pos / BASES_PER_WORD] >> ((pos % BASES_PER_WORD) << 1);
+                b"ACGT"[(code & 0b11) as usize]
+            })
+            .collect::<Vec<u8>>();
+        // the runs and intervals are sorted and not overlapped, clip the ones overlapping [bgn, end)
+        let clip = |r_bgn: u32, r_len: u32| {
+            let r_end = ((r_bgn + r_len) as usize).min(end);
+            ((r_bgn as usize).max(bgn) - bgn, r_end - bgn)
+        };
+        let first = self
+            .lower_case
+            .partition_point(|&(r_bgn, r_len)| ((r_bgn + r_len) as usize) <= bgn);
+        self.lower_case[first..]
+            .iter()
+            .take_while(|(r_bgn, _)| (*r_bgn as usize) < end)
+            .for_each(|&(r_bgn, r_len)| {
+                let (r_bgn, r_end) = clip(r_bgn, r_len);
+                seq[r_bgn..r_end].make_ascii_lowercase();
+            });
+        let first = self
+            .exceptions
+            .partition_point(|&(r_bgn, r_len, _)| ((r_bgn + r_len) as usize) <= bgn);
+        self.exceptions[first..]
+            .iter()
+            .take_while(|(r_bgn, _, _)| (*r_bgn as usize) < end)
+            .for_each(|&(r_bgn, r_len, b)| {
+                let (r_bgn, r_end) = clip(r_bgn, r_len);
+                seq[r_bgn..r_end].fill(b);
+            });
+        seq
+    }
+
+    /// unpack the whole sequence
+    pub fn to_seq(&self) -> Vec<u8> {
+        self.sub_seq(0, self.len)
+    }
+
+    /// the reverse complement, the 2-bit codes are reverse complemented by words without unpacking the bases
+    pub fn reverse_complement(&self) -> Self {
+        let n_words = self.words.len();
+        let mut words = self
+            .words
+            .iter()
+            .rev()
+            .map(|&w| reverse_complement_word(w))
+            .collect::<Vec<u64>>();
+        // shift out the padding bases of the last word which are at the beginning now, the new padding bits are zeros
+        let shift = ((n_words * BASES_PER_WORD - self.len) << 1) as u32;
+        if shift > 0 {
+            (0..n_words).for_each(|i| {
+                let next = words.get(i + 1).map_or(0, |w| w << (64 - shift));
+                words[i] = (words[i] >> shift) | next;
+            });
+        }
+        let len = self.len as u32;
+        let exceptions = self
+            .exceptions
+            .iter()
+            .rev()
+            .map(|&(bgn, r_len, b)| (len - bgn - r_len, r_len, reverse_complement(&[b])[0]))
+            .collect::<Vec<_>>();
+        // keep the codes of the exceptions as zeros
+        exceptions.iter().for_each(|&(bgn, r_len, _)| {
+            (bgn as usize..(bgn + r_len) as usize).for_each(|pos| {
+                words[pos / BASES_PER_WORD] &= !(0b11 << ((pos % BASES_PER_WORD) << 1));
+            })
+        });
+        let lower_case = self
+            .lower_case
+            .iter()
+            .rev()
+            .map(|&(bgn, r_len)| (len - bgn - r_len, r_len))
+            .collect();
+        PackedSeq {
+            len: self.len,
+            words,
+            exceptions,
+            lower_case,
+        }
+    }
+}
+
+impl From<&[u8]> for PackedSeq {
+    fn from(seq: &[u8]) -> Self {
+        PackedSeq::new(seq)
+    }
+}
@@ -0,0 +1,347 @@
+//! a simple partial order alignment (POA) graph for building multiple sequence alignments
+//! of short intervals, e.g., the sequences between two shared principal bundle anchors
+
+use rustc_hash::FxHashMap;
+
+pub struct PoaNode {
+    pub base: u8,
+    /// (target node, weight = number of sequences going through the edge)
+    pub out_edges: Vec<(usize, u32)>,
+    pub in_nodes: Vec<usize>,
+    /// the nodes with different bases aligned to this node
+    pub aligned_to: Vec<usize>,
+}
+
+pub struct PoaGraph {
+    pub nodes: Vec<PoaNode>,
+    /// the node path of each sequence added to the graph
+    pub seq_paths: Vec<Vec<usize>>,
+    match_score: i32,
+    mismatch_penalty: i32,
+    gap_penalty: i32,
+}
+
+impl PoaGraph {
+    pub fn new(match_score: i32, mismatch_penalty: i32, gap_penalty: i32) -> Self {
+        PoaGraph {
+            nodes: vec![],
+            seq_paths: vec![],
+            match_score,
+            mismatch_penalty,
+            gap_penalty,
+        }
+    }
+
+    fn add_node(&mut self, base: u8) -> usize {
+        self.nodes.push(PoaNode {
+            base,
+            out_edges: vec![],
+            in_nodes: vec![],
+            aligned_to: vec![],
+        });
+        self.nodes.len() - 1
+    }
+
+    fn add_edge(&mut self, v: usize, w: usize) {
+        if let Some(e) = self.nodes[v].out_edges.iter_mut().find(|e| e.0 == w) {
+            e.1 += 1;
+        } else {
+            self.nodes[v].out_edges.push((w, 1));
+            self.nodes[w].in_nodes.push(v);
+        }
+    }
+
+    pub fn topological_order(&self) -> Vec<usize> {
+        let mut in_degree = self
+            .nodes
+            .iter()
+            .map(|n| n.in_nodes.len())
+            .collect::<Vec<usize>>();
+        let mut stack = (0..self.nodes.len())
+            .filter(|&v| in_degree[v] == 0)
+            .rev()
+            .collect::<Vec<usize>>();
+        let mut order = Vec::<usize>::with_capacity(self.nodes.len());
+        while let Some(v) = stack.pop() {
+            order.push(v);
+            self.nodes[v].out_edges.iter().for_each(|&(w, _)| {
+                in_degree[w] -= 1;
+                if in_degree[w] == 0 {
+                    stack.push(w);
+                }
+            });
+        }
+        order
+    }
+
+    /// global alignment of a sequence to the graph with a linear gap penalty,
+    /// return the aligned pairs of (graph node, sequence position)
+    fn align_seq(&self, seq: &[u8]) -> Vec<(Option<usize>, Option<usize>)> {
+        let order = self.topological_order();
+        let mut row_of_node = vec![0_usize; self.nodes.len()];
+        order.iter().enumerate().for_each(|(i, &v)| {
+            row_of_node[v] = i + 1;
+        });
+        let pred_rows = order
+            .iter()
+            .map(|&v| {
+                let preds = self.nodes[v]
+                    .in_nodes
+                    .iter()
+                    .map(|&u| row_of_node[u])
+                    .collect::<Vec<usize>>();
+                if preds.is_empty() {
+                    vec![0]
+                } else {
+                    preds
+                }
+            })
+            .collect::<Vec<Vec<usize>>>();
+
+        let n_row = order.len() + 1;
+        let n_col = seq.len() + 1;
+        let gap = self.gap_penalty;
+        let s = |base0: u8, base1: u8| -> i32 {
+            if base0 == base1 {
+                self.match_score
+            } else {
+                -self.mismatch_penalty
+            }
+        };
+
+        // row 0 is a virtual start node
+        let mut scores = vec![0_i32; n_row * n_col];
+        (1..n_col).for_each(|j| scores[j] = -gap * j as i32);
+        for i in 1..n_row {
+            let v = order[i - 1];
+            let base = self.nodes[v].base;
+            let preds = &pred_rows[i - 1];
+            scores[i * n_col] = preds.iter().map(|&p| scores[p * n_col]).max().unwrap() - gap;
+            for j in 1..n_col {
+                let mut best = scores[i * n_col + j - 1] - gap;
+                preds.iter().for_each(|&p| {
+                    let m = scores[p * n_col + j - 1] + s(base, seq[j - 1]);
+                    let d = scores[p * n_col + j] - gap;
+                    best = best.max(m).max(d);
+                });
+                scores[i * n_col + j] = best;
+            }
+        }
+
+        let end_row = (1..n_row)
+            .filter(|&i| self.nodes[order[i - 1]].out_edges.is_empty())
+            .max_by_key(|&i| scores[i * n_col + n_col - 1])
+            .unwrap();
+
+        let mut aln_pairs = Vec::<(Option<usize>, Option<usize>)>::new();
+        let mut i = end_row;
+        let mut j = n_col - 1;
+        while i > 0 || j > 0 {
+            if i == 0 {
+                aln_pairs.push((None, Some(j - 1)));
+                j -= 1;
+                continue;
+            }
+            let v = order[i - 1];
+            let score = scores[i * n_col + j];
+            let preds = &pred_rows[i - 1];
+            if j > 0 {
+                if let Some(&p) = preds.iter().find(|&&p| {
+                    scores[p * n_col + j - 1] + s(self.nodes[v].base, seq[j - 1]) == score
+                }) {
+                    aln_pairs.push((Some(v), Some(j - 1)));
+                    i = p;
+                    j -= 1;
+                    continue;
+                }
+                if scores[i * n_col + j - 1] - gap == score {
+                    aln_pairs.push((None, Some(j - 1)));
+                    j -= 1;
+                    continue;
+                }
+            }
+            let p = *preds
+                .iter()
+                .find(|&&p| scores[p * n_col + j] - gap == score)
+                .unwrap();
+            aln_pairs.push((Some(v), None));
+            i = p;
+        }
+        aln_pairs.reverse();
+        aln_pairs
+    }
+
+    pub fn add_seq(&mut self, seq: &[u8]) {
+        let mut path = Vec::<usize>::with_capacity(seq.len());
+        if self.nodes.is_empty() || seq.is_empty() {
+            let mut p_node: Option<usize> = None;
+            seq.iter().for_each(|&base| {
+                let node = self.add_node(base);
+                if let Some(p_node) = p_node {
+                    self.add_edge(p_node, node);
+                }
+                path.push(node);
+                p_node = Some(node);
+            });
+            self.seq_paths.push(path);
+            return;
+        }
+
+        let aln_pairs = self.align_seq(seq);
+        let mut p_node: Option<usize> = None;
+        aln_pairs.into_iter().for_each(|(v, j)| {
+            if j.is_none() {
+                return;
+            }
+            let base = seq[j.unwrap()];
+            let node = match v {
+                None => self.add_node(base),
+                Some(v) if self.nodes[v].base == base => v,
+                Some(v) => {
+                    if let Some(&w) = self.nodes[v]
+                        .aligned_to
+                        .iter()
+                        .find(|&&w| self.nodes[w].base == base)
+                    {
+                        w
+                    } else {
+                        let w = self.add_node(base);
+                        let mut aligned_to = self.nodes[v].aligned_to.clone();
+                        aligned_to.push(v);
+                        aligned_to
+                            .iter()
+                            .for_each(|&u| self.nodes[u].aligned_to.push(w));
+                        self.nodes[w].aligned_to = aligned_to;
+                        w
+                    }
+                }
+            };
+            if let Some(p_node) = p_node {
+                self.add_edge(p_node, node);
+            }
+            path.push(node);
+            p_node = Some(node);
+        });
+        self.seq_paths.push(path);
+    }
+
+    /// convert the graph to the rows of a multiple sequence alignment, one row per added sequence
+    pub fn get_msa(&self) -> Vec<Vec<u8>> {
+        // the aligned nodes are put into the same column
+        let group_of_node = (0..self.nodes.len())
+            .map(|v| {
+                self.nodes[v]
+                    .aligned_to
+                    .iter()
+                    .fold(v, |m, &u| if u < m { u } else { m })
+            })
+            .collect::<Vec<usize>>();
+        let mut group_out_edges = FxHashMap::<usize, Vec<usize>>::default();
+        let mut group_in_degree = FxHashMap::<usize, usize>::default();
+        (0..self.nodes.len()).for_each(|v| {
+            let g0 = group_of_node[v];
+            group_in_degree.entry(g0).or_insert(0);
+            self.nodes[v].out_edges.iter().for_each(|&(w, _)| {
+                let g1 = group_of_node[w];
+                let e = group_out_edges.entry(g0).or_default();
+                if g1 != g0 && !e.contains(&g1) {
+                    e.push(g1);
+                    *group_in_degree.entry(g1).or_insert(0) += 1;
+                }
+            });
+        });
+
+        let mut group_column = FxHashMap::<usize, usize>::default();
+        let mut stack = group_in_degree
+            .iter()
+            .filter(|(_, &d)| d == 0)
+            .map(|(&g, _)| g)
+            .collect::<Vec<usize>>();
+        stack.sort_by(|a, b| b.cmp(a));
+        while let Some(g) = stack.pop() {
+            group_column.insert(g, group_column.len());
+            if let Some(out_groups) = group_out_edges.get(&g) {
+                out_groups.iter().for_each(|&g1| {
+                    let d = group_in_degree.get_mut(&g1).unwrap();
+                    *d -= 1;
+                    if *d == 0 {
+                        stack.push(g1);
+                    }
+                });
+            }
+        }
+        // groups in a cycle, should be rare, are appended at the end
+        self.topological_order().into_iter().for_each(|v| {
+            let g = group_of_node[v];
+            if !group_column.contains_key(&g) {
+                group_column.insert(g, group_column.len());
+            }
+        });
+
+        let n_col = group_column.len();
+        self.seq_paths
+            .iter()
+            .map(|path| {
+                let mut row = vec![b'-'; n_col];
+                path.iter().for_each(|&v| {
+                    row[*group_column.get(&group_of_node[v]).unwrap()] = self.nodes[v].base;
+                });
+                row
+            })
+            .collect()
+    }
+
+    /// the consensus from the majority vote of each column of the multiple sequence alignment
+    pub fn get_consensus(&self) -> Vec<u8> {
+        let msa = self.get_msa();
+        if msa.is_empty() {
+            return vec![];
+        }
+        (0..msa[0].len())
+            .filter_map(|col| {
+                let mut base_count = FxHashMap::<u8, usize>::default();
+                msa.iter().for_each(|row| {
+                    *base_count.entry(row[col]).or_insert(0) += 1;
+                });
+                let (base, _) = base_count
+                    .into_iter()
+                    .max_by_key(|&(base, count)| (count, base == b'-', base))
+                    .unwrap();
+                if base == b'-' {
+                    None
+                } else {
+                    Some(base)
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PoaGraph;
+
+    #[test]
+    fn test_poa_msa() {
+        let seqs = [
+            b"ACGTACGTTTGACCA".to_vec(),
+            b"ACGTACCTTTGACCA".to_vec(),
+            b"ACGTACGTTTTGACCA".to_vec(),
+            b"ACGTACGTTGACCA".to_vec(),
+        ];
+        let mut poa_graph = PoaGraph::new(2, 4, 4);
+        seqs.iter().for_each(|s| poa_graph.add_seq(s));
+        let msa = poa_graph.get_msa();
+        assert_eq!(msa.len(), seqs.len());
+        msa.iter().zip(seqs.iter()).for_each(|(row, seq)| {
+            assert_eq!(row.len(), msa[0].len());
+            let bases = row
+                .iter()
+                .filter(|&&c| c != b'-')
+                .copied()
+                .collect::<Vec<u8>>();
+            assert_eq!(&bases, seq);
+        });
+        assert_eq!(poa_graph.get_consensus(), b"ACGTACGTTTGACCA".to_vec());
+    }
+}
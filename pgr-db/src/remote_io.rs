@@ -0,0 +1,397 @@
+//! access to the database files (`.mdb`, `.frg`, `.sdx` and `.midx`) on the local disk, or on an HTTP(S)
+//! server or in an S3 bucket when built with the `remote` feature
+//!
+//! the remote files are read with the HTTP range requests in fixed size blocks, the recently used blocks
+//! are kept in memory, and in a local block cache directory if `PGR_REMOTE_CACHE_DIR` is set, so the
+//! centrally hosted pangenome databases can be used without copying them; the AGC files are read with
+//! the AGC library which needs a local file, so a remote AGC file is rejected with an error instead of
+//! being downloaded as a whole, the remote databases need the frg backend
+
+use memmap2::Mmap;
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, Read, Seek};
+
+#[cfg(feature = "remote")]
+use crate::lru_cache::LruCache;
+#[cfg(feature = "remote")]
+use rustc_hash::FxHasher;
+#[cfg(feature = "remote")]
+use std::hash::{Hash, Hasher};
+#[cfg(feature = "remote")]
+use std::io::SeekFrom;
+#[cfg(feature = "remote")]
+use std::path::PathBuf;
+#[cfg(feature = "remote")]
+use std::sync::{Arc, Mutex};
+
+/// the size of the blocks fetched from a remote file, overridden by `PGR_REMOTE_BLOCK_SIZE`
+#[cfg(feature = "remote")]
+pub const DEFAULT_REMOTE_BLOCK_SIZE: usize = 1 << 20;
+/// the max number of the blocks of a remote file kept in memory, overridden by `PGR_REMOTE_CACHE_BLOCKS`
+#[cfg(feature = "remote")]
+pub const DEFAULT_REMOTE_CACHE_BLOCKS: usize = 256;
+#[cfg(feature = "remote")]
+const REMOTE_RETRIES: usize = 3;
+
+pub trait ReadSeek: Read + Seek + Send {}
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+/// check if a path is the URL (`http://`, `https://` or `s3://`) of a remote file
+pub fn is_remote_path(path: &str) -> bool {
+    ["http://", "https://", "s3://"]
+        .iter()
+        .any(|scheme| path.starts_with(scheme))
+}
+
+#[cfg(not(feature = "remote"))]
+fn remote_unsupported(path: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!(
+            "can't open {}, the remote files are only supported with the `remote` feature",
+            path
+        ),
+    )
+}
+
+/// a database file read with the random accesses, memory mapped if it is local
+pub enum DataFile {
+    Local(Mmap),
+    #[cfg(feature = "remote")]
+    Remote(RemoteFile),
+}
+
+impl DataFile {
+    pub fn open(path: &str) -> Result<Self, io::Error> {
+        if is_remote_path(path) {
+            #[cfg(feature = "remote")]
+            return Ok(DataFile::Remote(RemoteFile::open(path)?));
+            #[cfg(not(feature = "remote"))]
+            return Err(remote_unsupported(path));
+        }
+        let file = File::open(path)?;
+        Ok(DataFile::Local(unsafe { Mmap::map(&file)? }))
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            DataFile::Local(mmap) => mmap.len(),
+            #[cfg(feature = "remote")]
+            DataFile::Remote(remote_file) => remote_file.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// read `len` bytes from `offset`, the bytes of a local file are borrowed from the memory map
+    pub fn read_at(&self, offset: usize, len: usize) -> Result<Cow<'_, [u8]>, io::Error> {
+        match self {
+            DataFile::Local(mmap) => mmap
+                .get(offset..offset + len)
+                .map(Cow::Borrowed)
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        format!(
+                            "reading {} bytes from {} beyond the end of the file ({} bytes)",
+                            len,
+                            offset,
+                            mmap.len()
+                        ),
+                    )
+                }),
+            #[cfg(feature = "remote")]
+            DataFile::Remote(remote_file) => remote_file.read_at(offset, len).map(Cow::Owned),
+        }
+    }
+}
+
+/// open a local or remote file for reading it sequentially
+pub fn open_file(path: &str) -> Result<Box<dyn ReadSeek>, io::Error> {
+    if is_remote_path(path) {
+        #[cfg(feature = "remote")]
+        return Ok(Box::new(RemoteReader {
+            file: RemoteFile::open(path)?,
+            pos: 0,
+        }));
+        #[cfg(not(feature = "remote"))]
+        return Err(remote_unsupported(path));
+    }
+    Ok(Box::new(File::open(path)?))
+}
+
+/// the local path of a file for the libraries reading the files by the paths, e.g., the AGC library, a
+/// remote file is rejected as it can't be read with the range requests
+pub fn local_file_path(path: &str) -> Result<String, io::Error> {
+    if is_remote_path(path) {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "can't open {} remotely, the file is read by a library that needs a local file, \
+                 copy it to the local disk or use a database with the frg backend",
+                path
+            ),
+        ));
+    }
+    Ok(path.to_string())
+}
+
+// `s3://bucket/key` is read from `https://bucket.s3.amazonaws.com/key`, or from `<AWS_ENDPOINT_URL>/bucket/key`
+// for the S3 compatible storages, the requests are not signed, so the objects need to be readable publicly
+#[cfg(feature = "remote")]
+pub(crate) fn to_http_url(path: &str) -> String {
+    match path.strip_prefix("s3://") {
+        Some(bucket_key) => match std::env::var("AWS_ENDPOINT_URL") {
+            Ok(endpoint) => format!("{}/{}", endpoint.trim_end_matches('/'), bucket_key),
+            Err(_) => {
+                let (bucket, key) = bucket_key.split_once('/').unwrap_or((bucket_key, ""));
+                format!("https://{}.s3.amazonaws.com/{}", bucket, key)
+            }
+        },
+        None => path.to_string(),
+    }
+}
+
+#[cfg(feature = "remote")]
+fn env_usize(name: &str) -> Option<usize> {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+}
+
+/// a file on an HTTP(S) server or in an S3 bucket read in blocks with the range requests
+#[cfg(feature = "remote")]
+pub struct RemoteFile {
+    url: String,
+    len: usize,
+    block_size: usize,
+    cache_dir: Option<PathBuf>,
+    agent: ureq::Agent,
+    blocks: Mutex<LruCache<usize, Arc<Vec<u8>>>>,
+}
+
+#[cfg(feature = "remote")]
+impl RemoteFile {
+    /// open a remote file, the length of the file is fetched with a range request for its first byte,
+    /// which also checks that the server supports the range requests
+    pub fn open(path: &str) -> Result<Self, io::Error> {
+        let url = to_http_url(path);
+        let agent = ureq::AgentBuilder::new()
+            .timeout(std::time::Duration::from_secs(60))
+            .build();
+        let response = agent
+            .get(&url)
+            .set("Range", "bytes=0-0")
+            .call()
+            .map_err(|e| {
+                io::Error::new(io::ErrorKind::Other, format!("fail to open {}: {}", url, e))
+            })?;
+        let len = response
+            .header("Content-Range")
+            .filter(|_| response.status() == 206)
+            .and_then(|content_range| content_range.rsplit('/').next())
+            .and_then(|len| len.parse::<usize>().ok())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("the server of {} does not support the range requests", url),
+                )
+            })?;
+
+        let block_size = env_usize("PGR_REMOTE_BLOCK_SIZE")
+            .unwrap_or(DEFAULT_REMOTE_BLOCK_SIZE)
+            .max(1);
+        let n_blocks = env_usize("PGR_REMOTE_CACHE_BLOCKS").unwrap_or(DEFAULT_REMOTE_CACHE_BLOCKS);
+        // the blocks of a file are cached in a directory named by the url, the length and the block size,
+        // so a changed remote file or block size does not reuse the stale blocks
+        let cache_dir = match std::env::var("PGR_REMOTE_CACHE_DIR") {
+            Ok(dir) => {
+                let mut hasher = FxHasher::default();
+                (url.as_str(), len, block_size).hash(&mut hasher);
+                let key = hasher.finish();
+                let cache_dir = PathBuf::from(dir).join(format!("{:016x}", key));
+                std::fs::create_dir_all(&cache_dir)?;
+                Some(cache_dir)
+            }
+            Err(_) => None,
+        };
+        Ok(RemoteFile {
+            url,
+            len,
+            block_size,
+            cache_dir,
+            agent,
+            blocks: Mutex::new(LruCache::new(n_blocks)),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn read_at(&self, offset: usize, len: usize) -> Result<Vec<u8>, io::Error> {
+        if offset + len > self.len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!(
+                    "reading {} bytes from {} beyond the end of {} ({} bytes)",
+                    len, offset, self.url, self.len
+                ),
+            ));
+        }
+        let mut buf = Vec::with_capacity(len);
+        let mut pos = offset;
+        while pos < offset + len {
+            let block_id = pos / self.block_size;
+            let block = self.get_block(block_id)?;
+            let block_offset = pos - block_id * self.block_size;
+            let n = (offset + len - pos).min(block.len() - block_offset);
+            buf.extend_from_slice(&block[block_offset..block_offset + n]);
+            pos += n;
+        }
+        Ok(buf)
+    }
+
+    fn get_block(&self, block_id: usize) -> Result<Arc<Vec<u8>>, io::Error> {
+        if let Some(block) = self.blocks.lock().unwrap().get(&block_id) {
+            return Ok(block);
+        }
+        let bgn = block_id * self.block_size;
+        let end = (bgn + self.block_size).min(self.len);
+        let block_path = self
+            .cache_dir
+            .as_ref()
+            .map(|cache_dir| cache_dir.join(format!("{}.blk", block_id)));
+        let cached_block = block_path
+            .as_ref()
+            .and_then(|block_path| std::fs::read(block_path).ok())
+            .filter(|block| block.len() == end - bgn);
+        let block = match cached_block {
+            Some(block) => block,
+            None => {
+                let block = self.fetch_range(bgn, end)?;
+                if let Some(block_path) = block_path {
+                    // written to a temporary file and renamed, so a partially written block is never read
+                    static TMP_FILE_COUNT: std::sync::atomic::AtomicUsize =
+                        std::sync::atomic::AtomicUsize::new(0);
+                    let tmp_file_id =
+                        TMP_FILE_COUNT.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    let tmp_path = block_path.with_extension(format!(
+                        "tmp.{}.{}",
+                        std::process::id(),
+                        tmp_file_id
+                    ));
+                    if std::fs::write(&tmp_path, &block).is_ok() {
+                        let _ = std::fs::rename(&tmp_path, &block_path);
+                    }
+                }
+                block
+            }
+        };
+        let block = Arc::new(block);
+        self.blocks.lock().unwrap().insert(block_id, block.clone());
+        Ok(block)
+    }
+
+    fn fetch_range(&self, bgn: usize, end: usize) -> Result<Vec<u8>, io::Error> {
+        let mut last_error = None;
+        for _ in 0..REMOTE_RETRIES {
+            let response = match self
+                .agent
+                .get(&self.url)
+                .set("Range", &format!("bytes={}-{}", bgn, end - 1))
+                .call()
+            {
+                Ok(response) => response,
+                Err(ureq::Error::Status(code, _)) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Other,
+                        format!(
+                            "fail to fetch the bytes {}-{} of {}, HTTP status {}",
+                            bgn, end, self.url, code
+                        ),
+                    ))
+                }
+                Err(e) => {
+                    last_error = Some(io::Error::new(
+                        io::ErrorKind::Other,
+                        format!(
+                            "fail to fetch the bytes {}-{} of {}: {}",
+                            bgn, end, self.url, e
+                        ),
+                    ));
+                    continue;
+                }
+            };
+            let mut buf = Vec::with_capacity(end - bgn);
+            match response
+                .into_reader()
+                .take((end - bgn) as u64)
+                .read_to_end(&mut buf)
+            {
+                Ok(n) if n == end - bgn => return Ok(buf),
+                Ok(n) => {
+                    last_error = Some(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        format!(
+                            "only {} of the bytes {}-{} of {} are fetched",
+                            n, bgn, end, self.url
+                        ),
+                    ))
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap())
+    }
+}
+
+// a remote file read sequentially through its block cache
+#[cfg(feature = "remote")]
+struct RemoteReader {
+    file: RemoteFile,
+    pos: usize,
+}
+
+#[cfg(feature = "remote")]
+impl Read for RemoteReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // not crossing the block boundary, so a read takes one block from the cache at most
+        let block_end = (self.pos / self.file.block_size + 1) * self.file.block_size;
+        let n = buf
+            .len()
+            .min(block_end.min(self.file.len).saturating_sub(self.pos));
+        if n == 0 {
+            return Ok(0);
+        }
+        buf[..n].copy_from_slice(&self.file.read_at(self.pos, n)?);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(feature = "remote")]
+impl Seek for RemoteReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => pos as i64,
+            SeekFrom::End(offset) => self.file.len as i64 + offset,
+            SeekFrom::Current(offset) => self.pos as i64 + offset,
+        };
+        if pos < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seeking before the start of the file",
+            ));
+        }
+        self.pos = pos as usize;
+        Ok(self.pos as u64)
+    }
+}
@@ -1,32 +1,35 @@
 #[cfg(feature = "with_agc")]
 use crate::agc_io::AGCFile;
-use crate::fasta_io::{reverse_complement, FastaReader, SeqRec};
+use crate::fasta_io::{reverse_complement, FastaReader, SeqRec, SeqRecChunks};
 use crate::graph_utils::{AdjList, AdjPair, ShmmrGraphNode};
-use crate::shmmrutils::{match_reads, sequence_to_shmmrs, DeltaPoint, ShmmrSpec, MM128};
+use crate::packed_seq::PackedSeq;
+use crate::remote_io::{self, DataFile};
+use crate::shmmrutils::{
+    match_reads, merge_intervals, sequence_to_shmmrs, sequence_to_shmmrs_masked, DeltaPoint,
+    ShmmrSpec, ShmmrTieBreak, MM128,
+};
 use bincode::{config, Decode, Encode};
 use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
 use flate2::bufread::MultiGzDecoder;
 use flate2::write::DeflateEncoder;
 use flate2::Compression;
-use memmap2::Mmap;
 use petgraph::graphmap::DiGraphMap;
 use petgraph::visit::Dfs;
 use petgraph::EdgeDirection::{Incoming, Outgoing};
 use rayon::prelude::*;
 use rustc_hash::{FxHashMap, FxHashSet};
 
+use std::borrow::Cow;
 use std::fmt;
 use std::fs::File;
-use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 
 pub const KMERSIZE: u32 = 56;
-pub const SHMMRSPEC: ShmmrSpec = ShmmrSpec {
-    w: 80,
-    k: KMERSIZE,
-    r: 4,
-    min_span: 64,
-    sketch: true,
-};
+pub const SHMMRSPEC: ShmmrSpec = ShmmrSpec::new(80, KMERSIZE, 4, 64, true);
+
+// the max numbers of the records and the bases of a batch of the sequences processed in parallel in the loading
+const SEQ_BATCH_SIZE: usize = 128;
+const SEQ_BATCH_MAX_BASES: usize = 1 << 30;
 
 pub type Bases = Vec<u8>;
 pub type AlnSegments = (u32, bool, u32, Vec<AlnSegment>); //(refFragID, orientation, SeqLength, AlnSegments)
@@ -52,6 +55,52 @@ pub enum Fragment {
     Prefix(Bases),
     Internal(Bases),
     Suffix(Bases),
+    // the 2-bit packed prefix, internal and suffix fragments
+    PackedPrefix(PackedSeq),
+    PackedInternal(PackedSeq),
+    PackedSuffix(PackedSeq),
+}
+
+impl Fragment {
+    /// the 2-bit packed fragment of a prefix, internal or suffix fragment
+    pub fn pack(self) -> Self {
+        match self {
+            Fragment::Prefix(b) => Fragment::PackedPrefix(PackedSeq::new(&b)),
+            Fragment::Internal(b) => Fragment::PackedInternal(PackedSeq::new(&b)),
+            Fragment::Suffix(b) => Fragment::PackedSuffix(PackedSeq::new(&b)),
+            f => f,
+        }
+    }
+
+    /// the unpacked fragment of a 2-bit packed fragment
+    pub fn unpack(self) -> Self {
+        match self {
+            Fragment::PackedPrefix(b) => Fragment::Prefix(b.to_seq()),
+            Fragment::PackedInternal(b) => Fragment::Internal(b.to_seq()),
+            Fragment::PackedSuffix(b) => Fragment::Suffix(b.to_seq()),
+            f => f,
+        }
+    }
+
+    /// the bases of an internal fragment, used as the base sequence of the aligned fragments
+    pub fn internal_bases(&self) -> Option<Cow<'_, Bases>> {
+        match self {
+            Fragment::Internal(b) => Some(Cow::Borrowed(b)),
+            Fragment::PackedInternal(b) => Some(Cow::Owned(b.to_seq())),
+            _ => None,
+        }
+    }
+
+    /// the length of the sequence added by the fragment, excluding the k-mer overlapping the previous fragment
+    pub fn seq_len(&self, k: u32) -> u32 {
+        match self {
+            Fragment::AlnSegments(d) => d.2 - k,
+            Fragment::Prefix(b) | Fragment::Suffix(b) => b.len() as u32,
+            Fragment::Internal(b) => b.len() as u32 - k,
+            Fragment::PackedPrefix(b) | Fragment::PackedSuffix(b) => b.len() as u32,
+            Fragment::PackedInternal(b) => b.len() as u32 - k,
+        }
+    }
 }
 
 impl fmt::Display for Fragment {
@@ -65,6 +114,13 @@ impl fmt::Display for Fragment {
             Fragment::Prefix(b) => write!(f, "Seq:{} AlignSegs:None", String::from_utf8_lossy(b)),
             Fragment::Internal(b) => write!(f, "Seq:{} AlignSegs:None", String::from_utf8_lossy(b)),
             Fragment::Suffix(b) => write!(f, "Seq:{} AlignSegs:None", String::from_utf8_lossy(b)),
+            Fragment::PackedPrefix(b) | Fragment::PackedInternal(b) | Fragment::PackedSuffix(b) => {
+                write!(
+                    f,
+                    "Seq:{} AlignSegs:None",
+                    String::from_utf8_lossy(&b.to_seq())
+                )
+            }
         }
     }
 }
@@ -97,6 +153,24 @@ pub struct CompactSeqDB {
     pub seqs: Vec<CompactSeq>,
     pub frag_map: ShmmrToFrags,
     pub frags: Option<Fragments>,
+    /// store the prefix, internal and suffix fragments in the 2-bit packed representation
+    pub pack_frags: bool,
+    /// the sorted and merged intervals `[bgn, end)` of the sequences (by the sequence names) skipped in the indexing
+    pub masked_intervals: FxHashMap<String, Vec<(u32, u32)>>,
+}
+
+/// the (sid, source, name, seq) tuples of a batch of the records, the sequence ids start from `sid`
+fn seq_vec_from_recs(
+    recs: Vec<SeqRec>,
+    sid: &mut u32,
+) -> Vec<(u32, Option<String>, String, Vec<u8>)> {
+    recs.into_iter()
+        .map(|rec| {
+            let seqname = String::from_utf8_lossy(&rec.id).into_owned();
+            *sid += 1;
+            (*sid - 1, rec.source, seqname, rec.seq)
+        })
+        .collect()
 }
 
 pub fn pair_shmmrs(shmmrs: &Vec<MM128>) -> Vec<(&MM128, &MM128)> {
@@ -183,9 +257,34 @@ impl CompactSeqDB {
             seqs,
             frag_map,
             frags,
+            pack_frags: false,
+            masked_intervals: FxHashMap::default(),
+        }
+    }
+
+    /// store the prefix, internal and suffix fragments in the 2-bit packed representation (about 1/4 of the
+    /// memory of the raw bases), the fragments already loaded are converted too
+    pub fn set_pack_frags(&mut self, pack_frags: bool) {
+        self.pack_frags = pack_frags;
+        if let Some(frags) = self.frags.take() {
+            self.frags = Some(
+                frags
+                    .into_par_iter()
+                    .map(|f| if pack_frags { f.pack() } else { f.unpack() })
+                    .collect(),
+            );
         }
     }
 
+    /// set the intervals `[bgn, end)` of the sequences (by the sequence names) to skip in the indexing, e.g.,
+    /// the known rDNA arrays, the shimmers overlapping the intervals are not indexed in the later loading
+    pub fn set_masked_intervals(&mut self, masked_intervals: FxHashMap<String, Vec<(u32, u32)>>) {
+        self.masked_intervals = masked_intervals
+            .into_iter()
+            .map(|(seq_name, intervals)| (seq_name, merge_intervals(intervals)))
+            .collect();
+    }
+
     pub fn seq_to_compressed(
         &mut self,
         source: Option<String>,
@@ -202,16 +301,18 @@ impl CompactSeqDB {
 
         let mut frg_id = frags.len() as u32;
         let mut seq_len = 0_usize;
+        let pack_frags = self.pack_frags;
+        let new_frag = |frg: Fragment| if pack_frags { frg.pack() } else { frg };
 
         //assert!(shmmrs.len() > 0);
         if shmmrs.is_empty() {
             let frg = seq[..].to_vec();
-            frags.push(Fragment::Prefix(frg));
+            frags.push(new_frag(Fragment::Prefix(frg)));
             seq_frags.push(frg_id);
             // frg_id += 1;
 
             let frg = Vec::<u8>::new();
-            frags.push(Fragment::Suffix(frg));
+            frags.push(new_frag(Fragment::Suffix(frg)));
             seq_frags.push(frg_id);
 
             return CompactSeq {
@@ -226,7 +327,7 @@ impl CompactSeqDB {
         let end = (shmmrs[0].pos() + 1) as usize;
         let frg = seq[..end].to_vec();
         seq_len += frg.len();
-        frags.push(Fragment::Prefix(frg));
+        frags.push(new_frag(Fragment::Prefix(frg)));
         seq_frags.push(frg_id);
         frg_id += 1;
 
@@ -250,8 +351,8 @@ impl CompactSeqDB {
                     let e = self.frag_map.get(&shmmr_pair).unwrap();
                     for t_frg_id in e.iter() {
                         let base_frg = frags.get(t_frg_id.0 as usize).unwrap();
-                        if let Fragment::Internal(b) = base_frg {
-                            let base_frg = b;
+                        if let Some(base_frg) = base_frg.internal_bases() {
+                            let base_frg = &*base_frg;
                             //assert!(base_frg.len() > KMERSIZE as usize);
                             let frg;
                             let rc;
@@ -332,7 +433,7 @@ impl CompactSeqDB {
                 let e = self.frag_map.get_mut(shmmr).unwrap();
                 e.push((frg_id, id, *bgn, *end, *orientation));
                 seq_len += (*end - *bgn) as usize;
-                frags.push(frg.clone());
+                frags.push(new_frag(frg.clone()));
                 seq_frags.push(frg_id);
                 frg_id += 1;
             }
@@ -343,7 +444,7 @@ impl CompactSeqDB {
         let bgn = (shmmrs[shmmrs.len() - 1].pos() + 1) as usize;
         let frg = seq[bgn..].to_vec();
         seq_len += frg.len();
-        frags.push(Fragment::Suffix(frg));
+        frags.push(new_frag(Fragment::Suffix(frg)));
         seq_frags.push(frg_id);
 
         assert_eq!(seq_len, seq.len());
@@ -463,8 +564,17 @@ impl CompactSeqDB {
     ) -> Vec<(u32, Vec<MM128>)> {
         let all_shmmrs = seqs
             .par_iter()
-            .map(|(sid, _, _, seq)| {
-                let shmmrs = sequence_to_shmmrs(*sid, seq, &self.shmmr_spec, false);
+            .map(|(sid, _, seq_name, seq)| {
+                let shmmrs = match self.masked_intervals.get(seq_name) {
+                    Some(masked_intervals) => sequence_to_shmmrs_masked(
+                        *sid,
+                        seq,
+                        &self.shmmr_spec,
+                        false,
+                        masked_intervals,
+                    ),
+                    None => sequence_to_shmmrs(*sid, seq, &self.shmmr_spec, false),
+                };
                 //let shmmrs = sequence_to_shmmrs2(*sid, &seq, 80, KMERSIZE, 4);
                 (*sid, shmmrs)
             })
@@ -473,39 +583,15 @@ impl CompactSeqDB {
     }
 
     fn load_seq_from_reader(&mut self, reader: &mut dyn Iterator<Item = io::Result<SeqRec>>) {
-        let mut seqs = <Vec<(u32, Option<String>, String, Vec<u8>)>>::new();
         let mut sid = self.seqs.len() as u32;
         if self.frags.is_none() {
             self.frags = Some(Fragments::new());
         };
 
-        loop {
-            let mut count = 0;
-            let mut end_ext_loop = false;
-            seqs.clear();
-
-            loop {
-                if let Some(rec) = reader.next() {
-                    let rec = rec.unwrap();
-                    let source = rec.source.clone();
-                    let seqname = String::from_utf8_lossy(&rec.id).into_owned();
-                    seqs.push((sid, source, seqname, rec.seq));
-                    sid += 1;
-                } else {
-                    end_ext_loop = true;
-                    break;
-                }
-                count += 1;
-                if count > 128 {
-                    break;
-                }
-            }
-
+        SeqRecChunks::new(reader, SEQ_BATCH_SIZE, SEQ_BATCH_MAX_BASES).for_each(|recs| {
+            let seqs = seq_vec_from_recs(recs.unwrap(), &mut sid);
             self.load_seqs_from_seq_vec(&seqs);
-            if end_ext_loop {
-                break;
-            }
-        }
+        });
     }
 
     pub fn load_seqs_from_seq_vec(&mut self, seqs: &Vec<(u32, Option<String>, String, Vec<u8>)>) {
@@ -546,39 +632,54 @@ impl CompactSeqDB {
         Ok(())
     }
 
-    fn load_index_from_reader(&mut self, reader: &mut dyn Iterator<Item = io::Result<SeqRec>>) {
-        let mut seqs = <Vec<(u32, Option<String>, String, Vec<u8>)>>::new();
-        let mut sid = 0;
-        loop {
-            let mut count = 0;
-            let mut end_ext_loop = false;
-            seqs.clear();
-
-            loop {
-                if let Some(rec) = reader.next() {
-                    let rec = rec.unwrap();
-                    let source = rec.source;
-                    let seqname = String::from_utf8_lossy(&rec.id).into_owned();
-                    seqs.push((sid, source, seqname, rec.seq));
-                    sid += 1;
-                } else {
-                    end_ext_loop = true;
-                    break;
-                }
-                count += 1;
-                if count > 128 {
-                    break;
-                }
+    /// load the sequences from a fastx file with the source set to `source` rather than the file path
+    pub fn load_seqs_from_fastx_with_source(
+        &mut self,
+        filepath: String,
+        source: String,
+        to_upper_case: bool,
+    ) -> Result<(), std::io::Error> {
+        let set_source = |r: io::Result<SeqRec>| {
+            r.map(|mut r| {
+                r.source = Some(source.clone());
+                r
+            })
+        };
+        match self.get_fastx_reader(filepath, to_upper_case)? {
+            GZFastaReader::GZFile(reader) => {
+                self.load_seq_from_reader(&mut reader.map(set_source))
             }
 
-            self.load_index_from_seq_vec(&seqs);
-            if end_ext_loop {
-                break;
+            GZFastaReader::RegularFile(reader) => {
+                self.load_seq_from_reader(&mut reader.map(set_source))
             }
-        }
+        };
+
+        Ok(())
+    }
+
+    fn load_index_from_reader(&mut self, reader: &mut dyn Iterator<Item = io::Result<SeqRec>>) {
+        // the sequence ids continue from the sequences already indexed
+        let mut sid = self.seqs.len() as u32;
+        SeqRecChunks::new(reader, SEQ_BATCH_SIZE, SEQ_BATCH_MAX_BASES).for_each(|recs| {
+            let seqs = seq_vec_from_recs(recs.unwrap(), &mut sid);
+            self.load_index_from_seq_vec(&seqs);
+        });
     }
 
     pub fn load_index_from_seq_vec(&mut self, seqs: &Vec<(u32, Option<String>, String, Vec<u8>)>) {
+        self.load_index_from_seq_vec_capped(seqs, None);
+    }
+
+    /// load the index of the sequences, the shimmer pairs with more than `max_frag_count` fragments (e.g., from
+    /// the centromeric satellites) are dropped from the index when the cap is given, the fragment counts of the
+    /// dropped shimmer pairs are accumulated in the map and the fragments of a dropped shimmer pair are not
+    /// indexed in the later batches
+    pub fn load_index_from_seq_vec_capped(
+        &mut self,
+        seqs: &Vec<(u32, Option<String>, String, Vec<u8>)>,
+        mut frag_cap: Option<(usize, &mut FxHashMap<ShmmrPair, usize>)>,
+    ) {
         let all_shmmrs = self.get_shmmrs_from_seqs(seqs);
         let seq_names = seqs
             .iter()
@@ -615,8 +716,21 @@ impl CompactSeqDB {
                     .iter()
                     .zip(cs.seq_frag_range.0..cs.seq_frag_range.0 + cs.seq_frag_range.1)
                     .for_each(|((shmmr, bgn, end, orientation), frg_id)| {
-                        let e = self.frag_map.entry(*shmmr).or_default();
-                        e.push((frg_id, sid, *bgn, *end, *orientation));
+                        if let Some((max_frag_count, dropped)) = frag_cap.as_mut() {
+                            if let Some(count) = dropped.get_mut(shmmr) {
+                                *count += 1;
+                                return;
+                            }
+                            let e = self.frag_map.entry(*shmmr).or_default();
+                            e.push((frg_id, sid, *bgn, *end, *orientation));
+                            if e.len() > *max_frag_count {
+                                dropped.insert(*shmmr, e.len());
+                                self.frag_map.remove(shmmr);
+                            }
+                        } else {
+                            let e = self.frag_map.entry(*shmmr).or_default();
+                            e.push((frg_id, sid, *bgn, *end, *orientation));
+                        }
                     });
                 self.seqs.push(cs);
             });
@@ -627,30 +741,9 @@ impl CompactSeqDB {
         reader: &mut dyn Iterator<Item = io::Result<SeqRec>>,
         writer: &mut Vec<u8>,
     ) {
-        let mut seqs = <Vec<(u32, Option<String>, String, Vec<u8>)>>::new();
         let mut sid = 0;
-        loop {
-            let mut count = 0;
-            let mut end_ext_loop = false;
-            seqs.clear();
-
-            loop {
-                if let Some(rec) = reader.next() {
-                    let rec = rec.unwrap();
-                    let source = rec.source;
-                    let seqname = String::from_utf8_lossy(&rec.id).into_owned();
-                    seqs.push((sid, source, seqname, rec.seq));
-                    sid += 1;
-                } else {
-                    end_ext_loop = true;
-                    break;
-                }
-                count += 1;
-                if count > 128 {
-                    break;
-                }
-            }
-
+        SeqRecChunks::new(reader, SEQ_BATCH_SIZE, SEQ_BATCH_MAX_BASES).for_each(|recs| {
+            let seqs = seq_vec_from_recs(recs.unwrap(), &mut sid);
             self.get_shmmrs_from_seqs(&seqs)
                 .iter()
                 .map(|(_, v)| v)
@@ -660,11 +753,7 @@ impl CompactSeqDB {
                         let _ = writer.write_u64::<LittleEndian>(m.y);
                     });
                 });
-
-            if end_ext_loop {
-                break;
-            }
-        }
+        });
     }
 
     pub fn load_index_from_fastx(
@@ -684,6 +773,32 @@ impl CompactSeqDB {
 
         Ok(())
     }
+
+    /// index the sequences from a fastx file with the source set to `source` rather than the file path, the
+    /// sequence ids continue from the sequences already indexed, so the files of multiple samples can be indexed
+    pub fn load_index_from_fastx_with_source(
+        &mut self,
+        filepath: String,
+        source: String,
+        to_upper_case: bool,
+    ) -> Result<(), std::io::Error> {
+        let set_source = |r: io::Result<SeqRec>| {
+            r.map(|mut r| {
+                r.source = Some(source.clone());
+                r
+            })
+        };
+        match self.get_fastx_reader(filepath, to_upper_case)? {
+            GZFastaReader::GZFile(reader) => {
+                self.load_index_from_reader(&mut reader.map(set_source))
+            }
+            GZFastaReader::RegularFile(reader) => {
+                self.load_index_from_reader(&mut reader.map(set_source))
+            }
+        };
+
+        Ok(())
+    }
     #[cfg(feature = "with_agc")]
     pub fn load_index_from_agcfile(&mut self, agcfile: AGCFile) -> Result<(), std::io::Error> {
         //let agcfile = AGCFile::new(filepath);
@@ -691,6 +806,55 @@ impl CompactSeqDB {
         self.load_index_from_reader(&mut agcfile.into_iter());
         Ok(())
     }
+
+    /// load the index from an AGC file with the sequences decoded in a separate thread, the decoded
+    /// sequences are sent in chunks through a bounded channel of `channel_size` chunks so the decoding
+    /// overlaps with the shimmer indexing, `progress` is called after each chunk is indexed; only
+    /// the sequences of the `samples` are indexed if it is specified
+    #[cfg(feature = "with_agc")]
+    pub fn load_index_from_agcfile_pipelined<F>(
+        &mut self,
+        agcfile: &AGCFile,
+        samples: Option<&FxHashSet<String>>,
+        channel_size: usize,
+        mut frag_cap: Option<(usize, &mut FxHashMap<ShmmrPair, usize>)>,
+        mut progress: F,
+    ) -> Result<(), std::io::Error>
+    where
+        F: FnMut(&[(u32, Option<String>, String, Vec<u8>)]),
+    {
+        let (sender, receiver) = std::sync::mpsc::sync_channel(channel_size);
+        // the sequence ids continue from the sequences already loaded
+        let sid_offset = self.seqs.len() as u32;
+        std::thread::scope(|scope| {
+            let reader = scope.spawn(move || -> Result<(), std::io::Error> {
+                let mut sid = sid_offset;
+                let recs = agcfile.into_iter().filter(|rec| match (rec, samples) {
+                    (Ok(rec), Some(samples)) => rec
+                        .source
+                        .as_ref()
+                        .is_some_and(|source| samples.contains(source)),
+                    _ => true,
+                });
+                for recs in SeqRecChunks::new(recs, SEQ_BATCH_SIZE, SEQ_BATCH_MAX_BASES) {
+                    let seqs = seq_vec_from_recs(recs?, &mut sid);
+                    if sender.send(seqs).is_err() {
+                        // the receiver is gone
+                        return Ok(());
+                    }
+                }
+                Ok(())
+            });
+            receiver.iter().for_each(|seqs| {
+                let frag_cap = frag_cap
+                    .as_mut()
+                    .map(|(max_frag_count, dropped)| (*max_frag_count, &mut **dropped));
+                self.load_index_from_seq_vec_capped(&seqs, frag_cap);
+                progress(&seqs[..]);
+            });
+            reader.join().expect("the AGC reader thread panicked")
+        })
+    }
 }
 
 impl CompactSeqDB {
@@ -716,9 +880,16 @@ impl CompactSeqDB {
                     //println!("I p: {} {} {}", frag_id, _p, _p + b.len()-self.shmmr_spec.k as usize);
                     //_p += b.len()-self.shmmr_spec.k as usize;
                 }
+                Fragment::PackedPrefix(b) | Fragment::PackedSuffix(b) => {
+                    reconstructed_seq.extend_from_slice(&b.to_seq());
+                }
+                Fragment::PackedInternal(b) => {
+                    reconstructed_seq
+                        .extend_from_slice(&b.sub_seq(self.shmmr_spec.k as usize, b.len()));
+                }
                 Fragment::AlnSegments((frg_id, reversed, _length, a)) => {
-                    if let Fragment::Internal(base_seq) = frags.get(*frg_id as usize).unwrap() {
-                        let mut seq = reconstruct_seq_from_aln_segs(base_seq, a);
+                    if let Some(base_seq) = frags.get(*frg_id as usize).unwrap().internal_bases() {
+                        let mut seq = reconstruct_seq_from_aln_segs(&base_seq, a);
                         /*  // for debugging
                         if *_length as usize != seq.len() {
                             println!("DBG X: {:?} {:?}", String::from_utf8_lossy(base_seq), a);
@@ -771,13 +942,7 @@ impl GetSeq for CompactSeqDB {
         let mut sub_seq_frag = vec![];
         let frags: &Vec<Fragment> = self.frags.as_ref().unwrap();
         for frag_id in frag_range.0..frag_range.0 + frag_range.1 {
-            let f = &frags[frag_id as usize];
-            let frag_len = match f {
-                Fragment::AlnSegments(d) => d.2 - self.shmmr_spec.k,
-                Fragment::Prefix(b) => b.len() as u32,
-                Fragment::Internal(b) => b.len() as u32 - self.shmmr_spec.k,
-                Fragment::Suffix(b) => b.len() as u32,
-            };
+            let frag_len = frags[frag_id as usize].seq_len(self.shmmr_spec.k);
             if (base_offset <= bgn && bgn < base_offset + frag_len)
                 || (base_offset <= end && end < base_offset + frag_len)
                 || (bgn <= base_offset && base_offset + frag_len <= end)
@@ -849,15 +1014,12 @@ impl CompactSeqDB {
             .map(|&frags| {
                 let mut total_frag_len = 0_u32;
                 frags.iter().for_each(|f| {
-                    total_frag_len += match f {
-                        Fragment::AlnSegments(d) => d.2 - self.shmmr_spec.k,
-                        Fragment::Prefix(b) => b.len() as u32,
-                        Fragment::Internal(b) => b.len() as u32 - self.shmmr_spec.k,
-                        Fragment::Suffix(b) => b.len() as u32,
-                    };
+                    total_frag_len += f.seq_len(self.shmmr_spec.k);
                 });
 
-                let w = bincode::encode_to_vec(frags.to_vec(), config).unwrap();
+                // the frg files always keep the unpacked fragments
+                let frags = frags.iter().map(|f| f.clone().unpack()).collect::<Vec<_>>();
+                let w = bincode::encode_to_vec(frags, config).unwrap();
                 let mut compressor = DeflateEncoder::new(Vec::new(), Compression::default());
                 compressor.write_all(&w).unwrap();
                 let compress_frag = compressor.finish().unwrap();
@@ -1239,7 +1401,7 @@ pub fn raw_query_fragment(
 
 pub fn raw_query_fragment_from_mmap_midx(
     frag_map_location: &ShmmrToIndexFileLocation,
-    frag_map_mmap_file: &Mmap,
+    frag_map_mmap_file: &DataFile,
     query_frag: &Vec<u8>,
     shmmr_spec: &ShmmrSpec,
 ) -> Vec<FragmentHit> {
@@ -1269,7 +1431,7 @@ pub fn raw_query_fragment_from_mmap_midx(
 pub fn get_shmmr_matches_from_mmap_file(
     frag_map_location: &ShmmrToIndexFileLocation,
     (s0, s1): ShmmrPair,
-    frag_map_mmap_file: &Mmap,
+    frag_map_mmap_file: &DataFile,
 ) -> Vec<(u32, u32, u32, u32, u8)> {
     if let Some(&(start, vec_len)) = frag_map_location.get(&(s0, s1)) {
         get_fragment_signatures_from_mmap_file(frag_map_mmap_file, start, vec_len)
@@ -1313,7 +1475,29 @@ pub fn write_shmmr_map_file(
     buf.write_u32::<LittleEndian>(shmmr_spec.k)?;
     buf.write_u32::<LittleEndian>(shmmr_spec.r)?;
     buf.write_u32::<LittleEndian>(shmmr_spec.min_span)?;
-    buf.write_u32::<LittleEndian>(shmmr_spec.sketch as u32)?;
+    // bit 0: sketch k-mers, bit 1: randstrobes, bit 2: spaced seed, bit 3: hash seed, bit 4: tie-breaking policy,
+    // the spaced seed mask, the hash seed and the tie-breaking policy (and its seed) follow the flag if they are set
+    let flag = shmmr_spec.sketch as u32
+        | (shmmr_spec.randstrobe as u32) << 1
+        | (shmmr_spec.seed_mask.is_some() as u32) << 2
+        | (shmmr_spec.hash_seed.is_some() as u32) << 3
+        | ((shmmr_spec.tie_break != ShmmrTieBreak::All) as u32) << 4;
+    buf.write_u32::<LittleEndian>(flag)?;
+    if let Some(seed_mask) = shmmr_spec.seed_mask {
+        buf.write_u64::<LittleEndian>(seed_mask)?;
+    }
+    if let Some(hash_seed) = shmmr_spec.hash_seed {
+        buf.write_u64::<LittleEndian>(hash_seed)?;
+    }
+    match shmmr_spec.tie_break {
+        ShmmrTieBreak::All => (),
+        ShmmrTieBreak::Lexicographic => buf.write_u64::<LittleEndian>(1)?,
+        ShmmrTieBreak::Position => buf.write_u64::<LittleEndian>(2)?,
+        ShmmrTieBreak::Random(seed) => {
+            buf.write_u64::<LittleEndian>(3)?;
+            buf.write_u64::<LittleEndian>(seed)?;
+        }
+    }
 
     buf.write_u64::<LittleEndian>(shmmr_map.len() as u64)?;
     shmmr_map
@@ -1335,6 +1519,59 @@ pub fn write_shmmr_map_file(
     Ok(())
 }
 
+/// read the intervals to skip in the indexing from a BED file (the sequence name, the 0-based begin and the end
+/// in the first three columns), the header, track and browser lines are ignored
+pub fn read_masked_intervals_from_bed(
+    filepath: &str,
+) -> Result<FxHashMap<String, Vec<(u32, u32)>>, io::Error> {
+    let mut masked_intervals = FxHashMap::<String, Vec<(u32, u32)>>::default();
+    let reader = BufReader::new(File::open(filepath)?);
+    for line in reader.lines() {
+        let line = line?;
+        if line.is_empty()
+            || line.starts_with('#')
+            || line.starts_with("track")
+            || line.starts_with("browser")
+        {
+            continue;
+        }
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("fail to parse the BED line: {}", line),
+            )
+        };
+        let mut fields = line.split('\t');
+        let seq_name = fields.next().ok_or_else(invalid)?;
+        let mut parse_pos = || -> Result<u32, io::Error> {
+            fields
+                .next()
+                .and_then(|field| field.trim().parse::<u32>().ok())
+                .ok_or_else(invalid)
+        };
+        let bgn = parse_pos()?;
+        let end = parse_pos()?;
+        masked_intervals
+            .entry(seq_name.to_string())
+            .or_default()
+            .push((bgn, end));
+    }
+    Ok(masked_intervals)
+}
+
+// the tie-breaking policy from its code in the mdb header
+fn tie_break_from_code(policy: u64, seed: u64) -> Result<ShmmrTieBreak, io::Error> {
+    match policy {
+        1 => Ok(ShmmrTieBreak::Lexicographic),
+        2 => Ok(ShmmrTieBreak::Position),
+        3 => Ok(ShmmrTieBreak::Random(seed)),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unknown tie-breaking policy in the mdb header",
+        )),
+    }
+}
+
 pub fn read_mdb_file(filepath: String) -> Result<(ShmmrSpec, ShmmrToFrags), io::Error> {
     let mut in_file =
         File::open(filepath).expect("Error while opening the SHIMMER map file (.mdb) file");
@@ -1358,6 +1595,35 @@ pub fn read_mdb_file(filepath: String) -> Result<(ShmmrSpec, ShmmrToFrags), io::
     let flag = LittleEndian::read_u32(&buf[cursor..cursor + 4]);
     cursor += 4;
     let sketch = (flag & 0b01) == 0b01;
+    let randstrobe = (flag & 0b10) == 0b10;
+    let seed_mask = if (flag & 0b100) == 0b100 {
+        let seed_mask = LittleEndian::read_u64(&buf[cursor..cursor + 8]);
+        cursor += 8;
+        Some(seed_mask)
+    } else {
+        None
+    };
+    let hash_seed = if (flag & 0b1000) == 0b1000 {
+        let hash_seed = LittleEndian::read_u64(&buf[cursor..cursor + 8]);
+        cursor += 8;
+        Some(hash_seed)
+    } else {
+        None
+    };
+    let tie_break = if (flag & 0b10000) == 0b10000 {
+        let policy = LittleEndian::read_u64(&buf[cursor..cursor + 8]);
+        cursor += 8;
+        let seed = if policy == 3 {
+            let seed = LittleEndian::read_u64(&buf[cursor..cursor + 8]);
+            cursor += 8;
+            seed
+        } else {
+            0
+        };
+        tie_break_from_code(policy, seed)?
+    } else {
+        ShmmrTieBreak::All
+    };
 
     let shmmr_spec = ShmmrSpec {
         w,
@@ -1365,6 +1631,10 @@ pub fn read_mdb_file(filepath: String) -> Result<(ShmmrSpec, ShmmrToFrags), io::
         r,
         min_span,
         sketch,
+        randstrobe,
+        seed_mask,
+        hash_seed,
+        tie_break,
     };
     u64bytes.clone_from_slice(&buf[cursor..cursor + 8]);
     let shmmr_key_len = usize::from_le_bytes(u64bytes);
@@ -1419,8 +1689,15 @@ pub fn read_mdb_file(filepath: String) -> Result<(ShmmrSpec, ShmmrToFrags), io::
 pub fn read_mdb_file_to_frag_locations(
     filepath: String,
 ) -> Result<(ShmmrSpec, ShmmrIndexFileLocation), io::Error> {
-    let mut in_file =
-        File::open(filepath).expect("open fail while reading the SHIMMER map (.mdb) file");
+    let mut in_file = remote_io::open_file(&filepath).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!(
+                "fail to open the SHIMMER map (.mdb) file {}: {}",
+                filepath, e
+            ),
+        )
+    })?;
     let mut tag_buf = [0_u8; 3];
 
     let mut u32bytes = [0_u8; 4];
@@ -1446,15 +1723,50 @@ pub fn read_mdb_file_to_frag_locations(
     in_file.read_exact(&mut u32bytes)?;
     let flag = LittleEndian::read_u32(&u32bytes);
     let sketch = (flag & 0b01) == 0b01;
+    let randstrobe = (flag & 0b10) == 0b10;
 
     cursor += 4 * 5;
 
+    let seed_mask = if (flag & 0b100) == 0b100 {
+        in_file.read_exact(&mut u64bytes)?;
+        cursor += 8;
+        Some(LittleEndian::read_u64(&u64bytes))
+    } else {
+        None
+    };
+    let hash_seed = if (flag & 0b1000) == 0b1000 {
+        in_file.read_exact(&mut u64bytes)?;
+        cursor += 8;
+        Some(LittleEndian::read_u64(&u64bytes))
+    } else {
+        None
+    };
+    let tie_break = if (flag & 0b10000) == 0b10000 {
+        in_file.read_exact(&mut u64bytes)?;
+        cursor += 8;
+        let policy = LittleEndian::read_u64(&u64bytes);
+        let seed = if policy == 3 {
+            in_file.read_exact(&mut u64bytes)?;
+            cursor += 8;
+            LittleEndian::read_u64(&u64bytes)
+        } else {
+            0
+        };
+        tie_break_from_code(policy, seed)?
+    } else {
+        ShmmrTieBreak::All
+    };
+
     let shmmr_spec = ShmmrSpec {
         w,
         k,
         r,
         min_span,
         sketch,
+        randstrobe,
+        seed_mask,
+        hash_seed,
+        tie_break,
     };
 
     in_file.read_exact(&mut u64bytes)?;
@@ -1481,32 +1793,35 @@ pub fn read_mdb_file_to_frag_locations(
 }
 
 pub fn get_fragment_signatures_from_mmap_file(
-    frag_map_file: &Mmap,
+    frag_map_file: &DataFile,
     start: usize,
     vec_len: usize,
 ) -> Vec<FragmentSignature> {
-    let mut cursor = start;
+    let frag_signatures = frag_map_file
+        .read_at(start, 17 * vec_len)
+        .expect("fail to read the fragment signatures from the SHIMMER map (.mdb) file");
+    let mut cursor = 0;
     (0..vec_len)
         .map(|_| {
             let mut u32bytes = [0_u8; 4];
             let mut v = (0_u32, 0_u32, 0_u32, 0_u32, 0_u8);
-            u32bytes.clone_from_slice(&frag_map_file[cursor..cursor + 4]);
+            u32bytes.clone_from_slice(&frag_signatures[cursor..cursor + 4]);
             v.0 = u32::from_le_bytes(u32bytes);
             cursor += 4;
 
-            u32bytes.clone_from_slice(&frag_map_file[cursor..cursor + 4]);
+            u32bytes.clone_from_slice(&frag_signatures[cursor..cursor + 4]);
             v.1 = u32::from_le_bytes(u32bytes);
             cursor += 4;
 
-            u32bytes.clone_from_slice(&frag_map_file[cursor..cursor + 4]);
+            u32bytes.clone_from_slice(&frag_signatures[cursor..cursor + 4]);
             v.2 = u32::from_le_bytes(u32bytes);
             cursor += 4;
 
-            u32bytes.clone_from_slice(&frag_map_file[cursor..cursor + 4]);
+            u32bytes.clone_from_slice(&frag_signatures[cursor..cursor + 4]);
             v.3 = u32::from_le_bytes(u32bytes);
             cursor += 4;
 
-            v.4 = frag_map_file[cursor..cursor + 1][0];
+            v.4 = frag_signatures[cursor];
             cursor += 1;
             v
         })
@@ -1514,11 +1829,8 @@ pub fn get_fragment_signatures_from_mmap_file(
 }
 
 pub fn read_mdb_file_parallel(filepath: String) -> Result<(ShmmrSpec, ShmmrToFrags), io::Error> {
-    let in_file =
-        File::open(filepath.clone()).expect("open fail while reading the SHIMMER map (.mdb) file");
-    let frag_map_file = unsafe {
-        Mmap::map(&in_file).expect("open fail while reading the SHIMMER map (.mdb) file")
-    };
+    let frag_map_file =
+        DataFile::open(&filepath).expect("open fail while reading the SHIMMER map (.mdb) file");
 
     let (shmmr_spec, rec_loc) = read_mdb_file_to_frag_locations(filepath)?;
 
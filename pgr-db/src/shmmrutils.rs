@@ -3,6 +3,7 @@
 use bincode::{Decode, Encode};
 use rustc_hash::FxHashMap;
 use std::fmt;
+use std::io;
 
 #[derive(Clone, Debug)]
 pub struct OvlpMatch {
@@ -24,6 +25,118 @@ pub struct ShmmrSpec {
     pub r: u32,
     pub min_span: u32,
     pub sketch: bool,
+    pub randstrobe: bool,
+    /// the spaced seed mask of the k-mers, the bit `k - 1 - i` is for the i-th base of a k-mer and the bases
+    /// with the bits unset are ignored in the hashes, it has to be palindromic to get the same anchors from both strands
+    pub seed_mask: Option<u64>,
+    /// the seed of the k-mer hashes for the decorrelated replicate sketches, `None` for the default `KMER_HASH_SEED`
+    pub hash_seed: Option<u64>,
+    /// how the minimizers with the same hash in a window are selected
+    pub tie_break: ShmmrTieBreak,
+}
+
+impl ShmmrSpec {
+    /// the spec with the default hashes (no spaced seed, the default hash seed), all the minimizers with the same
+    /// hash kept and without the randstrobes, the other fields can be set with the struct update syntax
+    pub const fn new(w: u32, k: u32, r: u32, min_span: u32, sketch: bool) -> Self {
+        ShmmrSpec {
+            w,
+            k,
+            r,
+            min_span,
+            sketch,
+            randstrobe: false,
+            seed_mask: None,
+            hash_seed: None,
+            tie_break: ShmmrTieBreak::All,
+        }
+    }
+}
+
+/// the policies to select the minimizers with the same hash in a window (e.g., the repeated k-mers
+/// of a low-complexity sequence), keeping all of them gives the same anchors from both strands but
+/// more anchors in the repeats, the other policies keep one of them
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Decode, Encode)]
+pub enum ShmmrTieBreak {
+    /// keep all the minimizers with the same hash
+    #[default]
+    All,
+    /// keep the one with the smallest (strand, position)
+    Lexicographic,
+    /// keep the leftmost one
+    Position,
+    /// keep the one with the smallest hash of the position mixed with the seed
+    Random(u64),
+}
+
+impl ShmmrTieBreak {
+    // the key for ordering the minimizers with the same hash
+    #[inline(always)]
+    fn tie_key(&self, m: &MM128) -> u64 {
+        match self {
+            ShmmrTieBreak::All => 0,
+            ShmmrTieBreak::Lexicographic => (m.y & 0b1) << 32 | m.pos() as u64,
+            ShmmrTieBreak::Position => m.pos() as u64,
+            ShmmrTieBreak::Random(seed) => u64hash(m.pos() as u64 ^ seed),
+        }
+    }
+
+    /// if the shimmer `m` is selected over the current minimum `min_mer` of a window
+    #[inline(always)]
+    pub fn is_selected(&self, m: &MM128, min_mer: &MM128) -> bool {
+        match self {
+            ShmmrTieBreak::All => m.x <= min_mer.x,
+            _ => (m.x, self.tie_key(m)) < (min_mer.x, self.tie_key(min_mer)),
+        }
+    }
+}
+
+/// parse a tie-breaking policy name, "all", "lexicographic", "position" or "random", the seed is
+/// for the "random" policy
+pub fn tie_break_from_str(policy: &str, seed: u64) -> Result<ShmmrTieBreak, io::Error> {
+    match policy {
+        "all" => Ok(ShmmrTieBreak::All),
+        "lexicographic" => Ok(ShmmrTieBreak::Lexicographic),
+        "position" => Ok(ShmmrTieBreak::Position),
+        "random" => Ok(ShmmrTieBreak::Random(seed)),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unknown tie-breaking policy: {}", policy),
+        )),
+    }
+}
+
+/// parse a spaced seed pattern, e.g., "1101101011", the i-th character is for the i-th base of a k-mer, "0" for
+/// the don't-care positions, the pattern should be palindromic, as long as the k-mer and starting and ending with "1"
+pub fn seed_mask_from_str(pattern: &str, k: u32) -> Result<u64, io::Error> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidInput, message.to_string());
+    let pattern = pattern.as_bytes();
+    if k > MAX_PACKED_KMER_SIZE {
+        return Err(invalid(
+            "the spaced seed is only supported for the k-mer size up to 56",
+        ));
+    }
+    if pattern.len() != k as usize {
+        return Err(invalid(
+            "the length of the spaced seed pattern is not the k-mer size",
+        ));
+    }
+    if pattern.iter().any(|&c| c != b'0' && c != b'1') {
+        return Err(invalid(
+            "the spaced seed pattern should only contain '0' or '1'",
+        ));
+    }
+    if pattern.iter().ne(pattern.iter().rev()) {
+        return Err(invalid("the spaced seed pattern is not palindromic"));
+    }
+    if pattern[0] != b'1' {
+        return Err(invalid(
+            "the spaced seed pattern should start and end with '1'",
+        ));
+    }
+    Ok(pattern
+        .iter()
+        .fold(0_u64, |mask, &c| mask << 1 | (c - b'0') as u64))
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -317,17 +430,19 @@ impl RingBuffer {
     }
 
     pub fn push(&mut self, m: MM128) {
+        // wrap the positions without the integer division, it is in the hot loop of the shimmer generation
+        self.v[self.end_pos] = m;
+        self.end_pos += 1;
+        if self.end_pos == self.size {
+            self.end_pos = 0;
+        }
         if self.len < self.size {
-            self.v[self.end_pos] = m;
-            self.end_pos += 1;
-            self.end_pos %= self.size;
             self.len += 1;
         } else {
-            self.v[self.end_pos] = m;
-            self.end_pos += 1;
-            self.end_pos %= self.size;
             self.start_pos += 1;
-            self.start_pos %= self.size;
+            if self.start_pos == self.size {
+                self.start_pos = 0;
+            }
         }
     }
 
@@ -351,12 +466,45 @@ impl RingBuffer {
         min
     }
 
+    /// the window index and the minimizer selected with the tie-breaking policy other than `ShmmrTieBreak::All`
+    pub fn get_min_with_tie_break(&self, tie_break: &ShmmrTieBreak) -> (usize, MM128) {
+        let mut min = (
+            0,
+            MM128 {
+                x: u64::MAX,
+                y: u64::MAX,
+            },
+        );
+        for i in 0..self.size {
+            let mm = self.get(i);
+            if tie_break.is_selected(&mm, &min.1) {
+                min = (i, mm);
+            }
+        }
+        min
+    }
+
     pub fn get(&self, i: usize) -> MM128 {
-        self.v[(self.start_pos + i) % self.size]
+        let i = self.start_pos + i;
+        if i < self.size {
+            self.v[i]
+        } else {
+            self.v[i - self.size]
+        }
     }
 }
 
 pub fn reduce_shmmr(mers: Vec<MM128>, r: u32, padding: bool) -> Vec<MM128> {
+    reduce_shmmr_with_tie_break(mers, r, padding, &ShmmrTieBreak::All)
+}
+
+/// reduce the shimmers by the factor `r`, the shimmers with the same hash in a window are selected with `tie_break`
+pub fn reduce_shmmr_with_tie_break(
+    mers: Vec<MM128>,
+    r: u32,
+    padding: bool,
+    tie_break: &ShmmrTieBreak,
+) -> Vec<MM128> {
     let mut shmmrs = Vec::<MM128>::new();
     let mut rbuf = RingBuffer::new(r as usize);
     let mut min_mer = MM128 {
@@ -388,20 +536,25 @@ pub fn reduce_shmmr(mers: Vec<MM128>, r: u32, padding: bool) -> Vec<MM128> {
         let m = mers[pos];
         rbuf.push(m);
         if mdist == (r - 1) as usize {
-            min_mer = rbuf.get_min();
             let mut last_i = 0_usize;
-            for i in 0..rbuf.size {
-                let mm = rbuf.get(i);
-                if mm.x == min_mer.x {
-                    shmmrs.push(mm);
-                    min_mer = mm;
-                    last_i = i;
+            if *tie_break == ShmmrTieBreak::All {
+                min_mer = rbuf.get_min();
+                for i in 0..rbuf.size {
+                    let mm = rbuf.get(i);
+                    if mm.x == min_mer.x {
+                        shmmrs.push(mm);
+                        min_mer = mm;
+                        last_i = i;
+                    }
                 }
+            } else {
+                (last_i, min_mer) = rbuf.get_min_with_tie_break(tie_break);
+                shmmrs.push(min_mer);
             }
             mdist = r as usize - 1 - last_i;
             pos += 1;
             continue;
-        } else if m.x <= min_mer.x && pos >= r as usize {
+        } else if tie_break.is_selected(&m, &min_mer) && pos >= r as usize {
             shmmrs.push(m);
             min_mer = m;
             mdist = 0;
@@ -414,15 +567,138 @@ pub fn reduce_shmmr(mers: Vec<MM128>, r: u32, padding: bool) -> Vec<MM128> {
     shmmrs
 }
 
-pub fn sequence_to_shmmrs1(
-    rid: u32,
-    seq: &Vec<u8>,
-    w: u32,
+pub const KMER_HASH_SEED: u64 = 0xAD12CF59;
+/// the largest k-mer size packed into the 2-bit planes, the longer k-mers are only hashed with a rolling hash
+pub const MAX_PACKED_KMER_SIZE: u32 = 56;
+/// the largest k-mer size supported with the rolling hash
+pub const MAX_KMER_SIZE: u32 = 256;
+// the (odd) base of the polynomial rolling hash of the long k-mers and the random values of A, C, G, T
+const ROLLING_HASH_BASE: u64 = 0x9e3779b97f4a7c15;
+const ROLLING_HASH_BASE_VALUES: [u64; 4] = [
+    0x3c8bfbb395c60474,
+    0x3193c18562a02b4c,
+    0x20323ed082572324,
+    0x295549f54be24456,
+];
+/// the number of the bases scanned for the k-mers hashed together in a batch
+pub const KMER_HASH_BATCH_SIZE: usize = 1024;
+
+#[inline(always)]
+fn kmer_planes_hash(plane0: u64, plane1: u64, seed: u64) -> u64 {
+    u64hash(plane0) ^ u64hash(plane1 ^ seed)
+}
+
+pub fn hash_kmer_planes_scalar(planes0: &[u64], planes1: &[u64], seed: u64, hashes: &mut [u64]) {
+    hashes
+        .iter_mut()
+        .zip(planes0.iter().zip(planes1.iter()))
+        .for_each(|(hash, (&plane0, &plane1))| *hash = kmer_planes_hash(plane0, plane1, seed));
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn u64hash_avx2(key: std::arch::x86_64::__m256i) -> std::arch::x86_64::__m256i {
+    use std::arch::x86_64::*;
+    let mut key = _mm256_add_epi64(
+        _mm256_xor_si256(key, _mm256_set1_epi64x(-1)),
+        _mm256_slli_epi64::<21>(key),
+    );
+    key = _mm256_xor_si256(key, _mm256_srli_epi64::<24>(key));
+    key = _mm256_add_epi64(
+        _mm256_add_epi64(key, _mm256_slli_epi64::<3>(key)),
+        _mm256_slli_epi64::<8>(key),
+    );
+    key = _mm256_xor_si256(key, _mm256_srli_epi64::<14>(key));
+    key = _mm256_add_epi64(
+        _mm256_add_epi64(key, _mm256_slli_epi64::<2>(key)),
+        _mm256_slli_epi64::<4>(key),
+    );
+    key = _mm256_xor_si256(key, _mm256_srli_epi64::<28>(key));
+    _mm256_add_epi64(key, _mm256_slli_epi64::<31>(key))
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn hash_kmer_planes_avx2(planes0: &[u64], planes1: &[u64], seed: u64, hashes: &mut [u64]) {
+    use std::arch::x86_64::*;
+    let n = hashes.len() / 4 * 4;
+    let seed_lanes = _mm256_set1_epi64x(seed as i64);
+    for i in (0..n).step_by(4) {
+        let plane0 = _mm256_loadu_si256(planes0.as_ptr().add(i) as *const __m256i);
+        let plane1 = _mm256_loadu_si256(planes1.as_ptr().add(i) as *const __m256i);
+        let hash = _mm256_xor_si256(
+            u64hash_avx2(plane0),
+            u64hash_avx2(_mm256_xor_si256(plane1, seed_lanes)),
+        );
+        _mm256_storeu_si256(hashes.as_mut_ptr().add(i) as *mut __m256i, hash);
+    }
+    hash_kmer_planes_scalar(&planes0[n..], &planes1[n..], seed, &mut hashes[n..]);
+}
+
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+unsafe fn u64hash_neon(key: std::arch::aarch64::uint64x2_t) -> std::arch::aarch64::uint64x2_t {
+    use std::arch::aarch64::*;
+    let mut key = vaddq_u64(
+        veorq_u64(key, vdupq_n_u64(u64::MAX)),
+        vshlq_n_u64::<21>(key),
+    );
+    key = veorq_u64(key, vshrq_n_u64::<24>(key));
+    key = vaddq_u64(vaddq_u64(key, vshlq_n_u64::<3>(key)), vshlq_n_u64::<8>(key));
+    key = veorq_u64(key, vshrq_n_u64::<14>(key));
+    key = vaddq_u64(vaddq_u64(key, vshlq_n_u64::<2>(key)), vshlq_n_u64::<4>(key));
+    key = veorq_u64(key, vshrq_n_u64::<28>(key));
+    vaddq_u64(key, vshlq_n_u64::<31>(key))
+}
+
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+unsafe fn hash_kmer_planes_neon(planes0: &[u64], planes1: &[u64], seed: u64, hashes: &mut [u64]) {
+    use std::arch::aarch64::*;
+    let n = hashes.len() / 2 * 2;
+    let seed_lanes = vdupq_n_u64(seed);
+    for i in (0..n).step_by(2) {
+        let plane0 = vld1q_u64(planes0.as_ptr().add(i));
+        let plane1 = vld1q_u64(planes1.as_ptr().add(i));
+        let hash = veorq_u64(
+            u64hash_neon(plane0),
+            u64hash_neon(veorq_u64(plane1, seed_lanes)),
+        );
+        vst1q_u64(hashes.as_mut_ptr().add(i), hash);
+    }
+    hash_kmer_planes_scalar(&planes0[n..], &planes1[n..], seed, &mut hashes[n..]);
+}
+
+/// hash the k-mers from their 2-bit planes, `hashes[i] = u64hash(planes0[i]) ^ u64hash(planes1[i] ^ seed)`,
+/// using AVX2 (x86_64, detected at runtime) or NEON (aarch64) and the scalar hashing otherwise
+pub fn hash_kmer_planes(planes0: &[u64], planes1: &[u64], seed: u64, hashes: &mut [u64]) {
+    assert!(planes0.len() == hashes.len() && planes1.len() == hashes.len());
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") {
+        unsafe { hash_kmer_planes_avx2(planes0, planes1, seed, hashes) };
+        return;
+    }
+    #[cfg(target_arch = "aarch64")]
+    if std::arch::is_aarch64_feature_detected!("neon") {
+        unsafe { hash_kmer_planes_neon(planes0, planes1, seed, hashes) };
+        return;
+    }
+    hash_kmer_planes_scalar(planes0, planes1, seed, hashes);
+}
+
+/// call `f(pos, hash, strand)` for the canonical k-mer ending at each position of the sequence,
+/// the 2-bit planes of the k-mers are rolled over the sequence and hashed in batches with `hash_kmer_planes`,
+/// only the bases in the spaced seed mask are used for choosing the strand and for the hashes,
+/// the k-mers longer than `MAX_PACKED_KMER_SIZE` are hashed with `for_each_long_kmer_hash`
+pub fn for_each_kmer_hash<F>(
+    seq: &[u8],
     k: u32,
-    r: u32,
-    min_span: u32,
-    padding: bool,
-) -> Vec<MM128> {
+    seed_mask: Option<u64>,
+    hash_seed: Option<u64>,
+    mut f: F,
+) where
+    F: FnMut(usize, u64, u64),
+{
     let base2bits: [u64; 256] = [
         0, 1, 2, 3, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4,
         4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4,
@@ -435,85 +711,185 @@ pub fn sequence_to_shmmrs1(
         4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4,
     ];
 
-    let mut shmmrs = Vec::<MM128>::new();
-
-    let mut pos = 0;
-    let mut mdist = 0;
+    if k > MAX_PACKED_KMER_SIZE {
+        assert!(seed_mask.is_none(), "no spaced seed support for k > 56");
+        for_each_long_kmer_hash(seq, k, hash_seed, f);
+        return;
+    }
     let shift = k - 1;
-    assert!(k <= 56);
-    assert!(w <= 128);
-    assert!(r > 0 && r < 13);
     let mut fmmer = (0_u64, 0_u64);
     let mut rmmer = (0_u64, 0_u64);
     let mask = u64::MAX >> (64 - k);
-    let mut rbuf = RingBuffer::new(w as usize);
-    let mut min_mer = MM128 {
-        x: u64::MAX,
-        y: u64::MAX,
-    };
-    loop {
-        if pos >= seq.len() {
-            break;
+    let seed_mask = seed_mask.unwrap_or(mask);
+    let hash_seed = hash_seed.unwrap_or(KMER_HASH_SEED);
+    // the k-mers of a batch, (the position << 1 | the strand) and the 2-bit planes
+    let mut pos_strands = vec![0_u64; KMER_HASH_BATCH_SIZE];
+    let mut planes0 = vec![0_u64; KMER_HASH_BATCH_SIZE];
+    let mut planes1 = vec![0_u64; KMER_HASH_BATCH_SIZE];
+    let mut hashes = vec![0_u64; KMER_HASH_BATCH_SIZE];
+    for (chunk_id, chunk) in seq.chunks(KMER_HASH_BATCH_SIZE).enumerate() {
+        let mut n = 0_usize;
+        for (i, &base) in chunk.iter().enumerate() {
+            let pos = chunk_id * KMER_HASH_BATCH_SIZE + i;
+            let c = base2bits[base as usize];
+            if c < 4 {
+                fmmer.0 <<= 1;
+                fmmer.0 |= c & 0b01;
+                fmmer.0 &= mask;
+                fmmer.1 <<= 1;
+                fmmer.1 |= (c & 0b10) >> 1;
+                fmmer.1 &= mask;
+
+                let rc = 0x3 ^ c;
+                rmmer.0 >>= 1;
+                rmmer.0 |= (rc & 0b01) << shift;
+                rmmer.0 &= mask;
+                rmmer.1 >>= 1;
+                rmmer.1 |= ((rc & 0b10) >> 1) << shift;
+                rmmer.1 &= mask;
+            }
+            let fseed = (fmmer.0 & seed_mask, fmmer.1 & seed_mask);
+            let rseed = (rmmer.0 & seed_mask, rmmer.1 & seed_mask);
+            if fseed == rseed || pos < k as usize {
+                continue;
+            }
+            let (mmer, strand) = if rseed.0 < fseed.0 {
+                (rseed, 1)
+            } else {
+                (fseed, 0)
+            };
+            pos_strands[n] = (pos as u64) << 1 | strand;
+            planes0[n] = mmer.0;
+            planes1[n] = mmer.1;
+            n += 1;
         }
+        hash_kmer_planes(&planes0[..n], &planes1[..n], hash_seed, &mut hashes[..n]);
+        pos_strands[..n]
+            .iter()
+            .zip(hashes[..n].iter())
+            .for_each(|(&pos_strand, &hash)| f((pos_strand >> 1) as usize, hash, pos_strand & 0b1));
+    }
+}
 
-        let c = base2bits[seq[pos] as usize];
-        // println!("C {} {} {}", seq[pos], pos, c);
+/// call `f(pos, hash, strand)` for the canonical k-mers longer than `MAX_PACKED_KMER_SIZE` (up to `MAX_KMER_SIZE`),
+/// the k-mers are not packed, both strands are hashed with a polynomial rolling hash (mod 2^64) and the smaller
+/// one is mixed with the hash seed
+pub fn for_each_long_kmer_hash<F>(seq: &[u8], k: u32, hash_seed: Option<u64>, mut f: F)
+where
+    F: FnMut(usize, u64, u64),
+{
+    assert!(k > 0 && k <= MAX_KMER_SIZE);
+    let hash_seed = hash_seed.unwrap_or(KMER_HASH_SEED);
+    let v = |c: usize| ROLLING_HASH_BASE_VALUES[c];
+    let base_k = ROLLING_HASH_BASE.wrapping_pow(k);
+    let base_k1 = ROLLING_HASH_BASE.wrapping_pow(k - 1);
+    // the inverse of the odd base mod 2^64 with the Newton iterations
+    let base_inv = (0..6).fold(ROLLING_HASH_BASE, |inv, _| {
+        inv.wrapping_mul(2_u64.wrapping_sub(ROLLING_HASH_BASE.wrapping_mul(inv)))
+    });
+    // the last k bases for removing the leaving base from the rolling hashes
+    let mut bases = vec![0_usize; k as usize];
+    let mut n_bases = 0_usize;
+    let mut fhash = 0_u64;
+    let mut rhash = 0_u64;
+    let mut rbase_pow = 1_u64;
+    for (pos, &base) in seq.iter().enumerate() {
+        let c = match base {
+            b'A' | b'a' => 0,
+            b'C' | b'c' => 1,
+            b'G' | b'g' => 2,
+            b'T' | b't' => 3,
+            _ => 4,
+        };
         if c < 4 {
-            fmmer.0 <<= 1;
-            fmmer.0 |= c & 0b01;
-            fmmer.0 &= mask;
-            fmmer.1 <<= 1;
-            fmmer.1 |= (c & 0b10) >> 1;
-            fmmer.1 &= mask;
-
-            let rc = 0x3 ^ c;
-            rmmer.0 >>= 1;
-            rmmer.0 |= (rc & 0b01) << shift;
-            rmmer.0 &= mask;
-            rmmer.1 >>= 1;
-            rmmer.1 |= ((rc & 0b10) >> 1) << shift;
-            rmmer.1 &= mask;
-        }
-        if fmmer == rmmer {
-            pos += 1;
-            continue;
+            let slot = n_bases % k as usize;
+            fhash = fhash.wrapping_mul(ROLLING_HASH_BASE).wrapping_add(v(c));
+            if n_bases < k as usize {
+                rhash = rhash.wrapping_add(v(3 - c).wrapping_mul(rbase_pow));
+                rbase_pow = rbase_pow.wrapping_mul(ROLLING_HASH_BASE);
+            } else {
+                let out = bases[slot];
+                fhash = fhash.wrapping_sub(v(out).wrapping_mul(base_k));
+                rhash = rhash
+                    .wrapping_sub(v(3 - out))
+                    .wrapping_mul(base_inv)
+                    .wrapping_add(v(3 - c).wrapping_mul(base_k1));
+            }
+            bases[slot] = c;
+            n_bases += 1;
         }
-        if pos < k as usize {
-            pos += 1;
+        if n_bases < k as usize || pos < k as usize || fhash == rhash {
             continue;
         }
-        let mut forward = true;
-        if rmmer.0 < fmmer.0 {
-            forward = false;
-        }
-
-        let mmer_hash = match forward {
-            true => u64hash(fmmer.0) ^ u64hash(fmmer.1 ^ 0xAD12CF59),
-            false => u64hash(rmmer.0) ^ u64hash(rmmer.1 ^ 0xAD12CF59),
-            //true => u64hash(fmmer.0) ^ u64hash(fmmer.1) ^ 0x0,
-            //false => u64hash(rmmer.0) ^ u64hash(rmmer.1) ^ 0x0,
+        let (kmer_hash, strand) = if rhash < fhash {
+            (rhash, 1)
+        } else {
+            (fhash, 0)
         };
-        let strand: u64 = if forward { 0 } else { 1 };
+        f(pos, u64hash(kmer_hash ^ hash_seed), strand);
+    }
+}
+
+pub fn sequence_to_shmmrs1(
+    rid: u32,
+    seq: &Vec<u8>,
+    w: u32,
+    k: u32,
+    r: u32,
+    min_span: u32,
+    padding: bool,
+) -> Vec<MM128> {
+    let spec = ShmmrSpec::new(w, k, r, min_span, false);
+    sequence_to_minimizer_shmmrs(rid, seq, &spec, padding)
+}
+
+/// the shimmers from the minimizers, the k-mers are hashed with the spaced seed mask and the hash seed of the spec
+pub fn sequence_to_minimizer_shmmrs(
+    rid: u32,
+    seq: &Vec<u8>,
+    spec: &ShmmrSpec,
+    padding: bool,
+) -> Vec<MM128> {
+    let (w, k, r, min_span) = (spec.w, spec.k, spec.r, spec.min_span);
+    let (seed_mask, hash_seed) = (spec.seed_mask, spec.hash_seed);
+    let tie_break = spec.tie_break;
+    // the span saturates for the k-mers longer than 255 bases
+    let span = k.min(u8::MAX as u32) as u64;
+    let mut shmmrs = Vec::<MM128>::new();
+
+    let mut mdist = 0;
+    assert!(k <= MAX_KMER_SIZE);
+    assert!(w <= 128);
+    assert!(r > 0 && r < 13);
+    let mut rbuf = RingBuffer::new(w as usize);
+    let mut min_mer = MM128 {
+        x: u64::MAX,
+        y: u64::MAX,
+    };
+    for_each_kmer_hash(seq, k, seed_mask, hash_seed, |pos, mmer_hash, strand| {
         let m = MM128 {
-            x: mmer_hash << 8 | k as u64,
+            x: mmer_hash << 8 | span,
             y: (rid as u64) << 32 | (pos as u64) << 1 | strand,
         };
         rbuf.push(m);
         //println!("mdist: {}", mdist);
         if mdist == (w - 1) as usize {
-            min_mer = rbuf.get_min();
-            for i in 0..rbuf.size {
-                let mm = rbuf.get(i);
-                if mm.x == min_mer.x {
-                    shmmrs.push(mm);
-                    min_mer = mm;
-                    //println!("dgb1: {} {}", pos, mm.x >> 8);
+            if tie_break == ShmmrTieBreak::All {
+                min_mer = rbuf.get_min();
+                for i in 0..rbuf.size {
+                    let mm = rbuf.get(i);
+                    if mm.x == min_mer.x {
+                        shmmrs.push(mm);
+                        min_mer = mm;
+                        //println!("dgb1: {} {}", pos, mm.x >> 8);
+                    }
                 }
+            } else {
+                (_, min_mer) = rbuf.get_min_with_tie_break(&tie_break);
+                shmmrs.push(min_mer);
             }
             mdist = pos - ((min_mer.y & 0xFFFFFFFF) >> 1) as usize;
-            pos += 1;
-            continue;
-        } else if m.x <= min_mer.x
+        } else if tie_break.is_selected(&m, &min_mer)
             && pos >= (w + k) as usize
             && pos < seq.len() - w as usize + k as usize
             && pos < seq.len()
@@ -522,16 +898,19 @@ pub fn sequence_to_shmmrs1(
             //println!("dbg0: {} {}", pos, m.x >> 8);
             min_mer = m;
             mdist = 0;
-            pos += 1;
-            continue;
+        } else {
+            mdist += 1;
         }
-        mdist += 1;
-        pos += 1;
-    }
+    });
 
     //let mut shmmrs = shmmrs;
     if r > 1 {
-        shmmrs = reduce_shmmr(reduce_shmmr(shmmrs, r, padding), r, padding);
+        shmmrs = reduce_shmmr_with_tie_break(
+            reduce_shmmr_with_tie_break(shmmrs, r, padding, &tie_break),
+            r,
+            padding,
+            &tie_break,
+        );
     };
     let mut shmmrs2 = Vec::<MM128>::new();
     shmmrs
@@ -556,78 +935,29 @@ pub fn sequence_to_shmmrs1(
 }
 
 pub fn sequence_to_shmmrs2(rid: u32, seq: &Vec<u8>, k: u32, r: u32, min_span: u32) -> Vec<MM128> {
-    let base2bits: [u64; 256] = [
-        0, 1, 2, 3, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4,
-        4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4,
-        4, 4, 4, 4, 4, 0, 4, 1, 4, 4, 4, 2, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 3, 4, 4, 4, 4, 4,
-        4, 4, 4, 4, 4, 4, 4, 0, 4, 1, 4, 4, 4, 2, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 3, 4, 4, 4,
-        4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4,
-        4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4,
-        4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4,
-        4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4,
-        4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4,
-    ];
+    let spec = ShmmrSpec::new(0, k, r, min_span, true);
+    sequence_to_sketch_shmmrs(rid, seq, &spec)
+}
 
+/// the sketch k-mers, the k-mers are hashed with the spaced seed mask and the hash seed of the spec
+pub fn sequence_to_sketch_shmmrs(rid: u32, seq: &Vec<u8>, spec: &ShmmrSpec) -> Vec<MM128> {
+    let (k, r, min_span) = (spec.k, spec.r, spec.min_span);
+    let (seed_mask, hash_seed) = (spec.seed_mask, spec.hash_seed);
+    // the span saturates for the k-mers longer than 255 bases
+    let span = k.min(u8::MAX as u32) as u64;
     let mut shmmrs = Vec::<MM128>::new();
 
-    let mut pos = 0;
-    let shift = k - 1;
-    assert!(k <= 56);
+    assert!(k <= MAX_KMER_SIZE);
     assert!(r > 0 && r < 13);
-    let mut fmmer = (0_u64, 0_u64);
-    let mut rmmer = (0_u64, 0_u64);
-    let mask = u64::MAX >> (64 - k);
-    loop {
-        if pos >= seq.len() {
-            break;
-        }
-
-        let c = base2bits[seq[pos] as usize];
-        // println!("C {} {} {}", seq[pos], pos, c);
-        if c < 4 {
-            fmmer.0 <<= 1;
-            fmmer.0 |= c & 0b01;
-            fmmer.0 &= mask;
-            fmmer.1 <<= 1;
-            fmmer.1 |= (c & 0b10) >> 1;
-            fmmer.1 &= mask;
-
-            let rc = 0x3 ^ c;
-            rmmer.0 >>= 1;
-            rmmer.0 |= (rc & 0b01) << shift;
-            rmmer.0 &= mask;
-            rmmer.1 >>= 1;
-            rmmer.1 |= ((rc & 0b10) >> 1) << shift;
-            rmmer.1 &= mask;
-        }
-        if fmmer == rmmer {
-            pos += 1;
-            continue;
-        }
-        if pos < k as usize {
-            pos += 1;
-            continue;
-        }
-        let mut forward = true;
-        if rmmer.0 < fmmer.0 {
-            forward = false;
-        }
-
-        let mmer_hash = match forward {
-            true => u64hash(fmmer.0) ^ u64hash(fmmer.1 ^ 0xAD12CF59),
-            false => u64hash(rmmer.0) ^ u64hash(rmmer.1 ^ 0xAD12CF59),
-        };
-
+    for_each_kmer_hash(seq, k, seed_mask, hash_seed, |pos, mmer_hash, strand| {
         if mmer_hash < u64::MAX >> 4 >> r {
-            let strand: u64 = if forward { 0 } else { 1 };
             let m = MM128 {
-                x: mmer_hash << 8 | k as u64,
+                x: mmer_hash << 8 | span,
                 y: (rid as u64) << 32 | (pos as u64) << 1 | strand,
             };
             shmmrs.push(m);
         }
-        pos += 1;
-    }
+    });
 
     let mut shmmrs2 = Vec::<MM128>::new();
     shmmrs
@@ -654,16 +984,171 @@ pub fn sequence_to_shmmrs2(rid: u32, seq: &Vec<u8>, k: u32, r: u32, min_span: u3
     shmmrs2
 }
 
+/// the number of the neighboring shimmers on each side considered for the second strobe
+pub const RANDSTROBE_WINDOW: usize = 4;
+
+fn randstrobe_partner<'a>(mers: impl Iterator<Item = &'a MM128>, h1: u64) -> Option<u64> {
+    // the first one with the minimum linking value is the closest one, so the choice
+    // is the same when the sequence is reverse complemented
+    mers.map(|m| m.hash()).min_by_key(|&h2| h1 ^ h2)
+}
+
+/// order-2 randstrobes linking each shimmer (the first strobe) to a second strobe picked
+/// from the next `RANDSTROBE_WINDOW` shimmers by the minimum of `h1 ^ h2`
+///
+/// the second strobe can be any of the shimmers in the window, so a strobe survives the indels
+/// or the mismatches that remove the shimmers between the two strobes. To get the same anchors
+/// from both strands, the strobe is built in both directions and the smaller hash is used.
+/// The position, the strand and the span of the first strobe are kept in the anchors
+pub fn sequence_to_randstrobes(
+    rid: u32,
+    seq: &Vec<u8>,
+    spec: &ShmmrSpec,
+    padding: bool,
+) -> Vec<MM128> {
+    let shmmrs = sequence_to_minimizer_shmmrs(rid, seq, spec, padding);
+    let mut strobes = Vec::<MM128>::with_capacity(shmmrs.len());
+    shmmrs.iter().enumerate().for_each(|(i, shmmr)| {
+        let h1 = shmmr.hash();
+        let downstream = randstrobe_partner(shmmrs[i + 1..].iter().take(RANDSTROBE_WINDOW), h1);
+        let upstream = randstrobe_partner(shmmrs[..i].iter().rev().take(RANDSTROBE_WINDOW), h1);
+        let strobe_hash = [downstream, upstream]
+            .into_iter()
+            .flatten()
+            .map(|h2| ((h1 >> 1).wrapping_add(h2 / 3)) & (u64::MAX >> 8))
+            .min()
+            .unwrap_or(h1);
+        let m = MM128 {
+            x: strobe_hash << 8 | (shmmr.x & 0xFF),
+            y: shmmr.y,
+        };
+        if strobes.last().map(|last| last.x) != Some(m.x) {
+            strobes.push(m);
+        }
+    });
+    strobes
+}
+
 pub fn sequence_to_shmmrs(
     rid: u32,
     seq: &Vec<u8>,
     shmmrspec: &ShmmrSpec,
     padding: bool,
 ) -> Vec<MM128> {
-    let (w, k, r, min_span) = (shmmrspec.w, shmmrspec.k, shmmrspec.r, shmmrspec.min_span);
-    if !shmmrspec.sketch {
-        sequence_to_shmmrs1(rid, seq, w, k, r, min_span, padding)
+    if shmmrspec.randstrobe {
+        sequence_to_randstrobes(rid, seq, shmmrspec, padding)
+    } else if !shmmrspec.sketch {
+        sequence_to_minimizer_shmmrs(rid, seq, shmmrspec, padding)
     } else {
-        sequence_to_shmmrs2(rid, seq, k, r, min_span)
+        sequence_to_sketch_shmmrs(rid, seq, shmmrspec)
     }
 }
+
+/// sort the intervals `[bgn, end)` and merge the overlapping ones, the empty intervals are dropped
+pub fn merge_intervals(mut intervals: Vec<(u32, u32)>) -> Vec<(u32, u32)> {
+    intervals.retain(|&(bgn, end)| bgn < end);
+    intervals.sort_unstable();
+    let mut merged = Vec::<(u32, u32)>::with_capacity(intervals.len());
+    intervals
+        .into_iter()
+        .for_each(|(bgn, end)| match merged.last_mut() {
+            Some(last) if bgn <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((bgn, end)),
+        });
+    merged
+}
+
+/// the shimmers of a sequence without the ones whose k-mers overlap the masked intervals `[bgn, end)`
+/// (e.g., the known rDNA arrays), the intervals need to be sorted and merged with `merge_intervals`
+pub fn sequence_to_shmmrs_masked(
+    rid: u32,
+    seq: &Vec<u8>,
+    shmmrspec: &ShmmrSpec,
+    padding: bool,
+    masked_intervals: &[(u32, u32)],
+) -> Vec<MM128> {
+    let shmmrs = sequence_to_shmmrs(rid, seq, shmmrspec, padding);
+    if masked_intervals.is_empty() {
+        return shmmrs;
+    }
+    shmmrs
+        .into_iter()
+        .filter(|m| {
+            // the k-mer of a shimmer ends at its position
+            let end = m.pos() + 1;
+            let bgn = end.saturating_sub(shmmrspec.k);
+            let i = masked_intervals.partition_point(|&(_, masked_end)| masked_end <= bgn);
+            i == masked_intervals.len() || masked_intervals[i].0 >= end
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SketchStats {
+    pub n_anchors: usize,
+    pub mean_spacing: f64,
+    pub median_spacing: u32,
+    /// the largest distance between the neighboring anchors or between an anchor and the ends of the region
+    pub max_gap: u32,
+    /// the fraction of the region covered by the spans of the anchor pairs
+    pub span_coverage: f64,
+}
+
+/// the anchor count and spacing statistics of the region `[bgn, end)` of a sequence, `shmmrs` are the anchors
+/// of the whole sequence so the statistics of the windows along a sequence can be computed from a single sketch
+pub fn sketch_stats_from_shmmrs(shmmrs: &[MM128], bgn: u32, end: u32) -> SketchStats {
+    if end <= bgn {
+        return SketchStats::default();
+    }
+    let anchors = shmmrs
+        .iter()
+        .filter(|m| m.pos() >= bgn && m.pos() < end)
+        .collect::<Vec<&MM128>>();
+    let positions = anchors.iter().map(|m| m.pos()).collect::<Vec<u32>>();
+    let mut spacings = positions
+        .windows(2)
+        .map(|w| w[1] - w[0])
+        .collect::<Vec<u32>>();
+    let mean_spacing = if spacings.is_empty() {
+        0.0
+    } else {
+        spacings.iter().map(|&s| s as f64).sum::<f64>() / spacings.len() as f64
+    };
+    let max_gap = match (positions.first(), positions.last()) {
+        (Some(&first), Some(&last)) => spacings
+            .iter()
+            .copied()
+            .chain([first - bgn, end - 1 - last])
+            .max()
+            .unwrap(),
+        _ => end - bgn,
+    };
+    spacings.sort_unstable();
+    let median_spacing = spacings.get(spacings.len() / 2).copied().unwrap_or(0);
+    // the spans of the neighboring anchor pairs overlap, so they cover from the first to the last anchor
+    // in the region
+    let span_coverage = match (anchors.first(), anchors.last()) {
+        (Some(first), Some(last)) if anchors.len() > 1 => {
+            let span_bgn = (first.pos() + 1)
+                .saturating_sub(first.span() as u32)
+                .max(bgn);
+            let span_end = (last.pos() + 1).min(end);
+            span_end.saturating_sub(span_bgn) as f64 / (end - bgn) as f64
+        }
+        _ => 0.0,
+    };
+    SketchStats {
+        n_anchors: positions.len(),
+        mean_spacing,
+        median_spacing,
+        max_gap,
+        span_coverage,
+    }
+}
+
+/// the anchor count and spacing statistics of a sequence with a shimmer spec, for checking the parameters
+/// against the regions that get too few anchors
+pub fn sketch_stats(seq: &Vec<u8>, spec: &ShmmrSpec) -> SketchStats {
+    let shmmrs = sequence_to_shmmrs(0, seq, spec, false);
+    sketch_stats_from_shmmrs(&shmmrs, 0, seq.len() as u32)
+}
@@ -1,7 +1,7 @@
 // src/lib.rs
 pub const VERSION_STRING: &str = env!("VERSION_STRING");
 use pgr_db::aln::{self, HitPair};
-use pgr_db::graph_utils::{AdjList, ShmmrGraphNode};
+use pgr_db::graph_utils::{AdjList, GraphSimplification, ShmmrGraphNode};
 use pgr_db::seq_db;
 //use pgr_db::seqs2variants;
 use pgr_db::shmmrutils::{sequence_to_shmmrs, DeltaPoint, ShmmrSpec};
@@ -10,8 +10,10 @@ use pgr_db::shmmrutils::{sequence_to_shmmrs, DeltaPoint, ShmmrSpec};
 use pgr_db::agc_io;
 
 use pgr_db::fasta_io;
+use pyo3::buffer::PyBuffer;
 use pyo3::exceptions;
 use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyString};
 use pyo3::wrap_pyfunction;
 use pyo3::Python;
 use rayon::prelude::*;
@@ -27,6 +29,7 @@ pub fn pgr_lib_version() -> PyResult<String> {
 
 
 type Bundles = Vec<Vec<(u64, u64, u8)>>; // each bundle is a Vec<node>, each node is (hash0, hash1, orientation)
+type Bubble = ((u64, u64, u8), (u64, u64, u8), Vec<(u64, u64, u8)>); // (entrance, exit, internal nodes)
 
 /// A class that stores pangenome indices and sequences with multiple backend storage options (AGC, fasta file, memory)
 /// Large set of genomic sequences, a user should use AGC backend. A binary file provides the command ``pgr-mdb``
@@ -76,12 +79,14 @@ impl SeqIndexDB {
             db_internal: pgr_db::ext::SeqIndexDB {
                 seq_db: None,
                 frg_db: None,
+                fai_db: None,
                 #[cfg(feature = "with_agc")]
                 agc_db: None,
                 shmmr_spec: None,
                 seq_index: None,
                 seq_info: None,
                 backend: Backend::UNKNOWN,
+                graph_simplification: None,
             },
             principal_bundles: None,
         }
@@ -113,6 +118,28 @@ impl SeqIndexDB {
         Ok(())
     }
 
+    /// use indexed fasta files for sequences and the index created from the fasta files, this does not
+    /// need the AGC library
+    ///
+    /// Parameters
+    /// ----------
+    ///
+    /// prefix: string
+    ///     the prefix to the `.mdb`, `.midx` and `.fasta_manifest` files, each line of the `.fasta_manifest`
+    ///     file contains a source (sample) name and the path to the (plain or bgzipped) fasta file of the source
+    ///     separated by a tab, the `.fai` index files are created if they do not exist
+    ///
+    /// Returns
+    /// -------
+    ///
+    /// None or I/O Error
+    ///
+    #[pyo3(text_signature = "($self, prefix)")]
+    pub fn load_from_indexed_fasta(&mut self, prefix: String) -> PyResult<()> {
+        self.db_internal.load_from_indexed_fasta(prefix)?;
+        Ok(())
+    }
+
     /// load and create the index created from a fasta / fastq file
     ///
     /// Parameters
@@ -154,6 +181,39 @@ impl SeqIndexDB {
         Ok(())
     }
 
+    /// load and create the index from a list of fasta / fastq files, the sequences of each file are labeled
+    /// with the file name (without the directory and the fasta/fastq extensions) as the source
+    ///
+    /// Parameters
+    /// ----------
+    ///
+    /// filepaths : list of string
+    ///     the paths to the fasta or fastq files, the file names can be the patterns with the `*` and `?` wildcards
+    ///
+    /// w, k, r, min_span, to_upper_case :
+    ///     the same as the ones of ``load_from_fastx()``
+    ///
+    /// Returns
+    /// -------
+    ///
+    /// None or I/O Error
+    ///     None
+    ///
+    #[pyo3(signature = (filepaths, w=80, k=56, r=4, min_span=64, to_upper_case=true))]
+    pub fn load_from_fastx_files(
+        &mut self,
+        filepaths: Vec<String>,
+        w: u32,
+        k: u32,
+        r: u32,
+        min_span: u32,
+        to_upper_case: bool,
+    ) -> PyResult<()> {
+        self.db_internal
+            .load_from_fastx_files(&filepaths, w, k, r, min_span, to_upper_case)?;
+        Ok(())
+    }
+
     #[pyo3(signature = (filepath,to_upper_case=true))]
     pub fn append_from_fastx(&mut self, filepath: String, to_upper_case: bool) -> PyResult<()> {
         assert!(
@@ -279,6 +339,19 @@ impl SeqIndexDB {
                     &shmmr_spec,
                 ))
             }
+            Backend::FAI => {
+                let (frag_location_map, frag_map_file) = (
+                    &self.db_internal.fai_db.as_ref().unwrap().frag_location_map,
+                    &self.db_internal.fai_db.as_ref().unwrap().frag_map_file,
+                );
+                let shmmr_spec = self.db_internal.shmmr_spec.as_ref().unwrap().clone();
+                Ok(pgr_db::seq_db::raw_query_fragment_from_mmap_midx(
+                    frag_location_map,
+                    frag_map_file,
+                    &seq,
+                    &shmmr_spec,
+                ))
+            }
             Backend::MEMORY | Backend::FASTX => {
                 let shmmr_spec = &self.db_internal.shmmr_spec.as_ref().unwrap();
                 let shmmr_to_frags = self.get_shmmr_map_internal().unwrap();
@@ -381,7 +454,7 @@ impl SeqIndexDB {
                 .db_internal
                 .query_fragment_to_hps_from_mmap_file(
                     &seq,
-                    penalty,
+                    penalty.into(),
                     max_count,
                     max_count_query,
                     max_count_target,
@@ -390,11 +463,11 @@ impl SeqIndexDB {
                     orientated
                 )
                 .unwrap()),
-            Backend::FRG => Ok(self
+            Backend::FRG | Backend::FAI => Ok(self
                 .db_internal
                 .query_fragment_to_hps_from_mmap_file(
                     &seq,
-                    penalty,
+                    penalty.into(),
                     max_count,
                     max_count_query,
                     max_count_target,
@@ -407,7 +480,7 @@ impl SeqIndexDB {
                 .db_internal
                 .query_fragment_to_hps(
                     &seq,
-                    penalty,
+                    penalty.into(),
                     max_count,
                     max_count_query,
                     max_count_target,
@@ -489,7 +562,7 @@ impl SeqIndexDB {
                 raw_query_hits,
                 &seq,
                 shmmr_spec,
-                penalty,
+                penalty.into(),
                 max_count,
                 max_count_query,
                 max_count_target,
@@ -841,6 +914,22 @@ impl SeqIndexDB {
         Ok(self.db_internal.get_sub_seq_by_id(sid, bgn, end)?)
     }
 
+    /// fetch a batch of sub-sequences by the sequence ids in parallel
+    ///
+    /// Parameters
+    /// ----------
+    /// sub_seqs : list
+    ///     a list of the tuples ``(sid, bgn, end)``, the coordinates are 0-based and the ends are exclusive
+    ///
+    /// Returns
+    /// -------
+    /// list
+    ///     a list of the sequences in the order of ``sub_seqs``
+    #[pyo3(text_signature = "($self, sub_seqs)")]
+    pub fn get_sub_seqs_by_id(&self, sub_seqs: Vec<(u32, usize, usize)>) -> PyResult<Vec<Vec<u8>>> {
+        Ok(self.db_internal.get_sub_seqs_by_id(sub_seqs)?)
+    }
+
     /// fetch a sequence
     ///
     /// Parameters
@@ -1014,6 +1103,184 @@ impl SeqIndexDB {
         pb
     }
 
+    /// Simplify MAPG before getting the principal bundles by clipping the low coverage tips and
+    /// popping the small bubbles, the noise in the single sequences fragments the principal bundles
+    ///
+    /// Parameters
+    /// ----------
+    /// max_tip_len : int
+    ///     remove the dead-end branches of up to ``max_tip_len`` nodes, ``0`` for not clipping the tips
+    ///
+    /// max_tip_coverage : int
+    ///     only remove the tips walked by at most ``max_tip_coverage`` sequences
+    ///
+    /// max_bubble_size : int
+    ///     collapse the bubbles of up to ``max_bubble_size`` nodes to their allele walked by the most
+    ///     sequences, ``0`` for not popping the bubbles
+    ///
+    /// Returns
+    /// -------
+    /// None
+    ///
+    #[pyo3(signature = (max_tip_len=4, max_tip_coverage=1, max_bubble_size=16))]
+    pub fn set_graph_simplification(
+        &mut self,
+        max_tip_len: usize,
+        max_tip_coverage: usize,
+        max_bubble_size: usize,
+    ) {
+        self.db_internal.graph_simplification = Some(GraphSimplification {
+            max_tip_len,
+            max_tip_coverage,
+            max_bubble_size,
+        });
+        self.principal_bundles = None;
+    }
+
+    /// Get the principal bundles of each connected component of MAPG, the components are processed
+    /// in parallel
+    ///
+    /// Parameters
+    /// ----------
+    /// min_count : int
+    ///     minimum coverage count to be included in the graph
+    ///
+    /// path_len_cut_off : int
+    ///     remove short path less than path_len_cut_off when generating the principal path
+    ///
+    /// keeps : list
+    ///     the sequence ids kept in the graph regardless the coverage
+    ///
+    /// Returns
+    /// -------
+    /// list
+    ///     list of the principal bundles of each component, in the same order as
+    ///     ``get_mapg_components()``, each node is a tuple of (hash0, hash1, orientation)
+    ///
+    #[pyo3(signature = (min_count, path_len_cutoff, keeps=None))]
+    pub fn get_principal_bundles_by_component(
+        &self,
+        min_count: usize,
+        path_len_cutoff: usize,
+        keeps: Option<Vec<u32>>,
+    ) -> Vec<Bundles> {
+        self.db_internal
+            .get_principal_bundles_by_component(min_count, path_len_cutoff, keeps)
+    }
+
+    /// Summarize the connected components of MAPG, the largest first
+    ///
+    /// Parameters
+    /// ----------
+    /// min_count : int
+    ///     minimum coverage count to be included in the graph
+    ///
+    /// keeps : list
+    ///     the sequence ids kept in the graph regardless the coverage
+    ///
+    /// Returns
+    /// -------
+    /// list
+    ///     list of ``(number_of_nodes, number_of_edges, sids)`` of the components, a node and an edge
+    ///     are counted once for both orientations, ``sids`` is the list of the sequence ids in the
+    ///     component
+    ///
+    #[pyo3(signature = (min_count, keeps=None))]
+    pub fn get_mapg_components(
+        &self,
+        min_count: usize,
+        keeps: Option<Vec<u32>>,
+    ) -> Vec<(usize, usize, Vec<u32>)> {
+        self.db_internal.get_mapg_components(min_count, keeps)
+    }
+
+    /// Find the superbubbles in MAPG with the allele paths walked by the sequences
+    ///
+    /// Parameters
+    /// ----------
+    /// min_count : int
+    ///     minimum coverage count to be included in the graph
+    ///
+    /// max_bubble_size : int
+    ///     the max number of the nodes visited for finding a bubble from an entrance
+    ///
+    /// keeps : list
+    ///     the sequence ids kept in the graph regardless the coverage
+    ///
+    /// Returns
+    /// -------
+    /// list
+    ///     list of ``((entrance, exit, internal_nodes), alleles)``, each allele is a tuple of
+    ///     ``(path, sids)`` where ``path`` is the list of nodes from the entrance to the exit
+    ///     and ``sids`` is the list of the sequence ids walking the path
+    ///
+    ///     each node is a tuple of (hash0, hash1, orientation)
+    ///
+    #[pyo3(signature = (min_count, max_bubble_size=1024, keeps=None))]
+    pub fn get_superbubbles(
+        &self,
+        min_count: usize,
+        max_bubble_size: usize,
+        keeps: Option<Vec<u32>>,
+    ) -> Vec<(Bubble, Vec<(Vec<(u64, u64, u8)>, Vec<u32>)>)> {
+        let to_tuple = |v: &ShmmrGraphNode| (v.0, v.1, v.2);
+        self.db_internal
+            .get_superbubbles(min_count, keeps, max_bubble_size)
+            .into_iter()
+            .map(|((s, t, internal), alleles)| {
+                let bubble = (
+                    to_tuple(&s),
+                    to_tuple(&t),
+                    internal.iter().map(to_tuple).collect(),
+                );
+                let alleles = alleles
+                    .into_iter()
+                    .map(|(path, sids)| (path.iter().map(to_tuple).collect(), sids))
+                    .collect();
+                (bubble, alleles)
+            })
+            .collect()
+    }
+
+    /// Find the cycles (e.g., the VNTR-like loops) in MAPG with the traversal counts of the sequences
+    ///
+    /// Parameters
+    /// ----------
+    /// min_count : int
+    ///     minimum coverage count to be included in the graph
+    ///
+    /// max_cycle_size : int
+    ///     the max number of the nodes in a cycle, the larger strongly connected components are not reported
+    ///
+    /// keeps : list
+    ///     the sequence ids kept in the graph regardless the coverage
+    ///
+    /// Returns
+    /// -------
+    /// list
+    ///     list of ``(nodes, unit_len, traversals)``, ``unit_len`` is the length of the repeat unit in bp,
+    ///     ``traversals`` is the list of ``(sid, count)``, the number of times each sequence walks the
+    ///     cycle (the copy number of the repeat unit)
+    ///
+    ///     each node is a tuple of (hash0, hash1, orientation)
+    ///
+    #[pyo3(signature = (min_count, max_cycle_size=64, keeps=None))]
+    pub fn get_mapg_cycles(
+        &self,
+        min_count: usize,
+        max_cycle_size: usize,
+        keeps: Option<Vec<u32>>,
+    ) -> Vec<(Vec<(u64, u64, u8)>, u32, Vec<(u32, usize)>)> {
+        self.db_internal
+            .get_mapg_cycles(min_count, keeps, max_cycle_size)
+            .into_iter()
+            .map(|(vertices, unit_len, traversals)| {
+                let vertices = vertices.iter().map(|v| (v.0, v.1, v.2)).collect();
+                (vertices, unit_len, traversals)
+            })
+            .collect()
+    }
+
     fn get_vertex_map_from_principal_bundles(
         &self,
         pb: Vec<Vec<(u64, u64, u8)>>,
@@ -1315,6 +1582,38 @@ impl SeqIndexDB {
         Ok(())
     }
 
+    /// Write the coverage of the segments and the links of MAPG, with the same segment ids as the GFA
+    /// from ``generate_mapg_gfa()``, to a TSV file
+    ///
+    /// Parameters
+    /// ----------
+    /// min_count : int
+    ///     the minimum number of times a pair of shimmers must be observed to be included in the graph
+    ///
+    /// filepath : string
+    ///     the path to the output file
+    ///
+    /// Returns
+    /// -------
+    ///
+    /// None
+    ///     The data is written into the file at filepath, each line is a segment (``S``) or a link
+    ///     (``L``) with its coverage, the number of the sequences and the ids of the sequences
+    ///     traversing it
+    ///
+    #[pyo3(signature = (min_count, filepath, method="from_fragmap", keeps=None))]
+    pub fn write_mapg_coverage_tsv(
+        &self,
+        min_count: usize,
+        filepath: &str,
+        method: &str,
+        keeps: Option<Vec<u32>>,
+    ) -> PyResult<()> {
+        self.db_internal
+            .write_mapg_coverage_tsv(min_count, filepath, method, keeps, None)?;
+        Ok(())
+    }
+
     /// Write additional meta data for GFA into a file
     ///
     /// Parameters
@@ -1402,6 +1701,111 @@ impl SeqIndexDB {
             Err(_) => Err(exceptions::PyException::new_err("consensus failed")),
         }
     }
+
+    /// correct a noisy read with the matching haplotype segments in the database, the read is anchored to
+    /// the haplotypes with ``query_fragment_to_hps()`` and corrected by the consensus of the read and the
+    /// segments guided by the read
+    ///
+    /// Parameters
+    /// ----------
+    /// seq : bytes, string or an object with the buffer protocol of bytes, e.g., a ``numpy`` array of ``uint8``
+    ///     the bases of the read
+    ///
+    /// penalty : float
+    ///     the gap penalty factor for sparse alignments in the SHIMMER space
+    ///
+    /// max_count : int
+    ///     the max count of SHIMMER used for the sparse alignment
+    ///
+    /// max_aln_span : int
+    ///     the span of the chain for building the sparse alignment directed acyclic graph
+    ///
+    /// min_anchor_count : int
+    ///     the min number of the anchor hits of a haplotype segment to be used for the correction
+    ///
+    /// max_segments : int
+    ///     the max number of the haplotype segments (with the most anchor hits) used for the correction
+    ///
+    /// flank : int
+    ///     the extra bases of the haplotype segments beyond the ends of the read
+    ///
+    /// min_cov : int
+    ///     the min number of the sequences (the read and the segments) supporting the bases of the read to keep them
+    ///
+    /// max_diff_rate : float
+    ///     the max difference rate between the read and the corrected read
+    ///
+    /// Returns
+    /// -------
+    /// tuple or None
+    ///     (``corrected_read``, ``number_of_differences``), the ``corrected_read`` is a list of bytes,
+    ///     None if no haplotype segment is found or the read can't be corrected
+    ///
+    #[pyo3(
+        signature = (seq, penalty = 0.025, max_count = 128, max_aln_span = 8, min_anchor_count = 10, max_segments = 4, flank = 200, min_cov = 2, max_diff_rate = 0.15)
+    )]
+    #[allow(clippy::too_many_arguments)]
+    pub fn correct_read(
+        &self,
+        seq: &PyAny,
+        penalty: f32,
+        max_count: u32,
+        max_aln_span: u32,
+        min_anchor_count: usize,
+        max_segments: usize,
+        flank: u32,
+        min_cov: u32,
+        max_diff_rate: f64,
+    ) -> PyResult<Option<(Vec<u8>, u32)>> {
+        let read = seq_from_py(seq)?;
+        let hits = self.query_fragment_to_hps(
+            read.clone(),
+            penalty,
+            Some(max_count),
+            Some(max_count),
+            Some(max_count),
+            Some(max_aln_span),
+            None,
+            Some(false),
+        )?;
+        let segments = pgr_db::ec::read_target_segments(
+            &hits,
+            read.len() as u32,
+            min_anchor_count,
+            max_segments,
+            flank,
+        );
+        let seq_info = match self.db_internal.seq_info.as_ref() {
+            Some(seq_info) => seq_info,
+            None => return Ok(None),
+        };
+        let segment_seqs = segments
+            .iter()
+            .filter_map(|&(sid, bgn, end, orientation, _)| {
+                let (_, _, t_len) = seq_info.get(&sid)?;
+                let end = end.min(*t_len);
+                if bgn >= end {
+                    return None;
+                }
+                let seq = self
+                    .db_internal
+                    .get_sub_seq_by_id(sid, bgn as usize, end as usize)
+                    .ok()?;
+                if orientation == 0 {
+                    Some(seq)
+                } else {
+                    Some(fasta_io::reverse_complement(&seq))
+                }
+            })
+            .collect::<Vec<Vec<u8>>>();
+        Ok(pgr_db::ec::correct_read_with_segments(
+            &read,
+            segment_seqs,
+            &None,
+            min_cov,
+            max_diff_rate,
+        ))
+    }
 }
 
 impl SeqIndexDB {
@@ -1413,6 +1817,7 @@ impl SeqIndexDB {
             Backend::FASTX => Some(&self.db_internal.seq_db.as_ref().unwrap().frag_map),
             Backend::MEMORY => Some(&self.db_internal.seq_db.as_ref().unwrap().frag_map),
             Backend::FRG => None,
+            Backend::FAI => None,
             Backend::UNKNOWN => None,
         }
     }
@@ -1489,6 +1894,42 @@ impl AGCFile {
         Ok(self.agc_file.get_sub_seq(sample_name, ctg_name, bgn, end))
     }
 
+    /// fetch a batch of sub-sequences from an AGC file in parallel
+    ///
+    /// Parameters
+    /// ----------
+    /// sub_seqs : list
+    ///     a list of the tuples ``(sample_name, ctg_name, bgn, end)``, the coordinates are 0-based
+    ///     and the ends are exclusive
+    ///
+    /// Returns
+    /// -------
+    /// list
+    ///     a list of the sequences in the order of ``sub_seqs``
+    #[pyo3(signature = (sub_seqs))]
+    pub fn get_sub_seqs(
+        &self,
+        sub_seqs: Vec<(String, String, usize, usize)>,
+    ) -> PyResult<Vec<Vec<u8>>> {
+        Ok(self.agc_file.get_sub_seqs(sub_seqs))
+    }
+
+    /// set the max number of the decompressed contigs cached in memory, the repeated fetches from
+    /// a cached contig are sliced from it without decompressing it again, ``0`` disables the cache
+    ///
+    /// Parameters
+    /// ----------
+    /// n_ctgs : int
+    ///     the max number of the cached contigs
+    ///
+    /// Returns
+    /// -------
+    /// None
+    #[pyo3(signature = (n_ctgs))]
+    pub fn set_ctg_cache_size(&self, n_ctgs: usize) {
+        self.agc_file.set_ctg_cache_size(n_ctgs)
+    }
+
     /// fetch a full contig sequence from an AGC file
     ///
     /// Parameters
@@ -1546,7 +1987,7 @@ pub fn sparse_aln(
 ) -> PyResult<Vec<(f32, Vec<HitPair>)>> {
     let mut hp = sp_hits.clone();
     let orientated = if let Some(orientated) = orientated {orientated} else {false}; 
-    Ok(aln::sparse_aln(&mut hp, max_span, penalty, max_gap, orientated))
+    Ok(aln::sparse_aln(&mut hp, max_span, penalty.into(), max_gap, orientated))
 }
 
 /// Generate a list of shimmer pair from a sequence
@@ -1588,13 +2029,7 @@ fn get_shmmr_pairs_from_seq(
     min_span: u32,
     padding: bool,
 ) -> PyResult<Vec<(u64, u64, u32, u32, u8)>> {
-    let shmmr_spec = ShmmrSpec {
-        w,
-        k,
-        r,
-        min_span,
-        sketch: false,
-    };
+    let shmmr_spec = ShmmrSpec::new(w, k, r, min_span, false);
     let shmmrs = sequence_to_shmmrs(0, &seq, &shmmr_spec, padding);
     let res = seq_db::pair_shmmrs(&shmmrs)
         .par_iter()
@@ -1660,13 +2095,7 @@ fn get_shmmr_dots(
     let mut y = Vec::<u32>::new();
     //let seq0v = seq0.to_string_lossy().as_bytes().to_vec();
     //let seq1v = seq1.to_string_lossy().as_bytes().to_vec();
-    let shmmr_spec = ShmmrSpec {
-        w,
-        k,
-        r,
-        min_span,
-        sketch: false,
-    };
+    let shmmr_spec = ShmmrSpec::new(w, k, r, min_span, false);
 
     let shmmr0 = sequence_to_shmmrs(0, &seq0, &shmmr_spec, false);
     let shmmr1 = sequence_to_shmmrs(1, &seq1, &shmmr_spec, false);
@@ -1707,14 +2136,62 @@ pub fn wfa_align_bases(
     open_penalty: i32,
     extension_penalty: i32,
 ) -> Option<(String, String)> {
-    aln::wfa_align_bases(
+    let aln_params = aln::AlnParams {
+        mismatch_penalty,
+        gap_open_penalty: open_penalty,
+        gap_extension_penalty: extension_penalty,
+        max_wf_length: Some(max_wf_length),
+        ..Default::default()
+    };
+    aln::wfa_align_bases(target_str, query_str, &aln_params)
+}
+
+/// perform SW alignment between two sequences
+///
+/// Parameters
+/// ----------
+/// target_str : string
+///     the target sequence
+///
+/// query_str : string
+///     the query sequence
+///
+/// mode : string
+///     "global" for the end-to-end alignment, "query-glocal" for aligning the whole query to a substring of the target
+///
+/// match_score, mismatch_penalty, open_penalty, extension_penalty : int
+///     the scoring parameters
+///
+/// Returns
+/// -------
+/// tuple
+///     (the begin and the end of the aligned target interval, the aligned target string, the aligned query string)
+///
+#[pyfunction(signature = (target_str, query_str, mode="global", match_score=0, mismatch_penalty=4, open_penalty=4, extension_penalty=1))]
+pub fn sw_align_bases(
+    target_str: &str,
+    query_str: &str,
+    mode: &str,
+    match_score: i32,
+    mismatch_penalty: i32,
+    open_penalty: i32,
+    extension_penalty: i32,
+) -> PyResult<Option<(u32, u32, String, String)>> {
+    let mode = aln::aln_mode_from_str(mode)
+        .map_err(|e| exceptions::PyException::new_err(e.to_string()))?;
+    let aln_params = aln::AlnParams {
+        match_score,
+        mismatch_penalty,
+        gap_open_penalty: open_penalty,
+        gap_extension_penalty: extension_penalty,
+        ..Default::default()
+    };
+    Ok(aln::sw_align_bases_with_mode(
         target_str,
         query_str,
-        max_wf_length,
-        mismatch_penalty,
-        open_penalty,
-        extension_penalty,
-    )
+        mode,
+        &aln_params,
+    ))
 }
 
 /// convert alignment string to alignment pair map
@@ -1744,12 +2221,8 @@ pub fn get_wfa_aln_pair_map(
     extension_penalty: i32,
     max_diff_percent: f32,
 ) -> Option<Vec<(u32, u32, char)>> {
-    let set_len_diff = (query_str.len() as i64 - target_str.len() as i64).unsigned_abs() as u32;
-    let max_wf_length = if let Some(max_wf_length) = max_wf_length {
-        max_wf_length
-    } else {
-        std::cmp::max(2*set_len_diff, 128_u32)
-    };
+    let max_wf_length = max_wf_length
+        .unwrap_or_else(|| aln::default_max_wf_length(target_str.len(), query_str.len()));
 
     if max_wf_length > 128
         && (max_wf_length as f32 / std::cmp::min(target_str.len(), query_str.len()) as f32)
@@ -1758,14 +2231,16 @@ pub fn get_wfa_aln_pair_map(
         return None;
     };
 
-    if let Some((aln_target_str, aln_query_str)) = aln::wfa_align_bases(
-        target_str,
-        query_str,
-        max_wf_length,
+    let aln_params = aln::AlnParams {
         mismatch_penalty,
-        open_penalty,
-        extension_penalty,
-    ) {
+        gap_open_penalty: open_penalty,
+        gap_extension_penalty: extension_penalty,
+        max_wf_length: Some(max_wf_length),
+        ..Default::default()
+    };
+    if let Some((aln_target_str, aln_query_str)) =
+        aln::wfa_align_bases(target_str, query_str, &aln_params)
+    {
         Some(aln::aln_pair_map(&aln_target_str, &aln_query_str))
     } else {
         None
@@ -1805,12 +2280,8 @@ pub fn get_variant_segments(
     extension_penalty: i32,
     max_diff_percent: f32,
 ) -> Option<(Vec<(u32, u32, char, String, String)>, Vec<(u32, u32, char)>)> {
-    let set_len_diff = (query_str.len() as i64 - target_str.len() as i64).unsigned_abs() as u32;
-    let max_wf_length = if let Some(max_wf_length) = max_wf_length {
-        max_wf_length
-    } else {
-        std::cmp::max(2*set_len_diff, 128_u32)
-    };
+    let max_wf_length = max_wf_length
+        .unwrap_or_else(|| aln::default_max_wf_length(target_str.len(), query_str.len()));
 
     if max_wf_length > 128
         && (max_wf_length as f32 / std::cmp::min(target_str.len(), query_str.len()) as f32)
@@ -1819,14 +2290,16 @@ pub fn get_variant_segments(
         return None;
     };
 
-    if let Some((aln_target_str, aln_query_str)) = aln::wfa_align_bases (
-        target_str,
-        query_str,
-        max_wf_length,
+    let aln_params = aln::AlnParams {
         mismatch_penalty,
-        open_penalty,
-        extension_penalty,
-    ) {
+        gap_open_penalty: open_penalty,
+        gap_extension_penalty: extension_penalty,
+        max_wf_length: Some(max_wf_length),
+        ..Default::default()
+    };
+    if let Some((aln_target_str, aln_query_str)) =
+        aln::wfa_align_bases(target_str, query_str, &aln_params)
+    {
         let aln_pairs = aln::aln_pair_map(&aln_target_str, &aln_query_str);
         Some((
             aln::get_variants_from_aln_pair_map(&aln_pairs, target_str, query_str),
@@ -1837,6 +2310,41 @@ pub fn get_variant_segments(
     }
 }
 
+/// convert the variant segments of an alignment to a CIGAR string and a MD tag
+///
+/// Parameters
+/// ----------
+/// variants : list
+///     the variant segments from ``get_variant_segments()`` or ``get_variants_from_aln_pair_map()``
+///
+/// target_str : string
+///     the target sequence of the alignment
+///
+/// query_len : int
+///     the length of the query sequence of the alignment
+///
+/// use_match_op : bool
+///     merge the matches and the mismatches into the ``M`` operations, otherwise ``=`` and ``X`` are used
+///
+/// Returns
+/// -------
+/// tuple
+///     (the CIGAR string, the MD tag), or None if the variant segments are not consistent with the sequence lengths
+///
+#[pyfunction(signature = (variants, target_str, query_len, use_match_op=true))]
+pub fn get_cigar_from_variant_segments(
+    variants: Vec<(u32, u32, char, String, String)>,
+    target_str: &str,
+    query_len: u32,
+    use_match_op: bool,
+) -> Option<(String, String)> {
+    let ops = aln::variant_segments_to_cigar_ops(&variants, target_str.len() as u32, query_len)?;
+    Some((
+        aln::cigar_ops_to_string(&ops, use_match_op),
+        aln::cigar_ops_to_md_tag(&ops, target_str.as_bytes()),
+    ))
+}
+
 /// Perform a naive de Bruijn graph consensus
 ///
 /// Parameters
@@ -1893,13 +2401,7 @@ pub fn shmmr_dbg_consensus(
     r: u32,
     min_span: u32,
 ) -> PyResult<Vec<(Vec<u8>, Vec<u32>)>> {
-    let spec = ShmmrSpec {
-        w,
-        k,
-        r,
-        min_span,
-        sketch: false,
-    };
+    let spec = ShmmrSpec::new(w, k, r, min_span, false);
     let consensus = pgr_db::ec::shmmr_dbg_consensus(seqs, &Some(spec));
     match consensus {
         Ok(seq) => Ok(seq),
@@ -1936,13 +2438,7 @@ pub fn guided_shmmr_dbg_consensus(
     min_span: u32,
     min_cov: u32,
 ) -> PyResult<(Vec<u8>, Vec<u32>)> {
-    let spec = ShmmrSpec {
-        w,
-        k,
-        r,
-        min_span,
-        sketch: false,
-    };
+    let spec = ShmmrSpec::new(w, k, r, min_span, false);
     let consensus = pgr_db::ec::guided_shmmr_dbg_consensus(seqs, &Some(spec), min_cov);
     match consensus {
         Ok(seq) => Ok(seq),
@@ -1976,13 +2472,7 @@ pub fn shmmr_sparse_aln_consensus(
     min_span: u32,
     min_cov: u32,
 ) -> PyResult<Vec<(Vec<u8>, Vec<u32>)>> {
-    let spec = ShmmrSpec {
-        w,
-        k,
-        r,
-        min_span,
-        sketch: false,
-    };
+    let spec = ShmmrSpec::new(w, k, r, min_span, false);
     let consensus = pgr_db::ec::shmmr_sparse_aln_consensus(seqs, &Some(spec), min_cov);
     match consensus {
         Ok(seq) => Ok(seq),
@@ -1992,6 +2482,216 @@ pub fn shmmr_sparse_aln_consensus(
     }
 }
 
+// the bases from a Python object, `bytes`, `str`, a list of integers, or an object with the buffer protocol
+// of bytes (e.g., a numpy array of `uint8`), so the sequences can be passed without converting them to lists
+fn seq_from_py(seq: &PyAny) -> PyResult<Vec<u8>> {
+    if let Ok(seq) = seq.downcast::<PyBytes>() {
+        return Ok(seq.as_bytes().to_vec());
+    }
+    if let Ok(seq) = seq.downcast::<PyString>() {
+        return Ok(seq.to_str()?.as_bytes().to_vec());
+    }
+    if let Ok(buffer) = PyBuffer::<u8>::get(seq) {
+        return buffer.to_vec(seq.py());
+    }
+    seq.extract::<Vec<u8>>()
+}
+
+/// Polish a structural variant candidate with the reads, the query segment of the candidate is used
+/// as the guide of the shimmer de Bruijn graph consensus of the segment and the reads
+///
+/// Parameters
+/// ----------
+/// t_seq : bytes, string or an object with the buffer protocol of bytes, e.g., a ``numpy`` array of ``uint8``
+///     the reference segment of the candidate, starting with the padding base
+///
+/// q_seq : bytes, string or an object with the buffer protocol of bytes
+///     the query segment of the candidate, starting with the same padding base as ``t_seq``
+///
+/// reads : list
+///     a list of the reads (in the orientation of the query segment) covering the candidate
+///
+/// k, w, r, min_span : int  
+///     specification of the shimmers for construting graph
+///
+/// min_cov : int
+///     the min coverage of the k-mers of the consensus
+///
+/// Returns
+/// -------
+/// tuple or None
+///     (``t_offset``, ``ref_allele``, ``alt_allele``, ``support``), the alleles are lists of bytes
+///     starting at ``t_offset`` of ``t_seq``, None if the candidate is not a variant after the polishing
+///
+#[pyfunction(signature = (t_seq, q_seq, reads = vec![], w = 31, k = 31, r = 1, min_span = 0, min_cov = 2))]
+#[allow(clippy::too_many_arguments)]
+pub fn polish_sv_candidate(
+    t_seq: &PyAny,
+    q_seq: &PyAny,
+    reads: Vec<&PyAny>,
+    w: u32,
+    k: u32,
+    r: u32,
+    min_span: u32,
+    min_cov: u32,
+) -> PyResult<Option<(usize, Vec<u8>, Vec<u8>, u32)>> {
+    let spec = ShmmrSpec::new(w, k, r, min_span, false);
+    let reads = reads
+        .into_iter()
+        .map(seq_from_py)
+        .collect::<PyResult<Vec<Vec<u8>>>>()?;
+    let allele = pgr_db::ec::polish_sv_candidate(
+        &seq_from_py(t_seq)?,
+        &seq_from_py(q_seq)?,
+        reads,
+        &Some(spec),
+        min_cov,
+    );
+    Ok(allele.map(|a| (a.t_offset, a.ref_allele, a.alt_allele, a.support)))
+}
+
+/// Polish a list of structural variant candidates, the reads sharing shimmer pairs with the query
+/// segment of a candidate are recruited for polishing it, and the candidates are polished in parallel
+///
+/// Parameters
+/// ----------
+/// candidates : list
+///     a list of (``t_seq``, ``q_seq``), see ``polish_sv_candidate()``
+///
+/// reads : list
+///     a list of the reads for all candidates
+///
+/// k, w, r, min_span : int  
+///     specification of the shimmers for construting graph
+///
+/// min_cov : int
+///     the min coverage of the k-mers of the consensus
+///
+/// Returns
+/// -------
+/// list
+///     a list of the polished alleles (or None) of the candidates, see ``polish_sv_candidate()``
+///
+#[pyfunction(signature = (candidates, reads, w = 31, k = 31, r = 1, min_span = 0, min_cov = 2))]
+pub fn polish_sv_candidates(
+    candidates: Vec<(&PyAny, &PyAny)>,
+    reads: Vec<&PyAny>,
+    w: u32,
+    k: u32,
+    r: u32,
+    min_span: u32,
+    min_cov: u32,
+) -> PyResult<Vec<Option<(usize, Vec<u8>, Vec<u8>, u32)>>> {
+    let spec = ShmmrSpec::new(w, k, r, min_span, false);
+    let candidates = candidates
+        .into_iter()
+        .map(|(t_seq, q_seq)| Ok((seq_from_py(t_seq)?, seq_from_py(q_seq)?)))
+        .collect::<PyResult<Vec<(Vec<u8>, Vec<u8>)>>>()?;
+    let reads = reads
+        .into_iter()
+        .map(seq_from_py)
+        .collect::<PyResult<Vec<Vec<u8>>>>()?;
+    let alleles = pgr_db::ec::polish_sv_candidates(&candidates, &reads, &Some(spec), min_cov);
+    Ok(alleles
+        .into_iter()
+        .map(|allele| allele.map(|a| (a.t_offset, a.ref_allele, a.alt_allele, a.support)))
+        .collect())
+}
+
+/// Find the haplotype segments for correcting a read from the hits of ``SeqIndexDB.query_fragment_to_hps()``
+///
+/// Parameters
+/// ----------
+/// hits : list
+///     the output of ``SeqIndexDB.query_fragment_to_hps()`` with the read as the query
+///
+/// read_len : int
+///     the length of the read
+///
+/// min_anchor_count : int
+///     the min number of the anchor hits of a haplotype segment
+///
+/// max_segments : int
+///     the max number of the haplotype segments (with the most anchor hits) returned
+///
+/// flank : int
+///     the extra bases of the haplotype segments beyond the ends of the read
+///
+/// Returns
+/// -------
+/// list
+///     a list of (``sid``, ``bgn``, ``end``, ``orientation``, ``anchor_count``), the ``end`` may be beyond the
+///     length of the sequence ``sid`` and should be clipped before fetching the segment
+///
+#[pyfunction(signature = (hits, read_len, min_anchor_count = 10, max_segments = 4, flank = 200))]
+pub fn read_target_segments(
+    hits: Vec<(u32, Vec<(f32, Vec<HitPair>)>)>,
+    read_len: u32,
+    min_anchor_count: usize,
+    max_segments: usize,
+    flank: u32,
+) -> PyResult<Vec<pgr_db::ec::ReadTargetSegment>> {
+    Ok(pgr_db::ec::read_target_segments(
+        &hits,
+        read_len,
+        min_anchor_count,
+        max_segments,
+        flank,
+    ))
+}
+
+/// Correct a noisy read with the haplotype segments matching it, e.g., the segments from
+/// ``read_target_segments()`` and ``SeqIndexDB.get_sub_seq_by_id()``
+///
+/// Parameters
+/// ----------
+/// read : bytes, string or an object with the buffer protocol of bytes, e.g., a ``numpy`` array of ``uint8``
+///     the bases of the read
+///
+/// segments : list
+///     a list of the haplotype segments in the orientation of the read
+///
+/// k, w, r, min_span : int  
+///     specification of the shimmers for construting graph
+///
+/// min_cov : int
+///     the min number of the sequences (the read and the segments) supporting the bases of the read to keep them
+///
+/// max_diff_rate : float
+///     the max difference rate between the read and the corrected read
+///
+/// Returns
+/// -------
+/// tuple or None
+///     (``corrected_read``, ``number_of_differences``), the ``corrected_read`` is a list of bytes,
+///     None if the read can't be corrected
+///
+#[pyfunction(signature = (read, segments, w = 31, k = 31, r = 1, min_span = 0, min_cov = 2, max_diff_rate = 0.15))]
+#[allow(clippy::too_many_arguments)]
+pub fn correct_read_with_segments(
+    read: &PyAny,
+    segments: Vec<&PyAny>,
+    w: u32,
+    k: u32,
+    r: u32,
+    min_span: u32,
+    min_cov: u32,
+    max_diff_rate: f64,
+) -> PyResult<Option<(Vec<u8>, u32)>> {
+    let spec = ShmmrSpec::new(w, k, r, min_span, false);
+    let segments = segments
+        .into_iter()
+        .map(seq_from_py)
+        .collect::<PyResult<Vec<Vec<u8>>>>()?;
+    Ok(pgr_db::ec::correct_read_with_segments(
+        &seq_from_py(read)?,
+        segments,
+        &Some(spec),
+        min_cov,
+        max_diff_rate,
+    ))
+}
+
 /// The internal `pgrtk` modules implemented with Rust.
 /// These classes and fucntion are re-exported as `pgrtk.*`
 /// so `import pgrtk` will bring these classes and function
@@ -2005,15 +2705,21 @@ fn pgrtk(_: Python, m: &PyModule) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(sparse_aln, m)?)?;
     m.add_function(wrap_pyfunction!(get_shmmr_dots, m)?)?;
     m.add_function(wrap_pyfunction!(wfa_align_bases, m)?)?;
+    m.add_function(wrap_pyfunction!(sw_align_bases, m)?)?;
     m.add_function(wrap_pyfunction!(wfa_aln_pair_map, m)?)?;
     m.add_function(wrap_pyfunction!(get_wfa_aln_pair_map, m)?)?;
     m.add_function(wrap_pyfunction!(get_variants_from_aln_pair_map, m)?)?;
     m.add_function(wrap_pyfunction!(get_variant_segments, m)?)?;
+    m.add_function(wrap_pyfunction!(get_cigar_from_variant_segments, m)?)?;
     m.add_function(wrap_pyfunction!(pgr_lib_version, m)?)?;
     m.add_function(wrap_pyfunction!(get_shmmr_pairs_from_seq, m)?)?;
     m.add_function(wrap_pyfunction!(naive_dbg_consensus, m)?)?;
     m.add_function(wrap_pyfunction!(shmmr_dbg_consensus, m)?)?;
     m.add_function(wrap_pyfunction!(guided_shmmr_dbg_consensus, m)?)?;
     m.add_function(wrap_pyfunction!(shmmr_sparse_aln_consensus, m)?)?;
+    m.add_function(wrap_pyfunction!(polish_sv_candidate, m)?)?;
+    m.add_function(wrap_pyfunction!(polish_sv_candidates, m)?)?;
+    m.add_function(wrap_pyfunction!(read_target_segments, m)?)?;
+    m.add_function(wrap_pyfunction!(correct_read_with_segments, m)?)?;
     Ok(())
 }
@@ -77,6 +77,13 @@ pub struct SequenceQuerySpec {
     pub bundle_merge_distance: usize,
 }
 
+/// two regions decomposed together by the server for a side-by-side comparison
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct CompareQuerySpec {
+    pub left: SequenceQuerySpec,
+    pub right: SequenceQuerySpec,
+}
+
 #[derive(Clone)]
 struct QueryState(String);
 
@@ -84,16 +91,116 @@ pub fn base_url() -> String {
     web_sys::window().unwrap().location().origin().unwrap()
 }
 
+// the custom ROIs saved by the users are kept in the local storage of the browser
+const CUSTOM_ROI_KEY: &str = "pgr_web_custom_rois";
+
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+fn load_custom_rois() -> FxHashMap<String, SequenceQuerySpec> {
+    local_storage()
+        .and_then(|storage| storage.get_item(CUSTOM_ROI_KEY).ok()?)
+        .and_then(|json| serde_json::from_str(&json[..]).ok())
+        .unwrap_or_default()
+}
+
+fn save_custom_roi(name: &str, query: &SequenceQuerySpec) {
+    let mut custom_rois = load_custom_rois();
+    custom_rois.insert(name.to_string(), query.clone());
+    if let Some(storage) = local_storage() {
+        let _ = storage.set_item(CUSTOM_ROI_KEY, &serde_json::to_string(&custom_rois).unwrap());
+    }
+}
+
+// the API key of a server started with `--api-key-file` is given once in the page URL as "?api_key=..",
+// it is kept in the local storage and sent to the server as a cookie, so the requests, the links opened in
+// new tabs and the WebSocket connections of the page are authorized
+const API_KEY_STORAGE_KEY: &str = "pgr_web_api_key";
+const API_KEY_COOKIE: &str = "pgr_api_key";
+
+fn init_api_key() -> Option<()> {
+    let window = web_sys::window()?;
+    let location = window.location();
+    let search = location.search().ok()?;
+    let url_key = url::form_urlencoded::parse(search.trim_start_matches('?').as_bytes())
+        .find(|(name, _)| name == "api_key")
+        .map(|(_, value)| value.to_string());
+    let api_key = match url_key {
+        Some(api_key) => {
+            if let Some(storage) = local_storage() {
+                let _ = storage.set_item(API_KEY_STORAGE_KEY, &api_key);
+            }
+            // remove the key from the address bar and the history
+            let path = location.pathname().ok()? + &location.hash().ok()?;
+            let _ = window
+                .history()
+                .ok()?
+                .replace_state_with_url(&wasm_bindgen::JsValue::NULL, "", Some(&path));
+            api_key
+        }
+        None => local_storage()?.get_item(API_KEY_STORAGE_KEY).ok()??,
+    };
+    let document = window.document()?.dyn_into::<web_sys::HtmlDocument>().ok()?;
+    document
+        .set_cookie(&format!("{}={}; path=/; SameSite=Strict", API_KEY_COOKIE, api_key))
+        .ok()
+}
+
+// the selected ROI and the query are kept in the URL hash as "#roi=..&query[source]=..&query[ctg]=..",
+// so a view can be shared and restored on reload
+#[derive(Serialize, Deserialize)]
+struct UrlState {
+    roi: String,
+    query: SequenceQuerySpec,
+}
+
+fn load_url_state() -> Option<UrlState> {
+    let hash = web_sys::window()?.location().hash().ok()?;
+    serde_qs::from_str(hash.strip_prefix('#')?).ok()
+}
+
+fn save_url_state(roi: &str, query: &SequenceQuerySpec) {
+    let state = UrlState {
+        roi: roi.to_string(),
+        query: query.clone(),
+    };
+    if let (Some(window), Ok(qstr)) = (web_sys::window(), serde_qs::to_string(&state)) {
+        // replace the history entry so the back button is not flooded by the edits of the parameters
+        if let Ok(history) = window.history() {
+            let _ = history.replace_state_with_url(
+                &wasm_bindgen::JsValue::NULL,
+                "",
+                Some(&format!("#{}", qstr)),
+            );
+        }
+    }
+}
+
+// parse a region string as "ctg:bgn-end", the commas in the coordinates are ignored
+fn parse_region(region: &str) -> Option<(String, usize, usize)> {
+    let (ctg, range) = region.trim().rsplit_once(':')?;
+    let (bgn, end) = range.split_once('-')?;
+    let bgn = bgn.replace(',', "").parse::<usize>().ok()?;
+    let end = end.replace(',', "").parse::<usize>().ok()?;
+    if ctg.is_empty() || bgn >= end {
+        return None;
+    }
+    Some((ctg.to_string(), bgn, end))
+}
+
 fn main() {
+    init_api_key();
     dioxus_web::launch(app);
     wasm_logger::init(wasm_logger::Config::default());
 }
 
 fn app(cx: Scope) -> Element {
     let roi_json = include_str!("data/ROIs.json");
-    let rois: FxHashMap<String, SequenceQuerySpec> = serde_json::from_str(roi_json).unwrap();
+    let mut rois: FxHashMap<String, SequenceQuerySpec> = serde_json::from_str(roi_json).unwrap();
+    rois.extend(load_custom_rois());
 
-    let query = use_state(cx, || SequenceQuerySpec {
+    let query = use_state(cx, || load_url_state().map(|state| state.query).unwrap_or_else(|| SequenceQuerySpec {
         source: "hg19_tagged.fa".to_string(),
         ctg: "chr1_hg19".to_string(),
         bgn: 104198140,
@@ -109,8 +216,9 @@ fn app(cx: Scope) -> Element {
         min_branch_size: 8,
         bundle_length_cutoff: 500,
         bundle_merge_distance: 10000,
-    });
+    }));
     let targets = use_state(cx, || <Option<TargetMatchPrincipalBundles>>::None);
+    let compare_query = use_state(cx, || <Option<SequenceQuerySpec>>::None);
     let query_state = use_state(cx, || "Please send a query".to_string());
 
     // let mut kvs = rois
@@ -121,9 +229,21 @@ fn app(cx: Scope) -> Element {
     //kvs.sort_by_key(|v| v.0.clone());
     //let labels = kvs.iter().map(|(k, _v)| k.clone()).collect::<Vec<_>>();
 
-    let selected_label = use_state(cx, || "".to_string());
+    let selected_label = use_state(cx, || load_url_state().map(|state| state.roi).unwrap_or_default());
     let rois = use_state(cx, || rois );
 
+    use_effect(cx, (query.get(), selected_label.get()), |(query, selected_label)| async move {
+        save_url_state(&selected_label, &query);
+    });
+
+    // fetch the results of the query restored from the URL
+    cx.use_hook(|| {
+        if load_url_state().is_some() {
+            get_targets(cx, query.get(), targets, query_state);
+            query_state.set("getting query results".to_string());
+        }
+    });
+
 
     cx.render(
         rsx! {
@@ -155,7 +275,9 @@ fn app(cx: Scope) -> Element {
                                 },
                                 "Set Query Parameters"
                             }
+                            div { class: "p-1", id: "set_region", set_region { query: query } }
                             div { class: "p-1", id: "set_parameters", set_parameters { query: query } }
+                            div { class: "p-1", id: "save_query", save_query { query: query, rois: rois, query_state: query_state } }
                             div { class: "flex flex-row p-1",
                                 div { class: "basis-1/2 p-1",
                                     update_query { query: query, targets: targets, query_state: query_state }
@@ -163,6 +285,12 @@ fn app(cx: Scope) -> Element {
                                 br {}
                                 div { class: "basis-1/2 p-1", id: "get_html", get_html { query: query } }
                             }
+                            div { class: "flex flex-row p-1",
+                                div { class: "basis-1/2 p-1",
+                                    set_compare_query { query: query, compare_query: compare_query, query_state: query_state }
+                                }
+                                div { class: "basis-1/2 p-1", id: "get_compare_html", get_compare_html { query: query, compare_query: compare_query } }
+                            }
                         }
                     }
                 }
@@ -236,6 +364,7 @@ fn data_list<'a>(
 fn get_preset_list<'a, T>(cx: Scope<'a, T>, message: &'a str, rois: &'a UseState<FxHashMap<String, SequenceQuerySpec>>) {
     let message = message.to_string();
     let rois = rois.to_owned();
+    let prefix = message.clone();
     
     cx.spawn(async move {
         let (mut _ws, mut wsio) = WsMeta::connect("ws://omnix:3000/ws", None)
@@ -249,7 +378,13 @@ fn get_preset_list<'a, T>(cx: Scope<'a, T>, message: &'a str, rois: &'a UseState
         let msg = wsio.next().await;
         let result = msg.expect_throw("Stream closed");
         if let WsMessage::Text(result) = result.clone() {
-            rois.set(serde_json::from_str(&result[..]).unwrap());
+            let mut new_rois: FxHashMap<String, SequenceQuerySpec> = serde_json::from_str(&result[..]).unwrap();
+            new_rois.extend(
+                load_custom_rois()
+                    .into_iter()
+                    .filter(|(k, _)| k.starts_with(&prefix)),
+            );
+            rois.set(new_rois);
         }
 
         // let window = web_sys::window().expect("global window does not exists");    
@@ -297,11 +432,76 @@ fn get_targets<'a, T>(
     })
 }
 
+// a row of the hit summary table
+#[derive(Clone)]
+struct HitRow {
+    sid: u32,
+    ctg: String,
+    src: String,
+    n_hits: usize,
+    q_bgn: u32,
+    q_end: u32,
+    q_len: u32,
+    t_bgn: u32,
+    t_end: u32,
+    t_len: u32,
+}
+
+const HIT_TABLE_COLUMNS: [&str; 8] = [
+    "sid",
+    "contig",
+    "source",
+    "hit count",
+    "query span",
+    "query len",
+    "target span",
+    "target len",
+];
+
+fn compare_hit_rows(a: &HitRow, b: &HitRow, column: usize) -> std::cmp::Ordering {
+    match column {
+        0 => a.sid.cmp(&b.sid),
+        1 => a.ctg.cmp(&b.ctg),
+        2 => a.src.cmp(&b.src),
+        3 => a.n_hits.cmp(&b.n_hits),
+        4 => (a.q_bgn, a.q_end).cmp(&(b.q_bgn, b.q_end)),
+        5 => a.q_len.cmp(&b.q_len),
+        6 => (a.t_bgn, a.t_end).cmp(&(b.t_bgn, b.t_end)),
+        _ => a.t_len.cmp(&b.t_len),
+    }
+}
+
+// the rows as a CSV file in a data URL
+fn hit_rows_to_csv_url(rows: &[HitRow]) -> String {
+    let mut csv = "sid,contig,source,hit_count,q_bgn,q_end,q_len,t_bgn,t_end,t_len\n".to_string();
+    rows.iter().for_each(|r| {
+        csv.push_str(&format!(
+            "{},\"{}\",\"{}\",{},{},{},{},{},{},{}\n",
+            r.sid, r.ctg, r.src, r.n_hits, r.q_bgn, r.q_end, r.q_len, r.t_bgn, r.t_end, r.t_len
+        ));
+    });
+    let encoded = csv
+        .bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b"-_.,".contains(&b) {
+                (b as char).to_string()
+            } else {
+                format!("%{:02X}", b)
+            }
+        })
+        .collect::<String>();
+    "data:text/csv;charset=utf-8,".to_string() + &encoded
+}
+
 #[inline_props]
 pub fn query_results<'a>(
     cx: Scope<'a>,
     targets: &'a UseState<Option<TargetMatchPrincipalBundles>>,
 ) -> Element<'a> {
+    // (the column to sort the rows, ascending)
+    let sort_by = use_state(cx, || (0_usize, true));
+    let source_filter = use_state(cx, || "".to_string());
+
     let targets = targets.current().as_ref().clone();
     if targets.is_none() {
         log::debug!("target none");
@@ -325,6 +525,34 @@ pub fn query_results<'a>(
     let bgn = query.bgn;
     let end = query.end;
 
+    let (sort_column, ascending) = *sort_by.get();
+    let mut rows = targets
+        .match_summary
+        .iter()
+        .flat_map(|(sid, hits)| {
+            let (ctg, src) = *sid_to_ctg_src.get(sid).unwrap();
+            hits.iter().map(move |ms| HitRow {
+                sid: *sid,
+                ctg: ctg.clone(),
+                src: src.clone(),
+                n_hits: ms.num_hits,
+                q_bgn: ms.q_bgn,
+                q_end: ms.q_end,
+                q_len: ms.q_end.abs_diff(ms.q_bgn),
+                t_bgn: ms.t_bgn,
+                t_end: ms.t_end,
+                t_len: ms.t_end.abs_diff(ms.t_bgn),
+            })
+        })
+        .filter(|r| r.src.contains(source_filter.get().as_str()))
+        .collect::<Vec<_>>();
+    rows.sort_by(|a, b| {
+        let ordering = compare_hit_rows(a, b, sort_column);
+        if ascending { ordering } else { ordering.reverse() }
+    });
+    let csv_url = hit_rows_to_csv_url(&rows);
+    let n_rows = rows.len();
+
     cx.render ( {
         
     rsx!{
@@ -333,64 +561,59 @@ pub fn query_results<'a>(
                 h2 { class: "px-8 py-2", p { "Returned Hits for Query: {ctg}:{bgn}-{end}" } }
                 div { class: "px-8 content-center overflow-auto min-w-[1280px] max-h-[60px]" }
             }
+            div { class: "flex flex-row px-8 py-1 space-x-2",
+                input {
+                    class: "basis-1/2 px-3 py-1.5",
+                    r#type: "text",
+                    placeholder: "filter by source",
+                    value: "{source_filter}",
+                    oninput: move |evt| source_filter.set(evt.value.clone()),
+                }
+                p { class: "basis-1/4 py-1.5", "{n_rows} hits" }
+                a {
+                    class: "basis-1/4 middle none center rounded-lg px-2 py-1.5 bg-blue-600 text-white",
+                    href: "{csv_url}",
+                    download: "hits_{ctg}_{bgn}_{end}.csv",
+                    "Download CSV"
+                }
+            }
             //hr { class: "my-2 h-px bg-gray-700 border-0 dark:bg-gray-700" }
             div { class: "flex flex-col px-8 py-1",
                 div { class: "flex-grow overflow-auto max-h-[650px]",
                     table { class: "table-fixed relative w-full",
                         thead {
                             tr {
-                                th { class: "px-1 py-2 sticky top-0 text-blue-900 bg-blue-300",
-                                    "sid"
-                                }
-                                th { class: "px-1 py-2 sticky top-0 text-blue-900 bg-blue-300",
-                                    "contig"
-                                }
-                                th { class: "px-1 py-2 sticky top-0 text-blue-900 bg-blue-300",
-                                    "source"
-                                }
-                                th { class: "px-1 py-2 sticky top-0 text-blue-900 bg-blue-300",
-                                    "hit count"
-                                }
-                                th { class: "px-1 py-2 sticky top-0 text-blue-900 bg-blue-300",
-                                    "query span"
-                                }
-                                th { class: "px-1 py-2 sticky top-0 text-blue-900 bg-blue-300",
-                                    "query len"
-                                }
-                                th { class: "px-1 py-2 sticky top-0 text-blue-900 bg-blue-300",
-                                    "target span"
-                                }
-                                th { class: "px-1 py-2 sticky top-0 text-blue-900 bg-blue-300",
-                                    "target len"
-                                }
+                                HIT_TABLE_COLUMNS.iter().enumerate().map(|(column, name)| {
+                                    let arrow = if column != sort_column { "" } else if ascending { " \u{25b2}" } else { " \u{25bc}" };
+                                    rsx!( th { class: "px-1 py-2 sticky top-0 text-blue-900 bg-blue-300 cursor-pointer",
+                                        onclick: move |_evt| {
+                                            if column == sort_column {
+                                                sort_by.set((column, !ascending));
+                                            } else {
+                                                sort_by.set((column, true));
+                                            }
+                                        },
+                                        "{name}{arrow}"
+                                    })
+                                })
                             }
                         }
                         tbody { class: "divide-y",
-                            rsx!(targets.match_summary.iter().map(|v| {
-                                        let sid = v.0;
-                                        let (ctg, src) = *sid_to_ctg_src.get(&sid).unwrap();
-                                        let style_classes = "px-1 py-2 text-center break-all";
-                                        let hit_summary = v.1.iter().map(move |match_summary| {
-                                            let ms = match_summary;
-                                            let q_span = format!("{}-{}", ms.q_bgn, ms.q_end);
-                                            let t_span = format!("{}-{}", ms.t_bgn, ms.t_end);
-                                            let q_len = if ms.q_end > ms.q_bgn { ms.q_end - ms.q_bgn } else { ms.q_bgn - ms.q_end };
-                                            let t_len = if ms.t_end > ms.t_bgn { ms.t_end - ms.t_bgn } else { ms.t_bgn - ms.t_end };
-                                            let n_hits = ms.num_hits;
-                                            rsx!( tr {
-                                                td { p {class: "{style_classes}", "{sid}"}}  
-                                                td { p {class: "{style_classes}", "{ctg}"}} 
-                                                td { p {class: "{style_classes}", "{src}"}}
-                                                td { p {class: "{style_classes}", "{n_hits}"}}
-                                                td { p {class: "{style_classes}", "{q_span}"}}
-                                                td { p {class: "{style_classes}", "{q_len}"}}
-                                                td { p {class: "{style_classes}", "{t_span}"}}
-                                                td { p {class: "{style_classes}", "{t_len}"}}
-                                                } )
-                                        });
-                                            
-                                    rsx!( hit_summary )
-                                    }))
+                            rows.iter().map(|r| {
+                                let style_classes = "px-1 py-2 text-center break-all";
+                                let q_span = format!("{}-{}", r.q_bgn, r.q_end);
+                                let t_span = format!("{}-{}", r.t_bgn, r.t_end);
+                                rsx!( tr {
+                                    td { p {class: "{style_classes}", "{r.sid}"}}
+                                    td { p {class: "{style_classes}", "{r.ctg}"}}
+                                    td { p {class: "{style_classes}", "{r.src}"}}
+                                    td { p {class: "{style_classes}", "{r.n_hits}"}}
+                                    td { p {class: "{style_classes}", "{q_span}"}}
+                                    td { p {class: "{style_classes}", "{r.q_len}"}}
+                                    td { p {class: "{style_classes}", "{t_span}"}}
+                                    td { p {class: "{style_classes}", "{r.t_len}"}}
+                                    } )
+                            })
                         }
                     }
                 }
@@ -530,6 +753,68 @@ fn set_parameters<'a>(cx: Scope<'a>, query: &'a UseState<SequenceQuerySpec>) ->
     })
 }
 
+#[inline_props]
+fn set_region<'a>(cx: Scope<'a>, query: &'a UseState<SequenceQuerySpec>) -> Element<'a> {
+    let region = format!("{}:{}-{}", query.ctg, query.bgn, query.end);
+    cx.render(rsx! {
+        div { class: "flex flex-row p-0",
+            div { class: "basis-2/4", "Region:" }
+            input {
+                class: "basis-2/4 px-3 py-1.5",
+                name: "region",
+                r#type: "text",
+                placeholder: "ctg:bgn-end",
+                value: "{region}",
+                oninput: move |evt| {
+                    if let Some((ctg, bgn, end)) = parse_region(&evt.value) {
+                        let mut new_query = (*query.get()).clone();
+                        new_query.ctg = ctg;
+                        new_query.bgn = bgn;
+                        new_query.end = end;
+                        query.set(new_query);
+                    }
+                }
+            }
+        }
+    })
+}
+
+#[inline_props]
+fn save_query<'a>(
+    cx: Scope<'a>,
+    query: &'a UseState<SequenceQuerySpec>,
+    rois: &'a UseState<FxHashMap<String, SequenceQuerySpec>>,
+    query_state: &'a UseState<String>,
+) -> Element<'a> {
+    let roi_name = use_state(cx, || "".to_string());
+    cx.render(rsx! {
+        div { class: "flex flex-row p-0",
+            input {
+                class: "basis-1/2 px-3 py-1.5",
+                r#type: "text",
+                placeholder: "custom ROI name",
+                value: "{roi_name}",
+                oninput: move |evt| roi_name.set(evt.value.clone()),
+            }
+            button {
+                class: "basis-1/2 middle none center rounded-lg px-2 py-1.5 bg-blue-600 text-white",
+                onclick: move |_evt| {
+                    let name = roi_name.get().trim().to_string();
+                    if name.is_empty() {
+                        return;
+                    }
+                    save_custom_roi(&name, query.get());
+                    let mut new_rois = rois.get().clone();
+                    new_rois.insert(name.clone(), query.get().clone());
+                    rois.set(new_rois);
+                    query_state.set(format!("the query is saved as the custom ROI {}", name));
+                },
+                "Save as Custom ROI"
+            }
+        }
+    })
+}
+
 #[inline_props]
 pub fn get_html<'a>(cx: Scope<'a>, query: &'a UseState<SequenceQuerySpec>) -> Element<'a> {
     let query = query.current().as_ref().clone();
@@ -549,6 +834,63 @@ pub fn get_html<'a>(cx: Scope<'a>, query: &'a UseState<SequenceQuerySpec>) -> El
     })
 }
 
+#[inline_props]
+fn set_compare_query<'a>(
+    cx: Scope<'a>,
+    query: &'a UseState<SequenceQuerySpec>,
+    compare_query: &'a UseState<Option<SequenceQuerySpec>>,
+    query_state: &'a UseState<String>,
+) -> Element<'a> {
+    cx.render({
+        rsx! {
+            button {
+                class: "middle none center w-full rounded-lg px-2 py-1.5 bg-blue-600 text-white",
+                onclick: move |_evt| {
+                    let query0 = query.get().clone();
+                    query_state.set(format!("comparing to {}:{}-{}", query0.ctg, query0.bgn, query0.end));
+                    compare_query.set(Some(query0));
+                },
+                "Set as Left Region"
+            }
+        }
+    })
+}
+
+// the current query is the right region of the comparison
+#[inline_props]
+pub fn get_compare_html<'a>(
+    cx: Scope<'a>,
+    query: &'a UseState<SequenceQuerySpec>,
+    compare_query: &'a UseState<Option<SequenceQuerySpec>>,
+) -> Element<'a> {
+    let Some(left) = compare_query.current().as_ref().clone() else {
+        return cx.render(rsx! {
+            button {
+                class: "middle none center w-full rounded-lg px-2 py-1.5 bg-gray-400 text-white",
+                disabled: true,
+                p { "Compare" }
+            }
+        });
+    };
+    let compare = CompareQuerySpec {
+        left,
+        right: query.current().as_ref().clone(),
+    };
+    let query_url = {
+        let qstr = serde_qs::to_string(&compare).unwrap();
+        base_url() + "/api/get_compare_html?" + &qstr[..]
+    };
+
+    cx.render({
+        rsx! {
+            button {
+                class: "middle none center w-full rounded-lg px-2 py-1.5 bg-blue-600 text-white",
+                a { class: "w-full", href: "{query_url}", target: "_blank", p { "Compare" } }
+            }
+        }
+    })
+}
+
 #[inline_props]
 pub fn update_query<'a>(
     cx: Scope<'a>,
@@ -3,10 +3,11 @@ use std::sync::Arc;
 
 use pgr_db::ext::{get_principal_bundle_decomposition, SeqIndexDB};
 use rayon::prelude::*;
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
 use serde::{Deserialize, Serialize};
 use svg::node::{self, element, Node};
 use svg::Document;
+use utoipa::{IntoParams, ToSchema};
 
 static CMAP: [&str; 97] = [
     "#870098", "#00aaa5", "#3bff00", "#ec0000", "#00a2c3", "#00f400", "#ff1500", "#0092dd",
@@ -23,7 +24,7 @@ static CMAP: [&str; 97] = [
     "#0000dd", "#009f00", "#f4e200", "#0000b9", "#00a248", "#dcf400", "#2d00a4", "#00aa8d",
     "#bcff00",
 ];
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct MatchSummary {
     pub q_bgn: u32,
     pub q_end: u32,
@@ -33,15 +34,37 @@ pub struct MatchSummary {
     pub reversed: bool,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct TargetMatchPrincipalBundles {
     pub query: SequenceQuerySpec,
+    #[schema(value_type = Vec<Object>)]
     pub match_summary: Vec<(u32, Vec<MatchSummary>)>, // (t_id, vec[(q_bgn, q_end, t_bgn, t_end, num_hits, reversed)])
+    #[schema(value_type = Vec<Object>)]
     pub sid_ctg_src: Vec<(u32, String, String)>,
     pub bundle_bed_records: Vec<Vec<PrincipalBundleBedRecord>>,
+    /// the total number of the matched sequences, `match_summary` only has the page set by the offset and the limit
+    #[serde(default)]
+    pub n_matched_seqs: usize,
+    /// the representative sequence of each principal bundle, (bundle id, (segment name, sequence))
+    #[serde(skip)]
+    pub bundle_seqs: FxHashMap<u32, (String, Vec<u8>)>,
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+/// two query regions decomposed together for a side-by-side comparison, the bundle ids are shared by the sequences
+/// of the two regions, the shimmer and the decomposition parameters of the left query are used for both regions
+#[derive(Deserialize, Serialize, Clone, Debug, ToSchema)]
+pub struct CompareQuerySpec {
+    pub left: SequenceQuerySpec,
+    pub right: SequenceQuerySpec,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ComparePrincipalBundles {
+    pub left: TargetMatchPrincipalBundles,
+    pub right: TargetMatchPrincipalBundles,
+}
+
+#[derive(Deserialize, Serialize, Clone, ToSchema)]
 pub struct PrincipalBundleBedRecord {
     pub ctg: String,
     pub bgn: u32,
@@ -63,7 +86,8 @@ pub struct ShmmrSpec {
     pub sketch: bool,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, ToSchema, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct SequenceQuerySpec {
     pub source: String,
     pub ctg: String,
@@ -81,6 +105,109 @@ pub struct SequenceQuerySpec {
     pub min_branch_size: usize,
     pub bundle_length_cutoff: usize,
     pub bundle_merge_distance: usize,
+    /// the number of the matched sequences (ordered by the sequence ids) to skip
+    #[serde(default)]
+    pub offset: usize,
+    /// the max number of the matched sequences to return, all are returned if not set
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// "summary" or "full" (default)
+    #[serde(default)]
+    pub detail: ResponseDetail,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseDetail {
+    /// the match summary only, the principal bundle decomposition is skipped
+    Summary,
+    /// the match summary and the principal bundle decomposition of the matched sequences
+    #[default]
+    Full,
+}
+
+/// a field-level error of an invalid query
+#[derive(Deserialize, Serialize, Debug, Clone, ToSchema)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// the JSON body of an error response
+#[derive(Deserialize, Serialize, Debug, Clone, ToSchema)]
+pub struct ApiError {
+    /// the HTTP status code
+    pub code: u16,
+    pub message: String,
+    /// the offending fields of an invalid query
+    pub errors: Vec<FieldError>,
+}
+
+impl SequenceQuerySpec {
+    /// check the query region and the shimmer parameters against the database, the field-level errors are returned
+    pub fn validate(&self, seq_db: &SeqIndexDB) -> Vec<FieldError> {
+        let mut errors = vec![];
+        let mut add_error = |field: &str, message: String| {
+            errors.push(FieldError {
+                field: field.to_string(),
+                message,
+            })
+        };
+        match seq_db
+            .seq_index
+            .as_ref()
+            .and_then(|seq_index| seq_index.get(&(self.ctg.clone(), Some(self.source.clone()))))
+        {
+            None => add_error(
+                "ctg",
+                format!("{}::{} is not in the database", self.source, self.ctg),
+            ),
+            Some((_sid, len)) => {
+                if self.end > *len as usize {
+                    add_error(
+                        "end",
+                        format!("{} is beyond the sequence length {}", self.end, len),
+                    );
+                }
+            }
+        }
+        if self.bgn >= self.end {
+            add_error("bgn", "bgn must be smaller than end".to_string());
+        }
+        if !self.sketch && (self.w == 0 || self.w > 128) {
+            add_error("w", "w must be between 1 and 128".to_string());
+        }
+        if self.k == 0 || self.k > 56 {
+            add_error("k", "k must be between 1 and 56".to_string());
+        }
+        if self.r == 0 || self.r > 12 {
+            add_error("r", "r must be between 1 and 12".to_string());
+        }
+        errors
+    }
+
+    /// the shimmer spec for building the sub-database of the matched sequences for the decomposition
+    pub fn pb_shmmr_spec(&self) -> pgr_db::ext::ShmmrSpec {
+        pgr_db::ext::ShmmrSpec::new(self.w, self.k, self.r, self.min_span, self.sketch)
+    }
+}
+
+impl CompareQuerySpec {
+    /// check the two queries, the fields of the errors are prefixed with "left." or "right."
+    pub fn validate(&self, seq_db: &SeqIndexDB) -> Vec<FieldError> {
+        [("left", &self.left), ("right", &self.right)]
+            .into_iter()
+            .flat_map(|(side, seq_query_spec)| {
+                seq_query_spec
+                    .validate(seq_db)
+                    .into_iter()
+                    .map(move |err| FieldError {
+                        field: format!("{}.{}", side, err.field),
+                        message: err.message,
+                    })
+            })
+            .collect()
+    }
 }
 
 #[allow(clippy::type_complexity)]
@@ -162,10 +289,27 @@ fn group_smps_by_principle_bundle_id(
     rtn_partitions
 }
 
-pub fn get_target_and_principal_bundle_decomposition(
+// the name of a matched sub-sequence, it is also a region string for pgr-fetch
+fn target_seq_name(src: &str, ctg: &str, bgn: u32, end: u32) -> String {
+    format!("{}::{}:{}-{}", src, ctg, bgn, end)
+}
+
+// the matched sequences in the page set by the offset and the limit of the query, the total number of
+// the matched sequences is returned with the match summary, the target sequences are not fetched for a summary query
+#[allow(clippy::type_complexity)]
+fn get_target_seq_list(
     seq_query_spec: &SequenceQuerySpec,
-    seq_db: Arc<SeqIndexDB>,
-) -> Option<TargetMatchPrincipalBundles> {
+    seq_db: &SeqIndexDB,
+    progress: &dyn Fn(&str, u32),
+) -> Result<
+    (
+        usize,
+        Vec<(u32, Vec<MatchSummary>)>,
+        Vec<(u32, String, String)>,
+        Vec<(String, Vec<u8>)>,
+    ),
+    std::io::Error,
+> {
     let sample_name = seq_query_spec.source.clone();
     let ctg_name = seq_query_spec.ctg.clone();
     let padding = seq_query_spec.padding;
@@ -192,9 +336,8 @@ pub fn get_target_and_principal_bundle_decomposition(
         seq_query_spec.end + padding
     };
 
-    let sub_seq = seq_db
-        .get_sub_seq(sample_name, ctg_name, q_seq_bgn, q_seq_end)
-        .unwrap();
+    progress("fetching sequence", 0);
+    let sub_seq = seq_db.get_sub_seq(sample_name, ctg_name, q_seq_bgn, q_seq_end)?;
 
     // println!(
     //     "DBG: sub_seq_len {:?} {} {}",
@@ -203,9 +346,10 @@ pub fn get_target_and_principal_bundle_decomposition(
     //     q_seq_end
     // );
 
+    progress("querying index", 10);
     let query_results = seq_db.query_fragment_to_hps_from_mmap_file(
         &sub_seq,
-        0.25,
+        0.25.into(),
         Some(128),
         Some(128),
         Some(128),
@@ -356,7 +500,8 @@ pub fn get_target_and_principal_bundle_decomposition(
         let match_summary = aln_range
             .into_iter()
             .map(|(sid, rgns)| {
-                let (ctg, _src, _ctg_len) = seq_db.seq_info.as_ref().unwrap().get(&sid).unwrap();
+                let (ctg, src, _ctg_len) = seq_db.seq_info.as_ref().unwrap().get(&sid).unwrap();
+                let src = src.as_deref().unwrap_or("N/A");
                 let hits = rgns
                     .into_iter()
                     .map(|(b, e, _, orientation, mut aln)| {
@@ -384,7 +529,7 @@ pub fn get_target_and_principal_bundle_decomposition(
                         v.t_bgn,
                         v.t_end,
                         if v.reversed { 1 } else { 0 },
-                        ctg.clone(),
+                        target_seq_name(src, ctg, v.t_bgn, v.t_end),
                     ))
                 });
                 (sid, hits)
@@ -395,12 +540,39 @@ pub fn get_target_and_principal_bundle_decomposition(
         (vec![], vec![])
     };
 
+    let n_matched_seqs = match_summary.len();
+    let mut match_summary = match_summary;
+    match_summary.sort_by_key(|(sid, _)| *sid);
+    let match_summary = match_summary
+        .into_iter()
+        .skip(seq_query_spec.offset)
+        .take(seq_query_spec.limit.unwrap_or(usize::MAX))
+        .collect::<Vec<_>>();
+    let page_sids = match_summary
+        .iter()
+        .map(|(sid, _)| *sid)
+        .collect::<FxHashSet<u32>>();
+    let mut sid_ctg_src = sid_ctg_src;
+    sid_ctg_src.retain(|(sid, _, _)| page_sids.contains(sid));
+    sid_ctg_src.sort();
+    let mut sub_seq_range_for_fasta = sub_seq_range_for_fasta;
+    if seq_query_spec.detail == ResponseDetail::Summary {
+        sub_seq_range_for_fasta.clear();
+    } else {
+        sub_seq_range_for_fasta.retain(|(sid, _, _, _, _)| page_sids.contains(sid));
+    }
+
+    progress("fetching target sequences", 40);
+    let target_seqs = seq_db.get_sub_seqs_by_id(
+        sub_seq_range_for_fasta
+            .iter()
+            .map(|(sid, b, e, _, _)| (*sid, *b as usize, *e as usize))
+            .collect(),
+    )?;
     let seq_list = sub_seq_range_for_fasta
         .par_iter()
-        .map(|(sid, b, e, orientation, target_seq_name)| {
-            let target_seq = seq_db
-                .get_sub_seq_by_id(*sid, *b as usize, *e as usize)
-                .unwrap();
+        .zip(target_seqs)
+        .map(|((_, _, _, orientation, target_seq_name), target_seq)| {
             let target_seq = if *orientation == 1 {
                 pgr_db::fasta_io::reverse_complement(&target_seq)
             } else {
@@ -409,26 +581,69 @@ pub fn get_target_and_principal_bundle_decomposition(
             (target_seq_name.into(), target_seq)
         })
         .collect::<Vec<(String, Vec<u8>)>>();
+    Ok((n_matched_seqs, match_summary, sid_ctg_src, seq_list))
+}
+
+pub fn get_target_and_principal_bundle_decomposition(
+    seq_query_spec: &SequenceQuerySpec,
+    seq_db: Arc<SeqIndexDB>,
+) -> Result<TargetMatchPrincipalBundles, std::io::Error> {
+    get_target_and_principal_bundle_decomposition_with_progress(seq_query_spec, seq_db, &|_, _| {})
+}
 
+/// the principal bundle decomposition with the stages and the percentages of the progress reported to `progress`
+pub fn get_target_and_principal_bundle_decomposition_with_progress(
+    seq_query_spec: &SequenceQuerySpec,
+    seq_db: Arc<SeqIndexDB>,
+    progress: &dyn Fn(&str, u32),
+) -> Result<TargetMatchPrincipalBundles, std::io::Error> {
+    let (n_matched_seqs, match_summary, sid_ctg_src, seq_list) =
+        get_target_seq_list(seq_query_spec, &seq_db, progress)?;
+
+    // no sub-database is built if only the summary is requested or no sequence passes the filters
+    if seq_query_spec.detail == ResponseDetail::Summary || seq_list.is_empty() {
+        return Ok(TargetMatchPrincipalBundles {
+            query: (*seq_query_spec).clone(),
+            match_summary,
+            sid_ctg_src,
+            bundle_bed_records: vec![],
+            n_matched_seqs,
+            bundle_seqs: FxHashMap::default(),
+        });
+    }
+
+    let (bundle_bed_records, bundle_seqs) =
+        get_principal_bundle_bed_records(seq_query_spec, seq_list, progress)?;
+
+    Ok(TargetMatchPrincipalBundles {
+        query: (*seq_query_spec).clone(),
+        match_summary,
+        sid_ctg_src,
+        bundle_bed_records,
+        n_matched_seqs,
+        bundle_seqs,
+    })
+}
+
+// decompose the sequences into the principal bundles, the bundle bed records of each sequence (sorted by the sequence
+// names) and the representative sequences of the bundles are returned
+#[allow(clippy::type_complexity)]
+fn get_principal_bundle_bed_records(
+    seq_query_spec: &SequenceQuerySpec,
+    seq_list: Vec<(String, Vec<u8>)>,
+    progress: &dyn Fn(&str, u32),
+) -> Result<
+    (
+        Vec<Vec<PrincipalBundleBedRecord>>,
+        FxHashMap<u32, (String, Vec<u8>)>,
+    ),
+    std::io::Error,
+> {
+    progress("building sub-db", 50);
     let mut new_seq_db = SeqIndexDB::new();
-    let shmmr_spec = ShmmrSpec {
-        w: seq_query_spec.w,
-        k: seq_query_spec.k,
-        r: seq_query_spec.r,
-        min_span: seq_query_spec.r,
-        sketch: seq_query_spec.sketch,
-    };
+    let shmmr_spec = seq_query_spec.pb_shmmr_spec();
 
-    new_seq_db
-        .load_from_seq_list(
-            seq_list,
-            "Memory".into(),
-            shmmr_spec.w,
-            shmmr_spec.k,
-            shmmr_spec.r,
-            shmmr_spec.min_span,
-        )
-        .expect("can't load seq_db");
+    new_seq_db.load_from_seq_list_with_spec(seq_list, "Memory".into(), shmmr_spec.clone())?;
 
     let (principal_bundles_with_id, vertex_to_bundle_id_direction_pos) = new_seq_db
         .get_principal_bundles_with_id(
@@ -442,15 +657,17 @@ pub fn get_target_and_principal_bundle_decomposition(
         .map(|v| (v.0, v.2.len()))
         .collect::<FxHashMap<usize, usize>>();
 
+    progress("decomposing", 70);
     let sid_smps =
         get_principal_bundle_decomposition(&vertex_to_bundle_id_direction_pos, &new_seq_db);
     let sid_smps: FxHashMap<u32, Vec<_>> = sid_smps.into_iter().collect();
 
     let mut seq_info = new_seq_db
         .seq_info
+        .as_ref()
         .unwrap()
-        .into_iter()
-        .map(|(k, v)| (k, v))
+        .iter()
+        .map(|(k, v)| (*k, v.clone()))
         .collect::<Vec<_>>();
 
     let mut repeat_count = FxHashMap::<u32, Vec<u32>>::default();
@@ -508,14 +725,268 @@ pub fn get_target_and_principal_bundle_decomposition(
         })
         .collect::<Vec<Vec<PrincipalBundleBedRecord>>>();
 
-    Some(TargetMatchPrincipalBundles {
-        query: (*seq_query_spec).clone(),
-        match_summary,
-        sid_ctg_src,
-        bundle_bed_records,
+    // the longest segment of each principal bundle is taken as the representative sequence in the bundle orientation
+    let mut bundle_representatives =
+        FxHashMap::<u32, (u32, u32, &PrincipalBundleBedRecord)>::default();
+    seq_info
+        .iter()
+        .zip(bundle_bed_records.iter())
+        .for_each(|((sid, (_, _, len)), records)| {
+            records.iter().for_each(|record| {
+                let e = bundle_representatives
+                    .entry(record.b_id)
+                    .or_insert((*sid, *len, record));
+                if record.end - record.bgn > e.2.end - e.2.bgn {
+                    *e = (*sid, *len, record);
+                }
+            })
+        });
+    let bundle_seqs = bundle_representatives
+        .into_iter()
+        .map(|(b_id, (sid, len, record))| {
+            let end = record.end.min(len);
+            let seq = new_seq_db.get_sub_seq_by_id(sid, record.bgn as usize, end as usize)?;
+            let seq = if record.b_direction == 1 {
+                pgr_db::fasta_io::reverse_complement(&seq)
+            } else {
+                seq
+            };
+            let name = format!("{}:{}-{}", record.ctg, record.bgn, end);
+            Ok((b_id, (name, seq)))
+        })
+        .collect::<Result<FxHashMap<u32, (String, Vec<u8>)>, std::io::Error>>()?;
+
+    Ok((bundle_bed_records, bundle_seqs))
+}
+
+/// decompose the sequences matched to the two query regions together so the bundles of the two regions are comparable
+pub fn get_compare_principal_bundle_decomposition(
+    compare_query_spec: &CompareQuerySpec,
+    seq_db: Arc<SeqIndexDB>,
+) -> Result<ComparePrincipalBundles, std::io::Error> {
+    let (left_n_matched_seqs, left_match_summary, left_sid_ctg_src, left_seq_list) =
+        get_target_seq_list(&compare_query_spec.left, &seq_db, &|_, _| {})?;
+    let (right_n_matched_seqs, right_match_summary, right_sid_ctg_src, right_seq_list) =
+        get_target_seq_list(&compare_query_spec.right, &seq_db, &|_, _| {})?;
+
+    // a contig can be matched by both regions, the names are tagged with the side to keep them apart
+    let seq_list = left_seq_list
+        .into_iter()
+        .map(|(name, seq)| (format!("left/{}", name), seq))
+        .chain(
+            right_seq_list
+                .into_iter()
+                .map(|(name, seq)| (format!("right/{}", name), seq)),
+        )
+        .collect::<Vec<_>>();
+    let (bundle_bed_records, bundle_seqs) = if seq_list.is_empty() {
+        (vec![], FxHashMap::default())
+    } else {
+        get_principal_bundle_bed_records(&compare_query_spec.left, seq_list, &|_, _| {})?
+    };
+
+    let mut left_bundle_bed_records = vec![];
+    let mut right_bundle_bed_records = vec![];
+    bundle_bed_records.into_iter().for_each(|mut records| {
+        let Some(ctg) = records.first().map(|r| r.ctg.clone()) else {
+            return;
+        };
+        let (side, prefix) = if ctg.starts_with("left/") {
+            (&mut left_bundle_bed_records, "left/")
+        } else {
+            (&mut right_bundle_bed_records, "right/")
+        };
+        records.iter_mut().for_each(|r| {
+            r.ctg = r.ctg[prefix.len()..].to_string();
+        });
+        side.push(records);
+    });
+
+    Ok(ComparePrincipalBundles {
+        left: TargetMatchPrincipalBundles {
+            query: compare_query_spec.left.clone(),
+            match_summary: left_match_summary,
+            sid_ctg_src: left_sid_ctg_src,
+            bundle_bed_records: left_bundle_bed_records,
+            n_matched_seqs: left_n_matched_seqs,
+            bundle_seqs: bundle_seqs.clone(),
+        },
+        right: TargetMatchPrincipalBundles {
+            query: compare_query_spec.right.clone(),
+            match_summary: right_match_summary,
+            sid_ctg_src: right_sid_ctg_src,
+            bundle_bed_records: right_bundle_bed_records,
+            n_matched_seqs: right_n_matched_seqs,
+            bundle_seqs,
+        },
     })
 }
 
+/// build the MAP graph of the sequences matched to the query region and return it as a sequence-resolved GFA
+pub fn get_region_gfa(
+    seq_query_spec: &SequenceQuerySpec,
+    seq_db: Arc<SeqIndexDB>,
+) -> Result<String, std::io::Error> {
+    let (_, _, _, seq_list) = get_target_seq_list(seq_query_spec, &seq_db, &|_, _| {})?;
+    if seq_list.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "no sequence matched to the region",
+        ));
+    }
+
+    let mut new_seq_db = SeqIndexDB::new();
+    new_seq_db.load_from_seq_list_with_spec(
+        seq_list,
+        "Memory".into(),
+        seq_query_spec.pb_shmmr_spec(),
+    )?;
+
+    let mut gfa = Vec::<u8>::new();
+    new_seq_db.write_mapg_gfa(
+        seq_query_spec.min_cov,
+        "from_fragmap",
+        None,
+        None,
+        true,
+        &mut gfa,
+    )?;
+    Ok(String::from_utf8_lossy(&gfa[..]).into_owned())
+}
+
+// the legend table of the bundles: the color, the mean segment length and the distribution of the copy numbers
+// over the shown sequences, a bundle is highlighted by clicking its row and hidden with the checkbox
+fn bundle_legend_html(targets: &TargetMatchPrincipalBundles) -> String {
+    let n_seqs = targets.bundle_bed_records.len();
+    // bundle id -> (the copy numbers in the sequences, the total segment length)
+    let mut bundle_stats = FxHashMap::<u32, (Vec<usize>, u64)>::default();
+    targets
+        .bundle_bed_records
+        .iter()
+        .enumerate()
+        .for_each(|(seq_idx, records)| {
+            records.iter().for_each(|r| {
+                let e = bundle_stats
+                    .entry(r.b_id)
+                    .or_insert_with(|| (vec![0; n_seqs], 0));
+                e.0[seq_idx] += 1;
+                e.1 += (r.end - r.bgn) as u64;
+            });
+        });
+    let mut bundle_ids = bundle_stats.keys().copied().collect::<Vec<_>>();
+    bundle_ids.sort();
+
+    let rows = bundle_ids
+        .into_iter()
+        .map(|bundle_id| {
+            let (copy_numbers, total_length) = bundle_stats.get(&bundle_id).unwrap();
+            let n_segments = copy_numbers.iter().sum::<usize>();
+            let mut copy_number_count = FxHashMap::<usize, usize>::default();
+            copy_numbers.iter().for_each(|&c| {
+                *copy_number_count.entry(c).or_insert(0) += 1;
+            });
+            let mut copy_number_count = copy_number_count.into_iter().collect::<Vec<_>>();
+            copy_number_count.sort();
+            let distribution = copy_number_count
+                .into_iter()
+                .map(|(c, n)| format!("{}:{}", c, n))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let bundle_class = format!("bundle_{bundle_id:05}");
+            format!(
+                r#"<tr id="legend_{bundle_class}">
+<td><input type="checkbox" checked onchange="set_bundle_visibility('{bundle_class}', this.checked)"></td>
+<td onclick="toggle_highlight('{bundle_class}')"><svg width="24" height="10"><rect class="{bundle_class}" width="24" height="10"/></svg></td>
+<td onclick="toggle_highlight('{bundle_class}')">{bundle_id}</td>
+<td>{}</td>
+<td>{n_segments}</td>
+<td>{distribution}</td>
+</tr>"#,
+                total_length / n_segments as u64
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!(
+        r#"<table id="bundle_legend" style="font-family:monospace; font-size:12px">
+<thead><tr><th>show</th><th>color</th><th>bundle</th><th>mean length</th><th>segments</th><th>copy number:sequences</th></tr></thead>
+<tbody>
+{rows}
+</tbody>
+</table>"#
+    )
+}
+
+/// serialize the query result into JSON chunks, the principal bundle records of a sequence are serialized
+/// when its chunk is consumed, so the whole JSON body of a large query is not built in memory at once
+pub fn pb_data_to_json_chunks(
+    data: Option<Arc<TargetMatchPrincipalBundles>>,
+) -> Box<dyn Iterator<Item = Vec<u8>> + Send> {
+    let data = match data {
+        None => return Box::new(std::iter::once(b"null".to_vec())),
+        Some(data) => data,
+    };
+    let head = format!(
+        "{{\"query\":{},\"match_summary\":{},\"sid_ctg_src\":{},\"n_matched_seqs\":{},\"bundle_bed_records\":[",
+        serde_json::to_string(&data.query).unwrap(),
+        serde_json::to_string(&data.match_summary).unwrap(),
+        serde_json::to_string(&data.sid_ctg_src).unwrap(),
+        data.n_matched_seqs
+    );
+    let records = (0..data.bundle_bed_records.len()).map(move |idx| {
+        let mut chunk = if idx == 0 { vec![] } else { vec![b','] };
+        serde_json::to_writer(&mut chunk, &data.bundle_bed_records[idx]).unwrap();
+        chunk
+    });
+    Box::new(
+        std::iter::once(head.into_bytes())
+            .chain(records)
+            .chain(std::iter::once(b"]}".to_vec())),
+    )
+}
+
+/// render the two regions in one page, the tracks of the left region are above the tracks of the right region
+pub fn compare_data_to_html_string(data: &ComparePrincipalBundles) -> String {
+    let tag_records = |side: &str, bundle_bed_records: &[Vec<PrincipalBundleBedRecord>]| {
+        bundle_bed_records
+            .iter()
+            .map(|records| {
+                records
+                    .iter()
+                    .map(|r| PrincipalBundleBedRecord {
+                        ctg: format!("{}/{}", side, r.ctg),
+                        ..r.clone()
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut bundle_bed_records = tag_records("left", &data.left.bundle_bed_records);
+    bundle_bed_records.extend(tag_records("right", &data.right.bundle_bed_records));
+    let targets = TargetMatchPrincipalBundles {
+        query: data.left.query.clone(),
+        match_summary: data
+            .left
+            .match_summary
+            .iter()
+            .chain(data.right.match_summary.iter())
+            .cloned()
+            .collect(),
+        sid_ctg_src: data
+            .left
+            .sid_ctg_src
+            .iter()
+            .chain(data.right.sid_ctg_src.iter())
+            .cloned()
+            .collect(),
+        bundle_bed_records,
+        n_matched_seqs: data.left.n_matched_seqs + data.right.n_matched_seqs,
+        bundle_seqs: FxHashMap::default(),
+    };
+    pb_data_to_html_string(&targets)
+}
+
 pub fn pb_data_to_html_string(targets: &TargetMatchPrincipalBundles) -> String {
     let mut target_lengths = targets
         .match_summary
@@ -539,6 +1010,33 @@ pub fn pb_data_to_html_string(targets: &TargetMatchPrincipalBundles) -> String {
         (ctg.clone(), ctg, b_segements)
     });
 
+    // the origins of the decomposed sequences in the database, (source::contig, contig, t_bgn, t_end, reversed)
+    let sid_to_ctg_src = targets
+        .sid_ctg_src
+        .iter()
+        .map(|(sid, ctg, src)| (*sid, (ctg, src)))
+        .collect::<FxHashMap<_, _>>();
+    let seq_origins = targets
+        .match_summary
+        .iter()
+        .flat_map(|(sid, hits)| {
+            let sid_to_ctg_src = &sid_to_ctg_src;
+            hits.iter().filter_map(move |h| {
+                let (ctg, src) = sid_to_ctg_src.get(sid)?;
+                Some((
+                    target_seq_name(src, ctg, h.t_bgn, h.t_end),
+                    (
+                        format!("{}::{}", src, ctg),
+                        (*ctg).clone(),
+                        h.t_bgn,
+                        h.t_end,
+                        h.reversed,
+                    ),
+                ))
+            })
+        })
+        .collect::<FxHashMap<_, _>>();
+
     let track_scaling = 1.0;
     let stroke_width = 1.0;
     let left_padding = 50.0;
@@ -564,6 +1062,11 @@ pub fn pb_data_to_html_string(targets: &TargetMatchPrincipalBundles) -> String {
                 *e += 1;
             });
 
+            // the origin of the track in the database, the tracks of a comparison are tagged with the side
+            let origin = seq_origins
+                .get(&ctg)
+                .or_else(|| ctg.split_once('/').and_then(|(_, name)| seq_origins.get(name)));
+
             // let offset = *ctg_to_offset.get(&ctg).unwrap_or(&0);
             let offset = 0;
             let paths: Vec<element::Group> = bundle_segment
@@ -617,19 +1120,41 @@ r#".{bundle_class} {{fill:{bundle_color}; stroke:{stroke_color}; stroke-width:{s
                         .set("d", path_str)
                         .set("class", "bundle ".to_string() + bundle_class.as_str());
                     let mut g = element::Group::new().set("transform", format!("translate({left_padding} {y_offset})"));
+                    // the region of the segment in the database for pgr-fetch
+                    let (region, strand) = match origin {
+                        Some((name, _, t_bgn, t_end, reversed)) => {
+                            let end0 = end0.min(t_end - t_bgn);
+                            let (b, e) = if *reversed { (t_end - end0, t_end - bgn0) } else { (t_bgn + bgn0, t_bgn + end0) };
+                            (format!("{}:{}-{}", name, b, e), if (direction == 1) != *reversed { '-' } else { '+' })
+                        }
+                        None => (format!("{}:{}-{}", ctg, bgn0, end0), if direction == 1 { '-' } else { '+' }),
+                    };
+                    p.assign("data-region", region.clone());
                     if !no_tooltips { // it may be good idea to disable it for every large region visualization
-                        p.append(element::Title::new(format!("{}:{}-{}:{}", ctg, bgn0, end0, bundle_id )));
+                        p.append(element::Title::new(format!(
+                            "{}:{}-{}\nbundle: {}\norientation: {}\nlength: {}\nregion: {}",
+                            ctg, bgn0, end0, bundle_id, strand, end0 - bgn0, region
+                        )));
                     };
                     g.append(p);
                     g
                 })
                 .collect();
 
-            let text = element::Text::new(annotation)
+            let mut text = element::Text::new(annotation)
                 .set("x", 20.0 + left_padding + track_range * scaling_factor)
                 .set("y", y_offset + 2.0)
                 .set("font-size", "10px")
-                .set("font-family", "monospace");
+                .set("font-family", "monospace")
+                .set("class", "track_label")
+                .set("data-track-y", y_offset);
+            // the variant calls are placed under the track with the origin
+            if let Some((_, origin_ctg, t_bgn, t_end, reversed)) = origin {
+                text.assign("data-ctg", origin_ctg.clone());
+                text.assign("data-bgn", *t_bgn);
+                text.assign("data-end", *t_end);
+                text.assign("data-reversed", if *reversed { "true" } else { "false" });
+            }
             y_offset += delta_y;
             (ctg, (paths, text))
         })
@@ -653,7 +1178,23 @@ r#".{bundle_class} {{fill:{bundle_color}; stroke:{stroke_color}; stroke-width:{s
         )
         .set("height", 56.0 + y_offset)
         .set("preserveAspectRatio", "none")
-        .set("id", "bundleViwer");
+        .set("id", "bundleViwer")
+        .set("data-scaling-factor", scaling_factor)
+        .set("data-left-padding", left_padding)
+        .set("data-track-panel-width", track_panel_width)
+        .set(
+            "data-figure-name",
+            format!(
+                "{}_{}_{}",
+                targets.query.ctg, targets.query.bgn, targets.query.end
+            ),
+        )
+        // the gene annotations of the backbone sequence are placed with the start of the queried range
+        .set("data-backbone-ctg", targets.query.ctg.clone())
+        .set(
+            "data-backbone-offset",
+            targets.query.bgn.saturating_sub(targets.query.padding),
+        );
 
     // insert CSS
     let stroke_width_rep = stroke_width * highlight_repeats;
@@ -664,6 +1205,8 @@ r#".{bundle_class} {{fill:{bundle_color}; stroke:{stroke_color}; stroke-width:{s
         format!(".bundle:hover {{ stroke-width:{stroke_width_hover};}}"),
         format!(".repeat:hover {{ stroke-width:{stroke_width_hover_rep};}}"),
         format!(".region {{ stroke-opacity: 0.5 }};"),
+        // keep the stroke width when the tracks are zoomed
+        ".bundle {vector-effect: non-scaling-stroke;}".to_string(),
     ];
     css_strings.extend(bundle_class_styles.values().cloned());
     //let h_factor = h_factor;
@@ -687,6 +1230,8 @@ r#".{bundle_class} {{fill:{bundle_color}; stroke:{stroke_color}; stroke-width:{s
         tick_interval
     };
 
+    // the ticks are redrawn for the visible range when the tracks are zoomed or panned
+    let mut ticks = element::Group::new().set("id", "ticks");
     let mut tickx = track_tick_interval;
     loop {
         if tickx > track_range as usize {
@@ -699,62 +1244,569 @@ r#".{bundle_class} {{fill:{bundle_color}; stroke:{stroke_color}; stroke-width:{s
             .set("fill", "none")
             .set("stroke-width", 1)
             .set("d", tick_path_str);
-        document.append(tick_path);
+        ticks.append(tick_path);
         tickx += track_tick_interval;
     }
+    document.append(ticks);
 
     let text = element::Text::new(format!("{} bps", track_range))
-        .set("x", 20.0 + left_padding + scaling_factor)
+        .set("id", "track_range")
+        .set("x", 20.0 + left_padding + track_range * scaling_factor)
         .set("y", -14)
         .set("font-size", "10px")
         .set("font-family", "sans-serif");
     document.append(text);
 
-    // insert the bundle paths
+    // the bundle paths are clipped to the track panel, they are zoomed and panned with the mouse wheel and dragging
+    let clip_path = element::ClipPath::new().set("id", "track_clip").add(
+        element::Rectangle::new()
+            .set("x", 0)
+            .set("y", -32)
+            .set("width", track_panel_width)
+            .set("height", 56.0 + y_offset),
+    );
+    document.append(element::Definitions::new().add(clip_path));
+    let mut tracks = element::Group::new().set("id", "tracks");
     ctg_with_svg_paths
         .into_iter()
         .for_each(|(_ctg, (paths, text))| {
             // println!("{}", ctg);
             document.append(text);
-            paths.into_iter().for_each(|path| document.append(path));
+            paths.into_iter().for_each(|path| tracks.append(path));
         });
+    document.append(
+        element::Group::new()
+            .set("clip-path", "url(#track_clip)")
+            .add(tracks),
+    );
 
     let out_str = Vec::new();
     let mut out_file = BufWriter::new(out_str);
     let msg = "can't write the HTML doc";
     writeln!(out_file, "<html><body>").expect(msg);
-    let jscript = r#"
+    let jscript = r##"
 <script>
 document.addEventListener('readystatechange', event => {
     if (event.target.readyState === "complete") {
         var bundles = document.getElementsByClassName("bundle");
         for (let i = 0; i < bundles.length; i++) {
             bundles[i].onclick = function (e) {
+                if (track_view.dragged) {
+                    return;
+                }
                 // alert(e.target.classList);
-                let is_highlighted = false;
                 let bundle_id = "";
                 for (let cidx = 0; cidx < e.target.classList.length; cidx++) {
-                    if (e.target.classList[cidx] == "highlighted") {
-                        is_highlighted = true;
-                    }
                     if (e.target.classList[cidx].match("bundle_")) {
                         bundle_id = e.target.classList[cidx]
                     }
                 };
-                var bundles2 = document.getElementsByClassName(bundle_id);
-                for (let j = 0; j < bundles2.length; j++) {
-                    if (is_highlighted) {
-                        bundles2[j].classList.remove("highlighted");
-                    } else {
-                        bundles2[j].classList.add("highlighted");
-                    }
+                toggle_highlight(bundle_id);
+                // copy the region of the segment for pgr-fetch
+                var region = e.target.getAttribute("data-region");
+                if (region && navigator.clipboard) {
+                    navigator.clipboard.writeText(region);
+                    document.getElementById("copy_status").textContent = "copied " + region;
                 }
             };
         }
+        setup_zoom_and_pan();
     }
 });
+
+function toggle_highlight(bundle_id) {
+    var bundles2 = document.querySelectorAll("#tracks ." + bundle_id);
+    var is_highlighted = bundles2.length > 0 && bundles2[0].classList.contains("highlighted");
+    for (let j = 0; j < bundles2.length; j++) {
+        if (is_highlighted) {
+            bundles2[j].classList.remove("highlighted");
+        } else {
+            bundles2[j].classList.add("highlighted");
+        }
+    }
+    var row = document.getElementById("legend_" + bundle_id);
+    if (row) {
+        row.style.fontWeight = is_highlighted ? "" : "bold";
+    }
+}
+
+// hide or show the segments of a bundle in the tracks
+function set_bundle_visibility(bundle_id, visible) {
+    document.querySelectorAll("#tracks ." + bundle_id).forEach(p => {
+        p.style.display = visible ? "" : "none";
+    });
+}
+
+// the tracks are transformed by translate(tx 0) scale(s 1), a track position p is drawn at
+// x = (p * scaling_factor + left_padding) * s + tx
+var track_view = { s: 1.0, tx: 0.0, dragged: false };
+
+function setup_zoom_and_pan() {
+    var svg = document.getElementById("bundleViwer");
+    var tracks = document.getElementById("tracks");
+    var scaling_factor = parseFloat(svg.dataset.scalingFactor);
+    var left_padding = parseFloat(svg.dataset.leftPadding);
+    var width = parseFloat(svg.dataset.trackPanelWidth);
+
+    function svg_x(e) {
+        var pt = svg.createSVGPoint();
+        pt.x = e.clientX;
+        pt.y = e.clientY;
+        return pt.matrixTransform(svg.getScreenCTM().inverse()).x;
+    }
+
+    function update() {
+        track_view.s = Math.min(Math.max(track_view.s, 1.0), 10000.0);
+        track_view.tx = Math.min(Math.max(track_view.tx, width * (1.0 - track_view.s)), 0.0);
+        tracks.setAttribute("transform", "translate(" + track_view.tx + " 0) scale(" + track_view.s + " 1)");
+        draw_ticks();
+    }
+
+    function draw_ticks() {
+        var ticks = document.getElementById("ticks");
+        while (ticks.firstChild) {
+            ticks.removeChild(ticks.firstChild);
+        }
+        var to_pos = x => ((x - track_view.tx) / track_view.s - left_padding) / scaling_factor;
+        var to_x = p => (p * scaling_factor + left_padding) * track_view.s + track_view.tx;
+        var bgn = Math.max(to_pos(left_padding), 0);
+        var end = to_pos(width);
+        var interval = Math.pow(10, Math.floor(Math.log10(Math.max(end - bgn, 10))) - 1);
+        if ((end - bgn) / interval > 50) {
+            interval *= 5;
+        } else if ((end - bgn) / interval > 20) {
+            interval *= 2;
+        }
+        var ns = "http://www.w3.org/2000/svg";
+        for (var p = Math.ceil(bgn / interval) * interval; p <= end; p += interval) {
+            var x = to_x(p);
+            var tick = document.createElementNS(ns, "path");
+            tick.setAttribute("stroke", "#000");
+            tick.setAttribute("fill", "none");
+            tick.setAttribute("stroke-width", 1);
+            tick.setAttribute("d", "M " + x + " -16 L " + x + " -20");
+            ticks.appendChild(tick);
+            var label = document.createElementNS(ns, "text");
+            label.setAttribute("x", x);
+            label.setAttribute("y", -22);
+            label.setAttribute("font-size", "8px");
+            label.setAttribute("font-family", "sans-serif");
+            label.setAttribute("text-anchor", "middle");
+            label.textContent = Math.round(p);
+            ticks.appendChild(label);
+        }
+        document.getElementById("track_range").textContent =
+            Math.round(bgn) + "-" + Math.round(end) + " (" + Math.round(end - bgn) + " bps)";
+    }
+
+    svg.addEventListener("wheel", function (e) {
+        e.preventDefault();
+        var x = svg_x(e);
+        var factor = e.deltaY < 0 ? 1.25 : 0.8;
+        var s = Math.min(Math.max(track_view.s * factor, 1.0), 10000.0);
+        track_view.tx = x - (x - track_view.tx) * s / track_view.s;
+        track_view.s = s;
+        update();
+    }, { passive: false });
+
+    var drag_x = null;
+    svg.addEventListener("mousedown", function (e) {
+        drag_x = svg_x(e);
+        track_view.dragged = false;
+    });
+    window.addEventListener("mousemove", function (e) {
+        if (drag_x === null) {
+            return;
+        }
+        var x = svg_x(e);
+        if (Math.abs(x - drag_x) > 2) {
+            track_view.dragged = true;
+        }
+        track_view.tx += x - drag_x;
+        drag_x = x;
+        update();
+    });
+    window.addEventListener("mouseup", function (e) {
+        drag_x = null;
+    });
+    // reset the view with a double click
+    svg.addEventListener("dblclick", function (e) {
+        track_view.s = 1.0;
+        track_view.tx = 0.0;
+        update();
+    });
+    update();
+}
+
+// the gene models {name, bgn, end, strand, blocks: [[bgn, end], ...]} of the contig from the BED (BED6 or BED12) lines
+function parse_bed(text, ctg) {
+    var models = [];
+    text.split("\n").forEach(line => {
+        var f = line.trim().split("\t");
+        if (f.length < 3 || f[0] != ctg) {
+            return;
+        }
+        var bgn = parseInt(f[1]);
+        var end = parseInt(f[2]);
+        var blocks = [[bgn, end]];
+        if (f.length >= 12) {
+            var sizes = f[10].split(",").filter(v => v != "").map(v => parseInt(v));
+            var starts = f[11].split(",").filter(v => v != "").map(v => parseInt(v));
+            blocks = starts.map((b, i) => [bgn + b, bgn + b + sizes[i]]);
+        }
+        models.push({ name: f[3] || "", bgn: bgn, end: end, strand: f[5] || ".", blocks: blocks });
+    });
+    return models;
+}
+
+// the transcripts (mRNA or transcript features) with the exons from the GFF3 lines, the genes are used if
+// there is no transcript
+function parse_gff(text, ctg) {
+    var transcripts = new Map();
+    var genes = [];
+    var exons = [];
+    text.split("\n").forEach(line => {
+        var f = line.trim().split("\t");
+        if (line.startsWith("#") || f.length < 9 || f[0] != ctg) {
+            return;
+        }
+        var attrs = {};
+        f[8].split(";").forEach(attr => {
+            var kv = attr.split("=");
+            if (kv.length == 2) {
+                attrs[kv[0].trim()] = kv[1];
+            }
+        });
+        var bgn = parseInt(f[3]) - 1;
+        var end = parseInt(f[4]);
+        var model = { name: attrs.Name || attrs.ID || "", bgn: bgn, end: end, strand: f[6], blocks: [] };
+        if (f[2] == "mRNA" || f[2] == "transcript") {
+            transcripts.set(attrs.ID, model);
+        } else if (f[2] == "gene") {
+            model.blocks.push([bgn, end]);
+            genes.push(model);
+        } else if (f[2] == "exon" && attrs.Parent) {
+            exons.push([attrs.Parent, bgn, end]);
+        }
+    });
+    exons.forEach(([parents, bgn, end]) => parents.split(",").forEach(parent => {
+        if (transcripts.has(parent)) {
+            transcripts.get(parent).blocks.push([bgn, end]);
+        }
+    }));
+    return transcripts.size > 0 ? Array.from(transcripts.values()) : genes;
+}
+
+var base_view = null;
+
+// render the gene models above the bundle tracks in the same coordinates as the tracks
+function add_annotation(text, filename) {
+    var svg = document.getElementById("bundleViwer");
+    var tracks = document.getElementById("tracks");
+    var clip = document.querySelector("#track_clip rect");
+    var scaling_factor = parseFloat(svg.dataset.scalingFactor);
+    var left_padding = parseFloat(svg.dataset.leftPadding);
+    var width = parseFloat(svg.dataset.trackPanelWidth);
+    var offset = parseInt(svg.dataset.backboneOffset);
+    var ctg = svg.dataset.backboneCtg;
+    if (base_view === null) {
+        var vb = svg.viewBox.baseVal;
+        base_view = {
+            x: vb.x, y: vb.y, width: vb.width, height: vb.height,
+            svg_height: parseFloat(svg.getAttribute("height")),
+            clip_y: parseFloat(clip.getAttribute("y")),
+            clip_height: parseFloat(clip.getAttribute("height"))
+        };
+    }
+    var is_gff = /\.gff3?$/i.test(filename);
+    var models = is_gff ? parse_gff(text, ctg) : parse_bed(text, ctg);
+    var max_pos = (width - left_padding) / scaling_factor;
+    models = models
+        .map(m => ({
+            name: m.name, bgn: m.bgn - offset, end: m.end - offset, strand: m.strand,
+            blocks: m.blocks.map(([b, e]) => [b - offset, e - offset])
+        }))
+        .filter(m => m.end > 0 && m.bgn < max_pos)
+        .sort((a, b) => a.bgn - b.bgn);
+
+    // pack the models into rows without overlaps
+    var row_ends = [];
+    var rows = models.map(m => {
+        var row = row_ends.findIndex(end => end < m.bgn);
+        if (row == -1) {
+            row = row_ends.length;
+            row_ends.push(m.end);
+        } else {
+            row_ends[row] = m.end;
+        }
+        return row;
+    });
+
+    var ns = "http://www.w3.org/2000/svg";
+    var row_height = 12;
+    var top = base_view.y - 4 - row_ends.length * row_height;
+    var old_genes = document.getElementById("genes");
+    if (old_genes) {
+        old_genes.remove();
+    }
+    var genes = document.createElementNS(ns, "g");
+    genes.setAttribute("id", "genes");
+    genes.setAttribute("transform", "translate(" + left_padding + " 0)");
+    models.forEach((m, i) => {
+        var y = top + rows[i] * row_height + row_height / 2;
+        var g = document.createElementNS(ns, "g");
+        var title = document.createElementNS(ns, "title");
+        title.textContent = m.name + " " + ctg + ":" + (m.bgn + offset) + "-" + (m.end + offset) + " (" + m.strand + ")";
+        g.appendChild(title);
+        var line = document.createElementNS(ns, "path");
+        line.setAttribute("d", "M " + m.bgn * scaling_factor + " " + y + " L " + m.end * scaling_factor + " " + y);
+        line.setAttribute("stroke", "#333");
+        line.setAttribute("vector-effect", "non-scaling-stroke");
+        g.appendChild(line);
+        m.blocks.forEach(([b, e]) => {
+            var block = document.createElementNS(ns, "rect");
+            block.setAttribute("x", b * scaling_factor);
+            block.setAttribute("y", y - 3);
+            block.setAttribute("width", Math.max((e - b) * scaling_factor, 0.1));
+            block.setAttribute("height", 6);
+            block.setAttribute("fill", m.strand == "-" ? "#a33" : "#33a");
+            g.appendChild(block);
+        });
+        genes.appendChild(g);
+    });
+    tracks.appendChild(genes);
+
+    var extra = base_view.y - top;
+    svg.setAttribute("viewBox", [base_view.x, top, base_view.width, base_view.height + extra].join(" "));
+    svg.setAttribute("height", base_view.svg_height + extra);
+    clip.setAttribute("y", base_view.clip_y - extra);
+    clip.setAttribute("height", base_view.clip_height + extra);
+}
+
+function load_annotation_file(file) {
+    file.text().then(text => add_annotation(text, file.name));
+}
+
+function load_annotation_url() {
+    var url = document.getElementById("annotation_url").value;
+    fetch(url)
+        .then(res => res.text())
+        .then(text => add_annotation(text, new URL(url, document.baseURI).pathname));
+}
+
+// the variant calls {ctg, bgn, end, type, label} from the VCF lines, the type is the SVTYPE or SNV/INDEL
+function parse_vcf(text) {
+    var calls = [];
+    text.split("\n").forEach(line => {
+        var f = line.trim().split("\t");
+        if (line.startsWith("#") || f.length < 8) {
+            return;
+        }
+        var bgn = parseInt(f[1]) - 1;
+        var end = bgn + f[3].length;
+        var info = {};
+        f[7].split(";").forEach(kv => {
+            var [k, v] = kv.split("=");
+            info[k] = v;
+        });
+        if (info.END) {
+            end = parseInt(info.END);
+        }
+        var type = info.SVTYPE || (f[3].length == 1 && f[4].length == 1 ? "SNV" : "INDEL");
+        calls.push({ ctg: f[0], bgn: bgn, end: end, type: type, label: f[0] + ":" + f[1] + " " + f[3] + ">" + f[4] });
+    });
+    return calls;
+}
+
+// the SV candidates from the pgr-alnmap bed files (svcnd.bed or ctgsv.bed), a SV candidate in an alignment is
+// placed on both the target and the query contigs
+function parse_alnmap_bed(text) {
+    var calls = [];
+    text.split("\n").forEach(line => {
+        var f = line.trim().split("\t");
+        if (f.length < 4) {
+            return;
+        }
+        var fields = f[3].split(":");
+        var type = fields[0];
+        calls.push({ ctg: f[0], bgn: parseInt(f[1]), end: parseInt(f[2]), type: type, label: f[3] });
+        if (type.startsWith("SVC") && fields.length > 2) {
+            var range = fields[2].split("-");
+            var qs = parseInt(range[0]);
+            var qe = parseInt(range[1]);
+            calls.push({ ctg: fields[1], bgn: Math.min(qs, qe), end: Math.max(qs, qe), type: type, label: f[3] });
+        }
+    });
+    return calls;
+}
+
+function variant_color(type) {
+    if (type == "SNV") {
+        return "#555";
+    } else if (type == "INDEL") {
+        return "#e80";
+    } else if (type == "BND" || type.startsWith("TR")) {
+        return "#90c";
+    }
+    return "#d00";
+}
+
+// draw the variant calls under the tracks of the same contigs in the coordinates of the tracks
+function add_variants(text, filename) {
+    var svg = document.getElementById("bundleViwer");
+    var tracks = document.getElementById("tracks");
+    var scaling_factor = parseFloat(svg.dataset.scalingFactor);
+    var calls = /\.vcf$/i.test(filename) ? parse_vcf(text) : parse_alnmap_bed(text);
+    var calls_by_ctg = new Map();
+    calls.forEach(c => {
+        if (!calls_by_ctg.has(c.ctg)) {
+            calls_by_ctg.set(c.ctg, []);
+        }
+        calls_by_ctg.get(c.ctg).push(c);
+    });
+
+    var ns = "http://www.w3.org/2000/svg";
+    var old_variants = document.getElementById("variants");
+    if (old_variants) {
+        old_variants.remove();
+    }
+    var variants = document.createElementNS(ns, "g");
+    variants.setAttribute("id", "variants");
+    variants.setAttribute("transform", "translate(" + svg.dataset.leftPadding + " 0)");
+    var n_calls = 0;
+    document.querySelectorAll(".track_label").forEach(label => {
+        var ctg = label.getAttribute("data-ctg");
+        if (!ctg || !calls_by_ctg.has(ctg)) {
+            return;
+        }
+        var t_bgn = parseInt(label.getAttribute("data-bgn"));
+        var t_end = parseInt(label.getAttribute("data-end"));
+        var reversed = label.getAttribute("data-reversed") == "true";
+        var y = parseFloat(label.getAttribute("data-track-y")) + 5;
+        calls_by_ctg.get(ctg).forEach(c => {
+            if (c.end <= t_bgn || c.bgn >= t_end) {
+                return;
+            }
+            var b = Math.max(c.bgn, t_bgn);
+            var e = Math.min(Math.max(c.end, c.bgn + 1), t_end);
+            var [p0, p1] = reversed ? [t_end - e, t_end - b] : [b - t_bgn, e - t_bgn];
+            var glyph = document.createElementNS(ns, "rect");
+            glyph.setAttribute("x", p0 * scaling_factor);
+            glyph.setAttribute("y", y);
+            glyph.setAttribute("width", Math.max((p1 - p0) * scaling_factor, 0.5));
+            glyph.setAttribute("height", 3);
+            glyph.setAttribute("fill", variant_color(c.type));
+            var title = document.createElementNS(ns, "title");
+            title.textContent = c.type + " " + c.label;
+            glyph.appendChild(title);
+            variants.appendChild(glyph);
+            n_calls += 1;
+        });
+    });
+    tracks.appendChild(variants);
+    document.getElementById("copy_status").textContent = n_calls + " variant calls placed";
+}
+
+function load_variant_file(file) {
+    file.text().then(text => add_variants(text, file.name));
+}
+
+// a standalone copy of the current view of the tracks with a legend of the bundles in the tracks
+function figure_with_legend() {
+    var svg = document.getElementById("bundleViwer");
+    var figure = svg.cloneNode(true);
+    figure.setAttribute("xmlns", "http://www.w3.org/2000/svg");
+    var bundle_classes = new Set();
+    svg.querySelectorAll("#tracks .bundle").forEach(p => p.classList.forEach(c => {
+        if (c.startsWith("bundle_")) {
+            bundle_classes.add(c);
+        }
+    }));
+    bundle_classes = Array.from(bundle_classes).sort();
+
+    var ns = "http://www.w3.org/2000/svg";
+    var view_box = svg.viewBox.baseVal;
+    var n_cols = 20;
+    var col_width = view_box.width / n_cols;
+    var row_height = 14;
+    var legend = document.createElementNS(ns, "g");
+    bundle_classes.forEach((bundle_class, i) => {
+        var x = view_box.x + (i % n_cols) * col_width + 4;
+        var y = view_box.y + view_box.height + Math.floor(i / n_cols) * row_height + 8;
+        var swatch = document.createElementNS(ns, "rect");
+        swatch.setAttribute("class", bundle_class);
+        swatch.setAttribute("x", x);
+        swatch.setAttribute("y", y);
+        swatch.setAttribute("width", 12);
+        swatch.setAttribute("height", 8);
+        legend.appendChild(swatch);
+        var label = document.createElementNS(ns, "text");
+        label.setAttribute("x", x + 16);
+        label.setAttribute("y", y + 8);
+        label.setAttribute("font-size", "10px");
+        label.setAttribute("font-family", "monospace");
+        label.textContent = parseInt(bundle_class.slice("bundle_".length));
+        legend.appendChild(label);
+    });
+    figure.appendChild(legend);
+
+    var height = view_box.height + Math.ceil(bundle_classes.length / n_cols) * row_height + 16;
+    figure.setAttribute("viewBox", [view_box.x, view_box.y, view_box.width, height].join(" "));
+    figure.setAttribute("height", height + parseFloat(svg.getAttribute("height")) - view_box.height);
+    return figure;
+}
+
+function download(url, filename) {
+    var a = document.createElement("a");
+    a.href = url;
+    a.download = filename;
+    document.body.appendChild(a);
+    a.click();
+    a.remove();
+}
+
+// export the figure as a SVG file or as a PNG image rasterized at two times of the figure size
+function export_figure(format) {
+    var figure = figure_with_legend();
+    var name = "pgr_bundles_" + figure.dataset.figureName;
+    var svg_string = new XMLSerializer().serializeToString(figure);
+    var svg_url = URL.createObjectURL(new Blob([svg_string], { type: "image/svg+xml" }));
+    if (format == "svg") {
+        download(svg_url, name + ".svg");
+        return;
+    }
+    var image = new Image();
+    image.onload = function () {
+        var scale = 2;
+        var canvas = document.createElement("canvas");
+        canvas.width = parseFloat(figure.getAttribute("width")) * scale;
+        canvas.height = parseFloat(figure.getAttribute("height")) * scale;
+        var ctx = canvas.getContext("2d");
+        ctx.fillStyle = "#fff";
+        ctx.fillRect(0, 0, canvas.width, canvas.height);
+        ctx.drawImage(image, 0, 0, canvas.width, canvas.height);
+        URL.revokeObjectURL(svg_url);
+        canvas.toBlob(blob => download(URL.createObjectURL(blob), name + ".png"));
+    };
+    image.src = svg_url;
+}
 </script>
-"#;
+<div>
+<button onclick="export_figure('svg')">Export SVG</button>
+<button onclick="export_figure('png')">Export PNG</button>
+<span id="copy_status"></span>
+</div>
+<div>
+Gene annotations (BED or GFF3) of the backbone sequence:
+<input type="file" accept=".bed,.gff,.gff3" onchange="load_annotation_file(this.files[0])">
+<input type="text" id="annotation_url" placeholder="the URL of a BED or GFF3 file">
+<button onclick="load_annotation_url()">Load</button>
+</div>
+<div>
+Variant calls (VCF) or SV candidates (svcnd.bed or ctgsv.bed) from pgr-alnmap:
+<input type="file" accept=".vcf,.bed" onchange="load_variant_file(this.files[0])">
+</div>
+"##;
     writeln!(out_file, "{}", jscript).expect(msg);
     let mut svg_elment = BufWriter::new(Vec::new());
     svg::write(&mut svg_elment, &document).unwrap();
@@ -764,6 +1816,7 @@ document.addEventListener('readystatechange', event => {
         String::from_utf8_lossy(&svg_elment.into_inner().unwrap())
     )
     .expect(msg);
+    writeln!(out_file, "{}", bundle_legend_html(targets)).expect(msg);
     writeln!(out_file, "</body></html>").expect(msg);
 
     let _ = out_file.flush();
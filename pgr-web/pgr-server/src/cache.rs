@@ -0,0 +1,147 @@
+use crate::bundle_processing::*;
+use pgr_db::lru_cache::LruCache;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// (the time the decomposition is cached, the decomposition)
+type CacheEntry = (Instant, Arc<TargetMatchPrincipalBundles>);
+
+/// a LRU cache of the principal bundle decompositions keyed by the JSON of the query specs, an entry expires
+/// after the TTL and is recomputed on the next query
+pub struct QueryCache {
+    ttl: Duration,
+    entries: Mutex<LruCache<String, CacheEntry>>,
+}
+
+impl QueryCache {
+    /// create a cache holding up to `capacity` decompositions, the cache is disabled if `capacity` is zero
+    pub fn new(capacity: usize, ttl_secs: u64) -> Self {
+        QueryCache {
+            ttl: Duration::from_secs(ttl_secs),
+            entries: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    /// get the cached decomposition of the query or compute and cache it, the errors are not cached
+    pub fn get_or_compute<F>(
+        &self,
+        seq_query_spec: &SequenceQuerySpec,
+        compute: F,
+    ) -> Result<Arc<TargetMatchPrincipalBundles>, std::io::Error>
+    where
+        F: FnOnce() -> Result<TargetMatchPrincipalBundles, std::io::Error>,
+    {
+        if self.entries.lock().unwrap().capacity() == 0 {
+            return compute().map(Arc::new);
+        }
+        let key = serde_json::to_string(seq_query_spec).unwrap();
+        let cached = self.entries.lock().unwrap().get(&key);
+        if let Some((created, data)) = cached {
+            if created.elapsed() <= self.ttl {
+                return Ok(data);
+            }
+        }
+        let data = Arc::new(compute()?);
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(key, (Instant::now(), data.clone()));
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc_hash::FxHashMap;
+    use std::cell::Cell;
+
+    fn query_spec(bgn: usize) -> SequenceQuerySpec {
+        serde_json::from_value(serde_json::json!({
+            "source": "hg19_tagged.fa",
+            "ctg": "chr6_hg19",
+            "bgn": bgn,
+            "end": bgn + 100000,
+            "padding": 10000,
+            "merge_range_tol": 120000,
+            "w": 48,
+            "k": 56,
+            "r": 4,
+            "min_span": 12,
+            "sketch": false,
+            "min_cov": 0,
+            "min_branch_size": 8,
+            "bundle_length_cutoff": 500,
+            "bundle_merge_distance": 10000,
+        }))
+        .unwrap()
+    }
+
+    // query the cache and count the computations
+    fn query(cache: &QueryCache, bgn: usize, n_computed: &Cell<usize>) {
+        let spec = query_spec(bgn);
+        let data = cache
+            .get_or_compute(&spec, || {
+                n_computed.set(n_computed.get() + 1);
+                Ok(TargetMatchPrincipalBundles {
+                    query: spec.clone(),
+                    match_summary: vec![],
+                    sid_ctg_src: vec![],
+                    bundle_bed_records: vec![],
+                    n_matched_seqs: 0,
+                    bundle_seqs: FxHashMap::default(),
+                })
+            })
+            .unwrap();
+        assert_eq!(data.query.bgn, bgn);
+    }
+
+    #[test]
+    fn test_cache_hit() {
+        let cache = QueryCache::new(2, 3600);
+        let n_computed = Cell::new(0);
+        query(&cache, 0, &n_computed);
+        query(&cache, 0, &n_computed);
+        assert_eq!(n_computed.get(), 1);
+        assert_eq!(cache.entries.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_ttl_expiry() {
+        let cache = QueryCache::new(2, 0);
+        let n_computed = Cell::new(0);
+        query(&cache, 0, &n_computed);
+        std::thread::sleep(Duration::from_millis(2));
+        query(&cache, 0, &n_computed);
+        assert_eq!(n_computed.get(), 2);
+        // the expired entry is replaced
+        assert_eq!(cache.entries.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_lru_eviction() {
+        let cache = QueryCache::new(2, 3600);
+        let n_computed = Cell::new(0);
+        query(&cache, 0, &n_computed);
+        query(&cache, 1, &n_computed);
+        // the query at 1 is used least recently when the query at 2 is cached
+        query(&cache, 0, &n_computed);
+        query(&cache, 2, &n_computed);
+        assert_eq!(n_computed.get(), 3);
+        assert_eq!(cache.entries.lock().unwrap().len(), 2);
+        query(&cache, 0, &n_computed);
+        assert_eq!(n_computed.get(), 3);
+        query(&cache, 1, &n_computed);
+        assert_eq!(n_computed.get(), 4);
+    }
+
+    #[test]
+    fn test_zero_capacity_bypass() {
+        let cache = QueryCache::new(0, 3600);
+        let n_computed = Cell::new(0);
+        query(&cache, 0, &n_computed);
+        query(&cache, 0, &n_computed);
+        assert_eq!(n_computed.get(), 2);
+        assert!(cache.entries.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,255 @@
+use crate::bundle_processing::*;
+use crate::cache::QueryCache;
+use pgr_db::ext::SeqIndexDB;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+use utoipa::ToSchema;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Pending,
+    Running,
+    Done,
+    Failed,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, ToSchema)]
+pub struct JobState {
+    pub job_id: u64,
+    pub status: JobStatus,
+    /// the error message of a failed job
+    pub message: Option<String>,
+}
+
+struct Job {
+    status: JobStatus,
+    message: Option<String>,
+    // the JSON of the decomposition, it is kept in the job directory instead if the directory is set
+    result: Option<Arc<String>>,
+    // when the job is done or failed, the finished jobs are evicted after the TTL
+    finished: Option<Instant>,
+}
+
+/// the queue of the principal bundle decomposition jobs, the jobs are run in the blocking thread pool
+/// with at most `n_workers` jobs running at the same time, the states of the finished jobs are kept for the TTL
+pub struct JobQueue {
+    next_job_id: AtomicU64,
+    jobs: Mutex<FxHashMap<u64, Job>>,
+    workers: Arc<Semaphore>,
+    job_dir: Option<PathBuf>,
+    ttl: Duration,
+}
+
+impl JobQueue {
+    /// create a job queue, the job ids continue from the results already persisted in `job_dir`, the done
+    /// or failed jobs are evicted after `ttl_secs` (the persisted results are still loaded from `job_dir`)
+    pub fn new(
+        n_workers: usize,
+        job_dir: Option<String>,
+        ttl_secs: u64,
+    ) -> Result<Self, std::io::Error> {
+        let job_dir = job_dir.map(PathBuf::from);
+        let mut next_job_id = 0_u64;
+        if let Some(job_dir) = job_dir.as_ref() {
+            std::fs::create_dir_all(job_dir)?;
+            std::fs::read_dir(job_dir)?.try_for_each(|entry| -> Result<(), std::io::Error> {
+                let path = entry?.path();
+                if let Some(job_id) = Self::job_id_from_path(&path) {
+                    next_job_id = next_job_id.max(job_id + 1);
+                }
+                Ok(())
+            })?;
+        }
+        Ok(JobQueue {
+            next_job_id: AtomicU64::new(next_job_id),
+            jobs: Mutex::new(FxHashMap::default()),
+            workers: Arc::new(Semaphore::new(n_workers.max(1))),
+            job_dir,
+            ttl: Duration::from_secs(ttl_secs),
+        })
+    }
+
+    fn job_id_from_path(path: &std::path::Path) -> Option<u64> {
+        if path.extension()? != "json" {
+            return None;
+        }
+        path.file_stem()?.to_str()?.parse::<u64>().ok()
+    }
+
+    fn result_path(&self, job_id: u64) -> Option<PathBuf> {
+        self.job_dir
+            .as_ref()
+            .map(|job_dir| job_dir.join(format!("{}.json", job_id)))
+    }
+
+    // remove the done or failed jobs finished longer than the TTL ago
+    fn evict_finished(&self, jobs: &mut FxHashMap<u64, Job>) {
+        jobs.retain(|_, job| match job.finished {
+            Some(finished) => finished.elapsed() <= self.ttl,
+            None => true,
+        });
+    }
+
+    fn set_status(&self, job_id: u64, status: JobStatus, message: Option<String>) {
+        let mut jobs = self.jobs.lock().unwrap();
+        self.evict_finished(&mut jobs);
+        let job = jobs.entry(job_id).or_insert(Job {
+            status,
+            message: None,
+            result: None,
+            finished: None,
+        });
+        job.status = status;
+        job.message = message;
+        if status == JobStatus::Done || status == JobStatus::Failed {
+            job.finished = Some(Instant::now());
+        }
+    }
+
+    fn finish(&self, job_id: u64, data: Arc<TargetMatchPrincipalBundles>) {
+        let json = match serde_json::to_string(&data) {
+            Ok(json) => json,
+            Err(err) => {
+                self.set_status(job_id, JobStatus::Failed, Some(err.to_string()));
+                return;
+            }
+        };
+        let result = if let Some(path) = self.result_path(job_id) {
+            if let Err(err) = std::fs::write(path, json) {
+                self.set_status(job_id, JobStatus::Failed, Some(err.to_string()));
+                return;
+            }
+            None
+        } else {
+            Some(Arc::new(json))
+        };
+        let mut jobs = self.jobs.lock().unwrap();
+        self.evict_finished(&mut jobs);
+        jobs.insert(
+            job_id,
+            Job {
+                status: JobStatus::Done,
+                message: None,
+                result,
+                finished: Some(Instant::now()),
+            },
+        );
+    }
+
+    /// queue a decomposition job and return the job id, the decomposition is shared with the query cache
+    pub fn submit(
+        self: &Arc<Self>,
+        seq_query_spec: SequenceQuerySpec,
+        seq_db: Arc<SeqIndexDB>,
+        cache: Arc<QueryCache>,
+    ) -> u64 {
+        let job_id = self.next_job_id.fetch_add(1, Ordering::Relaxed);
+        self.set_status(job_id, JobStatus::Pending, None);
+        let queue = self.clone();
+        tokio::spawn(async move {
+            let _permit = queue.workers.clone().acquire_owned().await.unwrap();
+            queue.set_status(job_id, JobStatus::Running, None);
+            let data = tokio::task::spawn_blocking(move || {
+                cache.get_or_compute(&seq_query_spec, || {
+                    get_target_and_principal_bundle_decomposition(&seq_query_spec, seq_db)
+                })
+            })
+            .await;
+            match data {
+                Ok(Ok(data)) => queue.finish(job_id, data),
+                Ok(Err(err)) => queue.set_status(job_id, JobStatus::Failed, Some(err.to_string())),
+                Err(err) => queue.set_status(job_id, JobStatus::Failed, Some(err.to_string())),
+            }
+        });
+        job_id
+    }
+
+    /// the state of a job and the JSON of the decomposition if the job is done, a persisted result
+    /// of an earlier server run is loaded from the job directory
+    pub fn get(&self, job_id: u64) -> Option<(JobState, Option<Arc<String>>)> {
+        let job = self.jobs.lock().unwrap().get(&job_id).map(|job| {
+            (
+                JobState {
+                    job_id,
+                    status: job.status,
+                    message: job.message.clone(),
+                },
+                job.result.clone(),
+            )
+        });
+        match job {
+            Some((state, result)) if state.status == JobStatus::Done && result.is_none() => {
+                let result = std::fs::read_to_string(self.result_path(job_id)?).ok()?;
+                Some((state, Some(Arc::new(result))))
+            }
+            Some(job) => Some(job),
+            None => {
+                let result = std::fs::read_to_string(self.result_path(job_id)?).ok()?;
+                Some((
+                    JobState {
+                        job_id,
+                        status: JobStatus::Done,
+                        message: None,
+                    },
+                    Some(Arc::new(result)),
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_job_dir(name: &str) -> PathBuf {
+        let job_dir =
+            std::env::temp_dir().join(format!("pgr-server-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&job_dir);
+        std::fs::create_dir_all(&job_dir).unwrap();
+        job_dir
+    }
+
+    #[test]
+    fn test_job_id_continues_from_job_dir() {
+        let job_dir = temp_job_dir("job-ids");
+        std::fs::write(job_dir.join("3.json"), "{}").unwrap();
+        std::fs::write(job_dir.join("7.json"), "{}").unwrap();
+        std::fs::write(job_dir.join("12.txt"), "").unwrap();
+        let queue = JobQueue::new(1, Some(job_dir.to_string_lossy().to_string()), 3600).unwrap();
+        assert_eq!(queue.next_job_id.load(Ordering::Relaxed), 8);
+        std::fs::remove_dir_all(&job_dir).unwrap();
+    }
+
+    #[test]
+    fn test_get_persisted_result() {
+        let job_dir = temp_job_dir("job-results");
+        std::fs::write(job_dir.join("5.json"), r#"{"bundles":[]}"#).unwrap();
+        let queue = JobQueue::new(1, Some(job_dir.to_string_lossy().to_string()), 3600).unwrap();
+        let (state, result) = queue.get(5).unwrap();
+        assert_eq!(state.status, JobStatus::Done);
+        assert_eq!(result.unwrap().as_str(), r#"{"bundles":[]}"#);
+        assert!(queue.get(6).is_none());
+        std::fs::remove_dir_all(&job_dir).unwrap();
+    }
+
+    #[test]
+    fn test_evict_finished_jobs() {
+        let queue = JobQueue::new(1, None, 0).unwrap();
+        queue.set_status(0, JobStatus::Running, None);
+        queue.set_status(1, JobStatus::Failed, Some("error".to_string()));
+        assert_eq!(queue.get(1).unwrap().0.message.as_deref(), Some("error"));
+        std::thread::sleep(Duration::from_millis(10));
+        // the failed job is evicted when the next job is queued, the running job is kept
+        queue.set_status(2, JobStatus::Pending, None);
+        assert!(queue.get(1).is_none());
+        assert_eq!(queue.get(0).unwrap().0.status, JobStatus::Running);
+        assert_eq!(queue.get(2).unwrap().0.status, JobStatus::Pending);
+    }
+}
@@ -1,60 +1,166 @@
 pub mod bundle_processing;
+pub mod cache;
+pub mod jobs;
 
 use axum::{
-    body::{boxed, Body},
+    body::{boxed, Body, BoxBody, Bytes, StreamBody},
+    extract::rejection::QueryRejection,
     extract::ws::{WebSocket, WebSocketUpgrade},
-    extract::Query,
-    http::{Response, StatusCode},
+    extract::{Path, Query, RawQuery},
+    http::{header, Request, Response, StatusCode},
+    middleware::{self, Next},
     response,
     response::Html,
+    response::IntoResponse,
     routing::{get, post},
     Json, Router,
 };
 use bundle_processing::*;
+use cache::QueryCache;
 use clap::{self, Parser};
+use jobs::{JobQueue, JobState, JobStatus};
 use pgr_db::ext::*;
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
 use std::net::SocketAddr;
 use std::{
     net::{IpAddr, Ipv6Addr},
     path::PathBuf,
     str::FromStr,
-    sync::Arc,
+    sync::{Arc, OnceLock},
 };
 use tokio::fs;
 use tower::{ServiceBuilder, ServiceExt};
+use tower_http::compression::CompressionLayer;
 use tower_http::cors::Any;
 use tower_http::cors::CorsLayer;
 use tower_http::services::ServeDir;
 use tower_http::trace::TraceLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use utoipa::OpenApi;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        get_number_of_ctgs,
+        post_query_for_json_data,
+        get_html_by_query,
+        post_region_gfa,
+        get_bundle_fasta,
+        post_job,
+        get_job,
+        post_compare_for_json_data,
+        get_compare_html
+    ),
+    components(schemas(
+        SequenceQuerySpec,
+        ResponseDetail,
+        TargetMatchPrincipalBundles,
+        MatchSummary,
+        PrincipalBundleBedRecord,
+        FieldError,
+        ApiError,
+        CompareQuerySpec,
+        ComparePrincipalBundles,
+        JobState,
+        JobStatus
+    ))
+)]
+struct ApiDoc;
+
+#[derive(Clone, Copy, clap::ValueEnum, Default, Debug)]
+enum Backend {
+    /// the AGC archive with the .mdb and .midx index files
+    #[default]
+    Agc,
+    /// the frg archive with the .mdb, .midx and .sdx files
+    Frag,
+    /// a fasta file indexed in memory when the server starts
+    Fasta,
+}
 
 #[derive(Parser, Debug)]
 #[clap(name = "pgr-server", about = "Experimental Server")]
 struct Opt {
     /// set the listen addr
-    #[clap(short = 'a', long = "addr", default_value = "::1")]
+    #[clap(
+        short = 'a',
+        long = "bind",
+        visible_alias = "addr",
+        default_value = "::1"
+    )]
     addr: String,
 
     /// set the listen port
     #[clap(short = 'p', long = "port", default_value = "5000")]
     port: u16,
 
-    /// set the directory where static files are to be found
-    #[clap(long = "static-dir", default_value = "./dist")]
-    static_dir: String,
+    /// set the directory where static files are to be found, the default is "./dist" or the embedded
+    /// frontend if the server is built with the `embed_frontend` feature
+    #[clap(long = "static-dir")]
+    static_dir: Option<String>,
 
-    /// set data_path_prefix
+    /// set the prefix of the database files (the path to the fasta file for the fasta backend)
     #[clap(
         short = 'd',
-        long = "data-path-prefix",
+        long = "db-prefix",
+        visible_alias = "data-path-prefix",
         default_value = "./pgr-tk-HGRP-y1-evaluation-set-v0"
     )]
     data_path_prefix: String,
 
-    /// set the listen port
+    /// set the database backend
+    #[clap(long = "backend", value_enum, default_value_t = Backend::Agc)]
+    backend: Backend,
+
+    /// use the frg database backend, same as `--backend frag`
     #[clap(short = 'f', long = "frg-file")]
     frg_file: bool,
+
+    /// set the number of threads for the queries, "0" for all CPUs available or the number set by RAYON_NUM_THREADS
+    #[clap(long = "threads", default_value_t = 0)]
+    threads: usize,
+
+    /// set the file of the API keys (one per line), the query endpoints require one of the keys in the
+    /// `x-api-key` header, as a bearer token, in the `api_key` query parameter or in the `pgr_api_key`
+    /// cookie if it is set
+    #[clap(long = "api-key-file")]
+    api_key_file: Option<String>,
+
+    /// set the number of the decomposition jobs running at the same time
+    #[clap(long = "job-workers", default_value_t = 2)]
+    job_workers: usize,
+
+    /// set the directory to persist the results of the decomposition jobs, the results are kept in memory if not set
+    #[clap(long = "job-dir")]
+    job_dir: Option<String>,
+
+    /// set the time in seconds to keep the states of the done or failed decomposition jobs
+    #[clap(long = "job-ttl", default_value_t = 3600)]
+    job_ttl: u64,
+
+    /// set the number of the query results kept in the cache, "0" to disable the cache
+    #[clap(long = "cache-size", default_value_t = 64)]
+    cache_size: usize,
+
+    /// set the time in seconds for a cached query result to expire
+    #[clap(long = "cache-ttl", default_value_t = 3600)]
+    cache_ttl: u64,
+
+    /// minimizer window size for indexing the fasta backend
+    #[clap(long, default_value_t = 80)]
+    w: u32,
+
+    /// minimizer k-mer size for indexing the fasta backend
+    #[clap(long, default_value_t = 56)]
+    k: u32,
+
+    /// sparse minimizer (shimmer) reduction factor for indexing the fasta backend
+    #[clap(long, default_value_t = 4)]
+    r: u32,
+
+    /// min span for neighboring minimiers for indexing the fasta backend
+    #[clap(long, default_value_t = 64)]
+    min_span: u32,
 }
 
 #[tokio::main]
@@ -69,19 +175,63 @@ async fn main() {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
+    if let Some(api_key_file) = opt.api_key_file.as_ref() {
+        let api_keys = std::fs::read_to_string(api_key_file)
+            .expect("fail to read the API key file")
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| line.to_string())
+            .collect::<FxHashSet<String>>();
+        if api_keys.is_empty() {
+            panic!("no API key in {}", api_key_file);
+        }
+        let _ = API_KEYS.set(api_keys);
+    }
+
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(opt.threads)
+        .build_global()
+        .unwrap();
+
     let mut seq_db = SeqIndexDB::new();
 
-    if opt.frg_file {
-        let _ = seq_db.load_from_frg_index(opt.data_path_prefix);
+    let backend = if opt.frg_file {
+        Backend::Frag
     } else {
-        #[cfg(feature = "with_agc")]
-        let _ = seq_db.load_from_agc_index(opt.data_path_prefix);
+        opt.backend
+    };
+    match backend {
+        Backend::Frag => seq_db
+            .load_from_frg_index(opt.data_path_prefix.clone())
+            .expect("fail to load the frg database"),
+        Backend::Fasta => seq_db
+            .load_from_fastx(
+                opt.data_path_prefix.clone(),
+                opt.w,
+                opt.k,
+                opt.r,
+                opt.min_span,
+                true,
+            )
+            .expect("fail to load the fasta file"),
+        Backend::Agc => {
+            #[cfg(feature = "with_agc")]
+            seq_db
+                .load_from_agc_index(opt.data_path_prefix.clone())
+                .expect("fail to load the AGC database");
 
-        #[cfg(not(feature = "with_agc"))]
-        panic!("This command is compiled with only frg file support, please specify `--frg-file");
+            #[cfg(not(feature = "with_agc"))]
+            panic!("This command is compiled with only frg file support, please specify `--backend frag` or `--backend fasta`");
+        }
     }
 
     let seq_db = Arc::new(seq_db);
+    let cache = Arc::new(QueryCache::new(opt.cache_size, opt.cache_ttl));
+    let job_queue = Arc::new(
+        JobQueue::new(opt.job_workers, opt.job_dir.clone(), opt.job_ttl)
+            .expect("fail to set up the job directory"),
+    );
     // build our application with a route
     let app = Router::new()
         .route(
@@ -95,17 +245,91 @@ async fn main() {
             "/api/post_query_for_json_data",
             post({
                 let seq_db = seq_db.clone();
-                move |params| post_query_for_json_data(params, seq_db)
+                let cache = cache.clone();
+                move |params| post_query_for_json_data(params, seq_db, cache)
             }),
         )
         .route(
             "/api/get_html_by_query",
             get({
                 let seq_db = seq_db.clone();
-                move |params| get_html_by_query(params, seq_db)
+                let cache = cache.clone();
+                move |params| get_html_by_query(params, seq_db, cache)
+            }),
+        )
+        .route(
+            "/api/region_gfa",
+            post({
+                let seq_db = seq_db.clone();
+                move |params| post_region_gfa(params, seq_db)
+            }),
+        )
+        .route(
+            "/api/jobs",
+            post({
+                let seq_db = seq_db.clone();
+                let job_queue = job_queue.clone();
+                let cache = cache.clone();
+                move |body| post_job(body, seq_db, job_queue, cache)
+            }),
+        )
+        .route(
+            "/api/jobs/:job_id",
+            get({
+                let job_queue = job_queue.clone();
+                move |job_id| get_job(job_id, job_queue)
+            }),
+        )
+        .route(
+            "/api/bundle/:b_id/fasta",
+            get({
+                let seq_db = seq_db.clone();
+                let cache = cache.clone();
+                move |b_id, params| get_bundle_fasta(b_id, params, seq_db, cache)
             }),
         )
-        .route("/ws", get(ws_handler))
+        .route(
+            "/api/post_compare_for_json_data",
+            post({
+                let seq_db = seq_db.clone();
+                move |body| post_compare_for_json_data(body, seq_db)
+            }),
+        )
+        .route(
+            "/api/get_compare_html",
+            get({
+                let seq_db = seq_db.clone();
+                move |query| get_compare_html(query, seq_db)
+            }),
+        )
+        .route(
+            "/ws/query",
+            get({
+                let seq_db = seq_db.clone();
+                let cache = cache.clone();
+                move |ws| ws_query_handler(ws, seq_db, cache)
+            }),
+        )
+        .route_layer(middleware::from_fn(check_api_key))
+        .route(
+            "/api/openapi.json",
+            get(|| async { Json(ApiDoc::openapi()) }),
+        )
+        .route("/ws", get(ws_handler));
+
+    // the frontend is served for the paths not in the API
+    #[cfg(feature = "embed_frontend")]
+    let app = match opt.static_dir {
+        Some(static_dir) => app.fallback(get(move |req| serve_static_dir(req, static_dir))),
+        None => app.fallback(get(serve_embedded_frontend)),
+    };
+    #[cfg(not(feature = "embed_frontend"))]
+    let app = {
+        let static_dir = opt.static_dir.unwrap_or_else(|| "./dist".to_string());
+        app.fallback(get(move |req| serve_static_dir(req, static_dir)))
+    };
+
+    let app = app
         .layer(
             CorsLayer::new()
                 .allow_origin(Any)
@@ -114,37 +338,7 @@ async fn main() {
                 .allow_headers(Any),
         )
         .layer(ServiceBuilder::new().layer(TraceLayer::new_for_http()))
-        .fallback(get(|req| async move {
-            match ServeDir::new(&opt.static_dir).oneshot(req).await {
-                Ok(res) => {
-                    let status = res.status();
-                    match status {
-                        StatusCode::NOT_FOUND => {
-                            let index_path = PathBuf::from(&opt.static_dir).join("index.html");
-                            let index_content = match fs::read_to_string(index_path).await {
-                                Err(_) => {
-                                    return Response::builder()
-                                        .status(StatusCode::NOT_FOUND)
-                                        .body(boxed(Body::from("index file not found")))
-                                        .unwrap()
-                                }
-                                Ok(index_content) => index_content,
-                            };
-
-                            Response::builder()
-                                .status(StatusCode::OK)
-                                .body(boxed(Body::from(index_content)))
-                                .unwrap()
-                        }
-                        _ => res.map(boxed),
-                    }
-                }
-                Err(_err) => Response::builder()
-                    .status(StatusCode::INTERNAL_SERVER_ERROR)
-                    .body(boxed(Body::from(format!("internal errors"))))
-                    .expect("error response"),
-            }
-        }));
+        .layer(CompressionLayer::new());
 
     // run it
     let addr = SocketAddr::from((
@@ -158,6 +352,143 @@ async fn main() {
         .unwrap();
 }
 
+// serve the static files of the frontend, the paths routed by the frontend get the index.html
+async fn serve_static_dir(req: Request<Body>, static_dir: String) -> Response<BoxBody> {
+    match ServeDir::new(&static_dir).oneshot(req).await {
+        Ok(res) => {
+            let status = res.status();
+            match status {
+                StatusCode::NOT_FOUND => {
+                    let index_path = PathBuf::from(&static_dir).join("index.html");
+                    let index_content = match fs::read_to_string(index_path).await {
+                        Err(_) => {
+                            return Response::builder()
+                                .status(StatusCode::NOT_FOUND)
+                                .body(boxed(Body::from("index file not found")))
+                                .unwrap()
+                        }
+                        Ok(index_content) => index_content,
+                    };
+
+                    Response::builder()
+                        .status(StatusCode::OK)
+                        .header(header::CONTENT_TYPE, "text/html")
+                        .body(boxed(Body::from(index_content)))
+                        .unwrap()
+                }
+                _ => res.map(boxed),
+            }
+        }
+        Err(_err) => Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(boxed(Body::from(format!("internal errors"))))
+            .expect("error response"),
+    }
+}
+
+// the frontend built with `trunk build --release` in `pgr-web/frontend`
+#[cfg(feature = "embed_frontend")]
+static FRONTEND_DIR: include_dir::Dir = include_dir::include_dir!("$CARGO_MANIFEST_DIR/../dist");
+
+#[cfg(feature = "embed_frontend")]
+async fn serve_embedded_frontend(uri: axum::http::Uri) -> response::Response {
+    let path = uri.path().trim_start_matches('/');
+    let file = FRONTEND_DIR
+        .get_file(path)
+        .or_else(|| FRONTEND_DIR.get_file("index.html"));
+    match file {
+        Some(file) => {
+            let content_type = match file.path().extension().and_then(|ext| ext.to_str()) {
+                Some("html") => "text/html",
+                Some("js") => "application/javascript",
+                Some("wasm") => "application/wasm",
+                Some("css") => "text/css",
+                Some("json") => "application/json",
+                Some("svg") => "image/svg+xml",
+                Some("png") => "image/png",
+                Some("ico") => "image/x-icon",
+                _ => "application/octet-stream",
+            };
+            ([(header::CONTENT_TYPE, content_type)], file.contents()).into_response()
+        }
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+// the API keys accepted by the query endpoints, no key is required if it is not set
+static API_KEYS: OnceLock<FxHashSet<String>> = OnceLock::new();
+
+// the cookie set by the frontend, the browser sends it with the links and the WebSocket connections
+const API_KEY_COOKIE: &str = "pgr_api_key";
+
+#[derive(serde::Deserialize)]
+struct ApiKeyParam {
+    api_key: Option<String>,
+}
+
+// the API key from the `x-api-key` header, the bearer token, the `api_key` query parameter or the cookie,
+// the browser GET requests and the WebSocket connections can't carry the custom headers
+fn request_api_key(req: &Request<Body>) -> Option<String> {
+    let headers = req.headers();
+    let header_key = headers
+        .get("x-api-key")
+        .and_then(|v| v.to_str().ok())
+        .or_else(|| {
+            headers
+                .get(header::AUTHORIZATION)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.strip_prefix("Bearer "))
+        });
+    if let Some(api_key) = header_key {
+        return Some(api_key.to_string());
+    }
+    let query_key = req
+        .uri()
+        .query()
+        .and_then(|query| serde_qs::from_str::<ApiKeyParam>(query).ok())
+        .and_then(|param| param.api_key);
+    if query_key.is_some() {
+        return query_key;
+    }
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .find_map(|cookie| {
+            let (name, value) = cookie.trim().split_once('=')?;
+            (name == API_KEY_COOKIE).then(|| value.to_string())
+        })
+}
+
+// compare in constant time for the keys of the same length, so the matched prefix of a key is not leaked by the timing
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0_u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+async fn check_api_key(
+    req: Request<Body>,
+    next: Next<Body>,
+) -> Result<response::Response, response::Response> {
+    if let Some(api_keys) = API_KEYS.get() {
+        let api_key = request_api_key(&req);
+        // all keys are compared without the short circuit
+        let is_valid = api_key.is_some_and(|api_key| {
+            api_keys.iter().fold(false, |is_valid, key| {
+                constant_time_eq(key.as_bytes(), api_key.as_bytes()) | is_valid
+            })
+        });
+        if !is_valid {
+            return Err(error_response(
+                StatusCode::UNAUTHORIZED,
+                "a valid API key is required",
+                vec![],
+            ));
+        }
+    }
+    Ok(next.run(req).await)
+}
+
 /*
 async fn handler(seq_db: Arc<SeqIndexDB>) -> impl IntoResponse {
     let n_ctg = 0;
@@ -169,42 +500,495 @@ async fn handler(seq_db: Arc<SeqIndexDB>) -> impl IntoResponse {
 }
 */
 
+/// the number of the contigs in the database
+#[utoipa::path(
+    get,
+    path = "/api/get_number_of_ctgs",
+    responses((status = 200, description = "the number of the contigs", body = usize))
+)]
 async fn get_number_of_ctgs(seq_db: Arc<SeqIndexDB>) -> Json<usize> {
-    let n_ctg = seq_db.seq_index.as_ref().unwrap().len();
+    let n_ctg = seq_db
+        .seq_index
+        .as_ref()
+        .map_or(0, |seq_index| seq_index.len());
     Json(n_ctg)
 }
 
+// parse a JSON query, a deserialization error is reported with the path to the offending field
+fn parse_query_spec(body: &[u8]) -> Result<Option<SequenceQuerySpec>, Vec<FieldError>> {
+    parse_json_body(body)
+}
+
+fn parse_json_body<T: serde::de::DeserializeOwned>(body: &[u8]) -> Result<T, Vec<FieldError>> {
+    let deserializer = &mut serde_json::Deserializer::from_slice(body);
+    serde_path_to_error::deserialize(deserializer).map_err(|err| {
+        vec![FieldError {
+            field: err.path().to_string(),
+            message: err.inner().to_string(),
+        }]
+    })
+}
+
+fn error_response(
+    status: StatusCode,
+    message: &str,
+    errors: Vec<FieldError>,
+) -> response::Response {
+    let error = ApiError {
+        code: status.as_u16(),
+        message: message.to_string(),
+        errors,
+    };
+    (status, Json(error)).into_response()
+}
+
+fn invalid_query_response(errors: Vec<FieldError>) -> response::Response {
+    error_response(StatusCode::UNPROCESSABLE_ENTITY, "invalid query", errors)
+}
+
+// the error of a query that can not be answered, e.g., the sequences can not be fetched from the database
+fn io_error_response(err: std::io::Error) -> response::Response {
+    let status = match err.kind() {
+        std::io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+        std::io::ErrorKind::InvalidInput => StatusCode::UNPROCESSABLE_ENTITY,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    error_response(status, &err.to_string(), vec![])
+}
+
+// run a decomposition on the blocking thread pool, so a long query does not stall the async workers
+async fn run_blocking<T, F>(compute: F) -> Result<T, std::io::Error>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, std::io::Error> + Send + 'static,
+{
+    tokio::task::spawn_blocking(compute)
+        .await
+        .unwrap_or_else(|err| {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                err.to_string(),
+            ))
+        })
+}
+
+fn missing_query_response() -> response::Response {
+    error_response(StatusCode::BAD_REQUEST, "a query is required", vec![])
+}
+
+/// the matched sequences of a query region and their principal bundle decomposition
+#[utoipa::path(
+    post,
+    path = "/api/post_query_for_json_data",
+    request_body = SequenceQuerySpec,
+    responses(
+        (status = 200, description = "the matches and the principal bundle decomposition, null without a query", body = TargetMatchPrincipalBundles),
+        (status = 422, description = "invalid query", body = ApiError),
+        (status = 500, description = "the sequences can not be fetched from the database", body = ApiError)
+    )
+)]
 async fn post_query_for_json_data(
-    Json(seq_query_spec): Json<Option<SequenceQuerySpec>>,
+    body: Bytes,
     seq_db: Arc<SeqIndexDB>,
-) -> Json<Option<TargetMatchPrincipalBundles>> {
-    if seq_query_spec.is_none() {
-        return Json(None);
+    cache: Arc<QueryCache>,
+) -> response::Response {
+    let seq_query_spec = match parse_query_spec(&body[..]) {
+        Ok(seq_query_spec) => seq_query_spec,
+        Err(errors) => return invalid_query_response(errors),
+    };
+    let data = if let Some(seq_query_spec) = seq_query_spec {
+        let errors = seq_query_spec.validate(&seq_db);
+        if !errors.is_empty() {
+            return invalid_query_response(errors);
+        }
+        tracing::debug!("query: {:?}", seq_query_spec);
+        match run_blocking(move || {
+            cache.get_or_compute(&seq_query_spec, || {
+                get_target_and_principal_bundle_decomposition(&seq_query_spec, seq_db)
+            })
+        })
+        .await
+        {
+            Ok(data) => Some(data),
+            Err(err) => return io_error_response(err),
+        }
+    } else {
+        None
     };
 
-    let seq_query_spec = seq_query_spec.unwrap();
-    println!("{:?}", seq_query_spec);
-    Json(get_target_and_principal_bundle_decomposition(
-        &seq_query_spec,
-        seq_db,
-    ))
+    // the response body is streamed in chunks, the decomposition of each sequence is serialized on demand
+    let chunks = pb_data_to_json_chunks(data).map(Ok::<_, std::io::Error>);
+    (
+        [(header::CONTENT_TYPE, "application/json")],
+        StreamBody::new(futures_util::stream::iter(chunks)),
+    )
+        .into_response()
+}
+
+/// the sequence-resolved GFA of the MAP graph of the sequences matched to a query region
+#[utoipa::path(
+    post,
+    path = "/api/region_gfa",
+    request_body = SequenceQuerySpec,
+    responses(
+        (status = 200, description = "the GFA of the region", body = String, content_type = "text/plain"),
+        (status = 404, description = "no sequence matched to the region", body = ApiError),
+        (status = 422, description = "invalid query", body = ApiError),
+        (status = 500, description = "the sequences can not be fetched from the database", body = ApiError)
+    )
+)]
+async fn post_region_gfa(body: Bytes, seq_db: Arc<SeqIndexDB>) -> response::Response {
+    let seq_query_spec = match parse_query_spec(&body[..]) {
+        Ok(Some(seq_query_spec)) => seq_query_spec,
+        Ok(None) => return missing_query_response(),
+        Err(errors) => return invalid_query_response(errors),
+    };
+    let errors = seq_query_spec.validate(&seq_db);
+    if !errors.is_empty() {
+        return invalid_query_response(errors);
+    }
+    tracing::debug!("region GFA query: {:?}", seq_query_spec);
+    match run_blocking(move || get_region_gfa(&seq_query_spec, seq_db)).await {
+        Ok(gfa) => gfa.into_response(),
+        Err(err) => io_error_response(err),
+    }
+}
+
+// check a query from the URL query parameters, the error response is returned for an invalid query
+fn check_query_params(
+    seq_query_spec: Result<Query<SequenceQuerySpec>, QueryRejection>,
+    seq_db: &SeqIndexDB,
+) -> Result<SequenceQuerySpec, response::Response> {
+    let Query(seq_query_spec) = seq_query_spec.map_err(|rejection| {
+        invalid_query_response(vec![FieldError {
+            field: "query".to_string(),
+            message: rejection.to_string(),
+        }])
+    })?;
+    let errors = seq_query_spec.validate(seq_db);
+    if !errors.is_empty() {
+        return Err(invalid_query_response(errors));
+    }
+    Ok(seq_query_spec)
 }
 
+/// the principal bundle decomposition of the sequences matched to a query region rendered as a HTML page
+#[utoipa::path(
+    get,
+    path = "/api/get_html_by_query",
+    params(SequenceQuerySpec),
+    responses(
+        (status = 200, description = "the HTML page", body = String, content_type = "text/html"),
+        (status = 422, description = "invalid query", body = ApiError),
+        (status = 500, description = "the sequences can not be fetched from the database", body = ApiError)
+    )
+)]
 async fn get_html_by_query(
-    Query(seq_query_spec): Query<SequenceQuerySpec>,
+    seq_query_spec: Result<Query<SequenceQuerySpec>, QueryRejection>,
     seq_db: Arc<SeqIndexDB>,
-) -> Html<String> {
-    //if seq_query_spec.is_none() {
-    //    return Html("<html><body>No Query Yet</body></html>".into());
-    //};
+    cache: Arc<QueryCache>,
+) -> response::Response {
+    let seq_query_spec = match check_query_params(seq_query_spec, &seq_db) {
+        Ok(seq_query_spec) => seq_query_spec,
+        Err(response) => return response,
+    };
+    tracing::debug!("query: {:?}", seq_query_spec);
+
+    let data = match run_blocking(move || {
+        cache.get_or_compute(&seq_query_spec, || {
+            get_target_and_principal_bundle_decomposition(&seq_query_spec, seq_db)
+        })
+    })
+    .await
+    {
+        Ok(data) => data,
+        Err(err) => return io_error_response(err),
+    };
+    let output = pb_data_to_html_string(&data);
 
-    //let seq_query_spec = seq_query_spec.unwrap();
-    println!("{:?}", seq_query_spec);
+    Html(output).into_response()
+}
 
-    let data = get_target_and_principal_bundle_decomposition(&seq_query_spec, seq_db);
-    let output = pb_data_to_html_string(&data.unwrap());
+/// the representative sequence (the longest segment) of a principal bundle in the decomposition of a query region
+#[utoipa::path(
+    get,
+    path = "/api/bundle/{b_id}/fasta",
+    params(
+        ("b_id" = u32, Path, description = "the principal bundle id"),
+        SequenceQuerySpec
+    ),
+    responses(
+        (status = 200, description = "the FASTA of the bundle sequence", body = String, content_type = "text/plain"),
+        (status = 404, description = "the bundle is not in the decomposition", body = ApiError),
+        (status = 422, description = "invalid query", body = ApiError),
+        (status = 500, description = "the sequences can not be fetched from the database", body = ApiError)
+    )
+)]
+async fn get_bundle_fasta(
+    Path(b_id): Path<u32>,
+    seq_query_spec: Result<Query<SequenceQuerySpec>, QueryRejection>,
+    seq_db: Arc<SeqIndexDB>,
+    cache: Arc<QueryCache>,
+) -> response::Response {
+    let seq_query_spec = match check_query_params(seq_query_spec, &seq_db) {
+        Ok(seq_query_spec) => seq_query_spec,
+        Err(response) => return response,
+    };
+    // the decomposition of the region is usually in the cache from the query of the region
+    let data = match run_blocking(move || {
+        cache.get_or_compute(&seq_query_spec, || {
+            get_target_and_principal_bundle_decomposition(&seq_query_spec, seq_db)
+        })
+    })
+    .await
+    {
+        Ok(data) => data,
+        Err(err) => return io_error_response(err),
+    };
+    match data.bundle_seqs.get(&b_id) {
+        None => error_response(
+            StatusCode::NOT_FOUND,
+            &format!("bundle {} is not in the decomposition", b_id),
+            vec![],
+        ),
+        Some((name, seq)) => format!(
+            ">bundle_{} {} len={}\n{}\n",
+            b_id,
+            name,
+            seq.len(),
+            String::from_utf8_lossy(&seq[..])
+        )
+        .into_response(),
+    }
+}
 
-    Html(output)
+/// queue a principal bundle decomposition job of a query region, the job state is polled with the job id
+#[utoipa::path(
+    post,
+    path = "/api/jobs",
+    request_body = SequenceQuerySpec,
+    responses(
+        (status = 200, description = "the queued job", body = JobState),
+        (status = 422, description = "invalid query", body = ApiError)
+    )
+)]
+async fn post_job(
+    body: Bytes,
+    seq_db: Arc<SeqIndexDB>,
+    job_queue: Arc<JobQueue>,
+    cache: Arc<QueryCache>,
+) -> response::Response {
+    let seq_query_spec = match parse_query_spec(&body[..]) {
+        Ok(Some(seq_query_spec)) => seq_query_spec,
+        Ok(None) => return missing_query_response(),
+        Err(errors) => return invalid_query_response(errors),
+    };
+    let errors = seq_query_spec.validate(&seq_db);
+    if !errors.is_empty() {
+        return invalid_query_response(errors);
+    }
+    tracing::debug!("job query: {:?}", seq_query_spec);
+    let job_id = job_queue.submit(seq_query_spec, seq_db, cache);
+    Json(JobState {
+        job_id,
+        status: JobStatus::Pending,
+        message: None,
+    })
+    .into_response()
+}
+
+/// the state of a decomposition job, the decomposition is in the `result` field when the job is done
+#[utoipa::path(
+    get,
+    path = "/api/jobs/{job_id}",
+    params(("job_id" = u64, Path, description = "the job id")),
+    responses(
+        (status = 200, description = "the job state", body = JobState),
+        (status = 404, description = "unknown job id", body = ApiError)
+    )
+)]
+async fn get_job(Path(job_id): Path<u64>, job_queue: Arc<JobQueue>) -> response::Response {
+    match job_queue.get(job_id) {
+        None => error_response(
+            StatusCode::NOT_FOUND,
+            &format!("unknown job id {}", job_id),
+            vec![],
+        ),
+        Some((state, None)) => Json(state).into_response(),
+        Some((state, Some(result))) => {
+            // splice the stored JSON of the decomposition into the job state
+            let state = serde_json::to_string(&state).unwrap();
+            let body = format!("{},\"result\":{}}}", &state[..state.len() - 1], result);
+            ([(header::CONTENT_TYPE, "application/json")], body).into_response()
+        }
+    }
+}
+
+/// the matched sequences of two query regions decomposed together, the bundle ids are shared by the two regions
+#[utoipa::path(
+    post,
+    path = "/api/post_compare_for_json_data",
+    request_body = CompareQuerySpec,
+    responses(
+        (status = 200, description = "the decompositions of the two regions", body = ComparePrincipalBundles),
+        (status = 422, description = "invalid query", body = ApiError),
+        (status = 500, description = "the sequences can not be fetched from the database", body = ApiError)
+    )
+)]
+async fn post_compare_for_json_data(body: Bytes, seq_db: Arc<SeqIndexDB>) -> response::Response {
+    let compare_query_spec = match parse_json_body::<CompareQuerySpec>(&body[..]) {
+        Ok(compare_query_spec) => compare_query_spec,
+        Err(errors) => return invalid_query_response(errors),
+    };
+    let errors = compare_query_spec.validate(&seq_db);
+    if !errors.is_empty() {
+        return invalid_query_response(errors);
+    }
+    tracing::debug!("compare query: {:?}", compare_query_spec);
+    match run_blocking(move || {
+        get_compare_principal_bundle_decomposition(&compare_query_spec, seq_db)
+    })
+    .await
+    {
+        Ok(data) => Json(data).into_response(),
+        Err(err) => io_error_response(err),
+    }
+}
+
+/// the two query regions decomposed together rendered as a HTML page, the queries are given as nested query
+/// parameters, e.g., `left[source]=..&left[ctg]=..&right[source]=..`
+#[utoipa::path(
+    get,
+    path = "/api/get_compare_html",
+    responses(
+        (status = 200, description = "the HTML page", body = String, content_type = "text/html"),
+        (status = 422, description = "invalid query", body = ApiError),
+        (status = 500, description = "the sequences can not be fetched from the database", body = ApiError)
+    )
+)]
+async fn get_compare_html(
+    RawQuery(query): RawQuery,
+    seq_db: Arc<SeqIndexDB>,
+) -> response::Response {
+    let compare_query_spec =
+        match serde_qs::from_str::<CompareQuerySpec>(&query.unwrap_or_default()) {
+            Ok(compare_query_spec) => compare_query_spec,
+            Err(err) => {
+                return invalid_query_response(vec![FieldError {
+                    field: "query".to_string(),
+                    message: err.to_string(),
+                }])
+            }
+        };
+    let errors = compare_query_spec.validate(&seq_db);
+    if !errors.is_empty() {
+        return invalid_query_response(errors);
+    }
+    tracing::debug!("compare query: {:?}", compare_query_spec);
+    match run_blocking(move || {
+        get_compare_principal_bundle_decomposition(&compare_query_spec, seq_db)
+    })
+    .await
+    {
+        Ok(data) => Html(compare_data_to_html_string(&data)).into_response(),
+        Err(err) => io_error_response(err),
+    }
+}
+
+async fn ws_query_handler(
+    ws: WebSocketUpgrade,
+    seq_db: Arc<SeqIndexDB>,
+    cache: Arc<QueryCache>,
+) -> response::Response {
+    ws.on_upgrade(move |socket| ws_handle_query(socket, seq_db, cache))
+}
+
+// each text message is a query in JSON, the stages of the query are sent back as {"stage":..,"percent":..}
+// and the decomposition as {"stage":"done","percent":100,"result":..}, an invalid query gets {"stage":"error","errors":..}
+// and a failed one {"stage":"error","message":..}
+async fn ws_handle_query(mut socket: WebSocket, seq_db: Arc<SeqIndexDB>, cache: Arc<QueryCache>) {
+    while let Some(Ok(msg)) = socket.recv().await {
+        let axum::extract::ws::Message::Text(msg) = msg else {
+            continue;
+        };
+        let seq_query_spec = match parse_query_spec(msg.as_bytes()) {
+            Ok(Some(seq_query_spec)) => {
+                let errors = seq_query_spec.validate(&seq_db);
+                if errors.is_empty() {
+                    Ok(seq_query_spec)
+                } else {
+                    Err(errors)
+                }
+            }
+            Ok(None) => continue,
+            Err(errors) => Err(errors),
+        };
+        let seq_query_spec = match seq_query_spec {
+            Ok(seq_query_spec) => seq_query_spec,
+            Err(errors) => {
+                let msg = serde_json::json!({"stage": "error", "errors": errors}).to_string();
+                if socket
+                    .send(axum::extract::ws::Message::Text(msg))
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+                continue;
+            }
+        };
+        tracing::debug!("ws query: {:?}", seq_query_spec);
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<(String, u32)>();
+        let seq_db = seq_db.clone();
+        let cache = cache.clone();
+        let task = tokio::task::spawn_blocking(move || {
+            cache.get_or_compute(&seq_query_spec, || {
+                get_target_and_principal_bundle_decomposition_with_progress(
+                    &seq_query_spec,
+                    seq_db,
+                    &|stage, percent| {
+                        let _ = tx.send((stage.to_string(), percent));
+                    },
+                )
+            })
+        });
+        // the channel is closed when the query is finished
+        while let Some((stage, percent)) = rx.recv().await {
+            let msg = serde_json::json!({"stage": stage, "percent": percent}).to_string();
+            if socket
+                .send(axum::extract::ws::Message::Text(msg))
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+        let msg = match task.await {
+            Ok(Ok(data)) => {
+                let result = pb_data_to_json_chunks(Some(data))
+                    .flatten()
+                    .collect::<Vec<u8>>();
+                format!(
+                    "{{\"stage\":\"done\",\"percent\":100,\"result\":{}}}",
+                    String::from_utf8_lossy(&result[..])
+                )
+            }
+            Ok(Err(err)) => {
+                serde_json::json!({"stage": "error", "message": err.to_string()}).to_string()
+            }
+            Err(err) => {
+                serde_json::json!({"stage": "error", "message": err.to_string()}).to_string()
+            }
+        };
+        if socket
+            .send(axum::extract::ws::Message::Text(msg))
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
 }
 
 async fn ws_handler(ws: WebSocketUpgrade) -> response::Response {
@@ -253,3 +1037,44 @@ async fn ws_handle_socket(mut socket: WebSocket) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_api_key() {
+        let req = Request::builder()
+            .uri("/api/get_html_by_query/?ctg=chr1&api_key=key0")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(request_api_key(&req).as_deref(), Some("key0"));
+
+        let req = Request::builder()
+            .uri("/ws/query")
+            .header(header::COOKIE, "theme=dark; pgr_api_key=key1")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(request_api_key(&req).as_deref(), Some("key1"));
+
+        let req = Request::builder()
+            .uri("/api/jobs?api_key=key0")
+            .header("x-api-key", "key2")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(request_api_key(&req).as_deref(), Some("key2"));
+
+        let req = Request::builder()
+            .uri("/ws/query")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(request_api_key(&req), None);
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"key0", b"key0"));
+        assert!(!constant_time_eq(b"key0", b"key1"));
+        assert!(!constant_time_eq(b"key0", b"key00"));
+    }
+}
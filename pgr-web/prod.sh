@@ -6,4 +6,4 @@ pushd frontend
 trunk build --release
 popd
 
-cargo run --bin pgr-server --release --  --addr 0.0.0.0 --port 3000 --static-dir ./dist --data-path-prefix /wd/pgr-tk-demo-data/data/pgr-tk-HGRP-y1-evaluation-set-v0
+cargo run --bin pgr-server --release --  --bind 0.0.0.0 --port 3000 --static-dir ./dist --db-prefix /wd/pgr-tk-demo-data/data/pgr-tk-HGRP-y1-evaluation-set-v0